@@ -0,0 +1,433 @@
+# English messages, and the fallback for anything missing from another locale.
+
+## Layout
+
+app-title = Contacts App
+app-tagline = Who you gonna call?
+nav-home = Home
+nav-dashboard = Dashboard
+nav-activity = Activity
+nav-settings = Settings
+locale-switch = Language
+
+## Contact fields
+
+field-first = First
+field-last = Last
+field-first-name = First Name
+field-last-name = Last Name
+field-name = Name
+field-phone = Phone
+field-phone-number = Phone Number
+field-email = Email
+field-created = Created
+field-updated = Last changed
+field-company = Company
+field-job-title = Job Title
+# chrono format, see https://docs.rs/chrono/latest/chrono/format/strftime/
+datetime-format = %Y-%m-%d %H:%M (UTC%:z)
+date-format = %Y-%m-%d
+time-just-now = just now
+time-minutes-ago = { $count ->
+    [one] a minute ago
+   *[other] { $count } minutes ago
+}
+time-hours-ago = { $count ->
+    [one] an hour ago
+   *[other] { $count } hours ago
+}
+time-days-ago = { $count ->
+    [one] a day ago
+   *[other] { $count } days ago
+}
+time-months-ago = { $count ->
+    [one] a month ago
+   *[other] { $count } months ago
+}
+time-years-ago = { $count ->
+    [one] a year ago
+   *[other] { $count } years ago
+}
+
+## Actions
+
+action-save = Save
+action-cannot-save = Cannot save
+action-cancel = Cancel
+action-edit = Edit
+action-view = View
+action-back = Back
+action-undo = Undo
+action-delete = Delete
+action-delete-contact = Delete Contact
+action-add-contact = Add Contact
+action-quick-add = Add
+action-create-anyway = Create anyway
+action-add-relationship = Link
+action-add-date = Add date
+action-remove = Remove
+action-upload = Upload
+action-show-activity = Activity
+action-companies = Companies
+action-upcoming-dates = Upcoming dates
+action-export-csv = Export CSV
+action-export-csv-excel = Export CSV (Excel, semicolon)
+action-print-pdf = Print (PDF)
+action-fix = Fix
+action-import = Import
+action-import-more = Import another file
+action-check-import = Check without importing
+action-change-mapping = Change the mapping
+action-apply = Apply
+action-trash = Trash
+action-restore = Restore
+action-empty-trash = Empty trash
+action-shares = Shared links
+action-share = Share
+action-revoke = Revoke
+action-api-keys = API keys
+action-create-api-key = Create key
+
+## Contact list
+
+contact-deleted = { $name } has been deleted.
+search-label = Search Term
+search-button = Search
+empty-no-contacts = No contacts yet
+empty-no-contacts-hint = Contacts you add or import show up here.
+empty-no-results = No contacts match
+empty-no-results-hint = Nothing matches the search, filters or letter. Try other ones, or see all contacts.
+action-create-first-contact = Create your first contact
+action-clear-search = Clear search
+filters = Filters
+filter-created-after = Created from
+filter-created-before = until before
+filter-email-domain = Email domain
+letter-bar = Browse by last name
+letter-all = All
+loading-more = Loading more…
+load-more = Load more
+feed-title = Newest contacts
+pagination-previous = Previous
+pagination-next = Next
+pagination-status = Page { $page } of { $pages } ({ $total } total)
+pagination-first = First
+pagination-last = Last
+pagination-jump = Go to page
+pagination-go = Go
+quick-add-label = Quick add
+
+## Contact forms
+
+contact-values = Contact Values
+contact-created = Contact successfully created. Redirecting
+contact-in-use = Email and/or phone number is already in use. Contact NOT SAVED
+form-already-submitted = This form has already been submitted. Contact NOT SAVED
+contact-save-failed = Saving the contact failed, please try again. Contact NOT SAVED
+email-and-phone-exist = ⛔ Email and phone number already exist in your contacts
+email-exists = ⛔ This email already exists in your contacts
+phone-exists = ⛔ This phone number already exists in your contacts
+email-taken = Another contact already has this email
+phone-taken = Another contact already has this phone number
+possible-duplicate = Possible duplicate of { $name } — create anyway?
+delete-confirm-this = Are you sure you want to delete this contact?
+delete-confirm = Are you sure you want to delete { $name }?
+
+## List parameters and search syntax
+
+query-too-long = Search terms can be at most { $max } characters long.
+invalid-date = "{ $value }" is not a valid { $name } date, use YYYY-MM-DD.
+invalid-email-domain = "{ $domain }" is not a valid email domain.
+invalid-letter = "{ $letter }" is not a letter from A to Z.
+invalid-page = "{ $page }" is not a valid page number.
+invalid-page-size = "{ $per_page }" is not a valid page size.
+invalid-sort = Contacts can't be sorted by "{ $sort }". Use one of: { $allowed }.
+invalid-sort-direction = "{ $dir }" is not a sort direction, use asc or desc.
+too-many-sort-columns = Contacts can be sorted by at most { $max } columns.
+repeated-sort-column = Contacts are already sorted by "{ $sort }", list each column once.
+too-many-sort-directions = There are more sort directions than sort columns.
+stale-page-link = That page link is no longer valid, go back to the first page.
+query-email-needs-domain = "{ $token }" needs a domain, like email:example.com.
+query-created-needs-date = "{ $token }" needs a date, like created:>=2024-01-01.
+query-no-tags = Contacts don't have tags, so tag: can't be searched for.
+query-conflicting-domains = Contacts can't have emails at both { $domain } and { $other }.
+query-empty-range = The created dates leave no days to search, the start has to come before the end.
+
+## Contact sheets
+
+invalid-contact-ids = "{ $ids }" is not a list of contact ids, use something like 3,1,4.
+too-many-sheet-contacts = A contact sheet can have at most { $max } contacts.
+
+## QR code
+
+qr-code-alt = QR code with this contact's details
+qr-code-caption = Scan to add this contact to your phone.
+
+## Data quality report
+
+quality-title = Data Quality Report
+quality-summary = { $issues } of { $checked } contacts need attention.
+quality-generated = Generated { $time }.
+quality-regenerate = Regenerate now
+quality-problems = Problems
+quality-pending = The report is being generated. Check back in a moment.
+problem-email-missing = Email is missing
+problem-email-spaces = Email contains spaces
+problem-email-no-at = Email has no @
+problem-email-form = Email is not of the form name@domain
+problem-email-domain = Email domain looks invalid
+problem-phone-missing = Phone number is missing
+problem-phone-symbols = Phone number contains letters or symbols
+problem-phone-digits = Phone number has too few or too many digits
+problem-name-missing = Name is missing
+problem-first-name-missing = First name is missing
+problem-last-name-missing = Last name is missing
+
+## Relationships
+
+relationships-title = Relationships
+relationships-none = Not linked to anyone yet.
+relationship-incoming = ({ $label } this contact)
+relationship-label = Relationship
+relationship-label-placeholder = spouse of, works with, ...
+relationship-related-email = Other contact's email
+relationship-label-missing = Say what the relationship is, like "works with".
+relationship-label-too-long = The relationship can be at most { $max } characters long.
+relationship-unknown-contact = There is no contact with the email { $email }.
+relationship-self = A contact can't be linked to itself.
+relationship-exists = The contacts are already linked like that.
+
+## Dates
+
+dates-title = Dates
+dates-none = No dates yet.
+date-label = What
+date-label-placeholder = anniversary, renewal, follow up, ...
+date-date = Date
+date-yearly = Every year
+date-yearly-note = every year
+date-label-missing = Say what the date is, like "anniversary".
+date-label-too-long = What the date is can be at most { $max } characters long.
+date-invalid = Pick a date.
+date-exists = The contact already has that date.
+upcoming-title = Upcoming dates
+upcoming-days = Days ahead
+upcoming-none = Nothing coming up in the next { $days ->
+    [1] day.
+   *[other] { $days } days.
+}
+upcoming-today = today
+upcoming-in-days = { $days ->
+    [1] tomorrow
+   *[other] in { $days } days
+}
+upcoming-years = { $years ->
+    [one] { $years } year
+   *[other] { $years } years
+}
+
+## Attachments
+
+attachments-title = Attachments
+attachments-none = No files attached yet.
+attachment-file = File
+attachment-malformed = The upload couldn't be read, try again.
+attachment-missing = Pick a file to upload.
+attachment-too-large = Files can be at most { $max }.
+attachment-type-not-allowed = Files of type { $type } can't be attached.
+
+## Companies
+
+companies-title = Companies
+companies-members = { $count ->
+    [one] { $count } contact
+   *[other] { $count } contacts
+}
+companies-none = No contacts have a company yet.
+
+## Import
+
+import-title = Import Contacts
+import-intro = Upload a CSV file of contacts, up to { $max }. Next you pick which column goes into which field.
+import-mapping-intro = Pick the field each column goes into, or ignore it. Rows that aren't valid contacts are skipped.
+import-saved-mapping = Saved mapping
+import-detected-columns = Detected columns
+import-column = Column
+import-sample = Sample values
+import-field = Field
+import-ignore = Ignore
+import-save-as = Save this mapping as (optional)
+import-not-csv = The file isn't a CSV file with a header row.
+import-not-utf8 = The file isn't UTF-8 text. Save it as "CSV UTF-8" and try again.
+import-expired = That upload has expired, upload the file again.
+import-nothing-mapped = Pick a field for at least one column.
+import-name-too-long = Mapping names can be at most { $max } characters.
+import-created = { $count ->
+    [one] { $count } contact created.
+   *[other] { $count } contacts created.
+}
+import-failed = { $count ->
+    [one] { $count } row skipped
+   *[other] { $count } rows skipped
+}
+import-dry-run = Nothing was imported yet. This is what importing the file with this mapping would do.
+import-would-create = { $count ->
+    [one] { $count } contact would be created.
+   *[other] { $count } contacts would be created.
+}
+import-would-fail = { $count ->
+    [one] { $count } row would be skipped
+   *[other] { $count } rows would be skipped
+}
+
+## Trash
+
+trash-title = Trash
+trash-intro = Deleted contacts are kept here for { $days ->
+    [one] { $days } day
+   *[other] { $days } days
+} and can be restored until then, with their files and dates. Their relationships are gone.
+trash-off = The trash is off: deleted contacts are gone for good once they can't be undone.
+trash-empty = The trash is empty.
+trash-purged-on = Deleted for good on
+trash-empty-confirm = Delete everything in the trash for good?
+trash-restore-failed = { $name } can't be restored: { $reason }
+
+## Share links
+
+shares-title = Shared links
+shares-intro = Anyone with one of these links can see what it shares, without an account, until it expires or is revoked.
+shares-none = Nothing has been shared yet. Share a contact from its page, or a company from the companies page.
+shares-shared = Shared
+shares-link = Link
+shares-status = Status
+shares-missing-contact = Deleted contact #{ $id }
+share-expires = Expires
+share-expires-in = { $days ->
+    [one] For { $days } day
+   *[other] For { $days } days
+}
+share-expires-never = Never
+share-status-active = Active
+share-status-expired = Expired
+share-status-revoked = Revoked
+share-revoke-confirm = Revoke this link? It stops working right away.
+share-invalid-expiry = A link can last from 1 to { $max } days, or until it's revoked.
+share-unknown-company = Nobody works at { $company }, so there is nothing to share.
+shared-download-vcard = Download as vCard
+shared-expires = This link stops working on
+
+## Dashboard
+
+dashboard-title = Dashboard
+dashboard-total = { $total ->
+    [one] { $total } contact
+   *[other] { $total } contacts
+} in total.
+dashboard-per-month = Added per month
+dashboard-top-domains = Top email domains
+dashboard-recently-added = Recently added
+dashboard-recently-edited = Recently edited
+dashboard-none = None yet.
+
+## Activity
+
+activity-title = Activity
+activity-when = When
+activity-change = Change
+activity-contact = Contact
+activity-actor = By
+activity-filter = Filter
+activity-for-contact = Changes to { $name } only.
+activity-show-all = Show all
+activity-only-contact = Only this contact's changes
+activity-none = Nothing has changed yet.
+activity-older = Older changes
+activity-created = Created
+activity-updated = Edited
+activity-deleted = Deleted
+activity-restored = Restored
+activity-changed = Changed
+
+## Settings
+
+settings-title = Settings
+settings-intro = How contacts are shown in this browser. Links that say otherwise still win.
+settings-per-page = Contacts per page
+settings-sort = Sort the list by
+settings-name-format = Write names as
+sort-id = Order added
+sort-first-name = First name
+sort-last-name = Last name
+sort-email = Email
+sort-company = Company
+sort-created-at = Date created
+example-first-name = Jane
+example-last-name = Doe
+invalid-per-page-preference = Contacts per page must be a number from 1 to { $max }.
+
+## API
+
+batch-too-large = A batch can have at most { $max } contacts.
+batch-too-many-operations = A batch can have at most { $max } operations.
+batch-contact-not-found = There is no contact with this id.
+api-key-missing = This API needs a key: send it as "Authorization: Bearer <key>".
+api-key-invalid = This API key doesn't exist or has been revoked.
+api-key-rate-limited = This API key has made too many requests. Wait a moment and retry, see "Retry-After".
+api-key-quota-exceeded = This API key has used up its requests for today. The quota starts over at midnight UTC.
+api-if-match-missing = Send the contact's ETag as "If-Match", to be sure you're changing the version you have.
+api-if-match-stale = The contact has changed since you fetched it. Fetch it again and retry with its new ETag.
+
+## API keys
+
+api-keys-title = API keys
+api-keys-required = Requests to the JSON API need one of these keys, sent as "Authorization: Bearer <key>". They work for everyone using this app.
+api-keys-optional = Requests to the JSON API can send one of these keys as "Authorization: Bearer <key>", and a key that doesn't work is turned down. Requests without a key are let through while api.require_key is off.
+api-keys-none = There are no API keys yet.
+api-keys-name = Name
+api-keys-name-placeholder = What it's for, like "Nightly sync"
+api-keys-key = Key
+api-keys-last-used = Last used
+api-keys-never-used = Never
+api-keys-revoked = Revoked
+api-keys-created = Key "{ $name }" created
+api-keys-shown-once = Copy it now: it isn't stored and won't be shown again.
+api-keys-revoke-confirm = Revoke this key? Requests with it stop working right away.
+api-keys-invalid-name = Give the key a name of up to { $max } characters.
+api-keys-invalid-limits = Limits are whole numbers of requests, at least 1. Leave one empty for no limit.
+api-keys-per-minute = Requests a minute
+api-keys-per-day = Requests a day
+api-keys-no-limit = No limit
+api-keys-limits = Limits
+api-keys-used-today = Requests today
+api-keys-limits-help = A key can have a rate and a daily quota, counted from midnight UTC. Requests over them get a 429, and answers tell clients where they stand in X-RateLimit-* headers.
+
+## Errors and notices
+
+error-title = Error
+error-internal-title = Internal Server Error
+error-internal-intro = We have encountered the following error while processing your request:
+error-request-id = When reporting this problem, please mention the request id:
+error-validation-title = Invalid request
+error-conflict-title = Conflict
+error-unauthorized-title = Not authorized
+not-found-title = Not found
+not-found-message = There is nothing here. The contact may have been deleted, or the link is wrong.
+not-found-back = Back to the contacts
+form-too-large = The form was too large to send. Shorten what you entered and try again.
+form-invalid = The form couldn't be read. Reload the page and try again.
+success-title = Success
+busy-title = Busy
+busy-message = The database is busy right now and your request could not be completed. Please try again in { $retry_after ->
+    [one] { $retry_after } second
+   *[other] { $retry_after } seconds
+}.
+server-busy-message = The server is handling too many requests right now. Please try again in { $retry_after ->
+    [one] { $retry_after } second
+   *[other] { $retry_after } seconds
+}.
+slow-down-title = Slow down
+slow-down-message = You are sending requests too quickly. Please try again in { $retry_after ->
+    [one] { $retry_after } second
+   *[other] { $retry_after } seconds
+}.
@@ -0,0 +1,445 @@
+# Serbian (Latin) messages.
+
+## Layout
+
+app-title = Imenik
+app-tagline = Koga ćeš da pozoveš?
+nav-home = Početna
+nav-dashboard = Pregled
+nav-activity = Aktivnost
+nav-settings = Podešavanja
+locale-switch = Jezik
+
+## Contact fields
+
+field-first = Ime
+field-last = Prezime
+field-first-name = Ime
+field-last-name = Prezime
+field-name = Ime i prezime
+field-phone = Telefon
+field-phone-number = Broj telefona
+field-email = Imejl
+field-created = Napravljen
+field-updated = Poslednja izmena
+field-company = Kompanija
+field-job-title = Radno mesto
+datetime-format = %d.%m.%Y. u %H:%M (UTC%:z)
+date-format = %d.%m.%Y.
+time-just-now = upravo sada
+time-minutes-ago = pre { $count } { $count ->
+    [one] minut
+   *[other] minuta
+}
+time-hours-ago = pre { $count } { $count ->
+    [one] sat
+    [few] sata
+   *[other] sati
+}
+time-days-ago = pre { $count } { $count ->
+    [one] dan
+   *[other] dana
+}
+time-months-ago = pre { $count } { $count ->
+    [one] mesec
+    [few] meseca
+   *[other] meseci
+}
+time-years-ago = pre { $count } { $count ->
+    [one] godinu
+    [few] godine
+   *[other] godina
+}
+
+## Actions
+
+action-save = Sačuvaj
+action-cannot-save = Ne može da se sačuva
+action-cancel = Otkaži
+action-edit = Izmeni
+action-view = Prikaži
+action-back = Nazad
+action-undo = Poništi
+action-delete = Obriši
+action-delete-contact = Obriši kontakt
+action-add-contact = Dodaj kontakt
+action-quick-add = Dodaj
+action-create-anyway = Ipak napravi
+action-add-relationship = Poveži
+action-add-date = Dodaj datum
+action-remove = Ukloni
+action-upload = Otpremi
+action-show-activity = Aktivnost
+action-companies = Kompanije
+action-upcoming-dates = Predstojeći datumi
+action-export-csv = Izvezi CSV
+action-export-csv-excel = Izvezi CSV (Excel, tačka-zarez)
+action-print-pdf = Odštampaj (PDF)
+action-fix = Ispravi
+action-import = Uvezi
+action-import-more = Uvezi još jedan fajl
+action-check-import = Proveri bez uvoza
+action-change-mapping = Promeni mapiranje
+action-apply = Primeni
+action-trash = Korpa
+action-restore = Vrati
+action-empty-trash = Isprazni korpu
+action-shares = Deljeni linkovi
+action-share = Podeli
+action-revoke = Opozovi
+action-api-keys = API ključevi
+action-create-api-key = Napravi ključ
+
+## Contact list
+
+contact-deleted = { $name } je obrisan/a.
+search-label = Pretraga
+search-button = Traži
+empty-no-contacts = Još nema kontakata
+empty-no-contacts-hint = Kontakti koje dodate ili uvezete pojaviće se ovde.
+empty-no-results = Nijedan kontakt ne odgovara
+empty-no-results-hint = Ništa ne odgovara pretrazi, filterima ili slovu. Probajte druge, ili pogledajte sve kontakte.
+action-create-first-contact = Napravite prvi kontakt
+action-clear-search = Obriši pretragu
+filters = Filteri
+filter-created-after = Napravljen od
+filter-created-before = do pre
+filter-email-domain = Domen imejla
+letter-bar = Pregled po prezimenu
+letter-all = Svi
+loading-more = Učitava se još…
+load-more = Učitaj još
+feed-title = Najnoviji kontakti
+pagination-previous = Prethodna
+pagination-next = Sledeća
+pagination-status = Strana { $page } od { $pages } (ukupno { $total })
+pagination-first = Prva
+pagination-last = Poslednja
+pagination-jump = Idi na stranu
+pagination-go = Idi
+quick-add-label = Brzo dodavanje
+
+## Contact forms
+
+contact-values = Podaci o kontaktu
+contact-created = Kontakt je uspešno napravljen. Preusmeravanje
+contact-in-use = Imejl i/ili broj telefona se već koristi. Kontakt NIJE SAČUVAN
+form-already-submitted = Ovaj formular je već poslat. Kontakt NIJE SAČUVAN
+contact-save-failed = Čuvanje kontakta nije uspelo, pokušajte ponovo. Kontakt NIJE SAČUVAN
+email-and-phone-exist = ⛔ Imejl i broj telefona već postoje u vašim kontaktima
+email-exists = ⛔ Ovaj imejl već postoji u vašim kontaktima
+phone-exists = ⛔ Ovaj broj telefona već postoji u vašim kontaktima
+email-taken = Drugi kontakt već ima ovaj imejl
+phone-taken = Drugi kontakt već ima ovaj broj telefona
+possible-duplicate = Moguć duplikat kontakta { $name } — ipak napraviti?
+delete-confirm-this = Da li sigurno želite da obrišete ovaj kontakt?
+delete-confirm = Da li sigurno želite da obrišete kontakt { $name }?
+
+## List parameters and search syntax
+
+query-too-long = Pojam za pretragu može imati najviše { $max } znakova.
+invalid-date = „{ $value }“ nije ispravan datum za { $name }, koristite GGGG-MM-DD.
+invalid-email-domain = „{ $domain }“ nije ispravan domen imejla.
+invalid-letter = „{ $letter }“ nije slovo od A do Z.
+invalid-page = „{ $page }“ nije ispravan broj strane.
+invalid-page-size = „{ $per_page }“ nije ispravna veličina strane.
+invalid-sort = Kontakti ne mogu da se sortiraju po „{ $sort }“. Koristite nešto od: { $allowed }.
+invalid-sort-direction = „{ $dir }“ nije smer sortiranja, koristite asc ili desc.
+too-many-sort-columns = Kontakti mogu da se sortiraju po najviše { $max } kolone.
+repeated-sort-column = Kontakti se već sortiraju po „{ $sort }“, navedite svaku kolonu jednom.
+too-many-sort-directions = Ima više smerova sortiranja nego kolona.
+stale-page-link = Ovaj link ka strani više ne važi, vratite se na prvu stranu.
+query-email-needs-domain = „{ $token }“ traži domen, na primer email:example.com.
+query-created-needs-date = „{ $token }“ traži datum, na primer created:>=2024-01-01.
+query-no-tags = Kontakti nemaju oznake, pa tag: ne može da se pretražuje.
+query-conflicting-domains = Kontakti ne mogu imati imejl i na { $domain } i na { $other }.
+query-empty-range = Datumi pravljenja ne ostavljaju nijedan dan za pretragu, početak mora biti pre kraja.
+
+## Contact sheets
+
+invalid-contact-ids = „{ $ids }“ nije spisak ID-jeva kontakata, koristite na primer 3,1,4.
+too-many-sheet-contacts = Lista kontakata za štampu može imati najviše { $max } kontakata.
+
+## QR code
+
+qr-code-alt = QR kod sa podacima ovog kontakta
+qr-code-caption = Skenirajte da dodate ovaj kontakt u telefon.
+
+## Data quality report
+
+quality-title = Izveštaj o kvalitetu podataka
+quality-summary = { $issues } od { $checked } kontakata treba proveriti.
+quality-generated = Napravljen { $time }.
+quality-regenerate = Napravi ponovo
+quality-problems = Problemi
+quality-pending = Izveštaj se pravi. Proverite ponovo za trenutak.
+problem-email-missing = Nedostaje imejl
+problem-email-spaces = Imejl sadrži razmake
+problem-email-no-at = Imejl nema @
+problem-email-form = Imejl nije oblika ime@domen
+problem-email-domain = Domen imejla ne izgleda ispravno
+problem-phone-missing = Nedostaje broj telefona
+problem-phone-symbols = Broj telefona sadrži slova ili simbole
+problem-phone-digits = Broj telefona ima premalo ili previše cifara
+problem-name-missing = Nedostaje ime
+problem-first-name-missing = Nedostaje ime
+problem-last-name-missing = Nedostaje prezime
+
+## Relationships
+
+relationships-title = Veze
+relationships-none = Još nije povezan/a ni sa kim.
+relationship-incoming = ({ $label } ovaj kontakt)
+relationship-label = Veza
+relationship-label-placeholder = supružnik od, radi sa, ...
+relationship-related-email = Imejl drugog kontakta
+relationship-label-missing = Navedite kakva je veza, na primer „radi sa”.
+relationship-label-too-long = Veza može imati najviše { $max } znakova.
+relationship-unknown-contact = Ne postoji kontakt sa imejlom { $email }.
+relationship-self = Kontakt ne može biti povezan sam sa sobom.
+relationship-exists = Kontakti su već povezani na taj način.
+
+## Dates
+
+dates-title = Datumi
+dates-none = Još nema datuma.
+date-label = Šta
+date-label-placeholder = godišnjica, obnova, javiti se, ...
+date-date = Datum
+date-yearly = Svake godine
+date-yearly-note = svake godine
+date-label-missing = Navedite šta je taj datum, na primer „godišnjica”.
+date-label-too-long = Opis datuma može imati najviše { $max } znakova.
+date-invalid = Izaberite datum.
+date-exists = Kontakt već ima taj datum.
+upcoming-title = Predstojeći datumi
+upcoming-days = Dana unapred
+upcoming-none = Ništa u { $days ->
+    [1] narednom danu.
+   *[other] narednih { $days } dana.
+}
+upcoming-today = danas
+upcoming-in-days = { $days ->
+    [1] sutra
+   *[other] za { $days } dana
+}
+upcoming-years = { $years } { $years ->
+    [one] godina
+    [few] godine
+   *[other] godina
+}
+
+## Attachments
+
+attachments-title = Prilozi
+attachments-none = Još nema priloženih fajlova.
+attachment-file = Fajl
+attachment-malformed = Otpremanje nije moglo da se pročita, pokušajte ponovo.
+attachment-missing = Izaberite fajl za otpremanje.
+attachment-too-large = Fajlovi mogu imati najviše { $max }.
+attachment-type-not-allowed = Fajlovi tipa { $type } ne mogu da se prilože.
+
+## Companies
+
+companies-title = Kompanije
+companies-members = { $count } { $count ->
+    [one] kontakt
+    [few] kontakta
+   *[other] kontakata
+}
+companies-none = Još nijedan kontakt nema kompaniju.
+
+## Import
+
+import-title = Uvoz kontakata
+import-intro = Otpremite CSV fajl sa kontaktima, do { $max }. Zatim birate koja kolona ide u koje polje.
+import-mapping-intro = Izaberite polje za svaku kolonu ili je zanemarite. Redovi koji nisu ispravni kontakti se preskaču.
+import-saved-mapping = Sačuvano mapiranje
+import-detected-columns = Prepoznate kolone
+import-column = Kolona
+import-sample = Primeri vrednosti
+import-field = Polje
+import-ignore = Zanemari
+import-save-as = Sačuvaj ovo mapiranje kao (nije obavezno)
+import-not-csv = Fajl nije CSV fajl sa redom zaglavlja.
+import-not-utf8 = Fajl nije UTF-8 tekst. Sačuvajte ga kao „CSV UTF-8” i pokušajte ponovo.
+import-expired = To otpremanje je isteklo, otpremite fajl ponovo.
+import-nothing-mapped = Izaberite polje za bar jednu kolonu.
+import-name-too-long = Naziv mapiranja može imati najviše { $max } znakova.
+import-created = { $count ->
+    [one] Napravljen je { $count } kontakt.
+    [few] Napravljena su { $count } kontakta.
+   *[other] Napravljeno je { $count } kontakata.
+}
+import-failed = { $count ->
+    [one] Preskočen je { $count } red
+    [few] Preskočena su { $count } reda
+   *[other] Preskočeno je { $count } redova
+}
+import-dry-run = Još ništa nije uvezeno. Ovo bi uradio uvoz fajla sa ovim mapiranjem.
+import-would-create = { $count ->
+    [one] Bio bi napravljen { $count } kontakt.
+    [few] Bila bi napravljena { $count } kontakta.
+   *[other] Bilo bi napravljeno { $count } kontakata.
+}
+import-would-fail = { $count ->
+    [one] Bio bi preskočen { $count } red
+    [few] Bila bi preskočena { $count } reda
+   *[other] Bilo bi preskočeno { $count } redova
+}
+
+## Trash
+
+trash-title = Korpa
+trash-intro = Obrisani kontakti se ovde čuvaju { $days } { $days ->
+    [one] dan
+   *[other] dana
+} i do tada mogu da se vrate, sa svojim fajlovima i datumima. Njihove veze su izgubljene.
+trash-off = Korpa je isključena: obrisani kontakti nestaju zauvek kada brisanje više ne može da se poništi.
+trash-empty = Korpa je prazna.
+trash-purged-on = Briše se zauvek
+trash-empty-confirm = Zauvek obrisati sve iz korpe?
+trash-restore-failed = { $name } ne može da se vrati: { $reason }
+
+## Share links
+
+shares-title = Deljeni linkovi
+shares-intro = Svako ko ima neki od ovih linkova vidi ono što on deli, bez naloga, dok ne istekne ili ne bude opozvan.
+shares-none = Još ništa nije podeljeno. Kontakt se deli sa njegove stranice, a firma sa stranice firmi.
+shares-shared = Deli se
+shares-link = Link
+shares-status = Stanje
+shares-missing-contact = Obrisan kontakt #{ $id }
+share-expires = Ističe
+share-expires-in = Na { $days } { $days ->
+    [one] dan
+   *[other] dana
+}
+share-expires-never = Nikad
+share-status-active = Aktivan
+share-status-expired = Istekao
+share-status-revoked = Opozvan
+share-revoke-confirm = Opozvati ovaj link? Odmah prestaje da radi.
+share-invalid-expiry = Link može da traje od 1 do { $max } dana, ili dok ne bude opozvan.
+share-unknown-company = Niko ne radi u firmi { $company }, pa nema šta da se podeli.
+shared-download-vcard = Preuzmi kao vCard
+shared-expires = Ovaj link prestaje da radi
+
+## Dashboard
+
+dashboard-title = Pregled
+dashboard-total = Ukupno { $total } { $total ->
+    [one] kontakt
+    [few] kontakta
+   *[other] kontakata
+}.
+dashboard-per-month = Dodato po mesecima
+dashboard-top-domains = Najčešći domeni imejla
+dashboard-recently-added = Nedavno dodati
+dashboard-recently-edited = Nedavno izmenjeni
+dashboard-none = Još nema nijednog.
+
+## Activity
+
+activity-title = Aktivnost
+activity-when = Kada
+activity-change = Izmena
+activity-contact = Kontakt
+activity-actor = Ko
+activity-filter = Filtriraj
+activity-for-contact = Samo izmene kontakta { $name }.
+activity-show-all = Prikaži sve
+activity-only-contact = Samo izmene ovog kontakta
+activity-none = Još ništa nije menjano.
+activity-older = Starije izmene
+activity-created = Dodat
+activity-updated = Izmenjen
+activity-deleted = Obrisan
+activity-restored = Vraćen
+activity-changed = Promenjen
+
+## Settings
+
+settings-title = Podešavanja
+settings-intro = Kako se kontakti prikazuju u ovom pregledaču. Linkovi koji kažu drugačije i dalje imaju prednost.
+settings-per-page = Kontakata po strani
+settings-sort = Sortiraj spisak po
+settings-name-format = Piši imena kao
+sort-id = Redosledu dodavanja
+sort-first-name = Imenu
+sort-last-name = Prezimenu
+sort-email = Imejlu
+sort-company = Kompaniji
+sort-created-at = Datumu kreiranja
+example-first-name = Jovana
+example-last-name = Jovanović
+invalid-per-page-preference = Broj kontakata po strani mora biti od 1 do { $max }.
+
+## API
+
+batch-too-large = Jedna grupa može imati najviše { $max } kontakata.
+batch-too-many-operations = Jedna grupa može imati najviše { $max } operacija.
+batch-contact-not-found = Ne postoji kontakt sa ovim id-jem.
+api-key-missing = Za ovaj API je potreban ključ: pošaljite ga kao "Authorization: Bearer <ključ>".
+api-key-invalid = Ovaj API ključ ne postoji ili je opozvan.
+api-key-rate-limited = Ovaj API ključ je poslao previše zahteva. Sačekajte malo i pokušajte ponovo, vidite "Retry-After".
+api-key-quota-exceeded = Ovaj API ključ je potrošio zahteve za danas. Kvota počinje iznova u ponoć po UTC-u.
+api-if-match-missing = Pošaljite ETag kontakta kao "If-Match", da biste bili sigurni da menjate verziju koju imate.
+api-if-match-stale = Kontakt je izmenjen otkad ste ga preuzeli. Preuzmite ga ponovo i pokušajte sa novim ETag-om.
+
+## API keys
+
+api-keys-title = API ključevi
+api-keys-required = Zahtevima JSON API-ju je potreban jedan od ovih ključeva, poslat kao "Authorization: Bearer <ključ>". Ključevi važe za sve koji koriste ovu aplikaciju.
+api-keys-optional = Zahtevi JSON API-ju mogu da pošalju jedan od ovih ključeva kao "Authorization: Bearer <ključ>", a ključ koji ne radi se odbija. Zahtevi bez ključa prolaze dok je api.require_key isključen.
+api-keys-none = Još nema API ključeva.
+api-keys-name = Naziv
+api-keys-name-placeholder = Čemu služi, na primer "Noćna sinhronizacija"
+api-keys-key = Ključ
+api-keys-last-used = Poslednji put korišćen
+api-keys-never-used = Nikad
+api-keys-revoked = Opozvan
+api-keys-created = Ključ "{ $name }" je napravljen
+api-keys-shown-once = Kopirajte ga sada: ne čuva se i neće biti ponovo prikazan.
+api-keys-revoke-confirm = Opozvati ovaj ključ? Zahtevi sa njim odmah prestaju da rade.
+api-keys-invalid-name = Dajte ključu naziv od najviše { $max } znakova.
+api-keys-invalid-limits = Ograničenja su celi brojevi zahteva, najmanje 1. Ostavite polje prazno za bez ograničenja.
+api-keys-per-minute = Zahteva u minuti
+api-keys-per-day = Zahteva dnevno
+api-keys-no-limit = Bez ograničenja
+api-keys-limits = Ograničenja
+api-keys-used-today = Zahteva danas
+api-keys-limits-help = Ključ može imati brzinu i dnevnu kvotu, koja se računa od ponoći po UTC-u. Zahtevi preko njih dobijaju 429, a odgovori klijentima javljaju stanje u zaglavljima X-RateLimit-*.
+
+## Errors and notices
+
+error-title = Greška
+error-internal-title = Interna greška servera
+error-internal-intro = Došlo je do sledeće greške pri obradi vašeg zahteva:
+error-request-id = Kada prijavljujete ovaj problem, navedite ID zahteva:
+error-validation-title = Neispravan zahtev
+error-conflict-title = Sukob
+error-unauthorized-title = Nije dozvoljeno
+not-found-title = Nije pronađeno
+not-found-message = Ovde nema ničega. Kontakt je možda obrisan, ili link nije ispravan.
+not-found-back = Nazad na kontakte
+form-too-large = Obrazac je prevelik za slanje. Skratite ono što ste uneli i pokušajte ponovo.
+form-invalid = Obrazac nije mogao da se pročita. Ponovo učitajte stranicu i pokušajte opet.
+success-title = Uspeh
+busy-title = Zauzeto
+busy-message = Baza podataka je trenutno zauzeta i vaš zahtev nije mogao da se obradi. Pokušajte ponovo za { $retry_after } { $retry_after ->
+    [one] sekundu
+    [few] sekunde
+   *[other] sekundi
+}.
+server-busy-message = Server trenutno obrađuje previše zahteva. Pokušajte ponovo za { $retry_after } { $retry_after ->
+    [one] sekundu
+    [few] sekunde
+   *[other] sekundi
+}.
+slow-down-title = Polako
+slow-down-message = Šaljete zahteve prebrzo. Pokušajte ponovo za { $retry_after } { $retry_after ->
+    [one] sekundu
+    [few] sekunde
+   *[other] sekundi
+}.
@@ -0,0 +1,11 @@
+-- Add up migration script here
+CREATE TABLE events (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  kind TEXT NOT NULL,
+  actor TEXT NOT NULL,
+  contact_id INTEGER,
+  payload TEXT NOT NULL DEFAULT '{}',
+  created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX events_kind_created_at ON events (kind, created_at);
+CREATE INDEX events_created_at ON events (created_at);
@@ -0,0 +1,4 @@
+-- When the contact was last changed, with millisecond precision so back to back edits still
+-- get different values. Used for ETags.
+ALTER TABLE contacts ADD COLUMN updated_at TEXT NOT NULL DEFAULT '';
+UPDATE contacts SET updated_at = created_at;
@@ -0,0 +1,2 @@
+ALTER TABLE contacts DROP COLUMN job_title;
+ALTER TABLE contacts DROP COLUMN company;
@@ -0,0 +1,3 @@
+-- Where the contact works and what they do there. Empty when not known.
+ALTER TABLE contacts ADD COLUMN company TEXT NOT NULL DEFAULT '';
+ALTER TABLE contacts ADD COLUMN job_title TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,11 @@
+-- Labeled links between two contacts: `contact_id` is `label` `related_id`, like "spouse of".
+-- They go away with either contact.
+CREATE TABLE contact_relationships (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  contact_id INTEGER NOT NULL REFERENCES contacts (id) ON DELETE CASCADE,
+  related_id INTEGER NOT NULL REFERENCES contacts (id) ON DELETE CASCADE,
+  label TEXT NOT NULL,
+  created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  UNIQUE (contact_id, related_id, label)
+);
+CREATE INDEX contact_relationships_related_id ON contact_relationships (related_id);
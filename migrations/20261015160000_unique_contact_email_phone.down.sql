@@ -0,0 +1,2 @@
+DROP INDEX contacts_phone_unique;
+DROP INDEX contacts_email_unique;
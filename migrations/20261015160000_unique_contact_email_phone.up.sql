@@ -0,0 +1,32 @@
+-- No two contacts may share an email or a phone number. Emails are compared ignoring case,
+-- and phone numbers without the usual punctuation, so "206 444 9876" and "(206) 444-9876"
+-- are the same number. Empty values (contacts synced in without one) don't count.
+--
+-- Contacts that already share one with an older contact get theirs tagged first, so the
+-- indexes can be built: "ana+duplicate-12@example.com" and "206 444 9876 (duplicate 12)".
+-- `webone dedupe` ignores the tags, so `webone dedupe --apply` merges them into the older one.
+UPDATE contacts
+SET email = CASE
+  WHEN instr(email, '@') > 0
+    THEN substr(email, 1, instr(email, '@') - 1) || '+duplicate-' || id || substr(email, instr(email, '@'))
+  ELSE email || '+duplicate-' || id
+END
+WHERE email <> '' AND EXISTS (
+  SELECT 1 FROM contacts older WHERE older.id < contacts.id AND lower(older.email) = lower(contacts.email)
+);
+UPDATE contacts
+SET phone_number = phone_number || ' (duplicate ' || id || ')'
+WHERE phone_number <> '' AND EXISTS (
+  SELECT 1 FROM contacts older
+  WHERE older.id < contacts.id
+    AND REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(
+      older.phone_number, ' ', ''), '-', ''), '(', ''), ')', ''), '.', ''), '+', ''), '/', '')
+      = REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(
+      contacts.phone_number, ' ', ''), '-', ''), '(', ''), ')', ''), '.', ''), '+', ''), '/', '')
+);
+
+CREATE UNIQUE INDEX contacts_email_unique ON contacts (lower(email)) WHERE email <> '';
+CREATE UNIQUE INDEX contacts_phone_unique ON contacts (
+  REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(
+    phone_number, ' ', ''), '-', ''), '(', ''), ')', ''), '.', ''), '+', ''), '/', '')
+) WHERE phone_number <> '';
@@ -0,0 +1,13 @@
+-- Files attached to contacts, stored on disk as `stored_name` in the attachments directory.
+-- No foreign key: in demo mode the contacts don't live in this database, so the app removes
+-- a contact's attachments itself when the contact is deleted for good.
+CREATE TABLE attachments (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  contact_id INTEGER NOT NULL,
+  filename TEXT NOT NULL,
+  content_type TEXT NOT NULL,
+  size INTEGER NOT NULL,
+  stored_name TEXT NOT NULL UNIQUE,
+  created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX attachments_contact_id ON attachments (contact_id);
@@ -0,0 +1,14 @@
+-- Deleted contacts, kept for `trash.retention_days` so they can be restored. `id` is the id the
+-- contact had, which it gets back on restore.
+CREATE TABLE trashed_contacts (
+  id INTEGER PRIMARY KEY,
+  first_name TEXT NOT NULL,
+  last_name TEXT NOT NULL,
+  phone_number TEXT NOT NULL,
+  email TEXT NOT NULL,
+  company TEXT NOT NULL,
+  job_title TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  deleted_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX trashed_contacts_deleted_at ON trashed_contacts (deleted_at);
@@ -0,0 +1,13 @@
+-- Read-only links to a contact, or to the contacts of a company, for people who can't use the
+-- app. Exactly one of `contact_id` and `company` is set. No foreign key, like attachments:
+-- a link to a contact that's gone just stops working. Times are UTC.
+CREATE TABLE share_links (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  token TEXT NOT NULL UNIQUE,
+  contact_id INTEGER,
+  company TEXT,
+  created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  expires_at TEXT,
+  revoked_at TEXT,
+  CHECK ((contact_id IS NULL) <> (company IS NULL))
+);
@@ -0,0 +1,11 @@
+-- Keys for the JSON API. Only a SHA-256 hash of each key is stored; `prefix` is its start, to
+-- tell keys apart. Times are UTC.
+CREATE TABLE api_keys (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  name TEXT NOT NULL,
+  prefix TEXT NOT NULL,
+  key_hash TEXT NOT NULL UNIQUE,
+  created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  last_used_at TEXT,
+  revoked_at TEXT
+);
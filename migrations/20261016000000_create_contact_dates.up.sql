@@ -0,0 +1,11 @@
+-- Labeled dates of a contact, like an anniversary or a renewal, as `YYYY-MM-DD`. A `yearly`
+-- date comes back every year, the others happen once. They go away with the contact.
+CREATE TABLE contact_dates (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  contact_id INTEGER NOT NULL REFERENCES contacts (id) ON DELETE CASCADE,
+  label TEXT NOT NULL,
+  date TEXT NOT NULL,
+  yearly INTEGER NOT NULL DEFAULT 0,
+  created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX contact_dates_contact_id ON contact_dates (contact_id);
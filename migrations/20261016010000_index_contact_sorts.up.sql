@@ -0,0 +1,7 @@
+-- Indexes for the contact list's sort columns, so a page is read off the index in order (see
+-- `ContactOrder::terms`). SQLite ends every index with the rowid, which is `id`, the tie
+-- breaker.
+CREATE INDEX contacts_first_name ON contacts (first_name);
+CREATE INDEX contacts_last_name ON contacts (last_name);
+CREATE INDEX contacts_email ON contacts (email);
+CREATE INDEX contacts_created_at ON contacts (created_at);
@@ -0,0 +1,3 @@
+-- The dates a trashed contact had (see `contact_dates`), as a JSON array of `label`, `date` and
+-- `yearly`, so it gets them back on restore.
+ALTER TABLE trashed_contacts ADD COLUMN dates TEXT NOT NULL DEFAULT '[]';
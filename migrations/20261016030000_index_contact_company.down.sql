@@ -0,0 +1 @@
+DROP INDEX contacts_company;
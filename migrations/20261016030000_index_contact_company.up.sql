@@ -0,0 +1,3 @@
+-- The company sort column, see `20261016010000_index_contact_sorts`. Its rowid ending is the
+-- `id` tie breaker.
+CREATE INDEX contacts_company ON contacts (company);
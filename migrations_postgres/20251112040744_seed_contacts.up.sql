@@ -0,0 +1,13 @@
+INSERT INTO contacts (first_name, last_name, phone_number, email) VALUES
+('Alice', 'Anderson', '555-0101', 'alice@example.com'),
+('Bob', 'Brown', '555-0102', 'bob@example.com'),
+('Carol', 'Chen', '555-0103', 'carol@example.com'),
+('David', 'Davis', '555-0104', 'david@example.com'),
+('Emma', 'Evans', '555-0105', 'emma@example.com'),
+('Frank', 'Foster', '555-0106', 'frank@example.com'),
+('Grace', 'Garcia', '555-0107', 'grace@example.com'),
+('Henry', 'Harris', '555-0108', 'henry@example.com'),
+('Iris', 'Ivanov', '555-0109', 'iris@example.com'),
+('Jack', 'Johnson', '555-0110', 'jack@example.com'),
+('Kate', 'Kim', '555-0111', 'kate@example.com'),
+('Leo', 'Lopez', '555-0112', 'leo@example.com');
@@ -0,0 +1,11 @@
+-- Add up migration script here
+CREATE TABLE events (
+  id BIGSERIAL PRIMARY KEY,
+  kind TEXT NOT NULL,
+  actor TEXT NOT NULL,
+  contact_id BIGINT,
+  payload TEXT NOT NULL DEFAULT '{}',
+  created_at TEXT NOT NULL DEFAULT to_char(CURRENT_TIMESTAMP AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS')
+);
+CREATE INDEX events_kind_created_at ON events (kind, created_at);
+CREATE INDEX events_created_at ON events (created_at);
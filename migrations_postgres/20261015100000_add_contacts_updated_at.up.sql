@@ -0,0 +1,5 @@
+-- When the contact was last changed, with millisecond precision so back to back edits still
+-- get different values. Used for ETags.
+ALTER TABLE contacts ADD COLUMN updated_at TEXT NOT NULL
+  DEFAULT to_char(clock_timestamp() AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS.MS');
+UPDATE contacts SET updated_at = created_at;
@@ -0,0 +1,18 @@
+-- Add up migration script here
+CREATE TABLE webhooks (
+  id BIGSERIAL PRIMARY KEY,
+  url TEXT NOT NULL,
+  secret TEXT NOT NULL,
+  created_at TEXT NOT NULL DEFAULT to_char(CURRENT_TIMESTAMP AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS')
+);
+CREATE TABLE webhook_deliveries (
+  id BIGSERIAL PRIMARY KEY,
+  webhook_id BIGINT NOT NULL,
+  url TEXT NOT NULL,
+  event TEXT NOT NULL,
+  status_code BIGINT,
+  outcome TEXT NOT NULL,
+  duration_ms BIGINT NOT NULL,
+  created_at TEXT NOT NULL DEFAULT to_char(CURRENT_TIMESTAMP AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS')
+);
+CREATE INDEX webhook_deliveries_webhook_id ON webhook_deliveries (webhook_id);
@@ -0,0 +1,9 @@
+ALTER TABLE contacts
+  ALTER COLUMN created_at DROP DEFAULT,
+  ALTER COLUMN updated_at DROP DEFAULT;
+ALTER TABLE contacts
+  ALTER COLUMN created_at TYPE TEXT USING to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS'),
+  ALTER COLUMN updated_at TYPE TEXT USING to_char(updated_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS.MS');
+ALTER TABLE contacts
+  ALTER COLUMN created_at SET DEFAULT to_char(CURRENT_TIMESTAMP AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS'),
+  ALTER COLUMN updated_at SET DEFAULT to_char(clock_timestamp() AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS.MS');
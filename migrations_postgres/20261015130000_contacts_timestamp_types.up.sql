@@ -0,0 +1,10 @@
+-- The contact timestamps were text in SQLite's format. Postgres has a real type for them.
+ALTER TABLE contacts
+  ALTER COLUMN created_at DROP DEFAULT,
+  ALTER COLUMN updated_at DROP DEFAULT;
+ALTER TABLE contacts
+  ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at::timestamp AT TIME ZONE 'UTC',
+  ALTER COLUMN updated_at TYPE TIMESTAMPTZ USING updated_at::timestamp AT TIME ZONE 'UTC';
+ALTER TABLE contacts
+  ALTER COLUMN created_at SET DEFAULT CURRENT_TIMESTAMP,
+  ALTER COLUMN updated_at SET DEFAULT clock_timestamp();
@@ -0,0 +1,27 @@
+-- No two contacts may share an email or a phone number. Emails are compared ignoring case,
+-- and phone numbers by their digits only, so "206 444 9876" and "(206) 444-9876" are the same
+-- number. Empty values (contacts synced in without one) don't count.
+--
+-- Contacts that already share one with an older contact get theirs tagged first, so the
+-- indexes can be built: "ana+duplicate-12@example.com" and "206 444 9876 (duplicate 12)".
+-- `webone dedupe` ignores the tags, so `webone dedupe --apply` merges them into the older one.
+UPDATE contacts
+SET email = CASE
+  WHEN position('@' IN email) > 0 THEN regexp_replace(email, '@', '+duplicate-' || id || '@')
+  ELSE email || '+duplicate-' || id
+END
+WHERE email <> '' AND EXISTS (
+  SELECT 1 FROM contacts older WHERE older.id < contacts.id AND lower(older.email) = lower(contacts.email)
+);
+UPDATE contacts
+SET phone_number = phone_number || ' (duplicate ' || id || ')'
+WHERE phone_number <> '' AND EXISTS (
+  SELECT 1 FROM contacts older
+  WHERE older.id < contacts.id
+    AND regexp_replace(older.phone_number, '[^0-9]', '', 'g')
+      = regexp_replace(contacts.phone_number, '[^0-9]', '', 'g')
+);
+
+CREATE UNIQUE INDEX contacts_email_unique ON contacts (lower(email)) WHERE email <> '';
+CREATE UNIQUE INDEX contacts_phone_unique ON contacts (regexp_replace(phone_number, '[^0-9]', '', 'g'))
+  WHERE phone_number <> '';
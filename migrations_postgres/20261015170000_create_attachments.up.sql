@@ -0,0 +1,13 @@
+-- Files attached to contacts, stored on disk as `stored_name` in the attachments directory.
+-- No foreign key: in demo mode the contacts don't live in this database, so the app removes
+-- a contact's attachments itself when the contact is deleted for good.
+CREATE TABLE attachments (
+  id BIGSERIAL PRIMARY KEY,
+  contact_id BIGINT NOT NULL,
+  filename TEXT NOT NULL,
+  content_type TEXT NOT NULL,
+  size BIGINT NOT NULL,
+  stored_name TEXT NOT NULL UNIQUE,
+  created_at TEXT NOT NULL DEFAULT to_char(CURRENT_TIMESTAMP AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS')
+);
+CREATE INDEX attachments_contact_id ON attachments (contact_id);
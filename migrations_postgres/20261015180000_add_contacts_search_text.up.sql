@@ -0,0 +1,4 @@
+-- The searchable fields lowercased and without diacritics, so `jose` finds `José`. Kept up to
+-- date by the app, see `fold::search_text`. NULL until the app fills it in on startup, which
+-- also lets a later migration set it back to NULL when the folding changes.
+ALTER TABLE contacts ADD COLUMN search_text TEXT;
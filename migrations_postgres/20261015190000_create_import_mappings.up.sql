@@ -0,0 +1,8 @@
+-- Column mappings saved on the import page, for importing more files of the same shape.
+-- `columns` is a JSON object from CSV header to contact field, like {"E-mail": "email"}.
+CREATE TABLE import_mappings (
+  id BIGSERIAL PRIMARY KEY,
+  name TEXT NOT NULL UNIQUE,
+  columns TEXT NOT NULL,
+  created_at TEXT NOT NULL DEFAULT to_char(CURRENT_TIMESTAMP AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS')
+);
@@ -0,0 +1,13 @@
+-- Read-only links to a contact, or to the contacts of a company, for people who can't use the
+-- app. Exactly one of `contact_id` and `company` is set. No foreign key, like attachments:
+-- a link to a contact that's gone just stops working. Times are UTC.
+CREATE TABLE share_links (
+  id BIGSERIAL PRIMARY KEY,
+  token TEXT NOT NULL UNIQUE,
+  contact_id BIGINT,
+  company TEXT,
+  created_at TEXT NOT NULL DEFAULT to_char(CURRENT_TIMESTAMP AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS'),
+  expires_at TEXT,
+  revoked_at TEXT,
+  CHECK ((contact_id IS NULL) <> (company IS NULL))
+);
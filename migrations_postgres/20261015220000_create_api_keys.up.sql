@@ -0,0 +1,11 @@
+-- Keys for the JSON API. Only a SHA-256 hash of each key is stored; `prefix` is its start, to
+-- tell keys apart. Times are UTC.
+CREATE TABLE api_keys (
+  id BIGSERIAL PRIMARY KEY,
+  name TEXT NOT NULL,
+  prefix TEXT NOT NULL,
+  key_hash TEXT NOT NULL UNIQUE,
+  created_at TEXT NOT NULL DEFAULT to_char(CURRENT_TIMESTAMP AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS'),
+  last_used_at TEXT,
+  revoked_at TEXT
+);
@@ -0,0 +1,4 @@
+ALTER TABLE api_keys DROP COLUMN quota_used;
+ALTER TABLE api_keys DROP COLUMN quota_day;
+ALTER TABLE api_keys DROP COLUMN requests_per_day;
+ALTER TABLE api_keys DROP COLUMN requests_per_minute;
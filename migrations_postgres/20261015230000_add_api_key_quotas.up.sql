@@ -0,0 +1,6 @@
+-- Limits of each API key, none when NULL: a rate in requests a minute, and a quota of requests
+-- a UTC day. `quota_used` counts the requests of day `quota_day` (a `YYYY-MM-DD`).
+ALTER TABLE api_keys ADD COLUMN requests_per_minute BIGINT;
+ALTER TABLE api_keys ADD COLUMN requests_per_day BIGINT;
+ALTER TABLE api_keys ADD COLUMN quota_day TEXT;
+ALTER TABLE api_keys ADD COLUMN quota_used BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+-- Labeled dates of a contact, like an anniversary or a renewal. A `yearly` date comes back
+-- every year, the others happen once. They go away with the contact.
+CREATE TABLE contact_dates (
+  id BIGSERIAL PRIMARY KEY,
+  contact_id BIGINT NOT NULL REFERENCES contacts (id) ON DELETE CASCADE,
+  label TEXT NOT NULL,
+  date DATE NOT NULL,
+  yearly BOOLEAN NOT NULL DEFAULT FALSE,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX contact_dates_contact_id ON contact_dates (contact_id);
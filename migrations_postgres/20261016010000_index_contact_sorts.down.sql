@@ -0,0 +1,4 @@
+DROP INDEX contacts_created_at;
+DROP INDEX contacts_email;
+DROP INDEX contacts_last_name;
+DROP INDEX contacts_first_name;
@@ -0,0 +1,7 @@
+-- Indexes for the contact list's sort columns and the `id` tie breaker, so a page is read off
+-- the index in order (see `ContactOrder::terms`). `created_at` is sorted to the second, like
+-- the cursor has it.
+CREATE INDEX contacts_first_name ON contacts (first_name, id);
+CREATE INDEX contacts_last_name ON contacts (last_name, id);
+CREATE INDEX contacts_email ON contacts (email, id);
+CREATE INDEX contacts_created_at ON contacts ((date_trunc('second', created_at AT TIME ZONE 'UTC')), id);
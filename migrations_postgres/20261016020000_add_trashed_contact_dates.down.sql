@@ -0,0 +1 @@
+ALTER TABLE trashed_contacts DROP COLUMN dates;
@@ -0,0 +1,2 @@
+-- The company sort column, with the `id` tie breaker, see `20261016010000_index_contact_sorts`.
+CREATE INDEX contacts_company ON contacts (company, id);
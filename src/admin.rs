@@ -0,0 +1,67 @@
+//! Who may use the admin pages and the API key settings. There are no user accounts, so it goes
+//! by the client's address: only clients in `server.admin_allow` get in, by default the
+//! machine the app runs on. Everyone else gets a 404, as if the pages weren't there.
+//!
+//! Behind a proxy with `server.trust_proxy_headers` on, the address is the forwarded client
+//! IP (see `proxy`), so the proxy itself doesn't count as local. With it off, every request
+//! through a proxy on the same machine would come from there, so requests with forwarding
+//! headers are refused whatever their address (see `proxy::UntrustedProxy`). A proxy that
+//! sends none can't be told apart: don't proxy these paths through one.
+//!
+//! The allowed clients' browsers are also open to every other site, so changes only go
+//! through from the app's own pages (see `origin`); a form elsewhere can't add a webhook.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use tracing::warn;
+
+use crate::{origin, proxy::UntrustedProxy, utils::AppError};
+
+/// The networks admin clients may come from, `server.admin_allow`.
+#[derive(Debug, Clone)]
+pub struct AdminAllow(pub Arc<[IpNet]>);
+
+impl AdminAllow {
+    pub fn new(networks: &[IpNet]) -> Self {
+        Self(networks.into())
+    }
+
+    fn allows(&self, addr: &SocketAddr) -> bool {
+        // IPv4 clients of a dual-stack socket show up as `::ffff:a.b.c.d`
+        let ip = addr.ip().to_canonical();
+        self.0.iter().any(|network| network.contains(&ip))
+    }
+}
+
+/// Middleware: lets requests from the allowed networks through and answers the others with a
+/// 404. Requests without a client address, like ones driven straight into the router, and
+/// ones through a proxy that isn't trusted are turned away too, and changes sent from another
+/// site's page get a 403.
+///
+/// Example usage:
+/// `router.layer(middleware::from_fn_with_state(admin_allow, admin::guard))`
+pub async fn guard(State(allow): State<AdminAllow>, request: Request, next: Next) -> Response {
+    if request.extensions().get::<UntrustedProxy>().is_some() {
+        warn!(
+            "Admin request to {} refused: it came through a proxy, and server.trust_proxy_headers is off",
+            request.uri().path()
+        );
+        return AppError::NotFound.into_response();
+    }
+    match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) if allow.allows(addr) => match origin::refuse_cross_site(&request) {
+            Some(refused) => refused,
+            None => next.run(request).await,
+        },
+        addr => {
+            warn!("Admin request to {} from {:?} refused", request.uri().path(), addr.map(|a| a.0.ip()));
+            AppError::NotFound.into_response()
+        }
+    }
+}
@@ -0,0 +1,469 @@
+//! Keys for the JSON API, made and revoked on the settings page.
+//!
+//! A key is sent as `Authorization: Bearer <key>` and shown only once, when it's made: the
+//! database has a SHA-256 hash of it and its first characters, so a leaked database doesn't
+//! leak the keys. There are no user accounts, so a key belongs to the whole app, not to
+//! whoever made it. With `api.require_key` every API request needs a working key, otherwise
+//! only the ones that send one are checked.
+//!
+//! A key can have a rate, in requests a minute, and a quota of requests a UTC day. `authorize`
+//! checks both for every request with the key and answers `429` once one is used up. The day's
+//! count is kept in the database, so it survives restarts; the rate is in memory.
+//!
+//! Checking a key only reads the database. When a key was last used and the requests towards
+//! its quota are counted in `KeyUsage` and written every `USAGE_FLUSH_INTERVAL`, so API reads
+//! don't turn into writes. A crash loses at most that long's worth of counts.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    Json,
+    extract::{FromRef, FromRequestParts, Request, State},
+    http::{HeaderName, HeaderValue, StatusCode, header, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::{
+    clock::SharedClock,
+    config::Config,
+    db::Db,
+    i18n,
+    rate_limit::{self, KeyRateLimiter, Usage},
+    utils::AppError,
+};
+#[cfg(feature = "postgres")]
+use crate::postgres;
+
+/// What every key starts with, to recognize one in a config file or a leak scanner.
+pub const KEY_PREFIX: &str = "wo_";
+/// Longest name a key can be given.
+pub const MAX_NAME_LEN: usize = 100;
+/// How much of a key is kept to tell it apart: `KEY_PREFIX` and 8 more characters.
+const SHOWN_PREFIX_LEN: usize = KEY_PREFIX.len() + 8;
+/// How the timestamps are written, in UTC, like event timestamps.
+const KEY_AT_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// How `quota_day` is written: the UTC day.
+const QUOTA_DAY_FORMAT: &str = "%Y-%m-%d";
+/// How often the use of the keys is written to the database, see `KeyUsage`.
+pub const USAGE_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// An API key, without the key itself.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    /// The start of the key, like `wo_1a2b3c4d`.
+    pub prefix: String,
+    /// Hex SHA-256 of the key.
+    pub key_hash: String,
+    /// As `KEY_AT_FORMAT`.
+    pub created_at: String,
+    /// When the key last got a request in, as `KEY_AT_FORMAT`.
+    pub last_used_at: Option<String>,
+    /// As `KEY_AT_FORMAT`.
+    pub revoked_at: Option<String>,
+    /// Most requests a minute, if limited.
+    pub requests_per_minute: Option<i64>,
+    /// Most requests a UTC day, if limited.
+    pub requests_per_day: Option<i64>,
+    /// The day `quota_used` counts the requests of, as `QUOTA_DAY_FORMAT`.
+    pub quota_day: Option<String>,
+    pub quota_used: i64,
+}
+
+/// The limits of a key, none when `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyLimits {
+    pub requests_per_minute: Option<i64>,
+    pub requests_per_day: Option<i64>,
+}
+
+impl KeyLimits {
+    /// Limits as typed into the settings page: empty for none, otherwise a positive number.
+    /// `None` if one isn't.
+    ///
+    /// Example usage:
+    /// `KeyLimits::parse("60", "")` is a rate of 60 requests a minute and no daily quota
+    pub fn parse(requests_per_minute: &str, requests_per_day: &str) -> Option<Self> {
+        let limit = |value: &str| match value.trim() {
+            "" => Some(None),
+            value => value.parse::<i64>().ok().filter(|limit| *limit > 0).map(Some),
+        };
+        Some(KeyLimits {
+            requests_per_minute: limit(requests_per_minute)?,
+            requests_per_day: limit(requests_per_day)?,
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn hash(key: &str) -> String {
+    hex(&Sha256::digest(key.as_bytes()))
+}
+
+fn parse_time(at: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(at, KEY_AT_FORMAT).ok().map(|at| at.and_utc())
+}
+
+impl ApiKey {
+    pub fn created(&self) -> Option<DateTime<Utc>> {
+        parse_time(&self.created_at)
+    }
+
+    pub fn last_used(&self) -> Option<DateTime<Utc>> {
+        self.last_used_at.as_deref().and_then(parse_time)
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+
+    /// Requests made with the key on the UTC day of `now`.
+    pub fn used_on(&self, now: DateTime<Utc>) -> i64 {
+        let today = now.format(QUOTA_DAY_FORMAT).to_string();
+        if self.quota_day.as_ref() == Some(&today) { self.quota_used } else { 0 }
+    }
+
+    /// Where the key stands against its daily quota at `now`, if it has one, after `used`
+    /// requests today.
+    fn daily_usage(&self, used: i64, now: DateTime<Utc>) -> Option<Usage> {
+        let limit = self.requests_per_day?;
+        let tomorrow = (now.date_naive() + TimeDelta::days(1)).and_hms_opt(0, 0, 0)?.and_utc();
+        Some(Usage {
+            limit,
+            remaining: (limit - used).max(0),
+            reset: (tomorrow - now).to_std().unwrap_or_default(),
+        })
+    }
+
+    /// Makes a key called `name` with `limits`. Returns it with the key itself, which can't be
+    /// had again.
+    pub async fn create(
+        db: &Db,
+        name: &str,
+        limits: KeyLimits,
+        now: DateTime<Utc>,
+    ) -> Result<(ApiKey, String), sqlx::Error> {
+        let key = format!("{KEY_PREFIX}{}", hex(&rand::random::<[u8; 32]>()));
+        let prefix = &key[..SHOWN_PREFIX_LEN];
+        let key_hash = hash(&key);
+        let created_at = now.format(KEY_AT_FORMAT).to_string();
+        let api_key = match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    ApiKey,
+                    r#"INSERT INTO api_keys (name, prefix, key_hash, created_at, requests_per_minute, requests_per_day)
+                    VALUES (?, ?, ?, ?, ?, ?)
+                    RETURNING id AS "id!", name, prefix, key_hash, created_at, last_used_at, revoked_at,
+                        requests_per_minute, requests_per_day, quota_day, quota_used"#,
+                    name,
+                    prefix,
+                    key_hash,
+                    created_at,
+                    limits.requests_per_minute,
+                    limits.requests_per_day,
+                )
+                .fetch_one(pool)
+                .await?
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::create_api_key(pool, name, prefix, &key_hash, &created_at, limits).await?,
+        };
+        Ok((api_key, key))
+    }
+
+    /// Every key, newest first.
+    pub async fn list(db: &Db) -> Result<Vec<ApiKey>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    ApiKey,
+                    r#"SELECT id AS "id!", name, prefix, key_hash, created_at, last_used_at, revoked_at,
+                        requests_per_minute, requests_per_day, quota_day, quota_used
+                    FROM api_keys ORDER BY id DESC"#
+                )
+                .fetch_all(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::list_api_keys(pool).await,
+        }
+    }
+
+    /// Revokes key `id` as of `now`. Returns the key, or `None` if there is no such key or it
+    /// was revoked already.
+    pub async fn revoke(db: &Db, id: i64, now: DateTime<Utc>) -> Result<Option<ApiKey>, sqlx::Error> {
+        let revoked_at = now.format(KEY_AT_FORMAT).to_string();
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    ApiKey,
+                    r#"UPDATE api_keys SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL
+                    RETURNING id AS "id!", name, prefix, key_hash, created_at, last_used_at, revoked_at,
+                        requests_per_minute, requests_per_day, quota_day, quota_used"#,
+                    revoked_at,
+                    id,
+                )
+                .fetch_optional(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::revoke_api_key(pool, id, &revoked_at).await,
+        }
+    }
+
+    /// Gives key `id` new `limits`. Returns the key, or `None` if there is no such key or it
+    /// was revoked.
+    pub async fn set_limits(db: &Db, id: i64, limits: KeyLimits) -> Result<Option<ApiKey>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    ApiKey,
+                    r#"UPDATE api_keys SET requests_per_minute = ?, requests_per_day = ?
+                    WHERE id = ? AND revoked_at IS NULL
+                    RETURNING id AS "id!", name, prefix, key_hash, created_at, last_used_at, revoked_at,
+                        requests_per_minute, requests_per_day, quota_day, quota_used"#,
+                    limits.requests_per_minute,
+                    limits.requests_per_day,
+                    id,
+                )
+                .fetch_optional(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::set_api_key_limits(pool, id, limits).await,
+        }
+    }
+
+    /// The key `key` is, if it hasn't been revoked. Only reads, see `KeyUsage` for counting
+    /// the request.
+    pub async fn authenticate(db: &Db, key: &str) -> Result<Option<ApiKey>, sqlx::Error> {
+        let key_hash = hash(key);
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    ApiKey,
+                    r#"SELECT id AS "id!", name, prefix, key_hash, created_at, last_used_at, revoked_at,
+                        requests_per_minute, requests_per_day, quota_day, quota_used
+                    FROM api_keys WHERE key_hash = ? AND revoked_at IS NULL"#,
+                    key_hash,
+                )
+                .fetch_optional(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::authenticate_api_key(pool, &key_hash).await,
+        }
+    }
+
+    /// Marks key `id` as last used at `used_at` and adds `requests` to its count for `day`,
+    /// starting it over if the count was for an earlier day.
+    async fn record_use(db: &Db, id: i64, used_at: &str, day: &str, requests: i64) -> Result<(), sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query!(
+                    r#"UPDATE api_keys SET last_used_at = ?1,
+                        quota_used = CASE WHEN quota_day = ?2 THEN quota_used + ?3 ELSE ?3 END, quota_day = ?2
+                    WHERE id = ?4"#,
+                    used_at,
+                    day,
+                    requests,
+                    id,
+                )
+                .execute(pool)
+                .await?;
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::record_api_key_use(pool, id, used_at, day, requests).await?,
+        }
+        Ok(())
+    }
+}
+
+/// Requests made with each key that aren't in the database yet, written by `flush`. Clones
+/// share the counts.
+#[derive(Debug, Clone, Default)]
+pub struct KeyUsage {
+    pending: Arc<Mutex<HashMap<i64, PendingUse>>>,
+}
+
+#[derive(Debug)]
+struct PendingUse {
+    /// The UTC day `requests` were made on, as `QUOTA_DAY_FORMAT`.
+    day: String,
+    requests: i64,
+    /// As `KEY_AT_FORMAT`.
+    last_used_at: String,
+}
+
+impl KeyUsage {
+    /// Counts a request with `key` at `now`. Returns how many requests the key has had on the
+    /// UTC day of `now`, this one included.
+    pub fn record(&self, key: &ApiKey, now: DateTime<Utc>) -> i64 {
+        let today = now.format(QUOTA_DAY_FORMAT).to_string();
+        let last_used_at = now.format(KEY_AT_FORMAT).to_string();
+        let mut pending = self.pending.lock().unwrap();
+        let entry = pending.entry(key.id).or_insert_with(|| PendingUse {
+            day: today.clone(),
+            requests: 0,
+            last_used_at: last_used_at.clone(),
+        });
+        if entry.day != today {
+            // The earlier day's requests don't count towards anything any more
+            entry.day = today;
+            entry.requests = 0;
+        }
+        entry.requests += 1;
+        entry.last_used_at = last_used_at;
+        key.used_on(now) + entry.requests
+    }
+
+    /// Writes the counted requests to the database. Counts that fail to be written are put
+    /// back for the next time.
+    pub async fn flush(&self, db: &Db) {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        for (id, used) in pending {
+            if let Err(e) = ApiKey::record_use(db, id, &used.last_used_at, &used.day, used.requests).await {
+                warn!("Failed to record the use of API key {id}: {e}");
+                let mut pending = self.pending.lock().unwrap();
+                match pending.get_mut(&id) {
+                    Some(newer) if newer.day == used.day => newer.requests += used.requests,
+                    Some(_) => {}
+                    None => {
+                        pending.insert(id, used);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Flushes the counts every `USAGE_FLUSH_INTERVAL` until the process exits.
+    pub fn spawn_flusher(&self, db: Db) {
+        let usage = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(USAGE_FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                usage.flush(&db).await;
+            }
+        });
+    }
+}
+
+/// The key the request was sent with, if any, as `authorize` let it through. Taken by the
+/// JSON API handlers, so none of them can be served without `authorize` in front of it.
+#[derive(Debug, Clone)]
+pub struct ApiAccess(pub Option<ApiKey>);
+
+impl<S: Send + Sync> FromRequestParts<S> for ApiAccess {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<ApiAccess>().cloned().ok_or_else(|| {
+            AppError::from(anyhow::anyhow!("The API route has no api_keys::authorize in front of it")).into_response()
+        })
+    }
+}
+
+/// Middleware for the JSON API: checks the key the request was sent with and its limits. A
+/// key that's unknown or revoked, or none when `api.require_key` is on, is a `401`, and a key
+/// that used up its rate or quota a `429` with `Retry-After`, both with the `error` in JSON.
+/// Answers with a key have `X-RateLimit-*` headers for the limit that has the fewest requests
+/// left.
+///
+/// Example usage:
+/// `router.layer(middleware::from_fn_with_state(state, api_keys::authorize::<AppState>))`
+pub async fn authorize<S>(State(state): State<S>, mut request: Request, next: Next) -> Response
+where
+    S: Send + Sync,
+    Arc<Config>: FromRef<S>,
+    Db: FromRef<S>,
+    SharedClock: FromRef<S>,
+    KeyRateLimiter: FromRef<S>,
+    KeyUsage: FromRef<S>,
+{
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|key| key.trim().to_string());
+    let Some(key) = bearer else {
+        if Arc::<Config>::from_ref(&state).api.require_key {
+            return unauthorized("api-key-missing");
+        }
+        request.extensions_mut().insert(ApiAccess(None));
+        return next.run(request).await;
+    };
+    let now = SharedClock::from_ref(&state).now();
+    let api_key = match ApiKey::authenticate(&Db::from_ref(&state), &key).await {
+        Ok(Some(api_key)) => api_key,
+        Ok(None) => return unauthorized("api-key-invalid"),
+        Err(e) => return AppError::from(e).into_response(),
+    };
+    let used_today = KeyUsage::from_ref(&state).record(&api_key, now);
+
+    let mut usages = Vec::new();
+    if let Some(usage) = api_key.daily_usage(used_today, now) {
+        if used_today > usage.limit {
+            info!("API key {} ({}) is over its daily quota", api_key.id, api_key.name);
+            return too_many_requests(usage, usage.reset, "api-key-quota-exceeded");
+        }
+        usages.push(usage);
+    }
+    if let Some(per_minute) = api_key.requests_per_minute {
+        match KeyRateLimiter::from_ref(&state).check(api_key.id, per_minute) {
+            Ok(usage) => usages.push(usage),
+            Err((wait, usage)) => {
+                info!("API key {} ({}) is over its rate", api_key.id, api_key.name);
+                return too_many_requests(usage, wait, "api-key-rate-limited");
+            }
+        }
+    }
+
+    info!("API request with key {} ({})", api_key.id, api_key.name);
+    request.extensions_mut().insert(ApiAccess(Some(api_key)));
+    let mut response = next.run(request).await;
+    if let Some(usage) = usages.into_iter().min_by_key(|usage| usage.remaining) {
+        rate_limit_headers(&mut response, usage);
+    }
+    response
+}
+
+fn unauthorized(message: &str) -> Response {
+    AppError::Unauthorized(i18n::t(message)).into_response()
+}
+
+fn too_many_requests(usage: Usage, wait: Duration, message: &str) -> Response {
+    let retry_after = rate_limit::retry_after_secs(wait).max(1);
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after)],
+        Json(json!({ "error": i18n::t(message) })),
+    )
+        .into_response();
+    rate_limit_headers(&mut response, usage);
+    response
+}
+
+/// `X-RateLimit-Limit`, `X-RateLimit-Remaining` and `X-RateLimit-Reset`, the seconds until the
+/// whole limit can be used again.
+fn rate_limit_headers(response: &mut Response, usage: Usage) {
+    let reset = rate_limit::retry_after_secs(usage.reset);
+    let headers = response.headers_mut();
+    headers.insert(HeaderName::from_static("x-ratelimit-limit"), HeaderValue::from(usage.limit));
+    headers.insert(HeaderName::from_static("x-ratelimit-remaining"), HeaderValue::from(usage.remaining));
+    headers.insert(HeaderName::from_static("x-ratelimit-reset"), HeaderValue::from(reset));
+}
@@ -0,0 +1,3824 @@
+//! The web app: its state, handlers and routes, and the server around them.
+//!
+//! `build_router` makes the whole app as a `Router`, for embedding it in another axum server
+//! or driving it from integration tests with `tower::ServiceExt::oneshot`. `serve` is what
+//! `webone serve` runs: the same app, listening on `server.listen` until shutdown.
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use askama::Template;
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::{DefaultBodyLimit, FromRef, OriginalUri, Path, Query, Request, State, rejection::JsonRejection},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    middleware,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{any, delete, get, post},
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tower::{Layer, ServiceExt as _};
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    set_header::SetResponseHeaderLayer,
+    trace::{DefaultOnResponse, TraceLayer},
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::io::ReaderStream;
+use tracing::{Level, error, info, warn};
+use crate::templates::ErrorMessageTemplate;
+use crate::templates::SuccessRedirectTemplate;
+use crate::templates::AdminBackupsTemplate;
+use crate::templates::AdminEventsTemplate;
+use crate::templates::AdminJobsTemplate;
+use crate::templates::QualityReportTemplate;
+use crate::templates::DashboardTemplate;
+use crate::templates::ActivityTemplate;
+use crate::templates::SettingsTemplate;
+use crate::templates::CompaniesTemplate;
+use crate::templates::{ImportFailure, ImportMappingTemplate, ImportResultTemplate, ImportTemplate};
+use crate::templates::TestEmailTemplate;
+use crate::templates::AdminWebhooksTemplate;
+use crate::templates::DavMultistatusTemplate;
+use crate::templates::{ShareRow, SharedContactsTemplate, SharesTemplate};
+use crate::templates::ApiKeysTemplate;
+use crate::{
+    admin::{self, AdminAllow},
+    api_keys::{self, ApiAccess, ApiKey, KeyLimits, KeyUsage},
+    assets::{self, Assets},
+    atom,
+    attachments::{self, Attachment, Attachments, UploadError},
+    backup::{self, BackupJob, Retention},
+    batch::{self, Operation, OperationResult, RejectionReason},
+    base_path,
+    carddav::{self, DavResource, Report},
+    clock::{SharedClock, SystemClock},
+    config::{Cli, Config},
+    contacts::{Contact, ContactCursor, ContactError, ContactPage, ContactOrder, ContactPatch, ContactSort, ContactWrite, NewContact, NewContactErrors},
+    dates::{self, ContactDate},
+    db::{Db, MEMORY_URL, MaybeBusy, PoolSettings, PoolStats, SqlitePragmas, StatementSettings},
+    deletions::PendingDeletions,
+    duplicates,
+    email::{self, Email, EmailJob, Mailer},
+    error_reporting,
+    etag::{ETag, Precondition},
+    events::{self, ActivityFilter, Actor, Event, EventFilter, PurgeEventsJob},
+    export::{self, CsvDialect},
+    forms::{self, FORM_BODY_LIMIT, Form, FormTokens},
+    gravatar,
+    htmx::Htmx,
+    i18n,
+    ids::{SharedIdGenerator, UuidGenerator},
+    import::{self, ColumnMapping, ContactField, ImportResult, PendingImports, SavedMapping},
+    jobs::{JOB_WORKERS, Jobs},
+    ldap::{self, LdapImportJob},
+    live::{LiveChange, LiveUpdates},
+    load_shed::{self, ConcurrencyLimit},
+    logging,
+    meilisearch::MeilisearchSearch,
+    memory::MemoryContactRepository,
+    pagination::Pagination,
+    params::{self, ListParams},
+    names::NameFormat,
+    origin,
+    preferences::{self, Preferences},
+    proxy::{self, TrustProxyHeaders},
+    pdf,
+    qr::QrCode,
+    quality::QualityReports,
+    rate_limit::{self, KeyRateLimiter, RateLimiter},
+    relationships::{self, Relationship},
+    reload::{self, LiveConfig},
+    repository::SharedContactRepository,
+    search::{DatabaseSearch, FuzzySearch, SharedSearchBackend},
+    search_cache::CachedContactRepository,
+    shares::{self, ShareLink, ShareStatus, ShareTarget},
+    tenants::{self, TenancyMode, Tenants, TooManyTenants, UnknownTenant},
+    templates::{self, AttachmentsTemplate, ContactEditRowTemplate, ContactRowTemplate, ContactRowsPageTemplate, ContactRowsTemplate, DatesTemplate, DeleteContactTemplate, DuplicateWarningTemplate, EditContactTemplate, EmptyState, IndexTemplate, NewContactTemplate, QuickAdd, QuickAddTemplate, QuickAddedTemplate, RelationshipsTemplate, ShowContactTemplate, TrashTemplate, UpcomingDatesTemplate},
+    trash::{self, PurgeTrashJob, TrashedContact},
+    utils::{self, AppError, safe_return_to},
+    vcard,
+    webhooks::{self, Webhook, WebhookDelivery, WebhookJob},
+};
+
+/// What every handler gets: the settings, the database and the background services on it.
+#[derive(Clone)]
+pub struct AppState {
+    /// Reloaded on SIGHUP, see `reload`.
+    config: LiveConfig,
+    db: Db,
+    contacts: SharedContactRepository,
+    search: SharedSearchBackend,
+    clock: SharedClock,
+    pending_deletions: PendingDeletions,
+    attachments: Attachments,
+    form_tokens: FormTokens,
+    quality_reports: QualityReports,
+    jobs: Jobs,
+    live: LiveUpdates,
+    imports: PendingImports,
+    /// Rates of API keys, see `api_keys::authorize`.
+    api_limits: KeyRateLimiter,
+    /// Use of API keys not written to the database yet.
+    api_usage: KeyUsage,
+}
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.get()
+    }
+}
+impl FromRef<AppState> for Db {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+impl FromRef<AppState> for SharedClock {
+    fn from_ref(state: &AppState) -> Self {
+        state.clock.clone()
+    }
+}
+impl FromRef<AppState> for KeyRateLimiter {
+    fn from_ref(state: &AppState) -> Self {
+        state.api_limits.clone()
+    }
+}
+impl FromRef<AppState> for KeyUsage {
+    fn from_ref(state: &AppState) -> Self {
+        state.api_usage.clone()
+    }
+}
+#[derive(Deserialize, Debug)]
+struct ReturnToParams {
+    return_to: Option<String>,
+}
+#[derive(Deserialize, Debug)]
+struct EditContactForm {
+    #[serde(flatten)]
+    contact: NewContact,
+    return_to: Option<String>,
+}
+#[derive(Deserialize, Debug)]
+struct PatchContactForm {
+    #[serde(flatten)]
+    patch: ContactPatch,
+    return_to: Option<String>,
+}
+#[derive(Deserialize, Debug)]
+struct NewContactForm {
+    #[serde(flatten)]
+    contact: NewContact,
+    /// Set by the "Create anyway" button, to go ahead despite a possible duplicate.
+    create_anyway: Option<String>,
+}
+#[derive(Deserialize, Debug)]
+struct RelationshipForm {
+    related_email: String,
+    label: String,
+}
+#[derive(Deserialize, Debug)]
+struct DateForm {
+    label: String,
+    /// `YYYY-MM-DD`, as a date input sends it.
+    date: String,
+    /// Set by the "Every year" checkbox.
+    yearly: Option<String>,
+}
+#[derive(Deserialize, Debug)]
+struct ApiKeyForm {
+    name: String,
+    #[serde(flatten)]
+    limits: ApiKeyLimitsForm,
+}
+/// Limits of an API key as typed in, empty for none. See `KeyLimits::parse`.
+#[derive(Deserialize, Debug)]
+struct ApiKeyLimitsForm {
+    #[serde(default)]
+    requests_per_minute: String,
+    #[serde(default)]
+    requests_per_day: String,
+}
+#[derive(Deserialize, Debug)]
+struct ShareForm {
+    /// The contact to share, or
+    contact_id: Option<i64>,
+    /// the company whose contacts to share.
+    company: Option<String>,
+    /// Empty for a link that works until it's revoked.
+    #[serde(default)]
+    expires_in_days: String,
+}
+#[derive(Deserialize, Debug)]
+struct UpcomingDatesParams {
+    /// Days ahead to look, `dates::UPCOMING_DAYS` if not given.
+    days: Option<u32>,
+}
+#[derive(Deserialize, Debug)]
+struct ContactSheetParams {
+    /// Comma separated contact ids, in the order they are printed.
+    #[serde(default)]
+    ids: String,
+}
+#[derive(Deserialize, Debug)]
+struct ExportParams {
+    #[serde(default)]
+    dialect: CsvDialect,
+}
+#[derive(Serialize, Debug)]
+struct HealthStatus {
+    status: &'static str,
+    database: String,
+}
+#[derive(Serialize, Debug)]
+struct ReadinessStatus {
+    status: &'static str,
+    database: String,
+    /// Migrations of this build missing from the database, see `Db::pending_migrations`.
+    pending_migrations: Vec<i64>,
+    pool: PoolUsage,
+}
+#[derive(Serialize, Debug)]
+struct PoolUsage {
+    connections: u32,
+    idle_connections: usize,
+    max_connections: u32,
+}
+#[derive(Serialize, Debug)]
+struct ContactListResponse {
+    contacts: Vec<Contact>,
+    page: i64,
+    per_page: i64,
+    total: i64,
+    /// Where the next page is, or `None` on the last one.
+    next: Option<String>,
+}
+#[derive(Serialize, Debug)]
+struct BatchResponse {
+    created: usize,
+    failed: usize,
+    results: Vec<ImportResult>,
+}
+#[derive(Serialize, Debug)]
+struct OperationsResponse {
+    /// Whether the changes were made, all of them, or none was.
+    applied: bool,
+    results: Vec<OperationResult>,
+}
+#[derive(Serialize, Debug)]
+struct DbStatus {
+    #[serde(flatten)]
+    pool: PoolStats,
+    max_connections: u32,
+    statement_cache_capacity: usize,
+}
+#[derive(Deserialize, Debug)]
+struct ValidateParams {
+    email: Option<String>,
+    phone_number: Option<String>,
+    /// The contact being edited, whose own email and phone number aren't duplicates.
+    exclude_id: Option<i64>,
+}
+
+async fn index() -> impl IntoResponse {
+    Redirect::permanent(&base_path::url("/contacts"))
+}
+
+/// Fallback for routes that don't exist: the same 404 page as a missing contact.
+///
+/// Example usage:
+/// `GET /nothing-here`
+async fn not_found() -> AppError {
+    AppError::NotFound
+}
+
+#[derive(Debug, Deserialize)]
+struct LocaleForm {
+    lang: String,
+}
+
+/// Switches the language: remembers the picked locale in the `lang` cookie for a year and goes
+/// back to the page the switcher was on. Unknown locales are ignored.
+///
+/// Example usage:
+/// `curl -X POST -d lang=sr -H 'Referer: http://localhost:2911/contacts' http://localhost:2911/locale`
+#[axum::debug_handler]
+async fn set_locale(headers: HeaderMap, Form(form): Form<LocaleForm>) -> Response {
+    // Only the path of the referring page is kept, so this can't redirect off the site
+    let back = headers
+        .get(header::REFERER)
+        .and_then(|referer| referer.to_str().ok())
+        .and_then(|referer| referer.parse::<axum::http::Uri>().ok())
+        .and_then(|uri| uri.path_and_query().map(|path| path.as_str().to_string()))
+        .filter(|path| path.starts_with('/') && !path.starts_with("//"))
+        .unwrap_or_else(|| base_path::url("/contacts"));
+    let redirect = Redirect::to(&back);
+    match i18n::supported(&form.lang) {
+        Some(locale) => {
+            let cookie = format!(
+                "{}={locale}; Path={}; Max-Age=31536000; SameSite=Lax",
+                i18n::LOCALE_COOKIE,
+                base_path::cookie_path()
+            );
+            ([(header::SET_COOKIE, cookie)], redirect).into_response()
+        }
+        None => redirect.into_response(),
+    }
+}
+
+
+/// Template function: Gets all contacts and renders them to the HTML. Limits the amount of
+/// contacts displayed based on `per_page`, or the configured `page_size` if not given.
+/// Invalid parameters are rejected with a 400 by the `ListParams` extractor. Answers 304 if the
+/// client's `If-None-Match` matches the rows, paging, pending deletions and A–Z counts.
+/// Active search requests from htmx (aimed at `#contact-rows`) only get the table rows back.
+///
+/// Example usage:
+/// `/contacts?q=smith&page=2&per_page=25&sort=last_name`
+/// `/contacts?q=smith+email:example.com+created:>=2024-01-01`
+/// `/contacts?email_domain=example.com&created_after=2024-01-01&created_before=2024-02-01`
+/// `/contacts?letter=G`
+#[axum::debug_handler]
+async fn contacts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    htmx: Htmx,
+    params: ListParams,
+) -> Result<Response, AppError> {
+    let (contacts, pagination) = list_page(&state, &params, &base_path::url("/contacts")).await?;
+    let letters = state.contacts.letter_counts(&params.filter).await?;
+    let filters = params.filters();
+    let ListParams { per_page, sort, filter, .. } = params;
+    let search = filter.text.unwrap_or_default();
+    let total = pagination.total;
+    let empty_state = EmptyState::of(contacts.len(), total, &filters);
+    let pending_deletions = state.pending_deletions.pending();
+
+    // Boosted links, history restores and the success redirect send `HX-Request` too, but they
+    // target the whole page
+    let rows_only = htmx.targets("contact-rows");
+    let rows: Vec<(i64, DateTime<Utc>)> = contacts.iter().map(|c| (c.id, c.updated_at)).collect();
+    let pending: Vec<i64> = pending_deletions.iter().map(|c| c.id).collect();
+    let etag = ETag::of(&(pagination.current_url(), per_page, total, rows, pending, &letters, rows_only));
+    if etag.matches(&headers) {
+        return Ok(vary_on_htmx(etag.not_modified()));
+    }
+
+    // PROCESS TEMPLATE
+    let html = if rows_only {
+        ContactRowsTemplate {
+            return_to: pagination.current_url(),
+            filters,
+            search,
+            contacts,
+            pagination,
+            sort,
+            letters,
+            empty_state,
+        }
+        .render()?
+    } else {
+        IndexTemplate {
+            return_to: pagination.current_url(),
+            filters,
+            search,
+            contacts,
+            pagination,
+            sort,
+            pending_deletions,
+            quick_add: QuickAdd::default(),
+            letters,
+            empty_state,
+        }
+        .render()?
+    };
+    Ok(vary_on_htmx(etag.attach((StatusCode::OK, Html(html)).into_response())))
+}
+
+/// One page of the contact list as selected by `params`, with a pager whose links point at
+/// `base_url`.
+async fn list_page(
+    state: &AppState,
+    params: &ListParams,
+    base_url: &str,
+) -> Result<(Vec<Contact>, Pagination), AppError> {
+    let ListParams { page, per_page, sort, after, filter, .. } = params;
+    let (per_page, sort) = (*per_page, *sort);
+    // The rows and the total, which comes with the first page and is cached for the others
+    // (see `search_cache`)
+    let fetch = async |page| -> Result<ContactPage, AppError> {
+        Ok(if filter.is_empty() {
+            state.contacts.search_page(filter, page, per_page, sort, after.as_ref()).await?
+        } else {
+            state.search.search_page(filter, page, per_page, sort, after.as_ref()).await?
+        })
+    };
+    let ContactPage { contacts, total } = fetch(*page).await?;
+    // A page past the end (`?page=9999`, or an old link after deletions) shows the last page
+    // instead of an empty one. Pages fetched by cursor are where the cursor says.
+    let last_page = Pagination::page_count(total, per_page);
+    let (page, mut contacts) = match after {
+        None if *page > last_page => (last_page, fetch(last_page).await?.contacts),
+        _ => (*page, contacts),
+    };
+    // Taken before pending deletions are dropped, so the next page starts after this one's rows
+    let next_after = contacts.last().map(|c| ContactCursor::after(c, sort).encode());
+    // Contacts waiting out their undo grace period are already gone as far as the user is concerned
+    contacts.retain(|c| !state.pending_deletions.is_pending(c.id));
+    let filters = params.filters();
+    // Defaults are left out of the links, unless a preference would fill in something else
+    let preferences = preferences::current();
+    let per_page_param = if per_page == state.config.get().page_size && preferences.per_page.is_none() {
+        String::new()
+    } else {
+        per_page.to_string()
+    };
+    let (sort_param, dir_param) = templates::sort_params(sort);
+    let mut query = filters.query().to_vec();
+    query.extend([("per_page", per_page_param.as_str()), ("sort", &sort_param), ("dir", &dir_param)]);
+    let pagination = Pagination::new(page, per_page, total, base_url, &query)
+    .with_cursors(after.as_ref().map(ContactCursor::encode), next_after);
+    Ok((contacts, pagination))
+}
+
+/// Fragment function: Just the `<tr>` rows of one page of the contact list, for "Load more"
+/// and infinite scroll. Takes the same parameters as `/contacts`. If there is a next page, the
+/// last row is a sentinel that loads it once it scrolls into view and replaces itself with it.
+///
+/// Example usage:
+/// `<tr hx-get="/contacts/rows?page=2" hx-trigger="revealed" hx-swap="outerHTML">`
+#[axum::debug_handler]
+async fn contact_rows(
+    State(state): State<AppState>,
+    params: ListParams,
+) -> Result<Html<String>, AppError> {
+    let (contacts, pagination) = list_page(&state, &params, &base_path::url("/contacts/rows")).await?;
+    let template = ContactRowsPageTemplate {
+        contacts,
+        return_to: pagination.with_base_url(&base_path::url("/contacts")).current_url(),
+        next_url: pagination.has_next().then(|| pagination.next_url()),
+        search: params.filter.text.unwrap_or_default(),
+    };
+    Ok(Html(template.render()?))
+}
+
+/// Template function: Renders the contacts grouped by company, with the number of contacts at
+/// each. Contacts without a company aren't listed. Within a company they are in the order of
+/// the preferred name format.
+///
+/// Example usage:
+/// `GET /contacts/companies`
+#[axum::debug_handler]
+async fn companies(State(state): State<AppState>) -> Result<(StatusCode, Html<String>), AppError> {
+    let mut companies = state.contacts.companies().await?;
+    let format = preferences::current().name_format;
+    for company in &mut companies {
+        company.contacts.sort_by(|a, b| format.compare_contacts(a, b));
+    }
+    let template = CompaniesTemplate { companies };
+    Ok((StatusCode::OK, Html(template.render()?)))
+}
+
+/// Rows of an uploaded file shown next to its columns on the mapping page.
+const IMPORT_PREVIEW_ROWS: usize = 3;
+
+/// The import page, with `error_message` if the upload was turned down.
+fn import_page(status: StatusCode, error_message: Option<String>) -> Result<Response, AppError> {
+    let template = ImportTemplate {
+        max_size: attachments::human_size(import::MAX_UPLOAD_BYTES as u64),
+        error_message,
+    };
+    Ok((status, Html(template.render()?)).into_response())
+}
+
+/// Template function: Renders the import page, where a CSV file is uploaded to have its columns
+/// mapped to contact fields.
+///
+/// Example usage:
+/// `GET /contacts/import`
+#[axum::debug_handler]
+async fn get_import() -> Result<Response, AppError> {
+    import_page(StatusCode::OK, None)
+}
+
+/// Keeps the CSV file in the multipart `file` field for mapping (see `import::PendingImports`)
+/// and goes to its mapping page. The body is only read up to `import::MAX_UPLOAD_BYTES`, and
+/// a file that isn't UTF-8 CSV with a header row gets the import page back.
+///
+/// Example usage:
+/// `curl -F file=@contacts.csv http://localhost:2911/contacts/import`
+#[axum::debug_handler]
+async fn post_import(State(state): State<AppState>, headers: HeaderMap, body: Body) -> Result<Response, AppError> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let too_large = UploadError::TooLarge { max_bytes: import::MAX_UPLOAD_BYTES as u64 };
+    let upload = match axum::body::to_bytes(body, import::MAX_UPLOAD_BYTES + attachments::MULTIPART_OVERHEAD).await {
+        Ok(bytes) => attachments::parse_upload(content_type, &bytes).and_then(|upload| match upload.data.len() {
+            0 => Err(UploadError::Missing),
+            size if size > import::MAX_UPLOAD_BYTES => Err(too_large),
+            _ => Ok(upload),
+        }),
+        Err(_) => Err(too_large),
+    };
+    let upload = match upload {
+        Ok(upload) => upload,
+        Err(e) => return import_page(StatusCode::UNPROCESSABLE_ENTITY, Some(e.message())),
+    };
+    let Ok(text) = String::from_utf8(upload.data) else {
+        return import_page(StatusCode::UNPROCESSABLE_ENTITY, Some(i18n::t("import-not-utf8")));
+    };
+    if import::preview(&text, 0).is_err() {
+        return import_page(StatusCode::UNPROCESSABLE_ENTITY, Some(i18n::t("import-not-csv")));
+    }
+    let token = state.imports.add(upload.filename, text);
+    Ok(Redirect::to(&base_path::url(&format!("/contacts/import/{token}"))).into_response())
+}
+
+#[derive(Deserialize, Debug)]
+struct ImportMappingParams {
+    /// A saved mapping to start from, by id. Empty for the detected columns.
+    mapping: Option<String>,
+}
+
+/// Template function: Renders the mapping page of an upload: its columns with a few values
+/// each and the field each one goes into, to start with the saved mapping picked with
+/// `?mapping=<id>` or what the columns look like (see `import::CsvProfile::detect`). An
+/// upload that expired gets the import page back.
+///
+/// Example usage:
+/// `GET /contacts/import/4f1c...?mapping=2`
+#[axum::debug_handler]
+async fn get_import_mapping(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    Query(params): Query<ImportMappingParams>,
+) -> Result<Response, AppError> {
+    let Some(upload) = state.imports.get(&token) else {
+        return import_page(StatusCode::NOT_FOUND, Some(i18n::t("import-expired")));
+    };
+    let preview = import::preview(&upload.text, IMPORT_PREVIEW_ROWS)?;
+    let saved_mappings = SavedMapping::list(&state.db).await?;
+    let saved = params
+        .mapping
+        .and_then(|id| id.parse::<i64>().ok())
+        .and_then(|id| saved_mappings.iter().find(|saved| saved.id == id))
+        .cloned();
+    let mapping = match &saved {
+        Some(saved) => saved.mapping(),
+        None => ColumnMapping::from_profile(preview.profile, &preview.headers),
+    };
+    let template = ImportMappingTemplate {
+        token,
+        filename: upload.filename,
+        preview,
+        mapping,
+        saved_id: saved.as_ref().map(|saved| saved.id),
+        save_as: saved.map(|saved| saved.name).unwrap_or_default(),
+        saved_mappings,
+        error_message: None,
+    };
+    Ok(Html(template.render()?).into_response())
+}
+
+/// Imports an upload with the fields picked for its columns (`column-<n>`, empty to ignore
+/// one) and renders what came of it, see `import::create_all`. With a `save_as` name the
+/// mapping is saved under it first, replacing any saved under the same name. With `dry_run`
+/// nothing is saved or imported: the page shows what would be (see `import::check_all`) and
+/// keeps the upload to import it for real.
+///
+/// Example usage:
+/// `curl -d 'column-0=first_name&column-1=last_name&column-2=email&save_as=Newsletter' http://localhost:2911/contacts/import/4f1c...`
+#[axum::debug_handler]
+async fn post_import_mapping(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    Actor(actor): Actor,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Response, AppError> {
+    let Some(upload) = state.imports.get(&token) else {
+        return import_page(StatusCode::NOT_FOUND, Some(i18n::t("import-expired")));
+    };
+    let preview = import::preview(&upload.text, IMPORT_PREVIEW_ROWS)?;
+    let mapping = ColumnMapping(
+        preview
+            .headers
+            .iter()
+            .enumerate()
+            .filter_map(|(index, header)| {
+                let field = ContactField::parse(form.get(&format!("column-{index}"))?)?;
+                Some((header.clone(), field))
+            })
+            .collect(),
+    );
+    let save_as = form.get("save_as").map(|name| name.trim()).unwrap_or_default().to_string();
+    let error_message = if mapping.0.is_empty() {
+        Some(i18n::t("import-nothing-mapped"))
+    } else if save_as.chars().count() > import::MAX_MAPPING_NAME_LEN {
+        Some(i18n::t_with("import-name-too-long", "max", import::MAX_MAPPING_NAME_LEN))
+    } else {
+        None
+    };
+    let new_contacts = match error_message {
+        Some(_) => None,
+        None => import::read_csv_mapped(&upload.text, &mapping).ok(),
+    };
+    let Some(new_contacts) = new_contacts else {
+        let template = ImportMappingTemplate {
+            token,
+            filename: upload.filename,
+            preview,
+            mapping,
+            saved_mappings: SavedMapping::list(&state.db).await?,
+            saved_id: None,
+            save_as,
+            error_message: error_message.or_else(|| Some(i18n::t("import-not-csv"))),
+        };
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, Html(template.render()?)).into_response());
+    };
+    let dry_run = form.contains_key("dry_run");
+    if !save_as.is_empty() && !dry_run {
+        SavedMapping::save(&state.db, &save_as, &mapping).await?;
+    }
+    let names: Vec<String> = new_contacts
+        .iter()
+        .map(|c| format!("{} {} <{}>", c.first_name, c.last_name, c.email))
+        .collect();
+    let results = if dry_run {
+        import::check_all(state.contacts.as_ref(), &state.clock, new_contacts).await?
+    } else {
+        import::create_all(state.contacts.as_ref(), &state.db, &state.jobs, &state.clock, &actor, new_contacts).await?
+    };
+    let created = results.iter().filter(|result| result.errors.is_none()).count();
+    if dry_run {
+        info!(dry_run, "Checked {}: {created} of {} contacts would be created", upload.filename, results.len());
+    } else {
+        state.imports.remove(&token);
+        info!("Imported {}: {created} of {} contacts created", upload.filename, results.len());
+    }
+    let failures = results
+        .into_iter()
+        .filter_map(|result| {
+            Some(ImportFailure {
+                row: result.index + 1,
+                name: names[result.index].clone(),
+                errors: result.errors?.summary(),
+            })
+        })
+        .collect();
+    let mut form: Vec<(String, String)> = form.into_iter().filter(|(name, _)| name != "dry_run").collect();
+    form.sort();
+    let template = ImportResultTemplate {
+        filename: upload.filename,
+        created,
+        failures,
+        dry_run,
+        token,
+        form,
+    };
+    Ok(Html(template.render()?).into_response())
+}
+
+/// The contact list is a full page or just its rows depending on the htmx headers, so caches
+/// have to keep them apart.
+fn vary_on_htmx(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert(header::VARY, HeaderValue::from_static("HX-Request, HX-Target"));
+    response
+}
+
+/// Records a contact change in the event log, queues its webhook deliveries and sends it to
+/// the open contact lists (see `live`).
+async fn record_contact_event(
+    state: &AppState,
+    kind: &str,
+    actor: &str,
+    contact_id: i64,
+    payload: serde_json::Value,
+) -> Result<(), AppError> {
+    Event::record(&state.db, kind, actor, Some(contact_id), payload.clone()).await?;
+    webhooks::fire(&state.db, &state.jobs, kind, contact_id, &payload, state.clock.now()).await?;
+    if state.live.has_listeners() {
+        let contact = match kind {
+            events::CONTACT_DELETED => None,
+            _ => Some(state.contacts.find_by_id(contact_id).await?),
+        };
+        state.live.publish(LiveChange { event: kind.to_string(), contact_id, contact });
+    }
+    Ok(())
+}
+
+/// New contact creation from form data. It performs checks to verify if the email and phone are
+/// unique. Otherwise it creates the contact, flashes the success message on screen and redirects.
+///
+/// A contact that looks like one that exists already (see `duplicates`) isn't created right
+/// away. The form comes back with a warning naming the existing contact and a "Create anyway"
+/// button, which posts it again with `create_anyway` set.
+///
+/// Each rendered form carries a one-time `form_token`, which is consumed when the contact is
+/// created. A second submission of the same form (double click, refresh) is rejected.
+///
+/// Without htmx (JavaScript disabled) the form is posted normally: errors re-render the form
+/// page with the entered values, and success redirects to the list.
+#[axum::debug_handler]
+async fn post_new_contact(
+    State(state): State<AppState>,
+    Actor(actor): Actor,
+    htmx: Htmx,
+    Form(form): Form<NewContactForm>,
+) -> Result<Response, AppError> {
+    // Axums Form extractor handles the NewContact
+    let mut new_contact = form.contact;
+    if let Some(errors) = new_contact.validate() {
+        let message = errors.summary();
+        new_contact.errors = Some(errors);
+        return new_contact_rejected(&state, &htmx, new_contact, &message);
+    }
+    // Validate fields
+    let valid_email = state.contacts.email_exists(new_contact.email.as_str(), None).await?;
+    let valid_phone = state.contacts.phone_exists(new_contact.phone_number.as_str(), None).await?;
+
+    if valid_email || valid_phone {
+        let message = i18n::t("contact-in-use");
+        new_contact_rejected(&state, &htmx, new_contact, &message)
+    } else {
+        if form.create_anyway.is_none() {
+            let pending = &state.pending_deletions;
+            let duplicate =
+                duplicates::find(state.contacts.as_ref(), &new_contact, |c| pending.is_pending(c.id)).await?;
+            if let Some(duplicate) = duplicate {
+                return new_contact_duplicate(&state, &htmx, new_contact, duplicate);
+            }
+        }
+        let token = new_contact.form_token.as_deref().unwrap_or_default();
+        if !state.form_tokens.consume(token) {
+            let message = i18n::t("form-already-submitted");
+            return new_contact_rejected(&state, &htmx, new_contact, &message);
+        }
+        let contact = match state.contacts.create(new_contact.clone(), state.clock.now()).await {
+            Ok(contact) => contact,
+            Err(e) => {
+                let rejected = SaveRejected::from_error(e, "Creating a contact")?;
+                state.form_tokens.release(token);
+                new_contact.errors = rejected.errors;
+                let mut response = new_contact_rejected(&state, &htmx, new_contact, &rejected.message)?;
+                *response.status_mut() = rejected.status;
+                return Ok(response);
+            }
+        };
+        record_contact_event(
+            &state,
+            events::CONTACT_CREATED,
+            &actor,
+            contact.id,
+            serde_json::to_value(&contact)?,
+        )
+        .await?;
+        if !htmx.wants_fragment() {
+            return Ok(Redirect::to(&base_path::url("/contacts")).into_response());
+        }
+        let success_template = SuccessRedirectTemplate { success_message: i18n::t("contact-created") };
+        let html = success_template.render()?;
+        Ok(Html(html).into_response())
+    }
+}
+
+/// Why the database refused to save a contact, for the form to show along with the entered
+/// values.
+struct SaveRejected {
+    /// `409 Conflict` if another contact has the email or phone number, else `200` like the
+    /// other form errors.
+    status: StatusCode,
+    /// "Already exists" on the conflicting field.
+    errors: Option<NewContactErrors>,
+    message: String,
+}
+
+impl SaveRejected {
+    /// What to tell the user about `err` from `action` ("Creating a contact"). Busy errors are
+    /// passed on as they are, the busy page covers those.
+    fn from_error(err: ContactError, action: &str) -> Result<Self, AppError> {
+        if let ContactError::Taken(field) = err {
+            info!("{action} refused, another contact has the same {field}");
+            let errors = NewContactErrors::taken(field);
+            return Ok(Self {
+                status: StatusCode::CONFLICT,
+                message: errors.summary(),
+                errors: Some(errors),
+            });
+        }
+        // Busy errors get the busy page, and a contact deleted in the meantime the 404 page
+        if matches!(err, ContactError::NotFound) || err.is_busy() {
+            return Err(err.into());
+        }
+        error!("{action} failed: {err}");
+        Ok(Self {
+            status: StatusCode::OK,
+            errors: None,
+            message: i18n::t("contact-save-failed"),
+        })
+    }
+}
+
+/// The error for a rejected new contact: a fragment for htmx, or the form page again with the
+/// entered values and a fresh form token.
+fn new_contact_rejected(
+    state: &AppState,
+    htmx: &Htmx,
+    contact: NewContact,
+    message: &str,
+) -> Result<Response, AppError> {
+    let html = if htmx.wants_fragment() {
+        ErrorMessageTemplate { error_message: message.into() }.render()?
+    } else {
+        NewContactTemplate {
+            contact: Some(contact),
+            form_token: state.form_tokens.issue(),
+            error_message: Some(message.into()),
+            duplicate: None,
+        }
+        .render()?
+    };
+    Ok(Html(html).into_response())
+}
+
+
+/// The warning for a new contact that may be a duplicate of `duplicate`: a fragment for htmx,
+/// or the form page again with the entered values.
+fn new_contact_duplicate(
+    state: &AppState,
+    htmx: &Htmx,
+    contact: NewContact,
+    duplicate: Contact,
+) -> Result<Response, AppError> {
+    let html = if htmx.wants_fragment() {
+        DuplicateWarningTemplate { duplicate }.render()?
+    } else {
+        NewContactTemplate {
+            contact: Some(contact),
+            form_token: state.form_tokens.issue(),
+            error_message: None,
+            duplicate: Some(duplicate),
+        }
+        .render()?
+    };
+    Ok(Html(html).into_response())
+}
+
+
+/// Template function: Renders the new contact creation HTML.
+#[axum::debug_handler]
+async fn get_new_contact(
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Html<String>), AppError> {
+    let new_template = NewContactTemplate {
+        contact: None,
+        form_token: state.form_tokens.issue(),
+        error_message: None,
+        duplicate: None,
+    };
+    let html = new_template.render()?;
+    Ok((StatusCode::OK, Html(html)))
+}
+
+/// Quick add from the form above the contact list: creates the contact from just the name,
+/// phone and email, with the same checks as `post_new_contact`. htmx gets the new contact's row,
+/// which the form puts at the top of the list, and a fresh form. A rejected add swaps the form
+/// back with the entered values and what's wrong, or the possible duplicate and a button to
+/// add it anyway.
+///
+/// The list page is cached by ETag, so the form doesn't carry a one-time token. A second
+/// submission of the same contact is refused because its email and phone are in use.
+///
+/// Without htmx it is posted normally: success redirects to the list, and a rejected add goes
+/// on in the full new contact form with the entered values.
+///
+/// Example usage:
+/// `curl -X POST -d 'first_name=Jane&last_name=Doe&phone_number=555-0100&email=jane@example.com' http://localhost:2911/contacts/quick_add`
+#[axum::debug_handler]
+async fn post_quick_add(
+    State(state): State<AppState>,
+    Actor(actor): Actor,
+    htmx: Htmx,
+    Form(form): Form<NewContactForm>,
+) -> Result<Response, AppError> {
+    let mut new_contact = form.contact;
+    if let Some(errors) = new_contact.validate() {
+        let message = errors.summary();
+        new_contact.errors = Some(errors);
+        return quick_add_rejected(&state, &htmx, new_contact, Some(message), None);
+    }
+    let email_taken = state.contacts.email_exists(new_contact.email.as_str(), None).await?;
+    let phone_taken = state.contacts.phone_exists(new_contact.phone_number.as_str(), None).await?;
+    if email_taken || phone_taken {
+        return quick_add_rejected(&state, &htmx, new_contact, Some(i18n::t("contact-in-use")), None);
+    }
+    if form.create_anyway.is_none() {
+        let pending = &state.pending_deletions;
+        let duplicate =
+            duplicates::find(state.contacts.as_ref(), &new_contact, |c| pending.is_pending(c.id)).await?;
+        if let Some(duplicate) = duplicate {
+            return quick_add_rejected(&state, &htmx, new_contact, None, Some(duplicate));
+        }
+    }
+    let contact = match state.contacts.create(new_contact.clone(), state.clock.now()).await {
+        Ok(contact) => contact,
+        Err(e) => {
+            let rejected = SaveRejected::from_error(e, "Quick adding a contact")?;
+            new_contact.errors = rejected.errors;
+            let mut response = quick_add_rejected(&state, &htmx, new_contact, Some(rejected.message), None)?;
+            *response.status_mut() = rejected.status;
+            return Ok(response);
+        }
+    };
+    record_contact_event(
+        &state,
+        events::CONTACT_CREATED,
+        &actor,
+        contact.id,
+        serde_json::to_value(&contact)?,
+    )
+    .await?;
+    if !htmx.wants_fragment() {
+        return Ok(Redirect::to(&base_path::url("/contacts")).into_response());
+    }
+    let template = QuickAddedTemplate {
+        contact,
+        return_to: base_path::url("/contacts"),
+        search: String::new(),
+        quick_add: QuickAdd::default(),
+    };
+    Ok(Html(template.render()?).into_response())
+}
+
+/// The quick-add form again with `contact` and why it wasn't added, swapped over the old form
+/// for htmx. Without htmx it's the new contact form page, which has room for the other fields.
+fn quick_add_rejected(
+    state: &AppState,
+    htmx: &Htmx,
+    contact: NewContact,
+    error_message: Option<String>,
+    duplicate: Option<Contact>,
+) -> Result<Response, AppError> {
+    if !htmx.wants_fragment() {
+        let html = NewContactTemplate {
+            contact: Some(contact),
+            form_token: state.form_tokens.issue(),
+            error_message,
+            duplicate,
+        }
+        .render()?;
+        return Ok(Html(html).into_response());
+    }
+    let html = QuickAddTemplate {
+        quick_add: QuickAdd { contact, error_message, duplicate },
+    }
+    .render()?;
+    // The form aims its response at the list, a rejected add belongs in the form
+    Ok((
+        [("HX-Retarget", "#quick-add"), ("HX-Reswap", "outerHTML")],
+        Html(html),
+    )
+        .into_response())
+}
+
+/// Contacts in the Atom feed.
+const FEED_ENTRIES: i64 = 50;
+
+/// `scheme://host` the request was sent to, and the base path, for absolute links. Behind a
+/// trusted proxy the scheme comes from `X-Forwarded-Proto` (see `proxy`).
+fn request_origin(headers: &HeaderMap) -> String {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let scheme = match header("x-forwarded-proto") {
+        Some("https") => "https",
+        _ => "http",
+    };
+    let host = header(header::HOST.as_str()).unwrap_or("localhost");
+    format!("{scheme}://{host}{}", base_path::get())
+}
+
+/// The newest contacts as an Atom feed, for feed readers and scripts. Links are absolute, to
+/// the host the feed was requested from. The ETag only changes when the entries do, so
+/// readers polling it get a 304 in between.
+///
+/// Example usage:
+/// `curl http://localhost:2911/contacts/feed.atom`
+#[axum::debug_handler]
+async fn contacts_feed(State(state): State<AppState>, headers: HeaderMap) -> Result<Response, AppError> {
+    let mut contacts = state.contacts.newest(FEED_ENTRIES).await?;
+    contacts.retain(|c| !state.pending_deletions.is_pending(c.id));
+    let origin = request_origin(&headers);
+    let versions: Vec<(i64, DateTime<Utc>)> = contacts.iter().map(|c| (c.id, c.updated_at)).collect();
+    let etag = ETag::of(&(&origin, &versions));
+    if etag.matches(&headers) {
+        return Ok(etag.not_modified());
+    }
+    let entries = contacts
+        .iter()
+        .map(|contact| {
+            let url = format!("{origin}/contacts/{}", contact.id);
+            let details = [&contact.job_title, &contact.company, &contact.email, &contact.phone_number];
+            let summary: Vec<&str> = details.iter().map(|d| d.trim()).filter(|d| !d.is_empty()).collect();
+            atom::Entry {
+                id: url.clone(),
+                title: preferences::display_name(contact),
+                updated: contact.updated_at,
+                published: Some(contact.created_at),
+                link: url,
+                summary: (!summary.is_empty()).then(|| summary.join(", ")),
+            }
+        })
+        .collect();
+    let feed = atom::Feed {
+        id: format!("{origin}/contacts/feed.atom"),
+        title: i18n::t("feed-title"),
+        updated: contacts.iter().map(|c| c.updated_at).max().unwrap_or_else(|| state.clock.now()),
+        author: i18n::t("app-title"),
+        links: vec![
+            atom::Link {
+                rel: "self",
+                href: format!("{origin}/contacts/feed.atom"),
+                media_type: Some("application/atom+xml"),
+            },
+            atom::Link {
+                rel: "alternate",
+                href: format!("{origin}/contacts"),
+                media_type: Some("text/html"),
+            },
+        ],
+        entries,
+    };
+    Ok(etag.attach(([(header::CONTENT_TYPE, atom::CONTENT_TYPE)], feed.to_xml()).into_response()))
+}
+
+/// Template function: Renders the individual contact HTML with the `Contact` data, its
+/// relationships, dates and attachments. The ETag is derived from the contact's id and
+/// `updated_at`, the relationships, the dates, the attachments and how long ago the contact was
+/// created and changed, so repeat visits get a 304 until any of them change.
+#[axum::debug_handler]
+async fn show_contact(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> Result<Response, AppError> {
+    let contact = state.contacts.find_by_id(id).await?;
+    let relationships = visible_relationships(&state, id).await?;
+    let dates = state.contacts.dates(id).await?;
+    let attachments = Attachment::list(&state.db, id).await?;
+    // The page says how long ago the contact was created and changed, so it's stale once that
+    // reads differently, and shows the times in the timezone, which a reload can change
+    let now = state.clock.now();
+    let ago = (i18n::relative_time(&contact.created_at, &now), i18n::relative_time(&contact.updated_at, &now));
+    let timezone = state.config.get().timezone;
+    let etag = ETag::of(&(contact.id, &contact.updated_at, &relationships, &dates, &attachments, &ago, timezone));
+    if etag.matches(&headers) {
+        return Ok(etag.not_modified());
+    }
+    let show_template = ShowContactTemplate {
+        contact,
+        timezone,
+        now,
+        relationships,
+        relationship_error: None,
+        dates,
+        date_error: None,
+        attachments,
+        attachment_error: None,
+    };
+    let html = show_template.render()?;
+    Ok(etag.attach((StatusCode::OK, Html(html)).into_response()))
+}
+
+/// Serves a QR code with contact `id`'s vCard as a PNG, to scan it straight into a phone's
+/// address book. It only changes when the contact does, so it has the same kind of ETag as
+/// the contact's page.
+///
+/// Example usage:
+/// `<img src="/contacts/1/qr.png">`
+#[axum::debug_handler]
+async fn contact_qr(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> Result<Response, AppError> {
+    let contact = state.contacts.find_by_id(id).await?;
+    let etag = ETag::of(&(contact.id, &contact.updated_at));
+    if etag.matches(&headers) {
+        return Ok(etag.not_modified());
+    }
+    let qr = QrCode::encode(vcard::to_vcard(&contact).as_bytes())
+        .context("The contact's vCard is too long for a QR code")?;
+    Ok(etag.attach(([(header::CONTENT_TYPE, "image/png")], qr.to_png()).into_response()))
+}
+
+/// Renders contact `id` as a printable PDF contact sheet, opened in the browser.
+///
+/// Example usage:
+/// `<a href="/contacts/1/pdf">Print</a>`
+#[axum::debug_handler]
+async fn contact_pdf(State(state): State<AppState>, Path(id): Path<i64>) -> Result<Response, AppError> {
+    let contact = state.contacts.find_by_id(id).await?;
+    let pdf = pdf::contact_sheet(&[contact], &state.config.get().timezone);
+    Ok(pdf_response(pdf, &format!("contact-{id}.pdf")))
+}
+
+/// Renders a selected set of contacts, given as comma separated `ids`, into one PDF contact
+/// sheet, in that order. At most `pdf::MAX_CONTACTS` of them.
+///
+/// Example usage:
+/// `GET /contacts/sheet.pdf?ids=3,1,4`
+#[axum::debug_handler]
+async fn contact_sheet_pdf(
+    State(state): State<AppState>,
+    Query(params): Query<ContactSheetParams>,
+) -> Result<Response, AppError> {
+    let ids: Option<Vec<i64>> = params
+        .ids
+        .split(',')
+        .map(|id| id.trim().parse().ok())
+        .collect();
+    let message = match ids {
+        None => i18n::t_with("invalid-contact-ids", "ids", params.ids.as_str()),
+        Some(ids) if ids.len() > pdf::MAX_CONTACTS => i18n::t_with("too-many-sheet-contacts", "max", pdf::MAX_CONTACTS),
+        Some(ids) => {
+            let mut contacts = Vec::with_capacity(ids.len());
+            for id in ids {
+                contacts.push(state.contacts.find_by_id(id).await?);
+            }
+            let pdf = pdf::contact_sheet(&contacts, &state.config.get().timezone);
+            return Ok(pdf_response(pdf, "contacts.pdf"));
+        }
+    };
+    let html = ErrorMessageTemplate { error_message: message }.render()?;
+    Ok((StatusCode::BAD_REQUEST, Html(html)).into_response())
+}
+
+fn pdf_response(pdf: Vec<u8>, filename: &str) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, "application/pdf".to_string()),
+            (header::CONTENT_DISPOSITION, format!("inline; filename=\"{filename}\"")),
+        ],
+        pdf,
+    )
+        .into_response()
+}
+
+/// Contact `id`'s relationships, leaving out contacts that are about to be deleted, in the
+/// order of the preferred name format.
+async fn visible_relationships(state: &AppState, id: i64) -> Result<Vec<Relationship>, AppError> {
+    let mut relationships = state.contacts.relationships(id).await?;
+    relationships.retain(|r| !state.pending_deletions.is_pending(r.other_id));
+    Relationship::sort(&mut relationships, preferences::current().name_format);
+    Ok(relationships)
+}
+
+/// The answer to adding or removing a relationship: the relationships section for htmx, else
+/// the contact's page, or a redirect to it if nothing went wrong.
+async fn relationships_response(
+    state: &AppState,
+    htmx: &Htmx,
+    contact: Contact,
+    error_message: Option<String>,
+) -> Result<Response, AppError> {
+    let contact_id = contact.id;
+    let relationships = visible_relationships(state, contact_id).await?;
+    let html = if htmx.wants_fragment() {
+        RelationshipsTemplate {
+            contact_id,
+            relationships,
+            error_message,
+        }
+        .render()?
+    } else if error_message.is_some() {
+        ShowContactTemplate {
+            contact,
+            timezone: state.config.get().timezone,
+            now: state.clock.now(),
+            relationships,
+            relationship_error: error_message,
+            dates: state.contacts.dates(contact_id).await?,
+            date_error: None,
+            attachments: Attachment::list(&state.db, contact_id).await?,
+            attachment_error: None,
+        }
+        .render()?
+    } else {
+        return Ok(Redirect::to(&base_path::url(&format!("/contacts/{}", contact.id))).into_response());
+    };
+    Ok(Html(html).into_response())
+}
+
+/// Links the contact to another one, looked up by email, with a label like "spouse of" or
+/// "works with". The label describes this contact, so the link reads "spouse of Bob" here and
+/// "Alice (spouse of this contact)" on Bob's page.
+///
+/// Example usage:
+/// `POST /contacts/1/relationships` with `label=works with&related_email=bob@example.com`
+#[axum::debug_handler]
+async fn add_relationship(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Actor(actor): Actor,
+    htmx: Htmx,
+    Form(form): Form<RelationshipForm>,
+) -> Result<Response, AppError> {
+    let contact = state.contacts.find_by_id(id).await?;
+    let label = form.label.trim();
+    let email = form.related_email.trim();
+    let related = state
+        .contacts
+        .find_by_email(email)
+        .await?
+        .filter(|c| !state.pending_deletions.is_pending(c.id));
+    let existing = state.contacts.relationships(id).await?;
+    let error_message = match &related {
+        _ if label.is_empty() => Some(i18n::t("relationship-label-missing")),
+        _ if label.chars().count() > relationships::MAX_LABEL_LEN => {
+            Some(i18n::t_with("relationship-label-too-long", "max", relationships::MAX_LABEL_LEN))
+        }
+        None => Some(i18n::t_with("relationship-unknown-contact", "email", email)),
+        Some(related) if related.id == id => Some(i18n::t("relationship-self")),
+        Some(related)
+            if existing
+                .iter()
+                .any(|r| !r.incoming && r.other_id == related.id && r.label == label) =>
+        {
+            Some(i18n::t("relationship-exists"))
+        }
+        Some(_) => None,
+    };
+    if let (None, Some(related)) = (&error_message, &related) {
+        let relationship_id = state.contacts.add_relationship(id, related.id, label).await?;
+        let payload = json!({ "id": relationship_id, "contact_id": id, "related_id": related.id, "label": label });
+        Event::record(&state.db, events::RELATIONSHIP_ADDED, &actor, Some(id), payload).await?;
+    }
+    relationships_response(&state, &htmx, contact, error_message).await
+}
+
+/// Removes one of the contact's relationships, from whichever end. htmx sends a `DELETE`,
+/// without JavaScript the remove button POSTs to `.../delete`.
+///
+/// Example usage:
+/// `DELETE /contacts/1/relationships/3`
+#[axum::debug_handler]
+async fn remove_relationship(
+    State(state): State<AppState>,
+    Path((id, relationship_id)): Path<(i64, i64)>,
+    Actor(actor): Actor,
+    htmx: Htmx,
+) -> Result<Response, AppError> {
+    let contact = state.contacts.find_by_id(id).await?;
+    if state.contacts.remove_relationship(id, relationship_id).await? {
+        let payload = json!({ "id": relationship_id, "contact_id": id });
+        Event::record(&state.db, events::RELATIONSHIP_REMOVED, &actor, Some(id), payload).await?;
+    }
+    relationships_response(&state, &htmx, contact, None).await
+}
+
+/// The answer to adding or removing a date: the dates section for htmx, else the contact's
+/// page, or a redirect to it if nothing went wrong.
+async fn dates_response(
+    state: &AppState,
+    htmx: &Htmx,
+    contact: Contact,
+    error_message: Option<String>,
+) -> Result<Response, AppError> {
+    let contact_id = contact.id;
+    let dates = state.contacts.dates(contact_id).await?;
+    let html = if htmx.wants_fragment() {
+        DatesTemplate {
+            contact_id,
+            dates,
+            error_message,
+        }
+        .render()?
+    } else if error_message.is_some() {
+        ShowContactTemplate {
+            contact,
+            timezone: state.config.get().timezone,
+            now: state.clock.now(),
+            relationships: visible_relationships(state, contact_id).await?,
+            relationship_error: None,
+            dates,
+            date_error: error_message,
+            attachments: Attachment::list(&state.db, contact_id).await?,
+            attachment_error: None,
+        }
+        .render()?
+    } else {
+        return Ok(Redirect::to(&base_path::url(&format!("/contacts/{contact_id}"))).into_response());
+    };
+    Ok(Html(html).into_response())
+}
+
+/// Gives the contact a labeled date, like an anniversary or a renewal, once or every year.
+///
+/// Example usage:
+/// `POST /contacts/1/dates` with `label=anniversary&date=2015-06-20&yearly=true`
+#[axum::debug_handler]
+async fn add_date(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Actor(actor): Actor,
+    htmx: Htmx,
+    Form(form): Form<DateForm>,
+) -> Result<Response, AppError> {
+    let contact = state.contacts.find_by_id(id).await?;
+    let label = form.label.trim();
+    let date = NaiveDate::parse_from_str(form.date.trim(), "%Y-%m-%d").ok();
+    let yearly = form.yearly.is_some();
+    let existing = state.contacts.dates(id).await?;
+    let error_message = match date {
+        _ if label.is_empty() => Some(i18n::t("date-label-missing")),
+        _ if label.chars().count() > dates::MAX_LABEL_LEN => {
+            Some(i18n::t_with("date-label-too-long", "max", dates::MAX_LABEL_LEN))
+        }
+        None => Some(i18n::t("date-invalid")),
+        Some(date) if existing.iter().any(|d| d.label == label && d.date == date) => Some(i18n::t("date-exists")),
+        Some(_) => None,
+    };
+    if let (None, Some(date)) = (&error_message, date) {
+        let date_id = state.contacts.add_date(id, label, date, yearly).await?;
+        let payload = json!({ "id": date_id, "contact_id": id, "label": label, "date": date, "yearly": yearly });
+        Event::record(&state.db, events::DATE_ADDED, &actor, Some(id), payload).await?;
+    }
+    dates_response(&state, &htmx, contact, error_message).await
+}
+
+/// Removes one of the contact's dates. htmx sends a `DELETE`, without JavaScript the remove
+/// button POSTs to `.../delete`.
+///
+/// Example usage:
+/// `DELETE /contacts/1/dates/3`
+#[axum::debug_handler]
+async fn remove_date(
+    State(state): State<AppState>,
+    Path((id, date_id)): Path<(i64, i64)>,
+    Actor(actor): Actor,
+    htmx: Htmx,
+) -> Result<Response, AppError> {
+    let contact = state.contacts.find_by_id(id).await?;
+    if state.contacts.remove_date(id, date_id).await? {
+        let payload = json!({ "id": date_id, "contact_id": id });
+        Event::record(&state.db, events::DATE_REMOVED, &actor, Some(id), payload).await?;
+    }
+    dates_response(&state, &htmx, contact, None).await
+}
+
+/// Template function: Renders the dates of every contact that come up in the next `days` days
+/// (`dates::UPCOMING_DAYS` by default), soonest first. Today is today in the configured
+/// timezone, and contacts that are about to be deleted are left out.
+///
+/// Example usage:
+/// `GET /contacts/dates?days=90`
+#[axum::debug_handler]
+async fn upcoming_dates(
+    State(state): State<AppState>,
+    Query(params): Query<UpcomingDatesParams>,
+) -> Result<(StatusCode, Html<String>), AppError> {
+    let days = params.days.unwrap_or(dates::UPCOMING_DAYS).clamp(1, dates::MAX_UPCOMING_DAYS);
+    let today = state.config.get().timezone.date(&state.clock.now());
+    let mut all = state.contacts.all_dates().await?;
+    all.retain(|d| !state.pending_deletions.is_pending(d.contact_id));
+    let template = UpcomingDatesTemplate {
+        upcoming: ContactDate::upcoming(all, today, days),
+        days,
+    };
+    Ok((StatusCode::OK, Html(template.render()?)))
+}
+
+/// The answer to uploading or removing an attachment: the attachments section for htmx, else
+/// the contact's page, or a redirect to it if nothing went wrong.
+async fn attachments_response(
+    state: &AppState,
+    htmx: &Htmx,
+    contact: Contact,
+    error_message: Option<String>,
+) -> Result<Response, AppError> {
+    let contact_id = contact.id;
+    let attachments = Attachment::list(&state.db, contact_id).await?;
+    let html = if htmx.wants_fragment() {
+        AttachmentsTemplate {
+            contact_id,
+            attachments,
+            error_message,
+        }
+        .render()?
+    } else if error_message.is_some() {
+        ShowContactTemplate {
+            contact,
+            timezone: state.config.get().timezone,
+            now: state.clock.now(),
+            relationships: visible_relationships(state, contact_id).await?,
+            relationship_error: None,
+            dates: state.contacts.dates(contact_id).await?,
+            date_error: None,
+            attachments,
+            attachment_error: error_message,
+        }
+        .render()?
+    } else {
+        return Ok(Redirect::to(&base_path::url(&format!("/contacts/{contact_id}"))).into_response());
+    };
+    Ok(Html(html).into_response())
+}
+
+/// Attaches the file in the multipart `file` field to the contact, if it is within the
+/// configured size and allowed types. The body is only read up to the size limit, so an
+/// oversized upload is turned down without being buffered whole.
+///
+/// Example usage:
+/// `curl -F file=@card.png http://localhost:2911/contacts/1/attachments`
+#[axum::debug_handler]
+async fn upload_attachment(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Actor(actor): Actor,
+    htmx: Htmx,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, AppError> {
+    let contact = state.contacts.find_by_id(id).await?;
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let upload = match axum::body::to_bytes(body, state.attachments.body_limit()).await {
+        Ok(bytes) => attachments::parse_upload(content_type, &bytes)
+            .and_then(|upload| state.attachments.check(&upload).map(|()| upload)),
+        // Apart from the limit it only fails on a dropped connection, and then nobody reads
+        // the answer
+        Err(_) => Err(state.attachments.too_large()),
+    };
+    let error_message = match upload {
+        Ok(upload) => {
+            let attachment = state.attachments.store(id, &upload).await?;
+            let payload = json!({
+                "id": attachment.id,
+                "contact_id": id,
+                "filename": attachment.filename,
+                "content_type": attachment.content_type,
+                "size": attachment.size,
+            });
+            Event::record(&state.db, events::ATTACHMENT_ADDED, &actor, Some(id), payload).await?;
+            None
+        }
+        Err(e) => Some(e.message()),
+    };
+    attachments_response(&state, &htmx, contact, error_message).await
+}
+
+/// Streams an attachment back under the name it was uploaded with. It is always sent as a
+/// download, and with `nosniff`, so an uploaded HTML or SVG file can't run in the app's origin.
+///
+/// Example usage:
+/// `curl -OJ http://localhost:2911/contacts/1/attachments/2`
+#[axum::debug_handler]
+async fn download_attachment(
+    State(state): State<AppState>,
+    Path((id, attachment_id)): Path<(i64, i64)>,
+) -> Result<Response, AppError> {
+    let Some(attachment) = Attachment::find(&state.db, id, attachment_id).await? else {
+        return Err(AppError::NotFound);
+    };
+    let file = match tokio::fs::File::open(state.attachments.path(&attachment)).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(AppError::NotFound),
+        Err(e) => return Err(e.into()),
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, attachment.content_type.clone()),
+            (header::CONTENT_LENGTH, attachment.size.to_string()),
+            (header::CONTENT_DISPOSITION, attachments::content_disposition(&attachment.filename)),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+/// Removes an attachment, file and all. htmx sends a `DELETE`, without JavaScript the remove
+/// button POSTs to `.../delete`.
+///
+/// Example usage:
+/// `DELETE /contacts/1/attachments/2`
+#[axum::debug_handler]
+async fn remove_attachment(
+    State(state): State<AppState>,
+    Path((id, attachment_id)): Path<(i64, i64)>,
+    Actor(actor): Actor,
+    htmx: Htmx,
+) -> Result<Response, AppError> {
+    let contact = state.contacts.find_by_id(id).await?;
+    if let Some(attachment) = Attachment::find(&state.db, id, attachment_id).await? {
+        state.attachments.remove(&attachment).await?;
+        let payload = json!({ "id": attachment.id, "contact_id": id, "filename": attachment.filename });
+        Event::record(&state.db, events::ATTACHMENT_REMOVED, &actor, Some(id), payload).await?;
+    }
+    attachments_response(&state, &htmx, contact, None).await
+}
+
+/// Template function: Renders the Edit contact HTML with the `Contact` data. The list page the
+/// user came from is carried along in `return_to` so saving can take them back there.
+#[axum::debug_handler]
+async fn get_edit_contact(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Query(params): Query<ReturnToParams>,
+) -> Result<(StatusCode, Html<String>), AppError> {
+    let contact = state.contacts.find_by_id(id).await?;
+    let edit_template = EditContactTemplate {
+        contact,
+        return_to: safe_return_to(params.return_to.as_deref()),
+        errors: None,
+        error_message: None,
+    };
+    let html = edit_template.render()?;
+    Ok((StatusCode::OK, Html(html)))
+}
+/// Updates existing contact by passing all the parameters, and updating the `Contact` struct from
+/// the new data. Then calling the `.update()` method with `&self` to make the changes in the
+/// database. Redirects back to the (allow-listed) `return_to` page, or the first list page.
+/// If the fields don't validate or the database refuses the update, the form comes back with
+/// what was entered and what's wrong with it.
+///
+/// Example usage:
+/// The edit page's form, which POSTs with `_method=PUT` to `/contacts/{id}` (see `put_contact`),
+/// or here to `/contacts/{id}/edit`.
+#[axum::debug_handler]
+async fn post_edit_contact(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Actor(actor): Actor,
+    Form(form): Form<EditContactForm>,
+) -> Result<Response, AppError> {
+    update_contact(&state, &actor, &Htmx::default(), id, form.contact, form.return_to).await
+}
+
+/// Updates a contact from a form. htmx (the list's inline editor) gets the display row back,
+/// or the editor row with the problem. Everything else is answered like `post_edit_contact`.
+async fn update_contact(
+    state: &AppState,
+    actor: &str,
+    htmx: &Htmx,
+    id: i64,
+    new: NewContact,
+    return_to: Option<String>,
+) -> Result<Response, AppError> {
+    let mut contact = state.contacts.find_by_id(id).await?;
+    let return_to = safe_return_to(return_to.as_deref());
+    let mut errors = new.validate();
+    contact.update_from(new);
+    if htmx.wants_fragment() {
+        let rejected = match errors {
+            Some(errors) => Some((StatusCode::OK, errors.summary())),
+            None => save_contact(state, actor, &contact)
+                .await?
+                .map(|rejected| (rejected.status, rejected.message)),
+        };
+        let (status, html) = match rejected {
+            Some((status, error_message)) => (
+                status,
+                ContactEditRowTemplate {
+                    contact,
+                    return_to,
+                    error_message: Some(error_message),
+                }
+                .render()?,
+            ),
+            None => (StatusCode::OK, ContactRowTemplate { contact, return_to, search: String::new() }.render()?),
+        };
+        return Ok((status, Html(html)).into_response());
+    }
+    let mut status = StatusCode::OK;
+    let mut error_message = None;
+    if errors.is_none() {
+        match save_contact(state, actor, &contact).await? {
+            None => return Ok(Redirect::to(&return_to).into_response()),
+            Some(rejected) => {
+                status = rejected.status;
+                // A conflict is shown on its field, anything else above the button
+                match rejected.errors {
+                    Some(conflict) => errors = Some(conflict),
+                    None => error_message = Some(rejected.message),
+                }
+            }
+        }
+    }
+    let template = EditContactTemplate {
+        contact,
+        return_to,
+        errors,
+        error_message,
+    };
+    Ok((status, Html(template.render()?)).into_response())
+}
+
+/// Saves an edited contact and records the event. Returns what to tell the user if the
+/// database refused the update, see `SaveRejected`.
+async fn save_contact(state: &AppState, actor: &str, contact: &Contact) -> Result<Option<SaveRejected>, AppError> {
+    if let Err(e) = state.contacts.update(contact, state.clock.now()).await {
+        let action = format!("Updating contact {}", contact.id);
+        return Ok(Some(SaveRejected::from_error(e, &action)?));
+    }
+    record_contact_event(
+        state,
+        events::CONTACT_UPDATED,
+        actor,
+        contact.id,
+        serde_json::to_value(contact)?,
+    )
+    .await?;
+    Ok(None)
+}
+
+/// Fragment function: The contact's row in the list, turned into an inline editor. Without
+/// JavaScript the "Edit" link goes to the full edit page instead.
+///
+/// Example usage:
+/// `<a hx-get="/contacts/1/edit_row" hx-target="closest tr" hx-swap="outerHTML">Edit</a>`
+#[axum::debug_handler]
+async fn get_edit_row(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Query(params): Query<ReturnToParams>,
+) -> Result<Html<String>, AppError> {
+    let template = ContactEditRowTemplate {
+        contact: state.contacts.find_by_id(id).await?,
+        return_to: safe_return_to(params.return_to.as_deref()),
+        error_message: None,
+    };
+    Ok(Html(template.render()?))
+}
+
+/// Fragment function: The contact's display row in the list, for cancelling an inline edit.
+#[axum::debug_handler]
+async fn get_contact_row(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Query(params): Query<ReturnToParams>,
+) -> Result<Html<String>, AppError> {
+    let template = ContactRowTemplate {
+        contact: state.contacts.find_by_id(id).await?,
+        return_to: safe_return_to(params.return_to.as_deref()),
+        search: String::new(),
+    };
+    Ok(Html(template.render()?))
+}
+
+/// Replaces all of a contact's fields, see `update_contact`. For htmx this saves an inline
+/// edit and returns the updated display row, or the editor row with the entered values and
+/// the problem.
+///
+/// Example usage:
+/// `<button hx-put="/contacts/1" hx-include="closest tr" hx-target="closest tr">Save</button>`
+#[axum::debug_handler]
+async fn put_contact(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Actor(actor): Actor,
+    htmx: Htmx,
+    Form(form): Form<EditContactForm>,
+) -> Result<Response, AppError> {
+    update_contact(&state, &actor, &htmx, id, form.contact, form.return_to).await
+}
+
+/// Changes only the fields the form has, keeping the others, see `update_contact`.
+///
+/// Example usage:
+/// `curl -X PATCH -d 'job_title=CTO' http://localhost:2911/contacts/1`
+#[axum::debug_handler]
+async fn patch_contact(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Actor(actor): Actor,
+    htmx: Htmx,
+    Form(form): Form<PatchContactForm>,
+) -> Result<Response, AppError> {
+    let current = state.contacts.find_by_id(id).await?;
+    update_contact(&state, &actor, &htmx, id, form.patch.apply(&current), form.return_to).await
+}
+
+/// Deletes contact by extracting the `id` from the path. The contact is not removed right away,
+/// it is queued in `PendingDeletions` and only deleted once the undo grace period runs out.
+///
+/// htmx would follow a redirect and swap the contact list into the page, so it gets an empty
+/// 200 instead: as is when deleting a row of the list (`#contact-{id}`), which removes it, and
+/// with `HX-Redirect` to the list otherwise. Browsers without JavaScript are redirected.
+///
+/// Example usage: 
+/// By passing on a HTTP `DELETE` method to the `/contacts/{id}` path, we can trigger this function.
+/// Without JavaScript the confirmation page POSTs to `/contacts/{id}/delete` instead.
+#[axum::debug_handler]
+async fn delete_contact(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Actor(actor): Actor,
+    htmx: Htmx,
+) -> Result<Response, AppError> {
+    let contact = state.contacts.find_by_id(id).await?;
+    record_contact_event(
+        &state,
+        events::CONTACT_DELETED,
+        &actor,
+        id,
+        serde_json::to_value(&contact)?,
+    )
+    .await?;
+    state.pending_deletions.schedule(contact);
+
+    let list_url = base_path::url("/contacts");
+    if htmx.targets(&format!("contact-{id}")) {
+        Ok(StatusCode::OK.into_response())
+    } else if htmx.request {
+        Ok(([("HX-Redirect", list_url)], StatusCode::OK).into_response())
+    } else {
+        Ok(Redirect::to(&list_url).into_response())
+    }
+}
+
+/// Upgrades to the WebSocket the contact list gets live updates over, see `live`.
+///
+/// Example usage:
+/// `new WebSocket("ws://localhost:2911/contacts/live")`
+#[axum::debug_handler]
+async fn contacts_live(State(state): State<AppState>, request: Request) -> Response {
+    state.live.accept(request)
+}
+
+/// Template function: Asks to confirm a deletion. This is where the delete button leads
+/// without JavaScript, standing in for htmx's `hx-confirm` dialog.
+#[axum::debug_handler]
+async fn confirm_delete_contact(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Html<String>, AppError> {
+    let contact = state.contacts.find_by_id(id).await?;
+    Ok(Html(DeleteContactTemplate { contact }.render()?))
+}
+
+/// Restores a contact that is still within its undo grace period. If the deletion has already
+/// been finalized there is nothing to restore and we just go back to the list.
+///
+/// Example usage:
+/// The "Undo" button on the index page POSTs to `/contacts/{id}/undo_delete`.
+#[axum::debug_handler]
+async fn undo_delete_contact(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Actor(actor): Actor,
+) -> Result<Redirect, AppError> {
+    if state.pending_deletions.cancel(id) {
+        record_contact_event(
+            &state,
+            events::CONTACT_RESTORED,
+            &actor,
+            id,
+            serde_json::json!({}),
+        )
+        .await?;
+        Ok(Redirect::to(&base_path::url(&format!("/contacts/{id}"))))
+    } else {
+        Ok(Redirect::to(&base_path::url("/contacts")))
+    }
+}
+
+/// The trash page, with `error_message` if a contact couldn't be restored.
+async fn trash_page(state: &AppState, status: StatusCode, error_message: Option<String>) -> Result<Response, AppError> {
+    let template = TrashTemplate {
+        contacts: TrashedContact::list(&state.db).await?,
+        retention_days: state.config.get().trash.retention_days,
+        error_message,
+    };
+    Ok((status, Html(template.render()?)).into_response())
+}
+
+/// Template function: Renders the trash, the deleted contacts that can still be restored, most
+/// recently deleted first, each with the day it's purged on. See `trash`.
+///
+/// Example usage:
+/// `GET /contacts/trash`
+#[axum::debug_handler]
+async fn contact_trash(State(state): State<AppState>) -> Result<Response, AppError> {
+    trash_page(&state, StatusCode::OK, None).await
+}
+
+/// Takes a contact out of the trash and stores it again with its id, dates and attachments,
+/// then goes to its page. If another contact has taken its email or phone number in the meantime
+/// it stays in the trash and the page says why.
+///
+/// Example usage:
+/// `curl -X POST http://localhost:2911/contacts/trash/12/restore`
+#[axum::debug_handler]
+async fn restore_contact(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Actor(actor): Actor,
+) -> Result<Response, AppError> {
+    let Some(trashed) = TrashedContact::find(&state.db, id).await? else {
+        return Ok(Redirect::to(&base_path::url("/contacts/trash")).into_response());
+    };
+    let contact = trashed.contact(state.clock.now());
+    if let Err(field) = state.contacts.restore(&contact).await? {
+        let name = format!("{} {}", contact.first_name, contact.last_name);
+        let reason = NewContactErrors::taken(field).summary();
+        let error_message = i18n::t_args("trash-restore-failed", &[("name", name.into()), ("reason", reason.into())]);
+        return trash_page(&state, StatusCode::CONFLICT, Some(error_message)).await;
+    }
+    for date in trashed.dates() {
+        state.contacts.add_date(id, &date.label, date.date, date.yearly).await?;
+    }
+    TrashedContact::remove(&state.db, id).await?;
+    info!("Restored contact {id} from the trash");
+    record_contact_event(&state, events::CONTACT_RESTORED, &actor, id, serde_json::to_value(&contact)?).await?;
+    Ok(Redirect::to(&base_path::url(&format!("/contacts/{id}"))).into_response())
+}
+
+/// Empties the trash now instead of waiting for the purge (see `trash::PURGE_TRASH_JOB`): every
+/// contact in it is deleted for good, with its attachments.
+///
+/// Example usage:
+/// `curl -X POST http://localhost:2911/contacts/trash/empty`
+#[axum::debug_handler]
+async fn empty_trash(State(state): State<AppState>, Actor(actor): Actor) -> Result<Redirect, AppError> {
+    let purged = trash::purge(&state.db, &state.attachments, None).await?;
+    info!("Emptied the trash for {actor}: {purged} contact(s) purged");
+    Ok(Redirect::to(&base_path::url("/contacts/trash")))
+}
+
+/// The shares page, with `error_message` if a link couldn't be made.
+async fn shares_page(
+    state: &AppState,
+    headers: &HeaderMap,
+    status: StatusCode,
+    error_message: Option<String>,
+) -> Result<Response, AppError> {
+    let origin = request_origin(headers);
+    let now = state.clock.now();
+    let mut shares = Vec::new();
+    for link in ShareLink::list(&state.db).await? {
+        let title = match link.target() {
+            ShareTarget::Contact(id) => match state.contacts.find_by_id(id).await {
+                Ok(contact) => preferences::display_name(&contact),
+                Err(ContactError::NotFound) => i18n::t_with("shares-missing-contact", "id", id),
+                Err(e) => return Err(e.into()),
+            },
+            ShareTarget::Company(company) => company,
+        };
+        shares.push(ShareRow {
+            url: format!("{origin}/share/{}", link.token),
+            status: link.status(now),
+            title,
+            link,
+        });
+    }
+    let template = SharesTemplate {
+        shares,
+        timezone: state.config.get().timezone,
+        error_message,
+    };
+    Ok((status, Html(template.render()?)).into_response())
+}
+
+/// Template function: Renders the share links, newest first, each with its URL to copy, when
+/// it expires and whether it still works. See `shares`.
+///
+/// Example usage:
+/// `GET /contacts/shares`
+#[axum::debug_handler]
+async fn contact_shares(State(state): State<AppState>, headers: HeaderMap) -> Result<Response, AppError> {
+    shares_page(&state, &headers, StatusCode::OK, None).await
+}
+
+/// Makes a share link to a contact or to the contacts of a company, then goes to the shares
+/// page to copy it. An expiry that's out of range or a company nobody works at is shown on
+/// the shares page with a 422.
+///
+/// Example usage:
+/// `curl -X POST -d 'contact_id=1&expires_in_days=7' http://localhost:2911/contacts/shares`
+#[axum::debug_handler]
+async fn create_share(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Actor(actor): Actor,
+    Form(form): Form<ShareForm>,
+) -> Result<Response, AppError> {
+    let expires_in_days = match form.expires_in_days.trim() {
+        "" => None,
+        days => match days.parse::<i64>() {
+            Ok(days) if (1..=shares::MAX_EXPIRY_DAYS).contains(&days) => Some(days),
+            _ => {
+                let error_message = i18n::t_with("share-invalid-expiry", "max", shares::MAX_EXPIRY_DAYS);
+                return shares_page(&state, &headers, StatusCode::UNPROCESSABLE_ENTITY, Some(error_message)).await;
+            }
+        },
+    };
+    let target = match (form.contact_id, form.company.as_deref().map(str::trim)) {
+        (Some(id), _) => ShareTarget::Contact(state.contacts.find_by_id(id).await?.id),
+        (None, Some(company)) if !company.is_empty() => {
+            let companies = state.contacts.companies().await?;
+            if !companies.iter().any(|c| c.name == company) {
+                let error_message = i18n::t_with("share-unknown-company", "company", company);
+                return shares_page(&state, &headers, StatusCode::UNPROCESSABLE_ENTITY, Some(error_message)).await;
+            }
+            ShareTarget::Company(company.to_string())
+        }
+        _ => return Err(AppError::NotFound),
+    };
+    let link = ShareLink::create(&state.db, &target, state.clock.now(), expires_in_days).await?;
+    info!("Shared {target:?} for {actor} as link {}, expires {:?}", link.id, link.expires_at);
+    let payload = json!({ "id": link.id, "contact_id": link.contact_id, "company": link.company, "expires_at": link.expires_at });
+    Event::record(&state.db, events::SHARE_CREATED, &actor, link.contact_id, payload).await?;
+    Ok(Redirect::to(&base_path::url("/contacts/shares")).into_response())
+}
+
+/// Revokes share link `id`: it stops working right away and stays on the shares page as
+/// revoked.
+///
+/// Example usage:
+/// `curl -X POST http://localhost:2911/contacts/shares/3/revoke`
+#[axum::debug_handler]
+async fn revoke_share(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Actor(actor): Actor,
+) -> Result<Redirect, AppError> {
+    if let Some(link) = ShareLink::revoke(&state.db, id, state.clock.now()).await? {
+        info!("Revoked share link {id} for {actor}");
+        let payload = json!({ "id": link.id, "contact_id": link.contact_id, "company": link.company });
+        Event::record(&state.db, events::SHARE_REVOKED, &actor, link.contact_id, payload).await?;
+    }
+    Ok(Redirect::to(&base_path::url("/contacts/shares")))
+}
+
+/// The contacts share link `token` shows, and what they are, if the link works. Contacts
+/// that are about to be deleted are left out.
+async fn shared_contacts(state: &AppState, token: &str) -> Result<(ShareLink, String, Vec<Contact>), AppError> {
+    let link = ShareLink::find_by_token(&state.db, token)
+        .await?
+        .filter(|link| link.status(state.clock.now()) == ShareStatus::Active)
+        .ok_or(AppError::NotFound)?;
+    let (title, mut contacts) = match link.target() {
+        ShareTarget::Contact(id) => {
+            let contact = state.contacts.find_by_id(id).await?;
+            (preferences::display_name(&contact), vec![contact])
+        }
+        ShareTarget::Company(name) => {
+            let company = state.contacts.companies().await?.into_iter().find(|c| c.name == name);
+            (name, company.map(|c| c.contacts).unwrap_or_default())
+        }
+    };
+    contacts.retain(|c| !state.pending_deletions.is_pending(c.id));
+    if contacts.is_empty() {
+        return Err(AppError::NotFound);
+    }
+    let format = preferences::current().name_format;
+    contacts.sort_by(|a, b| format.compare_contacts(a, b));
+    Ok((link, title, contacts))
+}
+
+/// Template function: Renders what share link `token` shares, read-only and without the
+/// app's navigation. A link that has expired, was revoked or never existed is a 404.
+///
+/// Example usage:
+/// `GET /share/3f2b8c...`
+#[axum::debug_handler]
+async fn show_share(State(state): State<AppState>, Path(token): Path<String>) -> Result<Response, AppError> {
+    let (link, title, contacts) = shared_contacts(&state, &token).await?;
+    let template = SharedContactsTemplate {
+        title,
+        contacts,
+        expires: link.expires(),
+        timezone: state.config.get().timezone,
+        vcard_url: base_path::url(&format!("/share/{token}/contacts.vcf")),
+    };
+    Ok(Html(template.render()?).into_response())
+}
+
+/// Serves what share link `token` shares as vCards, one file for all of them.
+///
+/// Example usage:
+/// `curl -O http://localhost:2911/share/3f2b8c.../contacts.vcf`
+#[axum::debug_handler]
+async fn share_vcard(State(state): State<AppState>, Path(token): Path<String>) -> Result<Response, AppError> {
+    let (_, _, contacts) = shared_contacts(&state, &token).await?;
+    let body: String = contacts.iter().map(vcard::to_vcard).collect();
+    Ok((
+        [
+            (header::CONTENT_TYPE, carddav::VCARD_CONTENT_TYPE),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"contacts.vcf\""),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Template function: Renders the admin event log, newest first, filtered by kind, actor
+/// and date range.
+///
+/// Example usage:
+/// `GET /admin/events?kind=contact.deleted&since=2025-01-01`
+#[axum::debug_handler]
+async fn admin_events(
+    State(state): State<AppState>,
+    Query(filter): Query<EventFilter>,
+) -> Result<(StatusCode, Html<String>), AppError> {
+    let events = Event::list(&state.db, &filter, 200).await?;
+    let template = AdminEventsTemplate {
+        events,
+        filter,
+        kinds: events::KINDS,
+    };
+    let html = template.render()?;
+    Ok((StatusCode::OK, Html(html)))
+}
+
+/// Template function: Lists the database backups in the configured `backup_dir`, newest
+/// first, with download links.
+///
+/// Example usage:
+/// `GET /admin/backups`
+#[axum::debug_handler]
+async fn admin_backups(State(state): State<AppState>) -> Result<(StatusCode, Html<String>), AppError> {
+    let database = &state.config.get().database;
+    let template = AdminBackupsTemplate {
+        backups: backup::list(&database.backup_dir).await?,
+        interval_hours: database.backup_interval_hours,
+        retention: Retention::from(database),
+        last_job: state.jobs.latest(backup::BACKUP_JOB).await?,
+    };
+    let html = template.render()?;
+    Ok((StatusCode::OK, Html(html)))
+}
+
+/// Queues a backup of the live database and goes to the job list. The backup job writes it
+/// with `VACUUM INTO` while the server keeps serving requests, encrypted to the
+/// `backup_recipient` if one is configured.
+///
+/// Example usage:
+/// `curl -X POST http://localhost:2911/admin/backup`
+#[axum::debug_handler]
+async fn create_backup(State(state): State<AppState>) -> Result<Redirect, AppError> {
+    let id = state.jobs.enqueue(backup::BACKUP_JOB, json!({})).await?;
+    info!("Queued database backup as job {id}");
+    Ok(Redirect::to(&base_path::url("/admin/jobs")))
+}
+
+/// Template function: Lists the most recent background jobs with their status and outcome,
+/// newest first.
+///
+/// Example usage:
+/// `GET /admin/jobs`
+#[axum::debug_handler]
+async fn admin_jobs(State(state): State<AppState>) -> Result<(StatusCode, Html<String>), AppError> {
+    let jobs = state.jobs.list(100).await?;
+    let ldap_import = state.config.get().ldap.url.is_some();
+    let html = AdminJobsTemplate { jobs, ldap_import }.render()?;
+    Ok((StatusCode::OK, Html(html)))
+}
+
+#[derive(Debug, Deserialize)]
+struct PurgeEventsForm {
+    older_than_days: u32,
+}
+
+/// Queues a job deleting events older than `older_than_days` and goes to the job list.
+///
+/// Example usage:
+/// `curl -X POST -d older_than_days=90 http://localhost:2911/admin/jobs/purge_events`
+#[axum::debug_handler]
+async fn purge_events(
+    State(state): State<AppState>,
+    Form(form): Form<PurgeEventsForm>,
+) -> Result<Redirect, AppError> {
+    let payload = json!({ "older_than_days": form.older_than_days });
+    let id = state.jobs.enqueue(events::PURGE_EVENTS_JOB, payload).await?;
+    info!("Queued purge of events older than {} days as job {id}", form.older_than_days);
+    Ok(Redirect::to(&base_path::url("/admin/jobs")))
+}
+
+#[derive(Debug, Deserialize)]
+struct LdapImportForm {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Queues an import from the configured LDAP server and goes to the job list, where the job's
+/// outcome lists what changed (or, with `dry_run`, what would have).
+///
+/// Example usage:
+/// `curl -X POST -d dry_run=true http://localhost:2911/admin/jobs/ldap_import`
+#[axum::debug_handler]
+async fn ldap_import(
+    State(state): State<AppState>,
+    Form(form): Form<LdapImportForm>,
+) -> Result<Redirect, AppError> {
+    if state.config.get().ldap.url.is_none() {
+        return Err(anyhow::anyhow!("No LDAP server is configured").into());
+    }
+    let payload = json!({ "dry_run": form.dry_run });
+    let id = state.jobs.enqueue(ldap::LDAP_IMPORT_JOB, payload).await?;
+    info!("Queued LDAP import (dry run: {}) as job {id}", form.dry_run);
+    Ok(Redirect::to(&base_path::url("/admin/jobs")))
+}
+
+#[derive(Debug, Deserialize)]
+struct TestEmailForm {
+    to: String,
+}
+
+/// Queues a test email to `to` and goes to the job list, where the email job shows whether
+/// sending worked.
+///
+/// Example usage:
+/// `curl -X POST -d to=me@example.com http://localhost:2911/admin/email/test`
+#[axum::debug_handler]
+async fn send_test_email(
+    State(state): State<AppState>,
+    Form(form): Form<TestEmailForm>,
+) -> Result<Redirect, AppError> {
+    let email = Email::render(&form.to, &TestEmailTemplate { sent_at: state.clock.now() })?;
+    let id = email.queue(&state.jobs).await?;
+    info!("Queued test email to {} as job {id}", form.to);
+    Ok(Redirect::to(&base_path::url("/admin/jobs")))
+}
+
+/// Template function: Lists the registered webhooks, and the delivery log below them. Secrets
+/// aren't shown here, only once when a webhook is added.
+///
+/// Example usage:
+/// `GET /admin/webhooks`
+#[axum::debug_handler]
+async fn admin_webhooks(State(state): State<AppState>) -> Result<(StatusCode, Html<String>), AppError> {
+    let template = AdminWebhooksTemplate {
+        webhooks: Webhook::list(&state.db).await?,
+        deliveries: WebhookDelivery::list(&state.db, 100).await?,
+        created: None,
+        error_message: None,
+    };
+    Ok((StatusCode::OK, Html(template.render()?)))
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhookForm {
+    url: String,
+}
+
+/// Registers a webhook for `url` and shows the webhook list with its secret, the only time it
+/// is shown. An invalid URL, or one that doesn't resolve to public addresses, re-renders
+/// the list with the error.
+///
+/// Example usage:
+/// `curl -X POST -d url=https://example.com/hook http://localhost:2911/admin/webhooks`
+#[axum::debug_handler]
+async fn create_webhook(
+    State(state): State<AppState>,
+    Form(form): Form<WebhookForm>,
+) -> Result<Response, AppError> {
+    let url = match webhooks::check_url(&form.url).await {
+        Ok(url) => url,
+        Err(e) => {
+            let template = AdminWebhooksTemplate {
+                webhooks: Webhook::list(&state.db).await?,
+                deliveries: WebhookDelivery::list(&state.db, 100).await?,
+                created: None,
+                error_message: Some(e.to_string()),
+            };
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, Html(template.render()?)).into_response());
+        }
+    };
+    let webhook = Webhook::create(&state.db, &url).await?;
+    info!("Registered webhook {} for {}", webhook.id, webhook.url);
+    let template = AdminWebhooksTemplate {
+        webhooks: Webhook::list(&state.db).await?,
+        deliveries: WebhookDelivery::list(&state.db, 100).await?,
+        created: Some(webhook),
+        error_message: None,
+    };
+    Ok((StatusCode::CREATED, Html(template.render()?)).into_response())
+}
+
+/// Removes a webhook and goes back to the webhook list.
+///
+/// Example usage:
+/// `curl -X POST http://localhost:2911/admin/webhooks/1/delete`
+#[axum::debug_handler]
+async fn delete_webhook(State(state): State<AppState>, Path(id): Path<i64>) -> Result<Redirect, AppError> {
+    Webhook::delete(&state.db, id).await?;
+    info!("Removed webhook {id}");
+    Ok(Redirect::to(&base_path::url("/admin/webhooks")))
+}
+
+/// Downloads a backup file. Only names of files created by `create_backup` are accepted,
+/// anything else is a 404.
+///
+/// Example usage:
+/// `curl -O http://localhost:2911/admin/backups/webone-20250101-120000.000.db`
+#[axum::debug_handler]
+async fn download_backup(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Response, AppError> {
+    let Some(path) = backup::path(&state.config.get().database.backup_dir, &name) else {
+        return Err(AppError::NotFound);
+    };
+    let file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(AppError::NotFound),
+        Err(e) => return Err(e.into()),
+    };
+    let disposition = format!("attachment; filename=\"{name}\"");
+    let content_type = if name.ends_with(".age") { "application/octet-stream" } else { "application/vnd.sqlite3" };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
+/// `/.well-known/carddav` (RFC 6764): tells address book apps where the CardDAV server is.
+#[axum::debug_handler]
+async fn well_known_carddav() -> Response {
+    (StatusCode::MOVED_PERMANENTLY, [(header::LOCATION, base_path::url(carddav::ROOT))]).into_response()
+}
+
+fn dav_options(allow: &'static str) -> Response {
+    (
+        StatusCode::OK,
+        [(carddav::DAV, carddav::DAV_COMPLIANCE), (header::ALLOW, allow)],
+    )
+        .into_response()
+}
+
+fn dav_method_not_allowed(allow: &'static str) -> Response {
+    (StatusCode::METHOD_NOT_ALLOWED, [(header::ALLOW, allow)]).into_response()
+}
+
+fn dav_multistatus(resources: Vec<DavResource>) -> Result<Response, AppError> {
+    let xml = DavMultistatusTemplate { resources }.render()?;
+    Ok((
+        StatusCode::MULTI_STATUS,
+        [
+            (header::CONTENT_TYPE, carddav::XML_CONTENT_TYPE),
+            (carddav::DAV, carddav::DAV_COMPLIANCE),
+        ],
+        xml,
+    )
+        .into_response())
+}
+
+/// Every contact as a card, leaving out the ones pending deletion like the list page does.
+async fn dav_cards(state: &AppState) -> Result<Vec<Contact>, AppError> {
+    let contacts = state.contacts.all().await?;
+    Ok(contacts
+        .into_iter()
+        .filter(|c| !state.pending_deletions.is_pending(c.id))
+        .collect())
+}
+
+const DAV_COLLECTION_METHODS: &str = "OPTIONS, PROPFIND";
+
+/// CardDAV discovery: the DAV root and the principal answer `PROPFIND` with where to go next
+/// (`current-user-principal`, then `addressbook-home-set`).
+///
+/// Example usage:
+/// `curl -X PROPFIND -H "Depth: 0" http://localhost:2911/dav/principal/`
+#[axum::debug_handler]
+async fn dav_discovery(method: Method, OriginalUri(uri): OriginalUri) -> Result<Response, AppError> {
+    let resource = match base_path::strip(uri.path()) {
+        carddav::PRINCIPAL => DavResource::principal(),
+        _ => DavResource::collection(carddav::ROOT),
+    };
+    match method.as_str() {
+        "PROPFIND" => dav_multistatus(vec![resource]),
+        "OPTIONS" => Ok(dav_options(DAV_COLLECTION_METHODS)),
+        _ => Ok(dav_method_not_allowed(DAV_COLLECTION_METHODS)),
+    }
+}
+
+/// The address book home. With `Depth: 1` it lists the one address book.
+///
+/// Example usage:
+/// `curl -X PROPFIND -H "Depth: 1" http://localhost:2911/dav/addressbooks/`
+#[axum::debug_handler]
+async fn dav_home(
+    State(state): State<AppState>,
+    method: Method,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    match method.as_str() {
+        "PROPFIND" => {
+            let mut resources = vec![DavResource::home()];
+            if carddav::wants_children(&headers) {
+                resources.push(DavResource::addressbook(&dav_cards(&state).await?));
+            }
+            dav_multistatus(resources)
+        }
+        "OPTIONS" => Ok(dav_options(DAV_COLLECTION_METHODS)),
+        _ => Ok(dav_method_not_allowed(DAV_COLLECTION_METHODS)),
+    }
+}
+
+const DAV_ADDRESSBOOK_METHODS: &str = "OPTIONS, PROPFIND, REPORT";
+
+/// The address book. `PROPFIND` with `Depth: 1` lists every card with its ETag,
+/// `addressbook-multiget` returns the requested cards and `addressbook-query` all of them.
+///
+/// Example usage:
+/// `curl -X PROPFIND -H "Depth: 1" http://localhost:2911/dav/addressbooks/contacts/`
+#[axum::debug_handler]
+async fn dav_addressbook(
+    State(state): State<AppState>,
+    method: Method,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Response, AppError> {
+    match method.as_str() {
+        "PROPFIND" => {
+            let contacts = dav_cards(&state).await?;
+            let mut resources = vec![DavResource::addressbook(&contacts)];
+            if carddav::wants_children(&headers) {
+                resources.extend(contacts.iter().map(|c| DavResource::card(c, false)));
+            }
+            dav_multistatus(resources)
+        }
+        "REPORT" => {
+            let report = match carddav::parse_report(&body) {
+                Ok(report) => report,
+                Err(e) => return Ok((StatusCode::BAD_REQUEST, format!("{e:#}")).into_response()),
+            };
+            let contacts = dav_cards(&state).await?;
+            let resources = match report {
+                Report::Multiget(hrefs) => {
+                    let by_id: HashMap<i64, &Contact> = contacts.iter().map(|c| (c.id, c)).collect();
+                    hrefs
+                        .iter()
+                        .map(|href| match carddav::contact_id(href).and_then(|id| by_id.get(&id)) {
+                            Some(contact) => DavResource::card(contact, true),
+                            None => DavResource::not_found(href),
+                        })
+                        .collect()
+                }
+                Report::Query => contacts.iter().map(|c| DavResource::card(c, true)).collect(),
+                Report::Unsupported(name) => {
+                    let message = format!("Unsupported report {name:?}");
+                    return Ok((StatusCode::FORBIDDEN, message).into_response());
+                }
+            };
+            dav_multistatus(resources)
+        }
+        "OPTIONS" => Ok(dav_options(DAV_ADDRESSBOOK_METHODS)),
+        _ => Ok(dav_method_not_allowed(DAV_ADDRESSBOOK_METHODS)),
+    }
+}
+
+const DAV_CARD_METHODS: &str = "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND";
+
+/// One card, `<id>.vcf`. `PUT` updates the contact, or creates one if the card doesn't exist;
+/// new cards get an id-based name, returned in `Location`, instead of the one the client
+/// picked. `If-Match` and `If-None-Match` are honoured so clients don't overwrite each other,
+/// and a card that doesn't validate like the contact form is a `422` with the problems.
+///
+/// Example usage:
+/// `curl http://localhost:2911/dav/addressbooks/contacts/1.vcf`
+/// `curl -X PUT -H "If-None-Match: *" --data-binary @card.vcf http://localhost:2911/dav/addressbooks/contacts/new.vcf`
+#[axum::debug_handler]
+async fn dav_card(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    method: Method,
+    headers: HeaderMap,
+    Actor(actor): Actor,
+    body: String,
+) -> Result<Response, AppError> {
+    let contact = match carddav::contact_id(&name).filter(|id| !state.pending_deletions.is_pending(*id)) {
+        Some(id) => match state.contacts.find_by_id(id).await {
+            Ok(contact) => Some(contact),
+            Err(ContactError::NotFound) => None,
+            Err(e) => return Err(e.into()),
+        },
+        None => None,
+    };
+    let current_etag = contact.as_ref().map(carddav::etag);
+
+    match (method.as_str(), contact) {
+        ("GET" | "HEAD", Some(contact)) => Ok((
+            [
+                (header::CONTENT_TYPE, carddav::VCARD_CONTENT_TYPE.to_string()),
+                (header::ETAG, carddav::etag(&contact)),
+            ],
+            vcard::to_vcard(&contact),
+        )
+            .into_response()),
+        ("PROPFIND", Some(contact)) => dav_multistatus(vec![DavResource::card(&contact, false)]),
+        ("PUT", contact) => {
+            if !carddav::preconditions_hold(&headers, current_etag.as_deref()) {
+                return Ok(StatusCode::PRECONDITION_FAILED.into_response());
+            }
+            let fields = match vcard::parse(&body) {
+                Ok(fields) => fields,
+                Err(e) => return Ok((StatusCode::BAD_REQUEST, format!("{e:#}")).into_response()),
+            };
+            let new_contact = NewContact::from(fields);
+            if let Some(errors) = new_contact.validate() {
+                return Ok((StatusCode::UNPROCESSABLE_ENTITY, errors.summary()).into_response());
+            }
+            match contact {
+                Some(mut contact) => {
+                    contact.update_from(new_contact);
+                    // Only if nobody changed it since the preconditions were checked. Another
+                    // contact with the card's email or phone number is a `409`
+                    match state.contacts.update_unchanged(&contact, state.clock.now()).await {
+                        Ok(()) => {}
+                        Err(ContactError::Changed) => return Ok(StatusCode::PRECONDITION_FAILED.into_response()),
+                        Err(e) => return Err(e.into()),
+                    }
+                    let contact = state.contacts.find_by_id(contact.id).await?;
+                    let payload = serde_json::to_value(&contact)?;
+                    record_contact_event(&state, events::CONTACT_UPDATED, &actor, contact.id, payload).await?;
+                    Ok((StatusCode::NO_CONTENT, [(header::ETAG, carddav::etag(&contact))]).into_response())
+                }
+                None => {
+                    let contact = state.contacts.create(new_contact, state.clock.now()).await?;
+                    let payload = serde_json::to_value(&contact)?;
+                    record_contact_event(&state, events::CONTACT_CREATED, &actor, contact.id, payload).await?;
+                    Ok((
+                        StatusCode::CREATED,
+                        [
+                            (header::LOCATION, base_path::url(&carddav::contact_href(contact.id))),
+                            (header::ETAG, carddav::etag(&contact)),
+                        ],
+                    )
+                        .into_response())
+                }
+            }
+        }
+        ("DELETE", Some(contact)) => {
+            if !carddav::preconditions_hold(&headers, current_etag.as_deref()) {
+                return Ok(StatusCode::PRECONDITION_FAILED.into_response());
+            }
+            // No undo from an address book app, so the contact goes right away, into the trash
+            // unless it's off. Like updates, only if nobody changed it since the check
+            if state.config.get().trash.retention_days > 0 {
+                let dates = state.contacts.dates(contact.id).await?;
+                TrashedContact::add(&state.db, &contact, &dates).await?;
+                if let Err(e) = state.contacts.delete_unchanged(&contact).await {
+                    TrashedContact::remove(&state.db, contact.id).await?;
+                    return match e {
+                        ContactError::Changed => Ok(StatusCode::PRECONDITION_FAILED.into_response()),
+                        e => Err(e.into()),
+                    };
+                }
+            } else {
+                match state.contacts.delete_unchanged(&contact).await {
+                    Ok(()) => state.attachments.remove_for_contact(contact.id).await?,
+                    Err(ContactError::Changed) => return Ok(StatusCode::PRECONDITION_FAILED.into_response()),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            let payload = serde_json::to_value(&contact)?;
+            record_contact_event(&state, events::CONTACT_DELETED, &actor, contact.id, payload).await?;
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        ("OPTIONS", _) => Ok(dav_options(DAV_CARD_METHODS)),
+        ("GET" | "HEAD" | "PROPFIND" | "DELETE", None) => Ok(StatusCode::NOT_FOUND.into_response()),
+        _ => Ok(dav_method_not_allowed(DAV_CARD_METHODS)),
+    }
+}
+
+/// Exports contacts as a CSV download. The `dialect` parameter picks between standard
+/// CSV and the semicolon/CRLF/BOM flavour that Excel in European locales expects.
+///
+/// Takes the same search, filter and sort parameters as `/contacts`, so the export has the
+/// contacts the list shows, in the same order. Without any it exports every contact by id.
+/// The last column, `name`, is written in the preferred name format.
+///
+/// The CSV is streamed: a background task reads the contacts row by row (or a page of search
+/// results at a time) and sends the output in chunks, so memory use doesn't grow with the
+/// number of contacts. An error halfway through can't change the status anymore; it aborts
+/// the download instead.
+///
+/// Example usage:
+/// `GET /contacts/export.csv?q=smith&sort=last_name&dialect=excel`
+#[axum::debug_handler]
+async fn export_csv(
+    State(state): State<AppState>,
+    Query(params): Query<ExportParams>,
+    list: ListParams,
+) -> impl IntoResponse {
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    let (contacts, search) = (state.contacts.clone(), state.search.clone());
+    // The preferences are request scoped and don't follow the spawned task
+    let name_format = preferences::current().name_format;
+    tokio::spawn(async move {
+        let exported = if list.filter.is_empty() && list.sort == ContactOrder::default() {
+            export::stream_csv(contacts.as_ref(), params.dialect, name_format, tx.clone()).await
+        } else {
+            export::stream_matching_csv(search.as_ref(), &list.filter, list.sort, params.dialect, name_format, tx.clone())
+                .await
+        };
+        if let Err(e) = exported {
+            error!("CSV export failed: {e:#}");
+            let _ = tx.send(Err(e)).await;
+        }
+    });
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"contacts.csv\""),
+        ],
+        Body::from_stream(ReceiverStream::new(rx)),
+    )
+}
+
+/// Validates input parameters by checking if email and/or phone already exist in the database.
+/// Returns form-level error HTML and updates the submit button state via OOB swap.
+///
+/// This validates BOTH fields together to avoid race conditions where fixing one field
+/// might incorrectly enable the button while the other field is still invalid.
+///
+/// Example usage:
+/// A GET request from `HTMX` when entering an email or phone into a form. The response
+/// replaces the `#form-errors` div and updates the submit button via `hx-swap-oob`.
+#[axum::debug_handler]
+async fn validate_input(
+    State(state): State<AppState>,
+    Query(params): Query<ValidateParams>,
+) -> Result<(StatusCode, Html<String>), AppError> {
+    // Validate both fields (either may be None if not yet entered)
+    let email_exists = match &params.email {
+        Some(email) if !email.is_empty() => {
+            state.contacts.email_exists(email, params.exclude_id).await?
+        }
+        _ => false,
+    };
+
+    let phone_exists = match &params.phone_number {
+        Some(phone) if !phone.is_empty() => {
+            state.contacts.phone_exists(phone, params.exclude_id).await?
+        }
+        _ => false,
+    };
+
+    // Build error message and button state based on combined validation
+    let error_msg = match (email_exists, phone_exists) {
+        (true, true) => i18n::t("email-and-phone-exist"),
+        (true, false) => i18n::t("email-exists"),
+        (false, true) => i18n::t("phone-exists"),
+        (false, false) => String::new(), // No error
+    };
+    let button_html = if error_msg.is_empty() {
+        format!(r#"<button id="submit-btn" hx-swap-oob="true">{}</button>"#, i18n::t("action-save"))
+    } else {
+        format!(
+            r#"<button id="submit-btn" hx-swap-oob="true" disabled class="btn-disabled">{}</button>"#,
+            i18n::t("action-cannot-save")
+        )
+    };
+
+    // Return error message (goes to #form-errors) + OOB button update
+    Ok((StatusCode::OK, Html(format!(
+        r#"{}<span hx-swap-oob="true" id="form-errors">{}</span>"#,
+        button_html, error_msg
+    ))))
+}
+/// Template function: Renders the dashboard with the contact statistics, worked out fresh on
+/// every visit.
+///
+/// Example usage:
+/// `GET /dashboard`
+#[axum::debug_handler]
+async fn dashboard(State(state): State<AppState>) -> Result<(StatusCode, Html<String>), AppError> {
+    let template = DashboardTemplate {
+        stats: state.contacts.stats(state.clock.now()).await?,
+        timezone: state.config.get().timezone,
+        now: state.clock.now(),
+    };
+    Ok((StatusCode::OK, Html(template.render()?)))
+}
+
+/// Changes shown per page of the activity feed.
+const ACTIVITY_PAGE_SIZE: i64 = 50;
+
+/// Template function: Renders the activity feed, the latest contact creates, edits, deletes
+/// and restores from the event log, newest first. `contact` narrows it down to one contact and
+/// `actor` to the changes made by one client; Older links page back with `before`.
+///
+/// Example usage:
+/// `GET /activity?contact=1&actor=127.0.0.1`
+#[axum::debug_handler]
+async fn activity(
+    State(state): State<AppState>,
+    Query(filter): Query<ActivityFilter>,
+) -> Result<(StatusCode, Html<String>), AppError> {
+    let mut events = Event::activity(&state.db, &filter, ACTIVITY_PAGE_SIZE + 1).await?;
+    let older = if events.len() as i64 > ACTIVITY_PAGE_SIZE {
+        events.truncate(ACTIVITY_PAGE_SIZE as usize);
+        events.last().map(|event| event.id)
+    } else {
+        None
+    };
+    let template = ActivityTemplate {
+        events,
+        filter,
+        timezone: state.config.get().timezone,
+        now: state.clock.now(),
+        older,
+    };
+    Ok((StatusCode::OK, Html(template.render()?)))
+}
+
+#[derive(Debug, Deserialize)]
+struct SettingsForm {
+    /// Empty to follow the configured page size.
+    per_page: String,
+    sort: String,
+    name_format: String,
+}
+
+/// Template function: Renders the display preferences form with this browser's preferences.
+///
+/// Example usage:
+/// `GET /settings`
+#[axum::debug_handler]
+async fn get_settings(
+    State(state): State<AppState>,
+    preferences: Preferences,
+) -> Result<Html<String>, AppError> {
+    let template = SettingsTemplate {
+        preferences,
+        default_per_page: state.config.get().page_size,
+        max_per_page: params::MAX_PER_PAGE,
+        error_message: None,
+    };
+    Ok(Html(template.render()?))
+}
+
+/// Saves the display preferences in the `prefs` cookie for a year and goes to the contact
+/// list, which shows them right away. A page size out of range gets the form back.
+///
+/// Example usage:
+/// `curl -X POST -d 'per_page=25&sort=last_name&name_format=last_first' http://localhost:2911/settings`
+#[axum::debug_handler]
+async fn post_settings(State(state): State<AppState>, Form(form): Form<SettingsForm>) -> Result<Response, AppError> {
+    let mut preferences = Preferences {
+        per_page: None,
+        sort: ContactSort::parse(form.sort.trim()),
+        name_format: NameFormat::parse(form.name_format.trim()).unwrap_or_default(),
+    };
+    match form.per_page.trim() {
+        "" => {}
+        per_page => match per_page.parse::<i64>() {
+            Ok(per_page) if (1..=params::MAX_PER_PAGE).contains(&per_page) => preferences.per_page = Some(per_page),
+            _ => {
+                let template = SettingsTemplate {
+                    preferences,
+                    default_per_page: state.config.get().page_size,
+                    max_per_page: params::MAX_PER_PAGE,
+                    error_message: Some(i18n::t_with("invalid-per-page-preference", "max", params::MAX_PER_PAGE)),
+                };
+                return Ok(Html(template.render()?).into_response());
+            }
+        },
+    }
+    Ok(([(header::SET_COOKIE, preferences.cookie())], Redirect::to(&base_path::url("/contacts"))).into_response())
+}
+
+/// Template function: Renders the latest data quality report. The report itself is generated
+/// in the background, so this never scans the contacts table on the request path.
+#[axum::debug_handler]
+async fn quality_report(
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Html<String>), AppError> {
+    let template = QualityReportTemplate {
+        report: state.quality_reports.latest(),
+    };
+    let html = template.render()?;
+    Ok((StatusCode::OK, Html(html)))
+}
+
+/// Kicks off a regeneration of the data quality report and goes back to the report page,
+/// which shows the previous report until the new one is ready.
+#[axum::debug_handler]
+async fn refresh_quality_report(State(state): State<AppState>) -> Redirect {
+    state.quality_reports.refresh_in_background(state.contacts.clone());
+    Redirect::to(&base_path::url("/reports/quality"))
+}
+
+/// Connection pool and prepared statement cache figures, for tuning
+/// `database.max_connections` and `database.statement_cache_capacity`. A cache that sits at
+/// the capacity means statements get evicted and prepared again.
+///
+/// Example usage:
+/// `curl http://localhost:2911/admin/db` returns
+/// `{"connections":2,"idle_connections":2,"cached_statements":7,"max_connections":10,"statement_cache_capacity":100}`
+#[axum::debug_handler]
+async fn admin_db(State(state): State<AppState>) -> Result<Json<DbStatus>, AppError> {
+    let database = &state.config.get().database;
+    Ok(Json(DbStatus {
+        pool: state.db.pool_stats().await?,
+        max_connections: database.max_connections,
+        statement_cache_capacity: database.statement_cache_capacity,
+    }))
+}
+
+/// The API keys page: `new_key` right after it's made, or `error_message` if it couldn't be.
+/// Never cached, as it can have a key on it.
+async fn api_keys_page(
+    state: &AppState,
+    status: StatusCode,
+    new_key: Option<(ApiKey, String)>,
+    error_message: Option<String>,
+) -> Result<Response, AppError> {
+    let config = state.config.get();
+    let template = ApiKeysTemplate {
+        keys: ApiKey::list(&state.db).await?,
+        new_key,
+        require_key: config.api.require_key,
+        timezone: config.timezone,
+        now: state.clock.now(),
+        error_message,
+    };
+    Ok((status, [(header::CACHE_CONTROL, "no-store")], Html(template.render()?)).into_response())
+}
+
+/// Template function: Renders the JSON API keys, newest first, with when each was last used,
+/// and the form to make one. See `api_keys`.
+///
+/// Example usage:
+/// `GET /settings/api_keys`
+#[axum::debug_handler]
+async fn get_api_keys(State(state): State<AppState>) -> Result<Response, AppError> {
+    api_keys_page(&state, StatusCode::OK, None, None).await
+}
+
+/// Makes an API key called `name`, with the limits the form has, and shows it, the only time
+/// it can be seen. A missing or too long name, or a limit that isn't a positive number, gets
+/// the form back with a 422.
+///
+/// Example usage:
+/// `curl -X POST -d 'name=Nightly sync&requests_per_minute=60&requests_per_day=10000' http://localhost:2911/settings/api_keys`
+#[axum::debug_handler]
+async fn create_api_key(
+    State(state): State<AppState>,
+    Actor(actor): Actor,
+    Form(form): Form<ApiKeyForm>,
+) -> Result<Response, AppError> {
+    let name = form.name.trim();
+    if name.is_empty() || name.chars().count() > api_keys::MAX_NAME_LEN {
+        let error_message = i18n::t_with("api-keys-invalid-name", "max", api_keys::MAX_NAME_LEN);
+        return api_keys_page(&state, StatusCode::UNPROCESSABLE_ENTITY, None, Some(error_message)).await;
+    }
+    let Some(limits) = KeyLimits::parse(&form.limits.requests_per_minute, &form.limits.requests_per_day) else {
+        let error_message = i18n::t("api-keys-invalid-limits");
+        return api_keys_page(&state, StatusCode::UNPROCESSABLE_ENTITY, None, Some(error_message)).await;
+    };
+    let (api_key, key) = ApiKey::create(&state.db, name, limits, state.clock.now()).await?;
+    info!("Created API key {} ({}) for {actor}", api_key.id, api_key.name);
+    let payload = json!({ "id": api_key.id, "name": api_key.name, "prefix": api_key.prefix });
+    Event::record(&state.db, events::API_KEY_CREATED, &actor, None, payload).await?;
+    api_keys_page(&state, StatusCode::CREATED, Some((api_key, key)), None).await
+}
+
+/// Gives API key `id` the rate and daily quota the form has, empty for none. They apply from
+/// the next request on. Limits that aren't positive numbers get the page back with a 422.
+///
+/// Example usage:
+/// `curl -X POST -d 'requests_per_minute=60&requests_per_day=' http://localhost:2911/settings/api_keys/2/limits`
+#[axum::debug_handler]
+async fn set_api_key_limits(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Actor(actor): Actor,
+    Form(form): Form<ApiKeyLimitsForm>,
+) -> Result<Response, AppError> {
+    let Some(limits) = KeyLimits::parse(&form.requests_per_minute, &form.requests_per_day) else {
+        let error_message = i18n::t("api-keys-invalid-limits");
+        return api_keys_page(&state, StatusCode::UNPROCESSABLE_ENTITY, None, Some(error_message)).await;
+    };
+    if let Some(api_key) = ApiKey::set_limits(&state.db, id, limits).await? {
+        info!("Changed the limits of API key {id} ({}) for {actor}", api_key.name);
+        let payload = json!({
+            "id": api_key.id,
+            "name": api_key.name,
+            "prefix": api_key.prefix,
+            "requests_per_minute": api_key.requests_per_minute,
+            "requests_per_day": api_key.requests_per_day,
+        });
+        Event::record(&state.db, events::API_KEY_LIMITS_CHANGED, &actor, None, payload).await?;
+    }
+    Ok(Redirect::to(&base_path::url("/settings/api_keys")).into_response())
+}
+
+/// Revokes API key `id`: requests with it get a 401 from now on. It stays on the list as
+/// revoked.
+///
+/// Example usage:
+/// `curl -X POST http://localhost:2911/settings/api_keys/2/revoke`
+#[axum::debug_handler]
+async fn revoke_api_key(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Actor(actor): Actor,
+) -> Result<Redirect, AppError> {
+    if let Some(api_key) = ApiKey::revoke(&state.db, id, state.clock.now()).await? {
+        info!("Revoked API key {id} ({}) for {actor}", api_key.name);
+        let payload = json!({ "id": api_key.id, "name": api_key.name, "prefix": api_key.prefix });
+        Event::record(&state.db, events::API_KEY_REVOKED, &actor, None, payload).await?;
+    }
+    Ok(Redirect::to(&base_path::url("/settings/api_keys")))
+}
+
+/// Most contacts one `POST /api/v1/contacts/batch` may create.
+const MAX_BATCH_CONTACTS: usize = 1000;
+
+/// Creates many contacts in one request, for programmatic imports, with `import::create_all`.
+/// The answer has a result per contact, in request order: its new `id`, or the `errors` by
+/// field, including "already exists" for an email or phone number another contact (or an
+/// earlier one in the batch) has. At most `MAX_BATCH_CONTACTS`.
+///
+/// Example usage:
+/// `curl -H 'Content-Type: application/json' -d '[{"first_name":"Jane","last_name":"Doe","phone_number":"206 555 0100","email":"jane@example.com"}]' http://localhost:2911/api/v1/contacts/batch`
+/// returns `{"created":1,"failed":0,"results":[{"index":0,"id":16}]}`
+#[axum::debug_handler]
+async fn api_create_contacts(
+    State(state): State<AppState>,
+    _access: ApiAccess,
+    Actor(actor): Actor,
+    body: Result<Json<Vec<NewContact>>, JsonRejection>,
+) -> Result<Response, AppError> {
+    let Json(contacts) = body?;
+    if contacts.len() > MAX_BATCH_CONTACTS {
+        let error = i18n::t_with("batch-too-large", "max", MAX_BATCH_CONTACTS);
+        return Ok((StatusCode::PAYLOAD_TOO_LARGE, Json(json!({ "error": error }))).into_response());
+    }
+    let results =
+        import::create_all(state.contacts.as_ref(), &state.db, &state.jobs, &state.clock, &actor, contacts).await?;
+    let created = results.iter().filter(|result| result.id.is_some()).count();
+    info!("Batch of {} contacts: {created} created", results.len());
+    Ok(Json(BatchResponse { created, failed: results.len() - created, results }).into_response())
+}
+
+/// Makes a batch of creates, updates and deletes in one transaction, for sync clients: all of
+/// them or, if one fails, none (see `batch`). The answer has a result per operation, in
+/// request order, with `applied` for all of them, or `failed` for the one that stopped the
+/// batch and `not_applied` for the rest: `422` if its fields don't validate, `409` if another
+/// contact has the email or phone number, `404` if the contact to change isn't there, `412`
+/// if its `if_match` isn't the contact's ETag anymore.
+/// Deleted contacts go to the trash right away, like CardDAV deletes. At most
+/// `batch::MAX_OPERATIONS`.
+///
+/// Example usage:
+/// `curl -H 'Content-Type: application/json' -d '[{"op":"update","id":1,"contact":{"job_title":"CTO"}},{"op":"delete","id":3}]' http://localhost:2911/api/v1/batch`
+/// returns `{"applied":true,"results":[{"index":0,"op":"update","status":"applied","id":1},{"index":1,"op":"delete","status":"applied","id":3}]}`
+#[axum::debug_handler]
+async fn api_batch(
+    State(state): State<AppState>,
+    _access: ApiAccess,
+    Actor(actor): Actor,
+    body: Result<Json<Vec<Operation>>, JsonRejection>,
+) -> Result<Response, AppError> {
+    let Json(operations) = body?;
+    if operations.len() > batch::MAX_OPERATIONS {
+        let error = i18n::t_with("batch-too-many-operations", "max", batch::MAX_OPERATIONS);
+        return Ok((StatusCode::PAYLOAD_TOO_LARGE, Json(json!({ "error": error }))).into_response());
+    }
+    let rejection = match batch::plan(state.contacts.as_ref(), &operations).await? {
+        Ok(writes) => {
+            // Like a CardDAV delete, no undo: into the trash before the contact goes, and out
+            // of it again if the batch is rolled back
+            let trash = state.config.get().trash.retention_days > 0;
+            let trashed: Vec<&Contact> = writes
+                .iter()
+                .filter_map(|write| match write {
+                    ContactWrite::Delete(contact) if trash => Some(contact),
+                    _ => None,
+                })
+                .collect();
+            for contact in &trashed {
+                let dates = state.contacts.dates(contact.id).await?;
+                TrashedContact::add(&state.db, contact, &dates).await?;
+            }
+            let result = state.contacts.apply_batch(&writes, state.clock.now()).await;
+            if !matches!(result, Ok(Ok(_))) {
+                for contact in &trashed {
+                    TrashedContact::remove(&state.db, contact.id).await?;
+                }
+            }
+            match result? {
+                Ok(applied) => {
+                    record_batch_events(&state, &actor, &writes, &applied).await?;
+                    info!("Batch of {} operations applied", operations.len());
+                    let results = batch::applied(&operations, &applied);
+                    return Ok(Json(OperationsResponse { applied: true, results }).into_response());
+                }
+                Err(failure) => batch::rejection(state.contacts.as_ref(), &writes, failure).await?,
+            }
+        }
+        Err(rejection) => rejection,
+    };
+    let status = match rejection.reason {
+        RejectionReason::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        RejectionReason::Taken(_) => StatusCode::CONFLICT,
+        RejectionReason::NotFound => StatusCode::NOT_FOUND,
+        RejectionReason::Stale(_) => StatusCode::PRECONDITION_FAILED,
+    };
+    info!("Batch of {} operations rolled back at operation {}", operations.len(), rejection.index);
+    let results = batch::rejected(&operations, rejection);
+    Ok((status, Json(OperationsResponse { applied: false, results })).into_response())
+}
+
+/// Records the changes of an applied batch like single ones: events, webhooks and live
+/// updates, and the attachments of deleted contacts when there's no trash to keep them for.
+async fn record_batch_events(
+    state: &AppState,
+    actor: &str,
+    writes: &[ContactWrite],
+    applied: &[Contact],
+) -> Result<(), AppError> {
+    for (write, contact) in writes.iter().zip(applied) {
+        let kind = match write {
+            ContactWrite::Create(_) => events::CONTACT_CREATED,
+            ContactWrite::Update(_) => events::CONTACT_UPDATED,
+            ContactWrite::Delete(_) => {
+                if state.config.get().trash.retention_days == 0 {
+                    state.attachments.remove_for_contact(contact.id).await?;
+                }
+                events::CONTACT_DELETED
+            }
+        };
+        record_contact_event(state, kind, actor, contact.id, serde_json::to_value(contact)?).await?;
+    }
+    Ok(())
+}
+
+/// One page of the contact list as JSON, with the total and a link to the next page. Takes
+/// the same parameters as `/contacts`, so bad ones get the same 400 (see `ListParams`), with
+/// the problem as `{"error": "..."}`.
+///
+/// Example usage:
+/// `curl 'http://localhost:2911/api/v1/contacts?q=smith&sort=company,last_name&per_page=25'`
+/// returns `{"contacts":[...],"page":1,"per_page":25,"total":3,"next":null}`
+#[axum::debug_handler]
+async fn api_list_contacts(
+    State(state): State<AppState>,
+    _access: ApiAccess,
+    params: ListParams,
+) -> Result<Response, AppError> {
+    let (contacts, pagination) = list_page(&state, &params, &base_path::url("/api/v1/contacts")).await?;
+    let next = pagination.has_next().then(|| pagination.next_url());
+    let Pagination { page, per_page, total, .. } = pagination;
+    Ok(Json(ContactListResponse { contacts, page, per_page, total, next }).into_response())
+}
+
+/// Contact `id` as JSON, with its `ETag::version` for the `If-Match` of a change to it.
+/// Answers `304 Not Modified` when `If-None-Match` has that ETag.
+///
+/// Example usage:
+/// `curl -i http://localhost:2911/api/v1/contacts/1` returns the contact with `ETag: "1-20261015093000123"`
+#[axum::debug_handler]
+async fn api_get_contact(
+    State(state): State<AppState>,
+    _access: ApiAccess,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> Result<Response, AppError> {
+    let contact = state.contacts.find_by_id(id).await?;
+    let etag = ETag::version(&contact);
+    if etag.matches(&headers) {
+        return Ok(etag.not_modified());
+    }
+    Ok(etag.attach(Json(contact).into_response()))
+}
+
+/// Replaces all of a contact's fields, like the edit form. Needs the contact's ETag in
+/// `If-Match` (see `api_if_match`). Answers with the saved contact and its new ETag, or the
+/// `errors` by field: `422` if they don't validate, `409` if another contact has the email or
+/// phone number.
+///
+/// Example usage:
+/// `curl -X PUT -H 'If-Match: "1-20261015093000123"' -H 'Content-Type: application/json' -d '{"first_name":"Jane","last_name":"Doe","phone_number":"206 555 0100","email":"jane@example.com"}' http://localhost:2911/api/v1/contacts/1`
+#[axum::debug_handler]
+async fn api_put_contact(
+    State(state): State<AppState>,
+    _access: ApiAccess,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    Actor(actor): Actor,
+    body: Result<Json<NewContact>, JsonRejection>,
+) -> Result<Response, AppError> {
+    let Json(new) = body?;
+    let contact = state.contacts.find_by_id(id).await?;
+    if let Some(rejected) = api_if_match(&headers, &contact) {
+        return Ok(rejected);
+    }
+    api_update_contact(&state, &actor, contact, new).await
+}
+
+/// Changes only the fields the body has, like `api_put_contact` otherwise.
+///
+/// Example usage:
+/// `curl -X PATCH -H 'If-Match: "1-20261015093000123"' -H 'Content-Type: application/json' -d '{"job_title":"CTO"}' http://localhost:2911/api/v1/contacts/1`
+#[axum::debug_handler]
+async fn api_patch_contact(
+    State(state): State<AppState>,
+    _access: ApiAccess,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    Actor(actor): Actor,
+    body: Result<Json<ContactPatch>, JsonRejection>,
+) -> Result<Response, AppError> {
+    let Json(patch) = body?;
+    let contact = state.contacts.find_by_id(id).await?;
+    if let Some(rejected) = api_if_match(&headers, &contact) {
+        return Ok(rejected);
+    }
+    let new = patch.apply(&contact);
+    api_update_contact(&state, &actor, contact, new).await
+}
+
+async fn api_update_contact(
+    state: &AppState,
+    actor: &str,
+    mut contact: Contact,
+    new: NewContact,
+) -> Result<Response, AppError> {
+    if let Some(errors) = new.validate() {
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(json!({ "errors": errors }))).into_response());
+    }
+    contact.update_from(new);
+    // Only if nobody changed it since `api_if_match` looked, so two clients with the same
+    // ETag can't both get through
+    if let Err(e) = state.contacts.update_unchanged(&contact, state.clock.now()).await {
+        if let ContactError::Changed = e {
+            return api_changed(state, contact.id).await;
+        }
+        return match SaveRejected::from_error(e, &format!("Updating contact {}", contact.id))? {
+            SaveRejected { errors: Some(errors), status, .. } => Ok((status, Json(json!({ "errors": errors }))).into_response()),
+            rejected => Ok((StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": rejected.message }))).into_response()),
+        };
+    }
+    record_contact_event(state, events::CONTACT_UPDATED, actor, contact.id, serde_json::to_value(&contact)?).await?;
+    let contact = state.contacts.find_by_id(contact.id).await?;
+    Ok(ETag::version(&contact).attach(Json(contact).into_response()))
+}
+
+/// Deletes a contact. Needs its ETag in `If-Match` (see `api_if_match`). Like a CardDAV
+/// delete there's no undo, so the contact goes right away, into the trash unless it's off.
+///
+/// Example usage:
+/// `curl -X DELETE -H 'If-Match: "1-20261015093000123"' http://localhost:2911/api/v1/contacts/1` returns `204 No Content`
+#[axum::debug_handler]
+async fn api_delete_contact(
+    State(state): State<AppState>,
+    _access: ApiAccess,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    Actor(actor): Actor,
+) -> Result<Response, AppError> {
+    let contact = state.contacts.find_by_id(id).await?;
+    if let Some(rejected) = api_if_match(&headers, &contact) {
+        return Ok(rejected);
+    }
+    // Like updates, only if nobody changed it since `api_if_match` looked
+    if state.config.get().trash.retention_days > 0 {
+        let dates = state.contacts.dates(contact.id).await?;
+        TrashedContact::add(&state.db, &contact, &dates).await?;
+        if let Err(e) = state.contacts.delete_unchanged(&contact).await {
+            TrashedContact::remove(&state.db, contact.id).await?;
+            return match e {
+                ContactError::Changed => api_changed(&state, contact.id).await,
+                e => Err(e.into()),
+            };
+        }
+    } else {
+        match state.contacts.delete_unchanged(&contact).await {
+            Ok(()) => state.attachments.remove_for_contact(contact.id).await?,
+            Err(ContactError::Changed) => return api_changed(&state, contact.id).await,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let payload = serde_json::to_value(&contact)?;
+    record_contact_event(&state, events::CONTACT_DELETED, &actor, contact.id, payload).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Turns down a change to `contact` unless the request's `If-Match` has its current ETag, so
+/// a client can't overwrite a change it hasn't seen: `428` without `If-Match`, `412` with the
+/// current ETag if it's stale.
+fn api_if_match(headers: &HeaderMap, contact: &Contact) -> Option<Response> {
+    let etag = ETag::version(contact);
+    let (status, message) = match etag.check_if_match(headers) {
+        Ok(()) => return None,
+        Err(Precondition::Required) => (StatusCode::PRECONDITION_REQUIRED, "api-if-match-missing"),
+        Err(Precondition::Failed) => (StatusCode::PRECONDITION_FAILED, "api-if-match-stale"),
+    };
+    info!("API change to contact {} turned down: {status}", contact.id);
+    Some(etag.attach((status, Json(json!({ "error": i18n::t(message) }))).into_response()))
+}
+
+/// The `412` for a change `api_if_match` let through that lost to another one made since,
+/// with the contact's ETag now if it's still there.
+async fn api_changed(state: &AppState, id: i64) -> Result<Response, AppError> {
+    info!("API change to contact {id} turned down: changed since it was checked");
+    let response = (StatusCode::PRECONDITION_FAILED, Json(json!({ "error": i18n::t("api-if-match-stale") }))).into_response();
+    match state.contacts.find_by_id(id).await {
+        Ok(contact) => Ok(ETag::version(&contact).attach(response)),
+        Err(ContactError::NotFound) => Ok(response),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Health check for load balancers and container orchestrators. Pings the database and
+/// returns 200 if it answers, or 503 otherwise. The error itself only goes to the log.
+///
+/// Example usage:
+/// `curl http://localhost:2911/healthz` returns `{"status":"ok","database":"ok"}`
+#[axum::debug_handler]
+async fn healthz(State(state): State<AppState>) -> (StatusCode, Json<HealthStatus>) {
+    match state.db.ping().await {
+        Ok(()) => (
+            StatusCode::OK,
+            Json(HealthStatus {
+                status: "ok",
+                database: "ok".into(),
+            }),
+        ),
+        Err(e) => {
+            error!("Health check failed: {e}");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(HealthStatus {
+                    status: "error",
+                    database: "unavailable".into(),
+                }),
+            )
+        }
+    }
+}
+
+/// Liveness probe: 200 as long as the process is up and serving requests. It doesn't touch
+/// the database, so an orchestrator doesn't restart the app over a database outage; that's
+/// what `/readyz` is for.
+///
+/// Example usage:
+/// `curl http://localhost:2911/livez` returns `{"status":"ok"}`
+#[axum::debug_handler]
+async fn livez() -> Json<serde_json::Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+/// Readiness probe: 200 once the database has every migration of this build and a pooled
+/// connection answers within `database.ready_timeout_ms`, 503 otherwise, so traffic is held
+/// back during startup and database outages. Both come with the pool's figures. Database
+/// errors are logged, the response only says the database is unavailable.
+///
+/// Example usage:
+/// `curl http://localhost:2911/readyz` returns
+/// `{"status":"ok","database":"ok","pending_migrations":[],"pool":{"connections":1,"idle_connections":1,"max_connections":10}}`
+#[axum::debug_handler]
+async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<ReadinessStatus>) {
+    let database = &state.config.get().database;
+    let timeout_ms = database.ready_timeout_ms;
+    // Read before the check, which takes a connection of its own
+    let (connections, idle_connections) = state.db.pool_usage();
+    let pool = PoolUsage {
+        connections,
+        idle_connections,
+        max_connections: database.max_connections,
+    };
+    let (database, pending_migrations) =
+        match tokio::time::timeout(Duration::from_millis(timeout_ms), state.db.pending_migrations()).await {
+            Ok(Ok(pending)) => ("ok".to_string(), pending),
+            Ok(Err(e)) => {
+                error!("Readiness check failed: {e}");
+                ("unavailable".to_string(), Vec::new())
+            }
+            Err(_) => (format!("no connection within {timeout_ms}ms"), Vec::new()),
+        };
+    let ready = database == "ok" && pending_migrations.is_empty();
+    if !ready {
+        error!("Not ready: database {database}, pending migrations {pending_migrations:?}");
+    }
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (
+        status,
+        Json(ReadinessStatus {
+            status: if ready { "ok" } else { "error" },
+            database,
+            pending_migrations,
+            pool,
+        }),
+    )
+}
+
+/// Resolves once the process receives SIGINT (Ctrl+C) or SIGTERM, which starts the graceful
+/// shutdown of the server.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("Shutdown signal received, draining in-flight requests");
+}
+
+/// Contact pages, rate limited per client IP and rendered in the request's locale.
+fn contact_routes(rate_limiter: &RateLimiter) -> Router<AppState> {
+    Router::new()
+        .route("/", get(contacts)) // Shows the contaxt
+        .route("/rows", get(contact_rows)) // Rows of one page, for infinite scroll
+        .route("/live", get(contacts_live)) // WebSocket with changes made elsewhere
+        .route("/companies", get(companies)) // Contacts grouped by company
+        .route("/dates", get(upcoming_dates)) // Upcoming dates of every contact
+        .route("/import", get(get_import).post(post_import)) // Upload a CSV file to import
+        .route("/import/{token}", get(get_import_mapping).post(post_import_mapping)) // Map its columns and import it
+        .route("/trash", get(contact_trash)) // Deleted contacts that can be restored
+        .route("/trash/{id}/restore", post(restore_contact)) // Restore one
+        .route("/trash/empty", post(empty_trash)) // Purge them all now
+        .route("/shares", get(contact_shares).post(create_share)) // Share links, make one
+        .route("/shares/{id}/revoke", post(revoke_share)) // Stop a link from working
+        .route("/new", post(post_new_contact).get(get_new_contact)) // New POST endpoint
+        .route("/quick_add", post(post_quick_add)) // Quick add from the list, returns the new row
+        .route("/{id}", get(show_contact).put(put_contact).patch(patch_contact).delete(delete_contact)) // Contact GET/PUT/PATCH/DELETE
+        .route("/sheet.pdf", get(contact_sheet_pdf)) // Printable sheet of selected contacts
+        .route("/{id}/pdf", get(contact_pdf)) // Printable contact sheet
+        .route("/{id}/qr.png", get(contact_qr)) // QR code with the contact's vCard
+        .route("/{id}/row", get(get_contact_row)) // Display row, to cancel an inline edit
+        .route("/{id}/edit_row", get(get_edit_row)) // Inline editor row
+        .route("/{id}/edit", post(post_edit_contact).get(get_edit_contact)) // Edit contact
+        .route("/{id}/delete", get(confirm_delete_contact).post(delete_contact)) // Delete without JS
+        .route("/{id}/undo_delete", post(undo_delete_contact)) // Undo a pending delete
+        .route("/{id}/relationships", post(add_relationship)) // Link to another contact
+        .route("/{id}/relationships/{relationship_id}", delete(remove_relationship)) // Unlink
+        .route("/{id}/relationships/{relationship_id}/delete", post(remove_relationship)) // Unlink without JS
+        .route("/{id}/dates", post(add_date)) // Add a date
+        .route("/{id}/dates/{date_id}", delete(remove_date)) // Remove a date
+        .route("/{id}/dates/{date_id}/delete", post(remove_date)) // Remove a date without JS
+        .route("/{id}/attachments", post(upload_attachment)) // Attach a file
+        .route("/{id}/attachments/{attachment_id}", get(download_attachment).delete(remove_attachment)) // Download or remove a file
+        .route("/{id}/attachments/{attachment_id}/delete", post(remove_attachment)) // Remove a file without JS
+        .route("/export.csv", get(export_csv)) // CSV export
+        .route("/feed.atom", get(contacts_feed)) // Atom feed of the newest contacts
+        .route("/validate", get(validate_input)) // Endpoint for validating input
+        .layer(DefaultBodyLimit::max(FORM_BODY_LIMIT))
+        .layer(middleware::from_fn_with_state(rate_limiter.clone(), rate_limit::limit))
+        .layer(middleware::from_fn(i18n::scope))
+}
+
+/// The JSON API, rate limited per client IP and per API key. Validation messages follow the
+/// request's locale.
+fn api_routes(rate_limiter: &RateLimiter, state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/contacts", get(api_list_contacts)) // One page of the contact list
+        .route("/contacts/batch", post(api_create_contacts)) // Create many contacts at once
+        .route("/batch", post(api_batch)) // Creates, updates and deletes, all or nothing
+        .route("/contacts/{id}", get(api_get_contact).put(api_put_contact).patch(api_patch_contact).delete(api_delete_contact)) // Contact GET, and PUT/PATCH/DELETE with If-Match
+        .layer(middleware::from_fn_with_state(state.clone(), api_keys::authorize::<AppState>))
+        .layer(middleware::from_fn(utils::json_errors)) // Errors as JSON rather than pages
+        .layer(middleware::from_fn_with_state(rate_limiter.clone(), rate_limit::limit))
+        .layer(middleware::from_fn(i18n::scope))
+}
+
+/// Admin pages, rate limited per client IP. They are in English, only the layout around
+/// them follows the request's locale. Nothing here is cacheable: the event log and
+/// backup list change all the time and backups shouldn't linger in browser caches. Only
+/// clients in `server.admin_allow` get to them, see `admin`.
+fn admin_routes(rate_limiter: &RateLimiter, admin_allow: &AdminAllow) -> Router<AppState> {
+    Router::new()
+        .route("/db", get(admin_db)) // Pool and statement cache figures
+        .route("/events", get(admin_events)) // Browse the domain event log
+        .route("/backup", post(create_backup)) // Back up the SQLite database
+        .route("/backups", get(admin_backups)) // List backups
+        .route("/backups/{name}", get(download_backup)) // Download a backup
+        .route("/jobs", get(admin_jobs)) // Background job status
+        .route("/jobs/purge_events", post(purge_events)) // Queue an event log purge
+        .route("/jobs/ldap_import", post(ldap_import)) // Queue an LDAP import
+        .route("/email/test", post(send_test_email)) // Queue a test email
+        .route("/webhooks", get(admin_webhooks).post(create_webhook)) // Webhooks and delivery log
+        .route("/webhooks/{id}/delete", post(delete_webhook)) // Remove a webhook
+        .layer(SetResponseHeaderLayer::overriding(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("no-store"),
+        ))
+        .layer(DefaultBodyLimit::max(FORM_BODY_LIMIT))
+        .layer(middleware::from_fn_with_state(rate_limiter.clone(), rate_limit::limit))
+        .layer(middleware::from_fn_with_state(admin_allow.clone(), admin::guard))
+        .layer(middleware::from_fn(i18n::scope))
+}
+
+/// Share links, for people without access to the rest of the app. Rate limited per client IP
+/// and rendered in the request's locale; not cached, indexed or leaked to other sites as a
+/// referrer, since the URL is all it takes to see them.
+fn share_routes(rate_limiter: &RateLimiter) -> Router<AppState> {
+    Router::new()
+        .route("/{token}", get(show_share)) // A shared contact or company
+        .route("/{token}/contacts.vcf", get(share_vcard)) // The same as vCards
+        .layer(SetResponseHeaderLayer::overriding(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static("no-store"),
+        ))
+        .layer(SetResponseHeaderLayer::overriding(
+            header::REFERRER_POLICY,
+            HeaderValue::from_static("no-referrer"),
+        ))
+        .layer(SetResponseHeaderLayer::overriding(
+            header::HeaderName::from_static("x-robots-tag"),
+            HeaderValue::from_static("noindex"),
+        ))
+        .layer(middleware::from_fn_with_state(rate_limiter.clone(), rate_limit::limit))
+        .layer(middleware::from_fn(i18n::scope))
+}
+
+/// CardDAV, rate limited per client IP. Methods are dispatched inside the handlers since
+/// `PROPFIND` and `REPORT` aren't standard HTTP methods.
+fn dav_routes(rate_limiter: &RateLimiter) -> Router<AppState> {
+    Router::new()
+        .route("/", any(dav_discovery)) // DAV root, points at the principal
+        .route("/principal/", any(dav_discovery)) // Points at the address book home
+        .route("/addressbooks/", any(dav_home)) // Address book home
+        .route("/addressbooks/contacts/", any(dav_addressbook)) // The address book
+        .route("/addressbooks/contacts/{name}", any(dav_card)) // One vCard
+        .layer(middleware::from_fn_with_state(rate_limiter.clone(), rate_limit::limit))
+}
+
+/// The dashboard, rate limited per client IP and rendered in the request's locale.
+fn dashboard_routes(rate_limiter: &RateLimiter) -> Router<AppState> {
+    Router::new()
+        .route("/", get(dashboard)) // Contact statistics
+        .layer(middleware::from_fn_with_state(rate_limiter.clone(), rate_limit::limit))
+        .layer(middleware::from_fn(i18n::scope))
+}
+
+/// The activity feed, rate limited per client IP and rendered in the request's locale.
+fn activity_routes(rate_limiter: &RateLimiter) -> Router<AppState> {
+    Router::new()
+        .route("/", get(activity)) // Recent contact changes
+        .layer(middleware::from_fn_with_state(rate_limiter.clone(), rate_limit::limit))
+        .layer(middleware::from_fn(i18n::scope))
+}
+
+/// The display preferences and the API keys, rate limited per client IP and rendered in the
+/// request's locale. Like the admin pages, the keys are only managed from clients in
+/// `server.admin_allow`, or anyone could make themselves one, and the forms only take posts
+/// from the app's own pages (see `origin`).
+fn settings_routes(rate_limiter: &RateLimiter, admin_allow: &AdminAllow) -> Router<AppState> {
+    let api_keys = Router::new()
+        .route("/api_keys", get(get_api_keys).post(create_api_key)) // JSON API keys, make one
+        .route("/api_keys/{id}/limits", post(set_api_key_limits)) // Change a key's rate and daily quota
+        .route("/api_keys/{id}/revoke", post(revoke_api_key)) // Stop a key from working
+        .route_layer(middleware::from_fn_with_state(admin_allow.clone(), admin::guard));
+    Router::new()
+        .route("/", get(get_settings).post(post_settings)) // Display preferences form
+        .merge(api_keys)
+        .layer(middleware::from_fn(origin::guard))
+        .layer(DefaultBodyLimit::max(FORM_BODY_LIMIT))
+        .layer(middleware::from_fn_with_state(rate_limiter.clone(), rate_limit::limit))
+        .layer(middleware::from_fn(i18n::scope))
+}
+
+/// Reports, rate limited per client IP and rendered in the request's locale.
+fn report_routes(rate_limiter: &RateLimiter) -> Router<AppState> {
+    Router::new()
+        .route("/quality", get(quality_report)) // Data quality report
+        .route("/quality/refresh", post(refresh_quality_report))
+        .layer(middleware::from_fn_with_state(rate_limiter.clone(), rate_limit::limit))
+        .layer(middleware::from_fn(i18n::scope))
+}
+
+/// Connects to the database, which applies pending migrations, and sets up the contact
+/// repository every command works with.
+pub async fn connect(config: &Config) -> anyhow::Result<(Db, SharedContactRepository)> {
+    let statement_settings = StatementSettings::from(&config.database);
+    let pool_settings = PoolSettings::from(&config.database);
+    let pragmas = SqlitePragmas::from(&config.database);
+    let pool = Db::connect(&config.database.url, &statement_settings, &pool_settings, &pragmas)
+        .await
+        .context("Failed to connect to database")?;
+    // Contacts from before the search_text column, or from before the folding last changed
+    let filled = Contact::fill_search_text(&pool)
+        .await
+        .context("Failed to fill in the contacts' search text")?;
+    if filled > 0 {
+        info!("Filled in the search text of {filled} contacts");
+    }
+    let repository = repository(config, &pool).await?;
+    Ok((pool, repository))
+}
+
+/// Where the contacts of `pool` are kept: the database itself, or in demo mode memory.
+async fn repository(config: &Config, pool: &Db) -> anyhow::Result<SharedContactRepository> {
+    // In demo mode the contacts live in memory, starting out with the seed data
+    if config.database.url == MEMORY_URL {
+        info!("Running in demo mode, contacts are kept in memory and lost on exit");
+        return Ok(Arc::new(MemoryContactRepository::new(Contact::all(pool).await?)));
+    }
+    Ok(Arc::new(pool.clone()))
+}
+
+/// The state a database is served with, with the background work on it started: deletion
+/// finalizing, quality reports, the job workers and the trash purge and LDAP import schedules.
+async fn app_state(config: Config, pool: Db, repository: SharedContactRepository, clock: SharedClock) -> anyhow::Result<AppState> {
+    let ids: SharedIdGenerator = Arc::new(UuidGenerator);
+    // Everything below changes contacts through this, so it knows when to drop its results
+    let repository: SharedContactRepository = match config.search.cache_ttl_secs {
+        0 => repository,
+        secs => Arc::new(CachedContactRepository::new(repository, Duration::from_secs(secs))),
+    };
+
+    // Deletions are finalized in the background once their undo grace period is over, into
+    // the trash unless it's off
+    let attachments = Attachments::new(pool.clone(), config.attachments.clone());
+    let pending_deletions = PendingDeletions::new((config.trash.retention_days > 0).then(|| pool.clone()));
+    pending_deletions.spawn_finalizer(repository.clone(), attachments.clone());
+
+    // The data quality report is generated on startup and then periodically
+    let quality_reports = QualityReports::default();
+    quality_reports.spawn_scheduler(repository.clone());
+
+    // Slow work like backups, purges, sending email and webhook deliveries runs as persistent
+    // jobs on a few worker tasks
+    let database = &config.database;
+    let mailer = Mailer::new(&config.email).context("Invalid email settings")?;
+    let jobs = Jobs::new(pool.clone(), clock.clone())
+        .register(
+            backup::BACKUP_JOB,
+            BackupJob {
+                db: pool.clone(),
+                dir: database.backup_dir.clone(),
+                recipient: database.backup_recipient.clone(),
+                retention: Retention::from(database),
+                clock: clock.clone(),
+            },
+        )
+        .register(
+            events::PURGE_EVENTS_JOB,
+            PurgeEventsJob {
+                db: pool.clone(),
+                clock: clock.clone(),
+            },
+        )
+        .register(
+            trash::PURGE_TRASH_JOB,
+            PurgeTrashJob {
+                db: pool.clone(),
+                attachments: attachments.clone(),
+                clock: clock.clone(),
+            },
+        )
+        .register(email::EMAIL_JOB, EmailJob { mailer })
+        .register(webhooks::WEBHOOK_JOB, WebhookJob::new(pool.clone(), clock.clone())?);
+    // Imports queue webhook deliveries on the same queue, through a clone of it
+    let jobs = jobs.clone().register(
+        ldap::LDAP_IMPORT_JOB,
+        LdapImportJob {
+            config: config.ldap.clone(),
+            contacts: repository.clone(),
+            db: pool.clone(),
+            jobs,
+            clock: clock.clone(),
+        },
+    );
+    if config.trash.retention_days > 0 {
+        trash::spawn_scheduler(jobs.clone(), config.trash.retention_days);
+    }
+    if let Some(hours) = config.ldap.interval_hours {
+        ldap::spawn_scheduler(jobs.clone(), Duration::from_secs(hours * 60 * 60));
+    }
+    match (config.database.backup_interval_hours, pool.sqlite()) {
+        (Some(hours), Some(_)) => backup::spawn_scheduler(jobs.clone(), Duration::from_secs(hours * 60 * 60)),
+        (Some(_), None) => warn!("Scheduled backups are only supported for SQLite databases, use pg_dump for Postgres"),
+        (None, _) => {}
+    }
+    jobs.start(JOB_WORKERS).await.context("Failed to start the job workers")?;
+    let api_usage = KeyUsage::default();
+    api_usage.spawn_flusher(pool.clone());
+
+    let search: SharedSearchBackend = match &config.search.meilisearch_url {
+        Some(url) => {
+            let meilisearch = MeilisearchSearch::new(&config.search, url, repository.clone())?;
+            meilisearch.spawn_sync(pool.clone());
+            Arc::new(meilisearch)
+        }
+        None if config.search.fuzzy => Arc::new(FuzzySearch::new(repository.clone())),
+        None => Arc::new(DatabaseSearch::new(repository.clone())),
+    };
+
+    Ok(AppState {
+        config: LiveConfig::new(config),
+        db: pool,
+        contacts: repository,
+        search,
+        clock: clock.clone(),
+        pending_deletions,
+        attachments,
+        form_tokens: FormTokens::new(clock, ids),
+        quality_reports,
+        jobs,
+        live: LiveUpdates::default(),
+        imports: PendingImports::default(),
+        api_limits: KeyRateLimiter::default(),
+        api_usage,
+    })
+}
+
+/// The health probes. `router` mounts them outside the cap on requests in flight, and like
+/// the other top-level routes they have no rate limit, so a busy server still answers them.
+fn probe_routes() -> Router<AppState> {
+    Router::new()
+        .route("/healthz", get(healthz)) // Health check with database ping
+        .route("/livez", get(livez)) // Liveness probe, the process is up
+        .route("/readyz", get(readyz)) // Readiness probe, migrated and the pool answers
+}
+
+/// Every route of the app but the probes, for one database. Each section is its own router so
+/// middleware can be applied per section; see `contact_routes` and friends below
+fn app_routes(rate_limiter: &RateLimiter, static_dir: Option<&std::path::Path>, state: &AppState) -> Router<AppState> {
+    let admin_allow = AdminAllow::new(&state.config.get().server.admin_allow);
+    Router::new()
+        .route("/", get(index)) // Main Page redirects to /contacts
+        .route("/locale", post(set_locale).layer(DefaultBodyLimit::max(FORM_BODY_LIMIT))) // Language switcher, sets the `lang` cookie
+        .nest("/contacts", contact_routes(rate_limiter))
+        .nest("/admin", admin_routes(rate_limiter, &admin_allow))
+        .nest("/reports", report_routes(rate_limiter))
+        .nest("/dashboard", dashboard_routes(rate_limiter))
+        .nest("/activity", activity_routes(rate_limiter))
+        .nest("/settings", settings_routes(rate_limiter, &admin_allow))
+        .nest("/api/v1", api_routes(rate_limiter, state))
+        .nest("/dav", dav_routes(rate_limiter))
+        .nest("/share", share_routes(rate_limiter))
+        .route("/dav/", any(dav_discovery)) // Nesting only matches the root without the slash
+        .route("/.well-known/carddav", any(well_known_carddav)) // CardDAV service discovery
+        .nest_service("/static", assets::service(static_dir)) // Serve static content
+        .fallback_service(any(not_found).layer(middleware::from_fn(i18n::scope))) // 404 page for unknown routes
+        .layer(middleware::from_fn(preferences::scope)) // Display preferences from the `prefs` cookie
+}
+
+/// What a tenant is served with, see `tenants`.
+#[derive(Clone)]
+struct TenantApp {
+    router: Router,
+    state: AppState,
+    base_path: &'static str,
+}
+
+/// Multi-tenant mode's state: the main database's app, for requests for no tenant, and the
+/// tenants' apps along with what it takes to make new ones.
+#[derive(Clone)]
+struct Tenancy {
+    /// The main database's, which tenants' are made from, see `tenants::tenant_config`.
+    config: LiveConfig,
+    main: Router,
+    tenants: Tenants<TenantApp>,
+    clock: SharedClock,
+    rate_limiter: RateLimiter,
+}
+
+/// Serves a request from the app of the tenant it is for, creating and migrating the tenant's
+/// database on its first request. Requests for no tenant go to the main database's app.
+async fn serve_tenant(State(tenancy): State<Tenancy>, mut request: Request) -> Response {
+    let name = match tenants::resolve(&tenancy.config.get().tenancy, &mut request) {
+        Ok(Some(name)) => name,
+        Ok(None) => return tenancy.main.oneshot(request).await.unwrap_or_else(|never| match never {}),
+        Err(UnknownTenant) => return AppError::NotFound.into_response(),
+    };
+    let max_tenants = tenancy.config.get().tenancy.max_tenants;
+    match tenancy.tenants.get_or_create(&name, max_tenants, || open_tenant(&tenancy, &name)).await {
+        Ok(tenant) => base_path::scope(tenant.base_path, tenant.router.oneshot(request))
+            .await
+            .unwrap_or_else(|never| match never {}),
+        Err(err) if err.is::<TooManyTenants>() => {
+            warn!("Not opening tenant {name}, {max_tenants} tenants are open already");
+            AppError::NotFound.into_response()
+        }
+        Err(err) => AppError::Internal(err).into_response(),
+    }
+}
+
+/// Connects to the tenant `name`'s database, creating it if it's new, and starts its app.
+async fn open_tenant(tenancy: &Tenancy, name: &str) -> anyhow::Result<TenantApp> {
+    let main = tenancy.config.get();
+    let config = tenants::tenant_config(&main, name);
+    tokio::fs::create_dir_all(&main.tenancy.dir)
+        .await
+        .with_context(|| format!("Failed to create {}", main.tenancy.dir.display()))?;
+    let (pool, repository) = connect(&config)
+        .await
+        .with_context(|| format!("Failed to open the database of tenant {name}"))?;
+    info!("Opened the database of tenant {name}");
+    let state = app_state(config, pool, repository, tenancy.clock.clone()).await?;
+    Ok(TenantApp {
+        router: app_routes(&tenancy.rate_limiter, main.server.static_dir.as_deref(), &state).with_state(state.clone()),
+        state,
+        base_path: tenants::base_path(&main, name),
+    })
+}
+
+/// The whole app for `config` and its database `pool`, as connected with `connect`, with the
+/// background tasks and job workers started. It's the same app `webone serve` serves, with
+/// all of its middleware, including the method override for plain HTML forms, so it can be
+/// merged into another axum server or driven from tests:
+///
+/// ```no_run
+/// # async fn example(config: webone::config::Config) -> anyhow::Result<()> {
+/// use axum::{body::Body, extract::ConnectInfo, http::Request};
+/// use tower::ServiceExt;
+///
+/// let (pool, _) = webone::app::connect(&config).await?;
+/// let (app, shutdown) = webone::app::build_router(config, pool).await?;
+/// // The rate limiter and the access log need the client's address
+/// let mut request = Request::get("/healthz").body(Body::empty())?;
+/// request.extensions_mut().insert(ConnectInfo(std::net::SocketAddr::from(([127, 0, 0, 1], 1234))));
+/// let response = app.oneshot(request).await?;
+/// assert!(response.status().is_success());
+/// shutdown.shutdown().await;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Servers embedding it have to serve it with
+/// `into_make_service_with_connect_info::<SocketAddr>()` for the same reason, and should call
+/// `ShutdownHandle::shutdown` once they stop serving it, like `serve` does. Its base path,
+/// static files and Gravatar setting are its own, so several apps can be built in one process.
+pub async fn build_router(config: Config, pool: Db) -> anyhow::Result<(Router, ShutdownHandle)> {
+    let repository = repository(&config, &pool).await?;
+    let clock: SharedClock = Arc::new(SystemClock);
+    let (app, served) = router(config, pool, repository, clock).await?;
+    Ok((app, ShutdownHandle(served)))
+}
+
+/// Finishes the work of an app made with `build_router` when it's no longer served.
+pub struct ShutdownHandle(Served);
+
+impl ShutdownHandle {
+    /// Finalizes the deletions still in their undo grace period, writes out the API key use
+    /// not written yet and closes the database pools, the main one's and the tenants'. Call it
+    /// once the requests are done.
+    pub async fn shutdown(self) {
+        self.0.shutdown().await;
+    }
+}
+
+/// What `scope_app` runs an app's requests with.
+#[derive(Clone)]
+struct Site {
+    base_path: &'static str,
+    assets: Assets,
+    config: LiveConfig,
+}
+
+/// Middleware: runs the request with its app's base path, static files and Gravatar setting
+/// (see `base_path`, `assets` and `gravatar`), which are per app rather than per process.
+async fn scope_app(State(site): State<Site>, request: Request, next: middleware::Next) -> Response {
+    let gravatar = site.config.get().gravatar.enabled;
+    let response = assets::scope(site.assets, gravatar::scope(gravatar, next.run(request)));
+    base_path::scope(site.base_path, response).await
+}
+
+/// The app states `router` made, to reload the config of and finish their work on at shutdown.
+#[derive(Clone)]
+struct Served {
+    main: AppState,
+    tenants: Tenants<TenantApp>,
+    rate_limiter: RateLimiter,
+}
+
+impl Served {
+    /// The configs of the main database's app and the tenants' opened so far, main first.
+    fn configs(&self) -> Vec<LiveConfig> {
+        std::iter::once(self.main.config.clone())
+            .chain(self.tenants.all().into_iter().map(|tenant| tenant.state.config))
+            .collect()
+    }
+
+    /// See `ShutdownHandle::shutdown`. Pools are closed so SQLite can checkpoint the WAL.
+    async fn shutdown(self) {
+        let states = std::iter::once(self.main).chain(self.tenants.all().into_iter().map(|tenant| tenant.state));
+        for state in states {
+            state.pending_deletions.finalize_all(state.contacts.as_ref(), &state.attachments).await;
+            state.api_usage.flush(&state.db).await;
+            state.db.close().await;
+        }
+    }
+}
+
+/// The app with all of its middleware, see `build_router`, and the states it was made with.
+async fn router(config: Config, pool: Db, repository: SharedContactRepository, clock: SharedClock) -> anyhow::Result<(Router, Served)> {
+    let rate_limiter = RateLimiter::new(&config.rate_limit);
+
+    let base_path = base_path::leak(&config.server.base_path);
+    let assets = Assets::new(config.server.static_dir.as_deref());
+    let trust_proxy_headers = TrustProxyHeaders(config.server.trust_proxy_headers);
+    let concurrency_limit = ConcurrencyLimit::new(&config.server);
+    let error_reporter = error_reporting::reporter(&config.error_reporting).context("Invalid error reporting settings")?;
+    let tenancy_mode = config.tenancy.mode;
+    let state = app_state(config.clone(), pool, repository, clock.clone()).await?;
+
+    // With multi-tenant mode on, every request first goes to the tenant it's for
+    let main = app_routes(&rate_limiter, config.server.static_dir.as_deref(), &state).with_state(state.clone());
+    let tenants = Tenants::default();
+    let app = match tenancy_mode {
+        TenancyMode::Off => main,
+        TenancyMode::Subdomain | TenancyMode::Path => Router::new().fallback(serve_tenant).with_state(Tenancy {
+            config: state.config.clone(),
+            main,
+            tenants: tenants.clone(),
+            clock,
+            rate_limiter: rate_limiter.clone(),
+        }),
+    };
+    let app = app
+        .layer(middleware::from_fn_with_state(error_reporter, error_reporting::report)); // 5xx responses to the error reporter
+    // Past the cap on requests in flight, requests get a 503 page instead of queueing up
+    let app = match concurrency_limit {
+        Some(limit) => app.layer(middleware::from_fn_with_state(limit, load_shed::limit)),
+        None => app,
+    };
+    // The probes go around the cap, so an orchestrator doesn't take a busy server for a dead
+    // one. In multi-tenant mode they check the main database
+    let app = probe_routes()
+        .with_state(state.clone())
+        .merge(app)
+        .layer(middleware::from_fn(logging::scope)) // The request id for error pages
+        .layer( // Access log: request id, method, path, client IP, status and latency for every request
+            TraceLayer::new_for_http()
+                .make_span_with(logging::request_span)
+                .on_response(DefaultOnResponse::new().level(Level::INFO)),
+        )
+        .layer(PropagateRequestIdLayer::x_request_id()) // Echoes the id in the response
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid)) // Unless the proxy sent one
+        .layer(middleware::from_fn_with_state(trust_proxy_headers, proxy::resolve)); // Client IP and scheme from a trusted proxy
+    // Behind a proxy at a path prefix, every route moves under it. Nesting only matches the
+    // prefix itself without the slash, which is how proxies usually pass it on
+    let app = match base_path {
+        "" => app,
+        base_path => Router::new()
+            .route(&format!("{base_path}/"), get(index))
+            .nest(base_path, app),
+    };
+
+    // `_method` for plain HTML forms. It changes what the request is routed as, so it goes
+    // around the router rather than in it
+    let app = Router::new().fallback_service(middleware::from_fn(forms::method_override).layer(app));
+    let site = Site {
+        base_path,
+        assets,
+        config: state.config.clone(),
+    };
+    let app = app.layer(middleware::from_fn_with_state(site, scope_app));
+    Ok((
+        app,
+        Served {
+            main: state,
+            tenants,
+            rate_limiter,
+        },
+    ))
+}
+
+/// Runs the web server, with the background tasks and job workers, until the process is told
+/// to shut down. `cli` is what `config` was loaded from, to load it again on SIGHUP.
+pub async fn serve(config: Config, pool: Db, repository: SharedContactRepository, clock: SharedClock, cli: Cli) -> anyhow::Result<()> {
+    let listen_addr = config.listen_addr();
+    let (app, served) = router(config, pool, repository, clock).await?;
+    let reloads = served.clone();
+    reload::spawn_on_hangup(cli, served.rate_limiter.clone(), move || reloads.configs());
+
+    let listener = tokio::net::TcpListener::bind(listen_addr)
+        .await
+        .with_context(|| format!("Failed to bind to {listen_addr}"))?;
+    info!("Listening on http://{}", listener.local_addr()?);
+
+    // Connect info is needed for the client IP in the rate limiter
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    // All requests are done - flush queued deletions and API key use and close the pools
+    served.shutdown().await;
+    info!("Shutdown complete");
+    Ok(())
+}
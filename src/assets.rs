@@ -0,0 +1,213 @@
+//! Static asset serving with cache-busting URLs.
+//!
+//! The files in `static/` are embedded into the binary at build time and served from memory,
+//! so a deployment is just the executable and the database. `server.static_dir` serves them
+//! from disk instead. (In debug builds rust-embed reads `static/` from disk anyway, so CSS
+//! changes show up without a rebuild, though the hashes only update on restart.)
+//!
+//! On startup every static file is hashed, and templates link to it as
+//! `/static/<name>.<hash>.<ext>` through `assets::url`. Hashed URLs change whenever the file
+//! does, so they are served with a year long `immutable` cache lifetime. Plain URLs still work
+//! but have to be revalidated on every use.
+//!
+//! The hashes belong to the app rather than the process: the app's router runs every request
+//! in `scope` with its `Assets`, so apps serving different `static_dir`s can live side by side.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::{DefaultHasher, Hasher},
+    path::Path,
+    sync::Arc,
+};
+
+use axum::{
+    Router,
+    extract::Request,
+    http::{
+        HeaderMap, HeaderValue, StatusCode, Uri,
+        header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+    },
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+};
+use rust_embed::Embed;
+use tower_http::services::ServeDir;
+use tracing::warn;
+
+use crate::base_path;
+
+#[derive(Embed)]
+#[folder = "static/"]
+struct EmbeddedAssets;
+
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+const REVALIDATE: &str = "no-cache";
+
+#[derive(Debug, Default)]
+struct Manifest {
+    /// `site.css` -> `site.0123456789abcdef.css`
+    hashed: HashMap<String, String>,
+    /// The reverse, to find the file a hashed URL points at.
+    originals: HashMap<String, String>,
+}
+
+/// The hashed names of an app's static files. Cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct Assets(Arc<Manifest>);
+
+tokio::task_local! {
+    static SCOPED: Assets;
+}
+
+impl Assets {
+    /// Hashes every static file, from `dir` if given or else the embedded ones. Done once when
+    /// the app is made; files added or changed afterwards keep their old URL until the next
+    /// restart.
+    pub fn new(dir: Option<&Path>) -> Self {
+        let mut manifest = Manifest::default();
+        match dir {
+            Some(dir) => {
+                if let Err(e) = collect(dir, dir, &mut manifest) {
+                    warn!("Failed to hash static assets in {}: {e}", dir.display());
+                }
+            }
+            None => {
+                for name in EmbeddedAssets::iter() {
+                    if let Some(file) = EmbeddedAssets::get(&name) {
+                        manifest.add(name.into_owned(), &file.data);
+                    }
+                }
+            }
+        }
+        Self(Arc::new(manifest))
+    }
+}
+
+/// Runs `f` with `assets` as the static files `url` and the `/static` service know of.
+///
+/// Example usage:
+/// `assets::scope(assets.clone(), next.run(request)).await`
+pub async fn scope<F: Future>(assets: Assets, f: F) -> F::Output {
+    SCOPED.scope(assets, f).await
+}
+
+/// The static files of the request being handled, to carry over to tasks it spawns. Outside
+/// `scope` there are none, and `url` gives plain URLs.
+pub fn current() -> Assets {
+    SCOPED.try_with(Assets::clone).unwrap_or_default()
+}
+
+fn collect(root: &Path, dir: &Path, manifest: &mut Manifest) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect(root, &path, manifest)?;
+            continue;
+        }
+        let Ok(relative) = path.strip_prefix(root) else {
+            continue;
+        };
+        let name = relative.to_string_lossy().replace('\\', "/");
+        manifest.add(name, &std::fs::read(&path)?);
+    }
+    Ok(())
+}
+
+impl Manifest {
+    fn add(&mut self, name: String, contents: &[u8]) {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(contents);
+        let hashed = hashed_name(&name, hasher.finish());
+        self.originals.insert(hashed.clone(), name.clone());
+        self.hashed.insert(name, hashed);
+    }
+}
+
+/// `vendor/htmx.min.js` -> `vendor/htmx.min.<hash>.js`
+fn hashed_name(name: &str, hash: u64) -> String {
+    let (dir, file) = name.rsplit_once('/').map_or(("", name), |(d, f)| (d, f));
+    let file = match file.rsplit_once('.') {
+        Some((stem, ext)) => format!("{stem}.{hash:016x}.{ext}"),
+        None => format!("{file}.{hash:016x}"),
+    };
+    if dir.is_empty() { file } else { format!("{dir}/{file}") }
+}
+
+/// Whether there is a static file with this name.
+pub fn exists(name: &str) -> bool {
+    current().0.hashed.contains_key(name)
+}
+
+/// URL of a static file, with its content hash if it was there on startup.
+///
+/// Example usage:
+/// `<link rel="stylesheet" href="{{ crate::assets::url("site.css") }}">`
+pub fn url(name: &str) -> String {
+    match current().0.hashed.get(name) {
+        Some(hashed) => base_path::url(&format!("/static/{hashed}")),
+        None => base_path::url(&format!("/static/{name}")),
+    }
+}
+
+/// The service for `/static`: embedded assets, or the files in `dir` if given, with hashed
+/// URLs and cache headers handled by `cache_headers`.
+///
+/// Example usage:
+/// `router.nest_service("/static", assets::service(config.server.static_dir.as_deref()))`
+pub fn service(dir: Option<&Path>) -> Router {
+    let router = match dir {
+        Some(dir) => Router::new().fallback_service(ServeDir::new(dir)),
+        None => Router::new().fallback(serve_embedded),
+    };
+    router.layer(middleware::from_fn(cache_headers))
+}
+
+/// Serves a file embedded in the binary, with its SHA-256 as the ETag.
+async fn serve_embedded(uri: Uri, headers: HeaderMap) -> Response {
+    let path = uri.path().trim_start_matches('/');
+    let Some(file) = EmbeddedAssets::get(path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let hash: String = file.metadata.sha256_hash()[..16]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    let etag = format!("\"{hash}\"");
+    if headers
+        .get(IF_NONE_MATCH)
+        .is_some_and(|value| value.as_bytes() == etag.as_bytes())
+    {
+        return (StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response();
+    }
+    (
+        [
+            (CONTENT_TYPE, file.metadata.mimetype().to_string()),
+            (ETAG, etag),
+        ],
+        file.data,
+    )
+        .into_response()
+}
+
+/// Middleware for the static file service: maps hashed URLs back to the file and sets
+/// `Cache-Control` on the response.
+async fn cache_headers(mut request: Request, next: Next) -> Response {
+    let assets = current();
+    let path = request.uri().path().trim_start_matches('/');
+    let original = assets.0.originals.get(path);
+    if let Some(original) = original
+        && let Ok(uri) = format!("/{original}").parse::<Uri>()
+    {
+        *request.uri_mut() = uri;
+    }
+
+    let mut response = next.run(request).await;
+    if response.status().is_success() {
+        let cache_control = if original.is_some() { IMMUTABLE } else { REVALIDATE };
+        response
+            .headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static(cache_control));
+    }
+    response
+}
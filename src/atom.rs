@@ -0,0 +1,125 @@
+//! A small Atom (RFC 4287) serializer, for the feed of newly added contacts. It covers the
+//! parts of the format the feed uses: feed metadata, links, and entries with a plain text
+//! summary.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use quick_xml::{
+    Writer,
+    events::{BytesDecl, BytesText, Event},
+};
+
+/// What Atom documents are served as.
+pub const CONTENT_TYPE: &str = "application/atom+xml; charset=utf-8";
+const NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+
+#[derive(Debug, Clone)]
+pub struct Feed {
+    /// Permanent, unique IRI of the feed, usually its own URL.
+    pub id: String,
+    pub title: String,
+    /// When anything in the feed last changed.
+    pub updated: DateTime<Utc>,
+    /// Required unless every entry has its own author, which ours don't.
+    pub author: String,
+    pub links: Vec<Link>,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Link {
+    /// `self` for the feed's own URL, `alternate` for the HTML version.
+    pub rel: &'static str,
+    pub href: String,
+    pub media_type: Option<&'static str>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: String,
+    pub title: String,
+    pub updated: DateTime<Utc>,
+    pub published: Option<DateTime<Utc>>,
+    /// The entry's HTML page.
+    pub link: String,
+    pub summary: Option<String>,
+}
+
+/// Atom's date format, RFC 3339 in UTC.
+fn timestamp(at: &DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn text_element(writer: &mut Writer<Vec<u8>>, name: &str, text: &str) -> std::io::Result<()> {
+    writer
+        .create_element(name)
+        .write_text_content(BytesText::new(text))
+        .map(|_| ())
+}
+
+impl Feed {
+    /// The feed as an XML document. Text is escaped by the writer, so names and summaries
+    /// can hold anything.
+    pub fn to_xml(&self) -> String {
+        let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+        // Writing into a Vec can't fail
+        self.write(&mut writer).expect("writing XML to memory");
+        String::from_utf8(writer.into_inner()).expect("quick-xml writes UTF-8")
+    }
+
+    fn write(&self, writer: &mut Writer<Vec<u8>>) -> std::io::Result<()> {
+        writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("utf-8"), None)))?;
+        writer
+            .create_element("feed")
+            .with_attribute(("xmlns", NAMESPACE))
+            .write_inner_content(|writer| {
+                text_element(writer, "id", &self.id)?;
+                text_element(writer, "title", &self.title)?;
+                text_element(writer, "updated", &timestamp(&self.updated))?;
+                writer
+                    .create_element("author")
+                    .write_inner_content(|writer| text_element(writer, "name", &self.author))?;
+                for link in &self.links {
+                    let mut element = writer
+                        .create_element("link")
+                        .with_attribute(("rel", link.rel))
+                        .with_attribute(("href", link.href.as_str()));
+                    if let Some(media_type) = link.media_type {
+                        element = element.with_attribute(("type", media_type));
+                    }
+                    element.write_empty()?;
+                }
+                for entry in &self.entries {
+                    entry.write(writer)?;
+                }
+                Ok(())
+            })?;
+        Ok(())
+    }
+}
+
+impl Entry {
+    fn write(&self, writer: &mut Writer<Vec<u8>>) -> std::io::Result<()> {
+        writer.create_element("entry").write_inner_content(|writer| {
+            text_element(writer, "id", &self.id)?;
+            text_element(writer, "title", &self.title)?;
+            text_element(writer, "updated", &timestamp(&self.updated))?;
+            if let Some(published) = &self.published {
+                text_element(writer, "published", &timestamp(published))?;
+            }
+            writer
+                .create_element("link")
+                .with_attribute(("rel", "alternate"))
+                .with_attribute(("type", "text/html"))
+                .with_attribute(("href", self.link.as_str()))
+                .write_empty()?;
+            if let Some(summary) = &self.summary {
+                writer
+                    .create_element("summary")
+                    .with_attribute(("type", "text"))
+                    .write_text_content(BytesText::new(summary))?;
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+}
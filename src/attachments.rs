@@ -0,0 +1,515 @@
+//! Files attached to contacts, like a scanned business card or a signed contract.
+//!
+//! The metadata lives in the `attachments` table and the file itself in the configured
+//! directory, under a random name so nothing the uploader picked ends up in a path. Uploads
+//! are `multipart/form-data` with a single `file` field, parsed here since the forms are the
+//! only place the app takes multipart bodies.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use tracing::{instrument, warn};
+
+use crate::{config::AttachmentsConfig, db::Db, i18n};
+#[cfg(feature = "postgres")]
+use crate::postgres;
+
+/// Name of the form field the file is uploaded in.
+pub const FILE_FIELD: &str = "file";
+/// Longest filename kept, in characters. Longer ones are cut off, extension and all.
+pub const MAX_FILENAME_LEN: usize = 200;
+/// Room on top of `max_bytes` for the multipart boundaries and part headers.
+pub const MULTIPART_OVERHEAD: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Hash, sqlx::FromRow)]
+pub struct Attachment {
+    pub id: i64,
+    pub contact_id: i64,
+    /// The name the file was uploaded with, only ever used for display and downloads.
+    pub filename: String,
+    pub content_type: String,
+    pub size: i64,
+    /// The file's name in the attachments directory.
+    pub stored_name: String,
+    pub created_at: String,
+}
+
+/// A file taken from an upload, not stored yet.
+#[derive(Debug)]
+pub struct Upload {
+    pub filename: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Why an upload was turned down.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UploadError {
+    /// Not a `multipart/form-data` body, or one we couldn't make sense of.
+    Malformed,
+    /// No file was picked, or it was empty.
+    Missing,
+    TooLarge { max_bytes: u64 },
+    TypeNotAllowed(String),
+}
+
+impl UploadError {
+    /// What to tell the user, in the current locale.
+    pub fn message(&self) -> String {
+        match self {
+            UploadError::Malformed => i18n::t("attachment-malformed"),
+            UploadError::Missing => i18n::t("attachment-missing"),
+            UploadError::TooLarge { max_bytes } => i18n::t_with("attachment-too-large", "max", human_size(*max_bytes)),
+            UploadError::TypeNotAllowed(content_type) => {
+                i18n::t_with("attachment-type-not-allowed", "type", content_type.as_str())
+            }
+        }
+    }
+}
+
+/// `bytes` the way people read file sizes: "512 B", "3.4 KB", "10.0 MB".
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KB", "MB", "GB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+impl Attachment {
+    /// The contact's attachments, oldest first.
+    pub async fn list(db: &Db, contact_id: i64) -> Result<Vec<Attachment>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    Attachment,
+                    r#"SELECT id AS "id!", contact_id, filename, content_type, size, stored_name, created_at
+                    FROM attachments WHERE contact_id = ? ORDER BY id"#,
+                    contact_id
+                )
+                .fetch_all(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::list_attachments(pool, contact_id).await,
+        }
+    }
+
+    /// The attachment, if it exists and belongs to the contact.
+    pub async fn find(db: &Db, contact_id: i64, id: i64) -> Result<Option<Attachment>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    Attachment,
+                    r#"SELECT id AS "id!", contact_id, filename, content_type, size, stored_name, created_at
+                    FROM attachments WHERE id = ? AND contact_id = ?"#,
+                    id,
+                    contact_id
+                )
+                .fetch_optional(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::find_attachment(pool, contact_id, id).await,
+        }
+    }
+
+    async fn insert(db: &Db, contact_id: i64, upload: &Upload, stored_name: &str) -> Result<Attachment, sqlx::Error> {
+        let size = upload.data.len() as i64;
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    Attachment,
+                    r#"INSERT INTO attachments (contact_id, filename, content_type, size, stored_name)
+                    VALUES (?, ?, ?, ?, ?)
+                    RETURNING id AS "id!", contact_id, filename, content_type, size, stored_name, created_at"#,
+                    contact_id,
+                    upload.filename,
+                    upload.content_type,
+                    size,
+                    stored_name,
+                )
+                .fetch_one(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::create_attachment(pool, contact_id, upload, size, stored_name).await,
+        }
+    }
+
+    /// Hands every attachment of one contact to another, for merging duplicates. Returns how
+    /// many moved.
+    pub async fn move_all(db: &Db, from_contact_id: i64, to_contact_id: i64) -> Result<u64, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query!(
+                    "UPDATE attachments SET contact_id = ? WHERE contact_id = ?",
+                    to_contact_id,
+                    from_contact_id
+                )
+                .execute(pool)
+                .await
+                .map(|result| result.rows_affected())
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::move_attachments(pool, from_contact_id, to_contact_id).await,
+        }
+    }
+
+    async fn delete_row(db: &Db, id: i64) -> Result<(), sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query!("DELETE FROM attachments WHERE id = ?", id)
+                    .execute(pool)
+                    .await
+                    .map(|_| ())
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::delete_attachment(pool, id).await,
+        }
+    }
+
+    pub fn human_size(&self) -> String {
+        human_size(self.size.max(0) as u64)
+    }
+}
+
+/// Attachment files on disk together with their rows. Cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Attachments {
+    db: Db,
+    config: AttachmentsConfig,
+}
+
+impl Attachments {
+    pub fn new(db: Db, config: AttachmentsConfig) -> Self {
+        Self { db, config }
+    }
+
+    /// Largest request body an upload can come in, file plus multipart framing.
+    pub fn body_limit(&self) -> usize {
+        usize::try_from(self.config.max_bytes)
+            .unwrap_or(usize::MAX)
+            .saturating_add(MULTIPART_OVERHEAD)
+    }
+
+    /// Checks the upload against the configured size and type limits.
+    pub fn check(&self, upload: &Upload) -> Result<(), UploadError> {
+        if upload.data.is_empty() {
+            return Err(UploadError::Missing);
+        }
+        if upload.data.len() as u64 > self.config.max_bytes {
+            return Err(UploadError::TooLarge {
+                max_bytes: self.config.max_bytes,
+            });
+        }
+        if !self
+            .config
+            .allowed_types
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(&upload.content_type))
+        {
+            return Err(UploadError::TypeNotAllowed(upload.content_type.clone()));
+        }
+        Ok(())
+    }
+
+    pub fn too_large(&self) -> UploadError {
+        UploadError::TooLarge {
+            max_bytes: self.config.max_bytes,
+        }
+    }
+
+    /// Where the attachment's file is kept.
+    pub fn path(&self, attachment: &Attachment) -> PathBuf {
+        self.config.dir.join(&attachment.stored_name)
+    }
+
+    /// Writes the file and records it. The file is removed again if the row can't be written,
+    /// so there are no files nothing points to.
+    #[instrument(name = "attachments.store", skip(self, upload), fields(size = upload.data.len()))]
+    pub async fn store(&self, contact_id: i64, upload: &Upload) -> anyhow::Result<Attachment> {
+        tokio::fs::create_dir_all(&self.config.dir)
+            .await
+            .with_context(|| format!("Failed to create attachments directory {}", self.config.dir.display()))?;
+        let stored_name = uuid::Uuid::new_v4().to_string();
+        let path = self.config.dir.join(&stored_name);
+        tokio::fs::write(&path, &upload.data)
+            .await
+            .with_context(|| format!("Failed to write attachment {}", path.display()))?;
+        match Attachment::insert(&self.db, contact_id, upload, &stored_name).await {
+            Ok(attachment) => Ok(attachment),
+            Err(e) => {
+                remove_file(&path).await;
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Removes the attachment's row and then its file.
+    #[instrument(name = "attachments.remove", skip(self, attachment), fields(id = attachment.id))]
+    pub async fn remove(&self, attachment: &Attachment) -> Result<(), sqlx::Error> {
+        Attachment::delete_row(&self.db, attachment.id).await?;
+        remove_file(&self.path(attachment)).await;
+        Ok(())
+    }
+
+    /// Removes everything attached to a contact that is being deleted for good.
+    pub async fn remove_for_contact(&self, contact_id: i64) -> Result<(), sqlx::Error> {
+        for attachment in Attachment::list(&self.db, contact_id).await? {
+            self.remove(&attachment).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Deletes a file that may already be gone. Failures are only logged: the row is what the app
+/// goes by, a leftover file just takes up space.
+async fn remove_file(path: &Path) {
+    match tokio::fs::remove_file(path).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!("Failed to remove attachment file {}: {e}", path.display()),
+    }
+}
+
+/// Takes the `file` field out of a `multipart/form-data` body, given the request's
+/// `Content-Type`. Other fields are ignored.
+pub fn parse_upload(content_type: &str, body: &[u8]) -> Result<Upload, UploadError> {
+    let boundary = boundary(content_type).ok_or(UploadError::Malformed)?;
+    let delimiter = format!("--{boundary}").into_bytes();
+    let mut rest = body;
+    let start = find(rest, &delimiter).ok_or(UploadError::Malformed)?;
+    rest = &rest[start + delimiter.len()..];
+    loop {
+        // `--` right after a delimiter closes the body
+        if rest.starts_with(b"--") {
+            return Err(UploadError::Missing);
+        }
+        rest = rest.strip_prefix(b"\r\n").ok_or(UploadError::Malformed)?;
+        let headers_end = find(rest, b"\r\n\r\n").ok_or(UploadError::Malformed)?;
+        let headers = std::str::from_utf8(&rest[..headers_end]).map_err(|_| UploadError::Malformed)?;
+        rest = &rest[headers_end + 4..];
+        let mut next = b"\r\n".to_vec();
+        next.extend_from_slice(&delimiter);
+        let end = find(rest, &next).ok_or(UploadError::Malformed)?;
+        let data = &rest[..end];
+        rest = &rest[end + next.len()..];
+
+        let mut name = None;
+        let mut filename = None;
+        let mut part_type = None;
+        for line in headers.split("\r\n") {
+            let Some((header, value)) = line.split_once(':') else {
+                continue;
+            };
+            if header.trim().eq_ignore_ascii_case("content-disposition") {
+                name = param(value, "name");
+                filename = param(value, "filename");
+            } else if header.trim().eq_ignore_ascii_case("content-type") {
+                part_type = Some(value.trim().to_string());
+            }
+        }
+        if name.as_deref() != Some(FILE_FIELD) {
+            continue;
+        }
+        let Some(filename) = filename.filter(|f| !f.is_empty()) else {
+            return Err(UploadError::Missing);
+        };
+        let content_type = part_type
+            .as_deref()
+            .and_then(|t| t.split(';').next())
+            .map(|t| t.trim().to_ascii_lowercase())
+            .filter(|t| !t.is_empty())
+            .unwrap_or_else(|| "application/octet-stream".into());
+        return Ok(Upload {
+            filename: clean_filename(&filename),
+            content_type,
+            data: data.to_vec(),
+        });
+    }
+}
+
+/// The `boundary` parameter of a `multipart/form-data` content type.
+fn boundary(content_type: &str) -> Option<String> {
+    let (mime, params) = content_type.split_once(';')?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    param(params, "boundary").filter(|b| !b.is_empty() && b.len() <= 70)
+}
+
+/// A `name=value` or `name="value"` parameter from a header value's `;` separated list.
+fn param(header: &str, name: &str) -> Option<String> {
+    params(header).into_iter().find_map(|part| {
+        let (key, value) = part.trim().split_once('=')?;
+        if !key.trim().eq_ignore_ascii_case(name) {
+            return None;
+        }
+        let value = value.trim();
+        Some(match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+            None => value.to_string(),
+        })
+    })
+}
+
+/// Splits a header value at the `;`s outside quoted strings, which a filename can contain.
+fn params(header: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in header.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                parts.push(&header[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&header[start..]);
+    parts
+}
+
+/// The uploaded filename without any directories (old browsers send the full path) or control
+/// characters, cut to `MAX_FILENAME_LEN`. Browsers send a `"` in the name as `%22`.
+fn clean_filename(filename: &str) -> String {
+    let filename = filename.replace("%22", "\"");
+    let base = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = base
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_FILENAME_LEN)
+        .collect();
+    match cleaned.trim() {
+        "" | "." | ".." => "attachment".into(),
+        name => name.to_string(),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// `Content-Disposition` value that downloads the file under its uploaded name: a plain ASCII
+/// `filename` for old clients and the exact name, percent-encoded, in `filename*` (RFC 6266).
+pub fn content_disposition(filename: &str) -> String {
+    let ascii: String = filename
+        .chars()
+        .map(|c| if c.is_ascii_graphic() && c != '"' && c != '\\' || c == ' ' { c } else { '_' })
+        .collect();
+    let encoded: String = filename
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect();
+    format!("attachment; filename=\"{ascii}\"; filename*=UTF-8''{encoded}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT_TYPE: &str = "multipart/form-data; boundary=XyZ";
+
+    /// A body with the `file` field, framed with `newline` and closed unless `close` is false.
+    fn body(disposition: &str, data: &[u8], newline: &str, close: bool) -> Vec<u8> {
+        let mut body = format!(
+            "--XyZ{newline}Content-Disposition: form-data; name=\"note\"{newline}{newline}hi{newline}\
+             --XyZ{newline}Content-Disposition: {disposition}{newline}Content-Type: text/plain{newline}{newline}"
+        )
+        .into_bytes();
+        body.extend_from_slice(data);
+        if close {
+            body.extend_from_slice(format!("{newline}--XyZ--{newline}").as_bytes());
+        }
+        body
+    }
+
+    fn attachments(max_bytes: u64) -> Attachments {
+        let pool = sqlx::SqlitePool::connect_lazy("sqlite::memory:").unwrap();
+        Attachments::new(
+            Db::Sqlite(pool),
+            AttachmentsConfig {
+                dir: PathBuf::from("attachments"),
+                max_bytes,
+                allowed_types: vec!["text/plain".into()],
+            },
+        )
+    }
+
+    #[test]
+    fn parses_the_file_field() {
+        let upload = parse_upload(
+            CONTENT_TYPE,
+            &body("form-data; name=\"file\"; filename=\"card.txt\"", b"Ada", "\r\n", true),
+        )
+        .unwrap();
+        assert_eq!(upload.filename, "card.txt");
+        assert_eq!(upload.content_type, "text/plain");
+        assert_eq!(upload.data, b"Ada");
+    }
+
+    #[test]
+    fn keeps_a_quoted_semicolon_in_the_filename() {
+        let upload = parse_upload(
+            CONTENT_TYPE,
+            &body("form-data; name=\"file\"; filename=\"a;b \\\"c\\\".txt\"", b"Ada", "\r\n", true),
+        )
+        .unwrap();
+        assert_eq!(upload.filename, "a;b \"c\".txt");
+        // The field name is still found after it
+        let upload = parse_upload(
+            CONTENT_TYPE,
+            &body("form-data; filename=\"x;name=y.txt\"; name=\"file\"", b"Ada", "\r\n", true),
+        )
+        .unwrap();
+        assert_eq!(upload.filename, "x;name=y.txt");
+    }
+
+    #[test]
+    fn rejects_a_body_cut_off_before_the_final_boundary() {
+        let disposition = "form-data; name=\"file\"; filename=\"card.txt\"";
+        let truncated = body(disposition, b"Ada Lovelace", "\r\n", false);
+        assert_eq!(parse_upload(CONTENT_TYPE, &truncated).unwrap_err(), UploadError::Malformed);
+        // Cut off inside the part's headers
+        let mut headers = body(disposition, b"", "\r\n", false);
+        headers.truncate(headers.len() - 10);
+        assert_eq!(parse_upload(CONTENT_TYPE, &headers).unwrap_err(), UploadError::Malformed);
+    }
+
+    #[test]
+    fn needs_crlf_framing_but_keeps_any_newlines_in_the_file() {
+        let disposition = "form-data; name=\"file\"; filename=\"card.txt\"";
+        let data = b"one\r\ntwo\nthree\r\n--Xy is not the boundary\r\n";
+        let upload = parse_upload(CONTENT_TYPE, &body(disposition, data, "\r\n", true)).unwrap();
+        assert_eq!(upload.data, data);
+        let lf = body(disposition, b"one\ntwo", "\n", true);
+        assert_eq!(parse_upload(CONTENT_TYPE, &lf).unwrap_err(), UploadError::Malformed);
+    }
+
+    #[tokio::test]
+    async fn turns_down_a_part_larger_than_the_limit() {
+        let attachments = attachments(4);
+        let disposition = "form-data; name=\"file\"; filename=\"card.txt\"";
+        let fits = parse_upload(CONTENT_TYPE, &body(disposition, b"Ada!", "\r\n", true)).unwrap();
+        assert_eq!(attachments.check(&fits), Ok(()));
+        let large = parse_upload(CONTENT_TYPE, &body(disposition, b"Ada L", "\r\n", true)).unwrap();
+        assert_eq!(attachments.check(&large).unwrap_err(), UploadError::TooLarge { max_bytes: 4 });
+        // The framing around it doesn't count against the limit
+        assert!(attachments.body_limit() > body(disposition, b"Ada!", "\r\n", true).len());
+    }
+}
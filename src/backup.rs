@@ -0,0 +1,307 @@
+//! Online SQLite backups. `VACUUM INTO` writes a consistent copy of the live database while
+//! the server keeps running, so there's no need to stop it to take a backup.
+//!
+//! With `database.backup_recipient` set to an age public key, backups are encrypted to that
+//! key and only the `.db.age` file is kept, so they can be shipped off-site as is. Decrypt
+//! with `age -d -i key.txt webone-....db.age > restored.db`. `webone export` encrypts its file
+//! to the same key with `encrypt`.
+//!
+//! With `database.backup_interval_hours` set a backup is also queued on that schedule (see
+//! `spawn_scheduler`), and after every backup the old ones are pruned by `Retention`. Backups
+//! are named after the app clock's time (see `clock`), and their age is taken from the name.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    iter,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use serde_json::{Value, json};
+use tracing::{info, warn};
+
+use crate::{
+    clock::SharedClock,
+    config::DatabaseConfig,
+    db::Db,
+    jobs::{JobHandler, Jobs},
+};
+
+/// Job kind that writes a backup, see `BackupJob`.
+pub const BACKUP_JOB: &str = "backup";
+
+const PREFIX: &str = "webone-";
+const EXTENSION: &str = ".db";
+const ENCRYPTED_EXTENSION: &str = ".db.age";
+/// The time a backup was taken, in its name.
+const NAME_TIME_FORMAT: &str = "%Y%m%d-%H%M%S%.3f";
+
+/// A backup file in the backup directory.
+#[derive(Debug, Clone)]
+pub struct Backup {
+    pub name: String,
+    pub encrypted: bool,
+    pub size: u64,
+    /// When it was taken, from its name, or the file's modification time if that has none.
+    pub created_at: DateTime<Utc>,
+}
+
+/// Backup files are named by the app, so anything that doesn't look like one (or tries to
+/// escape the directory) is not a backup.
+pub fn is_backup_name(name: &str) -> bool {
+    name.starts_with(PREFIX)
+        && (name.ends_with(EXTENSION) || name.ends_with(ENCRYPTED_EXTENSION))
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && !name.contains("..")
+}
+
+/// When the backup named `name` was taken, going by the timestamp `create` put in the name.
+fn name_time(name: &str) -> Option<DateTime<Utc>> {
+    let time = name.strip_prefix(PREFIX)?;
+    let time = time.strip_suffix(ENCRYPTED_EXTENSION).or_else(|| time.strip_suffix(EXTENSION))?;
+    NaiveDateTime::parse_from_str(time, NAME_TIME_FORMAT).ok().map(|time| time.and_utc())
+}
+
+/// Parses an age X25519 public key (`age1...`).
+pub fn parse_recipient(recipient: &str) -> anyhow::Result<age::x25519::Recipient> {
+    age::x25519::Recipient::from_str(recipient.trim())
+        .map_err(|e| anyhow::anyhow!("Invalid age recipient {recipient:?}: {e}"))
+}
+
+/// Writes a backup of the database to `webone-<timestamp>.db` in `dir`, creating the
+/// directory if needed. If `recipient` is given the backup is encrypted to it and written to
+/// `webone-<timestamp>.db.age` instead; the unencrypted copy is removed. The timestamp is
+/// `clock`'s time. Only SQLite databases can be backed up this way.
+pub async fn create(db: &Db, dir: &Path, recipient: Option<&str>, clock: &SharedClock) -> anyhow::Result<Backup> {
+    let recipient = recipient.map(parse_recipient).transpose()?;
+    let pool = db
+        .sqlite()
+        .context("Backups are only supported for SQLite databases, use pg_dump for Postgres")?;
+    tokio::fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("Failed to create backup directory {}", dir.display()))?;
+
+    let created_at = clock.now();
+    let name = format!("{PREFIX}{}{EXTENSION}", created_at.format(NAME_TIME_FORMAT));
+    let path = dir.join(&name);
+    // VACUUM INTO refuses to overwrite an existing file, so two backups in the same
+    // millisecond fail instead of clobbering each other
+    sqlx::query("VACUUM INTO ?")
+        .bind(path.to_string_lossy())
+        .execute(pool)
+        .await
+        .with_context(|| format!("Failed to write backup {}", path.display()))?;
+
+    let (name, path) = match recipient {
+        Some(recipient) => (format!("{name}.age"), encrypt(&path, recipient).await?),
+        None => (name, path),
+    };
+
+    let size = tokio::fs::metadata(&path).await?.len();
+    Ok(Backup {
+        encrypted: name.ends_with(ENCRYPTED_EXTENSION),
+        name,
+        size,
+        created_at,
+    })
+}
+
+/// Encrypts the file at `path` to `recipient` as `<path>.age` and removes the plaintext, also
+/// when encryption fails. Returns the encrypted file's path.
+pub async fn encrypt(path: &Path, recipient: age::x25519::Recipient) -> anyhow::Result<PathBuf> {
+    let mut encrypted_path = path.as_os_str().to_owned();
+    encrypted_path.push(".age");
+    let encrypted_path = PathBuf::from(encrypted_path);
+    let plain_path = path.to_path_buf();
+    let target = encrypted_path.clone();
+    let result = tokio::task::spawn_blocking(move || encrypt_file(&plain_path, &target, &recipient)).await?;
+    tokio::fs::remove_file(path).await?;
+    result.with_context(|| format!("Failed to encrypt {}", encrypted_path.display()))?;
+    Ok(encrypted_path)
+}
+
+fn encrypt_file(plain: &Path, target: &Path, recipient: &age::x25519::Recipient) -> anyhow::Result<()> {
+    let encryptor = age::Encryptor::with_recipients(iter::once(recipient as &dyn age::Recipient))?;
+    let mut input = BufReader::new(File::open(plain)?);
+    let mut writer = encryptor.wrap_output(BufWriter::new(File::create(target)?))?;
+    io::copy(&mut input, &mut writer)?;
+    writer.finish()?.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(())
+}
+
+/// Every backup in `dir`, newest first. A missing directory just means no backups yet.
+pub async fn list(dir: &Path) -> anyhow::Result<Vec<Backup>> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read backup directory {}", dir.display()));
+        }
+    };
+
+    let mut backups = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !is_backup_name(&name) {
+            continue;
+        }
+        let metadata = entry.metadata().await?;
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        let created_at = name_time(&name).unwrap_or_else(|| modified.into());
+        backups.push(Backup {
+            encrypted: name.ends_with(ENCRYPTED_EXTENSION),
+            name,
+            size: metadata.len(),
+            created_at,
+        });
+    }
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(backups)
+}
+
+/// Which backups to keep, by count and by age. 0 turns either limit off.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Retention {
+    /// How many of the newest backups are kept.
+    pub keep: usize,
+    /// Backups older than this many days are removed.
+    pub max_age_days: u32,
+}
+
+impl From<&DatabaseConfig> for Retention {
+    fn from(config: &DatabaseConfig) -> Self {
+        Self {
+            keep: config.backup_keep,
+            max_age_days: config.backup_max_age_days,
+        }
+    }
+}
+
+impl Retention {
+    /// Whether it ever removes anything.
+    pub fn is_off(&self) -> bool {
+        self.keep == 0 && self.max_age_days == 0
+    }
+}
+
+/// Removes the backups in `dir` that `retention` doesn't keep as of `now`: all but the newest
+/// `keep`, and those older than `max_age_days`. Returns the names of the removed ones.
+pub async fn prune(dir: &Path, retention: Retention, now: DateTime<Utc>) -> anyhow::Result<Vec<String>> {
+    if retention.is_off() {
+        return Ok(Vec::new());
+    }
+    let cutoff = (retention.max_age_days > 0).then(|| now - TimeDelta::days(retention.max_age_days.into()));
+    let mut removed = Vec::new();
+    // Newest first, so the ones past `keep` are the oldest
+    for (index, backup) in list(dir).await?.into_iter().enumerate() {
+        let too_many = retention.keep > 0 && index >= retention.keep;
+        let too_old = cutoff.is_some_and(|cutoff| backup.created_at < cutoff);
+        if too_many || too_old {
+            tokio::fs::remove_file(dir.join(&backup.name))
+                .await
+                .with_context(|| format!("Failed to remove old backup {}", backup.name))?;
+            removed.push(backup.name);
+        }
+    }
+    if !removed.is_empty() {
+        info!("Removed {} old backup(s) from {}", removed.len(), dir.display());
+    }
+    Ok(removed)
+}
+
+/// Path of the named backup, or `None` if the name isn't a valid backup name.
+pub fn path(dir: &Path, name: &str) -> Option<PathBuf> {
+    is_backup_name(name).then(|| dir.join(name))
+}
+
+/// Runs `BACKUP_JOB` jobs: a `create` into `dir`, encrypted to `recipient` if set, and then a
+/// `prune` by `retention`. The payload is ignored.
+pub struct BackupJob {
+    pub db: Db,
+    pub dir: PathBuf,
+    pub recipient: Option<String>,
+    pub retention: Retention,
+    pub clock: SharedClock,
+}
+
+#[async_trait]
+impl JobHandler for BackupJob {
+    async fn run(&self, _payload: &Value) -> anyhow::Result<String> {
+        let backup = create(&self.db, &self.dir, self.recipient.as_deref(), &self.clock).await?;
+        let removed = prune(&self.dir, self.retention, backup.created_at).await?;
+        Ok(match removed.len() {
+            0 => format!("Wrote {} ({} bytes)", backup.name, backup.size),
+            count => format!("Wrote {} ({} bytes), removed {count} old backup(s)", backup.name, backup.size),
+        })
+    }
+}
+
+/// Queues a backup every `interval`, the first one an `interval` after startup.
+pub fn spawn_scheduler(jobs: Jobs, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        // The first tick is immediate
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            if let Err(e) = jobs.enqueue(BACKUP_JOB, json!({})).await {
+                warn!("Failed to queue the scheduled backup: {e}");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::{app, clock::FixedClock, config::Config};
+
+    #[tokio::test]
+    async fn backups_are_named_and_pruned_by_the_clock() {
+        // A file, the in-memory one's `VACUUM INTO` doesn't write to the disk
+        let root = std::env::temp_dir().join(format!("webone-backup-test-{}", std::process::id()));
+        tokio::fs::create_dir_all(&root).await.unwrap();
+        let mut config = Config::default();
+        config.database.url = format!("sqlite://{}", root.join("contacts.db").display());
+        let (db, _) = app::connect(&config).await.unwrap();
+        let dir = root.join("backups");
+        let start = Utc.with_ymd_and_hms(2026, 10, 1, 2, 0, 0).unwrap();
+        let clock = Arc::new(FixedClock::new(start));
+        let shared: SharedClock = clock.clone();
+
+        let mut names = Vec::new();
+        for days in [0, 2, 3] {
+            clock.set(start + TimeDelta::days(days));
+            names.push(create(&db, &dir, None, &shared).await.unwrap().name);
+        }
+        assert_eq!(names[0], "webone-20261001-020000.000.db");
+        let listed = list(&dir).await.unwrap();
+        assert_eq!(listed.iter().map(|b| b.created_at).collect::<Vec<_>>(), [3, 2, 0].map(|d| start + TimeDelta::days(d)));
+
+        // Older than two days as of the last one
+        let retention = Retention { keep: 0, max_age_days: 2 };
+        assert_eq!(prune(&dir, retention, shared.now()).await.unwrap(), [names[0].clone()]);
+        let retention = Retention { keep: 1, max_age_days: 0 };
+        assert_eq!(prune(&dir, retention, shared.now()).await.unwrap(), [names[1].clone()]);
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[test]
+    fn name_times_come_from_create() {
+        let at = Utc.with_ymd_and_hms(2026, 10, 15, 9, 30, 0).unwrap() + TimeDelta::milliseconds(42);
+        assert_eq!(name_time("webone-20261015-093000.042.db"), Some(at));
+        assert_eq!(name_time("webone-20261015-093000.042.db.age"), Some(at));
+        assert_eq!(name_time("webone-nightly.db"), None);
+    }
+}
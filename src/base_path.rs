@@ -0,0 +1,86 @@
+//! The path prefix the app is served under (`server.base_path`), for running it behind a
+//! reverse proxy at something like `https://example.com/contacts-app/`.
+//!
+//! The routes are nested under the prefix, and every link, form action and redirect the app
+//! generates goes through `url`, so they all carry it. Templates prefix their literal paths
+//! with `crate::base_path::get()`. The default is no prefix, serving at the root.
+//!
+//! The prefix belongs to the app rather than the process: the app's router runs every request
+//! in `scope` with its prefix, so two apps with different ones can be served side by side.
+//! Tenants served by path (see `tenants`) each have their own prefix under it, set for the
+//! requests to them the same way.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{LazyLock, Mutex},
+};
+
+/// Prefixes made `'static` for `scope` so far, so each is only leaked once.
+static LEAKED: LazyLock<Mutex<HashMap<String, &'static str>>> = LazyLock::new(Mutex::default);
+
+tokio::task_local! {
+    static SCOPED: &'static str;
+}
+
+/// `base_path` with a leading slash and without trailing ones, or empty for the root:
+/// `contacts-app/` becomes `/contacts-app`, `/` becomes ``.
+pub fn normalize(base_path: &str) -> String {
+    let trimmed = base_path.trim().trim_matches('/');
+    if trimmed.is_empty() { String::new() } else { format!("/{trimmed}") }
+}
+
+/// `prefix`, already normalized, as a `'static` str for `scope`. The same prefix always comes
+/// back as the same str, leaked the first time only.
+///
+/// Example usage:
+/// `let prefix = base_path::leak(&config.server.base_path);`
+pub fn leak(prefix: &str) -> &'static str {
+    let mut leaked = LEAKED.lock().unwrap();
+    if let Some(prefix) = leaked.get(prefix) {
+        return prefix;
+    }
+    let prefix: &'static str = Box::leak(prefix.to_string().into_boxed_str());
+    leaked.insert(prefix.to_string(), prefix);
+    prefix
+}
+
+/// The prefix of the request being handled, like `/contacts-app`, or empty when serving at
+/// the root. Outside `scope`, like in background jobs, it is empty too.
+///
+/// Example usage:
+/// `<a href="{{ crate::base_path::get() }}/contacts/new">`
+pub fn get() -> &'static str {
+    SCOPED.try_with(|prefix| *prefix).unwrap_or_default()
+}
+
+/// Runs `f` with `prefix` as the prefix, the innermost one winning when nested.
+///
+/// Example usage:
+/// `base_path::scope("/contacts-app/t/acme", router.oneshot(request)).await`
+pub async fn scope<F: Future>(prefix: &'static str, f: F) -> F::Output {
+    SCOPED.scope(prefix, f).await
+}
+
+/// `path`, which starts with a slash, under the prefix.
+///
+/// Example usage:
+/// `Redirect::to(&base_path::url("/contacts"))`
+pub fn url(path: &str) -> String {
+    format!("{}{path}", get())
+}
+
+/// `path` with the prefix taken off, for matching request paths against the routes they were
+/// registered as. Paths outside the prefix come back unchanged.
+pub fn strip(path: &str) -> &str {
+    path.strip_prefix(get()).filter(|rest| rest.starts_with('/')).unwrap_or(path)
+}
+
+/// `Path` attribute for cookies, so they are only sent to this app and not the rest of the
+/// site it is proxied from.
+pub fn cookie_path() -> &'static str {
+    match get() {
+        "" => "/",
+        base_path => base_path,
+    }
+}
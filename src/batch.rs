@@ -0,0 +1,277 @@
+//! `POST /api/v1/batch`: creates, updates and deletes pushed together by sync clients, made
+//! all at once or not at all.
+//!
+//! Each operation is checked first, like the single-contact API would: new and updated
+//! fields are validated and the contacts to update or delete are looked up. Then the
+//! changes are made in order in one transaction (see `Contact::apply_batch`). The first
+//! operation that fails stops the batch, and nothing of it is kept.
+//!
+//! Updates and deletes can have an `if_match` with the contact's ETag, like the `If-Match` of
+//! a single change, and fail if the contact has changed since. It's the ETag the contact has
+//! before the batch, whatever earlier operations of it change. With or without one, an update
+//! or delete fails the same way if the contact is changed between the check and the
+//! transaction, rather than overwrite that change.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    contacts::{BatchFailure, BatchFailureReason, Contact, ContactError, ContactPatch, ContactWrite, NewContact, NewContactErrors},
+    etag::ETag,
+    i18n,
+    repository::ContactRepository,
+};
+
+/// Most operations one batch may have.
+pub const MAX_OPERATIONS: usize = 1000;
+
+/// One operation of a batch, tagged with `op`.
+///
+/// Example usage:
+/// `[{"op":"create","contact":{...}},{"op":"update","id":1,"if_match":"1-20261015093000123","contact":{"job_title":"CTO"}},{"op":"delete","id":3}]`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    Create {
+        contact: NewContact,
+    },
+    /// Changes only the fields `contact` has, like `PATCH`.
+    Update {
+        id: i64,
+        contact: ContactPatch,
+        #[serde(default)]
+        if_match: Option<String>,
+    },
+    Delete {
+        id: i64,
+        #[serde(default)]
+        if_match: Option<String>,
+    },
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Create { .. } => "create",
+            Operation::Update { .. } => "update",
+            Operation::Delete { .. } => "delete",
+        }
+    }
+
+    /// The contact the operation is on, if it's already stored.
+    pub fn id(&self) -> Option<i64> {
+        match self {
+            Operation::Create { .. } => None,
+            Operation::Update { id, .. } | Operation::Delete { id, .. } => Some(*id),
+        }
+    }
+}
+
+/// What became of an operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+    Applied,
+    /// The operation the batch stopped at.
+    Failed,
+    /// Another operation failed, so this one wasn't made or was rolled back.
+    NotApplied,
+}
+
+/// The result of one operation, in request order.
+#[derive(Debug, Serialize)]
+pub struct OperationResult {
+    pub index: usize,
+    pub op: &'static str,
+    pub status: OperationStatus,
+    /// The contact's id, the new one for a create.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    /// The fields that didn't validate or that another contact has.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<NewContactErrors>,
+    /// Why it failed otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The contact's current ETag, when its `if_match` was stale.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+}
+
+/// Why a batch made none of its changes: operation `index` failed.
+#[derive(Debug)]
+pub struct Rejection {
+    pub index: usize,
+    pub reason: RejectionReason,
+}
+
+#[derive(Debug)]
+pub enum RejectionReason {
+    /// The fields didn't validate.
+    Invalid(NewContactErrors),
+    /// Another contact has the email or phone number.
+    Taken(&'static str),
+    /// The contact to update or delete isn't there.
+    NotFound,
+    /// The contact's ETag isn't the operation's `if_match`, or it was changed after the batch
+    /// was checked. Has the current one.
+    Stale(ETag),
+}
+
+/// What `apply_batch` turning the batch down with `failure` means for its operations. An
+/// update or delete of a contact changed since `plan` checked it is `Stale`, with the ETag it
+/// has now, or `NotFound` if it's gone.
+///
+/// Example usage:
+/// `let rejection = batch::rejection(state.contacts.as_ref(), &writes, failure).await?;`
+pub async fn rejection(
+    contacts: &dyn ContactRepository,
+    writes: &[ContactWrite],
+    failure: BatchFailure,
+) -> Result<Rejection, ContactError> {
+    let reason = match (failure.reason, &writes[failure.index]) {
+        (BatchFailureReason::Taken(field), _) => RejectionReason::Taken(field),
+        (BatchFailureReason::Changed, ContactWrite::Update(contact) | ContactWrite::Delete(contact)) => {
+            match contacts.find_by_id(contact.id).await {
+                Ok(current) => RejectionReason::Stale(ETag::version(&current)),
+                Err(ContactError::NotFound) => RejectionReason::NotFound,
+                Err(e) => return Err(e),
+            }
+        }
+        (BatchFailureReason::Changed, ContactWrite::Create(_)) => RejectionReason::NotFound,
+    };
+    Ok(Rejection { index: failure.index, reason })
+}
+
+/// Checks `operations` and turns them into the changes to make, in order. An update is
+/// applied on top of the contact as the earlier operations of the batch leave it.
+///
+/// Example usage:
+/// `let writes = batch::plan(state.contacts.as_ref(), &operations).await?;`
+pub async fn plan(
+    contacts: &dyn ContactRepository,
+    operations: &[Operation],
+) -> Result<Result<Vec<ContactWrite>, Rejection>, ContactError> {
+    let mut current = HashMap::new();
+    let mut versions = HashMap::new();
+    let mut writes = Vec::with_capacity(operations.len());
+    for (index, operation) in operations.iter().enumerate() {
+        let reject = |reason| Ok(Err(Rejection { index, reason }));
+        let write = match operation {
+            Operation::Create { contact: new } => {
+                if let Some(errors) = new.validate() {
+                    return reject(RejectionReason::Invalid(errors));
+                }
+                ContactWrite::Create(new.clone())
+            }
+            Operation::Update { id, contact: patch, if_match } => {
+                let Some(mut contact) = lookup(contacts, &mut current, &mut versions, *id).await? else {
+                    return reject(RejectionReason::NotFound);
+                };
+                if let Some(stale) = stale(&versions[id], if_match.as_deref()) {
+                    return reject(stale);
+                }
+                let new = patch.clone().apply(&contact);
+                if let Some(errors) = new.validate() {
+                    return reject(RejectionReason::Invalid(errors));
+                }
+                contact.update_from(new);
+                current.insert(*id, Some(contact.clone()));
+                ContactWrite::Update(contact)
+            }
+            Operation::Delete { id, if_match } => {
+                let Some(contact) = lookup(contacts, &mut current, &mut versions, *id).await? else {
+                    return reject(RejectionReason::NotFound);
+                };
+                if let Some(stale) = stale(&versions[id], if_match.as_deref()) {
+                    return reject(stale);
+                }
+                current.insert(*id, None);
+                ContactWrite::Delete(contact)
+            }
+        };
+        writes.push(write);
+    }
+    Ok(Ok(writes))
+}
+
+/// Contact `id` as the batch so far leaves it, `None` if it isn't there or was deleted. The
+/// first time, its ETag from before the batch goes into `versions`.
+async fn lookup(
+    contacts: &dyn ContactRepository,
+    current: &mut HashMap<i64, Option<Contact>>,
+    versions: &mut HashMap<i64, ETag>,
+    id: i64,
+) -> Result<Option<Contact>, ContactError> {
+    if let Some(contact) = current.get(&id) {
+        return Ok(contact.clone());
+    }
+    let contact = match contacts.find_by_id(id).await {
+        Ok(contact) => Some(contact),
+        Err(ContactError::NotFound) => None,
+        Err(e) => return Err(e),
+    };
+    if let Some(contact) = &contact {
+        versions.insert(id, ETag::version(contact));
+    }
+    current.insert(id, contact.clone());
+    Ok(contact)
+}
+
+/// Why an operation with `if_match` on a contact at `version` can't go ahead, if it can't.
+fn stale(version: &ETag, if_match: Option<&str>) -> Option<RejectionReason> {
+    match if_match {
+        Some(tag) if !version.matches_tag(tag) => Some(RejectionReason::Stale(version.clone())),
+        _ => None,
+    }
+}
+
+/// The results for a batch that went through: `applied` has the contact of each operation.
+pub fn applied(operations: &[Operation], applied: &[Contact]) -> Vec<OperationResult> {
+    operations
+        .iter()
+        .zip(applied)
+        .enumerate()
+        .map(|(index, (operation, contact))| OperationResult {
+            index,
+            op: operation.name(),
+            status: OperationStatus::Applied,
+            id: Some(contact.id),
+            errors: None,
+            error: None,
+            etag: None,
+        })
+        .collect()
+}
+
+/// The results for a batch that was turned down by `rejection`: the operation that failed and
+/// why, and every other one as not applied.
+pub fn rejected(operations: &[Operation], rejection: Rejection) -> Vec<OperationResult> {
+    let mut results: Vec<OperationResult> = operations
+        .iter()
+        .enumerate()
+        .map(|(index, operation)| OperationResult {
+            index,
+            op: operation.name(),
+            status: OperationStatus::NotApplied,
+            id: operation.id(),
+            errors: None,
+            error: None,
+            etag: None,
+        })
+        .collect();
+    if let Some(failed) = results.get_mut(rejection.index) {
+        failed.status = OperationStatus::Failed;
+        match rejection.reason {
+            RejectionReason::Invalid(errors) => failed.errors = Some(errors),
+            RejectionReason::Taken(field) => failed.errors = Some(NewContactErrors::taken(field)),
+            RejectionReason::NotFound => failed.error = Some(i18n::t("batch-contact-not-found")),
+            RejectionReason::Stale(etag) => {
+                failed.error = Some(i18n::t("api-if-match-stale"));
+                failed.etag = Some(etag.as_str().to_string());
+            }
+        }
+    }
+    results
+}
@@ -0,0 +1,207 @@
+//! CardDAV (RFC 6352), so phone and desktop address books can sync the contacts natively.
+//!
+//! There is one address book, `/dav/addressbooks/contacts/`, holding every contact as
+//! `<id>.vcf`. Clients find it from `/.well-known/carddav` through the principal and the
+//! address book home, all of which answer `PROPFIND`. The address book also answers
+//! `addressbook-multiget` and `addressbook-query` `REPORT`s (query filters are ignored, every
+//! card is returned). Per-card ETags come from `updated_at`, and the collection's `getctag`
+//! changes whenever any card does, so clients can tell what to download again.
+//!
+//! The handlers are in `app.rs`; this module parses the requests and builds the resources
+//! for `DavMultistatusTemplate`.
+
+use anyhow::Context;
+use axum::http::{HeaderMap, HeaderName};
+use quick_xml::{Reader, events::Event};
+
+use crate::{contacts::Contact, vcard};
+
+pub const ROOT: &str = "/dav/";
+pub const PRINCIPAL: &str = "/dav/principal/";
+pub const HOME: &str = "/dav/addressbooks/";
+pub const ADDRESSBOOK: &str = "/dav/addressbooks/contacts/";
+
+/// Value of the `DAV` header: WebDAV class 1 and 3, plus CardDAV.
+pub const DAV_COMPLIANCE: &str = "1, 3, addressbook";
+pub const DAV: HeaderName = HeaderName::from_static("dav");
+pub const VCARD_CONTENT_TYPE: &str = "text/vcard; charset=utf-8";
+pub const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+/// `updated_at` as it goes into card ETags and the ctag, down to the millisecond.
+const VERSION_FORMAT: &str = "%Y%m%d%H%M%S%3f";
+
+/// One `<response>` in a multistatus body. Properties that don't apply are left unset.
+#[derive(Debug, Clone, Default)]
+pub struct DavResource {
+    pub href: String,
+    /// `false` renders a `404 Not Found` response, for multiget hrefs that don't exist.
+    pub found: bool,
+    pub collection: bool,
+    pub principal: bool,
+    pub addressbook: bool,
+    pub displayname: Option<String>,
+    pub current_user_principal: Option<&'static str>,
+    pub addressbook_home_set: Option<&'static str>,
+    pub getctag: Option<String>,
+    pub getetag: Option<String>,
+    pub getcontenttype: Option<&'static str>,
+    pub address_data: Option<String>,
+}
+
+impl DavResource {
+    /// A plain collection, like the DAV root.
+    pub fn collection(href: &str) -> Self {
+        Self {
+            href: href.to_string(),
+            found: true,
+            collection: true,
+            current_user_principal: Some(PRINCIPAL),
+            ..Self::default()
+        }
+    }
+
+    /// The one principal. There are no user accounts, so it stands for whoever connects.
+    pub fn principal() -> Self {
+        Self {
+            principal: true,
+            displayname: Some("webone".into()),
+            addressbook_home_set: Some(HOME),
+            ..Self::collection(PRINCIPAL)
+        }
+    }
+
+    pub fn home() -> Self {
+        Self {
+            addressbook_home_set: Some(HOME),
+            ..Self::collection(HOME)
+        }
+    }
+
+    /// The address book itself, with the ctag computed from `contacts`.
+    pub fn addressbook(contacts: &[Contact]) -> Self {
+        Self {
+            addressbook: true,
+            displayname: Some("Contacts".into()),
+            getctag: Some(ctag(contacts)),
+            ..Self::collection(ADDRESSBOOK)
+        }
+    }
+
+    /// A card, with its vCard body if `with_data` (for `REPORT`s).
+    pub fn card(contact: &Contact, with_data: bool) -> Self {
+        Self {
+            href: contact_href(contact.id),
+            found: true,
+            getetag: Some(etag(contact)),
+            getcontenttype: Some(VCARD_CONTENT_TYPE),
+            address_data: with_data.then(|| vcard::to_vcard(contact)),
+            ..Self::default()
+        }
+    }
+
+    pub fn not_found(href: &str) -> Self {
+        Self {
+            href: href.to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+pub fn contact_href(id: i64) -> String {
+    format!("{ADDRESSBOOK}{id}.vcf")
+}
+
+/// The contact id in a card name or href, `12.vcf` or `/dav/addressbooks/contacts/12.vcf`.
+pub fn contact_id(href: &str) -> Option<i64> {
+    let name = href.rsplit('/').next()?;
+    name.strip_suffix(".vcf")?.parse().ok()
+}
+
+/// Strong ETag of a card. Unlike the page ETags it doesn't change on restart, which would
+/// make every client download every card again.
+pub fn etag(contact: &Contact) -> String {
+    format!("\"{}-{}\"", contact.id, contact.updated_at.format(VERSION_FORMAT))
+}
+
+/// Changes whenever a card is added, changed or removed.
+pub fn ctag(contacts: &[Contact]) -> String {
+    let latest = contacts
+        .iter()
+        .map(|c| c.updated_at.format(VERSION_FORMAT).to_string())
+        .max()
+        .unwrap_or_default();
+    let ids: i64 = contacts.iter().map(|c| c.id).sum();
+    format!("{}-{latest}-{ids}", contacts.len())
+}
+
+/// Whether the request's `Depth` asks for the children too. `PROPFIND` defaults to infinity,
+/// which is answered like `1`.
+pub fn wants_children(headers: &HeaderMap) -> bool {
+    headers
+        .get("depth")
+        .and_then(|v| v.to_str().ok())
+        .is_none_or(|depth| depth.trim() != "0")
+}
+
+/// Whether `If-Match` / `If-None-Match` allow changing a card whose current ETag is
+/// `current` (`None` if it doesn't exist yet).
+pub fn preconditions_hold(headers: &HeaderMap, current: Option<&str>) -> bool {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+    if let Some(if_match) = header("if-match")
+        && !(if_match == "*" && current.is_some())
+        && current.is_none_or(|etag| !if_match.split(',').any(|tag| tag.trim() == etag))
+    {
+        return false;
+    }
+    if let Some(if_none_match) = header("if-none-match")
+        && let Some(etag) = current
+        && (if_none_match == "*" || if_none_match.split(',').any(|tag| tag.trim() == etag))
+    {
+        return false;
+    }
+    true
+}
+
+/// The `REPORT`s the address book supports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Report {
+    /// `addressbook-multiget`: the listed cards.
+    Multiget(Vec<String>),
+    /// `addressbook-query`: every card.
+    Query,
+    /// Anything else, by element name.
+    Unsupported(String),
+}
+
+/// Reads which report a `REPORT` body asks for, and the hrefs of a multiget.
+pub fn parse_report(body: &str) -> anyhow::Result<Report> {
+    let mut reader = Reader::from_str(body);
+    reader.config_mut().trim_text(true);
+    let mut report = None;
+    let mut hrefs = Vec::new();
+    let mut in_href = false;
+    loop {
+        match reader.read_event().context("Invalid XML in REPORT body")? {
+            Event::Start(element) | Event::Empty(element) => {
+                let local_name = element.local_name();
+                let name: &str = local_name.as_ref();
+                let name = name.to_string();
+                in_href = name == "href";
+                if report.is_none() {
+                    report = Some(name);
+                }
+            }
+            Event::Text(text) if in_href => {
+                let href: &str = text.as_ref();
+                hrefs.push(href.trim().to_string());
+            }
+            Event::End(_) => in_href = false,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(match report.as_deref() {
+        Some("addressbook-multiget") => Report::Multiget(hrefs),
+        Some("addressbook-query") => Report::Query,
+        other => Report::Unsupported(other.unwrap_or_default().to_string()),
+    })
+}
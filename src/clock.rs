@@ -0,0 +1,125 @@
+//! The current time, as a dependency. Code that stamps or expires things asks the `Clock` in
+//! `AppState` instead of calling `Utc::now()` itself, so tests can freeze time with a
+//! `FixedClock` and compare exact timestamps.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, TimeDelta, Utc};
+use serde::Deserialize;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The real time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to.
+///
+/// Example usage:
+/// `let clock = FixedClock::new(Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap());`
+/// `clock.advance(TimeDelta::hours(2));`
+#[derive(Debug)]
+pub struct FixedClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl FixedClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self { now: Mutex::new(now) }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: TimeDelta) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// Timezone timestamps are shown in. Everything is stored in UTC, this only changes how pages
+/// write it: `UTC`, `local` (the server's own timezone, with its daylight saving time), or a
+/// fixed offset like `+02:00`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub enum DisplayTimezone {
+    #[default]
+    Utc,
+    Local,
+    Offset(FixedOffset),
+}
+
+impl DisplayTimezone {
+    /// `at` in this timezone, with a `chrono` format string.
+    ///
+    /// Example usage:
+    /// `DisplayTimezone::Local.format(&contact.created_at, "%Y-%m-%d %H:%M %:z")`
+    pub fn format(&self, at: &DateTime<Utc>, format: &str) -> String {
+        match self {
+            DisplayTimezone::Utc => at.format(format).to_string(),
+            DisplayTimezone::Local => at.with_timezone(&Local).format(format).to_string(),
+            DisplayTimezone::Offset(offset) => at.with_timezone(offset).format(format).to_string(),
+        }
+    }
+
+    /// The day it is at `at` in this timezone.
+    pub fn date(&self, at: &DateTime<Utc>) -> NaiveDate {
+        match self {
+            DisplayTimezone::Utc => at.date_naive(),
+            DisplayTimezone::Local => at.with_timezone(&Local).date_naive(),
+            DisplayTimezone::Offset(offset) => at.with_timezone(offset).date_naive(),
+        }
+    }
+}
+
+impl FromStr for DisplayTimezone {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            tz if tz.eq_ignore_ascii_case("utc") || tz == "Z" => Ok(DisplayTimezone::Utc),
+            tz if tz.eq_ignore_ascii_case("local") => Ok(DisplayTimezone::Local),
+            tz => tz.parse().map(DisplayTimezone::Offset).map_err(|_| {
+                format!("\"{tz}\" is not a timezone, use UTC, local or an offset like +02:00")
+            }),
+        }
+    }
+}
+
+impl TryFrom<String> for DisplayTimezone {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for DisplayTimezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayTimezone::Utc => f.write_str("UTC"),
+            DisplayTimezone::Local => f.write_str("local"),
+            DisplayTimezone::Offset(offset) => write!(f, "{offset}"),
+        }
+    }
+}
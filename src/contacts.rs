@@ -1,35 +1,781 @@
-use serde::Deserialize;
-use sqlx::SqlitePool;
+use std::{borrow::Cow, cmp::Ordering, collections::HashMap};
 
-// TODO: Figure out how to get creation errors.
-// So far I have no way to error out here besides just having the database freak out.
-#[derive(Debug, Deserialize)]
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::Connection;
+use tracing::instrument;
+
+#[cfg(feature = "postgres")]
+use crate::postgres;
+use crate::{
+    db::{self, Db, shapes},
+    fold, i18n,
+    names::NameFormat,
+    phone,
+    repository::ContactStream,
+    validation,
+};
+
+/// Format `created_at` is stored in by SQLite, which has no timestamp type, and compared in as
+/// a sort key.
+pub const CREATED_AT_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// Format `updated_at` is stored in by SQLite. Milliseconds, so it changes with every edit.
+pub const UPDATED_AT_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+/// What's wrong with the fields of a submitted contact, in the current locale, so the form
+/// can show it next to them. `None` for the fields that are fine.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct NewContactErrors {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub first_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub last_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub phone_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub email: Option<String>,
 }
-#[derive(Debug, Deserialize)]
+
+impl NewContactErrors {
+    /// "Already exists" on `field` (`email` or `phone_number`), for when the database refuses
+    /// a contact because another one has the same value, see `db::conflicting_field`.
+    pub fn taken(field: &str) -> Self {
+        match field {
+            "email" => Self { email: Some(i18n::t("email-taken")), ..Self::default() },
+            _ => Self { phone_number: Some(i18n::t("phone-taken")), ..Self::default() },
+        }
+    }
+
+    /// The problem with `field`, by its form name, or an empty string if there is none.
+    pub fn get(&self, field: &str) -> &str {
+        let error = match field {
+            "first_name" => &self.first_name,
+            "last_name" => &self.last_name,
+            "phone_number" => &self.phone_number,
+            "email" => &self.email,
+            _ => &None,
+        };
+        error.as_deref().unwrap_or_default()
+    }
+
+    /// All the problems in one line, for places with no room to show them by field.
+    pub fn summary(&self) -> String {
+        [&self.first_name, &self.last_name, &self.phone_number, &self.email]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct NewContact {
     pub first_name: String,
     pub last_name: String,
     pub phone_number: String,
     pub email: String,
+    /// Optional in forms, so clients posting only the other fields keep working.
+    #[serde(default)]
+    pub company: String,
+    #[serde(default)]
+    pub job_title: String,
     pub errors: Option<NewContactErrors>,
+    /// One-time token from the create form, see `FormTokens`.
+    pub form_token: Option<String>,
 }
 
-#[derive(Debug)]
+impl NewContact {
+    /// Checks the fields with the `validation` rules. `None` if they are all fine.
+    pub fn validate(&self) -> Option<NewContactErrors> {
+        let (first_name, last_name) = match validation::name_problem(&self.first_name, &self.last_name) {
+            Some(problem @ "problem-last-name-missing") => (None, Some(i18n::t(problem))),
+            Some(problem) => (Some(i18n::t(problem)), None),
+            None => (None, None),
+        };
+        let errors = NewContactErrors {
+            first_name,
+            last_name,
+            phone_number: validation::phone_problem(&self.phone_number).map(i18n::t),
+            email: validation::email_problem(&self.email).map(i18n::t),
+        };
+        let valid = [&errors.first_name, &errors.last_name, &errors.phone_number, &errors.email]
+            .iter()
+            .all(|error| error.is_none());
+        (!valid).then_some(errors)
+    }
+
+    /// The `search_text` column for these fields, see `fold::search_text`.
+    pub fn search_text(&self) -> String {
+        fold::search_text(&[
+            &self.first_name,
+            &self.last_name,
+            &self.company,
+            &self.job_title,
+            &self.email,
+            &self.phone_number,
+        ])
+    }
+}
+
+/// A partial update (`PATCH`): only the fields that are set change.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ContactPatch {
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub phone_number: Option<String>,
+    pub email: Option<String>,
+    pub company: Option<String>,
+    pub job_title: Option<String>,
+}
+
+impl ContactPatch {
+    /// `contact`'s fields with the patch's on top, to be validated and saved like a full
+    /// update.
+    pub fn apply(self, contact: &Contact) -> NewContact {
+        NewContact {
+            first_name: self.first_name.unwrap_or_else(|| contact.first_name.clone()),
+            last_name: self.last_name.unwrap_or_else(|| contact.last_name.clone()),
+            phone_number: self.phone_number.unwrap_or_else(|| contact.phone_number.clone()),
+            email: self.email.unwrap_or_else(|| contact.email.clone()),
+            company: self.company.unwrap_or_else(|| contact.company.clone()),
+            job_title: self.job_title.unwrap_or_else(|| contact.job_title.clone()),
+            errors: None,
+            form_token: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Contact {
     pub id: i64,
     pub first_name: String,
     pub last_name: String,
     pub phone_number: String,
     pub email: String,
-    pub created_at: String,
+    /// Empty when not known, like `job_title`.
+    pub company: String,
+    pub job_title: String,
+    pub created_at: DateTime<Utc>,
+    /// Last change, set on every update.
+    pub updated_at: DateTime<Utc>,
+}
+
+/// One page of contacts and the number of contacts there are in all, see
+/// `Contact::search_page`.
+#[derive(Debug, Clone)]
+pub struct ContactPage {
+    pub contacts: Vec<Contact>,
+    pub total: i64,
+}
+
+/// A row of `Contact::search_rows`: the contact and, when asked for, the number of contacts
+/// matching the filter in all, from `COUNT(*) OVER ()`.
+#[derive(Debug, sqlx::FromRow)]
+pub struct CountedContact {
+    #[sqlx(flatten)]
+    pub contact: Contact,
+    pub total: Option<i64>,
+}
+
+impl CountedContact {
+    /// The contacts and the total of the first row. An empty page has no rows to carry it.
+    pub fn split(rows: Vec<Self>) -> (Vec<Contact>, Option<i64>) {
+        let total = rows.first().and_then(|row| row.total);
+        (rows.into_iter().map(|row| row.contact).collect(), total)
+    }
+}
+
+/// Columns the contact list can be sorted by. Anything else in a `sort` parameter is
+/// rejected, so user input never ends up in an `ORDER BY`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ContactSort {
+    #[default]
+    Id,
+    FirstName,
+    LastName,
+    Email,
+    Company,
+    CreatedAt,
+}
+
+impl ContactSort {
+    pub const ALL: &[ContactSort] = &[
+        ContactSort::Id,
+        ContactSort::FirstName,
+        ContactSort::LastName,
+        ContactSort::Email,
+        ContactSort::Company,
+        ContactSort::CreatedAt,
+    ];
+
+    /// The column name, which is also the value of the `sort` query parameter.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContactSort::Id => "id",
+            ContactSort::FirstName => "first_name",
+            ContactSort::LastName => "last_name",
+            ContactSort::Email => "email",
+            ContactSort::Company => "company",
+            ContactSort::CreatedAt => "created_at",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|sort| sort.as_str() == value)
+    }
+}
+
+/// Most columns a `ContactOrder` can sort by.
+pub const MAX_SORT_KEYS: usize = 3;
+
+/// Which way one column of a `ContactOrder` sorts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl SortDirection {
+    /// The value of the `dir` query parameter.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortDirection::Asc => "asc",
+            SortDirection::Desc => "desc",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        [SortDirection::Asc, SortDirection::Desc].into_iter().find(|dir| dir.as_str() == value)
+    }
+
+    /// `ordering` of two values ascending, as it is in this direction.
+    pub fn apply(&self, ordering: Ordering) -> Ordering {
+        match self {
+            SortDirection::Asc => ordering,
+            SortDirection::Desc => ordering.reverse(),
+        }
+    }
+}
+
+/// The order of the contact list: one to `MAX_SORT_KEYS` different columns, each ascending or
+/// descending, with ties broken by id. In list URLs it is `sort=company,last_name` with an
+/// optional `dir=desc,asc`, the directions in the same order and ascending where left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ContactOrder {
+    keys: [(ContactSort, SortDirection); MAX_SORT_KEYS],
+    len: usize,
+}
+
+/// Why `sort` and `dir` parameters don't make a `ContactOrder`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderProblem {
+    UnknownColumn(String),
+    UnknownDirection(String),
+    TooManyColumns,
+    RepeatedColumn(ContactSort),
+    TooManyDirections,
+}
+
+impl ContactOrder {
+    /// Sorts by `keys`, in that order. `None` if there are none, more than `MAX_SORT_KEYS`,
+    /// or a column comes up twice.
+    pub fn new(keys: &[(ContactSort, SortDirection)]) -> Option<Self> {
+        if keys.is_empty() || keys.len() > MAX_SORT_KEYS {
+            return None;
+        }
+        if keys.iter().enumerate().any(|(i, (column, _))| keys[..i].iter().any(|(other, _)| other == column)) {
+            return None;
+        }
+        let mut order = Self { keys: [keys[0]; MAX_SORT_KEYS], len: keys.len() };
+        order.keys[..keys.len()].copy_from_slice(keys);
+        Some(order)
+    }
+
+    /// The order in `sort` and `dir` parameter values, comma separated column names and
+    /// directions. Missing directions are ascending.
+    pub fn parse(sort: &str, dir: &str) -> Result<Self, OrderProblem> {
+        let columns = sort
+            .split(',')
+            .map(str::trim)
+            .filter(|column| !column.is_empty())
+            .map(|column| ContactSort::parse(column).ok_or_else(|| OrderProblem::UnknownColumn(column.to_string())))
+            .collect::<Result<Vec<_>, _>>()?;
+        let dirs = match dir.trim() {
+            "" => Vec::new(),
+            dir => dir
+                .split(',')
+                .map(str::trim)
+                .map(|d| match d {
+                    "" => Ok(SortDirection::Asc),
+                    d => SortDirection::parse(d).ok_or_else(|| OrderProblem::UnknownDirection(d.to_string())),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+        if columns.is_empty() {
+            return Err(OrderProblem::UnknownColumn(sort.trim().to_string()));
+        }
+        if columns.len() > MAX_SORT_KEYS {
+            return Err(OrderProblem::TooManyColumns);
+        }
+        if dirs.len() > columns.len() {
+            return Err(OrderProblem::TooManyDirections);
+        }
+        if let Some(column) = columns.iter().enumerate().find_map(|(i, c)| columns[..i].contains(c).then_some(*c)) {
+            return Err(OrderProblem::RepeatedColumn(column));
+        }
+        let keys: Vec<_> = columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| (*column, dirs.get(i).copied().unwrap_or_default()))
+            .collect();
+        // Everything `new` checks has been ruled out above
+        Self::new(&keys).ok_or(OrderProblem::TooManyColumns)
+    }
+
+    pub fn keys(&self) -> &[(ContactSort, SortDirection)] {
+        &self.keys[..self.len]
+    }
+
+    /// The column sorted by first.
+    pub fn primary(&self) -> (ContactSort, SortDirection) {
+        self.keys[0]
+    }
+
+    /// How `column` is sorted, `None` if it isn't.
+    pub fn direction(&self, column: ContactSort) -> Option<SortDirection> {
+        self.keys().iter().find(|(c, _)| *c == column).map(|(_, dir)| *dir)
+    }
+
+    /// The `sort` parameter value, like `company,last_name`.
+    pub fn sort_param(&self) -> String {
+        self.keys().iter().map(|(column, _)| column.as_str()).collect::<Vec<_>>().join(",")
+    }
+
+    /// The `dir` parameter value, like `desc,asc`. Empty when everything is ascending, the
+    /// default.
+    pub fn dir_param(&self) -> String {
+        if self.keys().iter().all(|(_, dir)| *dir == SortDirection::Asc) {
+            return String::new();
+        }
+        self.keys().iter().map(|(_, dir)| dir.as_str()).collect::<Vec<_>>().join(",")
+    }
+
+    /// The columns the list is really ordered by: the keys, then `id` to break ties unless it
+    /// is one of them already. The tie breaker goes the same way as the last key, so ordering
+    /// by one column, either way, walks that column's index forwards or backwards.
+    pub fn terms(&self) -> Vec<(ContactSort, SortDirection)> {
+        let mut terms = self.keys().to_vec();
+        if self.direction(ContactSort::Id).is_none() {
+            terms.push((ContactSort::Id, self.keys[self.len - 1].1));
+        }
+        terms
+    }
+
+    /// The `ORDER BY` of the list queries, with `column` writing each of the `terms` the way
+    /// the database's index on it has it. Only `ContactSort` names end up in the SQL.
+    pub fn order_by(&self, column: impl Fn(ContactSort) -> &'static str) -> String {
+        self.terms()
+            .iter()
+            .map(|(sort, dir)| format!("{} {}", column(*sort), dir.as_str().to_uppercase()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Keyset condition of the list queries, the rows after a cursor in this order. `key`
+    /// writes the parameter holding the cursor's value for the `i`th of the `terms` (see
+    /// `ContactCursor::values`). When every term goes the same way it's one row value
+    /// comparison, which the index serves directly, otherwise it's spelled out term by term.
+    pub fn after_condition(
+        &self,
+        column: impl Fn(ContactSort) -> &'static str,
+        key: impl Fn(ContactSort, usize) -> String,
+    ) -> String {
+        let terms = self.terms();
+        let columns: Vec<_> = terms.iter().map(|(sort, _)| column(*sort)).collect();
+        let keys: Vec<_> = terms.iter().enumerate().map(|(i, (sort, _))| key(*sort, i)).collect();
+        let after = |dir: SortDirection| match dir {
+            SortDirection::Asc => ">",
+            SortDirection::Desc => "<",
+        };
+        if terms.iter().all(|(_, dir)| *dir == terms[0].1) {
+            return format!("({}) {} ({})", columns.join(", "), after(terms[0].1), keys.join(", "));
+        }
+        let alternatives: Vec<_> = terms
+            .iter()
+            .enumerate()
+            .map(|(i, (_, dir))| {
+                let same: String = (0..i).map(|j| format!("{} = {} AND ", columns[j], keys[j])).collect();
+                format!("({same}{} {} {})", columns[i], after(*dir), keys[i])
+            })
+            .collect();
+        format!("({})", alternatives.join(" OR "))
+    }
+
+    /// Which of `a` and `b` comes first, the same way the list queries order them.
+    pub fn compare(&self, a: &Contact, b: &Contact) -> Ordering {
+        self.terms()
+            .iter()
+            .map(|(column, dir)| dir.apply(a.sort_key(*column).cmp(&b.sort_key(*column))))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl From<ContactSort> for ContactOrder {
+    /// Ascending by just `column`.
+    fn from(column: ContactSort) -> Self {
+        Self { keys: [(column, SortDirection::Asc); MAX_SORT_KEYS], len: 1 }
+    }
+}
+
+impl Default for ContactOrder {
+    fn default() -> Self {
+        ContactSort::default().into()
+    }
+}
+
+/// What the contact list is narrowed down to. Every part is optional and the ones that are set
+/// all have to match. The values are bound as query parameters, never spliced into the SQL.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ContactFilter {
+    /// Free text, matched against the name, company, job title, email and phone number, and
+    /// against the phone number's digits if it looks like one.
+    pub text: Option<String>,
+    /// Created on or after this day.
+    pub created_after: Option<NaiveDate>,
+    /// Created before this day, so `created_after` to `created_before` is a half-open range.
+    pub created_before: Option<NaiveDate>,
+    /// Email at exactly this domain, lowercase and without the `@`.
+    pub email_domain: Option<String>,
+    /// Last name starting with this letter, an uppercase `A` to `Z`.
+    pub last_initial: Option<char>,
+}
+
+/// Letters of the A–Z bar over the contact list.
+pub const LETTERS: std::ops::RangeInclusive<char> = 'A'..='Z';
+
+/// How many contacts have a last name starting with `letter`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LetterCount {
+    pub letter: char,
+    pub count: i64,
+}
+
+impl LetterCount {
+    /// Every letter of `LETTERS` with its count from `counts`, `(initial, count)` rows as the
+    /// aggregate queries return them, and 0 for the letters that aren't there. Initials that
+    /// aren't a letter of the bar are left out.
+    pub fn fill(counts: &[(String, i64)]) -> Vec<LetterCount> {
+        LETTERS
+            .map(|letter| LetterCount {
+                letter,
+                count: counts
+                    .iter()
+                    .filter(|(initial, _)| initial.chars().eq([letter]))
+                    .map(|(_, count)| count)
+                    .sum(),
+            })
+            .collect()
+    }
+}
+
+impl ContactFilter {
+    /// Free text search only, like the search box.
+    pub fn text(text: &str) -> Self {
+        Self {
+            text: Some(text.to_string()),
+            ..Self::default()
+        }
+    }
+
+    /// `true` if nothing is filtered, so every contact matches.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// `LIKE` pattern for the free text, folded to match the `search_text` column.
+    pub fn pattern(&self) -> Option<String> {
+        self.text.as_ref().map(|text| format!("%{}%", fold::fold(text)))
+    }
+
+    /// `LIKE` pattern for the digits of the stored phone numbers, if the free text looks like
+    /// a phone number. See `phone::search_pattern`.
+    pub fn phone_pattern(&self) -> Option<String> {
+        self.text.as_deref().and_then(phone::search_pattern)
+    }
+
+    /// `LIKE` pattern for the last name initial.
+    pub fn initial_pattern(&self) -> Option<String> {
+        self.last_initial.map(|letter| format!("{letter}%"))
+    }
+
+    /// The same filter without the last name initial, for counting contacts per letter.
+    pub fn without_initial(&self) -> Self {
+        Self {
+            last_initial: None,
+            ..self.clone()
+        }
+    }
+
+    /// The dates as `created_at` prefixes. SQLite stores `created_at` as text, and `YYYY-MM-DD`
+    /// sorts before every time on that day, so comparing the text does the right thing.
+    pub fn created_bounds(&self) -> (Option<String>, Option<String>) {
+        let day = |date: &NaiveDate| date.format("%Y-%m-%d").to_string();
+        (self.created_after.as_ref().map(day), self.created_before.as_ref().map(day))
+    }
+
+    /// The same test as the SQL queries, for stores that filter in memory.
+    pub fn matches(&self, contact: &Contact) -> bool {
+        let created = contact.created_at.date_naive();
+        let text_matches = self.text.as_ref().is_none_or(|text| {
+            let phone_digits = phone::search_pattern(text).map(|pattern| pattern.trim_matches('%').to_string());
+            contact.search_text().contains(&fold::fold(text))
+                || phone_digits.is_some_and(|digits| phone::normalize(&contact.phone_number).contains(&digits))
+        });
+        let domain_matches = self.email_domain.as_ref().is_none_or(|domain| {
+            contact
+                .email
+                .split_once('@')
+                .is_some_and(|(_, d)| d.to_lowercase() == *domain)
+        });
+        let initial_matches = self.last_initial.is_none_or(|letter| {
+            contact.last_name.chars().next().is_some_and(|c| c.to_ascii_uppercase() == letter)
+        });
+        text_matches
+            && domain_matches
+            && initial_matches
+            && self.created_after.is_none_or(|after| created >= after)
+            && self.created_before.is_none_or(|before| created < before)
+    }
+}
+
+/// One change of `Contact::apply_batch`.
+#[derive(Debug, Clone)]
+pub enum ContactWrite {
+    Create(NewContact),
+    /// Saves the contact's fields.
+    Update(Contact),
+    /// Deletes the contact with this one's id.
+    Delete(Contact),
+}
+
+/// Why `Contact::apply_batch` made none of its changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchFailure {
+    /// The change that couldn't be made.
+    pub index: usize,
+    pub reason: BatchFailureReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchFailureReason {
+    /// Another contact has this field (`email` or `phone_number`).
+    Taken(&'static str),
+    /// The contact to update or delete was changed or deleted since it was read, like
+    /// `ContactError::Changed`.
+    Changed,
+}
+
+/// The `updated_at` of each contact as an `apply_batch` goes, for its changes to be checked
+/// against: the one the contact was read at, until a change of the batch moves it on.
+#[derive(Debug, Default)]
+pub struct BatchVersions(HashMap<i64, DateTime<Utc>>);
+
+impl BatchVersions {
+    /// `contact` with the `updated_at` the batch has left it at so far.
+    pub fn current(&self, contact: &Contact) -> Contact {
+        Contact {
+            updated_at: self.0.get(&contact.id).copied().unwrap_or(contact.updated_at),
+            ..contact.clone()
+        }
+    }
+
+    pub fn set(&mut self, id: i64, updated_at: DateTime<Utc>) {
+        self.0.insert(id, updated_at);
+    }
+}
+
+/// Why a contact couldn't be found or saved. `AppError` turns these into a `404`, a `409` and
+/// the error page.
+#[derive(Debug)]
+pub enum ContactError {
+    /// There is no contact with that id, or not anymore.
+    NotFound,
+    /// Another contact already has this field (`email` or `phone_number`).
+    Taken(&'static str),
+    /// The contact was changed or deleted since the version a conditional change was based
+    /// on, see `Contact::update_unchanged`.
+    Changed,
+    /// Anything else the database reported, busy included.
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for ContactError {
+    fn from(err: sqlx::Error) -> Self {
+        if let sqlx::Error::RowNotFound = err {
+            return Self::NotFound;
+        }
+        match db::conflicting_field(&err) {
+            Some(field) => Self::Taken(field),
+            None => Self::Database(err),
+        }
+    }
+}
+
+impl std::fmt::Display for ContactError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContactError::NotFound => f.write_str("No such contact"),
+            ContactError::Taken(field) => write!(f, "Another contact has the same {field}"),
+            ContactError::Changed => f.write_str("The contact was changed since"),
+            ContactError::Database(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ContactError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ContactError::Database(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl db::MaybeBusy for ContactError {
+    fn is_busy(&self) -> bool {
+        matches!(self, ContactError::Database(err) if db::is_busy(err))
+    }
+}
+
+/// A company and the contacts that work there, for the grouped view.
+#[derive(Debug, Clone)]
+pub struct Company {
+    pub name: String,
+    pub contacts: Vec<Contact>,
+}
+
+impl Company {
+    /// Groups contacts that are already sorted by company, keeping the order. Contacts
+    /// without a company are left out.
+    pub fn group(contacts: Vec<Contact>) -> Vec<Company> {
+        let mut companies: Vec<Company> = Vec::new();
+        for contact in contacts.into_iter().filter(|c| !c.company.is_empty()) {
+            match companies.last_mut() {
+                Some(company) if company.name == contact.company => company.contacts.push(contact),
+                _ => companies.push(Company {
+                    name: contact.company.clone(),
+                    contacts: vec![contact],
+                }),
+            }
+        }
+        companies
+    }
+}
+
+/// Keyset pagination cursor: the position of the last contact on a page in `order`. The next
+/// page starts right after it, which stays fast on big tables where `OFFSET` has to walk over
+/// every skipped row. Passed around as an opaque hex string, see `encode`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ContactCursor {
+    pub order: ContactOrder,
+    /// The contact's values in the order's columns, see `Contact::sort_key`.
+    pub keys: Vec<String>,
+    pub id: i64,
+}
+
+impl ContactCursor {
+    /// Cursor for the page after `contact`.
+    pub fn after(contact: &Contact, order: ContactOrder) -> Self {
+        Self {
+            order,
+            keys: order.keys().iter().map(|(column, _)| contact.sort_key(*column).into_owned()).collect(),
+            id: contact.id,
+        }
+    }
+
+    /// The cursor's value for each of its order's `terms`, in that order, which is how the
+    /// list queries bind them.
+    pub fn values(&self) -> Vec<String> {
+        let mut values = self.keys.clone();
+        if self.order.direction(ContactSort::Id).is_none() {
+            values.push(format!("{:020}", self.id));
+        }
+        values
+    }
+
+    /// `true` if `contact` comes after the cursor in its order, the keyset condition of the
+    /// list queries.
+    pub fn precedes(&self, contact: &Contact) -> bool {
+        self.order
+            .terms()
+            .iter()
+            .zip(self.values())
+            .map(|((column, dir), value)| dir.apply(contact.sort_key(*column).as_ref().cmp(value.as_str())))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+            .is_gt()
+    }
+
+    pub fn encode(&self) -> String {
+        let parts = (self.order.sort_param(), self.order.dir_param(), self.id, &self.keys);
+        // Serializing strings and a number can't fail
+        serde_json::to_string(&parts)
+            .unwrap_or_default()
+            .bytes()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    /// `None` for anything `encode` didn't produce.
+    pub fn decode(cursor: &str) -> Option<Self> {
+        if !cursor.is_ascii() || !cursor.len().is_multiple_of(2) {
+            return None;
+        }
+        let bytes = (0..cursor.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let (sort, dir, id, keys): (String, String, i64, Vec<String>) = serde_json::from_slice(&bytes).ok()?;
+        let order = ContactOrder::parse(&sort, &dir).ok()?;
+        (keys.len() == order.keys().len()).then_some(Self { order, keys, id })
+    }
 }
 
 impl Contact {
+    /// First and last name, for messages about the contact and vCard's `FN`, which are the
+    /// same whatever the reader's display preference.
+    pub fn full_name(&self) -> String {
+        NameFormat::FirstLast.format(&self.first_name, &self.last_name)
+    }
+
+    /// The `search_text` column for the contact's current fields, see `fold::search_text`.
+    pub fn search_text(&self) -> String {
+        fold::search_text(&[
+            &self.first_name,
+            &self.last_name,
+            &self.company,
+            &self.job_title,
+            &self.email,
+            &self.phone_number,
+        ])
+    }
+
+    /// The value of the sort column, as compared by the list queries and cursors. Everything
+    /// is compared as text: the id zero-padded to 20 digits so it sorts like the number, and
+    /// `created_at` as `CREATED_AT_FORMAT`.
+    pub fn sort_key(&self, sort: ContactSort) -> Cow<'_, str> {
+        match sort {
+            ContactSort::Id => Cow::Owned(format!("{:020}", self.id)),
+            ContactSort::FirstName => Cow::Borrowed(&self.first_name),
+            ContactSort::LastName => Cow::Borrowed(&self.last_name),
+            ContactSort::Email => Cow::Borrowed(&self.email),
+            ContactSort::Company => Cow::Borrowed(&self.company),
+            ContactSort::CreatedAt => Cow::Owned(self.created_at.format(CREATED_AT_FORMAT).to_string()),
+        }
+    }
+
     /// Update the existing contact from a `NewContact` struct. This is useful when updating contacts via
     /// the edit form as we don't have to pass the entire Contact (id, created_at)
     pub fn update_from(&mut self, new: NewContact) {
@@ -37,100 +783,783 @@ impl Contact {
         self.last_name = new.last_name;
         self.phone_number = new.phone_number;
         self.email = new.email;
+        self.company = new.company;
+        self.job_title = new.job_title;
+    }
+    #[instrument(name = "contacts.create", skip_all, fields(binds = %shapes(&[&new.first_name, &new.last_name, &new.phone_number, &new.email, &new.company, &new.job_title])))]
+    pub async fn create(db: &Db, new: NewContact, now: DateTime<Utc>) -> Result<Self, ContactError> {
+        let created = match db {
+            Db::Sqlite(pool) => insert_sqlite(pool, &new, now).await,
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::create_contact(pool, &new, now).await,
+        };
+        Ok(created?)
+    }
+
+    /// Stores the new contacts in one transaction, each in a savepoint of its own so one that
+    /// is refused doesn't take the others with it. Returns a result per contact, in order: the
+    /// stored contact, or the field (`email` or `phone_number`) another contact, possibly an
+    /// earlier one in the batch, already has. Any other error rolls back the whole batch.
+    #[instrument(name = "contacts.create_many", skip_all, fields(count = new.len()))]
+    pub async fn create_many(
+        db: &Db,
+        new: &[NewContact],
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Result<Self, &'static str>>, sqlx::Error> {
+        Self::insert_many(db, new, now, true).await
     }
-    pub async fn create(pool: &SqlitePool, new: NewContact) -> Result<Self, sqlx::Error> {
-        sqlx::query_as!(
-            Contact,
-            "INSERT INTO contacts (first_name, last_name, phone_number, email) VALUES (?, ?, ?, ?) RETURNING *",
-            new.first_name,
-            new.last_name,
-            new.phone_number,
-            new.email,
-        )
-        .fetch_one(pool)
-        .await
-    }
-
-    pub async fn update(&self, pool: &SqlitePool) -> Result<(), sqlx::Error> {
-        sqlx::query!(
-        "UPDATE contacts SET first_name = ?, last_name = ?, phone_number = ?, email = ? WHERE id = ?",
-            self.first_name,
-            self.last_name,
-            self.phone_number,
-            self.email,
-            self.id,
-        )
-            .execute(pool)
-            .await
-            .map(|_| ()) // Like Ok(())
-    }
-    pub async fn delete(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
-        sqlx::query!("DELETE FROM contacts WHERE id = ?", id,)
-            .execute(pool)
-            .await?;
 
+    /// Inserts the new contacts like `create_many` and rolls them all back, for a dry run.
+    /// Returns, per contact in order, the field another contact already has, or `None` if it
+    /// would be stored.
+    #[instrument(name = "contacts.check_many", skip_all, fields(count = new.len()))]
+    pub async fn check_many(db: &Db, new: &[NewContact], now: DateTime<Utc>) -> Result<Vec<Option<&'static str>>, sqlx::Error> {
+        let results = Self::insert_many(db, new, now, false).await?;
+        Ok(results.into_iter().map(Result::err).collect())
+    }
+
+    /// `create_many`, with the transaction committed or, for `check_many`, rolled back.
+    async fn insert_many(
+        db: &Db,
+        new: &[NewContact],
+        now: DateTime<Utc>,
+        commit: bool,
+    ) -> Result<Vec<Result<Self, &'static str>>, sqlx::Error> {
+        let mut results = Vec::with_capacity(new.len());
+        match db {
+            Db::Sqlite(pool) => {
+                let mut tx = pool.begin().await?;
+                for contact in new {
+                    let mut savepoint = tx.begin().await?;
+                    let result = insert_sqlite(&mut *savepoint, contact, now).await;
+                    results.push(finish_savepoint(savepoint, result).await?);
+                }
+                if commit {
+                    tx.commit().await?;
+                } else {
+                    tx.rollback().await?;
+                }
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => {
+                let mut tx = pool.begin().await?;
+                for contact in new {
+                    let mut savepoint = tx.begin().await?;
+                    let result = postgres::create_contact(&mut *savepoint, contact, now).await;
+                    results.push(finish_savepoint(savepoint, result).await?);
+                }
+                if commit {
+                    tx.commit().await?;
+                } else {
+                    tx.rollback().await?;
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    #[instrument(name = "contacts.update", skip_all, fields(binds = %shapes(&[&self.first_name, &self.last_name, &self.phone_number, &self.email, &self.company, &self.job_title, &self.id])))]
+    /// Fails with `ContactError::NotFound` if the contact was deleted in the meantime.
+    pub async fn update(&self, db: &Db, now: DateTime<Utc>) -> Result<(), ContactError> {
+        let now = self.next_version(now);
+        let changed = match db {
+            Db::Sqlite(pool) => update_sqlite(pool, self, now, None).await?,
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::update_contact(pool, self, now, None).await?,
+        };
+        if changed == 0 {
+            return Err(ContactError::NotFound);
+        }
+        Ok(())
+    }
+
+    /// Like `update`, but only if the stored contact is still the version this one was read
+    /// at: the same `updated_at`, to the millisecond its ETag has. Otherwise nothing is written
+    /// and it fails with `ContactError::Changed`, so of two clients changing the same version
+    /// only the first gets through.
+    #[instrument(name = "contacts.update_unchanged", skip_all, fields(binds = %shapes(&[&self.id])))]
+    pub async fn update_unchanged(&self, db: &Db, now: DateTime<Utc>) -> Result<(), ContactError> {
+        let now = self.next_version(now);
+        let changed = match db {
+            Db::Sqlite(pool) => update_sqlite(pool, self, now, Some(self.updated_at)).await?,
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::update_contact(pool, self, now, Some(self.updated_at)).await?,
+        };
+        if changed == 0 {
+            return Err(ContactError::Changed);
+        }
         Ok(())
     }
 
-    pub async fn get_all(pool: &SqlitePool, page: i64, per_page: i64) -> Result<Vec<Contact>, sqlx::Error> {
-        let offset = (page - 1) * per_page;
-        sqlx::query_as!(
-            Contact,
-            "SELECT * FROM contacts ORDER BY id LIMIT ? OFFSET ?",
-            per_page,
-            offset,
+    /// The `updated_at` for a change made at `now`: at least a millisecond after the current
+    /// one, so two saves within a millisecond still get different ETags.
+    pub fn next_version(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now.max(self.updated_at + TimeDelta::milliseconds(1))
+    }
+
+    #[instrument(name = "contacts.delete", skip_all, fields(binds = %shapes(&[&id])))]
+    pub async fn delete(db: &Db, id: i64) -> Result<(), ContactError> {
+        match db {
+            Db::Sqlite(pool) => {
+                delete_sqlite(pool, id, None).await?;
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => {
+                postgres::delete_contact(pool, id, None).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the contact if it is still the version `contact` was read at, like
+    /// `update_unchanged`. Fails with `ContactError::Changed` otherwise.
+    #[instrument(name = "contacts.delete_unchanged", skip_all, fields(binds = %shapes(&[&contact.id])))]
+    pub async fn delete_unchanged(db: &Db, contact: &Contact) -> Result<(), ContactError> {
+        let deleted = match db {
+            Db::Sqlite(pool) => delete_sqlite(pool, contact.id, Some(contact.updated_at)).await?,
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::delete_contact(pool, contact.id, Some(contact.updated_at)).await?,
+        };
+        if deleted == 0 {
+            return Err(ContactError::Changed);
+        }
+        Ok(())
+    }
+
+    /// Makes the changes in `writes` in order, in one transaction: either all of them are
+    /// made, or, if one can't be, none are and that one's `BatchFailure` comes back. Returns
+    /// the contact each change was made to: the new contact, the updated one with its
+    /// `updated_at` moved on like `update` does, or the deleted one. Any other error rolls
+    /// everything back too.
+    ///
+    /// Updates and deletes are made like `update_unchanged` and `delete_unchanged`, against the
+    /// version the contact was read at or an earlier change of the batch left it at, so a
+    /// change made since the batch was checked isn't overwritten.
+    #[instrument(name = "contacts.apply_batch", skip_all, fields(count = writes.len()))]
+    pub async fn apply_batch(
+        db: &Db,
+        writes: &[ContactWrite],
+        now: DateTime<Utc>,
+    ) -> Result<Result<Vec<Contact>, BatchFailure>, sqlx::Error> {
+        let mut applied = Vec::with_capacity(writes.len());
+        let mut versions = BatchVersions::default();
+        match db {
+            Db::Sqlite(pool) => {
+                let mut tx = pool.begin().await?;
+                for (index, write) in writes.iter().enumerate() {
+                    let result = match write {
+                        ContactWrite::Create(new) => insert_sqlite(&mut *tx, new, now).await,
+                        ContactWrite::Update(contact) => {
+                            let contact = versions.current(contact);
+                            let now = contact.next_version(now);
+                            versions.set(contact.id, now);
+                            update_sqlite(&mut *tx, &contact, now, Some(contact.updated_at))
+                                .await
+                                .and_then(|updated| written(&contact, updated, now))
+                        }
+                        ContactWrite::Delete(contact) => {
+                            let contact = versions.current(contact);
+                            delete_sqlite(&mut *tx, contact.id, Some(contact.updated_at))
+                                .await
+                                .and_then(|deleted| written(&contact, deleted, contact.updated_at))
+                        }
+                    };
+                    match batch_result(index, result)? {
+                        Ok(contact) => applied.push(contact),
+                        Err(failure) => return Ok(Err(failure)), // Dropping `tx` rolls it back
+                    }
+                }
+                tx.commit().await?;
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => {
+                let mut tx = pool.begin().await?;
+                for (index, write) in writes.iter().enumerate() {
+                    let result = match write {
+                        ContactWrite::Create(new) => postgres::create_contact(&mut *tx, new, now).await,
+                        ContactWrite::Update(contact) => {
+                            let contact = versions.current(contact);
+                            let now = contact.next_version(now);
+                            versions.set(contact.id, now);
+                            postgres::update_contact(&mut *tx, &contact, now, Some(contact.updated_at))
+                                .await
+                                .and_then(|updated| written(&contact, updated, now))
+                        }
+                        ContactWrite::Delete(contact) => {
+                            let contact = versions.current(contact);
+                            postgres::delete_contact(&mut *tx, contact.id, Some(contact.updated_at))
+                                .await
+                                .and_then(|deleted| written(&contact, deleted, contact.updated_at))
+                        }
+                    };
+                    match batch_result(index, result)? {
+                        Ok(contact) => applied.push(contact),
+                        Err(failure) => return Ok(Err(failure)),
+                    }
+                }
+                tx.commit().await?;
+            }
+        }
+        Ok(Ok(applied))
+    }
+
+    /// Stores `contact` again with its own id and timestamps, for a contact taken out of the
+    /// trash. Gives back the field (`email` or `phone_number`) another contact has taken since,
+    /// if any, and then stores nothing.
+    #[instrument(name = "contacts.restore", skip_all, fields(binds = %shapes(&[&contact.id])))]
+    pub async fn restore(db: &Db, contact: &Contact) -> Result<Result<(), &'static str>, sqlx::Error> {
+        let result = match db {
+            Db::Sqlite(pool) => {
+                let created_at = contact.created_at.format(CREATED_AT_FORMAT).to_string();
+                let updated_at = contact.updated_at.format(UPDATED_AT_FORMAT).to_string();
+                let search_text = contact.search_text();
+                sqlx::query!(
+                    r#"INSERT INTO contacts (id, first_name, last_name, phone_number, email, company, job_title,
+                        search_text, created_at, updated_at)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+                    contact.id,
+                    contact.first_name,
+                    contact.last_name,
+                    contact.phone_number,
+                    contact.email,
+                    contact.company,
+                    contact.job_title,
+                    search_text,
+                    created_at,
+                    updated_at,
+                )
+                .execute(pool)
+                .await
+                .map(|_| ())
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::restore_contact(pool, contact).await,
+        };
+        match result {
+            Ok(()) => Ok(Ok(())),
+            Err(e) => db::conflicting_field(&e).map(Err).ok_or(e),
+        }
+    }
+
+    /// One page of every contact in `sort` order: `search` with an empty filter. With an
+    /// `after` cursor the page starts after that contact and `page` is ignored.
+    #[instrument(name = "contacts.get_all", skip_all, fields(binds = %shapes(&[&sort.sort_param(), &per_page, &page])))]
+    pub async fn get_all(
+        db: &Db,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Result<Vec<Contact>, sqlx::Error> {
+        Self::search(db, &ContactFilter::default(), page, per_page, sort, after).await
+    }
+
+    /// Every contact in the table, used for exports.
+    #[instrument(name = "contacts.all", skip_all)]
+    pub async fn all(db: &Db) -> Result<Vec<Contact>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    Contact,
+                    r#"SELECT id, first_name, last_name, phone_number, email, company, job_title,
+                        created_at AS "created_at: _", updated_at AS "updated_at: _"
+                    FROM contacts ORDER BY id"#
+                )
+                .fetch_all(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::all_contacts(pool).await,
+        }
+    }
+
+    /// Fills in `search_text` for the contacts without it: ones from before the column, or
+    /// from before a migration reset it for a change in the folding. Returns how many there
+    /// were.
+    #[instrument(name = "contacts.fill_search_text", skip_all)]
+    pub async fn fill_search_text(db: &Db) -> Result<usize, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                let mut tx = pool.begin().await?;
+                let contacts = sqlx::query_as!(
+                    Contact,
+                    r#"SELECT id, first_name, last_name, phone_number, email, company, job_title,
+                        created_at AS "created_at: _", updated_at AS "updated_at: _"
+                    FROM contacts WHERE search_text IS NULL"#
+                )
+                .fetch_all(&mut *tx)
+                .await?;
+                for contact in &contacts {
+                    let search_text = contact.search_text();
+                    sqlx::query!("UPDATE contacts SET search_text = ? WHERE id = ?", search_text, contact.id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                tx.commit().await?;
+                Ok(contacts.len())
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::fill_search_text(pool).await,
+        }
+    }
+
+    /// Every contact, ordered by id, streamed from the database as rows come in.
+    pub fn stream_all(db: &Db) -> ContactStream<'_> {
+        match db {
+            Db::Sqlite(pool) => sqlx::query_as!(
+                Contact,
+                r#"SELECT id, first_name, last_name, phone_number, email, company, job_title,
+                    created_at AS "created_at: _", updated_at AS "updated_at: _"
+                FROM contacts ORDER BY id"#
             )
-            .fetch_all(pool)
-            .await
+            .fetch(pool),
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::stream_all_contacts(pool),
+        }
+    }
+
+    /// The `limit` most recently created contacts, newest first.
+    #[instrument(name = "contacts.newest", skip_all)]
+    pub async fn newest(db: &Db, limit: i64) -> Result<Vec<Contact>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    Contact,
+                    r#"SELECT id AS "id!", first_name, last_name, phone_number, email, company, job_title,
+                        created_at AS "created_at: _", updated_at AS "updated_at: _"
+                    FROM contacts
+                    ORDER BY created_at DESC, id DESC
+                    LIMIT ?"#,
+                    limit,
+                )
+                .fetch_all(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::recently_added_contacts(pool, limit).await,
+        }
+    }
+
+    /// Every contact with a company, ordered by company and then name, for `Company::group`.
+    #[instrument(name = "contacts.with_company", skip_all)]
+    pub async fn with_company(db: &Db) -> Result<Vec<Contact>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    Contact,
+                    r#"SELECT id AS "id!", first_name, last_name, phone_number, email, company, job_title,
+                        created_at AS "created_at: _", updated_at AS "updated_at: _"
+                    FROM contacts
+                    WHERE company <> ''
+                    ORDER BY company, last_name, first_name, id"#
+                )
+                .fetch_all(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::contacts_with_company(pool).await,
+        }
+    }
+
+    #[instrument(name = "contacts.find_by_id", skip_all, fields(binds = %shapes(&[&id])))]
+    pub async fn find_by_id(db: &Db, id: i64) -> Result<Self, ContactError> {
+        let contact = match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    Contact,
+                    r#"SELECT id, first_name, last_name, phone_number, email, company, job_title,
+                        created_at AS "created_at: _", updated_at AS "updated_at: _"
+                    FROM contacts WHERE id = ?"#,
+                    id
+                )
+                .fetch_one(pool)
+                    .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::find_contact(pool, id).await,
+        };
+        Ok(contact?)
+    }
+
+    /// The contact with this email, ignoring case like the unique index does, the oldest one
+    /// if several share it.
+    #[instrument(name = "contacts.find_by_email", skip_all, fields(binds = %shapes(&[&email])))]
+    pub async fn find_by_email(db: &Db, email: &str) -> Result<Option<Self>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    Contact,
+                    r#"SELECT id, first_name, last_name, phone_number, email, company, job_title,
+                        created_at AS "created_at: _", updated_at AS "updated_at: _"
+                    FROM contacts WHERE lower(email) = lower(?) ORDER BY id LIMIT 1"#,
+                    email
+                )
+                .fetch_optional(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::find_contact_by_email(pool, email).await,
+        }
     }
 
-    pub async fn find_by_id(pool: &SqlitePool, id: i64) -> Result<Self, sqlx::Error> {
-        sqlx::query_as!(Contact, "SELECT * FROM contacts WHERE id = ?", id)
-            .fetch_one(pool)
-            .await
+    /// Contacts matching `filter`, a page at a time in `sort` order. Unset parts of the filter
+    /// are bound as `NULL`, which turns their condition off. With an `after` cursor the page
+    /// starts after that contact and `page` is ignored. See `search_rows` for the query.
+    #[instrument(name = "contacts.search", skip_all, fields(binds = %shapes(&[&filter.text, &sort.sort_param(), &per_page, &page])))]
+    pub async fn search(
+        db: &Db,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let (contacts, _) = Self::search_rows(db, filter, page, per_page, sort, after, false).await?;
+        Ok(contacts)
     }
 
-    pub async fn search(pool: &SqlitePool, search: &str, page: i64, per_page: i64) -> Result<Vec<Contact>, sqlx::Error> {
-        let pattern = format!("%{}%", search);
-        let offset = (page - 1) * per_page;
-        sqlx::query_as!(
-            Contact,
-            "SELECT * FROM contacts WHERE first_name LIKE ? OR last_name LIKE ? LIMIT ? OFFSET ?",
-            pattern,
-            pattern,
-            per_page,
-            offset,
-        )
-        .fetch_all(pool)
-        .await
+    /// `search` along with the number of contacts matching `filter` in all, for the pager.
+    /// Without a cursor the total is a `COUNT(*) OVER ()` in the same query, so the first page
+    /// of a new search is one round trip. After a cursor that would only count the rows past
+    /// it, and a page past the end has no row to carry it, so those take a separate `count`,
+    /// which `CachedContactRepository` keeps for every page of the filter.
+    #[instrument(name = "contacts.search_page", skip_all, fields(binds = %shapes(&[&filter.text, &sort.sort_param(), &per_page, &page])))]
+    pub async fn search_page(
+        db: &Db,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Result<ContactPage, sqlx::Error> {
+        let (contacts, total) = Self::search_rows(db, filter, page, per_page, sort, after, after.is_none()).await?;
+        let total = match total {
+            Some(total) => total,
+            None => Self::count(db, filter).await?,
+        };
+        Ok(ContactPage { contacts, total })
     }
 
-    pub async fn validate_email(pool: &SqlitePool, email: &str) -> Result<bool, sqlx::Error> {
+    /// The query behind `search` and `search_page`, with `COUNT(*) OVER ()` on every row if
+    /// `with_total` is set.
+    ///
+    /// The `ORDER BY` and the cursor condition name the sorted columns themselves (see
+    /// `ContactOrder::terms`), so with the indexes on them a page is read off an index instead
+    /// of sorting every match. That means splicing the column names into the SQL, which keeps
+    /// this query from being checked at compile time like the others; they only ever come from
+    /// `ContactSort`.
+    async fn search_rows(
+        db: &Db,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+        with_total: bool,
+    ) -> Result<(Vec<Self>, Option<i64>), sqlx::Error> {
+        let offset = if after.is_some() { 0 } else { (page - 1) * per_page };
+        match db {
+            Db::Sqlite(pool) => {
+                // SQLite compares the text `created_at` as is, it's stored as the cursor has it
+                let after_condition = match after {
+                    Some(_) => sort.after_condition(|column| column.as_str(), |column, i| match column {
+                        ContactSort::Id => format!("CAST(?{} AS INTEGER)", i + 9),
+                        _ => format!("?{}", i + 9),
+                    }),
+                    None => "TRUE".to_string(),
+                };
+                let sql = format!(
+                    "SELECT id, first_name, last_name, phone_number, email, company, job_title, created_at, updated_at,
+                        {} AS total
+                    FROM contacts
+                    WHERE (?1 IS NULL OR search_text LIKE ?1
+                        OR REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(phone_number, ' ', ''), '-', ''), '(', ''), ')', ''), '.', ''), '+', ''), '/', '') LIKE ?6)
+                      AND (?2 IS NULL OR created_at >= ?2)
+                      AND (?3 IS NULL OR created_at < ?3)
+                      AND (?4 IS NULL OR LOWER(SUBSTR(email, INSTR(email, '@') + 1)) = ?4)
+                      AND (?5 IS NULL OR last_name LIKE ?5)
+                      AND {after_condition}
+                    ORDER BY {}
+                    LIMIT ?7 OFFSET ?8",
+                    if with_total { "COUNT(*) OVER ()" } else { "NULL" },
+                    sort.order_by(|column| column.as_str()),
+                );
+                let (created_after, created_before) = filter.created_bounds();
+                let mut query = sqlx::query_as::<_, CountedContact>(&sql)
+                    .bind(filter.pattern())
+                    .bind(created_after)
+                    .bind(created_before)
+                    .bind(filter.email_domain.as_deref())
+                    .bind(filter.initial_pattern())
+                    .bind(filter.phone_pattern())
+                    .bind(per_page)
+                    .bind(offset);
+                for value in after.map(ContactCursor::values).unwrap_or_default() {
+                    query = query.bind(value);
+                }
+                query.fetch_all(pool).await.map(CountedContact::split)
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::search_contacts(pool, filter, sort, per_page, offset, after, with_total).await,
+        }
+    }
+
+    /// Number of contacts matching `filter`, all of them for an empty one. Used for the page
+    /// count in the list views.
+    #[instrument(name = "contacts.count", skip_all)]
+    pub async fn count(db: &Db, filter: &ContactFilter) -> Result<i64, sqlx::Error> {
+        let pattern = filter.pattern();
+        let (created_after, created_before) = filter.created_bounds();
+        let email_domain = filter.email_domain.as_deref();
+        let phone_pattern = filter.phone_pattern();
+        let initial = filter.initial_pattern();
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_scalar!(
+                    r#"SELECT COUNT(*) FROM contacts
+                    WHERE (?1 IS NULL OR search_text LIKE ?1
+                        OR REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(phone_number, ' ', ''), '-', ''), '(', ''), ')', ''), '.', ''), '+', ''), '/', '') LIKE ?6)
+                      AND (?2 IS NULL OR created_at >= ?2)
+                      AND (?3 IS NULL OR created_at < ?3)
+                      AND (?4 IS NULL OR LOWER(SUBSTR(email, INSTR(email, '@') + 1)) = ?4)
+                      AND (?5 IS NULL OR last_name LIKE ?5)"#,
+                    pattern,
+                    created_after,
+                    created_before,
+                    email_domain,
+                    initial,
+                    phone_pattern,
+                )
+                .fetch_one(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::count_contacts(pool, filter).await,
+        }
+    }
+
+    /// Number of contacts matching `filter` per last name initial, for the A–Z bar. The
+    /// filter's own initial is ignored, so the bar shows every letter the rest of it allows.
+    #[instrument(name = "contacts.letter_counts", skip_all)]
+    pub async fn letter_counts(db: &Db, filter: &ContactFilter) -> Result<Vec<LetterCount>, sqlx::Error> {
+        let pattern = filter.pattern();
+        let (created_after, created_before) = filter.created_bounds();
+        let email_domain = filter.email_domain.as_deref();
+        let phone_pattern = filter.phone_pattern();
+        let counts: Vec<(String, i64)> = match db {
+            Db::Sqlite(pool) => {
+                sqlx::query!(
+                    r#"SELECT UPPER(SUBSTR(last_name, 1, 1)) AS "initial!: String", COUNT(*) AS "count!: i64"
+                    FROM contacts
+                    WHERE (?1 IS NULL OR search_text LIKE ?1
+                        OR REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(phone_number, ' ', ''), '-', ''), '(', ''), ')', ''), '.', ''), '+', ''), '/', '') LIKE ?5)
+                      AND (?2 IS NULL OR created_at >= ?2)
+                      AND (?3 IS NULL OR created_at < ?3)
+                      AND (?4 IS NULL OR LOWER(SUBSTR(email, INSTR(email, '@') + 1)) = ?4)
+                    GROUP BY 1"#,
+                    pattern,
+                    created_after,
+                    created_before,
+                    email_domain,
+                    phone_pattern,
+                )
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .map(|row| (row.initial, row.count))
+                .collect()
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::letter_counts(pool, &filter.without_initial()).await?,
+        };
+        Ok(LetterCount::fill(&counts))
+    }
+
+    /// `true` if a contact other than `exclude_id` (the one being edited, if any) has `email`,
+    /// ignoring case.
+    #[instrument(name = "contacts.validate_email", skip_all, fields(binds = %shapes(&[&email, &exclude_id])))]
+    pub async fn validate_email(db: &Db, email: &str, exclude_id: Option<i64>) -> Result<bool, sqlx::Error> {
         // Check if there is an entry in the database.
-        let exists: bool = sqlx::query_scalar!(
-            "SELECT EXISTS(SELECT 1 FROM contacts where email = ?)",
-            email
-        )
-        .fetch_one(pool)
-        .await?
-            == 1;
+        let exists: bool = match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_scalar!(
+                    "SELECT EXISTS(SELECT 1 FROM contacts where lower(email) = lower(?1) AND (?2 IS NULL OR id <> ?2))",
+                    email,
+                    exclude_id
+                )
+                .fetch_one(pool)
+                .await?
+                    == 1
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::email_exists(pool, email, exclude_id).await?,
+        };
 
         Ok(exists)
     }
 
+    /// `true` if a contact other than `exclude_id` has `phone_number`, compared without its
+    /// punctuation the way the unique index on it does, so a number that would be refused when
+    /// saved is reported here already.
+    #[instrument(name = "contacts.validate_phone", skip_all, fields(binds = %shapes(&[&phone_number, &exclude_id])))]
     pub async fn validate_phone(
-        pool: &SqlitePool,
+        db: &Db,
         phone_number: &str,
+        exclude_id: Option<i64>,
     ) -> Result<bool, sqlx::Error> {
         // Check if there is an entry in the database.
-        let exists: bool = sqlx::query_scalar!(
-            "SELECT EXISTS(SELECT 1 FROM contacts where phone_number = ?)",
-            phone_number
-        )
-        .fetch_one(pool)
-        .await?
-            == 1;
+        let exists: bool = match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_scalar!(
+                    r#"SELECT EXISTS(SELECT 1 FROM contacts
+                    WHERE phone_number <> ''
+                      AND REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(phone_number, ' ', ''), '-', ''), '(', ''), ')', ''), '.', ''), '+', ''), '/', '')
+                        = REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(REPLACE(?1, ' ', ''), '-', ''), '(', ''), ')', ''), '.', ''), '+', ''), '/', '')
+                      AND (?2 IS NULL OR id <> ?2)) AS "exists!""#,
+                    phone_number,
+                    exclude_id
+                )
+                .fetch_one(pool)
+                .await?
+                    == 1
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => {
+                postgres::phone_exists(pool, phone_number, exclude_id).await?
+            }
+        };
 
         Ok(exists)
     }
 }
+
+async fn insert_sqlite<'e>(
+    executor: impl sqlx::SqliteExecutor<'e>,
+    new: &NewContact,
+    now: DateTime<Utc>,
+) -> Result<Contact, sqlx::Error> {
+    let created_at = now.format(CREATED_AT_FORMAT).to_string();
+    let updated_at = now.format(UPDATED_AT_FORMAT).to_string();
+    let search_text = new.search_text();
+    sqlx::query_as!(
+        Contact,
+        r#"INSERT INTO contacts (first_name, last_name, phone_number, email, company, job_title, search_text,
+            created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        RETURNING id, first_name, last_name, phone_number, email, company, job_title,
+            created_at AS "created_at: _", updated_at AS "updated_at: _""#,
+        new.first_name,
+        new.last_name,
+        new.phone_number,
+        new.email,
+        new.company,
+        new.job_title,
+        search_text,
+        created_at,
+        updated_at,
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// Updates the contact's row, only if its `updated_at` is still `version` when that is set.
+/// Stored timestamps can lack the milliseconds, so the column is compared in `strftime`'s
+/// `%f` form, which is `UPDATED_AT_FORMAT`'s.
+async fn update_sqlite<'e>(
+    executor: impl sqlx::SqliteExecutor<'e>,
+    contact: &Contact,
+    now: DateTime<Utc>,
+    version: Option<DateTime<Utc>>,
+) -> Result<u64, sqlx::Error> {
+    let updated_at = now.format(UPDATED_AT_FORMAT).to_string();
+    let version = version.map(|version| version.format(UPDATED_AT_FORMAT).to_string());
+    let search_text = contact.search_text();
+    sqlx::query!(
+        r#"UPDATE contacts
+        SET first_name = ?, last_name = ?, phone_number = ?, email = ?, company = ?, job_title = ?,
+            search_text = ?, updated_at = ?
+        WHERE id = ? AND (?10 IS NULL OR strftime('%Y-%m-%d %H:%M:%f', updated_at) = ?10)"#,
+        contact.first_name,
+        contact.last_name,
+        contact.phone_number,
+        contact.email,
+        contact.company,
+        contact.job_title,
+        search_text,
+        updated_at,
+        contact.id,
+        version,
+    )
+    .execute(executor)
+    .await
+    .map(|result| result.rows_affected())
+}
+
+/// Deletes the contact's row, only if its `updated_at` is still `version` when that is set,
+/// like `update_sqlite`.
+async fn delete_sqlite<'e>(
+    executor: impl sqlx::SqliteExecutor<'e>,
+    id: i64,
+    version: Option<DateTime<Utc>>,
+) -> Result<u64, sqlx::Error> {
+    let version = version.map(|version| version.format(UPDATED_AT_FORMAT).to_string());
+    sqlx::query!(
+        "DELETE FROM contacts WHERE id = ?1 AND (?2 IS NULL OR strftime('%Y-%m-%d %H:%M:%f', updated_at) = ?2)",
+        id,
+        version
+    )
+    .execute(executor)
+    .await
+    .map(|result| result.rows_affected())
+}
+
+/// `contact` as an `apply_batch` update or delete left it, or `RowNotFound` if no row was
+/// `changed` because it isn't at that version any more.
+fn written(contact: &Contact, changed: u64, updated_at: DateTime<Utc>) -> Result<Contact, sqlx::Error> {
+    if changed == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(Contact {
+        updated_at,
+        ..contact.clone()
+    })
+}
+
+/// An `apply_batch` change's outcome: the contact, the `BatchFailure` to roll back with, or
+/// any other error.
+fn batch_result(
+    index: usize,
+    result: Result<Contact, sqlx::Error>,
+) -> Result<Result<Contact, BatchFailure>, sqlx::Error> {
+    let reason = match result {
+        Ok(contact) => return Ok(Ok(contact)),
+        Err(sqlx::Error::RowNotFound) => BatchFailureReason::Changed,
+        Err(e) => match db::conflicting_field(&e) {
+            Some(field) => BatchFailureReason::Taken(field),
+            None => return Err(e),
+        },
+    };
+    Ok(Err(BatchFailure { index, reason }))
+}
+
+/// Keeps a `create_many` insert, or rolls it back if another contact has its email or phone
+/// number.
+async fn finish_savepoint<DB: sqlx::Database>(
+    savepoint: sqlx::Transaction<'_, DB>,
+    result: Result<Contact, sqlx::Error>,
+) -> Result<Result<Contact, &'static str>, sqlx::Error> {
+    match result {
+        Ok(contact) => {
+            savepoint.commit().await?;
+            Ok(Ok(contact))
+        }
+        Err(e) => match db::conflicting_field(&e) {
+            Some(field) => {
+                savepoint.rollback().await?;
+                Ok(Err(field))
+            }
+            None => Err(e),
+        },
+    }
+}
@@ -0,0 +1,245 @@
+//! Labeled dates of a contact beyond the ones on the contact itself, like "anniversary",
+//! "contract renewal" or "follow up". A yearly date comes back every year, the others happen
+//! once. They are listed on the contact's page, and the upcoming ones of every contact on
+//! `/contacts/dates`.
+
+use chrono::{Datelike, NaiveDate};
+use serde::Serialize;
+use tracing::instrument;
+
+#[cfg(feature = "postgres")]
+use crate::postgres;
+use crate::{
+    db::{Db, shapes},
+    preferences,
+};
+
+/// Longest label accepted, in characters.
+pub const MAX_LABEL_LEN: usize = 50;
+
+/// Days ahead the upcoming dates page looks by default, and at most.
+pub const UPCOMING_DAYS: u32 = 30;
+pub const MAX_UPCOMING_DAYS: u32 = 366;
+
+/// A date of a contact, with the contact's name.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, sqlx::FromRow)]
+pub struct ContactDate {
+    pub id: i64,
+    pub contact_id: i64,
+    pub first_name: String,
+    pub last_name: String,
+    pub label: String,
+    pub date: NaiveDate,
+    pub yearly: bool,
+}
+
+impl ContactDate {
+    /// The contact's name in the current request's preferred format.
+    pub fn name(&self) -> String {
+        preferences::current().name(&self.first_name, &self.last_name)
+    }
+
+    /// When the date is next on or after `today`: itself for a one-off date, and this year's or
+    /// next year's for a yearly one, which only starts coming back after its own date.
+    /// February 29th falls on the 28th in other years. `None` for a one-off date that has
+    /// passed.
+    pub fn next(&self, today: NaiveDate) -> Option<NaiveDate> {
+        if !self.yearly || self.date >= today {
+            return (self.date >= today).then_some(self.date);
+        }
+        let in_year = |year: i32| {
+            NaiveDate::from_ymd_opt(year, self.date.month(), self.date.day())
+                .or_else(|| NaiveDate::from_ymd_opt(year, 2, 28))
+        };
+        in_year(today.year())
+            .filter(|date| *date >= today)
+            .or_else(|| in_year(today.year() + 1))
+    }
+
+    /// The dates of `dates` that are next within `days` days of `today`, soonest first.
+    pub fn upcoming(dates: Vec<ContactDate>, today: NaiveDate, days: u32) -> Vec<UpcomingDate> {
+        let mut upcoming: Vec<UpcomingDate> = dates
+            .into_iter()
+            .filter_map(|date| {
+                let on = date.next(today)?;
+                let days_left = (on - today).num_days();
+                (days_left <= i64::from(days)).then_some(UpcomingDate { date, on, days_left })
+            })
+            .collect();
+        upcoming.sort_by(|a, b| {
+            (a.on, &a.date.label, &a.date.last_name, &a.date.first_name, a.date.id)
+                .cmp(&(b.on, &b.date.label, &b.date.last_name, &b.date.first_name, b.date.id))
+        });
+        upcoming
+    }
+
+    /// The dates of contact `id`, in the order they fall in the year, and then by label.
+    #[instrument(name = "dates.for_contact", skip_all, fields(binds = %shapes(&[&id])))]
+    pub async fn for_contact(db: &Db, id: i64) -> Result<Vec<ContactDate>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    ContactDate,
+                    r#"SELECT d.id AS "id!", d.contact_id, c.first_name, c.last_name, d.label,
+                        d.date AS "date: NaiveDate", d.yearly AS "yearly: bool"
+                    FROM contact_dates d
+                    JOIN contacts c ON c.id = d.contact_id
+                    WHERE d.contact_id = ?
+                    ORDER BY d.date, d.label, d.id"#,
+                    id,
+                )
+                .fetch_all(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::contact_dates(pool, Some(id)).await,
+        }
+    }
+
+    /// The dates of every contact, for picking the upcoming ones.
+    #[instrument(name = "dates.all", skip_all)]
+    pub async fn all(db: &Db) -> Result<Vec<ContactDate>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    ContactDate,
+                    r#"SELECT d.id AS "id!", d.contact_id, c.first_name, c.last_name, d.label,
+                        d.date AS "date: NaiveDate", d.yearly AS "yearly: bool"
+                    FROM contact_dates d
+                    JOIN contacts c ON c.id = d.contact_id
+                    ORDER BY d.date, d.label, d.id"#,
+                )
+                .fetch_all(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::contact_dates(pool, None).await,
+        }
+    }
+
+    /// Gives contact `contact_id` the date `date` as `label`. Returns the new date's id.
+    #[instrument(name = "dates.add", skip_all, fields(binds = %shapes(&[&contact_id, &label, &date, &yearly])))]
+    pub async fn add(db: &Db, contact_id: i64, label: &str, date: NaiveDate, yearly: bool) -> Result<i64, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_scalar!(
+                    r#"INSERT INTO contact_dates (contact_id, label, date, yearly)
+                    VALUES (?, ?, ?, ?)
+                    RETURNING id AS "id!""#,
+                    contact_id,
+                    label,
+                    date,
+                    yearly,
+                )
+                .fetch_one(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::add_contact_date(pool, contact_id, label, date, yearly).await,
+        }
+    }
+
+    /// Removes date `id` if it is contact `contact_id`'s. Returns whether there was one.
+    #[instrument(name = "dates.remove", skip_all, fields(binds = %shapes(&[&id, &contact_id])))]
+    pub async fn remove(db: &Db, contact_id: i64, id: i64) -> Result<bool, sqlx::Error> {
+        let removed = match db {
+            Db::Sqlite(pool) => {
+                sqlx::query!("DELETE FROM contact_dates WHERE id = ? AND contact_id = ?", id, contact_id)
+                    .execute(pool)
+                    .await?
+                    .rows_affected()
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::remove_contact_date(pool, contact_id, id).await?,
+        };
+        Ok(removed > 0)
+    }
+}
+
+/// A date as it next comes up, for the upcoming dates page.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct UpcomingDate {
+    pub date: ContactDate,
+    /// The day it is next on.
+    pub on: NaiveDate,
+    /// Days from today until `on`, 0 for today.
+    pub days_left: i64,
+}
+
+impl UpcomingDate {
+    /// How many years a yearly date has been coming back for, like the 10 of a 10th
+    /// anniversary. 0 for one-off dates and the first time.
+    pub fn years(&self) -> i32 {
+        if self.date.yearly { self.on.year() - self.date.date.year() } else { 0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(date: &str, yearly: bool) -> ContactDate {
+        ContactDate {
+            id: 1,
+            contact_id: 1,
+            first_name: "Jane".into(),
+            last_name: "Doe".into(),
+            label: "anniversary".into(),
+            date: date.parse().unwrap(),
+            yearly,
+        }
+    }
+
+    fn day(day: &str) -> NaiveDate {
+        day.parse().unwrap()
+    }
+
+    fn years(contact_date: &ContactDate, today: &str) -> i32 {
+        let on = contact_date.next(day(today)).unwrap();
+        UpcomingDate { date: contact_date.clone(), on, days_left: (on - day(today)).num_days() }.years()
+    }
+
+    #[test]
+    fn one_off_dates_happen_once() {
+        let renewal = date("2026-11-01", false);
+        assert_eq!(renewal.next(day("2026-10-15")), Some(day("2026-11-01")));
+        assert_eq!(renewal.next(day("2026-11-01")), Some(day("2026-11-01")));
+        assert_eq!(renewal.next(day("2026-11-02")), None);
+        assert_eq!(years(&renewal, "2026-10-15"), 0);
+    }
+
+    #[test]
+    fn yearly_dates_come_back_this_year_or_next() {
+        let anniversary = date("2015-06-20", true);
+        assert_eq!(anniversary.next(day("2026-06-20")), Some(day("2026-06-20")));
+        assert_eq!(anniversary.next(day("2026-06-21")), Some(day("2027-06-20")));
+        assert_eq!(years(&anniversary, "2026-06-01"), 11);
+        assert_eq!(years(&anniversary, "2026-10-15"), 12);
+    }
+
+    #[test]
+    fn february_29th_falls_on_the_28th_in_other_years() {
+        let leap = date("2024-02-29", true);
+        assert_eq!(leap.next(day("2026-01-01")), Some(day("2026-02-28")));
+        assert_eq!(leap.next(day("2026-03-01")), Some(day("2027-02-28")));
+        assert_eq!(leap.next(day("2027-03-01")), Some(day("2028-02-29")));
+        assert_eq!(years(&leap, "2027-03-01"), 4);
+    }
+
+    #[test]
+    fn yearly_dates_in_the_future_start_on_their_own_date() {
+        let wedding = date("2030-05-01", true);
+        assert_eq!(wedding.next(day("2026-10-15")), Some(day("2030-05-01")));
+        assert_eq!(years(&wedding, "2026-10-15"), 0);
+        assert_eq!(wedding.next(day("2030-05-02")), Some(day("2031-05-01")));
+        assert_eq!(years(&wedding, "2030-05-02"), 1);
+    }
+
+    #[test]
+    fn upcoming_keeps_the_dates_within_the_window_soonest_first() {
+        let dates = vec![date("2015-11-20", true), date("2026-10-20", false), date("2026-09-01", false)];
+        let upcoming = ContactDate::upcoming(dates, day("2026-10-15"), 30);
+        let on: Vec<(NaiveDate, i64)> = upcoming.iter().map(|u| (u.on, u.days_left)).collect();
+        assert_eq!(on, [(day("2026-10-20"), 5)]);
+    }
+}
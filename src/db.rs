@@ -0,0 +1,452 @@
+use std::{future::Future, str::FromStr, time::Duration};
+
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+#[cfg(feature = "postgres")]
+use sqlx::{
+    PgPool,
+    postgres::{PgConnectOptions, PgPoolOptions},
+};
+use sqlx::{
+    ConnectOptions, Connection, SqlitePool,
+    error::DatabaseError,
+    sqlite::{SqliteConnectOptions, SqliteError, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
+};
+
+use crate::config::DatabaseConfig;
+
+/// Per-connection statement settings for the pool. Every statement is logged at `statements`
+/// level, and anything that runs longer than `slow_threshold` is logged at WARN so it stands
+/// out from the rest. Each connection keeps up to `cache_capacity` prepared statements, so
+/// the hot list and validation queries are only parsed and planned once per connection.
+#[derive(Debug, Clone)]
+pub struct StatementSettings {
+    pub statements: LevelFilter,
+    pub slow_threshold: Duration,
+    pub cache_capacity: usize,
+}
+
+impl From<&DatabaseConfig> for StatementSettings {
+    fn from(config: &DatabaseConfig) -> Self {
+        Self {
+            statements: config.sql_log,
+            slow_threshold: Duration::from_millis(config.slow_query_ms),
+            cache_capacity: config.statement_cache_capacity,
+        }
+    }
+}
+
+/// Connection pool sizing. SQLite only ever has one writer, so more connections help reads
+/// but not writes: writers beyond the first wait for the lock (see `retry_busy`), and requests
+/// wait up to `acquire_timeout` for a free connection. Connections idle for `idle_timeout` are
+/// closed, `None` keeps them open.
+#[derive(Debug, Clone)]
+pub struct PoolSettings {
+    pub max_connections: u32,
+    pub acquire_timeout: Duration,
+    pub idle_timeout: Option<Duration>,
+}
+
+impl From<&DatabaseConfig> for PoolSettings {
+    fn from(config: &DatabaseConfig) -> Self {
+        Self {
+            max_connections: config.max_connections,
+            acquire_timeout: Duration::from_secs(config.acquire_timeout_secs),
+            idle_timeout: (config.idle_timeout_secs > 0).then(|| Duration::from_secs(config.idle_timeout_secs)),
+        }
+    }
+}
+
+/// SQLite's journal, `database.journal_mode`. WAL lets reads go on while a write is under way;
+/// the others block them, and are only for file systems WAL doesn't work on, like network
+/// shares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalMode {
+    Wal,
+    Delete,
+    Truncate,
+    Persist,
+}
+
+/// How often SQLite waits for writes to reach the disk, `database.synchronous`. `normal` is
+/// safe with WAL: a power cut can lose the last commits but never corrupts the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+/// The pragmas every SQLite connection is opened with. `busy_timeout` is how long a statement
+/// waits for another connection's write lock before failing with `SQLITE_BUSY`, after which
+/// `retry_busy` backs off and tries again.
+#[derive(Debug, Clone)]
+pub struct SqlitePragmas {
+    pub journal_mode: JournalMode,
+    pub synchronous: Synchronous,
+    pub busy_timeout: Duration,
+    pub foreign_keys: bool,
+}
+
+impl From<&DatabaseConfig> for SqlitePragmas {
+    fn from(config: &DatabaseConfig) -> Self {
+        Self {
+            journal_mode: config.journal_mode,
+            synchronous: config.synchronous,
+            busy_timeout: Duration::from_millis(config.busy_timeout_ms),
+            foreign_keys: config.foreign_keys,
+        }
+    }
+}
+
+impl SqlitePragmas {
+    /// `options` with the pragmas set. The journal mode is left out for in-memory databases,
+    /// which don't have one on disk.
+    fn apply(&self, options: SqliteConnectOptions, in_memory: bool) -> SqliteConnectOptions {
+        let options = options
+            .synchronous(match self.synchronous {
+                Synchronous::Off => SqliteSynchronous::Off,
+                Synchronous::Normal => SqliteSynchronous::Normal,
+                Synchronous::Full => SqliteSynchronous::Full,
+                Synchronous::Extra => SqliteSynchronous::Extra,
+            })
+            .busy_timeout(self.busy_timeout)
+            .foreign_keys(self.foreign_keys);
+        if in_memory {
+            return options;
+        }
+        options.journal_mode(match self.journal_mode {
+            JournalMode::Wal => SqliteJournalMode::Wal,
+            JournalMode::Delete => SqliteJournalMode::Delete,
+            JournalMode::Truncate => SqliteJournalMode::Truncate,
+            JournalMode::Persist => SqliteJournalMode::Persist,
+        })
+    }
+}
+
+/// `database.url` for demo mode: contacts are kept in a `MemoryContactRepository` and
+/// everything else in a throwaway in-memory SQLite database.
+pub const MEMORY_URL: &str = "memory:";
+
+/// Connection pool figures, see `Db::pool_stats`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolStats {
+    pub connections: u32,
+    pub idle_connections: usize,
+    pub cached_statements: usize,
+}
+
+/// The database the app runs against. SQLite is always available, Postgres only when the
+/// crate is built with the `postgres` feature. Which one is used is picked from the scheme of
+/// the database URL.
+#[derive(Debug, Clone)]
+pub enum Db {
+    Sqlite(SqlitePool),
+    #[cfg(feature = "postgres")]
+    Postgres(PgPool),
+}
+
+impl Db {
+    /// Connects to the database with statement logging (and for SQLite, `pragmas`) applied to
+    /// every connection in the pool, sized by `pool_settings`, and runs any pending migrations. For SQLite the database file is created if it
+    /// doesn't exist yet, so a fresh `DATABASE_URL` is all it takes to get going.
+    pub async fn connect(
+        database_url: &str,
+        settings: &StatementSettings,
+        pool_settings: &PoolSettings,
+        pragmas: &SqlitePragmas,
+    ) -> anyhow::Result<Self> {
+        if database_url.starts_with("postgres:") || database_url.starts_with("postgresql:") {
+            return Self::connect_postgres(database_url, settings, pool_settings).await;
+        }
+        if database_url == MEMORY_URL {
+            return Self::connect_memory(settings, pool_settings, pragmas).await;
+        }
+
+        let options = SqliteConnectOptions::from_str(database_url)?.create_if_missing(true);
+        let options = pragmas
+            .apply(options, false)
+            .log_statements(settings.statements)
+            .log_slow_statements(LevelFilter::Warn, settings.slow_threshold)
+            .statement_cache_capacity(settings.cache_capacity);
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(pool_settings.max_connections)
+            .acquire_timeout(pool_settings.acquire_timeout)
+            .idle_timeout(pool_settings.idle_timeout)
+            .connect_with(options)
+            .await?;
+        sqlx::migrate!().run(&pool).await?;
+        Ok(Db::Sqlite(pool))
+    }
+
+    /// An in-memory SQLite database, migrated and seeded like a fresh file would be. It only
+    /// lives as long as a connection is open, so the pool keeps one around for good, whatever
+    /// the idle timeout.
+    async fn connect_memory(
+        settings: &StatementSettings,
+        pool_settings: &PoolSettings,
+        pragmas: &SqlitePragmas,
+    ) -> anyhow::Result<Self> {
+        let options = pragmas
+            .apply(SqliteConnectOptions::from_str("sqlite::memory:")?, true)
+            .log_statements(settings.statements)
+            .log_slow_statements(LevelFilter::Warn, settings.slow_threshold)
+            .statement_cache_capacity(settings.cache_capacity);
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(pool_settings.max_connections)
+            .acquire_timeout(pool_settings.acquire_timeout)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await?;
+        sqlx::migrate!().run(&pool).await?;
+        Ok(Db::Sqlite(pool))
+    }
+
+    #[cfg(feature = "postgres")]
+    async fn connect_postgres(database_url: &str, settings: &StatementSettings, pool_settings: &PoolSettings) -> anyhow::Result<Self> {
+        let options = PgConnectOptions::from_str(database_url)?
+            .log_statements(settings.statements)
+            .log_slow_statements(LevelFilter::Warn, settings.slow_threshold)
+            .statement_cache_capacity(settings.cache_capacity);
+
+        let pool = PgPoolOptions::new()
+            .max_connections(pool_settings.max_connections)
+            .acquire_timeout(pool_settings.acquire_timeout)
+            .idle_timeout(pool_settings.idle_timeout)
+            .connect_with(options)
+            .await?;
+        sqlx::migrate!("./migrations_postgres").run(&pool).await?;
+        Ok(Db::Postgres(pool))
+    }
+
+    #[cfg(not(feature = "postgres"))]
+    async fn connect_postgres(
+        _database_url: &str,
+        _settings: &StatementSettings,
+        _pool_settings: &PoolSettings,
+    ) -> anyhow::Result<Self> {
+        anyhow::bail!("Postgres support is not compiled in, rebuild with `--features postgres`")
+    }
+
+    /// Checks the database is reachable by running `SELECT 1` on a pooled connection.
+    pub async fn ping(&self) -> Result<(), sqlx::Error> {
+        match self {
+            Db::Sqlite(pool) => sqlx::query("SELECT 1").execute(pool).await.map(|_| ()),
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => sqlx::query("SELECT 1").execute(pool).await.map(|_| ()),
+        }
+    }
+
+    /// Versions of the migrations this build knows of that aren't applied to the database yet.
+    /// Empty once `connect` has run them, unless the database was swapped or rolled back
+    /// underneath the running server.
+    pub async fn pending_migrations(&self) -> Result<Vec<i64>, sqlx::Error> {
+        const APPLIED: &str = "SELECT version FROM _sqlx_migrations WHERE success";
+        let (migrator, applied) = match self {
+            Db::Sqlite(pool) => (sqlx::migrate!(), sqlx::query_scalar::<_, i64>(APPLIED).fetch_all(pool).await?),
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => (
+                sqlx::migrate!("./migrations_postgres"),
+                sqlx::query_scalar::<_, i64>(APPLIED).fetch_all(pool).await?,
+            ),
+        };
+        Ok(migrator
+            .iter()
+            .filter(|migration| !migration.migration_type.is_down_migration())
+            .map(|migration| migration.version)
+            .filter(|version| !applied.contains(version))
+            .collect())
+    }
+
+    /// Connections open in the pool and how many of them are idle. Unlike `pool_stats` it
+    /// doesn't need a connection, so it answers even when the pool is exhausted.
+    pub fn pool_usage(&self) -> (u32, usize) {
+        match self {
+            Db::Sqlite(pool) => (pool.size(), pool.num_idle()),
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => (pool.size(), pool.num_idle()),
+        }
+    }
+
+    /// Pool and prepared statement cache figures, with the cache sampled from one pooled
+    /// connection (each connection has its own cache, and they fill up alike).
+    pub async fn pool_stats(&self) -> Result<PoolStats, sqlx::Error> {
+        match self {
+            Db::Sqlite(pool) => {
+                // Read before acquiring, so our own connection doesn't count as busy
+                let (connections, idle_connections) = self.pool_usage();
+                let cached_statements = pool.acquire().await?.cached_statements_size();
+                Ok(PoolStats {
+                    connections,
+                    idle_connections,
+                    cached_statements,
+                })
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => {
+                // Read before acquiring, so our own connection doesn't count as busy
+                let (connections, idle_connections) = self.pool_usage();
+                let cached_statements = pool.acquire().await?.cached_statements_size();
+                Ok(PoolStats {
+                    connections,
+                    idle_connections,
+                    cached_statements,
+                })
+            }
+        }
+    }
+
+    /// The SQLite pool, for the few features that only exist on SQLite.
+    pub fn sqlite(&self) -> Option<&SqlitePool> {
+        match self {
+            Db::Sqlite(pool) => Some(pool),
+            #[cfg(feature = "postgres")]
+            Db::Postgres(_) => None,
+        }
+    }
+
+    /// Closes every connection in the pool, waiting for checked out ones to be returned.
+    pub async fn close(&self) {
+        match self {
+            Db::Sqlite(pool) => pool.close().await,
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => pool.close().await,
+        }
+    }
+}
+
+/// How many times an operation is retried after SQLite reports the database as busy.
+pub const BUSY_RETRIES: u32 = 3;
+/// Delay before the first retry. It doubles after each attempt.
+const BUSY_BACKOFF: Duration = Duration::from_millis(50);
+/// What the "database busy" page tells clients to wait before retrying.
+pub const BUSY_RETRY_AFTER_SECS: u64 = 2;
+
+/// Whether the error is SQLite's `SQLITE_BUSY` or `SQLITE_LOCKED`: another connection holds
+/// the write lock and the statement gave up waiting for it. These are worth retrying, unlike
+/// most database errors. Errors from other databases never are: Postgres codes are SQLSTATE
+/// strings, and some of them would parse as numbers matching these.
+pub fn is_busy(err: &sqlx::Error) -> bool {
+    let sqlx::Error::Database(db_err) = err else {
+        return false;
+    };
+    let Some(sqlite_err) = db_err.try_downcast_ref::<SqliteError>() else {
+        return false;
+    };
+    // Extended result codes keep the primary code in the low byte, e.g. SQLITE_BUSY_SNAPSHOT
+    // is 517 = 5 | (2 << 8)
+    sqlite_err
+        .code()
+        .and_then(|code| code.parse::<i32>().ok())
+        .is_some_and(|code| matches!(code & 0xff, 5 | 6))
+}
+
+/// The contact field, by its form name, that another contact already has if the error is a
+/// violation of the `contacts_email_unique` or `contacts_phone_unique` index. SQLite only
+/// names the index in the message, as the column for the one on `email`.
+pub fn conflicting_field(err: &sqlx::Error) -> Option<&'static str> {
+    let sqlx::Error::Database(db_err) = err else {
+        return None;
+    };
+    if !db_err.is_unique_violation() {
+        return None;
+    }
+    let name = db_err.constraint().unwrap_or_else(|| db_err.message());
+    if name.contains("contacts_email_unique") || name.contains("contacts.email") {
+        Some("email")
+    } else if name.contains("contacts_phone_unique") {
+        Some("phone_number")
+    } else {
+        None
+    }
+}
+
+/// An error that may be the database being busy, which `retry_busy` tries again on.
+pub trait MaybeBusy {
+    fn is_busy(&self) -> bool;
+}
+
+impl MaybeBusy for sqlx::Error {
+    fn is_busy(&self) -> bool {
+        is_busy(self)
+    }
+}
+
+/// Runs `op`, retrying it up to `BUSY_RETRIES` times with exponential backoff while it fails
+/// with a busy database. Any other error, or the last busy error, is returned as is.
+pub async fn retry_busy<T, E, F, Fut>(mut op: F) -> Result<T, E>
+where
+    E: MaybeBusy,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut delay = BUSY_BACKOFF;
+    for attempt in 1..=BUSY_RETRIES {
+        match op().await {
+            Err(e) if e.is_busy() => {
+                warn!("Database busy (attempt {attempt} of {}), retrying in {delay:?}", BUSY_RETRIES + 1);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    op().await
+}
+
+/// Describes a bind parameter without leaking its value into the logs. Used in the query
+/// spans so a slow statement warning shows what kind of input it was run with.
+pub trait BindShape {
+    fn shape(&self) -> String;
+}
+
+impl BindShape for &str {
+    fn shape(&self) -> String {
+        format!("text({})", self.chars().count())
+    }
+}
+
+impl BindShape for String {
+    fn shape(&self) -> String {
+        self.as_str().shape()
+    }
+}
+
+impl BindShape for i64 {
+    fn shape(&self) -> String {
+        "int".into()
+    }
+}
+
+impl BindShape for bool {
+    fn shape(&self) -> String {
+        "bool".into()
+    }
+}
+
+impl BindShape for chrono::NaiveDate {
+    fn shape(&self) -> String {
+        "date".into()
+    }
+}
+
+/// `null` for unset optional parameters.
+impl<T: BindShape> BindShape for Option<T> {
+    fn shape(&self) -> String {
+        self.as_ref().map_or_else(|| "null".into(), BindShape::shape)
+    }
+}
+
+/// Formats a list of bind parameters as their shapes, e.g. `[text(5), int, int]`.
+pub fn shapes(binds: &[&dyn BindShape]) -> String {
+    let shapes: Vec<String> = binds.iter().map(|b| b.shape()).collect();
+    format!("[{}]", shapes.join(", "))
+}
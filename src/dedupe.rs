@@ -0,0 +1,372 @@
+//! Batch deduplication, for cleaning up after a big import.
+//!
+//! Two contacts are exact duplicates when they have the same email (ignoring case) or phone
+//! number (ignoring punctuation), and fuzzy ones when only their names are similar, by the
+//! rules `duplicates` uses for new contacts. Duplicates of duplicates end up in the same group.
+//! A group is merged into its oldest contact with `duplicates::merge`: the others'
+//! relationships, dates and attachments move over to it, then they are deleted and it gets
+//! their fields in one `apply_batch`, so a merge that fails half way loses nothing. The UI has
+//! no merge of its own, the contact form only warns about duplicates (see
+//! `duplicates::find`), so this is the only place contacts are merged.
+//!
+//! Runs with `webone dedupe`, which only reports the groups. `--apply` merges the exact ones,
+//! and the fuzzy ones too with `--fuzzy`, as two people can share a name.
+//!
+//! The migration that made emails and phone numbers unique tagged the ones older contacts
+//! already had, like `ana+duplicate-12@example.com` and `206 444 9876 (duplicate 12)`. The
+//! tags are left out when comparing, so those contacts are exact duplicates here.
+
+use std::collections::HashMap;
+
+use serde_json::json;
+use tracing::info;
+
+use crate::{
+    attachments::Attachment,
+    clock::SharedClock,
+    contacts::{BatchFailure, BatchFailureReason, Contact, ContactWrite},
+    db::Db,
+    duplicates::{self, NameKey},
+    events::{self, Event},
+    jobs::Jobs,
+    phone,
+    repository::SharedContactRepository,
+    webhooks,
+};
+
+/// Actor of the events recorded for merges.
+pub const ACTOR: &str = "dedupe";
+
+/// Why the contacts of a group count as duplicates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// Each is linked to another by email or phone number.
+    Exact,
+    /// Some are only linked by their names.
+    Fuzzy,
+}
+
+impl MatchKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MatchKind::Exact => "exact",
+            MatchKind::Fuzzy => "fuzzy",
+        }
+    }
+}
+
+/// Contacts that are probably the same person.
+#[derive(Debug)]
+pub struct DuplicateGroup {
+    pub kind: MatchKind,
+    /// By id, so the one kept comes first.
+    pub contacts: Vec<Contact>,
+    /// The kept contact with the others merged into it.
+    pub merged: Contact,
+}
+
+impl DuplicateGroup {
+    fn new(kind: MatchKind, contacts: Vec<Contact>) -> Self {
+        let merged = contacts[1..]
+            .iter()
+            .fold(contacts[0].clone(), |keep, duplicate| duplicates::merge(&keep, duplicate));
+        Self { kind, contacts, merged }
+    }
+
+    pub fn keep(&self) -> &Contact {
+        &self.contacts[0]
+    }
+
+    /// The contacts merged into `keep` and deleted.
+    pub fn duplicates(&self) -> &[Contact] {
+        &self.contacts[1..]
+    }
+
+    /// Whether `--apply` merges the group, given whether `--fuzzy` was passed.
+    pub fn applies(&self, fuzzy: bool) -> bool {
+        fuzzy || self.kind == MatchKind::Exact
+    }
+}
+
+/// `value` without a `<prefix><id><suffix>` tag the unique email and phone migration added.
+fn untag(value: &str, prefix: &str, suffix: &str) -> String {
+    if let Some(start) = value.find(prefix) {
+        let rest = &value[start + prefix.len()..];
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits > 0 && rest[digits..].starts_with(suffix) {
+            return format!("{}{}", &value[..start], &rest[digits + suffix.len()..]);
+        }
+    }
+    value.to_string()
+}
+
+/// Disjoint sets of contact indexes, for joining duplicates of duplicates into one group.
+struct Sets {
+    parents: Vec<usize>,
+}
+
+impl Sets {
+    fn new(len: usize) -> Self {
+        Self { parents: (0..len).collect() }
+    }
+
+    fn root(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    fn join(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.root(a), self.root(b));
+        // The lower index stays the root, so the order of the groups doesn't depend on the
+        // order of the joins
+        self.parents[a.max(b)] = a.min(b);
+    }
+}
+
+/// What a dedupe changes, worked out before anything is written.
+#[derive(Debug, Default)]
+pub struct DedupePlan {
+    pub scanned: usize,
+    /// By the id of the contact kept.
+    pub groups: Vec<DuplicateGroup>,
+}
+
+impl DedupePlan {
+    /// Groups `contacts` by who they probably are. Every name is compared to every other one,
+    /// so this takes a while for large address books.
+    pub fn new(mut contacts: Vec<Contact>) -> Self {
+        contacts.sort_by_key(|contact| contact.id);
+        let len = contacts.len();
+        let mut exact = Sets::new(len);
+        let mut any = Sets::new(len);
+
+        let mut by_email: HashMap<String, usize> = HashMap::new();
+        let mut by_phone: HashMap<String, usize> = HashMap::new();
+        for (i, contact) in contacts.iter().enumerate() {
+            for (seen, key) in [
+                (&mut by_email, duplicates::normalize_email(&untag(&contact.email, "+duplicate-", ""))),
+                (&mut by_phone, phone::normalize(&untag(&contact.phone_number, " (duplicate ", ")"))),
+            ] {
+                if key.is_empty() {
+                    continue;
+                }
+                match seen.get(&key) {
+                    Some(&first) => {
+                        exact.join(first, i);
+                        any.join(first, i);
+                    }
+                    None => {
+                        seen.insert(key, i);
+                    }
+                }
+            }
+        }
+
+        let names: Vec<NameKey> = contacts
+            .iter()
+            .map(|contact| NameKey::new(&contact.first_name, &contact.last_name))
+            .collect();
+        for i in 0..len {
+            for j in i + 1..len {
+                if any.root(i) != any.root(j) && names[i].similar(&names[j]) {
+                    any.join(i, j);
+                }
+            }
+        }
+
+        let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+        for i in 0..len {
+            members.entry(any.root(i)).or_default().push(i);
+        }
+        let mut roots: Vec<usize> = members.keys().copied().filter(|root| members[root].len() > 1).collect();
+        roots.sort_unstable();
+        let groups = roots
+            .into_iter()
+            .map(|root| {
+                let indexes = &members[&root];
+                let kind = if indexes.iter().all(|&i| exact.root(i) == exact.root(root)) {
+                    MatchKind::Exact
+                } else {
+                    MatchKind::Fuzzy
+                };
+                DuplicateGroup::new(kind, indexes.iter().map(|&i| contacts[i].clone()).collect())
+            })
+            .collect();
+        Self { scanned: len, groups }
+    }
+
+    /// One line counts, like `120 contacts, 3 exact and 1 fuzzy duplicate groups`.
+    pub fn summary(&self) -> String {
+        let exact = self.groups.iter().filter(|g| g.kind == MatchKind::Exact).count();
+        format!(
+            "{} contacts, {exact} exact and {} fuzzy duplicate groups",
+            self.scanned,
+            self.groups.len() - exact
+        )
+    }
+
+    /// The summary followed by the groups: a line with the contact kept and the ones merged
+    /// into it, then a `~` line for every field the merge fills in.
+    pub fn report(&self) -> String {
+        let mut lines = vec![self.summary()];
+        for group in &self.groups {
+            let duplicates: Vec<String> = group.duplicates().iter().map(describe).collect();
+            lines.push(format!(
+                "{}: {} <- {}",
+                group.kind.as_str(),
+                describe(group.keep()),
+                duplicates.join(", ")
+            ));
+            for (field, old, new) in changes(group.keep(), &group.merged) {
+                lines.push(format!("  ~ {field} {old:?} -> {new:?}"));
+            }
+        }
+        lines.join("\n")
+    }
+
+    /// Merges the groups `DuplicateGroup::applies` says to, recording an event (and queueing
+    /// webhooks) for every change like the forms do. Returns how many contacts were merged
+    /// away.
+    pub async fn apply(
+        &self,
+        fuzzy: bool,
+        contacts: &SharedContactRepository,
+        db: &Db,
+        jobs: &Jobs,
+        clock: &SharedClock,
+    ) -> anyhow::Result<usize> {
+        let mut merged = 0;
+        for group in self.groups.iter().filter(|group| group.applies(fuzzy)) {
+            let keep = group.keep();
+            for duplicate in group.duplicates() {
+                move_relationships(group, duplicate, contacts, db).await?;
+                move_dates(keep.id, duplicate, contacts, db).await?;
+                Attachment::move_all(db, duplicate.id, keep.id).await?;
+            }
+            // The duplicates go before the kept contact takes over their emails or phone
+            // numbers, which have to be unique
+            let mut writes: Vec<ContactWrite> = group.duplicates().iter().cloned().map(ContactWrite::Delete).collect();
+            if !changes(keep, &group.merged).is_empty() {
+                writes.push(ContactWrite::Update(group.merged.clone()));
+            }
+            let applied = match contacts.apply_batch(&writes, clock.now()).await? {
+                Ok(applied) => applied,
+                Err(BatchFailure { reason: BatchFailureReason::Taken(field), .. }) => {
+                    anyhow::bail!("Merging into {}: another contact has the same {field}", describe(keep))
+                }
+                Err(BatchFailure { index, reason: BatchFailureReason::Changed }) => {
+                    let contact = group.duplicates().get(index).unwrap_or(keep);
+                    anyhow::bail!("Merging into {}: {} changed since the scan", describe(keep), describe(contact))
+                }
+            };
+            for (write, contact) in writes.iter().zip(&applied) {
+                let kind = match write {
+                    ContactWrite::Update(_) => events::CONTACT_UPDATED,
+                    _ => events::CONTACT_DELETED,
+                };
+                record(db, jobs, clock, kind, contact).await?;
+            }
+            merged += group.duplicates().len();
+        }
+        Ok(merged)
+    }
+}
+
+/// The fields that differ between two versions of a contact, with their old and new values.
+fn changes<'a>(before: &'a Contact, after: &'a Contact) -> Vec<(&'static str, &'a str, &'a str)> {
+    [
+        ("first name", &before.first_name, &after.first_name),
+        ("last name", &before.last_name, &after.last_name),
+        ("email", &before.email, &after.email),
+        ("phone", &before.phone_number, &after.phone_number),
+        ("company", &before.company, &after.company),
+        ("job title", &before.job_title, &after.job_title),
+    ]
+    .into_iter()
+    .filter(|(_, old, new)| old != new)
+    .map(|(field, old, new)| (field, old.as_str(), new.as_str()))
+    .collect()
+}
+
+/// `#12 Jane Doe <jane@example.com>`
+fn describe(contact: &Contact) -> String {
+    format!("#{} {} {} <{}>", contact.id, contact.first_name, contact.last_name, contact.email)
+}
+
+/// Gives `duplicate`'s relationships to the group's kept contact, except the ones it already
+/// has and the ones between contacts of the group, which would end up linking it to itself.
+async fn move_relationships(
+    group: &DuplicateGroup,
+    duplicate: &Contact,
+    contacts: &SharedContactRepository,
+    db: &Db,
+) -> anyhow::Result<()> {
+    let keep = group.keep().id;
+    let existing = contacts.relationships(keep).await?;
+    for relationship in contacts.relationships(duplicate.id).await? {
+        let other = relationship.other_id;
+        if group.contacts.iter().any(|contact| contact.id == other)
+            || existing.iter().any(|r| {
+                r.other_id == other && r.incoming == relationship.incoming && r.label == relationship.label
+            })
+        {
+            continue;
+        }
+        let (contact_id, related_id) = if relationship.incoming { (other, keep) } else { (keep, other) };
+        let id = contacts.add_relationship(contact_id, related_id, &relationship.label).await?;
+        let payload = json!({ "id": id, "contact_id": contact_id, "related_id": related_id, "label": relationship.label });
+        Event::record(db, events::RELATIONSHIP_ADDED, ACTOR, Some(contact_id), payload).await?;
+    }
+    Ok(())
+}
+
+/// Gives `duplicate`'s dates to contact `keep`, except the ones it already has.
+async fn move_dates(keep: i64, duplicate: &Contact, contacts: &SharedContactRepository, db: &Db) -> anyhow::Result<()> {
+    let existing = contacts.dates(keep).await?;
+    for date in contacts.dates(duplicate.id).await? {
+        if existing.iter().any(|d| d.label == date.label && d.date == date.date) {
+            continue;
+        }
+        let id = contacts.add_date(keep, &date.label, date.date, date.yearly).await?;
+        let payload = json!({ "id": id, "contact_id": keep, "label": date.label, "date": date.date, "yearly": date.yearly });
+        Event::record(db, events::DATE_ADDED, ACTOR, Some(keep), payload).await?;
+    }
+    Ok(())
+}
+
+async fn record(db: &Db, jobs: &Jobs, clock: &SharedClock, kind: &str, contact: &Contact) -> anyhow::Result<()> {
+    let payload = serde_json::to_value(contact)?;
+    Event::record(db, kind, ACTOR, Some(contact.id), payload.clone()).await?;
+    webhooks::fire(db, jobs, kind, contact.id, &payload, clock.now()).await
+}
+
+/// Runs one dedupe: groups every contact and merges the groups unless `dry_run`. Returns the
+/// report.
+///
+/// Example usage:
+/// `let report = dedupe::run(&contacts, &db, &jobs, &clock, false, true).await?;`
+pub async fn run(
+    contacts: &SharedContactRepository,
+    db: &Db,
+    jobs: &Jobs,
+    clock: &SharedClock,
+    dry_run: bool,
+    fuzzy: bool,
+) -> anyhow::Result<String> {
+    let plan = DedupePlan::new(contacts.all().await?);
+    let report = plan.report();
+    if dry_run {
+        return Ok(format!("Dry run, nothing changed (merge with --apply). {report}"));
+    }
+    let merged = plan.apply(fuzzy, contacts, db, jobs, clock).await?;
+    info!(merged, "Dedupe: {}", plan.summary());
+    let skipped = plan.groups.iter().filter(|group| !group.applies(fuzzy)).count();
+    Ok(if skipped > 0 {
+        format!("Merged {merged} contacts, left {skipped} fuzzy groups (merge them with --fuzzy). {report}")
+    } else {
+        format!("Merged {merged} contacts. {report}")
+    })
+}
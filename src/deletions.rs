@@ -0,0 +1,141 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tracing::{error, info};
+
+use crate::{
+    attachments::Attachments,
+    contacts::Contact,
+    db::Db,
+    repository::{ContactRepository, SharedContactRepository},
+    trash::TrashedContact,
+};
+
+/// How long a deleted contact can still be restored before it is removed for good.
+pub const UNDO_DELETE_GRACE: Duration = Duration::from_secs(10);
+
+struct PendingDeletion {
+    contact: Contact,
+    deadline: Instant,
+}
+
+/// Registry of contacts that have been deleted by the user but not yet removed from the
+/// database. Deletions sit here for `UNDO_DELETE_GRACE` so they can be undone, and
+/// `finalize_expired` does the actual `DELETE` once the deadline passes: into the trash, or
+/// for good with the contact's attachments when there is none (see `trash`).
+#[derive(Clone, Default)]
+pub struct PendingDeletions {
+    inner: Arc<Mutex<HashMap<i64, PendingDeletion>>>,
+    trash: Option<Db>,
+}
+
+impl PendingDeletions {
+    /// Deletions that go into the trash in `db`, or for good without one.
+    pub fn new(trash: Option<Db>) -> Self {
+        Self {
+            inner: Arc::default(),
+            trash,
+        }
+    }
+
+    /// Queues the contact for deletion once the grace period is over.
+    pub fn schedule(&self, contact: Contact) {
+        let deadline = Instant::now() + UNDO_DELETE_GRACE;
+        self.inner
+            .lock()
+            .unwrap()
+            .insert(contact.id, PendingDeletion { contact, deadline });
+    }
+
+    /// Takes the contact out of the queue. Returns `false` if it was not pending anymore
+    /// (never deleted, or already finalized).
+    pub fn cancel(&self, id: i64) -> bool {
+        self.inner.lock().unwrap().remove(&id).is_some()
+    }
+
+    pub fn is_pending(&self, id: i64) -> bool {
+        self.inner.lock().unwrap().contains_key(&id)
+    }
+
+    /// All contacts currently waiting to be deleted, oldest first.
+    pub fn pending(&self) -> Vec<Contact> {
+        let inner = self.inner.lock().unwrap();
+        let mut pending: Vec<&PendingDeletion> = inner.values().collect();
+        pending.sort_by_key(|p| p.deadline);
+        pending.into_iter().map(|p| p.contact.clone()).collect()
+    }
+
+    fn take_expired(&self, now: Instant) -> Vec<Contact> {
+        let mut inner = self.inner.lock().unwrap();
+        let expired: Vec<i64> = inner
+            .iter()
+            .filter(|(_, p)| p.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        expired
+            .iter()
+            .filter_map(|id| inner.remove(id))
+            .map(|p| p.contact)
+            .collect()
+    }
+
+    /// Deletes every contact whose grace period has run out.
+    pub async fn finalize_expired(&self, contacts: &dyn ContactRepository, attachments: &Attachments) {
+        self.finalize(contacts, attachments, Instant::now()).await;
+    }
+
+    /// Deletes every pending contact right away, grace period or not. Used on shutdown so
+    /// deletions the user asked for aren't lost with the in-memory registry.
+    pub async fn finalize_all(&self, contacts: &dyn ContactRepository, attachments: &Attachments) {
+        self.finalize(contacts, attachments, Instant::now() + UNDO_DELETE_GRACE).await;
+    }
+
+    async fn finalize(&self, contacts: &dyn ContactRepository, attachments: &Attachments, now: Instant) {
+        for contact in self.take_expired(now) {
+            let id = contact.id;
+            if let Some(db) = &self.trash {
+                // Trashed first, so a failure leaves the contact where it was rather than lost
+                let trashed = match contacts.dates(id).await {
+                    Ok(dates) => TrashedContact::add(db, &contact, &dates).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = trashed {
+                    error!("Failed to move contact {id} to the trash: {e}");
+                    continue;
+                }
+            }
+            match contacts.delete(id).await {
+                Ok(()) if self.trash.is_some() => {
+                    info!("Moved contact {id} to the trash");
+                    continue;
+                }
+                Ok(()) => info!("Deleted contact {id}"),
+                Err(e) => {
+                    error!("Failed to delete contact {id}: {e}");
+                    if let Some(db) = &self.trash {
+                        let _ = TrashedContact::remove(db, id).await;
+                    }
+                    continue;
+                }
+            }
+            if let Err(e) = attachments.remove_for_contact(id).await {
+                error!("Failed to remove the attachments of contact {id}: {e}");
+            }
+        }
+    }
+
+    /// Spawns the background task that finalizes deletions once a second.
+    pub fn spawn_finalizer(&self, contacts: SharedContactRepository, attachments: Attachments) {
+        let pending = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                pending.finalize_expired(contacts.as_ref(), &attachments).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,140 @@
+//! Looks for contacts that are probably the same person as a new one, before it is created,
+//! and merges ones that are (see `dedupe` for doing that in bulk).
+//!
+//! Exact email and phone matches are refused outright by the create form. This catches the
+//! near misses: emails that only differ in case, phone numbers written with different
+//! punctuation, and names that are the same apart from case, spacing, order or a typo.
+
+use tokio_stream::StreamExt;
+
+use crate::{
+    contacts::{Contact, NewContact},
+    phone,
+    repository::ContactRepository,
+};
+
+/// Names at least this long (in characters, without spaces) may be a typo apart and still
+/// count as similar. Shorter ones have to match, or "Al Li" would match "Al Le".
+const MIN_FUZZY_NAME_LEN: usize = 8;
+/// Most single character edits two similar names can be apart.
+const MAX_NAME_DISTANCE: usize = 2;
+
+/// Lowercase, without surrounding whitespace.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Lowercase words of a name, in order.
+fn name_words(first_name: &str, last_name: &str) -> Vec<String> {
+    format!("{first_name} {last_name}")
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Levenshtein distance between two strings, in characters.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// A name prepared for comparing, so comparing it to many others doesn't split and lowercase
+/// it every time.
+pub struct NameKey {
+    sorted_words: Vec<String>,
+    /// The words run together, in order.
+    joined: String,
+    len: usize,
+}
+
+impl NameKey {
+    pub fn new(first_name: &str, last_name: &str) -> Self {
+        let words = name_words(first_name, last_name);
+        let joined = words.concat();
+        let mut sorted_words = words;
+        sorted_words.sort();
+        let len = joined.chars().count();
+        Self { sorted_words, joined, len }
+    }
+
+    /// See `similar_names`.
+    pub fn similar(&self, other: &NameKey) -> bool {
+        if self.sorted_words.is_empty() || other.sorted_words.is_empty() {
+            return false;
+        }
+        if self.sorted_words == other.sorted_words {
+            return true;
+        }
+        self.len.min(other.len) >= MIN_FUZZY_NAME_LEN
+            && self.len.abs_diff(other.len) <= MAX_NAME_DISTANCE
+            && distance(&self.joined, &other.joined) <= MAX_NAME_DISTANCE
+    }
+}
+
+/// Whether two names are probably the same: equal ignoring case and spacing, the same words
+/// in another order ("Doe Jane"), or a couple of typos apart for longer names.
+pub fn similar_names(a: (&str, &str), b: (&str, &str)) -> bool {
+    NameKey::new(a.0, a.1).similar(&NameKey::new(b.0, b.1))
+}
+
+/// Whether `contact` is probably the same person as `new`.
+pub fn is_possible_duplicate(new: &NewContact, contact: &Contact) -> bool {
+    let email = normalize_email(&new.email);
+    let phone_number = phone::normalize(&new.phone_number);
+    (!email.is_empty() && normalize_email(&contact.email) == email)
+        || (!phone_number.is_empty() && phone::normalize(&contact.phone_number) == phone_number)
+        || similar_names(
+            (&new.first_name, &new.last_name),
+            (&contact.first_name, &contact.last_name),
+        )
+}
+
+/// `keep` with what it's missing filled in from `duplicate`, a contact that is the same
+/// person: empty fields take the duplicate's value, and where both have one `keep`'s wins.
+///
+/// Example usage:
+/// `let merged = duplicates::merge(&oldest, &imported);`
+pub fn merge(keep: &Contact, duplicate: &Contact) -> Contact {
+    let pick = |ours: &str, theirs: &str| {
+        if ours.is_empty() { theirs.to_string() } else { ours.to_string() }
+    };
+    Contact {
+        first_name: pick(&keep.first_name, &duplicate.first_name),
+        last_name: pick(&keep.last_name, &duplicate.last_name),
+        phone_number: pick(&keep.phone_number, &duplicate.phone_number),
+        email: pick(&keep.email, &duplicate.email),
+        company: pick(&keep.company, &duplicate.company),
+        job_title: pick(&keep.job_title, &duplicate.job_title),
+        ..keep.clone()
+    }
+}
+
+/// The first contact, by id, that is probably the same person as `new`, skipping the ones
+/// `ignore` is `true` for. Reads through the contacts one at a time, so it doesn't hold them
+/// all in memory.
+///
+/// Example usage:
+/// `duplicates::find(state.contacts.as_ref(), &new_contact, |c| pending.is_pending(c.id)).await?`
+pub async fn find(
+    contacts: &dyn ContactRepository,
+    new: &NewContact,
+    ignore: impl Fn(&Contact) -> bool,
+) -> Result<Option<Contact>, sqlx::Error> {
+    let mut rows = contacts.stream_all();
+    while let Some(contact) = rows.next().await {
+        let contact = contact?;
+        if !ignore(&contact) && is_possible_duplicate(new, &contact) {
+            return Ok(Some(contact));
+        }
+    }
+    Ok(None)
+}
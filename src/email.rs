@@ -0,0 +1,124 @@
+//! Outgoing email over SMTP.
+//!
+//! Messages are rendered from Askama templates in `templates/email/` and sent through the job
+//! queue as `EMAIL_JOB` jobs, so a slow or unreachable mail server never holds up a request
+//! and failed sends are retried. Without `email.smtp_url` nothing is sent; messages are
+//! written to the log instead, which is handy during development.
+
+use askama::Template;
+use async_trait::async_trait;
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{Mailbox, header::ContentType},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::info;
+
+use crate::{
+    config::EmailConfig,
+    jobs::{JobHandler, Jobs},
+};
+
+/// Job kind that sends one `Email`, which is the job's payload.
+pub const EMAIL_JOB: &str = "email.send";
+
+/// Parses an address like `webone <webone@example.com>` or just `webone@example.com`.
+pub fn parse_mailbox(address: &str) -> anyhow::Result<Mailbox> {
+    address
+        .trim()
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Invalid email address {address:?}: {e}"))
+}
+
+/// A message ready to send. Plain text only.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// A template for an email body, which also knows the subject line.
+pub trait EmailTemplate: Template {
+    fn subject(&self) -> String;
+}
+
+impl Email {
+    /// Renders `message` into an email to `to`.
+    ///
+    /// Example usage:
+    /// `Email::render("me@example.com", &TestEmailTemplate { sent_at })?.queue(&jobs).await?;`
+    pub fn render(to: &str, message: &impl EmailTemplate) -> askama::Result<Self> {
+        Ok(Self {
+            to: to.to_string(),
+            subject: message.subject(),
+            body: message.render()?,
+        })
+    }
+
+    /// Queues the email for sending and returns the job id. An invalid `to` address is an
+    /// error here rather than a job that can never succeed.
+    pub async fn queue(&self, jobs: &Jobs) -> anyhow::Result<i64> {
+        parse_mailbox(&self.to)?;
+        Ok(jobs.enqueue(EMAIL_JOB, serde_json::to_value(self)?).await?)
+    }
+}
+
+/// Sends emails through the configured SMTP server. Cheap to clone, clones share the
+/// connection pool.
+#[derive(Clone)]
+pub struct Mailer {
+    transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    /// Doesn't connect yet, connections are opened on the first send.
+    pub fn new(config: &EmailConfig) -> anyhow::Result<Self> {
+        let transport = config
+            .smtp_url
+            .as_deref()
+            .map(|url| AsyncSmtpTransport::<Tokio1Executor>::from_url(url).map(|b| b.build()))
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("Invalid smtp_url: {e}"))?;
+        Ok(Self {
+            transport,
+            from: parse_mailbox(&config.from)?,
+        })
+    }
+
+    /// Sends right away. Most code should `Email::queue` instead.
+    pub async fn send(&self, email: &Email) -> anyhow::Result<()> {
+        let Some(transport) = &self.transport else {
+            info!(
+                "No smtp_url configured, not sending email to {}\nSubject: {}\n\n{}",
+                email.to, email.subject, email.body
+            );
+            return Ok(());
+        };
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(parse_mailbox(&email.to)?)
+            .subject(&email.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body.clone())?;
+        transport.send(message).await?;
+        Ok(())
+    }
+}
+
+/// Runs `EMAIL_JOB` jobs.
+pub struct EmailJob {
+    pub mailer: Mailer,
+}
+
+#[async_trait]
+impl JobHandler for EmailJob {
+    async fn run(&self, payload: &Value) -> anyhow::Result<String> {
+        let email: Email = serde_json::from_value(payload.clone())?;
+        self.mailer.send(&email).await?;
+        let sent = if self.mailer.transport.is_some() { "Sent" } else { "Logged" };
+        Ok(format!("{sent} \"{}\" to {}", email.subject, email.to))
+    }
+}
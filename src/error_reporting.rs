@@ -0,0 +1,222 @@
+//! Error reporting: every 5xx response is handed to an `ErrorReporter` with the request it
+//! answered, so production errors reach someone instead of only the log.
+//!
+//! `AppError` puts the error behind its response in the response extensions as a
+//! `ReportedError`, and `report` (a middleware around every route) picks it up together with
+//! the request's method, URI, id and client IP. 5xx responses that didn't come from an
+//! `AppError` are reported too, without an error.
+//!
+//! With the `sentry` feature, `error_reporting.sentry_dsn` sends them to Sentry (or anything
+//! that speaks its protocol, like GlitchTip). Otherwise they are only logged, as before.
+
+use std::{net::{IpAddr, SocketAddr}, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{Method, StatusCode, Uri},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::config::ErrorReportingConfig;
+
+/// Where 5xx responses are reported to.
+pub trait ErrorReporter: Send + Sync {
+    fn report(&self, report: &ErrorReport);
+}
+
+pub type SharedErrorReporter = Arc<dyn ErrorReporter>;
+
+/// The error behind a response, put in its extensions by `AppError` for `report`.
+#[derive(Debug, Clone)]
+pub struct ReportedError(pub Arc<anyhow::Error>);
+
+/// A 5xx response and the request it answered.
+#[derive(Debug)]
+pub struct ErrorReport {
+    pub status: StatusCode,
+    pub method: Method,
+    /// Path and query, under the base path.
+    pub uri: Uri,
+    /// See `X-Request-Id`.
+    pub request_id: Option<String>,
+    pub client_ip: Option<IpAddr>,
+    /// Unset for 5xx responses that didn't come from an `AppError`.
+    pub error: Option<Arc<anyhow::Error>>,
+}
+
+impl ErrorReport {
+    /// The error's message, or the status for responses without one.
+    pub fn message(&self) -> String {
+        match &self.error {
+            Some(err) => err.to_string(),
+            None => self.status.to_string(),
+        }
+    }
+}
+
+/// Reports nothing. `AppError` already logs every error, with the request's span.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogOnly;
+
+impl ErrorReporter for LogOnly {
+    fn report(&self, _report: &ErrorReport) {}
+}
+
+/// The reporter `config` asks for: Sentry when a DSN is set, `LogOnly` otherwise. Fails on a
+/// DSN that can't be parsed, or one set in a build without the `sentry` feature.
+pub fn reporter(config: &ErrorReportingConfig) -> anyhow::Result<SharedErrorReporter> {
+    let Some(dsn) = &config.sentry_dsn else {
+        return Ok(Arc::new(LogOnly));
+    };
+    #[cfg(feature = "sentry")]
+    {
+        Ok(Arc::new(sentry::SentryReporter::new(dsn, config.environment.clone())?))
+    }
+    #[cfg(not(feature = "sentry"))]
+    {
+        let _ = dsn;
+        anyhow::bail!("error_reporting sentry_dsn needs a build with the `sentry` feature")
+    }
+}
+
+/// Middleware: hands every 5xx response to the reporter. Goes inside the access log's span
+/// and the request id layer, so both are known.
+///
+/// Example usage:
+/// `router.layer(middleware::from_fn_with_state(reporter, error_reporting::report))`
+pub async fn report(State(reporter): State<SharedErrorReporter>, request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let response = next.run(request).await;
+    if response.status().is_server_error() {
+        reporter.report(&ErrorReport {
+            status: response.status(),
+            method,
+            uri,
+            request_id,
+            client_ip,
+            error: response.extensions().get::<ReportedError>().map(|ReportedError(err)| err.clone()),
+        });
+    }
+    response
+}
+
+#[cfg(feature = "sentry")]
+mod sentry {
+    //! Sentry's envelope endpoint, called with reqwest instead of the Sentry SDK.
+
+    use std::time::Duration;
+
+    use anyhow::Context;
+    use chrono::{SecondsFormat, Utc};
+    use reqwest::{Client, Url, header::CONTENT_TYPE};
+    use serde_json::{Value, json};
+    use tracing::warn;
+    use uuid::Uuid;
+
+    use super::{ErrorReport, ErrorReporter};
+
+    /// How long Sentry gets to take an event before it is dropped.
+    const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Sends each report as a Sentry event, in the background so the response isn't held up.
+    pub struct SentryReporter {
+        client: Client,
+        /// `https://<host>/api/<project>/envelope/`
+        endpoint: Url,
+        dsn: String,
+        public_key: String,
+        environment: Option<String>,
+    }
+
+    impl SentryReporter {
+        /// Takes a DSN like `https://<public key>@o0.ingest.sentry.io/<project id>`.
+        pub fn new(dsn: &str, environment: Option<String>) -> anyhow::Result<Self> {
+            let parsed = Url::parse(dsn).with_context(|| format!("Invalid Sentry DSN {dsn:?}"))?;
+            let public_key = parsed.username().to_string();
+            let project = parsed.path_segments().and_then(|mut segments| segments.next_back()).unwrap_or_default();
+            anyhow::ensure!(
+                !public_key.is_empty() && !project.is_empty(),
+                "Sentry DSN {dsn:?} needs a public key and a project id"
+            );
+            let mut endpoint = parsed.clone();
+            endpoint.set_username("").ok();
+            endpoint.set_password(None).ok();
+            let prefix = parsed.path().trim_end_matches(project).trim_end_matches('/');
+            endpoint.set_path(&format!("{prefix}/api/{project}/envelope/"));
+            Ok(Self {
+                client: Client::builder().timeout(SEND_TIMEOUT).build()?,
+                endpoint,
+                dsn: dsn.to_string(),
+                public_key,
+                environment,
+            })
+        }
+
+        /// The event, with the error's causes as chained exceptions, root cause first.
+        fn event(&self, report: &ErrorReport, event_id: &str) -> Value {
+            let exceptions: Vec<Value> = match &report.error {
+                Some(err) => err
+                    .chain()
+                    .rev()
+                    .map(|cause| json!({ "type": "Error", "value": cause.to_string() }))
+                    .collect(),
+                None => vec![json!({ "type": "HttpError", "value": report.status.to_string() })],
+            };
+            json!({
+                "event_id": event_id,
+                "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+                "platform": "other",
+                "level": "error",
+                "logger": "webone",
+                "release": concat!("webone@", env!("CARGO_PKG_VERSION")),
+                "environment": self.environment,
+                "transaction": format!("{} {}", report.method, report.uri.path()),
+                "exception": { "values": exceptions },
+                "request": {
+                    "method": report.method.as_str(),
+                    "url": report.uri.path(),
+                    "query_string": report.uri.query().unwrap_or_default(),
+                },
+                "user": { "ip_address": report.client_ip.map(|ip| ip.to_string()) },
+                "tags": {
+                    "status": report.status.as_u16(),
+                    "request_id": report.request_id,
+                },
+            })
+        }
+    }
+
+    impl ErrorReporter for SentryReporter {
+        fn report(&self, report: &ErrorReport) {
+            let event_id = Uuid::new_v4().simple().to_string();
+            let header = json!({ "event_id": event_id, "dsn": self.dsn });
+            let body = format!("{header}\n{}\n{}\n", json!({ "type": "event" }), self.event(report, &event_id));
+            let request = self
+                .client
+                .post(self.endpoint.clone())
+                .header(CONTENT_TYPE, "application/x-sentry-envelope")
+                .header(
+                    "X-Sentry-Auth",
+                    format!("Sentry sentry_version=7, sentry_client=webone/{}, sentry_key={}", env!("CARGO_PKG_VERSION"), self.public_key),
+                )
+                .body(body);
+            tokio::spawn(async move {
+                match request.send().await.and_then(|response| response.error_for_status()) {
+                    Ok(_) => {}
+                    Err(err) => warn!("Failed to send an error report to Sentry: {err}"),
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! ETags for conditional GETs. A handler builds an `ETag` from whatever its page is rendered
+//! from and answers `304 Not Modified` when the client already has that version, which saves
+//! re-rendering and re-sending pages that htmx polls or users revisit.
+//!
+//! The JSON API goes the other way too: it answers with the contact's `ETag::version`, and
+//! takes changes to the contact only with that ETag in `If-Match`, so a client can't overwrite
+//! a change it hasn't seen.
+
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::OnceLock,
+};
+
+use axum::{
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{CACHE_CONTROL, ETAG, IF_MATCH, IF_NONE_MATCH},
+    },
+    response::{IntoResponse, Response},
+};
+
+use crate::{carddav, contacts::Contact, i18n, preferences};
+
+/// Random per process. Mixed into every ETag so pages rendered by a previous run, possibly with
+/// different templates, are never considered fresh.
+fn generation() -> u64 {
+    static GENERATION: OnceLock<u64> = OnceLock::new();
+    *GENERATION.get_or_init(rand::random)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ETag(String);
+
+/// Why `If-Match` doesn't let a change through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precondition {
+    /// The request has no `If-Match`: `428 Precondition Required`.
+    Required,
+    /// It doesn't list the current ETag: `412 Precondition Failed`.
+    Failed,
+}
+
+impl ETag {
+    /// ETag for a page rendered from `value`. Anything that changes the page has to be part of
+    /// `value`, except the locale and the display preferences, which are always mixed in.
+    ///
+    /// Example usage:
+    /// `ETag::of(&(contact.id, &contact.updated_at))`
+    pub fn of<T: Hash + ?Sized>(value: &T) -> Self {
+        let mut hasher = DefaultHasher::new();
+        generation().hash(&mut hasher);
+        i18n::current().hash(&mut hasher);
+        preferences::current().hash(&mut hasher);
+        value.hash(&mut hasher);
+        Self(format!("\"{:016x}\"", hasher.finish()))
+    }
+
+    /// Strong ETag of the stored version of a contact, for the JSON API. Unlike `of` it
+    /// doesn't change on restart or with the locale, so it's the contact's CardDAV ETag.
+    ///
+    /// Example usage:
+    /// `ETag::version(&contact).attach(Json(contact).into_response())`
+    pub fn version(contact: &Contact) -> Self {
+        Self(carddav::etag(contact))
+    }
+
+    /// Whether the request's `If-Match` lists this ETag (or `*`). Weak ETags never match.
+    pub fn check_if_match(&self, headers: &HeaderMap) -> Result<(), Precondition> {
+        let mut tags = headers
+            .get_all(IF_MATCH)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .peekable();
+        if tags.peek().is_none() {
+            return Err(Precondition::Required);
+        }
+        if tags.any(|tag| self.matches_tag(tag)) {
+            Ok(())
+        } else {
+            Err(Precondition::Failed)
+        }
+    }
+
+    /// Whether `tag`, from an `If-Match` header or an API body, is this ETag (or `*`). In JSON
+    /// the quotes may be left off.
+    pub fn matches_tag(&self, tag: &str) -> bool {
+        let tag = tag.trim();
+        tag == "*" || tag == self.0 || self.0.strip_prefix('"').and_then(|etag| etag.strip_suffix('"')) == Some(tag)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether the request's `If-None-Match` lists this ETag (or `*`).
+    pub fn matches(&self, headers: &HeaderMap) -> bool {
+        headers
+            .get_all(IF_NONE_MATCH)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == "*" || tag == self.0)
+    }
+
+    /// `304 Not Modified` for a client that already has this version.
+    pub fn not_modified(&self) -> Response {
+        self.attach(StatusCode::NOT_MODIFIED.into_response())
+    }
+
+    /// Adds the ETag to a response, with `Cache-Control: no-cache` so browsers revalidate
+    /// instead of showing a stale copy.
+    pub fn attach(&self, mut response: Response) -> Response {
+        if let Ok(value) = HeaderValue::from_str(&self.0) {
+            response.headers_mut().insert(ETAG, value);
+        }
+        response
+            .headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        response
+    }
+}
@@ -0,0 +1,299 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::request::Parts,
+};
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::{clock::SharedClock, db::Db, jobs::JobHandler, preferences};
+#[cfg(feature = "postgres")]
+use crate::postgres;
+
+pub const CONTACT_CREATED: &str = "contact.created";
+pub const CONTACT_UPDATED: &str = "contact.updated";
+pub const CONTACT_DELETED: &str = "contact.deleted";
+pub const CONTACT_RESTORED: &str = "contact.restored";
+pub const RELATIONSHIP_ADDED: &str = "relationship.added";
+pub const RELATIONSHIP_REMOVED: &str = "relationship.removed";
+pub const DATE_ADDED: &str = "date.added";
+pub const DATE_REMOVED: &str = "date.removed";
+pub const ATTACHMENT_ADDED: &str = "attachment.added";
+pub const ATTACHMENT_REMOVED: &str = "attachment.removed";
+pub const SHARE_CREATED: &str = "share.created";
+pub const SHARE_REVOKED: &str = "share.revoked";
+pub const API_KEY_CREATED: &str = "api_key.created";
+pub const API_KEY_REVOKED: &str = "api_key.revoked";
+pub const API_KEY_LIMITS_CHANGED: &str = "api_key.limits_changed";
+
+/// Job kind that deletes events older than the payload's `older_than_days`.
+pub const PURGE_EVENTS_JOB: &str = "events.purge";
+
+/// Every event kind the app records, used for the filter on the admin events page.
+pub const KINDS: &[&str] = &[
+    CONTACT_CREATED,
+    CONTACT_UPDATED,
+    CONTACT_DELETED,
+    CONTACT_RESTORED,
+    RELATIONSHIP_ADDED,
+    RELATIONSHIP_REMOVED,
+    DATE_ADDED,
+    DATE_REMOVED,
+    ATTACHMENT_ADDED,
+    ATTACHMENT_REMOVED,
+    SHARE_CREATED,
+    SHARE_REVOKED,
+    API_KEY_CREATED,
+    API_KEY_REVOKED,
+    API_KEY_LIMITS_CHANGED,
+];
+
+/// Who triggered an event. There are no user accounts, so the client IP stands in for the
+/// actor.
+#[derive(Debug, Clone)]
+pub struct Actor(pub String);
+
+impl<S> FromRequestParts<S> for Actor
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let actor = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string())
+            .unwrap_or_else(|| "unknown".into());
+        Ok(Actor(actor))
+    }
+}
+
+/// A domain event, persisted in the `events` table. `payload` holds a JSON snapshot of
+/// whatever the event is about.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Event {
+    pub id: i64,
+    pub kind: String,
+    pub actor: String,
+    pub contact_id: Option<i64>,
+    pub payload: String,
+    pub created_at: String,
+}
+
+/// Filters for browsing the event log. Empty values mean "don't filter on this". `since` and
+/// `until` are inclusive `YYYY-MM-DD` dates.
+#[derive(Debug, Default, Deserialize)]
+pub struct EventFilter {
+    pub kind: Option<String>,
+    pub actor: Option<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+}
+
+/// Filters for the activity feed: one contact's changes, one actor's, or both. `before` is the
+/// id of the last event on the previous page, for the Older link.
+#[derive(Debug, Default, Deserialize)]
+pub struct ActivityFilter {
+    pub contact: Option<i64>,
+    pub actor: Option<String>,
+    pub before: Option<i64>,
+}
+
+impl EventFilter {
+    fn non_empty(value: &Option<String>) -> Option<&str> {
+        value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+    }
+}
+
+impl Event {
+    #[instrument(name = "events.record", skip(db, payload))]
+    pub async fn record(
+        db: &Db,
+        kind: &str,
+        actor: &str,
+        contact_id: Option<i64>,
+        payload: Value,
+    ) -> Result<(), sqlx::Error> {
+        let payload = payload.to_string();
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query!(
+                    "INSERT INTO events (kind, actor, contact_id, payload) VALUES (?, ?, ?, ?)",
+                    kind,
+                    actor,
+                    contact_id,
+                    payload,
+                )
+                .execute(pool)
+                .await
+                .map(|_| ())
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => {
+                postgres::record_event(pool, kind, actor, contact_id, &payload).await
+            }
+        }
+    }
+
+    /// Newest events first, narrowed down by the filter.
+    #[instrument(name = "events.list", skip(db))]
+    pub async fn list(db: &Db, filter: &EventFilter, limit: i64) -> Result<Vec<Event>, sqlx::Error> {
+        let kind = EventFilter::non_empty(&filter.kind);
+        let actor = EventFilter::non_empty(&filter.actor);
+        let since = EventFilter::non_empty(&filter.since);
+        let until = EventFilter::non_empty(&filter.until);
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    Event,
+                    r#"SELECT * FROM events
+                    WHERE (?1 IS NULL OR kind = ?1)
+                      AND (?2 IS NULL OR actor = ?2)
+                      AND (?3 IS NULL OR created_at >= date(?3))
+                      AND (?4 IS NULL OR created_at < date(?4, '+1 day'))
+                    ORDER BY id DESC
+                    LIMIT ?5"#,
+                    kind,
+                    actor,
+                    since,
+                    until,
+                    limit,
+                )
+                .fetch_all(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => {
+                postgres::list_events(pool, kind, actor, since, until, limit).await
+            }
+        }
+    }
+
+    /// Contact events after the one with id `after`, oldest first, at most `limit` of them.
+    /// For following the log, see `meilisearch`.
+    #[instrument(name = "events.contact_changes_after", skip(db))]
+    pub async fn contact_changes_after(db: &Db, after: i64, limit: i64) -> Result<Vec<Event>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    Event,
+                    r#"SELECT * FROM events
+                    WHERE id > ?1 AND kind LIKE 'contact.%'
+                    ORDER BY id
+                    LIMIT ?2"#,
+                    after,
+                    limit,
+                )
+                .fetch_all(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::contact_events_after(pool, after, limit).await,
+        }
+    }
+
+    /// Id of the newest event, 0 if there are none yet.
+    pub async fn last_id(db: &Db) -> Result<i64, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_scalar!(r#"SELECT COALESCE(MAX(id), 0) AS "id!: i64" FROM events"#)
+                    .fetch_one(pool)
+                    .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::last_event_id(pool).await,
+        }
+    }
+
+    /// Newest contact events first (creates, edits, deletes and restores), narrowed down by
+    /// the filter, for the activity feed.
+    #[instrument(name = "events.activity", skip(db))]
+    pub async fn activity(db: &Db, filter: &ActivityFilter, limit: i64) -> Result<Vec<Event>, sqlx::Error> {
+        let actor = EventFilter::non_empty(&filter.actor);
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    Event,
+                    r#"SELECT * FROM events
+                    WHERE kind LIKE 'contact.%'
+                      AND (?1 IS NULL OR contact_id = ?1)
+                      AND (?2 IS NULL OR actor = ?2)
+                      AND (?3 IS NULL OR id < ?3)
+                    ORDER BY id DESC
+                    LIMIT ?4"#,
+                    filter.contact,
+                    actor,
+                    filter.before,
+                    limit,
+                )
+                .fetch_all(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => {
+                postgres::list_activity(pool, filter.contact, actor, filter.before, limit).await
+            }
+        }
+    }
+
+    /// When the event happened, from the `YYYY-MM-DD HH:MM:SS` UTC text in `created_at`.
+    pub fn occurred_at(&self) -> Option<DateTime<Utc>> {
+        NaiveDateTime::parse_from_str(&self.created_at, "%Y-%m-%d %H:%M:%S")
+            .ok()
+            .map(|at| at.and_utc())
+    }
+
+    /// The name of the contact a contact event is about, from the snapshot in the payload, in
+    /// the current request's name format. It's still there after the contact is deleted.
+    pub fn contact_name(&self) -> Option<String> {
+        let payload: Value = serde_json::from_str(&self.payload).ok()?;
+        let first_name = payload["first_name"].as_str().unwrap_or_default();
+        let last_name = payload["last_name"].as_str().unwrap_or_default();
+        let name = preferences::current().name(first_name, last_name);
+        (!name.is_empty()).then_some(name)
+    }
+
+    /// Deletes events created before `before` (`YYYY-MM-DD HH:MM:SS`), returning how many.
+    #[instrument(name = "events.purge", skip(db))]
+    pub async fn purge_before(db: &Db, before: &str) -> Result<u64, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query!("DELETE FROM events WHERE created_at < ?", before)
+                    .execute(pool)
+                    .await
+                    .map(|r| r.rows_affected())
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::purge_events(pool, before).await,
+        }
+    }
+}
+
+/// Runs `PURGE_EVENTS_JOB` jobs.
+///
+/// Example usage:
+/// `jobs.enqueue(PURGE_EVENTS_JOB, json!({ "older_than_days": 90 })).await?;`
+pub struct PurgeEventsJob {
+    pub db: Db,
+    pub clock: SharedClock,
+}
+
+#[async_trait]
+impl JobHandler for PurgeEventsJob {
+    async fn run(&self, payload: &Value) -> anyhow::Result<String> {
+        let days = payload["older_than_days"]
+            .as_i64()
+            .filter(|days| *days >= 0)
+            .context("older_than_days must be a number of days")?;
+        let before = self.clock.now() - TimeDelta::days(days);
+        let before = before.format("%Y-%m-%d %H:%M:%S").to_string();
+        let purged = Event::purge_before(&self.db, &before).await?;
+        Ok(format!("Deleted {purged} event(s) from before {before}"))
+    }
+}
@@ -0,0 +1,233 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use csv::{Terminator, Writer, WriterBuilder};
+use serde::Deserialize;
+use tokio::{fs::File, io::AsyncWriteExt, sync::mpsc::Sender};
+use tokio_stream::StreamExt;
+
+use crate::{
+    backup,
+    contacts::{CREATED_AT_FORMAT, Contact, ContactCursor, ContactFilter, ContactOrder},
+    names::NameFormat,
+    repository::ContactRepository,
+    search::SearchBackend,
+    vcard,
+};
+
+/// Streamed exports are sent in chunks of about this many bytes.
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Filtered exports read the matching contacts in pages of this many rows.
+const SEARCH_PAGE_ROWS: i64 = 500;
+
+/// UTF-8 byte order mark. Excel needs it to read the file as UTF-8 instead of the system
+/// codepage.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// CSV flavour of an export.
+///
+/// `Standard` is plain RFC 4180 style CSV (comma, LF). `Excel` is what Excel expects in
+/// locales that use a decimal comma: semicolon delimited, CRLF line endings and a BOM, so the
+/// file opens with every field in its own column.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CsvDialect {
+    #[default]
+    Standard,
+    Excel,
+}
+
+impl CsvDialect {
+    fn delimiter(self) -> u8 {
+        match self {
+            CsvDialect::Standard => b',',
+            CsvDialect::Excel => b';',
+        }
+    }
+
+    fn terminator(self) -> Terminator {
+        match self {
+            CsvDialect::Standard => Terminator::Any(b'\n'),
+            CsvDialect::Excel => Terminator::CRLF,
+        }
+    }
+}
+
+/// The columns. `name` comes last, so files read by column position before it was added still
+/// line up.
+const CSV_HEADER: [&str; 9] = [
+    "id",
+    "first_name",
+    "last_name",
+    "phone_number",
+    "email",
+    "company",
+    "job_title",
+    "created_at",
+    "name",
+];
+
+/// CSV output in the given dialect, handed out in pieces as it is written so an export never
+/// has to be held in memory as a whole. The BOM (for Excel) and header row come first. The
+/// `name` column is written in `name_format`.
+pub struct CsvChunks {
+    dialect: CsvDialect,
+    name_format: NameFormat,
+    writer: Writer<Vec<u8>>,
+}
+
+impl CsvChunks {
+    pub fn new(dialect: CsvDialect, name_format: NameFormat) -> anyhow::Result<Self> {
+        let mut out = Vec::new();
+        if dialect == CsvDialect::Excel {
+            out.extend_from_slice(UTF8_BOM);
+        }
+        let mut writer = Self::writer(dialect, out);
+        writer.write_record(CSV_HEADER)?;
+        Ok(Self { dialect, name_format, writer })
+    }
+
+    fn writer(dialect: CsvDialect, out: Vec<u8>) -> Writer<Vec<u8>> {
+        WriterBuilder::new()
+            .delimiter(dialect.delimiter())
+            .terminator(dialect.terminator())
+            .from_writer(out)
+    }
+
+    pub fn write(&mut self, contact: &Contact) -> anyhow::Result<()> {
+        self.writer.write_record([
+            contact.id.to_string().as_str(),
+            &contact.first_name,
+            &contact.last_name,
+            &contact.phone_number,
+            &contact.email,
+            &contact.company,
+            &contact.job_title,
+            &contact.created_at.format(CREATED_AT_FORMAT).to_string(),
+            &self.name_format.format(&contact.first_name, &contact.last_name),
+        ])?;
+        Ok(())
+    }
+
+    /// Roughly how many bytes `take` would return.
+    pub fn buffered(&self) -> usize {
+        self.writer.get_ref().len()
+    }
+
+    /// Everything written since the last call.
+    pub fn take(&mut self) -> anyhow::Result<Vec<u8>> {
+        let writer = std::mem::replace(&mut self.writer, Self::writer(self.dialect, Vec::new()));
+        Ok(writer.into_inner()?)
+    }
+}
+
+/// Writes the contacts as CSV in the given dialect, header row included.
+pub fn write_csv(contacts: &[Contact], dialect: CsvDialect, name_format: NameFormat) -> anyhow::Result<Vec<u8>> {
+    let mut csv = CsvChunks::new(dialect, name_format)?;
+    for contact in contacts {
+        csv.write(contact)?;
+    }
+    csv.take()
+}
+
+/// Streams every contact as CSV into `chunks`, reading them from the database row by row.
+/// Stops early, without an error, if the receiver goes away (the client disconnected).
+///
+/// Example usage:
+/// `tokio::spawn(async move { export::stream_csv(contacts.as_ref(), dialect, name_format, tx).await })`
+pub async fn stream_csv(
+    contacts: &dyn ContactRepository,
+    dialect: CsvDialect,
+    name_format: NameFormat,
+    chunks: Sender<anyhow::Result<Vec<u8>>>,
+) -> anyhow::Result<()> {
+    let mut csv = CsvChunks::new(dialect, name_format)?;
+    let mut rows = contacts.stream_all();
+    while let Some(contact) = rows.next().await {
+        csv.write(&contact?)?;
+        if csv.buffered() >= CHUNK_SIZE && chunks.send(Ok(csv.take()?)).await.is_err() {
+            return Ok(());
+        }
+    }
+    let _ = chunks.send(Ok(csv.take()?)).await;
+    Ok(())
+}
+
+/// Streams the contacts matching `filter` as CSV into `chunks`, in `sort` order like the list
+/// shows them. The search backend has no row stream, so this goes through its results a page
+/// at a time, each one starting after the last contact of the one before.
+/// Stops early, without an error, if the receiver goes away.
+///
+/// Example usage:
+/// `tokio::spawn(async move { export::stream_matching_csv(search.as_ref(), &filter, sort, dialect, name_format, tx).await })`
+pub async fn stream_matching_csv(
+    search: &dyn SearchBackend,
+    filter: &ContactFilter,
+    sort: ContactOrder,
+    dialect: CsvDialect,
+    name_format: NameFormat,
+    chunks: Sender<anyhow::Result<Vec<u8>>>,
+) -> anyhow::Result<()> {
+    let mut csv = CsvChunks::new(dialect, name_format)?;
+    let mut after = None;
+    loop {
+        let page = search.search(filter, 1, SEARCH_PAGE_ROWS, sort, after.as_ref()).await?;
+        for contact in &page {
+            csv.write(contact)?;
+        }
+        if csv.buffered() >= CHUNK_SIZE && chunks.send(Ok(csv.take()?)).await.is_err() {
+            return Ok(());
+        }
+        match page.last() {
+            Some(last) if page.len() as i64 == SEARCH_PAGE_ROWS => after = Some(ContactCursor::after(last, sort)),
+            _ => break,
+        }
+    }
+    let _ = chunks.send(Ok(csv.take()?)).await;
+    Ok(())
+}
+
+/// Writes every contact to a file for `webone export <file>`: vCards for a `.vcf` path, CSV in
+/// `dialect` for anything else. Contacts are read row by row, like `stream_csv` does. With a
+/// `recipient` (an age public key, see `backup::encrypt`) only an encrypted `<file>.age` is
+/// kept. Returns how many contacts were written and the path they were written to.
+///
+/// Example usage:
+/// `let (count, path) = export::write_file(contacts.as_ref(), Path::new("contacts.csv"), CsvDialect::Standard, None).await?;`
+pub async fn write_file(
+    contacts: &dyn ContactRepository,
+    path: &Path,
+    dialect: CsvDialect,
+    recipient: Option<&str>,
+) -> anyhow::Result<(usize, PathBuf)> {
+    // Checked before anything is written
+    let recipient = recipient.map(backup::parse_recipient).transpose()?;
+    let vcards = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("vcf"));
+    let mut file = File::create(path)
+        .await
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut csv = CsvChunks::new(dialect, NameFormat::default())?;
+    let mut count = 0;
+    let mut rows = contacts.stream_all();
+    while let Some(contact) = rows.next().await {
+        let contact = contact?;
+        if vcards {
+            file.write_all(vcard::to_vcard(&contact).as_bytes()).await?;
+        } else {
+            csv.write(&contact)?;
+            if csv.buffered() >= CHUNK_SIZE {
+                file.write_all(&csv.take()?).await?;
+            }
+        }
+        count += 1;
+    }
+    if !vcards {
+        file.write_all(&csv.take()?).await?;
+    }
+    file.flush().await?;
+    drop(file);
+    match recipient {
+        Some(recipient) => Ok((count, backup::encrypt(path, recipient).await?)),
+        None => Ok((count, path.to_path_buf())),
+    }
+}
@@ -0,0 +1,39 @@
+//! Case and diacritic folding for search, so `jose` finds `José` and `dordevic` finds
+//! `Đorđević`. Contacts keep a folded copy of their searchable fields in `search_text`, written
+//! with every insert and update, and search terms are folded the same way before they are
+//! matched against it.
+
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
+
+/// `text` lowercased, with accents and other combining marks taken off the letters. Compatibility
+/// decomposition also turns ligatures and full-width letters into plain ones. Letters that carry
+/// their stroke or slash in the letter itself don't decompose, the common ones are mapped by
+/// hand.
+pub fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.nfkd().filter(|c| !is_combining_mark(*c)).flat_map(char::to_lowercase) {
+        match c {
+            'đ' => folded.push('d'),
+            'ł' => folded.push('l'),
+            'ø' => folded.push('o'),
+            'ı' => folded.push('i'),
+            'ß' => folded.push_str("ss"),
+            'æ' => folded.push_str("ae"),
+            'œ' => folded.push_str("oe"),
+            'þ' => folded.push_str("th"),
+            _ => folded.push(c),
+        }
+    }
+    folded
+}
+
+/// The `search_text` column for a contact with these fields: each one folded, separated by
+/// spaces, so a search for a whole name like `jose garcia` matches too.
+pub fn search_text(fields: &[&str]) -> String {
+    fields
+        .iter()
+        .filter(|field| !field.is_empty())
+        .map(|field| fold(field))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use askama::Template;
+use axum::{
+    body::Body,
+    extract::{FromRequest, Request},
+    http::{HeaderValue, Method, StatusCode, header::CONTENT_TYPE},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::de::DeserializeOwned;
+use tracing::warn;
+
+use crate::{
+    clock::{SharedClock, SystemClock},
+    htmx::Htmx,
+    i18n,
+    ids::{SharedIdGenerator, UuidGenerator},
+    templates::{BadRequestTemplate, ErrorMessageTemplate},
+};
+
+/// Largest form body accepted, set with `DefaultBodyLimit` on the routers that take forms.
+/// The biggest form, a contact, is a few hundred bytes; uploads have their own limit.
+pub const FORM_BODY_LIMIT: usize = 64 * 1024;
+
+/// How long an issued form token stays valid. Anything older is dropped the next time a
+/// token is issued.
+pub const FORM_TOKEN_TTL: TimeDelta = TimeDelta::hours(1);
+
+/// One-time tokens embedded in create forms. A token is issued when the form is rendered and
+/// consumed when the form is successfully submitted, so a double click or a refresh that
+/// resubmits the same form can't create the same contact twice.
+#[derive(Clone)]
+pub struct FormTokens {
+    clock: SharedClock,
+    ids: SharedIdGenerator,
+    issued: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
+}
+
+impl Default for FormTokens {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock), Arc::new(UuidGenerator))
+    }
+}
+
+impl FormTokens {
+    /// Tokens come from `ids` and expire by `clock`.
+    pub fn new(clock: SharedClock, ids: SharedIdGenerator) -> Self {
+        Self {
+            clock,
+            ids,
+            issued: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Issues a fresh token for a form that is about to be rendered.
+    pub fn issue(&self) -> String {
+        let token = self.ids.new_id();
+        let now = self.clock.now();
+        let mut issued = self.issued.lock().unwrap();
+        issued.retain(|_, at| now - *at < FORM_TOKEN_TTL);
+        issued.insert(token.clone(), now);
+        token
+    }
+
+    /// Consumes the token. Returns `false` if the token was never issued, has expired, or
+    /// has already been used.
+    pub fn consume(&self, token: &str) -> bool {
+        match self.issued.lock().unwrap().remove(token) {
+            Some(at) => self.clock.now() - at < FORM_TOKEN_TTL,
+            None => false,
+        }
+    }
+
+    /// Gives back a token whose submission failed after it was consumed, like a contact the
+    /// database refused, so the same form can be fixed and submitted again.
+    pub fn release(&self, token: &str) {
+        self.issued.lock().unwrap().insert(token.to_string(), self.clock.now());
+    }
+}
+
+/// Form field naming the method a POST stands for, see `method_override`.
+pub const METHOD_FIELD: &str = "_method";
+
+/// Middleware letting plain HTML forms, which can only GET and POST, use the other methods: a
+/// POST whose form body has `_method=PUT`, `PATCH` or `DELETE` is routed as that method. The
+/// body is passed on unchanged, so handlers ignore the field like any other they don't know.
+///
+/// Example usage:
+/// `<form method="post" action="/contacts/1"><input type="hidden" name="_method" value="DELETE">`
+pub async fn method_override(request: Request, next: Next) -> Response {
+    let is_form = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+    if request.method() != Method::POST || !is_form {
+        return next.run(request).await;
+    }
+    let htmx = Htmx::from_headers(request.headers());
+    let (mut parts, body) = request.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, FORM_BODY_LIMIT).await else {
+        let message = i18n::t("form-too-large");
+        return FormRejection { status: StatusCode::PAYLOAD_TOO_LARGE, message, htmx }.into_response();
+    };
+    let fields: Vec<(String, String)> = serde_urlencoded::from_bytes(&bytes).unwrap_or_default();
+    let method = fields
+        .iter()
+        .find(|(name, _)| name == METHOD_FIELD)
+        .and_then(|(_, value)| match value.to_ascii_uppercase().as_str() {
+            "PUT" => Some(Method::PUT),
+            "PATCH" => Some(Method::PATCH),
+            "DELETE" => Some(Method::DELETE),
+            _ => None,
+        });
+    if let Some(method) = method {
+        parts.method = method;
+    }
+    next.run(Request::from_parts(parts, Body::from(bytes))).await
+}
+
+/// `axum::Form`, but a body that is too large or can't be read is answered with an error
+/// people can read, in their language, instead of axum's plain text: an error fragment put into
+/// the layout's `#request-errors` for htmx, a full page otherwise. The status stays axum's
+/// (413, 415 or 422).
+///
+/// Example usage:
+/// `async fn post_settings(Form(form): Form<SettingsForm>) -> ...`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Form<T>(pub T);
+
+impl<T, S> FromRequest<S> for Form<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = FormRejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let htmx = Htmx::from_headers(req.headers());
+        match axum::Form::<T>::from_request(req, state).await {
+            Ok(axum::Form(value)) => Ok(Form(value)),
+            Err(rejection) => {
+                warn!("Rejected form: {}", rejection.body_text());
+                let status = rejection.status();
+                let message = if status == StatusCode::PAYLOAD_TOO_LARGE {
+                    i18n::t("form-too-large")
+                } else {
+                    i18n::t("form-invalid")
+                };
+                Err(FormRejection { status, message, htmx })
+            }
+        }
+    }
+}
+
+/// A form `Form` couldn't take, see there.
+#[derive(Debug)]
+pub struct FormRejection {
+    pub status: StatusCode,
+    pub message: String,
+    htmx: Htmx,
+}
+
+impl IntoResponse for FormRejection {
+    fn into_response(self) -> Response {
+        let fragment = self.htmx.wants_fragment();
+        let rendered = if fragment {
+            ErrorMessageTemplate {
+                error_message: self.message,
+            }
+            .render()
+        } else {
+            BadRequestTemplate {
+                error_message: self.message,
+            }
+            .render()
+        };
+        let Ok(html) = rendered else {
+            return (self.status, "Bad Request").into_response();
+        };
+        let mut response = (self.status, Html(html)).into_response();
+        if fragment {
+            // Wherever the form meant its response to go, the error goes to the layout's slot
+            let headers = response.headers_mut();
+            headers.insert("HX-Retarget", HeaderValue::from_static("#request-errors"));
+            headers.insert("HX-Reswap", HeaderValue::from_static("innerHTML"));
+        }
+        response
+    }
+}
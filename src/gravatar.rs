@@ -0,0 +1,48 @@
+//! Contact pictures from Gravatar (`gravatar.enabled`). Contacts have no pictures of their own,
+//! so the list and the contact page show the one Gravatar has for the email address, or an
+//! identicon made from it if there is none.
+//!
+//! The pictures are loaded by the browser, so with this on Gravatar learns which (hashed)
+//! addresses are looked at and by whom. It's off by default for that reason.
+//!
+//! Whether they are on is the app's setting, not the process's: the app's router runs every
+//! request in `scope` with its current `gravatar.enabled`, so a reload takes effect on the next
+//! request.
+
+use std::future::Future;
+
+use sha2::{Digest, Sha256};
+
+tokio::task_local! {
+    static ENABLED: bool;
+}
+
+/// Runs `f` with the pictures on or off. Outside it they are off.
+///
+/// Example usage:
+/// `gravatar::scope(config.gravatar.enabled, next.run(request)).await`
+pub async fn scope<F: Future>(enabled: bool, f: F) -> F::Output {
+    ENABLED.scope(enabled, f).await
+}
+
+/// Whether the pictures are on for the request being handled, to carry over to tasks it
+/// spawns.
+pub fn enabled() -> bool {
+    ENABLED.try_with(|enabled| *enabled).unwrap_or(false)
+}
+
+/// URL of the picture for `email`, `size` pixels square, or `None` when Gravatar is off or
+/// there is no email. The address is trimmed and lowercased and hashed with SHA-256, as
+/// Gravatar expects, so the address itself isn't sent.
+///
+/// Example usage:
+/// `{% if let Some(src) = crate::gravatar::url(&contact.email, 32) %}<img src="{{ src }}">{% endif %}`
+pub fn url(email: &str, size: u32) -> Option<String> {
+    let email = email.trim().to_lowercase();
+    if !enabled() || email.is_empty() {
+        return None;
+    }
+    let hash: String = Sha256::digest(email.as_bytes()).iter().map(|b| format!("{b:02x}")).collect();
+    // Twice the size, for high-density screens
+    Some(format!("https://www.gravatar.com/avatar/{hash}?s={}&d=identicon", size * 2))
+}
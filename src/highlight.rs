@@ -0,0 +1,72 @@
+//! Marks the words of a search where they occur in the text of a result, so the list shows why
+//! each contact matched. Words are found the way search finds them, ignoring case and accents
+//! (see `fold`), and marked in the text as it was written.
+
+use std::fmt::Write;
+
+use askama::filters::{Html, escape};
+
+use crate::fold;
+
+/// `text` as HTML, escaped, with every occurrence of a word of `search` in a `<mark>`. Words
+/// that overlap or touch are marked as one.
+///
+/// Example usage:
+/// `highlight::mark("José García", "jose")` is `<mark>José</mark> García`
+pub fn mark(text: &str, search: &str) -> String {
+    let words: Vec<Vec<char>> = search
+        .split_whitespace()
+        .map(|word| fold::fold(word).chars().collect::<Vec<_>>())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    // The folded text, with the byte range in `text` each folded character came from
+    let mut folded: Vec<char> = Vec::new();
+    let mut origins: Vec<(usize, usize)> = Vec::new();
+    for (start, c) in text.char_indices() {
+        for folded_char in fold::fold(c.encode_utf8(&mut [0; 4])).chars() {
+            folded.push(folded_char);
+            origins.push((start, start + c.len_utf8()));
+        }
+    }
+
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for word in &words {
+        for (i, window) in folded.windows(word.len()).enumerate() {
+            if window == word.as_slice() {
+                ranges.push((origins[i].0, origins[i + word.len() - 1].1));
+            }
+        }
+    }
+    ranges.sort_unstable();
+
+    let mut html = String::with_capacity(text.len());
+    let mut position = 0;
+    for (start, end) in merge(ranges) {
+        push_escaped(&mut html, &text[position..start]);
+        html.push_str("<mark>");
+        push_escaped(&mut html, &text[start..end]);
+        html.push_str("</mark>");
+        position = end;
+    }
+    push_escaped(&mut html, &text[position..]);
+    html
+}
+
+/// Sorted byte ranges with the overlapping and touching ones joined.
+fn merge(ranges: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    let mut merged: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn push_escaped(html: &mut String, text: &str) {
+    let Ok(escaped) = escape(text, Html);
+    // Writing into a String can't fail
+    let _ = write!(html, "{escaped}");
+}
@@ -0,0 +1,57 @@
+//! The htmx request headers, for handlers that answer htmx with a fragment and everything else
+//! (plain form posts and links without JavaScript) with a full page.
+
+use std::convert::Infallible;
+
+use axum::{
+    extract::FromRequestParts,
+    http::{HeaderMap, request::Parts},
+};
+
+/// What htmx told us about the request. Everything is empty for a request that didn't come
+/// from htmx, e.g. a browser with JavaScript disabled.
+///
+/// Example usage:
+/// `async fn post_new_contact(htmx: Htmx, ...) { if htmx.wants_fragment() { ... } }`
+#[derive(Debug, Clone, Default)]
+pub struct Htmx {
+    /// `HX-Request`: the request was made by htmx.
+    pub request: bool,
+    /// `HX-Boosted`: a boosted link or form, which swaps in the whole page.
+    pub boosted: bool,
+    /// `HX-Target`: id of the element the response goes into, if it has one.
+    pub target: Option<String>,
+}
+
+impl Htmx {
+    /// Reads the htmx headers, for code that has the headers but can't use the extractor.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        Htmx {
+            request: header("HX-Request") == Some("true"),
+            boosted: header("HX-Boosted").is_some(),
+            target: header("HX-Target").map(str::to_string),
+        }
+    }
+
+    /// Whether the response is swapped into part of the page, rather than shown as a page.
+    pub fn wants_fragment(&self) -> bool {
+        self.request && !self.boosted
+    }
+
+    /// Whether this is a (non-boosted) htmx request aimed at the element with this id.
+    pub fn targets(&self, id: &str) -> bool {
+        self.wants_fragment() && self.target.as_deref() == Some(id)
+    }
+}
+
+impl<S> FromRequestParts<S> for Htmx
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Htmx::from_headers(&parts.headers))
+    }
+}
@@ -0,0 +1,189 @@
+//! Translations of the user-facing text. Messages are in Fluent syntax in
+//! `locales/<locale>/main.ftl` and built into the binary.
+//!
+//! Every request gets a locale from the `lang` cookie (set by the language switcher), or else
+//! the best match for its `Accept-Language`, or English. The `scope` middleware makes it the
+//! current locale while the request is handled, so templates and error messages only need a
+//! message id: `{{ crate::i18n::t("action-save") }}`. Anything that runs outside a request,
+//! like jobs and emails, gets English.
+
+use std::{collections::HashMap, sync::LazyLock};
+
+use axum::{
+    extract::Request,
+    http::{
+        HeaderMap, HeaderValue,
+        header::{ACCEPT_LANGUAGE, CONTENT_LANGUAGE, COOKIE, VARY},
+    },
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use fluent_bundle::{FluentArgs, FluentResource, FluentValue, concurrent::FluentBundle};
+use fluent_langneg::{NegotiationStrategy, accepted_languages, negotiate_languages};
+use tracing::warn;
+use unic_langid::LanguageIdentifier;
+
+/// Locales there are messages for, with their names in their own language for the switcher.
+pub const LOCALES: &[(&str, &str)] = &[("en", "English"), ("sr", "Srpski")];
+/// Locale used when nothing better matches, and for messages a locale is missing.
+pub const DEFAULT_LOCALE: &str = "en";
+/// Cookie holding the locale picked in the switcher. It wins over `Accept-Language`.
+pub const LOCALE_COOKIE: &str = "lang";
+
+const SOURCES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en/main.ftl")),
+    ("sr", include_str!("../locales/sr/main.ftl")),
+];
+
+static BUNDLES: LazyLock<HashMap<&'static str, FluentBundle<FluentResource>>> = LazyLock::new(|| {
+    SOURCES
+        .iter()
+        .map(|&(locale, source)| {
+            let resource = FluentResource::try_new(source.to_string())
+                .unwrap_or_else(|(_, errors)| panic!("Invalid messages for {locale}: {errors:?}"));
+            let mut bundle = FluentBundle::new_concurrent(vec![langid(locale)]);
+            // The isolation marks Fluent puts around arguments end up verbatim in attributes
+            bundle.set_use_isolating(false);
+            bundle
+                .add_resource(resource)
+                .unwrap_or_else(|errors| panic!("Duplicate messages for {locale}: {errors:?}"));
+            (locale, bundle)
+        })
+        .collect()
+});
+
+tokio::task_local! {
+    static LOCALE: &'static str;
+}
+
+fn langid(locale: &str) -> LanguageIdentifier {
+    locale.parse().expect("locales in LOCALES are valid language identifiers")
+}
+
+/// The locale of the request being handled, `DEFAULT_LOCALE` outside of one.
+pub fn current() -> &'static str {
+    LOCALE.try_with(|locale| *locale).unwrap_or(DEFAULT_LOCALE)
+}
+
+/// Runs `f` as if handling a request in `locale`, for rendering outside of one.
+pub fn with_locale<R>(locale: &'static str, f: impl FnOnce() -> R) -> R {
+    LOCALE.sync_scope(locale, f)
+}
+
+/// `locale` if there are messages for it.
+pub fn supported(locale: &str) -> Option<&'static str> {
+    LOCALES
+        .iter()
+        .map(|&(supported, _)| supported)
+        .find(|supported| supported.eq_ignore_ascii_case(locale.trim()))
+}
+
+/// The message `id` in the current locale. Messages missing from it come from
+/// `DEFAULT_LOCALE`, and ones missing from there too come out as the id.
+///
+/// Example usage:
+/// `{{ crate::i18n::t("action-save") }}`
+pub fn t(id: &str) -> String {
+    format(id, None)
+}
+
+/// `t` for a message with one argument.
+///
+/// Example usage:
+/// `{{ crate::i18n::t_with("delete-confirm", "name", contact.full_name()) }}`
+pub fn t_with<'a>(id: &str, name: &'a str, value: impl Into<FluentValue<'a>>) -> String {
+    t_args(id, &[(name, value.into())])
+}
+
+/// `t` for a message with any number of arguments.
+///
+/// Example usage:
+/// `i18n::t_args("pagination-status", &[("page", page.into()), ("pages", pages.into())])`
+pub fn t_args(id: &str, args: &[(&str, FluentValue)]) -> String {
+    let args = args.iter().map(|(name, value)| (*name, value.clone())).collect();
+    format(id, Some(&args))
+}
+
+/// How long before `now` `at` was, like "3 days ago", in whole minutes, hours, days, months
+/// (of 30 days) or years, whichever is the largest. Under a minute, and the future, is "just
+/// now".
+///
+/// Example usage:
+/// `i18n::relative_time(&contact.created_at, &state.clock.now())`
+pub fn relative_time(at: &DateTime<Utc>, now: &DateTime<Utc>) -> String {
+    let elapsed = *now - *at;
+    let (id, count) = if elapsed < TimeDelta::minutes(1) {
+        return t("time-just-now");
+    } else if elapsed < TimeDelta::hours(1) {
+        ("time-minutes-ago", elapsed.num_minutes())
+    } else if elapsed < TimeDelta::days(1) {
+        ("time-hours-ago", elapsed.num_hours())
+    } else if elapsed < TimeDelta::days(30) {
+        ("time-days-ago", elapsed.num_days())
+    } else if elapsed < TimeDelta::days(365) {
+        ("time-months-ago", elapsed.num_days() / 30)
+    } else {
+        ("time-years-ago", elapsed.num_days() / 365)
+    };
+    t_with(id, "count", count)
+}
+
+fn format(id: &str, args: Option<&FluentArgs>) -> String {
+    let formatted = [current(), DEFAULT_LOCALE].into_iter().find_map(|locale| {
+        let bundle = BUNDLES.get(locale)?;
+        let pattern = bundle.get_message(id)?.value()?;
+        let mut errors = Vec::new();
+        let text = bundle.format_pattern(pattern, args, &mut errors);
+        if !errors.is_empty() {
+            warn!("Errors formatting message {id} in {locale}: {errors:?}");
+        }
+        Some(text.into_owned())
+    });
+    formatted.unwrap_or_else(|| {
+        warn!("No message {id}");
+        id.to_string()
+    })
+}
+
+/// The locale for a request: the `lang` cookie if it names a supported one, else the best
+/// match for `Accept-Language`, else `DEFAULT_LOCALE`.
+pub fn negotiate(headers: &HeaderMap) -> &'static str {
+    let cookie = headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == LOCALE_COOKIE)
+        .and_then(|(_, value)| supported(value));
+    if let Some(locale) = cookie {
+        return locale;
+    }
+
+    let requested = headers
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .map(accepted_languages::parse)
+        .unwrap_or_default();
+    let available: Vec<LanguageIdentifier> = LOCALES.iter().map(|&(locale, _)| langid(locale)).collect();
+    let default = langid(DEFAULT_LOCALE);
+    negotiate_languages(&requested, &available, Some(&default), NegotiationStrategy::Filtering)
+        .first()
+        .and_then(|best| supported(best.language.as_str()))
+        .unwrap_or(DEFAULT_LOCALE)
+}
+
+/// Middleware: handles the request in its negotiated locale, and says which one it was in
+/// `Content-Language`.
+///
+/// Example usage:
+/// `router.layer(middleware::from_fn(i18n::scope))`
+pub async fn scope(request: Request, next: Next) -> Response {
+    let locale = negotiate(request.headers());
+    let mut response = LOCALE.scope(locale, next.run(request)).await;
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_LANGUAGE, HeaderValue::from_static(locale));
+    headers.append(VARY, HeaderValue::from_static("Accept-Language, Cookie"));
+    response
+}
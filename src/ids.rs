@@ -0,0 +1,47 @@
+//! Generation of random identifiers (form tokens), as a dependency in `AppState` like the
+//! `Clock`, so tests can predict them.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
+use uuid::Uuid;
+
+pub trait IdGenerator: Send + Sync {
+    fn new_id(&self) -> String;
+}
+
+pub type SharedIdGenerator = Arc<dyn IdGenerator>;
+
+/// Random v4 UUIDs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UuidGenerator;
+
+impl IdGenerator for UuidGenerator {
+    fn new_id(&self) -> String {
+        Uuid::new_v4().to_string()
+    }
+}
+
+/// `<prefix>-1`, `<prefix>-2`, ... in order.
+#[derive(Debug)]
+pub struct SequentialIds {
+    prefix: String,
+    next: AtomicU64,
+}
+
+impl SequentialIds {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            next: AtomicU64::new(1),
+        }
+    }
+}
+
+impl IdGenerator for SequentialIds {
+    fn new_id(&self) -> String {
+        format!("{}-{}", self.prefix, self.next.fetch_add(1, Ordering::SeqCst))
+    }
+}
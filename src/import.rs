@@ -0,0 +1,605 @@
+//! Creating many contacts at once: the batch API (`POST /api/v1/contacts/batch`) and
+//! `webone import <file>`.
+//!
+//! Files are read by their extension: `.csv` with a header row naming the columns (the
+//! export's columns, so an export can be imported again; `id`, `created_at` and `name` are
+//! ignored, or those of a Google Contacts or Outlook export, see `CsvProfile`), `.json` with an array like the batch API takes, and `.vcf` with one or more
+//! vCards. Either way every contact is checked like the create form does, and the valid ones
+//! are stored together.
+//!
+//! The import page (`/contacts/import`) takes CSV files with any columns: the upload is kept in
+//! `PendingImports` while its columns are matched to contact fields by hand, starting from
+//! what `CsvProfile::detect` makes of them or a mapping saved from an earlier import
+//! (`SavedMapping`).
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use csv::{Reader, ReaderBuilder, StringRecord};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    clock::SharedClock,
+    contacts::{NewContact, NewContactErrors},
+    db::Db,
+    events::{self, Event},
+    i18n,
+    jobs::Jobs,
+    repository::ContactRepository,
+    vcard, webhooks,
+};
+#[cfg(feature = "postgres")]
+use crate::postgres;
+
+/// Actor of the events recorded for contacts created by `webone import`.
+pub const ACTOR: &str = "cli-import";
+/// Largest file the import page takes.
+pub const MAX_UPLOAD_BYTES: usize = 5 * 1024 * 1024;
+/// Uploads waiting to be mapped at most. Past it, the oldest is dropped.
+const MAX_PENDING_IMPORTS: usize = 16;
+/// How long an upload waits to be mapped.
+const PENDING_IMPORT_TTL: Duration = Duration::from_secs(60 * 60);
+/// Longest name a saved mapping can have, in characters.
+pub const MAX_MAPPING_NAME_LEN: usize = 100;
+
+/// What happened to one contact of a batch: created with `id`, or not because of `errors`.
+/// In a dry run (see `check_all`) neither is set for a contact that would be created.
+#[derive(Serialize, Debug)]
+pub struct ImportResult {
+    /// Position of the contact in the request or file.
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<NewContactErrors>,
+}
+
+/// Validates each contact like the create form does and stores the valid ones in a single
+/// transaction (see `Contact::create_many`), recording an event (and queueing webhooks) as
+/// `actor` for each one created. Returns a result per contact, in the order given: its new
+/// `id`, or the `errors` by field, including "already exists" for an email or phone number
+/// another contact (or an earlier one in the batch) has.
+///
+/// Example usage:
+/// `let results = import::create_all(contacts.as_ref(), &db, &jobs, &clock, &actor, new_contacts).await?;`
+pub async fn create_all(
+    contacts: &dyn ContactRepository,
+    db: &Db,
+    jobs: &Jobs,
+    clock: &SharedClock,
+    actor: &str,
+    new_contacts: Vec<NewContact>,
+) -> anyhow::Result<Vec<ImportResult>> {
+    let (mut results, valid, valid_indexes) = validate_all(new_contacts);
+    let stored = contacts.create_many(&valid, clock.now()).await?;
+    for (index, result) in valid_indexes.into_iter().zip(stored) {
+        match result {
+            Ok(contact) => {
+                let payload = serde_json::to_value(&contact)?;
+                Event::record(db, events::CONTACT_CREATED, actor, Some(contact.id), payload.clone()).await?;
+                webhooks::fire(db, jobs, events::CONTACT_CREATED, contact.id, &payload, clock.now()).await?;
+                results.push(ImportResult { index, id: Some(contact.id), errors: None });
+            }
+            Err(field) => results.push(ImportResult { index, id: None, errors: Some(NewContactErrors::taken(field)) }),
+        }
+    }
+    results.sort_by_key(|result| result.index);
+    Ok(results)
+}
+
+/// Dry run of `create_all`: checks each contact the same way, against the database too (see
+/// `ContactRepository::check_many`), without storing any or recording events. A contact that
+/// would be created gets a result with neither `id` nor `errors`.
+///
+/// Example usage:
+/// `let results = import::check_all(contacts.as_ref(), &clock, new_contacts).await?;`
+pub async fn check_all(
+    contacts: &dyn ContactRepository,
+    clock: &SharedClock,
+    new_contacts: Vec<NewContact>,
+) -> anyhow::Result<Vec<ImportResult>> {
+    let (mut results, valid, valid_indexes) = validate_all(new_contacts);
+    let conflicts = contacts.check_many(&valid, clock.now()).await?;
+    for (index, conflict) in valid_indexes.into_iter().zip(conflicts) {
+        let errors = conflict.map(NewContactErrors::taken);
+        results.push(ImportResult { index, id: None, errors });
+    }
+    results.sort_by_key(|result| result.index);
+    Ok(results)
+}
+
+/// Validates each contact like the create form does. Returns the results of the invalid ones
+/// and the valid ones along with their positions.
+fn validate_all(new_contacts: Vec<NewContact>) -> (Vec<ImportResult>, Vec<NewContact>, Vec<usize>) {
+    let mut results = Vec::with_capacity(new_contacts.len());
+    let (mut valid, mut valid_indexes) = (Vec::new(), Vec::new());
+    for (index, contact) in new_contacts.into_iter().enumerate() {
+        match contact.validate() {
+            Some(errors) => results.push(ImportResult { index, id: None, errors: Some(errors) }),
+            None => {
+                valid.push(contact);
+                valid_indexes.push(index);
+            }
+        }
+    }
+    (results, valid, valid_indexes)
+}
+
+/// A contact field a CSV column can be read into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContactField {
+    FirstName,
+    LastName,
+    PhoneNumber,
+    Email,
+    Company,
+    JobTitle,
+}
+
+impl ContactField {
+    pub const ALL: [ContactField; 6] = [
+        ContactField::FirstName,
+        ContactField::LastName,
+        ContactField::PhoneNumber,
+        ContactField::Email,
+        ContactField::Company,
+        ContactField::JobTitle,
+    ];
+
+    /// The field's form name, as in `NewContact`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ContactField::FirstName => "first_name",
+            ContactField::LastName => "last_name",
+            ContactField::PhoneNumber => "phone_number",
+            ContactField::Email => "email",
+            ContactField::Company => "company",
+            ContactField::JobTitle => "job_title",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|field| field.as_str() == value)
+    }
+
+    /// The field's name in the current locale.
+    pub fn label(self) -> String {
+        i18n::t(match self {
+            ContactField::FirstName => "field-first-name",
+            ContactField::LastName => "field-last-name",
+            ContactField::PhoneNumber => "field-phone-number",
+            ContactField::Email => "field-email",
+            ContactField::Company => "field-company",
+            ContactField::JobTitle => "field-job-title",
+        })
+    }
+}
+
+/// Which columns of a CSV file hold which contact field, `webone import --profile`. Left out,
+/// the profile is picked by the header row (see `CsvProfile::detect`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CsvProfile {
+    /// The export's own columns: `first_name`, `last_name`, `phone_number` and so on.
+    Webone,
+    /// Google Contacts' "Google CSV" export, the current one or the older one.
+    Google,
+    /// Outlook's CSV export, from the desktop app or Outlook.com.
+    Outlook,
+}
+
+/// Columns a field may be in, in order of preference. Headers match ignoring case, and the
+/// first column with a value wins, so a contact without a mobile number gets its work one.
+struct ColumnMap {
+    first_name: &'static [&'static str],
+    last_name: &'static [&'static str],
+    phone_number: &'static [&'static str],
+    email: &'static [&'static str],
+    company: &'static [&'static str],
+    job_title: &'static [&'static str],
+}
+
+impl ColumnMap {
+    fn names(&self, field: ContactField) -> &'static [&'static str] {
+        match field {
+            ContactField::FirstName => self.first_name,
+            ContactField::LastName => self.last_name,
+            ContactField::PhoneNumber => self.phone_number,
+            ContactField::Email => self.email,
+            ContactField::Company => self.company,
+            ContactField::JobTitle => self.job_title,
+        }
+    }
+}
+
+const WEBONE_COLUMNS: ColumnMap = ColumnMap {
+    first_name: &["first_name"],
+    last_name: &["last_name"],
+    phone_number: &["phone_number"],
+    email: &["email"],
+    company: &["company"],
+    job_title: &["job_title"],
+};
+
+const GOOGLE_COLUMNS: ColumnMap = ColumnMap {
+    first_name: &["First Name", "Given Name"],
+    last_name: &["Last Name", "Family Name"],
+    phone_number: &["Phone 1 - Value", "Phone 2 - Value", "Phone 3 - Value"],
+    email: &["E-mail 1 - Value", "E-mail 2 - Value", "E-mail 3 - Value"],
+    company: &["Organization Name", "Organization 1 - Name"],
+    job_title: &["Organization Title", "Organization 1 - Title"],
+};
+
+const OUTLOOK_COLUMNS: ColumnMap = ColumnMap {
+    first_name: &["First Name"],
+    last_name: &["Last Name"],
+    phone_number: &["Mobile Phone", "Primary Phone", "Business Phone", "Home Phone", "Other Phone"],
+    email: &["E-mail Address", "E-mail 2 Address", "E-mail 3 Address"],
+    company: &["Company"],
+    job_title: &["Job Title"],
+};
+
+/// Google puts every value of a field with several in one column, like
+/// `+1 206 555 0100 ::: +1 206 555 0199`.
+const GOOGLE_SEPARATOR: &str = " ::: ";
+
+impl CsvProfile {
+    /// The profile whose columns `headers` has: Google's and Outlook's by columns only their
+    /// exports have, our own otherwise.
+    pub fn detect(headers: &StringRecord) -> Self {
+        let has = |name: &str| headers.iter().any(|header| header.trim().eq_ignore_ascii_case(name));
+        if has("E-mail 1 - Value") || has("Phone 1 - Value") || has("Given Name") {
+            CsvProfile::Google
+        } else if has("E-mail Address") || has("Business Phone") || has("Mobile Phone") {
+            CsvProfile::Outlook
+        } else {
+            CsvProfile::Webone
+        }
+    }
+
+    fn columns(self) -> &'static ColumnMap {
+        match self {
+            CsvProfile::Webone => &WEBONE_COLUMNS,
+            CsvProfile::Google => &GOOGLE_COLUMNS,
+            CsvProfile::Outlook => &OUTLOOK_COLUMNS,
+        }
+    }
+}
+
+/// Which header goes into which field, as picked on the import page. Headers that aren't in it
+/// are ignored. Several can go into one field, and then the first one with a value wins.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ColumnMapping(pub BTreeMap<String, ContactField>);
+
+impl ColumnMapping {
+    /// What `profile` reads each of `headers` as, to start the import page from.
+    pub fn from_profile(profile: CsvProfile, headers: &[String]) -> Self {
+        let columns = profile.columns();
+        let mapping = headers
+            .iter()
+            .filter_map(|header| {
+                let field = ContactField::ALL.into_iter().find(|&field| {
+                    columns
+                        .names(field)
+                        .iter()
+                        .any(|name| header.trim().eq_ignore_ascii_case(name))
+                })?;
+                Some((header.clone(), field))
+            })
+            .collect();
+        Self(mapping)
+    }
+
+    /// The field `header` goes into, if any.
+    pub fn field(&self, header: &str) -> Option<ContactField> {
+        self.0.get(header).copied()
+    }
+}
+
+/// The column indexes each field is read from, in order of preference, by `ContactField`.
+struct FieldColumns([Vec<usize>; 6]);
+
+impl FieldColumns {
+    /// The columns `profile` reads, in its order of preference.
+    fn from_profile(profile: CsvProfile, headers: &StringRecord) -> Self {
+        let columns = profile.columns();
+        Self(ContactField::ALL.map(|field| {
+            columns
+                .names(field)
+                .iter()
+                .filter_map(|name| headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name)))
+                .collect()
+        }))
+    }
+
+    /// The columns `mapping` puts in each field, in the file's order.
+    fn from_mapping(mapping: &ColumnMapping, headers: &StringRecord) -> Self {
+        Self(ContactField::ALL.map(|field| {
+            headers
+                .iter()
+                .enumerate()
+                .filter(|(_, header)| mapping.field(header) == Some(field))
+                .map(|(index, _)| index)
+                .collect()
+        }))
+    }
+
+    /// The first value of `field` in `record` that isn't empty.
+    fn value(&self, field: ContactField, record: &StringRecord) -> String {
+        self.0[field as usize]
+            .iter()
+            .filter_map(|&index| record.get(index))
+            .map(|value| value.split(GOOGLE_SEPARATOR).next().unwrap_or_default().trim())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+/// A reader for CSV `text`, with its header row. The delimiter is a semicolon if the header
+/// row has one and no comma, as in exports for Excel, and a leading byte order mark is skipped.
+fn csv_reader(text: &str) -> anyhow::Result<(StringRecord, Reader<&[u8]>)> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let header = text.lines().next().unwrap_or_default();
+    let delimiter = if header.contains(';') && !header.contains(',') { b';' } else { b',' };
+    let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let headers = reader.headers().context("The header row is unreadable")?.clone();
+    Ok((headers, reader))
+}
+
+/// The contacts in the rest of the file, with `columns`. Columns that are missing are empty.
+fn read_records(mut reader: Reader<&[u8]>, columns: &FieldColumns) -> anyhow::Result<Vec<NewContact>> {
+    reader
+        .records()
+        .enumerate()
+        .map(|(index, record)| {
+            let record = record.with_context(|| format!("Row {} is unreadable", index + 1))?;
+            let value = |field| columns.value(field, &record);
+            Ok(NewContact {
+                first_name: value(ContactField::FirstName),
+                last_name: value(ContactField::LastName),
+                phone_number: value(ContactField::PhoneNumber),
+                email: value(ContactField::Email),
+                company: value(ContactField::Company),
+                job_title: value(ContactField::JobTitle),
+                errors: None,
+                form_token: None,
+            })
+        })
+        .collect()
+}
+
+/// The contacts in a CSV file, by `profile` or the one its header row looks like. Columns that
+/// aren't contact fields are ignored.
+fn read_csv(text: &str, profile: Option<CsvProfile>) -> anyhow::Result<Vec<NewContact>> {
+    let (headers, reader) = csv_reader(text)?;
+    let profile = profile.unwrap_or_else(|| CsvProfile::detect(&headers));
+    info!("Reading the CSV file with the {profile:?} columns");
+    read_records(reader, &FieldColumns::from_profile(profile, &headers))
+}
+
+/// The contacts in a CSV file, with the columns `mapping` picks.
+pub fn read_csv_mapped(text: &str, mapping: &ColumnMapping) -> anyhow::Result<Vec<NewContact>> {
+    let (headers, reader) = csv_reader(text)?;
+    read_records(reader, &FieldColumns::from_mapping(mapping, &headers))
+}
+
+/// The start of a CSV file, for mapping its columns.
+#[derive(Debug, Clone)]
+pub struct CsvPreview {
+    pub headers: Vec<String>,
+    /// The first few rows, as many values as there are headers each.
+    pub rows: Vec<Vec<String>>,
+    /// The profile the headers look like.
+    pub profile: CsvProfile,
+}
+
+impl CsvPreview {
+    /// The values in the column at `index`, for showing next to its header.
+    pub fn samples(&self, index: usize) -> Vec<&str> {
+        self.rows
+            .iter()
+            .map(|row| row.get(index).map(String::as_str).unwrap_or_default())
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+}
+
+/// The headers and first `rows` rows of CSV `text`. Fails if the headers can't be read or there
+/// aren't any.
+pub fn preview(text: &str, rows: usize) -> anyhow::Result<CsvPreview> {
+    let (headers, mut reader) = csv_reader(text)?;
+    anyhow::ensure!(headers.iter().any(|header| !header.trim().is_empty()), "The file has no header row");
+    let rows = reader
+        .records()
+        .take(rows)
+        .filter_map(Result::ok)
+        .map(|record| (0..headers.len()).map(|index| record.get(index).unwrap_or_default().to_string()).collect())
+        .collect();
+    Ok(CsvPreview {
+        headers: headers.iter().map(str::to_string).collect(),
+        rows,
+        profile: CsvProfile::detect(&headers),
+    })
+}
+
+/// A column mapping saved on the import page under a name, for files of the same shape.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct SavedMapping {
+    pub id: i64,
+    pub name: String,
+    /// `ColumnMapping` as JSON.
+    pub columns: String,
+    pub created_at: String,
+}
+
+impl SavedMapping {
+    /// The mapping. One that can't be read any more maps nothing.
+    pub fn mapping(&self) -> ColumnMapping {
+        serde_json::from_str(&self.columns).unwrap_or_default()
+    }
+
+    /// Every saved mapping, by name.
+    pub async fn list(db: &Db) -> Result<Vec<SavedMapping>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    SavedMapping,
+                    r#"SELECT id AS "id!", name, columns, created_at FROM import_mappings ORDER BY name"#
+                )
+                    .fetch_all(pool)
+                    .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::list_import_mappings(pool).await,
+        }
+    }
+
+    /// Saves `mapping` as `name`, replacing the one saved under that name before.
+    pub async fn save(db: &Db, name: &str, mapping: &ColumnMapping) -> anyhow::Result<SavedMapping> {
+        let columns = serde_json::to_string(mapping)?;
+        let saved = match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    SavedMapping,
+                    r#"INSERT INTO import_mappings (name, columns) VALUES (?, ?)
+                    ON CONFLICT (name) DO UPDATE SET columns = excluded.columns
+                    RETURNING id AS "id!", name, columns, created_at"#,
+                    name,
+                    columns,
+                )
+                .fetch_one(pool)
+                .await?
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::save_import_mapping(pool, name, &columns).await?,
+        };
+        Ok(saved)
+    }
+}
+
+/// A CSV file uploaded on the import page.
+#[derive(Debug, Clone)]
+pub struct PendingImport {
+    pub filename: String,
+    pub text: Arc<str>,
+    uploaded_at: Instant,
+}
+
+/// Uploads waiting for their columns to be mapped, by a random token. Kept in memory for up to
+/// an hour, and only the latest few. Cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct PendingImports {
+    uploads: Arc<Mutex<HashMap<String, PendingImport>>>,
+}
+
+impl PendingImports {
+    /// Keeps `text` for mapping. Returns the token to get it back with.
+    pub fn add(&self, filename: String, text: String) -> String {
+        let mut uploads = self.uploads.lock().unwrap();
+        uploads.retain(|_, upload| upload.uploaded_at.elapsed() < PENDING_IMPORT_TTL);
+        if uploads.len() >= MAX_PENDING_IMPORTS
+            && let Some(oldest) = uploads.iter().min_by_key(|(_, upload)| upload.uploaded_at).map(|(token, _)| token.clone())
+        {
+            uploads.remove(&oldest);
+        }
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        uploads.insert(
+            token.clone(),
+            PendingImport {
+                filename,
+                text: text.into(),
+                uploaded_at: Instant::now(),
+            },
+        );
+        token
+    }
+
+    /// The upload, unless it was imported or expired.
+    pub fn get(&self, token: &str) -> Option<PendingImport> {
+        let uploads = self.uploads.lock().unwrap();
+        uploads
+            .get(token)
+            .filter(|upload| upload.uploaded_at.elapsed() < PENDING_IMPORT_TTL)
+            .cloned()
+    }
+
+    /// Drops the upload once it's imported.
+    pub fn remove(&self, token: &str) {
+        self.uploads.lock().unwrap().remove(token);
+    }
+}
+
+/// The contacts in a `.csv`, `.json` or `.vcf` file, CSV columns by `profile` (see
+/// `read_csv`). Fails on a file that can't be read as a whole, naming the first record that
+/// can't be.
+pub async fn read_file(path: &Path, profile: Option<CsvProfile>) -> anyhow::Result<Vec<NewContact>> {
+    let text = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+    let contacts = match extension.as_str() {
+        "csv" => read_csv(&text, profile)?,
+        "json" => serde_json::from_str(&text).context("Expected an array of contacts")?,
+        "vcf" | "vcard" => vcard::parse_all(&text)?.into_iter().map(NewContact::from).collect(),
+        _ => anyhow::bail!("Can't tell what {} holds, name it .csv, .json or .vcf", path.display()),
+    };
+    Ok(contacts)
+}
+
+/// Imports a file: reads it with `read_file` and creates its contacts with `create_all`, or
+/// with `dry_run` only checks them with `check_all`. Returns the report, a count line followed
+/// by a line per contact that wasn't (or wouldn't be) created.
+///
+/// Example usage:
+/// `let report = import::run(contacts.as_ref(), &db, &jobs, &clock, Path::new("contacts.csv"), None, false).await?;`
+pub async fn run(
+    contacts: &dyn ContactRepository,
+    db: &Db,
+    jobs: &Jobs,
+    clock: &SharedClock,
+    path: &Path,
+    profile: Option<CsvProfile>,
+    dry_run: bool,
+) -> anyhow::Result<String> {
+    let new_contacts = read_file(path, profile).await?;
+    let names: Vec<String> = new_contacts
+        .iter()
+        .map(|c| format!("{} {} <{}>", c.first_name, c.last_name, c.email))
+        .collect();
+    if dry_run {
+        let results = check_all(contacts, clock, new_contacts).await?;
+        let created = results.iter().filter(|result| result.errors.is_none()).count();
+        info!(dry_run, "Checked {}: {created} of {} contacts would be created", path.display(), results.len());
+        let count = format!(
+            "Dry run, nothing changed. {created} would be created, {} would fail",
+            results.len() - created
+        );
+        return Ok(report(count, &results, &names));
+    }
+    let results = create_all(contacts, db, jobs, clock, ACTOR, new_contacts).await?;
+    let created = results.iter().filter(|result| result.id.is_some()).count();
+    info!("Imported {}: {created} of {} contacts created", path.display(), results.len());
+    Ok(report(format!("{created} created, {} failed", results.len() - created), &results, &names))
+}
+
+/// `count` followed by a line per contact of `results` with errors, named by `names`.
+fn report(count: String, results: &[ImportResult], names: &[String]) -> String {
+    let mut lines = vec![count];
+    for result in results {
+        if let Some(errors) = &result.errors {
+            lines.push(format!("- #{} {}: {}", result.index + 1, names[result.index], errors.summary()));
+        }
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,300 @@
+//! Background jobs. Work that is too slow for a request (backups, purging old events) is
+//! written to the `jobs` table and picked up by a small pool of worker tasks, so it survives a
+//! restart and can be retried when it fails.
+//!
+//! A job has a `kind` and a JSON payload. Each kind has a `JobHandler` registered with
+//! `Jobs::register`; enqueueing a kind nobody handles fails the job right away. A failed
+//! attempt is retried after `JOB_RETRY_BACKOFF`, doubling each time, until the job has used up
+//! its `max_attempts`. Jobs that were running when the server stopped are queued again on the
+//! next start.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde_json::Value;
+use tokio::sync::Notify;
+use tracing::{error, info, instrument, warn};
+
+use crate::{clock::SharedClock, db::Db};
+
+#[cfg(feature = "postgres")]
+use crate::postgres;
+
+/// Number of worker tasks, i.e. how many jobs run at the same time.
+pub const JOB_WORKERS: usize = 2;
+/// How often idle workers look for jobs that became due, such as retries. New jobs wake them
+/// up right away.
+pub const JOB_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Wait before the first retry of a failed job. Doubles with every further attempt.
+pub const JOB_RETRY_BACKOFF: TimeDelta = TimeDelta::seconds(30);
+/// Attempts a job gets unless enqueued with a different limit.
+pub const DEFAULT_MAX_ATTEMPTS: i64 = 3;
+/// Format of the timestamp columns. Sorts the same as the times it represents.
+pub const JOB_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub const QUEUED: &str = "queued";
+pub const RUNNING: &str = "running";
+pub const DONE: &str = "done";
+pub const FAILED: &str = "failed";
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Job {
+    pub id: i64,
+    pub kind: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i64,
+    pub max_attempts: i64,
+    /// What the last attempt returned, or its error.
+    pub outcome: Option<String>,
+    pub run_at: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Does the work for one kind of job. The returned text is shown as the job's outcome on the
+/// admin page; an error fails the attempt.
+#[async_trait]
+pub trait JobHandler: Send + Sync {
+    async fn run(&self, payload: &Value) -> anyhow::Result<String>;
+}
+
+/// The job queue: enqueues jobs and runs them on worker tasks.
+///
+/// Example usage:
+/// `let jobs = Jobs::new(db.clone(), clock.clone()).register(backup::BACKUP_JOB, handler);`
+/// `jobs.start(JOB_WORKERS).await?;`
+/// `jobs.enqueue(backup::BACKUP_JOB, json!({})).await?;`
+#[derive(Clone)]
+pub struct Jobs {
+    db: Db,
+    clock: SharedClock,
+    handlers: Arc<HashMap<&'static str, Arc<dyn JobHandler>>>,
+    wake: Arc<Notify>,
+}
+
+impl Jobs {
+    pub fn new(db: Db, clock: SharedClock) -> Self {
+        Self {
+            db,
+            clock,
+            handlers: Arc::default(),
+            wake: Arc::default(),
+        }
+    }
+
+    /// Registers the handler for jobs of `kind`. Handlers have to be registered before `start`.
+    pub fn register(mut self, kind: &'static str, handler: impl JobHandler + 'static) -> Self {
+        Arc::make_mut(&mut self.handlers).insert(kind, Arc::new(handler));
+        self
+    }
+
+    fn now(&self) -> String {
+        format_time(self.clock.now())
+    }
+
+    /// Queues jobs left `running` by a previous run, then spawns `workers` worker tasks.
+    pub async fn start(&self, workers: usize) -> Result<(), sqlx::Error> {
+        let requeued = requeue_running(&self.db, &self.now()).await?;
+        if requeued > 0 {
+            warn!("Queued {requeued} interrupted job(s) again");
+        }
+        for _ in 0..workers {
+            let jobs = self.clone();
+            tokio::spawn(async move { jobs.work().await });
+        }
+        Ok(())
+    }
+
+    /// Adds a job with `DEFAULT_MAX_ATTEMPTS` to the queue and returns its id.
+    pub async fn enqueue(&self, kind: &str, payload: Value) -> Result<i64, sqlx::Error> {
+        self.enqueue_with_attempts(kind, payload, DEFAULT_MAX_ATTEMPTS).await
+    }
+
+    #[instrument(name = "jobs.enqueue", skip(self, payload))]
+    pub async fn enqueue_with_attempts(
+        &self,
+        kind: &str,
+        payload: Value,
+        max_attempts: i64,
+    ) -> Result<i64, sqlx::Error> {
+        let payload = payload.to_string();
+        let now = self.now();
+        let id = match &self.db {
+            Db::Sqlite(pool) => {
+                sqlx::query_scalar!(
+                    r#"INSERT INTO jobs (kind, payload, max_attempts, run_at, created_at, updated_at)
+                    VALUES (?1, ?2, ?3, ?4, ?4, ?4)
+                    RETURNING id"#,
+                    kind,
+                    payload,
+                    max_attempts,
+                    now,
+                )
+                .fetch_one(pool)
+                .await?
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::enqueue_job(pool, kind, &payload, max_attempts, &now).await?,
+        };
+        self.wake.notify_one();
+        Ok(id)
+    }
+
+    /// The newest jobs first.
+    pub async fn list(&self, limit: i64) -> Result<Vec<Job>, sqlx::Error> {
+        match &self.db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(Job, "SELECT * FROM jobs ORDER BY id DESC LIMIT ?", limit)
+                    .fetch_all(pool)
+                    .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::list_jobs(pool, limit).await,
+        }
+    }
+
+    /// The newest job of `kind`, if there ever was one.
+    pub async fn latest(&self, kind: &str) -> Result<Option<Job>, sqlx::Error> {
+        match &self.db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(Job, "SELECT * FROM jobs WHERE kind = ? ORDER BY id DESC LIMIT 1", kind)
+                    .fetch_optional(pool)
+                    .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::latest_job(pool, kind).await,
+        }
+    }
+
+    async fn work(&self) {
+        loop {
+            match claim(&self.db, &self.now()).await {
+                Ok(Some(job)) => self.run(job).await,
+                Ok(None) => {
+                    tokio::select! {
+                        _ = self.wake.notified() => {}
+                        _ = tokio::time::sleep(JOB_POLL_INTERVAL) => {}
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to fetch the next job: {e}");
+                    tokio::time::sleep(JOB_POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+
+    #[instrument(name = "jobs.run", skip(self, job), fields(id = job.id, kind = %job.kind, attempt = job.attempts))]
+    async fn run(&self, job: Job) {
+        let Some(handler) = self.handlers.get(job.kind.as_str()).cloned() else {
+            error!("No handler for job {} of kind {:?}", job.id, job.kind);
+            let outcome = format!("No handler for jobs of kind {:?}", job.kind);
+            if let Err(e) = finish(&self.db, job.id, FAILED, &outcome, &job.run_at, &self.now()).await {
+                error!("Failed to record the outcome of job {}: {e}", job.id);
+            }
+            return;
+        };
+        let payload: Value = serde_json::from_str(&job.payload).unwrap_or_default();
+        // On its own task so a panicking handler fails the job instead of the worker
+        let result = match tokio::spawn(async move { handler.run(&payload).await }).await {
+            Ok(result) => result,
+            Err(e) => Err(anyhow::anyhow!("Job panicked: {e}")),
+        };
+
+        let now = self.clock.now();
+        let (status, outcome, run_at) = match result {
+            Ok(outcome) => {
+                info!("Job {} ({}) done: {outcome}", job.id, job.kind);
+                (DONE, outcome, now)
+            }
+            Err(e) if job.attempts < job.max_attempts => {
+                let run_at = now + retry_delay(job.attempts);
+                warn!("Job {} ({}) failed, retrying at {run_at}: {e:#}", job.id, job.kind);
+                (QUEUED, format!("{e:#}"), run_at)
+            }
+            Err(e) => {
+                error!("Job {} ({}) failed for good: {e:#}", job.id, job.kind);
+                (FAILED, format!("{e:#}"), now)
+            }
+        };
+        if let Err(e) = finish(&self.db, job.id, status, &outcome, &format_time(run_at), &format_time(now)).await {
+            error!("Failed to record the outcome of job {}: {e}", job.id);
+        }
+    }
+}
+
+/// `JOB_RETRY_BACKOFF`, doubled for every attempt after the first.
+fn retry_delay(attempts: i64) -> TimeDelta {
+    JOB_RETRY_BACKOFF * 2_i32.pow(attempts.clamp(1, 10) as u32 - 1)
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format(JOB_TIME_FORMAT).to_string()
+}
+
+/// Marks the oldest due job as running and returns it. Only one worker gets any given job.
+async fn claim(db: &Db, now: &str) -> Result<Option<Job>, sqlx::Error> {
+    match db {
+        Db::Sqlite(pool) => {
+            // SQLite has a single writer, so the UPDATE claims the job atomically
+            sqlx::query_as!(
+                Job,
+                r#"UPDATE jobs SET status = 'running', attempts = attempts + 1, updated_at = ?1
+                WHERE id = (
+                  SELECT id FROM jobs WHERE status = 'queued' AND run_at <= ?1 ORDER BY run_at, id LIMIT 1
+                )
+                RETURNING id AS "id!", kind, payload, status, attempts, max_attempts, outcome, run_at, created_at, updated_at"#,
+                now,
+            )
+            .fetch_optional(pool)
+            .await
+        }
+        #[cfg(feature = "postgres")]
+        Db::Postgres(pool) => postgres::claim_job(pool, now).await,
+    }
+}
+
+async fn finish(
+    db: &Db,
+    id: i64,
+    status: &str,
+    outcome: &str,
+    run_at: &str,
+    now: &str,
+) -> Result<(), sqlx::Error> {
+    match db {
+        Db::Sqlite(pool) => {
+            sqlx::query!(
+                "UPDATE jobs SET status = ?, outcome = ?, run_at = ?, updated_at = ? WHERE id = ?",
+                status,
+                outcome,
+                run_at,
+                now,
+                id,
+            )
+            .execute(pool)
+            .await
+            .map(|_| ())
+        }
+        #[cfg(feature = "postgres")]
+        Db::Postgres(pool) => postgres::finish_job(pool, id, status, outcome, run_at, now).await,
+    }
+}
+
+async fn requeue_running(db: &Db, now: &str) -> Result<u64, sqlx::Error> {
+    match db {
+        Db::Sqlite(pool) => {
+            sqlx::query!(
+                "UPDATE jobs SET status = 'queued', run_at = ?1, updated_at = ?1 WHERE status = 'running'",
+                now,
+            )
+            .execute(pool)
+            .await
+            .map(|r| r.rows_affected())
+        }
+        #[cfg(feature = "postgres")]
+        Db::Postgres(pool) => postgres::requeue_running_jobs(pool, now).await,
+    }
+}
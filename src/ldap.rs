@@ -0,0 +1,344 @@
+//! Contact import from an LDAP or Active Directory server.
+//!
+//! Entries matching `ldap.filter` under `ldap.base_dn` become contacts: `givenName`/`sn` (or
+//! `cn` split at the last space) for the name, `mail`, `telephoneNumber`, `company` (or `o`)
+//! and `title`. Contacts are matched to entries by email, case-insensitively. The directory
+//! wins: a matched contact takes the entry's name, phone number, company and job title, except
+//! where the entry leaves them empty.
+//! Contacts without an entry are left alone, the import never deletes anything.
+//!
+//! Runs once with `webone ldap-import [--dry-run]`, or every `ldap.interval_hours` as an
+//! `LDAP_IMPORT_JOB` job. A dry run only reports what would change.
+
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use ldap3::{
+    LdapConnAsync, Scope, SearchEntry,
+    adapters::{Adapter, EntriesOnly, PagedResults},
+};
+use serde_json::{Value, json};
+use tracing::{info, instrument, warn};
+
+use crate::{
+    clock::SharedClock,
+    config::LdapConfig,
+    contacts::{Contact, NewContact},
+    db::Db,
+    events::{self, Event},
+    jobs::{JobHandler, Jobs},
+    repository::SharedContactRepository,
+    webhooks,
+};
+
+/// Job kind that runs one import. The payload is `{"dry_run": bool}`.
+pub const LDAP_IMPORT_JOB: &str = "ldap.import";
+/// Actor of the events recorded for imported changes.
+pub const ACTOR: &str = "ldap-import";
+/// Attributes read from each entry.
+pub const ATTRIBUTES: &[&str] = &["cn", "givenName", "sn", "mail", "telephoneNumber", "company", "o", "title"];
+/// Entries fetched per page. Active Directory refuses to return more than 1000 at once.
+const PAGE_SIZE: i32 = 500;
+
+/// An entry that can't become a contact, like one without `mail`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedEntry {
+    pub dn: String,
+    pub reason: String,
+}
+
+/// The contact fields of one directory entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryEntry {
+    pub dn: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub phone_number: String,
+    pub email: String,
+    pub company: String,
+    pub job_title: String,
+}
+
+impl DirectoryEntry {
+    /// Maps a search result, or says why it can't be imported.
+    fn from_search(entry: SearchEntry) -> Result<Self, String> {
+        // Attribute names come back in whatever case the server stores them in
+        let attr = |name: &str| {
+            entry
+                .attrs
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .and_then(|(_, values)| values.first())
+                .map(|value| value.trim().to_string())
+                .unwrap_or_default()
+        };
+        let email = attr("mail");
+        if email.is_empty() {
+            return Err("no mail".into());
+        }
+        let (mut first_name, mut last_name) = (attr("givenName"), attr("sn"));
+        if first_name.is_empty() && last_name.is_empty() {
+            let cn = attr("cn");
+            let (first, last) = cn.rsplit_once(' ').unwrap_or((&cn, ""));
+            first_name = first.trim().to_string();
+            last_name = last.trim().to_string();
+        }
+        if first_name.is_empty() && last_name.is_empty() {
+            return Err("no name".into());
+        }
+        // Active Directory has `company`, plain LDAP directories the organization in `o`
+        let mut company = attr("company");
+        if company.is_empty() {
+            company = attr("o");
+        }
+        Ok(Self {
+            dn: entry.dn,
+            first_name,
+            last_name,
+            phone_number: attr("telephoneNumber"),
+            email,
+            company,
+            job_title: attr("title"),
+        })
+    }
+
+    fn new_contact(&self) -> NewContact {
+        NewContact {
+            first_name: self.first_name.clone(),
+            last_name: self.last_name.clone(),
+            phone_number: self.phone_number.clone(),
+            email: self.email.clone(),
+            company: self.company.clone(),
+            job_title: self.job_title.clone(),
+            errors: None,
+            form_token: None,
+        }
+    }
+
+    /// `contact` with this entry's values applied, empty values keeping the contact's.
+    fn apply_to(&self, contact: &Contact) -> Contact {
+        let pick = |ours: &str, theirs: &str| {
+            if ours.is_empty() { theirs.to_string() } else { ours.to_string() }
+        };
+        Contact {
+            first_name: pick(&self.first_name, &contact.first_name),
+            last_name: pick(&self.last_name, &contact.last_name),
+            phone_number: pick(&self.phone_number, &contact.phone_number),
+            company: pick(&self.company, &contact.company),
+            job_title: pick(&self.job_title, &contact.job_title),
+            ..contact.clone()
+        }
+    }
+}
+
+/// Binds to the configured server and reads every matching entry, split into the ones that
+/// can become contacts and the ones that can't.
+#[instrument(name = "ldap.fetch", skip_all, fields(url = config.url.as_deref().unwrap_or_default()))]
+pub async fn fetch(config: &LdapConfig) -> anyhow::Result<(Vec<DirectoryEntry>, Vec<SkippedEntry>)> {
+    let url = config.url.as_deref().context("No ldap.url configured")?;
+    let (conn, mut ldap) = LdapConnAsync::new(url)
+        .await
+        .with_context(|| format!("Failed to connect to {url}"))?;
+    ldap3::drive!(conn);
+
+    if let Some(bind_dn) = &config.bind_dn {
+        let password = config.bind_password.as_deref().unwrap_or_default();
+        ldap.simple_bind(bind_dn, password)
+            .await?
+            .success()
+            .with_context(|| format!("Failed to bind as {bind_dn}"))?;
+    }
+
+    let adapters: Vec<Box<dyn Adapter<_, _>>> =
+        vec![Box::new(EntriesOnly::new()), Box::new(PagedResults::new(PAGE_SIZE))];
+    let mut search = ldap
+        .streaming_search_with(adapters, &config.base_dn, Scope::Subtree, &config.filter, ATTRIBUTES.to_vec())
+        .await?;
+    let mut entries = Vec::new();
+    let mut skipped = Vec::new();
+    while let Some(entry) = search.next().await? {
+        let entry = SearchEntry::construct(entry);
+        let dn = entry.dn.clone();
+        match DirectoryEntry::from_search(entry) {
+            Ok(entry) => entries.push(entry),
+            Err(reason) => skipped.push(SkippedEntry { dn, reason }),
+        }
+    }
+    search
+        .finish()
+        .await
+        .success()
+        .with_context(|| format!("Search for {} under {:?} failed", config.filter, config.base_dn))?;
+    ldap.unbind().await?;
+    Ok((entries, skipped))
+}
+
+/// What an import changes, worked out before anything is written.
+#[derive(Debug, Default)]
+pub struct ImportPlan {
+    pub creates: Vec<DirectoryEntry>,
+    /// Contacts as they are now, and as they will be.
+    pub updates: Vec<(Contact, Contact)>,
+    pub unchanged: usize,
+    pub skipped: Vec<SkippedEntry>,
+}
+
+impl ImportPlan {
+    /// Compares the entries to the contacts there are now.
+    pub fn new(contacts: &[Contact], entries: Vec<DirectoryEntry>, skipped: Vec<SkippedEntry>) -> Self {
+        let by_email: HashMap<String, &Contact> =
+            contacts.iter().map(|c| (c.email.to_lowercase(), c)).collect();
+        let mut plan = Self { skipped, ..Self::default() };
+        let mut seen = HashMap::new();
+        for entry in entries {
+            let email = entry.email.to_lowercase();
+            if let Some(first_dn) = seen.get(&email) {
+                let reason = format!("same mail as {first_dn}");
+                plan.skipped.push(SkippedEntry { dn: entry.dn, reason });
+                continue;
+            }
+            seen.insert(email.clone(), entry.dn.clone());
+            match by_email.get(&email) {
+                Some(contact) => {
+                    let updated = entry.apply_to(contact);
+                    if updated.first_name == contact.first_name
+                        && updated.last_name == contact.last_name
+                        && updated.phone_number == contact.phone_number
+                        && updated.company == contact.company
+                        && updated.job_title == contact.job_title
+                    {
+                        plan.unchanged += 1;
+                    } else {
+                        plan.updates.push(((*contact).clone(), updated));
+                    }
+                }
+                None => plan.creates.push(entry),
+            }
+        }
+        plan
+    }
+
+    /// One line counts, like `2 to create, 1 to update, 10 unchanged, 0 skipped`.
+    pub fn summary(&self) -> String {
+        format!(
+            "{} to create, {} to update, {} unchanged, {} skipped",
+            self.creates.len(),
+            self.updates.len(),
+            self.unchanged,
+            self.skipped.len()
+        )
+    }
+
+    /// The summary followed by a line per change: `+` creates, `~` updates, `-` skipped.
+    pub fn report(&self) -> String {
+        let mut lines = vec![self.summary()];
+        for entry in &self.creates {
+            lines.push(format!(
+                "+ {} {} <{}> {}",
+                entry.first_name, entry.last_name, entry.email, entry.phone_number
+            ));
+        }
+        for (before, after) in &self.updates {
+            let mut changes = Vec::new();
+            for (field, old, new) in [
+                ("first name", &before.first_name, &after.first_name),
+                ("last name", &before.last_name, &after.last_name),
+                ("phone", &before.phone_number, &after.phone_number),
+                ("company", &before.company, &after.company),
+                ("job title", &before.job_title, &after.job_title),
+            ] {
+                if old != new {
+                    changes.push(format!("{field} {old:?} -> {new:?}"));
+                }
+            }
+            lines.push(format!("~ #{} <{}>: {}", before.id, before.email, changes.join(", ")));
+        }
+        for skipped in &self.skipped {
+            lines.push(format!("- {}: {}", skipped.dn, skipped.reason));
+        }
+        lines.join("\n")
+    }
+
+    /// Writes the changes, recording an event (and queueing webhooks) for each like the forms
+    /// do.
+    pub async fn apply(
+        &self,
+        contacts: &SharedContactRepository,
+        db: &Db,
+        jobs: &Jobs,
+        clock: &SharedClock,
+    ) -> anyhow::Result<()> {
+        for entry in &self.creates {
+            let contact = contacts.create(entry.new_contact(), clock.now()).await?;
+            record(db, jobs, clock, events::CONTACT_CREATED, &contact).await?;
+        }
+        for (_, contact) in &self.updates {
+            contacts.update(contact, clock.now()).await?;
+            let contact = contacts.find_by_id(contact.id).await?;
+            record(db, jobs, clock, events::CONTACT_UPDATED, &contact).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn record(db: &Db, jobs: &Jobs, clock: &SharedClock, kind: &str, contact: &Contact) -> anyhow::Result<()> {
+    let payload = serde_json::to_value(contact)?;
+    Event::record(db, kind, ACTOR, Some(contact.id), payload.clone()).await?;
+    webhooks::fire(db, jobs, kind, contact.id, &payload, clock.now()).await
+}
+
+/// Runs one import: fetches the entries, plans, and applies the plan unless `dry_run`.
+/// Returns the report.
+///
+/// Example usage:
+/// `let report = ldap::import(&config.ldap, &contacts, &db, &jobs, &clock, true).await?;`
+pub async fn import(
+    config: &LdapConfig,
+    contacts: &SharedContactRepository,
+    db: &Db,
+    jobs: &Jobs,
+    clock: &SharedClock,
+    dry_run: bool,
+) -> anyhow::Result<String> {
+    let (entries, skipped) = fetch(config).await?;
+    let plan = ImportPlan::new(&contacts.all().await?, entries, skipped);
+    if !dry_run {
+        plan.apply(contacts, db, jobs, clock).await?;
+    }
+    let report = plan.report();
+    info!(dry_run, "LDAP import: {}", plan.summary());
+    Ok(if dry_run { format!("Dry run, nothing changed. {report}") } else { report })
+}
+
+/// Runs `LDAP_IMPORT_JOB` jobs.
+pub struct LdapImportJob {
+    pub config: LdapConfig,
+    pub contacts: SharedContactRepository,
+    pub db: Db,
+    pub jobs: Jobs,
+    pub clock: SharedClock,
+}
+
+#[async_trait]
+impl JobHandler for LdapImportJob {
+    async fn run(&self, payload: &Value) -> anyhow::Result<String> {
+        let dry_run = payload["dry_run"].as_bool().unwrap_or(false);
+        import(&self.config, &self.contacts, &self.db, &self.jobs, &self.clock, dry_run).await
+    }
+}
+
+/// Queues an import every `interval`, the first one an `interval` after startup.
+pub fn spawn_scheduler(jobs: Jobs, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        // The first tick is immediate
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            if let Err(e) = jobs.enqueue(LDAP_IMPORT_JOB, json!({ "dry_run": false })).await {
+                warn!("Failed to queue the scheduled LDAP import: {e}");
+            }
+        }
+    });
+}
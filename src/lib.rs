@@ -1,3 +1,67 @@
+pub mod admin;
+pub mod api_keys;
+pub mod app;
+pub mod assets;
+pub mod atom;
+pub mod attachments;
+pub mod backup;
+pub mod batch;
+pub mod base_path;
+pub mod carddav;
+pub mod clock;
+pub mod config;
 pub mod contacts;
+pub mod dates;
+pub mod db;
+pub mod dedupe;
+pub mod deletions;
+pub mod duplicates;
+pub mod email;
+pub mod error_reporting;
+pub mod etag;
+pub mod events;
+pub mod export;
+pub mod fold;
+pub mod forms;
+pub mod gravatar;
+pub mod highlight;
+pub mod htmx;
+pub mod i18n;
+pub mod ids;
+pub mod import;
+pub mod jobs;
+pub mod ldap;
+pub mod live;
+pub mod load_shed;
+pub mod logging;
+pub mod meilisearch;
+pub mod memory;
+pub mod names;
+pub mod origin;
+pub mod pagination;
+pub mod params;
+pub mod pdf;
+pub mod phone;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod preferences;
+pub mod proxy;
+pub mod qr;
+pub mod quality;
+pub mod query;
+pub mod rate_limit;
+pub mod reload;
+pub mod relationships;
+pub mod repository;
+pub mod search;
+pub mod search_cache;
+pub mod seed;
+pub mod shares;
+pub mod stats;
 pub mod templates;
+pub mod tenants;
+pub mod trash;
 pub mod utils;
+pub mod validation;
+pub mod vcard;
+pub mod webhooks;
@@ -0,0 +1,278 @@
+//! Live updates of the contact list over a WebSocket (`/contacts/live`), so people with the
+//! list open see each other's changes without reloading.
+//!
+//! Every contact event (see `record_contact_event`) is published to a broadcast channel, and
+//! each open list gets it as a JSON text message: the event, the contact's id and, unless it
+//! was deleted, its list row rendered in that browser's language and display preferences:
+//! `{"event":"contact.updated","id":3,"html":"<tr id=\"contact-3\">...</tr>"}`. `static/live.js`
+//! swaps the row in, removes it, or adds it at the top of an unfiltered first page.
+//!
+//! axum's WebSocket support needs tokio-tungstenite, so this speaks the little of RFC 6455 it
+//! needs itself: the handshake, unfragmented text frames out, and answering pings and closes.
+//! Anything else clients send is read and ignored.
+
+use std::{net::SocketAddr, time::Duration};
+
+use askama::Template;
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION, UPGRADE},
+    },
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use hyper_util::rt::TokioIo;
+use serde_json::json;
+use sha1::{Digest, Sha1};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::{broadcast, mpsc},
+    time::Instant,
+};
+use tracing::{debug, warn};
+
+use crate::{
+    assets, base_path,
+    contacts::Contact,
+    events, gravatar, i18n, origin,
+    preferences::{self, Preferences},
+    templates::ContactRowTemplate,
+};
+
+/// Changes kept for lists that fall behind. One that falls further misses the oldest.
+const CHANNEL_CAPACITY: usize = 64;
+/// How often an idle connection is pinged, so proxies don't time it out.
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+/// Largest frame accepted from a client. They only ever send pings and closes.
+const MAX_CLIENT_FRAME: u64 = 4096;
+/// Fixed by RFC 6455 for the `Sec-WebSocket-Accept` hash.
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// A contact event: `contact` as it is now, or `None` if it was deleted.
+#[derive(Debug, Clone)]
+pub struct LiveChange {
+    pub event: String,
+    pub contact_id: i64,
+    pub contact: Option<Contact>,
+}
+
+/// The broadcast channel open lists listen on, shared through `AppState`.
+#[derive(Clone)]
+pub struct LiveUpdates {
+    sender: broadcast::Sender<LiveChange>,
+}
+
+impl Default for LiveUpdates {
+    fn default() -> Self {
+        Self { sender: broadcast::channel(CHANNEL_CAPACITY).0 }
+    }
+}
+
+impl LiveUpdates {
+    /// Whether any list is open, to skip looking up a contact nobody would be sent.
+    pub fn has_listeners(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    /// Sends `change` to every open list.
+    pub fn publish(&self, change: LiveChange) {
+        // Fails only when nobody is listening
+        let _ = self.sender.send(change);
+    }
+
+    /// Upgrades `request` to a WebSocket that gets every change from now on, rendered in the
+    /// request's locale, preferences and base path. Refuses requests that aren't WebSocket handshakes,
+    /// and ones from pages on another site.
+    ///
+    /// Example usage:
+    /// `async fn contacts_live(State(state): State<AppState>, request: Request) -> Response { state.live.accept(request) }`
+    pub fn accept(&self, mut request: Request) -> Response {
+        let headers = request.headers();
+        let Some(accept) = handshake_accept(headers) else {
+            return (StatusCode::BAD_REQUEST, "Expected a WebSocket handshake").into_response();
+        };
+        // Browsers send cookies along with WebSocket handshakes from any site, and nothing else
+        // stops other sites from reading along
+        if !origin::same_origin(headers) {
+            return (StatusCode::FORBIDDEN, "Cross-origin WebSocket refused").into_response();
+        }
+        let peer = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| *addr);
+        let changes = self.sender.subscribe();
+        let (locale, preferences, base_path) = (i18n::current(), preferences::current(), base_path::get());
+        let (assets, gravatar) = (assets::current(), gravatar::enabled());
+        let on_upgrade = hyper::upgrade::on(&mut request);
+        tokio::spawn(async move {
+            match on_upgrade.await {
+                Ok(upgraded) => {
+                    debug!(?peer, "Live updates connected");
+                    let connection = serve(TokioIo::new(upgraded), changes, locale, preferences);
+                    let connection = assets::scope(assets, gravatar::scope(gravatar, connection));
+                    let result = base_path::scope(base_path, connection).await;
+                    debug!(?peer, ?result, "Live updates disconnected");
+                }
+                Err(err) => warn!("Live updates upgrade failed: {err}"),
+            }
+        });
+        (
+            StatusCode::SWITCHING_PROTOCOLS,
+            [
+                (UPGRADE, HeaderValue::from_static("websocket")),
+                (CONNECTION, HeaderValue::from_static("upgrade")),
+                (SEC_WEBSOCKET_ACCEPT, accept),
+            ],
+        )
+            .into_response()
+    }
+}
+
+/// `Sec-WebSocket-Accept` for a valid version 13 handshake, `None` for anything else.
+fn handshake_accept(headers: &HeaderMap) -> Option<HeaderValue> {
+    let has_token = |name, token: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    };
+    if !has_token(CONNECTION, "upgrade") || !has_token(UPGRADE, "websocket") {
+        return None;
+    }
+    if headers.get(SEC_WEBSOCKET_VERSION)?.as_bytes() != b"13" {
+        return None;
+    }
+    let key = headers.get(SEC_WEBSOCKET_KEY)?.as_bytes();
+    let mut hash = Sha1::new();
+    hash.update(key);
+    hash.update(ACCEPT_GUID);
+    HeaderValue::from_str(&STANDARD.encode(hash.finalize())).ok()
+}
+
+/// The message for `change`, see the module docs.
+fn message(change: &LiveChange, locale: &'static str, preferences: Preferences) -> anyhow::Result<String> {
+    let html = match &change.contact {
+        Some(contact) if change.event != events::CONTACT_DELETED => {
+            let template = ContactRowTemplate {
+                contact: contact.clone(),
+                return_to: base_path::url("/contacts"),
+                search: String::new(),
+            };
+            Some(i18n::with_locale(locale, || preferences::with(preferences, || template.render()))?)
+        }
+        _ => None,
+    };
+    Ok(json!({ "event": change.event, "id": change.contact_id, "html": html }).to_string())
+}
+
+/// What the reading half hands over to the writing half.
+enum Control {
+    Ping(Vec<u8>),
+    Close,
+}
+
+/// Sends changes until the client goes away. Reading runs in its own task, since a frame read
+/// can't be interrupted halfway.
+async fn serve<S>(
+    stream: S,
+    mut changes: broadcast::Receiver<LiveChange>,
+    locale: &'static str,
+    preferences: Preferences,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let (control_sender, mut control) = mpsc::channel(8);
+    let read = tokio::spawn(async move {
+        loop {
+            let sent = match read_frame(&mut reader).await {
+                Ok((OPCODE_PING, payload)) => control_sender.send(Control::Ping(payload)).await,
+                Ok((OPCODE_CLOSE, _)) | Err(_) => {
+                    let _ = control_sender.send(Control::Close).await;
+                    return;
+                }
+                Ok(_) => Ok(()),
+            };
+            if sent.is_err() {
+                return;
+            }
+        }
+    });
+    let mut keepalive = tokio::time::interval_at(Instant::now() + PING_INTERVAL, PING_INTERVAL);
+    let result = loop {
+        tokio::select! {
+            change = changes.recv() => match change {
+                Ok(change) => {
+                    let text = message(&change, locale, preferences)?;
+                    write_frame(&mut writer, OPCODE_TEXT, text.as_bytes()).await?;
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => debug!(missed, "Live updates fell behind"),
+                Err(broadcast::error::RecvError::Closed) => break Ok(()),
+            },
+            control = control.recv() => match control {
+                Some(Control::Ping(payload)) => write_frame(&mut writer, OPCODE_PONG, &payload).await?,
+                Some(Control::Close) | None => {
+                    // The client may be gone already, so a failed close doesn't matter
+                    let _ = write_frame(&mut writer, OPCODE_CLOSE, &[]).await;
+                    break Ok(());
+                }
+            },
+            _ = keepalive.tick() => write_frame(&mut writer, OPCODE_PING, &[]).await?,
+        }
+    };
+    read.abort();
+    result
+}
+
+/// Writes one unfragmented, unmasked frame, as servers do.
+async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= usize::from(u16::MAX) => {
+            frame.push(126);
+            frame.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend((len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame).await?;
+    writer.flush().await
+}
+
+/// Reads one frame, unmasking its payload. Returns its opcode and payload.
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head).await?;
+    let opcode = head[0] & 0x0F;
+    let masked = head[1] & 0x80 != 0;
+    let len = match head[1] & 0x7F {
+        126 => u64::from(reader.read_u16().await?),
+        127 => reader.read_u64().await?,
+        len => u64::from(len),
+    };
+    if len > MAX_CLIENT_FRAME {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "WebSocket frame too large"));
+    }
+    let mut mask = [0u8; 4];
+    if masked {
+        reader.read_exact(&mut mask).await?;
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload).await?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((opcode, payload))
+}
@@ -0,0 +1,72 @@
+//! Load shedding: a cap on how many requests are handled at once. Past it, requests wait a
+//! moment for a slot and are then turned away with a 503 "server busy" page, rather than
+//! piling up behind SQLite's write lock until every one of them times out.
+//!
+//! A request holds its slot until its response starts, so streamed downloads and live update
+//! sockets don't count against the cap for as long as they stay open.
+
+use std::{sync::Arc, time::Duration};
+
+use askama::Template;
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode, header::RETRY_AFTER},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+use tokio::sync::Semaphore;
+use tracing::debug;
+
+use crate::{config::ServerConfig, i18n, templates::ServerBusyTemplate};
+
+/// What the "server busy" page tells clients to wait before retrying.
+const RETRY_AFTER_SECS: u64 = 1;
+
+/// The request slots, `server.max_concurrent_requests` of them, shared by every request.
+#[derive(Clone)]
+pub struct ConcurrencyLimit {
+    slots: Arc<Semaphore>,
+    queue_timeout: Duration,
+}
+
+impl ConcurrencyLimit {
+    /// `None` when `server.max_concurrent_requests` is 0, which means no cap.
+    pub fn new(config: &ServerConfig) -> Option<Self> {
+        (config.max_concurrent_requests > 0).then(|| Self {
+            slots: Arc::new(Semaphore::new(config.max_concurrent_requests)),
+            queue_timeout: Duration::from_millis(config.queue_timeout_ms),
+        })
+    }
+}
+
+/// Middleware: handles the request once a slot is free, or answers with a 503 if none frees
+/// up within `server.queue_timeout_ms`. Goes outside the error reporter, as a busy server
+/// isn't an error to report, and inside the access log.
+///
+/// Example usage:
+/// `router.layer(middleware::from_fn_with_state(limit, load_shed::limit))`
+pub async fn limit(State(limit): State<ConcurrencyLimit>, request: Request, next: Next) -> Response {
+    let slot = tokio::time::timeout(limit.queue_timeout, limit.slots.clone().acquire_owned()).await;
+    match slot {
+        Ok(Ok(_slot)) => next.run(request).await,
+        // The semaphore is never closed, so this is the timeout
+        _ => {
+            debug!("Too many requests in flight, turning one away");
+            server_busy(i18n::negotiate(request.headers()))
+        }
+    }
+}
+
+fn server_busy(locale: &'static str) -> Response {
+    let template = ServerBusyTemplate {
+        retry_after: RETRY_AFTER_SECS,
+    };
+    let mut response = match i18n::with_locale(locale, || template.render()) {
+        Ok(html) => (StatusCode::SERVICE_UNAVAILABLE, Html(html)).into_response(),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "Service Unavailable").into_response(),
+    };
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS));
+    response
+}
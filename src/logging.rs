@@ -0,0 +1,196 @@
+//! Log output: human readable lines (the default), or JSON lines with `log_format = "json"`
+//! for log collectors like Loki or CloudWatch to take in without parsing rules.
+//!
+//! A JSON line is one flat object: `timestamp`, `level`, `target`, the event's fields
+//! (`message` included) and the fields of the spans it happened in, so the access log's
+//! `request_id`, `method`, `uri` and `client_ip` end up on every line logged while handling a
+//! request. `span` names the innermost span.
+
+use std::{fmt, net::SocketAddr, sync::OnceLock};
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{SecondsFormat, Utc};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tracing::{
+    Event, Span, Subscriber,
+    field::{Field, Visit},
+    info_span,
+    span::Record,
+};
+use tracing_subscriber::{
+    EnvFilter, Registry,
+    field::RecordFields,
+    fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields, format::Writer},
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    reload,
+    util::SubscriberInitExt,
+};
+
+/// How log lines are written, `log_format`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// For people: colored, one line per event with its spans in front.
+    #[default]
+    Text,
+    /// One JSON object per line, for log collectors.
+    Json,
+}
+
+/// Swaps the filter `init` made from `log_level`, see `set_level`. Unset when `RUST_LOG` set
+/// the filter instead.
+static LEVEL: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Installs the global subscriber. `RUST_LOG` wins over `log_level`.
+///
+/// Example usage:
+/// `logging::init(&config.log_level, config.log_format);`
+pub fn init(log_level: &str, format: LogFormat) {
+    let (filter, from_env) = match EnvFilter::try_from_default_env() {
+        Ok(filter) => (filter, true),
+        Err(_) => (EnvFilter::new(log_level), false),
+    };
+    let (filter, handle) = reload::Layer::new(filter);
+    let registry = tracing_subscriber::registry().with(filter);
+    match format {
+        LogFormat::Text => registry.with(tracing_subscriber::fmt::layer()).init(),
+        LogFormat::Json => registry.with(tracing_subscriber::fmt::layer().fmt_fields(JsonFields).event_format(JsonFormat)).init(),
+    }
+    if !from_env {
+        let _ = LEVEL.set(handle);
+    }
+}
+
+/// Switches to `log_level`, when the config is reloaded. Does nothing when `RUST_LOG` set the
+/// level, as it wins over `log_level`.
+pub fn set_level(log_level: &str) -> anyhow::Result<()> {
+    if let Some(handle) = LEVEL.get() {
+        handle.reload(EnvFilter::try_new(log_level)?)?;
+    }
+    Ok(())
+}
+
+tokio::task_local! {
+    static REQUEST_ID: Option<String>;
+}
+
+/// The request's `X-Request-Id`, as `SetRequestIdLayer` set or kept it.
+fn header_request_id(request: &Request) -> Option<&str> {
+    request.headers().get("x-request-id").and_then(|value| value.to_str().ok())
+}
+
+/// The id of the request being handled, for pages to show, `None` outside of one.
+pub fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok().flatten()
+}
+
+/// Middleware: handles the request with its id as the current one, see `request_id`.
+///
+/// Example usage:
+/// `router.layer(middleware::from_fn(logging::scope))`
+pub async fn scope(request: Request, next: Next) -> Response {
+    let request_id = header_request_id(&request).map(str::to_string);
+    REQUEST_ID.scope(request_id, next.run(request)).await
+}
+
+/// Span of the access log for one request: its id (see `X-Request-Id`), method, URI and the
+/// client IP `proxy::resolve` settled on.
+pub fn request_span(request: &Request) -> Span {
+    let request_id = header_request_id(request).unwrap_or_default();
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string())
+        .unwrap_or_default();
+    info_span!(
+        "request",
+        request_id,
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        client_ip = %client_ip,
+    )
+}
+
+/// Collects fields into a JSON object. Numbers and booleans stay what they are, everything
+/// else becomes a string.
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().into(), format!("{value:?}").into());
+    }
+}
+
+/// Keeps span fields as a JSON object, for `JsonFormat` to merge into the lines.
+struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: RecordFields>(&self, mut writer: Writer<'writer>, fields: R) -> fmt::Result {
+        let mut map = Map::new();
+        fields.record(&mut JsonVisitor(&mut map));
+        write!(writer, "{}", Value::Object(map))
+    }
+
+    fn add_fields(&self, current: &mut FormattedFields<Self>, fields: &Record<'_>) -> fmt::Result {
+        let mut map: Map<String, Value> = serde_json::from_str(&current.fields).unwrap_or_default();
+        fields.record(&mut JsonVisitor(&mut map));
+        current.fields = Value::Object(map).to_string();
+        Ok(())
+    }
+}
+
+/// Writes each event as a JSON line, see the module docs.
+struct JsonFormat;
+
+impl<S> FormatEvent<S, JsonFields> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, JsonFields>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut line = Map::new();
+        line.insert("timestamp".into(), Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true).into());
+        line.insert("level".into(), metadata.level().as_str().into());
+        line.insert("target".into(), metadata.target().into());
+        if let Some(scope) = ctx.event_scope() {
+            // Outermost first, so an inner span's field wins over an outer one's of the same name
+            for span in scope.from_root() {
+                line.insert("span".into(), span.name().into());
+                if let Some(fields) = span.extensions().get::<FormattedFields<JsonFields>>()
+                    && let Ok(Value::Object(fields)) = serde_json::from_str(&fields.fields)
+                {
+                    line.extend(fields);
+                }
+            }
+        }
+        event.record(&mut JsonVisitor(&mut line));
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
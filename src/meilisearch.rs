@@ -0,0 +1,437 @@
+//! Contact search with Meilisearch, an external search engine, for address books too big for
+//! `FuzzySearch` to rank in memory. Turned on with `search.meilisearch_url`. Searches come
+//! back ranked by relevance and find contacts despite typos; a list without search text is
+//! still the database's, in the requested order.
+//!
+//! Meilisearch keeps its own copy of the contacts, kept in sync from the event log (see
+//! `events`) by `spawn_sync`. On startup it builds a fresh index next to the live one and swaps
+//! them, then it follows the log: every contact event since the last one it saw sends that
+//! contact again, or deletes it if it's gone. Since it reads the log rather than listening in
+//! the process, changes made by `webone import` or `webone dedupe` show up too.
+//!
+//! Meilisearch works through its task queue in order, so the requests here only queue work and
+//! don't wait for it. A search right after a change can miss it for a moment.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use reqwest::{Client, Method, Url, header::CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio_stream::StreamExt;
+use tracing::{info, warn};
+
+use crate::{
+    config::SearchConfig,
+    contacts::{Contact, ContactCursor, ContactError, ContactFilter, ContactOrder, ContactPage},
+    db::Db,
+    events::{self, Event},
+    phone,
+    repository::SharedContactRepository,
+    search::SearchBackend,
+};
+
+/// How long a request to Meilisearch may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often the event log is checked for contact changes.
+const SYNC_INTERVAL: Duration = Duration::from_secs(2);
+/// Contacts sent per request when the index is built, and events read per check.
+const BATCH_SIZE: usize = 1000;
+/// Most matches a search counts and pages through. Meilisearch stops at 1000 unless told
+/// otherwise.
+const MAX_TOTAL_HITS: u64 = 100_000;
+
+/// A contact as indexed: its fields, plus the ones the filters and the phone number search
+/// need.
+#[derive(Serialize)]
+struct Document<'a> {
+    #[serde(flatten)]
+    contact: &'a Contact,
+    phone_digits: String,
+    /// `created_at` as a Unix timestamp, since filters only compare numbers.
+    created_ts: i64,
+    email_domain: String,
+    initial: String,
+}
+
+impl<'a> Document<'a> {
+    fn of(contact: &'a Contact) -> Self {
+        Self {
+            contact,
+            phone_digits: phone::normalize(&contact.phone_number),
+            created_ts: contact.created_at.timestamp(),
+            email_domain: contact.email.split_once('@').map(|(_, domain)| domain.to_lowercase()).unwrap_or_default(),
+            initial: contact.last_name.chars().next().map(|c| c.to_uppercase().to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    hits: Vec<Contact>,
+    #[serde(rename = "totalHits")]
+    total_hits: i64,
+}
+
+/// `SearchBackend` on a Meilisearch index, see the module docs.
+#[derive(Clone)]
+pub struct MeilisearchSearch {
+    client: Client,
+    url: Url,
+    key: Option<String>,
+    index: String,
+    contacts: SharedContactRepository,
+}
+
+impl MeilisearchSearch {
+    pub fn new(config: &SearchConfig, url: &str, contacts: SharedContactRepository) -> anyhow::Result<Self> {
+        let mut url = Url::parse(url).with_context(|| format!("Invalid Meilisearch URL {url:?}"))?;
+        // The API paths are joined to it, which would replace a last segment without a slash
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+        Ok(Self {
+            client: Client::builder().timeout(REQUEST_TIMEOUT).build()?,
+            url,
+            key: config.meilisearch_key.clone(),
+            index: config.meilisearch_index.clone(),
+            contacts,
+        })
+    }
+
+    /// Sends a request to Meilisearch and returns its JSON answer, failing on an error status.
+    async fn request(&self, method: Method, path: &str, body: Option<Value>) -> anyhow::Result<Value> {
+        let url = self.url.join(path).with_context(|| format!("Invalid Meilisearch path {path}"))?;
+        let mut request = self.client.request(method.clone(), url);
+        if let Some(key) = &self.key {
+            request = request.bearer_auth(key);
+        }
+        if let Some(body) = body {
+            request = request.header(CONTENT_TYPE, "application/json").body(body.to_string());
+        }
+        let response = request.send().await.with_context(|| format!("Failed to reach Meilisearch for {method} {path}"))?;
+        let status = response.status();
+        let text = response.text().await?;
+        anyhow::ensure!(status.is_success(), "Meilisearch answered {method} {path} with {status}: {text}");
+        Ok(serde_json::from_str(&text).unwrap_or(Value::Null))
+    }
+
+    /// One search request: a page of `per_page` hits, or just the total for a `per_page` of 0.
+    async fn query(&self, filter: &ContactFilter, text: &str, page: i64, per_page: i64) -> anyhow::Result<SearchResponse> {
+        let body = json!({
+            "q": text,
+            "filter": conditions(filter),
+            "page": page.max(1),
+            "hitsPerPage": per_page.max(0),
+        });
+        let response = self.request(Method::POST, &format!("indexes/{}/search", self.index), Some(body)).await?;
+        serde_json::from_value(response).context("Unexpected Meilisearch search response")
+    }
+
+    /// Queues `contacts` to be added to `index`, or replaced if they are there already.
+    async fn add(&self, index: &str, contacts: &[Contact]) -> anyhow::Result<()> {
+        let documents: Vec<Document> = contacts.iter().map(Document::of).collect();
+        self.request(Method::POST, &format!("indexes/{index}/documents"), Some(json!(documents))).await?;
+        Ok(())
+    }
+
+    /// Creates `index` if it doesn't exist yet, and sets what is searched and filtered.
+    async fn create_index(&self, index: &str) -> anyhow::Result<()> {
+        // Queued whether it exists or not, the task just fails if it does
+        self.request(Method::POST, "indexes", Some(json!({ "uid": index, "primaryKey": "id" }))).await?;
+        let settings = json!({
+            "searchableAttributes": ["first_name", "last_name", "company", "job_title", "email", "phone_number", "phone_digits"],
+            "filterableAttributes": ["created_ts", "email_domain", "initial"],
+            "pagination": { "maxTotalHits": MAX_TOTAL_HITS },
+        });
+        self.request(Method::PATCH, &format!("indexes/{index}/settings"), Some(settings)).await?;
+        Ok(())
+    }
+
+    /// Builds a new index of every contact next to the live one and swaps them, so searches
+    /// keep working meanwhile and contacts deleted while the server was down go away.
+    async fn rebuild(&self) -> anyhow::Result<usize> {
+        let building = format!("{}_rebuild", self.index);
+        // Left over if an earlier rebuild was cut short
+        self.request(Method::DELETE, &format!("indexes/{building}"), None).await.ok();
+        self.create_index(&self.index).await?;
+        self.create_index(&building).await?;
+        let mut contacts = self.contacts.stream_all();
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut count = 0;
+        while let Some(contact) = contacts.next().await {
+            batch.push(contact?);
+            if batch.len() == BATCH_SIZE {
+                self.add(&building, &batch).await?;
+                count += batch.len();
+                batch.clear();
+            }
+        }
+        if !batch.is_empty() {
+            self.add(&building, &batch).await?;
+            count += batch.len();
+        }
+        self.request(Method::POST, "swap-indexes", Some(json!([{ "indexes": [self.index, building] }]))).await?;
+        self.request(Method::DELETE, &format!("indexes/{building}"), None).await?;
+        Ok(count)
+    }
+
+    /// Sends the contacts of the contact events after `after` again, or deletes them if they
+    /// are gone. Returns the id of the last event seen.
+    async fn follow(&self, db: &Db, after: i64) -> anyhow::Result<i64> {
+        let changes = Event::contact_changes_after(db, after, BATCH_SIZE as i64).await?;
+        let mut last = after;
+        for event in changes {
+            last = event.id;
+            let Some(contact_id) = event.contact_id else { continue };
+            let contact = match event.kind.as_str() {
+                events::CONTACT_DELETED => None,
+                _ => match self.contacts.find_by_id(contact_id).await {
+                    Ok(contact) => Some(contact),
+                    // Deleted again since
+                    Err(ContactError::NotFound) => None,
+                    Err(err) => anyhow::bail!("Failed to read contact {contact_id}: {err}"),
+                },
+            };
+            match contact {
+                Some(contact) => self.add(&self.index, &[contact]).await?,
+                None => {
+                    self.request(Method::DELETE, &format!("indexes/{}/documents/{contact_id}", self.index), None).await?;
+                }
+            }
+        }
+        Ok(last)
+    }
+
+    /// Rebuilds the index, then follows the event log until the process exits. Events from
+    /// the start of the rebuild on are sent again after it, so nothing changed meanwhile is
+    /// missed. A failure is logged and tried again on the next round.
+    pub fn spawn_sync(&self, db: Db) {
+        let search = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SYNC_INTERVAL);
+            let mut last = None;
+            loop {
+                interval.tick().await;
+                last = match last {
+                    None => match Event::last_id(&db).await {
+                        Ok(start) => match search.rebuild().await {
+                            Ok(count) => {
+                                info!("Sent {count} contacts to the Meilisearch index {}", search.index);
+                                Some(start)
+                            }
+                            Err(err) => {
+                                warn!("Failed to build the Meilisearch index: {err:#}");
+                                None
+                            }
+                        },
+                        Err(err) => {
+                            warn!("Failed to read the event log for Meilisearch: {err}");
+                            None
+                        }
+                    },
+                    Some(after) => match search.follow(&db, after).await {
+                        Ok(last) => Some(last),
+                        Err(err) => {
+                            warn!("Failed to send contact changes to Meilisearch: {err:#}");
+                            Some(after)
+                        }
+                    },
+                };
+            }
+        });
+    }
+}
+
+/// The filter's conditions besides the text, in Meilisearch's filter syntax.
+fn conditions(filter: &ContactFilter) -> Vec<String> {
+    let timestamp = |date: &chrono::NaiveDate| date.and_hms_opt(0, 0, 0).map_or(0, |time| time.and_utc().timestamp());
+    let quoted = |value: &str| format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut conditions = Vec::new();
+    if let Some(after) = &filter.created_after {
+        conditions.push(format!("created_ts >= {}", timestamp(after)));
+    }
+    if let Some(before) = &filter.created_before {
+        conditions.push(format!("created_ts < {}", timestamp(before)));
+    }
+    if let Some(domain) = &filter.email_domain {
+        conditions.push(format!("email_domain = {}", quoted(domain)));
+    }
+    if let Some(initial) = filter.last_initial {
+        conditions.push(format!("initial = {}", quoted(&initial.to_string())));
+    }
+    conditions
+}
+
+#[async_trait]
+impl SearchBackend for MeilisearchSearch {
+    /// With search text, pages are by number in order of relevance, which has no keyset to
+    /// start after, so `after` is left out. The list's links carry the page number as well.
+    async fn search(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> anyhow::Result<Vec<Contact>> {
+        match &filter.text {
+            Some(text) => Ok(self.query(filter, text, page, per_page).await?.hits),
+            None => Ok(self.contacts.search(filter, page, per_page, sort, after).await?),
+        }
+    }
+
+    async fn count(&self, filter: &ContactFilter) -> anyhow::Result<i64> {
+        match &filter.text {
+            Some(text) => Ok(self.query(filter, text, 1, 0).await?.total_hits),
+            None => Ok(self.contacts.count(filter).await?),
+        }
+    }
+
+    /// The hits and the total come back from the same request.
+    async fn search_page(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> anyhow::Result<ContactPage> {
+        match &filter.text {
+            Some(text) => {
+                let response = self.query(filter, text, page, per_page).await?;
+                Ok(ContactPage { contacts: response.hits, total: response.total_hits })
+            }
+            None => Ok(self.contacts.search_page(filter, page, per_page, sort, after).await?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{Json, Router, extract::Request, http::Method as HttpMethod};
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::{app, config::Config, contacts::NewContact, db::MEMORY_URL};
+
+    /// The requests a stub Meilisearch got: method, path and JSON body.
+    type Received = Arc<Mutex<Vec<(HttpMethod, String, Value)>>>;
+
+    /// A Meilisearch that takes every request and answers `{}`, on a local port.
+    async fn stub() -> (String, Received) {
+        let received = Received::default();
+        let kept = received.clone();
+        let app = Router::new().fallback(move |request: Request| {
+            let kept = kept.clone();
+            async move {
+                let (parts, body) = request.into_parts();
+                let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+                let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+                kept.lock().unwrap().push((parts.method, parts.uri.path().to_string(), body));
+                Json(json!({}))
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/meili", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (url, received)
+    }
+
+    async fn search() -> (MeilisearchSearch, Db, Received) {
+        let mut config = Config::default();
+        config.database.url = MEMORY_URL.to_string();
+        let (db, contacts) = app::connect(&config).await.unwrap();
+        let (url, received) = stub().await;
+        (MeilisearchSearch::new(&config.search, &url, contacts).unwrap(), db, received)
+    }
+
+    fn new_contact(n: usize) -> NewContact {
+        NewContact {
+            first_name: format!("Test{n}"),
+            last_name: "Contact".into(),
+            phone_number: format!("+1 999 000 01{n:02}"),
+            email: format!("test{n}@webone.test"),
+            ..NewContact::default()
+        }
+    }
+
+    #[test]
+    fn conditions_quote_the_values() {
+        assert!(conditions(&ContactFilter::default()).is_empty());
+        let filter = ContactFilter {
+            text: Some("ignored".into()),
+            created_after: NaiveDate::from_ymd_opt(2026, 10, 1),
+            created_before: NaiveDate::from_ymd_opt(2026, 10, 15),
+            email_domain: Some(r#"ex"ample\.com"#.into()),
+            last_initial: Some('D'),
+        };
+        assert_eq!(
+            conditions(&filter),
+            [
+                "created_ts >= 1790812800",
+                "created_ts < 1792022400",
+                r#"email_domain = "ex\"ample\\.com""#,
+                r#"initial = "D""#,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn follow_sends_the_changed_contacts_and_deletes_the_gone_ones() {
+        let (search, db, received) = search().await;
+        let now = chrono::Utc::now();
+        let kept = search.contacts.create(new_contact(1), now).await.unwrap();
+        let gone = search.contacts.create(new_contact(2), now).await.unwrap();
+        let start = Event::last_id(&db).await.unwrap();
+        Event::record(&db, events::CONTACT_UPDATED, "test", Some(kept.id), json!({})).await.unwrap();
+        // Updated, then deleted before the log was read
+        Event::record(&db, events::CONTACT_UPDATED, "test", Some(gone.id), json!({})).await.unwrap();
+        search.contacts.delete(gone.id).await.unwrap();
+        Event::record(&db, events::CONTACT_DELETED, "test", Some(gone.id), json!({})).await.unwrap();
+        Event::record(&db, "webhook.created", "test", None, json!({})).await.unwrap();
+
+        let last = search.follow(&db, start).await.unwrap();
+        let received = std::mem::take(&mut *received.lock().unwrap());
+        let paths: Vec<(HttpMethod, &str)> = received.iter().map(|(method, path, _)| (method.clone(), path.as_str())).collect();
+        let gone_path = format!("/meili/indexes/contacts/documents/{}", gone.id);
+        assert_eq!(
+            paths,
+            [
+                (HttpMethod::POST, "/meili/indexes/contacts/documents"),
+                (HttpMethod::DELETE, gone_path.as_str()),
+                (HttpMethod::DELETE, gone_path.as_str()),
+            ]
+        );
+        assert_eq!(received[0].2[0]["id"], kept.id);
+        assert_eq!(received[0].2[0]["phone_digits"], "19990000101");
+        // The last contact event, the others aren't read
+        assert_eq!(last, start + 3);
+        assert_eq!(search.follow(&db, last).await.unwrap(), last);
+    }
+
+    #[tokio::test]
+    async fn rebuild_fills_a_new_index_and_swaps_it_in() {
+        let (search, _db, received) = search().await;
+        search.contacts.create(new_contact(1), chrono::Utc::now()).await.unwrap();
+        let count = search.contacts.all().await.unwrap().len();
+        assert_eq!(search.rebuild().await.unwrap(), count);
+
+        let received = received.lock().unwrap();
+        let added: Vec<&Value> = received
+            .iter()
+            .filter(|(method, path, _)| method == HttpMethod::POST && path == "/meili/indexes/contacts_rebuild/documents")
+            .map(|(_, _, body)| body)
+            .collect();
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].as_array().unwrap().len(), count);
+        let (method, path, body) = &received[received.len() - 2];
+        assert_eq!((method, path.as_str()), (&HttpMethod::POST, "/meili/swap-indexes"));
+        assert_eq!(body, &json!([{ "indexes": ["contacts", "contacts_rebuild"] }]));
+        let (method, path, _) = &received[received.len() - 1];
+        assert_eq!((method, path.as_str()), (&HttpMethod::DELETE, "/meili/indexes/contacts_rebuild"));
+    }
+}
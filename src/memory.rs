@@ -0,0 +1,545 @@
+//! `MemoryContactRepository`, the contact store of demo mode (`database.url = "memory:"`) and
+//! of tests. It holds the contacts in a `Vec` and answers every query by walking it, so it is
+//! only meant for small address books.
+//!
+//! It refuses the same things the SQL backends do, so the app behaves the same on either: the
+//! unique email and phone number checks (see `taken_field`), versions for `update_unchanged`,
+//! and batches that apply all or nothing. Each check and the write it guards happen under one
+//! write lock, the way the database does them in one statement or transaction, so concurrent
+//! requests can't both pass a check and then both write.
+
+use std::sync::{
+    RwLock,
+    atomic::{AtomicI64, Ordering as AtomicOrdering},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::{
+    contacts::{BatchFailure, BatchFailureReason, BatchVersions, Company, Contact, ContactCursor, ContactError, ContactFilter, ContactOrder, ContactPage, ContactWrite, LetterCount, NewContact},
+    dates::ContactDate,
+    phone,
+    relationships::Relationship,
+    repository::{ContactRepository, ContactStream},
+    stats::ContactStats,
+};
+
+/// Contact store that lives entirely in memory, for demos and tests. Everything is lost when
+/// the process exits. Selected with `database.url = "memory:"`.
+#[derive(Debug, Default)]
+pub struct MemoryContactRepository {
+    contacts: RwLock<Vec<Contact>>,
+    next_id: AtomicI64,
+    relationships: RwLock<Vec<StoredRelationship>>,
+    next_relationship_id: AtomicI64,
+    dates: RwLock<Vec<StoredDate>>,
+    next_date_id: AtomicI64,
+}
+
+/// A relationship the way the SQL backends store it: `contact_id` is `label` `related_id`.
+#[derive(Debug, Clone)]
+struct StoredRelationship {
+    id: i64,
+    contact_id: i64,
+    related_id: i64,
+    label: String,
+}
+
+/// A date the way the SQL backends store it, without the contact's name.
+#[derive(Debug, Clone)]
+struct StoredDate {
+    id: i64,
+    contact_id: i64,
+    label: String,
+    date: NaiveDate,
+    yearly: bool,
+}
+
+impl MemoryContactRepository {
+    /// Starts out with the given contacts. New ids continue after the highest existing one.
+    pub fn new(contacts: Vec<Contact>) -> Self {
+        let next_id = contacts.iter().map(|c| c.id).max().unwrap_or(0) + 1;
+        Self {
+            contacts: RwLock::new(contacts),
+            next_id: AtomicI64::new(next_id),
+            relationships: RwLock::default(),
+            next_relationship_id: AtomicI64::new(1),
+            dates: RwLock::default(),
+            next_date_id: AtomicI64::new(1),
+        }
+    }
+
+    /// `new` as stored with `id`.
+    fn contact(id: i64, new: NewContact, now: DateTime<Utc>) -> Contact {
+        Contact {
+            id,
+            first_name: new.first_name,
+            last_name: new.last_name,
+            phone_number: new.phone_number,
+            email: new.email,
+            company: new.company,
+            job_title: new.job_title,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Stores `new` in `contacts`, the locked contacts, with the next id if no other contact
+    /// has its email or phone number (see `taken_field`). Checking and storing under the same
+    /// lock keeps two creates of one number from both getting in.
+    fn insert(&self, contacts: &mut Vec<Contact>, new: NewContact, now: DateTime<Utc>) -> Result<Contact, &'static str> {
+        if let Some(field) = taken_field(contacts, &new.email, &new.phone_number) {
+            return Err(field);
+        }
+        let contact = Self::contact(self.next_id.fetch_add(1, AtomicOrdering::SeqCst), new, now);
+        contacts.push(contact.clone());
+        Ok(contact)
+    }
+
+    fn sorted(mut contacts: Vec<Contact>, sort: ContactOrder) -> Vec<Contact> {
+        contacts.sort_by(|a, b| sort.compare(a, b));
+        contacts
+    }
+
+    /// Same paging as the SQL queries: from the `after` cursor if given, else by offset.
+    fn page(
+        contacts: Vec<Contact>,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Vec<Contact> {
+        let contacts = Self::sorted(contacts, sort);
+        let skip = match after {
+            Some(cursor) => contacts
+                .iter()
+                .take_while(|c| !cursor.precedes(c))
+                .count(),
+            None => ((page - 1) * per_page).max(0) as usize,
+        };
+        contacts
+            .into_iter()
+            .skip(skip)
+            .take(per_page.max(0) as usize)
+            .collect()
+    }
+}
+
+/// The field another of `contacts` has the same value in the way the unique indexes see it:
+/// the same email ignoring case, or the same phone number digits.
+fn taken_field(contacts: &[Contact], email: &str, phone_number: &str) -> Option<&'static str> {
+    let phone_number = phone::normalize(phone_number);
+    contacts.iter().find_map(|c| {
+        if !email.is_empty() && c.email.eq_ignore_ascii_case(email) {
+            Some("email")
+        } else if !phone_number.is_empty() && phone::normalize(&c.phone_number) == phone_number {
+            Some("phone_number")
+        } else {
+            None
+        }
+    })
+}
+
+/// Whether `stored` is still the version `read` was read at, to the millisecond like the ETag.
+fn same_version(stored: &Contact, read: &Contact) -> bool {
+    stored.updated_at.timestamp_millis() == read.updated_at.timestamp_millis()
+}
+
+#[async_trait]
+impl ContactRepository for MemoryContactRepository {
+    /// Refuses the contact the way `create_many` does.
+    async fn create(&self, new: NewContact, now: DateTime<Utc>) -> Result<Contact, ContactError> {
+        self.insert(&mut self.contacts.write().unwrap(), new, now).map_err(ContactError::Taken)
+    }
+
+    /// Refuses contacts the way the unique indexes do (see `taken_field`), including for
+    /// earlier ones in the batch.
+    async fn create_many(
+        &self,
+        new: &[NewContact],
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Result<Contact, &'static str>>, sqlx::Error> {
+        let mut contacts = self.contacts.write().unwrap();
+        Ok(new.iter().map(|contact| self.insert(&mut contacts, contact.clone(), now)).collect())
+    }
+
+    /// Adds the contacts to a copy of the contacts the way `create_many` does, and drops it.
+    async fn check_many(&self, new: &[NewContact], now: DateTime<Utc>) -> Result<Vec<Option<&'static str>>, sqlx::Error> {
+        let mut contacts = self.contacts.read().unwrap().clone();
+        Ok(new
+            .iter()
+            .map(|contact| {
+                let conflict = taken_field(&contacts, &contact.email, &contact.phone_number);
+                if conflict.is_none() {
+                    contacts.push(Self::contact(0, contact.clone(), now));
+                }
+                conflict
+            })
+            .collect())
+    }
+
+    /// Makes the changes on a copy of the contacts, refusing them the way `create_many` does,
+    /// or the way `update_unchanged` does if the contact isn't at its version any more, and
+    /// keeps it only if all of them could be made.
+    async fn apply_batch(
+        &self,
+        writes: &[ContactWrite],
+        now: DateTime<Utc>,
+    ) -> Result<Result<Vec<Contact>, BatchFailure>, sqlx::Error> {
+        let mut stored = self.contacts.write().unwrap();
+        let mut contacts = stored.clone();
+        let mut next_id = self.next_id.load(AtomicOrdering::SeqCst);
+        let mut applied = Vec::with_capacity(writes.len());
+        let mut deleted = Vec::new();
+        let mut versions = BatchVersions::default();
+        for (index, write) in writes.iter().enumerate() {
+            let read = match write {
+                ContactWrite::Create(_) => None,
+                ContactWrite::Update(contact) | ContactWrite::Delete(contact) => Some(versions.current(contact)),
+            };
+            let (email, phone_number) = match write {
+                ContactWrite::Create(new) => (&new.email, &new.phone_number),
+                ContactWrite::Update(contact) | ContactWrite::Delete(contact) => (&contact.email, &contact.phone_number),
+            };
+            let id = read.as_ref().map(|read| read.id);
+            let position = read
+                .as_ref()
+                .and_then(|read| contacts.iter().position(|c| c.id == read.id && same_version(c, read)));
+            if id.is_some() && position.is_none() {
+                return Ok(Err(BatchFailure { index, reason: BatchFailureReason::Changed }));
+            }
+            if !matches!(write, ContactWrite::Delete(_)) {
+                let others: Vec<Contact> = contacts.iter().filter(|c| Some(c.id) != id).cloned().collect();
+                if let Some(field) = taken_field(&others, email, phone_number) {
+                    return Ok(Err(BatchFailure { index, reason: BatchFailureReason::Taken(field) }));
+                }
+            }
+            let contact = match write {
+                ContactWrite::Create(new) => {
+                    let contact = Contact {
+                        id: next_id,
+                        first_name: new.first_name.clone(),
+                        last_name: new.last_name.clone(),
+                        phone_number: new.phone_number.clone(),
+                        email: new.email.clone(),
+                        company: new.company.clone(),
+                        job_title: new.job_title.clone(),
+                        created_at: now,
+                        updated_at: now,
+                    };
+                    next_id += 1;
+                    contacts.push(contact.clone());
+                    contact
+                }
+                ContactWrite::Update(_) => {
+                    let position = position.expect("checked above");
+                    let read = read.expect("an update has a contact");
+                    let updated_at = read.next_version(now);
+                    versions.set(read.id, updated_at);
+                    contacts[position] = Contact { updated_at, ..read };
+                    contacts[position].clone()
+                }
+                ContactWrite::Delete(_) => {
+                    let contact = contacts.remove(position.expect("checked above"));
+                    deleted.push(contact.id);
+                    contact
+                }
+            };
+            applied.push(contact);
+        }
+        *stored = contacts;
+        self.next_id.store(next_id, AtomicOrdering::SeqCst);
+        self.relationships
+            .write()
+            .unwrap()
+            .retain(|r| !deleted.contains(&r.contact_id) && !deleted.contains(&r.related_id));
+        self.dates.write().unwrap().retain(|d| !deleted.contains(&d.contact_id));
+        Ok(Ok(applied))
+    }
+
+    async fn update(&self, contact: &Contact, now: DateTime<Utc>) -> Result<(), ContactError> {
+        let mut contacts = self.contacts.write().unwrap();
+        let others: Vec<Contact> = contacts.iter().filter(|c| c.id != contact.id).cloned().collect();
+        if let Some(field) = taken_field(&others, &contact.email, &contact.phone_number) {
+            return Err(ContactError::Taken(field));
+        }
+        let existing = contacts.iter_mut().find(|c| c.id == contact.id).ok_or(ContactError::NotFound)?;
+        *existing = Contact {
+            updated_at: contact.next_version(now),
+            ..contact.clone()
+        };
+        Ok(())
+    }
+
+    async fn update_unchanged(&self, contact: &Contact, now: DateTime<Utc>) -> Result<(), ContactError> {
+        // Checked and written under the one lock, like the conditional `UPDATE`
+        let mut contacts = self.contacts.write().unwrap();
+        if !contacts.iter().any(|c| c.id == contact.id && same_version(c, contact)) {
+            return Err(ContactError::Changed);
+        }
+        let others: Vec<Contact> = contacts.iter().filter(|c| c.id != contact.id).cloned().collect();
+        if let Some(field) = taken_field(&others, &contact.email, &contact.phone_number) {
+            return Err(ContactError::Taken(field));
+        }
+        let existing = contacts.iter_mut().find(|c| c.id == contact.id).ok_or(ContactError::Changed)?;
+        *existing = Contact {
+            updated_at: contact.next_version(now),
+            ..contact.clone()
+        };
+        Ok(())
+    }
+
+    async fn delete(&self, id: i64) -> Result<(), ContactError> {
+        self.contacts.write().unwrap().retain(|c| c.id != id);
+        self.relationships
+            .write()
+            .unwrap()
+            .retain(|r| r.contact_id != id && r.related_id != id);
+        self.dates.write().unwrap().retain(|d| d.contact_id != id);
+        Ok(())
+    }
+
+    async fn delete_unchanged(&self, contact: &Contact) -> Result<(), ContactError> {
+        {
+            let mut contacts = self.contacts.write().unwrap();
+            let before = contacts.len();
+            contacts.retain(|c| !(c.id == contact.id && same_version(c, contact)));
+            if contacts.len() == before {
+                return Err(ContactError::Changed);
+            }
+        }
+        self.delete(contact.id).await
+    }
+
+    /// Refuses the contact the way `create_many` does.
+    async fn restore(&self, contact: &Contact) -> Result<Result<(), &'static str>, sqlx::Error> {
+        let mut contacts = self.contacts.write().unwrap();
+        if let Some(field) = taken_field(&contacts, &contact.email, &contact.phone_number) {
+            return Ok(Err(field));
+        }
+        contacts.push(contact.clone());
+        self.next_id.fetch_max(contact.id + 1, AtomicOrdering::SeqCst);
+        Ok(Ok(()))
+    }
+
+    async fn get_all(
+        &self,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Result<Vec<Contact>, sqlx::Error> {
+        let contacts = self.contacts.read().unwrap().clone();
+        Ok(Self::page(contacts, page, per_page, sort, after))
+    }
+
+    async fn all(&self) -> Result<Vec<Contact>, sqlx::Error> {
+        let contacts = self.contacts.read().unwrap().clone();
+        Ok(Self::sorted(contacts, ContactOrder::default()))
+    }
+
+    fn stream_all(&self) -> ContactStream<'_> {
+        let contacts = self.contacts.read().unwrap().clone();
+        Box::pin(tokio_stream::iter(Self::sorted(contacts, ContactOrder::default()).into_iter().map(Ok)))
+    }
+
+    async fn newest(&self, limit: i64) -> Result<Vec<Contact>, sqlx::Error> {
+        let mut contacts = self.contacts.read().unwrap().clone();
+        contacts.sort_by_key(|c| std::cmp::Reverse((c.created_at, c.id)));
+        contacts.truncate(usize::try_from(limit).unwrap_or(0));
+        Ok(contacts)
+    }
+
+    async fn companies(&self) -> Result<Vec<Company>, sqlx::Error> {
+        let mut contacts = self.contacts.read().unwrap().clone();
+        contacts.sort_by(|a, b| {
+            (&a.company, &a.last_name, &a.first_name, a.id).cmp(&(&b.company, &b.last_name, &b.first_name, b.id))
+        });
+        Ok(Company::group(contacts))
+    }
+
+    async fn find_by_id(&self, id: i64) -> Result<Contact, ContactError> {
+        self.contacts
+            .read()
+            .unwrap()
+            .iter()
+            .find(|c| c.id == id)
+            .cloned()
+            .ok_or(ContactError::NotFound)
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<Contact>, sqlx::Error> {
+        let contacts = self.contacts.read().unwrap();
+        Ok(contacts.iter().filter(|c| c.email.eq_ignore_ascii_case(email)).min_by_key(|c| c.id).cloned())
+    }
+
+    async fn search(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Result<Vec<Contact>, sqlx::Error> {
+        let contacts: Vec<Contact> = self
+            .contacts
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|c| filter.matches(c))
+            .cloned()
+            .collect();
+        Ok(Self::page(contacts, page, per_page, sort, after))
+    }
+
+    async fn search_page(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Result<ContactPage, sqlx::Error> {
+        let contacts = self.search(filter, page, per_page, sort, after).await?;
+        let total = self.count(filter).await?;
+        Ok(ContactPage { contacts, total })
+    }
+
+    async fn count(&self, filter: &ContactFilter) -> Result<i64, sqlx::Error> {
+        let contacts = self.contacts.read().unwrap();
+        Ok(contacts.iter().filter(|c| filter.matches(c)).count() as i64)
+    }
+
+    async fn letter_counts(&self, filter: &ContactFilter) -> Result<Vec<LetterCount>, sqlx::Error> {
+        let filter = filter.without_initial();
+        let contacts = self.contacts.read().unwrap();
+        let initials: Vec<(String, i64)> = contacts
+            .iter()
+            .filter(|c| filter.matches(c))
+            .filter_map(|c| c.last_name.chars().next())
+            .map(|initial| (initial.to_uppercase().to_string(), 1))
+            .collect();
+        Ok(LetterCount::fill(&initials))
+    }
+
+    async fn email_exists(&self, email: &str, exclude_id: Option<i64>) -> Result<bool, sqlx::Error> {
+        Ok(self
+            .contacts
+            .read()
+            .unwrap()
+            .iter()
+            .any(|c| c.email.eq_ignore_ascii_case(email) && Some(c.id) != exclude_id))
+    }
+
+    async fn phone_exists(&self, phone_number: &str, exclude_id: Option<i64>) -> Result<bool, sqlx::Error> {
+        // Compared like `taken_field` does, the way the unique index sees it
+        let phone_number = phone::normalize(phone_number);
+        Ok(!phone_number.is_empty()
+            && self
+                .contacts
+                .read()
+                .unwrap()
+                .iter()
+                .any(|c| Some(c.id) != exclude_id && phone::normalize(&c.phone_number) == phone_number))
+    }
+
+    async fn stats(&self, now: DateTime<Utc>) -> Result<ContactStats, sqlx::Error> {
+        Ok(ContactStats::from_contacts(&self.contacts.read().unwrap(), now))
+    }
+
+    async fn relationships(&self, id: i64) -> Result<Vec<Relationship>, sqlx::Error> {
+        let contacts = self.contacts.read().unwrap();
+        let mut relationships: Vec<(Relationship, &Contact)> = self
+            .relationships
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|r| r.contact_id == id || r.related_id == id)
+            .filter_map(|r| {
+                let incoming = r.contact_id != id;
+                let other_id = if incoming { r.contact_id } else { r.related_id };
+                let other = contacts.iter().find(|c| c.id == other_id)?;
+                let relationship = Relationship {
+                    id: r.id,
+                    label: r.label.clone(),
+                    other_id,
+                    other_first_name: other.first_name.clone(),
+                    other_last_name: other.last_name.clone(),
+                    incoming,
+                };
+                Some((relationship, other))
+            })
+            .collect();
+        relationships.sort_by(|(a, a_other), (b, b_other)| {
+            (&a.label, &a_other.last_name, &a_other.first_name, a.id)
+                .cmp(&(&b.label, &b_other.last_name, &b_other.first_name, b.id))
+        });
+        Ok(relationships.into_iter().map(|(relationship, _)| relationship).collect())
+    }
+
+    async fn add_relationship(&self, contact_id: i64, related_id: i64, label: &str) -> Result<i64, sqlx::Error> {
+        let id = self.next_relationship_id.fetch_add(1, AtomicOrdering::SeqCst);
+        self.relationships.write().unwrap().push(StoredRelationship {
+            id,
+            contact_id,
+            related_id,
+            label: label.to_string(),
+        });
+        Ok(id)
+    }
+
+    async fn remove_relationship(&self, contact_id: i64, id: i64) -> Result<bool, sqlx::Error> {
+        let mut relationships = self.relationships.write().unwrap();
+        let before = relationships.len();
+        relationships.retain(|r| r.id != id || (r.contact_id != contact_id && r.related_id != contact_id));
+        Ok(relationships.len() < before)
+    }
+
+    async fn dates(&self, id: i64) -> Result<Vec<ContactDate>, sqlx::Error> {
+        let mut dates: Vec<ContactDate> = self.all_dates().await?.into_iter().filter(|d| d.contact_id == id).collect();
+        dates.sort_by(|a, b| (a.date, &a.label, a.id).cmp(&(b.date, &b.label, b.id)));
+        Ok(dates)
+    }
+
+    async fn all_dates(&self) -> Result<Vec<ContactDate>, sqlx::Error> {
+        let contacts = self.contacts.read().unwrap();
+        Ok(self
+            .dates
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|d| {
+                let contact = contacts.iter().find(|c| c.id == d.contact_id)?;
+                Some(ContactDate {
+                    id: d.id,
+                    contact_id: d.contact_id,
+                    first_name: contact.first_name.clone(),
+                    last_name: contact.last_name.clone(),
+                    label: d.label.clone(),
+                    date: d.date,
+                    yearly: d.yearly,
+                })
+            })
+            .collect())
+    }
+
+    async fn add_date(&self, contact_id: i64, label: &str, date: NaiveDate, yearly: bool) -> Result<i64, sqlx::Error> {
+        let id = self.next_date_id.fetch_add(1, AtomicOrdering::SeqCst);
+        self.dates.write().unwrap().push(StoredDate {
+            id,
+            contact_id,
+            label: label.to_string(),
+            date,
+            yearly,
+        });
+        Ok(id)
+    }
+
+    async fn remove_date(&self, contact_id: i64, id: i64) -> Result<bool, sqlx::Error> {
+        let mut dates = self.dates.write().unwrap();
+        let before = dates.len();
+        dates.retain(|d| d.id != id || d.contact_id != contact_id);
+        Ok(dates.len() < before)
+    }
+}
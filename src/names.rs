@@ -0,0 +1,78 @@
+//! How a contact's name is written out and ordered: "Jane Doe" or "Doe, Jane". Pages, the PDF
+//! sheets, the CSV export and lists ordered by name all go through `NameFormat`, so a name
+//! reads the same everywhere. Which format a request gets is its display preference, see
+//! `preferences`.
+
+use std::cmp::Ordering;
+
+use crate::contacts::Contact;
+
+/// How a contact's name is written out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum NameFormat {
+    /// "Jane Doe", first name first.
+    #[default]
+    FirstLast,
+    /// "Doe, Jane", and the last name column first in the list.
+    LastFirst,
+}
+
+impl NameFormat {
+    pub const ALL: &[NameFormat] = &[NameFormat::FirstLast, NameFormat::LastFirst];
+
+    /// The value in the cookie and the settings form.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NameFormat::FirstLast => "first_last",
+            NameFormat::LastFirst => "last_first",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|format| format.as_str() == value)
+    }
+
+    pub fn last_first(&self) -> bool {
+        *self == NameFormat::LastFirst
+    }
+
+    /// The name in this format. Whitespace around and inside the parts is tidied up, and if
+    /// one part is empty the other is written alone, without a stray comma. Empty if both are.
+    pub fn format(&self, first_name: &str, last_name: &str) -> String {
+        let (first, last) = (squash(first_name), squash(last_name));
+        match (self, first.is_empty(), last.is_empty()) {
+            (_, true, _) => last,
+            (_, _, true) => first,
+            (NameFormat::FirstLast, ..) => format!("{first} {last}"),
+            (NameFormat::LastFirst, ..) => format!("{last}, {first}"),
+        }
+    }
+
+    /// `contact`'s name in this format, or its email for a contact without one, so there is
+    /// always something to show and link.
+    pub fn contact(&self, contact: &Contact) -> String {
+        let name = self.format(&contact.first_name, &contact.last_name);
+        if name.is_empty() { contact.email.clone() } else { name }
+    }
+
+    /// Orders two names the way a list in this format reads: by the part written first, then
+    /// the other one, ignoring case.
+    pub fn compare(&self, (a_first, a_last): (&str, &str), (b_first, b_last): (&str, &str)) -> Ordering {
+        let key = |first: &str, last: &str| match self {
+            NameFormat::FirstLast => (first.trim().to_lowercase(), last.trim().to_lowercase()),
+            NameFormat::LastFirst => (last.trim().to_lowercase(), first.trim().to_lowercase()),
+        };
+        key(a_first, a_last).cmp(&key(b_first, b_last))
+    }
+
+    /// `compare` for two contacts, by id when their names are the same.
+    pub fn compare_contacts(&self, a: &Contact, b: &Contact) -> Ordering {
+        self.compare((&a.first_name, &a.last_name), (&b.first_name, &b.last_name))
+            .then(a.id.cmp(&b.id))
+    }
+}
+
+/// `part` trimmed, with runs of whitespace inside it turned into single spaces.
+fn squash(part: &str) -> String {
+    part.split_whitespace().collect::<Vec<_>>().join(" ")
+}
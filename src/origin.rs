@@ -0,0 +1,51 @@
+//! Whether a request comes from one of the app's own pages. Browsers send cookies and go to
+//! local addresses for any site, so a page elsewhere can post a form to the admin pages of an
+//! app running on the reader's machine, or open its live socket. Browsers say where a request
+//! started in `Sec-Fetch-Site`, and older ones in `Origin`; requests with neither didn't come
+//! from a page, like ones from `curl`, and are let through.
+
+use axum::{
+    extract::Request,
+    http::{
+        HeaderMap, StatusCode,
+        header::{HOST, ORIGIN},
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tracing::warn;
+
+/// Whether the page making the request is on this host. `Sec-Fetch-Site: none` is the reader
+/// typing the address or following a bookmark.
+pub fn same_origin(headers: &HeaderMap) -> bool {
+    if let Some(site) = headers.get("sec-fetch-site") {
+        return matches!(site.as_bytes(), b"same-origin" | b"none");
+    }
+    let Some(origin) = headers.get(ORIGIN).and_then(|value| value.to_str().ok()) else {
+        // Not a browser
+        return true;
+    };
+    let host = headers.get(HOST).and_then(|value| value.to_str().ok()).unwrap_or_default();
+    origin.split_once("://").is_some_and(|(_, origin_host)| origin_host.eq_ignore_ascii_case(host))
+}
+
+/// The 403 for a change that another site asked for, or `None` if `request` only reads or
+/// comes from this one.
+pub fn refuse_cross_site(request: &Request) -> Option<Response> {
+    if request.method().is_safe() || same_origin(request.headers()) {
+        return None;
+    }
+    warn!("Cross-site {} to {} refused", request.method(), request.uri().path());
+    Some((StatusCode::FORBIDDEN, "Cross-site request refused").into_response())
+}
+
+/// Middleware: refuses `POST`s and other changes that came from another site's page.
+///
+/// Example usage:
+/// `router.layer(middleware::from_fn(origin::guard))`
+pub async fn guard(request: Request, next: Next) -> Response {
+    match refuse_cross_site(&request) {
+        Some(refused) => refused,
+        None => next.run(request).await,
+    }
+}
@@ -0,0 +1,109 @@
+//! Paging state for list views, rendered by the `pagination.html` partial.
+
+/// Where a list view is in its result set. Built by the handler and passed to the template
+/// as `pagination`, which `{% include "pagination.html" %}` picks up.
+#[derive(Debug, Clone)]
+pub struct Pagination {
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+    base_url: String,
+    /// Query parameters every page link keeps, e.g. the search term.
+    query: Vec<(String, String)>,
+    /// Opaque keyset cursor this page was fetched with, if any.
+    after: Option<String>,
+    /// Cursor the next page starts after. The Next link uses it instead of an offset.
+    next_after: Option<String>,
+}
+
+impl Pagination {
+    /// Empty query values are left out of the links, so an empty search doesn't end up as
+    /// `?q=` on every page.
+    pub fn new(page: i64, per_page: i64, total: i64, base_url: &str, query: &[(&str, &str)]) -> Self {
+        Self {
+            page: page.max(1),
+            per_page: per_page.max(1),
+            total: total.max(0),
+            base_url: base_url.to_string(),
+            query: query
+                .iter()
+                .filter(|(_, value)| !value.is_empty())
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            after: None,
+            next_after: None,
+        }
+    }
+
+    /// Adds keyset cursors: `after` is the one this page was fetched with, `next_after` the one
+    /// the next page starts after.
+    pub fn with_cursors(self, after: Option<String>, next_after: Option<String>) -> Self {
+        Self { after, next_after, ..self }
+    }
+
+    /// Number of pages `total` items make at `per_page` a page. Always at least 1, so an empty
+    /// list still reads "page 1 of 1".
+    pub fn page_count(total: i64, per_page: i64) -> i64 {
+        let per_page = per_page.max(1);
+        ((total.max(0) + per_page - 1) / per_page).max(1)
+    }
+
+    pub fn total_pages(&self) -> i64 {
+        Self::page_count(self.total, self.per_page)
+    }
+
+    pub fn has_prev(&self) -> bool {
+        self.page > 1
+    }
+
+    pub fn has_next(&self) -> bool {
+        self.page < self.total_pages()
+    }
+
+    /// URL of the given page, keeping the preserved query parameters. The page is found by
+    /// offset; only `current_url` and `next_url` use cursors.
+    pub fn url(&self, page: i64) -> String {
+        self.link(page, None)
+    }
+
+    fn link(&self, page: i64, after: Option<&str>) -> String {
+        let mut params = vec![("page", page.to_string())];
+        params.extend(self.query.iter().map(|(k, v)| (k.as_str(), v.clone())));
+        if let Some(after) = after {
+            params.push(("after", after.to_string()));
+        }
+        // Serializing a list of string pairs can't fail
+        format!(
+            "{}?{}",
+            self.base_url,
+            serde_urlencoded::to_string(params).unwrap_or_default()
+        )
+    }
+
+    /// Where the "jump to page" form is sent, with `query` as hidden fields.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// The query parameters every page link keeps, without the page and cursor.
+    pub fn query(&self) -> &[(String, String)] {
+        &self.query
+    }
+
+    pub fn current_url(&self) -> String {
+        self.link(self.page, self.after.as_deref())
+    }
+
+    /// URL of the next page, starting after the `next_after` cursor if there is one.
+    pub fn next_url(&self) -> String {
+        self.link(self.page + 1, self.next_after.as_deref())
+    }
+
+    /// The same position, with links to another endpoint that takes the same parameters.
+    pub fn with_base_url(&self, base_url: &str) -> Self {
+        Self {
+            base_url: base_url.to_string(),
+            ..self.clone()
+        }
+    }
+}
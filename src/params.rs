@@ -0,0 +1,273 @@
+use std::sync::Arc;
+
+use askama::Template;
+use axum::{
+    extract::{FromRef, FromRequestParts, Query},
+    http::{StatusCode, request::Parts},
+    response::{Html, IntoResponse, Response},
+};
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    contacts::{ContactCursor, ContactFilter, ContactOrder, ContactSort, MAX_SORT_KEYS, OrderProblem},
+    i18n,
+    preferences::Preferences,
+    query,
+    templates::ErrorMessageTemplate,
+    utils,
+};
+
+/// Largest `per_page` a client can ask for. Bigger values are clamped down to it.
+pub const MAX_PER_PAGE: i64 = 100;
+/// Largest `page` taken as asked, so the offset of any page fits an `i64`. Bigger ones are
+/// clamped down to it, and from there to the last page like any page past the end.
+pub const MAX_PAGE: i64 = i64::MAX / MAX_PER_PAGE;
+/// Longest search term accepted in `q`.
+pub const MAX_QUERY_LEN: usize = 100;
+
+/// Validated parameters for the contact list endpoints.
+///
+/// - `q`: search term, trimmed. Empty means no search. At most `MAX_QUERY_LEN` characters.
+///   Can contain filter tokens like `email:example.com`, see `query`.
+/// - `created_after`, `created_before`: `YYYY-MM-DD`, contacts created on or after / before
+///   that day.
+/// - `email_domain`: contacts with an email at that domain, `example.com` or `@example.com`.
+/// - `letter`: contacts whose last name starts with that letter, `A` to `Z` in either case.
+/// - `page`: 1 or more, defaults to 1. Clamped to `MAX_PAGE`.
+/// - `per_page`: clamped to `1..=MAX_PER_PAGE`, defaults to the browser's preference (see
+///   `Preferences`), else the configured `page_size`.
+/// - `sort`: up to `MAX_SORT_KEYS` different `ContactSort` columns, comma separated, like
+///   `company,last_name`. Defaults to the preferred one, else `id`.
+/// - `dir`: `asc` or `desc` for each of the `sort` columns, in the same order, like `desc,asc`.
+///   Columns without one are ascending.
+///
+/// Anything else is rejected with a 400 and an error fragment, in the request's locale, before
+/// the handler runs.
+///
+/// Example usage:
+/// `async fn contacts(State(state): State<AppState>, params: ListParams) -> ...`
+#[derive(Debug, Clone)]
+pub struct ListParams {
+    pub q: Option<String>,
+    pub created_after: Option<NaiveDate>,
+    pub created_before: Option<NaiveDate>,
+    /// Lowercase, without the `@`.
+    pub email_domain: Option<String>,
+    /// Uppercase.
+    pub letter: Option<char>,
+    pub page: i64,
+    pub per_page: i64,
+    pub sort: ContactOrder,
+    /// Everything the list is filtered by, from `q` and the filter parameters together.
+    pub filter: ContactFilter,
+    /// Keyset cursor from a Next link. The page starts right after it, and `page` is only
+    /// used for display.
+    pub after: Option<ContactCursor>,
+}
+
+/// The parameters as they come in. Everything is a string so bad values get a readable
+/// message instead of a serde error.
+#[derive(Debug, Deserialize)]
+struct RawListParams {
+    q: Option<String>,
+    created_after: Option<String>,
+    created_before: Option<String>,
+    email_domain: Option<String>,
+    letter: Option<String>,
+    page: Option<String>,
+    per_page: Option<String>,
+    sort: Option<String>,
+    dir: Option<String>,
+    after: Option<String>,
+}
+
+/// The search and filter values as they go into list URLs and the search form. Unset ones are
+/// empty.
+#[derive(Debug, Clone, Default)]
+pub struct ListFilters {
+    pub q: String,
+    pub created_after: String,
+    pub created_before: String,
+    pub email_domain: String,
+    /// The A–Z bar's letter, shown there rather than with the other filters.
+    pub letter: String,
+}
+
+impl ListFilters {
+    /// Query parameters for links to the same search. Empty values are dropped by
+    /// `Pagination`.
+    pub fn query(&self) -> [(&'static str, &str); 5] {
+        [
+            ("q", &self.q),
+            ("created_after", &self.created_after),
+            ("created_before", &self.created_before),
+            ("email_domain", &self.email_domain),
+            ("letter", &self.letter),
+        ]
+    }
+
+    /// `true` if any of the filters besides the search term is set, which opens them up in
+    /// the search form.
+    pub fn has_filters(&self) -> bool {
+        !(self.created_after.is_empty() && self.created_before.is_empty() && self.email_domain.is_empty())
+    }
+
+    /// `true` if the list is narrowed down at all: by the search term, a filter or a letter.
+    pub fn is_narrowed(&self) -> bool {
+        !self.q.is_empty() || !self.letter.is_empty() || self.has_filters()
+    }
+}
+
+/// A `YYYY-MM-DD` date parameter. Empty means unset.
+fn parse_date(name: &str, value: Option<&str>) -> Result<Option<NaiveDate>, String> {
+    match value.map(str::trim).filter(|v| !v.is_empty()) {
+        None => Ok(None),
+        Some(value) => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| i18n::t_args("invalid-date", &[("value", value.into()), ("name", name.into())])),
+    }
+}
+
+impl ListParams {
+    pub fn filters(&self) -> ListFilters {
+        let day = |date: Option<NaiveDate>| date.map(|d| d.to_string()).unwrap_or_default();
+        ListFilters {
+            q: self.q.clone().unwrap_or_default(),
+            created_after: day(self.created_after),
+            created_before: day(self.created_before),
+            email_domain: self.email_domain.clone().unwrap_or_default(),
+            letter: self.letter.map(String::from).unwrap_or_default(),
+        }
+    }
+
+    fn parse(raw: RawListParams, default_per_page: i64, default_sort: ContactSort) -> Result<Self, String> {
+        let q = raw
+            .q
+            .map(|q| q.trim().to_string())
+            .filter(|q| !q.is_empty());
+        if let Some(q) = &q
+            && q.chars().count() > MAX_QUERY_LEN
+        {
+            return Err(i18n::t_with("query-too-long", "max", MAX_QUERY_LEN));
+        }
+
+        let created_after = parse_date("created_after", raw.created_after.as_deref())?;
+        let created_before = parse_date("created_before", raw.created_before.as_deref())?;
+
+        let email_domain = raw
+            .email_domain
+            .map(|domain| domain.trim().trim_start_matches('@').to_lowercase())
+            .filter(|domain| !domain.is_empty());
+        if let Some(domain) = &email_domain
+            && (domain.len() > 253 || domain.contains(['@', ' ']))
+        {
+            return Err(i18n::t_with("invalid-email-domain", "domain", domain.as_str()));
+        }
+
+        let letter = match raw.letter.as_deref().map(str::trim).filter(|l| !l.is_empty()) {
+            None => None,
+            Some(letter) => match letter.chars().collect::<Vec<_>>()[..] {
+                [c] if c.is_ascii_alphabetic() => Some(c.to_ascii_uppercase()),
+                _ => return Err(i18n::t_with("invalid-letter", "letter", letter)),
+            },
+        };
+
+        let from_params = ContactFilter {
+            text: None,
+            created_after,
+            created_before,
+            email_domain: email_domain.clone(),
+            last_initial: letter,
+        };
+        let from_q = q.as_deref().map(query::parse).transpose()?.unwrap_or_default();
+        let filter = query::combine(from_q, &from_params)?;
+
+        let page = match raw.page.as_deref().map(str::trim).filter(|p| !p.is_empty()) {
+            None => 1,
+            Some(page) => match page.parse::<i64>() {
+                Ok(page) if page >= 1 => page.min(MAX_PAGE),
+                _ => return Err(i18n::t_with("invalid-page", "page", page)),
+            },
+        };
+
+        let per_page = match raw.per_page.as_deref().map(str::trim).filter(|p| !p.is_empty()) {
+            None => default_per_page,
+            Some(per_page) => per_page
+                .parse::<i64>()
+                .map_err(|_| i18n::t_with("invalid-page-size", "per_page", per_page))?,
+        }
+        .clamp(1, MAX_PER_PAGE);
+
+        let sort = raw.sort.as_deref().map(str::trim).filter(|s| !s.is_empty()).unwrap_or(default_sort.as_str());
+        let sort = ContactOrder::parse(sort, raw.dir.as_deref().unwrap_or_default()).map_err(|problem| match problem {
+            OrderProblem::UnknownColumn(column) => {
+                let allowed: Vec<&str> = ContactSort::ALL.iter().map(ContactSort::as_str).collect();
+                i18n::t_args("invalid-sort", &[("sort", column.into()), ("allowed", allowed.join(", ").into())])
+            }
+            OrderProblem::UnknownDirection(dir) => i18n::t_with("invalid-sort-direction", "dir", dir),
+            OrderProblem::TooManyColumns => i18n::t_with("too-many-sort-columns", "max", MAX_SORT_KEYS),
+            OrderProblem::RepeatedColumn(column) => i18n::t_with("repeated-sort-column", "sort", column.as_str()),
+            OrderProblem::TooManyDirections => i18n::t("too-many-sort-directions"),
+        })?;
+
+        // A cursor only makes sense in the order it was taken in
+        let after = match raw.after.as_deref().map(str::trim).filter(|a| !a.is_empty()) {
+            None => None,
+            Some(after) => match ContactCursor::decode(after) {
+                Some(cursor) if cursor.order == sort => Some(cursor),
+                _ => return Err(i18n::t("stale-page-link")),
+            },
+        };
+
+        Ok(Self {
+            q,
+            created_after,
+            created_before,
+            email_domain,
+            letter,
+            page,
+            per_page,
+            sort,
+            filter,
+            after,
+        })
+    }
+}
+
+impl<S> FromRequestParts<S> for ListParams
+where
+    S: Send + Sync,
+    Arc<Config>: FromRef<S>,
+{
+    type Rejection = ListParamsRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(raw) = Query::<RawListParams>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| ListParamsRejection(e.body_text()))?;
+        let config = Arc::<Config>::from_ref(state);
+        let preferences = Preferences::from_headers(&parts.headers);
+        let per_page = preferences.per_page.unwrap_or(config.page_size);
+        ListParams::parse(raw, per_page, preferences.sort.unwrap_or_default()).map_err(ListParamsRejection)
+    }
+}
+
+/// Bad list parameters: a 400 with the problem in an error fragment, or as JSON from the API
+/// (see `utils::json_errors`).
+#[derive(Debug)]
+pub struct ListParamsRejection(pub String);
+
+impl IntoResponse for ListParamsRejection {
+    fn into_response(self) -> Response {
+        let template = ErrorMessageTemplate {
+            error_message: self.0.clone(),
+        };
+        let response = match template.render() {
+            Ok(html) => (StatusCode::BAD_REQUEST, Html(html)).into_response(),
+            Err(_) => (StatusCode::BAD_REQUEST, "Bad Request").into_response(),
+        };
+        utils::with_message(response, self.0)
+    }
+}
@@ -0,0 +1,271 @@
+//! Printable contact sheets as PDF: each contact's name with its details underneath, as many
+//! to an A4 page as fit.
+//!
+//! The PDF is written by hand, it only needs text and lines. It uses the standard Helvetica
+//! fonts every PDF reader has, so nothing is embedded, but they only cover the Windows-1252
+//! characters. `č`, `ć` and `đ` lose their accents, anything else outside of those (Cyrillic,
+//! for one) is printed as `?`.
+
+use crate::{clock::DisplayTimezone, contacts::Contact, i18n, preferences};
+
+/// Most contacts on one sheet.
+pub const MAX_CONTACTS: usize = 100;
+
+/// A4, in points.
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 56.0;
+/// Where the values start, right of their labels.
+const VALUE_X: f32 = MARGIN + 110.0;
+const NAME_SIZE: f32 = 16.0;
+/// Extra space between a name and its details.
+const NAME_GAP: f32 = 4.0;
+const FIELD_SIZE: f32 = 11.0;
+const LINE_HEIGHT: f32 = 15.0;
+/// Space between two contacts, with a rule in the middle.
+const CONTACT_GAP: f32 = 24.0;
+/// Values are wrapped after this many characters. There are no font metrics to measure text
+/// with, but this many Helvetica characters of everyday text fit between `VALUE_X` and the
+/// right margin.
+const WRAP_CHARS: usize = 55;
+
+#[derive(Clone, Copy)]
+enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "/F1",
+            Font::Bold => "/F2",
+        }
+    }
+}
+
+/// The Windows-1252 byte for `c`, which the standard fonts' `WinAnsiEncoding` draws.
+fn win_ansi(c: char) -> Option<u8> {
+    match c {
+        ' '..='~' | '\u{A0}'..='\u{FF}' => Some(c as u8),
+        '€' => Some(0x80),
+        '„' => Some(0x84),
+        '…' => Some(0x85),
+        'Š' => Some(0x8A),
+        'Œ' => Some(0x8C),
+        'Ž' => Some(0x8E),
+        '‘' => Some(0x91),
+        '’' => Some(0x92),
+        '“' => Some(0x93),
+        '”' => Some(0x94),
+        '•' => Some(0x95),
+        '–' => Some(0x96),
+        '—' => Some(0x97),
+        'š' => Some(0x9A),
+        'œ' => Some(0x9C),
+        'ž' => Some(0x9E),
+        'Ÿ' => Some(0x9F),
+        _ => None,
+    }
+}
+
+/// What to print for a character Windows-1252 doesn't have: the plain letter for the
+/// Serbian Latin ones, `?` for the rest.
+fn fallback(c: char) -> u8 {
+    match c {
+        'Č' | 'Ć' => b'C',
+        'č' | 'ć' => b'c',
+        'Đ' => b'D',
+        'đ' => b'd',
+        _ => b'?',
+    }
+}
+
+/// `text` as a PDF string literal. Bytes outside of ASCII are written as octal escapes, so the
+/// content stream stays plain ASCII.
+fn string_literal(text: &str) -> String {
+    let mut literal = String::from("(");
+    for byte in text.chars().map(|c| win_ansi(c).unwrap_or_else(|| fallback(c))) {
+        match byte {
+            b'(' | b')' | b'\\' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            b' '..=b'~' => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\{byte:03o}")),
+        }
+    }
+    literal.push(')');
+    literal
+}
+
+/// `value` cut into lines of at most `WRAP_CHARS` characters, at spaces where possible.
+fn wrap(value: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in value.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > WRAP_CHARS {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+        // A single word too long for a line of its own, like a long email address
+        while line.chars().count() > WRAP_CHARS {
+            let split = line.char_indices().nth(WRAP_CHARS).map_or(line.len(), |(i, _)| i);
+            let rest = line.split_off(split);
+            lines.push(std::mem::replace(&mut line, rest));
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// The labeled details printed under a contact's name, in the current locale. Empty ones are
+/// left out.
+fn details(contact: &Contact, timezone: &DisplayTimezone) -> Vec<(String, Vec<String>)> {
+    let created = timezone.format(&contact.created_at, &i18n::t("datetime-format"));
+    [
+        ("field-company", contact.company.as_str()),
+        ("field-job-title", contact.job_title.as_str()),
+        ("field-phone", contact.phone_number.as_str()),
+        ("field-email", contact.email.as_str()),
+        ("field-created", created.as_str()),
+    ]
+    .into_iter()
+    .map(|(label, value)| (i18n::t(label), wrap(value)))
+    .filter(|(_, lines)| !lines.is_empty())
+    .collect()
+}
+
+/// Content streams of the pages, written top to bottom.
+struct Pages {
+    pages: Vec<String>,
+    /// Baseline of the last line written on the current page.
+    y: f32,
+}
+
+impl Pages {
+    fn new() -> Self {
+        Self {
+            pages: vec![String::new()],
+            y: PAGE_HEIGHT - MARGIN,
+        }
+    }
+
+    /// Whether nothing was written on the current page yet.
+    fn at_top(&self) -> bool {
+        self.y == PAGE_HEIGHT - MARGIN
+    }
+
+    /// Starts a new page unless `height` more points fit on this one. A block taller than a
+    /// whole page gets a page of its own and runs off the bottom.
+    fn make_room(&mut self, height: f32) {
+        if self.y - height < MARGIN && !self.at_top() {
+            self.pages.push(String::new());
+            self.y = PAGE_HEIGHT - MARGIN;
+        }
+    }
+
+    fn current(&mut self) -> &mut String {
+        self.pages.last_mut().expect("there is always a page")
+    }
+
+    fn text(&mut self, font: Font, size: f32, x: f32, text: &str) {
+        let (y, font, literal) = (self.y, font.resource(), string_literal(text));
+        self.current()
+            .push_str(&format!("BT {font} {size} Tf {x} {y} Td {literal} Tj ET\n"));
+    }
+
+    fn rule(&mut self) {
+        let (y, right) = (self.y, PAGE_WIDTH - MARGIN);
+        self.current()
+            .push_str(&format!("0.5 w 0.7 G {MARGIN} {y} m {right} {y} l S\n"));
+    }
+}
+
+/// A PDF with the contacts in the given order. Labels and dates are in the current locale,
+/// the dates in `timezone`, and names in the current preferred format.
+///
+/// Example usage:
+/// `let pdf = pdf::contact_sheet(&[contact], &state.config.get().timezone);`
+pub fn contact_sheet(contacts: &[Contact], timezone: &DisplayTimezone) -> Vec<u8> {
+    let mut pages = Pages::new();
+    for (i, contact) in contacts.iter().enumerate() {
+        let details = details(contact, timezone);
+        let lines = details.iter().map(|(_, lines)| lines.len()).sum::<usize>();
+        let height = NAME_SIZE + NAME_GAP + LINE_HEIGHT * lines as f32;
+        if i > 0 {
+            pages.make_room(CONTACT_GAP + height);
+            // Contacts at the top of a page don't need separating from the one before
+            if !pages.at_top() {
+                pages.y -= CONTACT_GAP / 2.0;
+                pages.rule();
+                pages.y -= CONTACT_GAP / 2.0;
+            }
+        }
+        pages.y -= NAME_SIZE;
+        pages.text(Font::Bold, NAME_SIZE, MARGIN, &preferences::display_name(contact));
+        pages.y -= NAME_GAP;
+        for (label, lines) in &details {
+            for (n, line) in lines.iter().enumerate() {
+                pages.y -= LINE_HEIGHT;
+                if n == 0 {
+                    pages.text(Font::Bold, FIELD_SIZE, MARGIN, label);
+                }
+                pages.text(Font::Regular, FIELD_SIZE, VALUE_X, line);
+            }
+        }
+    }
+    document(&pages.pages)
+}
+
+/// A whole PDF file around the pages' content streams: catalog, page tree, the two fonts, the
+/// pages, and the cross-reference table with each object's byte offset.
+fn document(pages: &[String]) -> Vec<u8> {
+    // Objects 1 to 4 are the catalog, page tree and fonts, then a page and its content for
+    // each page
+    let page_object = |i: usize| 5 + 2 * i;
+    let kids: Vec<String> = (0..pages.len()).map(|i| format!("{} 0 R", page_object(i))).collect();
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), pages.len()),
+        font("Helvetica"),
+        font("Helvetica-Bold"),
+    ];
+    for (i, content) in pages.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+             /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+            page_object(i) + 1
+        ));
+        objects.push(format!("<< /Length {} >>\nstream\n{content}endstream", content.len()));
+    }
+
+    let mut out = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend_from_slice(format!("{} 0 obj\n{object}\nendobj\n", i + 1).as_bytes());
+    }
+    let xref = out.len();
+    out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        out.extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+    }
+    out.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        )
+        .as_bytes(),
+    );
+    out
+}
+
+fn font(name: &str) -> String {
+    format!("<< /Type /Font /Subtype /Type1 /BaseFont /{name} /Encoding /WinAnsiEncoding >>")
+}
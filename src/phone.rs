@@ -0,0 +1,70 @@
+//! Phone numbers as people read and search for them. They are stored the way they were
+//! entered; pages show them tidied up with `format` (the `phone` template filter), and search
+//! compares just the digits, so `(206) 444-9876` finds a contact saved as `206 444 9876` and
+//! the other way around.
+//!
+//! Only North American numbers get a fixed layout, they are the only ones whose grouping can
+//! be told from the digits alone. Other numbers keep the grouping they were entered with.
+
+/// Characters allowed in a phone number besides digits.
+pub const PUNCTUATION: &[char] = &['+', '-', '(', ')', '.', ' '];
+
+/// Fewest digits in a search term for it to be matched against phone numbers, so a house
+/// number in a search doesn't match every phone with those two digits.
+const MIN_SEARCH_DIGITS: usize = 3;
+
+/// Just the digits, so `+1 (206) 444-9876` and `1 206 444 9876` are the same number.
+pub fn normalize(phone_number: &str) -> String {
+    phone_number.chars().filter(char::is_ascii_digit).collect()
+}
+
+/// The number written out for reading. North American numbers are `(206) 444-9876`, or
+/// `+1 206-444-9876` if they were entered with the country code. Anything else keeps its digit
+/// groups, separated by single spaces: `+381 (64) 123-4567` is `+381 64 123 4567`.
+pub fn format(phone_number: &str) -> String {
+    let phone_number = phone_number.trim();
+    let digits = normalize(phone_number);
+    let international = phone_number.starts_with('+');
+    match digits.len() {
+        10 if !international && north_american(&digits) => {
+            format!("({}) {}-{}", &digits[..3], &digits[3..6], &digits[6..])
+        }
+        11 if digits.starts_with('1') && north_american(&digits[1..]) => {
+            format!("+1 {}-{}-{}", &digits[1..4], &digits[4..7], &digits[7..])
+        }
+        _ => tidy(phone_number),
+    }
+}
+
+/// `LIKE` pattern for a search term that could be (part of) a phone number: its digits, for
+/// matching against the digits of the stored numbers. A North American country code is left
+/// out, so `+1 206 444 9876` finds `206 444 9876` too. `None` for a term with anything besides
+/// digits and phone punctuation in it, or too few digits.
+pub fn search_pattern(text: &str) -> Option<String> {
+    let text = text.trim();
+    if text.chars().any(|c| !c.is_ascii_digit() && !PUNCTUATION.contains(&c)) {
+        return None;
+    }
+    let digits = normalize(text);
+    let digits = match digits.strip_prefix('1') {
+        Some(national) if north_american(national) => national,
+        _ => &digits,
+    };
+    (digits.len() >= MIN_SEARCH_DIGITS).then(|| format!("%{digits}%"))
+}
+
+/// A ten digit North American number: area code and exchange don't start with 0 or 1. This is
+/// what tells `206 444 9876` apart from a number like `064 123 4567`.
+fn north_american(digits: &str) -> bool {
+    let bytes = digits.as_bytes();
+    bytes.len() == 10 && (b'2'..=b'9').contains(&bytes[0]) && (b'2'..=b'9').contains(&bytes[3])
+}
+
+/// The number's groups separated by single spaces, the `+` kept in front.
+fn tidy(phone_number: &str) -> String {
+    let groups: Vec<&str> = phone_number
+        .split(|c: char| c != '+' && PUNCTUATION.contains(&c))
+        .filter(|group| !group.is_empty())
+        .collect();
+    groups.join(" ")
+}
@@ -0,0 +1,861 @@
+//! Postgres versions of the queries, used when the app runs against a `postgres://` URL.
+//!
+//! The SQLite queries are checked at compile time with the `sqlx` macros against the
+//! `DATABASE_URL` SQLite database. These can't be, since that would need a live Postgres
+//! server at build time, so they are plain runtime queries mirroring the SQLite ones. The
+//! differences are the `$N` placeholders and `ILIKE` for case-insensitive search (SQLite's
+//! `LIKE` is case-insensitive already). The contact timestamps are `TIMESTAMPTZ` columns here,
+//! so they are formatted like SQLite's text wherever they are compared as a sort key.
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::{PgExecutor, PgPool};
+
+use crate::{
+    api_keys::{ApiKey, KeyLimits},
+    attachments::{Attachment, Upload},
+    contacts::{Contact, ContactCursor, ContactFilter, ContactOrder, ContactSort, CountedContact, NewContact},
+    dates::ContactDate,
+    repository::ContactStream,
+    events::Event,
+    import::SavedMapping,
+    jobs::Job,
+    relationships::Relationship,
+    shares::ShareLink,
+    stats::DomainCount,
+    trash::TrashedContact,
+    webhooks::{Webhook, WebhookDelivery},
+};
+
+pub async fn create_contact<'e>(
+    executor: impl PgExecutor<'e>,
+    new: &NewContact,
+    now: DateTime<Utc>,
+) -> Result<Contact, sqlx::Error> {
+    sqlx::query_as(
+        r#"INSERT INTO contacts (first_name, last_name, phone_number, email, company, job_title, search_text,
+            created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING *"#,
+    )
+    .bind(&new.first_name)
+    .bind(&new.last_name)
+    .bind(&new.phone_number)
+    .bind(&new.email)
+    .bind(&new.company)
+    .bind(&new.job_title)
+    .bind(new.search_text())
+    .bind(now)
+    .bind(now)
+    .fetch_one(executor)
+    .await
+}
+
+/// Returns how many contacts were updated, 0 if there is no such contact or, when `version`
+/// is set, its `updated_at` isn't that to the millisecond any more.
+pub async fn update_contact<'e>(
+    executor: impl PgExecutor<'e>,
+    contact: &Contact,
+    updated_at: DateTime<Utc>,
+    version: Option<DateTime<Utc>>,
+) -> Result<u64, sqlx::Error> {
+    sqlx::query(
+        r#"UPDATE contacts
+        SET first_name = $1, last_name = $2, phone_number = $3, email = $4, company = $5, job_title = $6,
+            search_text = $7, updated_at = $8
+        WHERE id = $9
+          AND ($10::timestamptz IS NULL OR date_trunc('milliseconds', updated_at) = date_trunc('milliseconds', $10::timestamptz))"#,
+    )
+    .bind(&contact.first_name)
+    .bind(&contact.last_name)
+    .bind(&contact.phone_number)
+    .bind(&contact.email)
+    .bind(&contact.company)
+    .bind(&contact.job_title)
+    .bind(contact.search_text())
+    .bind(updated_at)
+    .bind(contact.id)
+    .bind(version)
+    .execute(executor)
+    .await
+    .map(|result| result.rows_affected())
+}
+
+pub async fn fill_search_text(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let contacts: Vec<Contact> = sqlx::query_as("SELECT * FROM contacts WHERE search_text IS NULL")
+        .fetch_all(&mut *tx)
+        .await?;
+    for contact in &contacts {
+        sqlx::query("UPDATE contacts SET search_text = $1 WHERE id = $2")
+            .bind(contact.search_text())
+            .bind(contact.id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(contacts.len())
+}
+
+/// Returns how many contacts were deleted, 0 if there is no such contact or, like
+/// `update_contact`, it isn't the `version` any more.
+pub async fn delete_contact<'e>(
+    executor: impl PgExecutor<'e>,
+    id: i64,
+    version: Option<DateTime<Utc>>,
+) -> Result<u64, sqlx::Error> {
+    sqlx::query(
+        "DELETE FROM contacts WHERE id = $1
+          AND ($2::timestamptz IS NULL OR date_trunc('milliseconds', updated_at) = date_trunc('milliseconds', $2::timestamptz))",
+    )
+    .bind(id)
+    .bind(version)
+    .execute(executor)
+    .await
+    .map(|result| result.rows_affected())
+}
+
+pub async fn restore_contact(pool: &PgPool, contact: &Contact) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"INSERT INTO contacts (id, first_name, last_name, phone_number, email, company, job_title, search_text,
+            created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)"#,
+    )
+    .bind(contact.id)
+    .bind(&contact.first_name)
+    .bind(&contact.last_name)
+    .bind(&contact.phone_number)
+    .bind(&contact.email)
+    .bind(&contact.company)
+    .bind(&contact.job_title)
+    .bind(contact.search_text())
+    .bind(contact.created_at)
+    .bind(contact.updated_at)
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+/// How the list queries write a sort column. `created_at` is cut to the second, the way the
+/// cursor and SQLite have it, with an index on that expression.
+fn sort_column(column: ContactSort) -> &'static str {
+    match column {
+        ContactSort::CreatedAt => "date_trunc('second', created_at AT TIME ZONE 'UTC')",
+        column => column.as_str(),
+    }
+}
+
+pub async fn all_contacts(pool: &PgPool) -> Result<Vec<Contact>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM contacts ORDER BY id")
+        .fetch_all(pool)
+        .await
+}
+
+pub fn stream_all_contacts(pool: &PgPool) -> ContactStream<'_> {
+    sqlx::query_as("SELECT * FROM contacts ORDER BY id").fetch(pool)
+}
+
+pub async fn find_contact(pool: &PgPool, id: i64) -> Result<Contact, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM contacts WHERE id = $1")
+        .bind(id)
+        .fetch_one(pool)
+        .await
+}
+
+pub async fn contacts_with_company(pool: &PgPool) -> Result<Vec<Contact>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM contacts WHERE company <> '' ORDER BY company, last_name, first_name, id")
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn find_contact_by_email(pool: &PgPool, email: &str) -> Result<Option<Contact>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM contacts WHERE lower(email) = lower($1) ORDER BY id LIMIT 1")
+        .bind(email)
+        .fetch_optional(pool)
+        .await
+}
+
+/// `ContactFilter` conditions, with the text pattern, the two created bounds, the email
+/// domain, the last name initial pattern and the phone digits pattern in `$n` to `$n+5`. A
+/// `NULL` parameter turns its condition off.
+fn filter_conditions(n: usize) -> String {
+    let (text, after, before, domain, initial, phone) = (n, n + 1, n + 2, n + 3, n + 4, n + 5);
+    format!(
+        "(${text}::text IS NULL OR search_text LIKE ${text}
+            OR regexp_replace(phone_number, '[^0-9]', '', 'g') LIKE ${phone}::text)
+        AND (${after}::text IS NULL OR created_at >= ${after}::timestamp AT TIME ZONE 'UTC')
+        AND (${before}::text IS NULL OR created_at < ${before}::timestamp AT TIME ZONE 'UTC')
+        AND (${domain}::text IS NULL OR LOWER(SUBSTRING(email FROM POSITION('@' IN email) + 1)) = ${domain})
+        AND (${initial}::text IS NULL OR last_name ILIKE ${initial})"
+    )
+}
+
+/// See `Contact::search_rows`. The filter is in `$1` to `$6`, the limit and offset in `$7` and
+/// `$8`, and the cursor's values from `$9` on.
+pub async fn search_contacts(
+    pool: &PgPool,
+    filter: &ContactFilter,
+    sort: ContactOrder,
+    limit: i64,
+    offset: i64,
+    after: Option<&ContactCursor>,
+    with_total: bool,
+) -> Result<(Vec<Contact>, Option<i64>), sqlx::Error> {
+    let (created_after, created_before) = filter.created_bounds();
+    let after_condition = match after {
+        Some(_) => sort.after_condition(sort_column, |column, i| match column {
+            ContactSort::Id => format!("${}::text::bigint", i + 9),
+            ContactSort::CreatedAt => format!("${}::text::timestamp", i + 9),
+            _ => format!("${}::text", i + 9),
+        }),
+        None => "TRUE".to_string(),
+    };
+    let sql = format!(
+        "SELECT *, {} AS total FROM contacts WHERE {} AND {after_condition} ORDER BY {} LIMIT $7 OFFSET $8",
+        if with_total { "COUNT(*) OVER ()" } else { "NULL::bigint" },
+        filter_conditions(1),
+        sort.order_by(sort_column),
+    );
+    let mut query = sqlx::query_as::<_, CountedContact>(&sql)
+        .bind(filter.pattern())
+        .bind(created_after)
+        .bind(created_before)
+        .bind(&filter.email_domain)
+        .bind(filter.initial_pattern())
+        .bind(filter.phone_pattern())
+        .bind(limit)
+        .bind(offset);
+    for value in after.map(ContactCursor::values).unwrap_or_default() {
+        query = query.bind(value);
+    }
+    query.fetch_all(pool).await.map(CountedContact::split)
+}
+
+pub async fn count_contacts(pool: &PgPool, filter: &ContactFilter) -> Result<i64, sqlx::Error> {
+    let (created_after, created_before) = filter.created_bounds();
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM contacts WHERE {}", filter_conditions(1)))
+        .bind(filter.pattern())
+        .bind(created_after)
+        .bind(created_before)
+        .bind(&filter.email_domain)
+        .bind(filter.initial_pattern())
+        .bind(filter.phone_pattern())
+        .fetch_one(pool)
+        .await
+}
+
+/// `(initial, count)` of the contacts matching `filter`, grouped by last name initial.
+pub async fn letter_counts(pool: &PgPool, filter: &ContactFilter) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    let (created_after, created_before) = filter.created_bounds();
+    sqlx::query_as(&format!(
+        "SELECT UPPER(LEFT(last_name, 1)), COUNT(*) FROM contacts WHERE {} GROUP BY 1",
+        filter_conditions(1)
+    ))
+    .bind(filter.pattern())
+    .bind(created_after)
+    .bind(created_before)
+    .bind(&filter.email_domain)
+    .bind(filter.initial_pattern())
+    .bind(filter.phone_pattern())
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn email_exists(pool: &PgPool, email: &str, exclude_id: Option<i64>) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM contacts WHERE lower(email) = lower($1) AND ($2::BIGINT IS NULL OR id <> $2))")
+        .bind(email)
+        .bind(exclude_id)
+        .fetch_one(pool)
+        .await
+}
+
+pub async fn phone_exists(pool: &PgPool, phone_number: &str, exclude_id: Option<i64>) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"SELECT EXISTS(SELECT 1 FROM contacts
+        WHERE phone_number <> ''
+          AND regexp_replace(phone_number, '[^0-9]', '', 'g') = regexp_replace($1, '[^0-9]', '', 'g')
+          AND ($2::BIGINT IS NULL OR id <> $2))"#,
+    )
+        .bind(phone_number)
+        .bind(exclude_id)
+        .fetch_one(pool)
+        .await
+}
+
+/// Contacts created per `YYYY-MM` month, from the day `since` on.
+pub async fn contacts_per_month(pool: &PgPool, since: &str) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    sqlx::query_as(
+        r#"SELECT to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM') AS month, COUNT(*)
+        FROM contacts
+        WHERE created_at >= $1::timestamp AT TIME ZONE 'UTC'
+        GROUP BY 1"#,
+    )
+    .bind(since)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn top_email_domains(pool: &PgPool, limit: i64) -> Result<Vec<DomainCount>, sqlx::Error> {
+    sqlx::query_as(
+        r#"SELECT LOWER(SUBSTRING(email FROM POSITION('@' IN email) + 1)) AS domain, COUNT(*) AS count
+        FROM contacts
+        WHERE POSITION('@' IN email) > 0
+        GROUP BY 1
+        ORDER BY 2 DESC, 1
+        LIMIT $1"#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn recently_added_contacts(pool: &PgPool, limit: i64) -> Result<Vec<Contact>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM contacts ORDER BY created_at DESC, id DESC LIMIT $1")
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn recently_edited_contacts(pool: &PgPool, limit: i64) -> Result<Vec<Contact>, sqlx::Error> {
+    sqlx::query_as(
+        r#"SELECT * FROM contacts
+        WHERE updated_at >= created_at + INTERVAL '1 second'
+        ORDER BY updated_at DESC, id DESC
+        LIMIT $1"#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn contact_relationships(pool: &PgPool, id: i64) -> Result<Vec<Relationship>, sqlx::Error> {
+    sqlx::query_as(
+        r#"SELECT r.id, r.label, c.id AS other_id,
+            c.first_name AS other_first_name, c.last_name AS other_last_name,
+            r.contact_id <> $1 AS incoming
+        FROM contact_relationships r
+        JOIN contacts c ON c.id = CASE WHEN r.contact_id = $1 THEN r.related_id ELSE r.contact_id END
+        WHERE r.contact_id = $1 OR r.related_id = $1
+        ORDER BY r.label, c.last_name, c.first_name, r.id"#,
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn add_relationship(pool: &PgPool, contact_id: i64, related_id: i64, label: &str) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        "INSERT INTO contact_relationships (contact_id, related_id, label) VALUES ($1, $2, $3) RETURNING id",
+    )
+    .bind(contact_id)
+    .bind(related_id)
+    .bind(label)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn remove_relationship(pool: &PgPool, contact_id: i64, id: i64) -> Result<u64, sqlx::Error> {
+    sqlx::query("DELETE FROM contact_relationships WHERE id = $1 AND (contact_id = $2 OR related_id = $2)")
+        .bind(id)
+        .bind(contact_id)
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected())
+}
+
+pub async fn contact_dates(pool: &PgPool, contact_id: Option<i64>) -> Result<Vec<ContactDate>, sqlx::Error> {
+    sqlx::query_as(
+        r#"SELECT d.id, d.contact_id, c.first_name, c.last_name, d.label, d.date, d.yearly
+        FROM contact_dates d
+        JOIN contacts c ON c.id = d.contact_id
+        WHERE $1::BIGINT IS NULL OR d.contact_id = $1
+        ORDER BY d.date, d.label, d.id"#,
+    )
+    .bind(contact_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn add_contact_date(
+    pool: &PgPool,
+    contact_id: i64,
+    label: &str,
+    date: NaiveDate,
+    yearly: bool,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("INSERT INTO contact_dates (contact_id, label, date, yearly) VALUES ($1, $2, $3, $4) RETURNING id")
+        .bind(contact_id)
+        .bind(label)
+        .bind(date)
+        .bind(yearly)
+        .fetch_one(pool)
+        .await
+}
+
+pub async fn remove_contact_date(pool: &PgPool, contact_id: i64, id: i64) -> Result<u64, sqlx::Error> {
+    sqlx::query("DELETE FROM contact_dates WHERE id = $1 AND contact_id = $2")
+        .bind(id)
+        .bind(contact_id)
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected())
+}
+
+pub async fn record_event(
+    pool: &PgPool,
+    kind: &str,
+    actor: &str,
+    contact_id: Option<i64>,
+    payload: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO events (kind, actor, contact_id, payload) VALUES ($1, $2, $3, $4)")
+        .bind(kind)
+        .bind(actor)
+        .bind(contact_id)
+        .bind(payload)
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+pub async fn list_events(
+    pool: &PgPool,
+    kind: Option<&str>,
+    actor: Option<&str>,
+    since: Option<&str>,
+    until: Option<&str>,
+    limit: i64,
+) -> Result<Vec<Event>, sqlx::Error> {
+    sqlx::query_as(
+        r#"SELECT * FROM events
+        WHERE ($1::text IS NULL OR kind = $1)
+          AND ($2::text IS NULL OR actor = $2)
+          AND ($3::text IS NULL OR created_at >= $3::date::text)
+          AND ($4::text IS NULL OR created_at < ($4::date + 1)::text)
+        ORDER BY id DESC
+        LIMIT $5"#,
+    )
+    .bind(kind)
+    .bind(actor)
+    .bind(since)
+    .bind(until)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn contact_events_after(pool: &PgPool, after: i64, limit: i64) -> Result<Vec<Event>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM events WHERE id > $1 AND kind LIKE 'contact.%' ORDER BY id LIMIT $2")
+        .bind(after)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn last_event_id(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM events").fetch_one(pool).await
+}
+
+pub async fn list_activity(
+    pool: &PgPool,
+    contact_id: Option<i64>,
+    actor: Option<&str>,
+    before: Option<i64>,
+    limit: i64,
+) -> Result<Vec<Event>, sqlx::Error> {
+    sqlx::query_as(
+        r#"SELECT * FROM events
+        WHERE kind LIKE 'contact.%'
+          AND ($1::bigint IS NULL OR contact_id = $1)
+          AND ($2::text IS NULL OR actor = $2)
+          AND ($3::bigint IS NULL OR id < $3)
+        ORDER BY id DESC
+        LIMIT $4"#,
+    )
+    .bind(contact_id)
+    .bind(actor)
+    .bind(before)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn purge_events(pool: &PgPool, before: &str) -> Result<u64, sqlx::Error> {
+    sqlx::query("DELETE FROM events WHERE created_at < $1")
+        .bind(before)
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected())
+}
+
+pub async fn enqueue_job(
+    pool: &PgPool,
+    kind: &str,
+    payload: &str,
+    max_attempts: i64,
+    now: &str,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"INSERT INTO jobs (kind, payload, max_attempts, run_at, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $4, $4)
+        RETURNING id"#,
+    )
+    .bind(kind)
+    .bind(payload)
+    .bind(max_attempts)
+    .bind(now)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn list_jobs(pool: &PgPool, limit: i64) -> Result<Vec<Job>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM jobs ORDER BY id DESC LIMIT $1")
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn latest_job(pool: &PgPool, kind: &str) -> Result<Option<Job>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM jobs WHERE kind = $1 ORDER BY id DESC LIMIT 1")
+        .bind(kind)
+        .fetch_optional(pool)
+        .await
+}
+
+/// `SKIP LOCKED` lets concurrent workers each claim a different job instead of waiting on
+/// each other.
+pub async fn claim_job(pool: &PgPool, now: &str) -> Result<Option<Job>, sqlx::Error> {
+    sqlx::query_as(
+        r#"UPDATE jobs SET status = 'running', attempts = attempts + 1, updated_at = $1
+        WHERE id = (
+          SELECT id FROM jobs WHERE status = 'queued' AND run_at <= $1 ORDER BY run_at, id LIMIT 1
+          FOR UPDATE SKIP LOCKED
+        )
+        RETURNING *"#,
+    )
+    .bind(now)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn finish_job(
+    pool: &PgPool,
+    id: i64,
+    status: &str,
+    outcome: &str,
+    run_at: &str,
+    now: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE jobs SET status = $1, outcome = $2, run_at = $3, updated_at = $4 WHERE id = $5")
+        .bind(status)
+        .bind(outcome)
+        .bind(run_at)
+        .bind(now)
+        .bind(id)
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+pub async fn requeue_running_jobs(pool: &PgPool, now: &str) -> Result<u64, sqlx::Error> {
+    sqlx::query("UPDATE jobs SET status = 'queued', run_at = $1, updated_at = $1 WHERE status = 'running'")
+        .bind(now)
+        .execute(pool)
+        .await
+        .map(|r| r.rows_affected())
+}
+
+pub async fn create_webhook(pool: &PgPool, url: &str, secret: &str) -> Result<Webhook, sqlx::Error> {
+    sqlx::query_as("INSERT INTO webhooks (url, secret) VALUES ($1, $2) RETURNING *")
+        .bind(url)
+        .bind(secret)
+        .fetch_one(pool)
+        .await
+}
+
+pub async fn list_webhooks(pool: &PgPool) -> Result<Vec<Webhook>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM webhooks ORDER BY id")
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn find_webhook(pool: &PgPool, id: i64) -> Result<Option<Webhook>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM webhooks WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn delete_webhook(pool: &PgPool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM webhooks WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+pub async fn record_webhook_delivery(
+    pool: &PgPool,
+    webhook: &Webhook,
+    event: &str,
+    status_code: Option<i64>,
+    outcome: &str,
+    duration_ms: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"INSERT INTO webhook_deliveries (webhook_id, url, event, status_code, outcome, duration_ms)
+        VALUES ($1, $2, $3, $4, $5, $6)"#,
+    )
+    .bind(webhook.id)
+    .bind(&webhook.url)
+    .bind(event)
+    .bind(status_code)
+    .bind(outcome)
+    .bind(duration_ms)
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+pub async fn list_webhook_deliveries(pool: &PgPool, limit: i64) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM webhook_deliveries ORDER BY id DESC LIMIT $1")
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn list_attachments(pool: &PgPool, contact_id: i64) -> Result<Vec<Attachment>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM attachments WHERE contact_id = $1 ORDER BY id")
+        .bind(contact_id)
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn find_attachment(pool: &PgPool, contact_id: i64, id: i64) -> Result<Option<Attachment>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM attachments WHERE id = $1 AND contact_id = $2")
+        .bind(id)
+        .bind(contact_id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn create_attachment(
+    pool: &PgPool,
+    contact_id: i64,
+    upload: &Upload,
+    size: i64,
+    stored_name: &str,
+) -> Result<Attachment, sqlx::Error> {
+    sqlx::query_as(
+        r#"INSERT INTO attachments (contact_id, filename, content_type, size, stored_name)
+        VALUES ($1, $2, $3, $4, $5) RETURNING *"#,
+    )
+    .bind(contact_id)
+    .bind(&upload.filename)
+    .bind(&upload.content_type)
+    .bind(size)
+    .bind(stored_name)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn delete_attachment(pool: &PgPool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM attachments WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+pub async fn move_attachments(pool: &PgPool, from_contact_id: i64, to_contact_id: i64) -> Result<u64, sqlx::Error> {
+    sqlx::query("UPDATE attachments SET contact_id = $1 WHERE contact_id = $2")
+        .bind(to_contact_id)
+        .bind(from_contact_id)
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected())
+}
+
+pub async fn list_import_mappings(pool: &PgPool) -> Result<Vec<SavedMapping>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM import_mappings ORDER BY name")
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn save_import_mapping(pool: &PgPool, name: &str, columns: &str) -> Result<SavedMapping, sqlx::Error> {
+    sqlx::query_as(
+        r#"INSERT INTO import_mappings (name, columns) VALUES ($1, $2)
+        ON CONFLICT (name) DO UPDATE SET columns = excluded.columns
+        RETURNING *"#,
+    )
+    .bind(name)
+    .bind(columns)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn trash_contact(pool: &PgPool, contact: &Contact, created_at: &str, dates: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"INSERT INTO trashed_contacts (id, first_name, last_name, phone_number, email, company, job_title, created_at, dates)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT (id) DO UPDATE SET first_name = excluded.first_name, last_name = excluded.last_name,
+            phone_number = excluded.phone_number, email = excluded.email, company = excluded.company,
+            job_title = excluded.job_title, created_at = excluded.created_at, dates = excluded.dates,
+            deleted_at = DEFAULT"#,
+    )
+    .bind(contact.id)
+    .bind(&contact.first_name)
+    .bind(&contact.last_name)
+    .bind(&contact.phone_number)
+    .bind(&contact.email)
+    .bind(&contact.company)
+    .bind(&contact.job_title)
+    .bind(created_at)
+    .bind(dates)
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+pub async fn list_trashed_contacts(pool: &PgPool) -> Result<Vec<TrashedContact>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM trashed_contacts ORDER BY deleted_at DESC, id DESC")
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn find_trashed_contact(pool: &PgPool, id: i64) -> Result<Option<TrashedContact>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM trashed_contacts WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn remove_trashed_contact(pool: &PgPool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM trashed_contacts WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await
+        .map(|_| ())
+}
+
+pub async fn remove_trashed_contacts(pool: &PgPool, before: Option<&str>) -> Result<Vec<TrashedContact>, sqlx::Error> {
+    sqlx::query_as("DELETE FROM trashed_contacts WHERE $1::text IS NULL OR deleted_at < $1 RETURNING *")
+        .bind(before)
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn create_share_link(
+    pool: &PgPool,
+    token: &str,
+    contact_id: Option<i64>,
+    company: Option<&str>,
+    created_at: &str,
+    expires_at: Option<&str>,
+) -> Result<ShareLink, sqlx::Error> {
+    sqlx::query_as(
+        r#"INSERT INTO share_links (token, contact_id, company, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5) RETURNING *"#,
+    )
+    .bind(token)
+    .bind(contact_id)
+    .bind(company)
+    .bind(created_at)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn list_share_links(pool: &PgPool) -> Result<Vec<ShareLink>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM share_links ORDER BY id DESC")
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn find_share_link(pool: &PgPool, token: &str) -> Result<Option<ShareLink>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM share_links WHERE token = $1")
+        .bind(token)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn revoke_share_link(pool: &PgPool, id: i64, revoked_at: &str) -> Result<Option<ShareLink>, sqlx::Error> {
+    sqlx::query_as("UPDATE share_links SET revoked_at = $1 WHERE id = $2 AND revoked_at IS NULL RETURNING *")
+        .bind(revoked_at)
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn create_api_key(
+    pool: &PgPool,
+    name: &str,
+    prefix: &str,
+    key_hash: &str,
+    created_at: &str,
+    limits: KeyLimits,
+) -> Result<ApiKey, sqlx::Error> {
+    sqlx::query_as(
+        r#"INSERT INTO api_keys (name, prefix, key_hash, created_at, requests_per_minute, requests_per_day)
+        VALUES ($1, $2, $3, $4, $5, $6) RETURNING *"#,
+    )
+    .bind(name)
+    .bind(prefix)
+    .bind(key_hash)
+    .bind(created_at)
+    .bind(limits.requests_per_minute)
+    .bind(limits.requests_per_day)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn list_api_keys(pool: &PgPool) -> Result<Vec<ApiKey>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM api_keys ORDER BY id DESC")
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn revoke_api_key(pool: &PgPool, id: i64, revoked_at: &str) -> Result<Option<ApiKey>, sqlx::Error> {
+    sqlx::query_as("UPDATE api_keys SET revoked_at = $1 WHERE id = $2 AND revoked_at IS NULL RETURNING *")
+        .bind(revoked_at)
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn set_api_key_limits(pool: &PgPool, id: i64, limits: KeyLimits) -> Result<Option<ApiKey>, sqlx::Error> {
+    sqlx::query_as(
+        r#"UPDATE api_keys SET requests_per_minute = $1, requests_per_day = $2
+        WHERE id = $3 AND revoked_at IS NULL RETURNING *"#,
+    )
+    .bind(limits.requests_per_minute)
+    .bind(limits.requests_per_day)
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn authenticate_api_key(pool: &PgPool, key_hash: &str) -> Result<Option<ApiKey>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM api_keys WHERE key_hash = $1 AND revoked_at IS NULL")
+        .bind(key_hash)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn record_api_key_use(
+    pool: &PgPool,
+    id: i64,
+    used_at: &str,
+    day: &str,
+    requests: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"UPDATE api_keys SET last_used_at = $1,
+            quota_used = CASE WHEN quota_day = $2 THEN quota_used + $3 ELSE $3 END, quota_day = $2
+        WHERE id = $4"#,
+    )
+    .bind(used_at)
+    .bind(day)
+    .bind(requests)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,120 @@
+//! Display preferences, remembered per browser in the `prefs` cookie and changed on the settings
+//! page: how many contacts a list page shows, the list's default sort, and whether names read
+//! "Jane Doe" or "Doe, Jane".
+//!
+//! Explicit `per_page` and `sort` parameters still win, so list links look the same for
+//! everyone. Like the locale, the preferences are made current for the request by the `scope`
+//! middleware, so templates can format names without having them passed in:
+//! `{{ crate::preferences::display_name(contact) }}`.
+
+use std::convert::Infallible;
+
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::{HeaderMap, header::COOKIE, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    base_path,
+    contacts::{Contact, ContactSort},
+    names::NameFormat,
+};
+
+/// Cookie holding the preferences, as `per_page=25&sort=last_name&name_format=last_first`.
+pub const PREFERENCES_COOKIE: &str = "prefs";
+
+/// One browser's display preferences. `None` means the configured or built-in default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Preferences {
+    pub per_page: Option<i64>,
+    pub sort: Option<ContactSort>,
+    pub name_format: NameFormat,
+}
+
+/// The cookie's contents. Strings, so a value from an older version that no longer parses is
+/// dropped instead of throwing the whole cookie away.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Stored {
+    per_page: Option<String>,
+    sort: Option<String>,
+    name_format: Option<String>,
+}
+
+tokio::task_local! {
+    static CURRENT: Preferences;
+}
+
+impl Preferences {
+    /// The preferences in the request's `prefs` cookie, defaults if there is none.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, _)| *name == PREFERENCES_COOKIE)
+            .and_then(|(_, value)| serde_urlencoded::from_str::<Stored>(value).ok())
+            .map(|stored| Self {
+                per_page: stored.per_page.and_then(|n| n.parse().ok()).filter(|n| *n >= 1),
+                sort: stored.sort.as_deref().and_then(ContactSort::parse),
+                name_format: stored.name_format.as_deref().and_then(NameFormat::parse).unwrap_or_default(),
+            })
+            .unwrap_or_default()
+    }
+
+    /// `Set-Cookie` value remembering these preferences for a year.
+    pub fn cookie(&self) -> String {
+        let stored = Stored {
+            per_page: self.per_page.map(|n| n.to_string()),
+            sort: self.sort.map(|sort| sort.as_str().to_string()),
+            name_format: Some(self.name_format.as_str().to_string()),
+        };
+        // Serializing optional strings can't fail
+        let value = serde_urlencoded::to_string(stored).unwrap_or_default();
+        format!(
+            "{PREFERENCES_COOKIE}={value}; Path={}; Max-Age=31536000; SameSite=Lax",
+            base_path::cookie_path()
+        )
+    }
+
+    /// The contact's name in the preferred format, see `NameFormat::format`.
+    pub fn name(&self, first_name: &str, last_name: &str) -> String {
+        self.name_format.format(first_name, last_name)
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Preferences {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_headers(&parts.headers))
+    }
+}
+
+/// The preferences of the request being handled, defaults outside of one.
+pub fn current() -> Preferences {
+    CURRENT.try_with(|preferences| *preferences).unwrap_or_default()
+}
+
+/// Runs `f` with `preferences` as the current ones, for rendering outside of a request.
+pub fn with<R>(preferences: Preferences, f: impl FnOnce() -> R) -> R {
+    CURRENT.sync_scope(preferences, f)
+}
+
+/// `contact`'s name in the current request's preferred format, its email if it has no name.
+pub fn display_name(contact: &Contact) -> String {
+    current().name_format.contact(contact)
+}
+
+/// Middleware: handles the request with the preferences from its cookie as the current ones.
+///
+/// Example usage:
+/// `router.layer(middleware::from_fn(preferences::scope))`
+pub async fn scope(request: Request, next: Next) -> Response {
+    let preferences = Preferences::from_headers(request.headers());
+    CURRENT.scope(preferences, next.run(request)).await
+}
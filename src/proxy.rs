@@ -0,0 +1,71 @@
+//! Reverse proxy headers. Behind a proxy every request comes from the proxy's address, and
+//! over plain HTTP when it terminates TLS. With `server.trust_proxy_headers` on,
+//! `X-Forwarded-For` and `X-Forwarded-Proto` say what the client actually used: the client IP
+//! replaces the connection's address for rate limiting, the event log's actor and the access
+//! log, and absolute links (the Atom feed's) use the forwarded scheme.
+//!
+//! Off, which is the default, the headers are dropped before anything reads them, so clients
+//! can't claim another address. Only turn it on when the app can't be reached except through
+//! the proxy. A request that came with any forwarding header is marked `UntrustedProxy` then,
+//! as its address is most likely the proxy's, so `admin::guard` can turn it away.
+
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+/// The headers proxies add that give a request away as proxied, trusted or not.
+const FORWARDING_HEADERS: [&str; 5] = [X_FORWARDED_FOR, X_FORWARDED_PROTO, "x-forwarded-host", "x-real-ip", "forwarded"];
+
+/// Whether the forwarded headers are believed, `server.trust_proxy_headers`.
+#[derive(Debug, Clone, Copy)]
+pub struct TrustProxyHeaders(pub bool);
+
+/// Request extension: the request came with forwarding headers that aren't trusted, so its
+/// address can't be told from the proxy's.
+#[derive(Debug, Clone, Copy)]
+pub struct UntrustedProxy;
+
+/// Middleware: with trusted headers, swaps the connection's address for the forwarded client
+/// IP; without, marks the request `UntrustedProxy` if it has any and removes them. Goes
+/// outside every other layer, the access log included.
+///
+/// Example usage:
+/// `router.layer(middleware::from_fn_with_state(TrustProxyHeaders(true), proxy::resolve))`
+pub async fn resolve(State(TrustProxyHeaders(trusted)): State<TrustProxyHeaders>, mut request: Request, next: Next) -> Response {
+    if trusted {
+        if let Some(ip) = forwarded_client(request.headers())
+            && let Some(ConnectInfo(addr)) = request.extensions_mut().get_mut::<ConnectInfo<SocketAddr>>()
+        {
+            addr.set_ip(ip);
+        }
+    } else {
+        if FORWARDING_HEADERS.iter().any(|name| request.headers().contains_key(*name)) {
+            request.extensions_mut().insert(UntrustedProxy);
+        }
+        request.headers_mut().remove(X_FORWARDED_FOR);
+        request.headers_mut().remove(X_FORWARDED_PROTO);
+    }
+    next.run(request).await
+}
+
+/// The client address in `X-Forwarded-For`: the last entry, the one the proxy in front of the
+/// app added. Entries before it came from the client or proxies further out, and could be
+/// made up.
+fn forwarded_client(headers: &HeaderMap) -> Option<IpAddr> {
+    headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .next_back()?
+        .trim()
+        .parse()
+        .ok()
+}
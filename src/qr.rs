@@ -0,0 +1,692 @@
+//! QR codes (ISO/IEC 18004) as PNG images, for scanning a contact's vCard into a phone.
+//!
+//! Only what that needs is here: byte mode, error correction level M (about 15% of the code
+//! can be damaged and it still reads), and the smallest of the 40 versions the data fits in.
+//! The PNG is written by hand too, black and white with one bit per pixel and uncompressed
+//! deflate blocks, which are still small at that depth.
+
+/// Pixels per module (one square of the code).
+const SCALE: usize = 6;
+/// Light modules around the code, the quiet zone scanners need to find it.
+const BORDER: usize = 4;
+
+/// Error correction codewords per block at level M, by version.
+const ECC_CODEWORDS_PER_BLOCK: [usize; 41] = [
+    0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+];
+/// Error correction blocks at level M, by version.
+const ECC_BLOCKS: [usize; 41] = [
+    0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23, 25, 26,
+    28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+];
+/// Level M in the format information.
+const FORMAT_LEVEL_M: u32 = 0b00;
+
+/// A QR code's modules, `true` for dark, by row.
+pub struct QrCode {
+    size: usize,
+    modules: Vec<Vec<bool>>,
+    /// Finder, timing, alignment and format modules, which data and masks leave alone.
+    function: Vec<Vec<bool>>,
+}
+
+impl QrCode {
+    /// Encodes `data` in the smallest version it fits in, with whichever mask scores best.
+    /// `None` if it's longer than the 2331 bytes version 40 holds.
+    ///
+    /// Example usage:
+    /// `let png = QrCode::encode(vcard.as_bytes()).map(|qr| qr.to_png());`
+    pub fn encode(data: &[u8]) -> Option<Self> {
+        let version = (1..=40).find(|&version| {
+            let count_bits = if version < 10 { 8 } else { 16 };
+            data.len() < 1 << count_bits && 4 + count_bits + data.len() * 8 <= data_codewords(version) * 8
+        })?;
+        let size = version * 4 + 17;
+        let mut qr = Self {
+            size,
+            modules: vec![vec![false; size]; size],
+            function: vec![vec![false; size]; size],
+        };
+        qr.draw_function_patterns(version);
+        qr.draw_codewords(&add_error_correction(version, &data_codewords_for(version, data)));
+
+        let mask = (0..8)
+            .min_by_key(|&mask| {
+                qr.apply_mask(mask);
+                qr.draw_format_bits(mask);
+                let penalty = qr.penalty();
+                // Masking twice undoes it
+                qr.apply_mask(mask);
+                penalty
+            })
+            .expect("there are 8 masks");
+        qr.apply_mask(mask);
+        qr.draw_format_bits(mask);
+        Some(qr)
+    }
+
+    /// Sets a module that belongs to a function pattern.
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y][x] = dark;
+        self.function[y][x] = true;
+    }
+
+    fn draw_function_patterns(&mut self, version: usize) {
+        let size = self.size;
+        for i in 0..size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+        for (x, y) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            self.draw_finder(x, y);
+        }
+        let positions = alignment_positions(version, size);
+        let last = positions.len().saturating_sub(1);
+        for (i, &x) in positions.iter().enumerate() {
+            for (j, &y) in positions.iter().enumerate() {
+                // Not where they would overlap the finders
+                if ![(0, 0), (0, last), (last, 0)].contains(&(i, j)) {
+                    self.draw_alignment(x, y);
+                }
+            }
+        }
+        // Reserves the format modules, the real bits go in once the mask is picked
+        self.draw_format_bits(0);
+        self.draw_version(version);
+    }
+
+    /// A finder pattern centered on `x`, `y`, with its light separator.
+    fn draw_finder(&mut self, x: usize, y: usize) {
+        for dy in -4..=4_isize {
+            for dx in -4..=4_isize {
+                let (fx, fy) = (x as isize + dx, y as isize + dy);
+                if (0..self.size as isize).contains(&fx) && (0..self.size as isize).contains(&fy) {
+                    let distance = dx.abs().max(dy.abs());
+                    self.set_function(fx as usize, fy as usize, distance != 2 && distance != 4);
+                }
+            }
+        }
+    }
+
+    fn draw_alignment(&mut self, x: usize, y: usize) {
+        for dy in -2..=2_isize {
+            for dx in -2..=2_isize {
+                let dark = dx.abs().max(dy.abs()) != 1;
+                self.set_function((x as isize + dx) as usize, (y as isize + dy) as usize, dark);
+            }
+        }
+    }
+
+    /// The error correction level and mask, twice, plus the module that is always dark.
+    fn draw_format_bits(&mut self, mask: u32) {
+        let bits = format_bits(mask);
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+        let size = self.size;
+
+        for i in 0..=5 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        self.set_function(8, size - 8, true);
+    }
+
+    /// The version, in two 6 by 3 blocks. Only versions 7 and up have them.
+    fn draw_version(&mut self, version: usize) {
+        if version < 7 {
+            return;
+        }
+        let bits = version_bits(version);
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 != 0;
+            let (a, b) = (self.size - 11 + i % 3, i / 3);
+            self.set_function(a, b, dark);
+            self.set_function(b, a, dark);
+        }
+    }
+
+    /// Fills the modules that aren't function patterns with `codewords`, in the zigzag of
+    /// two module wide columns from the bottom right corner.
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let mut i = 0;
+        let mut right = self.size - 1;
+        while right >= 1 {
+            // The vertical timing pattern is skipped as a whole
+            if right == 6 {
+                right = 5;
+            }
+            for vertical in 0..self.size {
+                for j in 0..2 {
+                    let x = right - j;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward { self.size - 1 - vertical } else { vertical };
+                    if !self.function[y][x] && i < codewords.len() * 8 {
+                        self.modules[y][x] = (codewords[i >> 3] >> (7 - (i & 7))) & 1 != 0;
+                        i += 1;
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    /// Flips the data modules where `mask`'s pattern is dark.
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let flip = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                if flip && !self.function[y][x] {
+                    self.modules[y][x] = !self.modules[y][x];
+                }
+            }
+        }
+    }
+
+    /// How hard the code is to scan, by the standard's four rules: long runs of one color,
+    /// 2 by 2 blocks of one color, lookalikes of the finder pattern, and an uneven balance of
+    /// dark and light. Lower is better.
+    fn penalty(&self) -> usize {
+        const FINDER_LIKE: [bool; 7] = [true, false, true, true, true, false, true];
+        let size = self.size;
+        let columns: Vec<Vec<bool>> = (0..size).map(|x| (0..size).map(|y| self.modules[y][x]).collect()).collect();
+        let mut penalty = 0;
+        for line in self.modules.iter().chain(&columns) {
+            let mut run = 1;
+            for i in 1..=size {
+                if i < size && line[i] == line[i - 1] {
+                    run += 1;
+                    continue;
+                }
+                if run >= 5 {
+                    penalty += run - 2;
+                }
+                run = 1;
+            }
+            for start in 0..=size - 7 {
+                if line[start..start + 7] == FINDER_LIKE {
+                    let light = |from: usize, to: usize| (from..to).all(|i| !line[i]);
+                    let before = start >= 4 && light(start - 4, start);
+                    let after = start + 11 <= size && light(start + 7, start + 11);
+                    if before || after {
+                        penalty += 40;
+                    }
+                }
+            }
+        }
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let color = self.modules[y][x];
+                if color == self.modules[y][x + 1] && color == self.modules[y + 1][x] && color == self.modules[y + 1][x + 1] {
+                    penalty += 3;
+                }
+            }
+        }
+        let dark = self.modules.iter().flatten().filter(|&&dark| dark).count();
+        let total = size * size;
+        // Every 5% away from half dark costs 10
+        let off = (dark * 20).abs_diff(total * 10);
+        penalty + off.div_ceil(total).saturating_sub(1) * 10
+    }
+
+    /// The code as a PNG, `SCALE` pixels per module with a `BORDER` module quiet zone.
+    pub fn to_png(&self) -> Vec<u8> {
+        let width = (self.size + 2 * BORDER) * SCALE;
+        let row_bytes = width.div_ceil(8);
+        // One bit per pixel, 1 is white. Each row starts with a filter type byte, 0 for none.
+        let mut pixels = Vec::with_capacity((row_bytes + 1) * width);
+        for py in 0..width {
+            pixels.push(0);
+            let start = pixels.len();
+            pixels.resize(start + row_bytes, 0);
+            for px in 0..width {
+                if !self.is_dark_pixel(px, py) {
+                    pixels[start + px / 8] |= 0x80 >> (px % 8);
+                }
+            }
+        }
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        header.extend_from_slice(&(width as u32).to_be_bytes());
+        // Bit depth 1, grayscale, deflate, standard filters, not interlaced
+        header.extend_from_slice(&[1, 0, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&pixels));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    fn is_dark_pixel(&self, px: usize, py: usize) -> bool {
+        let (x, y) = (px / SCALE, py / SCALE);
+        (BORDER..BORDER + self.size).contains(&x)
+            && (BORDER..BORDER + self.size).contains(&y)
+            && self.modules[y - BORDER][x - BORDER]
+    }
+}
+
+/// The 15 format bits for level M and `mask`: the 5 data bits, their BCH error correction,
+/// and the standard's XOR pattern so they are never all light.
+fn format_bits(mask: u32) -> u32 {
+    let data = FORMAT_LEVEL_M << 3 | mask;
+    let mut remainder = data;
+    for _ in 0..10 {
+        remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+    }
+    (data << 10 | remainder) ^ 0x5412
+}
+
+/// The 18 version bits: the version's 6 bits and their BCH error correction.
+fn version_bits(version: usize) -> u32 {
+    let mut remainder = version as u32;
+    for _ in 0..12 {
+        remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1F25);
+    }
+    (version as u32) << 12 | remainder
+}
+
+/// Modules left for data and error correction once the function patterns are drawn, in bits.
+fn raw_data_modules(version: usize) -> usize {
+    let mut modules = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let alignments = version / 7 + 2;
+        modules -= (25 * alignments - 10) * alignments - 55;
+        if version >= 7 {
+            modules -= 36;
+        }
+    }
+    modules
+}
+
+/// Data codewords a version holds at level M.
+fn data_codewords(version: usize) -> usize {
+    raw_data_modules(version) / 8 - ECC_CODEWORDS_PER_BLOCK[version] * ECC_BLOCKS[version]
+}
+
+/// Centers of the alignment patterns on either axis.
+fn alignment_positions(version: usize, size: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let count = version / 7 + 2;
+    let step = if version == 32 {
+        26
+    } else {
+        (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2
+    };
+    let mut positions: Vec<usize> = (0..count - 1).map(|i| size - 7 - i * step).collect();
+    positions.push(6);
+    positions.reverse();
+    positions
+}
+
+/// `data` in byte mode, with the terminator and padding that fill up the version.
+fn data_codewords_for(version: usize, data: &[u8]) -> Vec<u8> {
+    let capacity = data_codewords(version) * 8;
+    let mut bits: Vec<bool> = Vec::with_capacity(capacity);
+    let mut push = |value: usize, count: usize| {
+        bits.extend((0..count).rev().map(|i| (value >> i) & 1 != 0));
+    };
+    // Byte mode
+    push(0b0100, 4);
+    push(data.len(), if version < 10 { 8 } else { 16 });
+    for &byte in data {
+        push(byte.into(), 8);
+    }
+    let terminator = (capacity - bits.len()).min(4);
+    bits.resize(bits.len() + terminator, false);
+    bits.resize(bits.len().div_ceil(8) * 8, false);
+
+    let mut codewords: Vec<u8> = bits
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | u8::from(bit)))
+        .collect();
+    for pad in [0xEC, 0x11].into_iter().cycle() {
+        if codewords.len() * 8 >= capacity {
+            break;
+        }
+        codewords.push(pad);
+    }
+    codewords
+}
+
+/// Splits the data into the version's blocks, adds each one's Reed-Solomon codewords, and
+/// interleaves them all in the order they are placed.
+fn add_error_correction(version: usize, data: &[u8]) -> Vec<u8> {
+    let blocks = ECC_BLOCKS[version];
+    let ecc_len = ECC_CODEWORDS_PER_BLOCK[version];
+    let raw_codewords = raw_data_modules(version) / 8;
+    let short_blocks = blocks - raw_codewords % blocks;
+    let short_len = raw_codewords / blocks;
+    let divisor = reed_solomon_divisor(ecc_len);
+
+    let mut split = Vec::with_capacity(blocks);
+    let mut start = 0;
+    for i in 0..blocks {
+        let data_len = short_len - ecc_len + usize::from(i >= short_blocks);
+        let block = &data[start..start + data_len];
+        start += data_len;
+        split.push((block.to_vec(), reed_solomon_remainder(block, &divisor)));
+    }
+
+    let mut result = Vec::with_capacity(raw_codewords);
+    let longest = short_len - ecc_len + 1;
+    for i in 0..longest {
+        result.extend(split.iter().filter_map(|(block, _)| block.get(i)));
+    }
+    for i in 0..ecc_len {
+        result.extend(split.iter().map(|(_, ecc)| ecc[i]));
+    }
+    result
+}
+
+/// Product of two elements of GF(2^8) with the QR code polynomial.
+fn gf_multiply(a: u8, b: u8) -> u8 {
+    let mut product: u16 = 0;
+    for i in (0..8).rev() {
+        product = (product << 1) ^ ((product >> 7) * 0x11D);
+        product ^= ((u16::from(b) >> i) & 1) * u16::from(a);
+    }
+    product as u8
+}
+
+/// The generator polynomial for `degree` error correction codewords, highest term first and
+/// without its leading 1.
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0; degree];
+    result[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (r, &d) in result.iter_mut().zip(divisor) {
+            *r ^= gf_multiply(d, factor);
+        }
+    }
+    result
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// `data` as a zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xFFFF;
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(u8::from(blocks.peek().is_none()));
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFF_u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(text: &str) -> u32 {
+        u32::from_str_radix(text, 2).unwrap()
+    }
+
+    /// The codewords of `qr`, read back the way a scanner does: the format bits give the mask,
+    /// which is taken off, and the data modules are read in their zigzag.
+    fn read_codewords(qr: &QrCode) -> Vec<u8> {
+        let size = qr.size;
+        let module = |x: usize, y: usize| u32::from(qr.modules[y][x]);
+        // Bit 14 first, once around the top left finder and once split between the other two
+        let mut first = 0;
+        for (x, y) in [(0, 8), (1, 8), (2, 8), (3, 8), (4, 8), (5, 8), (7, 8), (8, 8), (8, 7)] {
+            first = first << 1 | module(x, y);
+        }
+        for y in (0..=5).rev() {
+            first = first << 1 | module(8, y);
+        }
+        let mut second = 0;
+        for y in (size - 7..size).rev() {
+            second = second << 1 | module(8, y);
+        }
+        for x in size - 8..size {
+            second = second << 1 | module(x, 8);
+        }
+        assert_eq!(first, second, "the two copies of the format bits differ");
+        let mask = (0..8).find(|&mask| format_bits(mask) == first).expect("format bits of level M");
+        assert_eq!(module(8, size - 8), 1, "the dark module");
+
+        let mut unmasked = QrCode {
+            size,
+            modules: qr.modules.clone(),
+            function: qr.function.clone(),
+        };
+        unmasked.apply_mask(mask);
+        let mut bits = Vec::new();
+        let mut columns: Vec<usize> = (7..size).rev().step_by(2).collect();
+        columns.extend([5, 3, 1]);
+        for (pair, right) in columns.into_iter().enumerate() {
+            let rows: Vec<usize> = if pair % 2 == 0 { (0..size).rev().collect() } else { (0..size).collect() };
+            for y in rows {
+                for x in [right, right - 1] {
+                    if !qr.function[y][x] {
+                        bits.push(unmasked.modules[y][x]);
+                    }
+                }
+            }
+        }
+        bits.chunks_exact(8)
+            .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | u8::from(bit)))
+            .collect()
+    }
+
+    /// `true` if `codeword`s, data then error correction, are a Reed-Solomon code word: the
+    /// polynomial is 0 at the generator's roots, 2^0 to 2^(ecc_len - 1).
+    fn has_no_errors(codewords: &[u8], ecc_len: usize) -> bool {
+        let mut root = 1;
+        (0..ecc_len).all(|_| {
+            let value = codewords.iter().fold(0, |acc, &c| gf_multiply(acc, root) ^ c);
+            root = gf_multiply(root, 0x02);
+            value == 0
+        })
+    }
+
+    /// Decodes `qr` back to its byte mode data, checking every block's error correction.
+    fn decode(qr: &QrCode) -> Vec<u8> {
+        let version = (qr.size - 17) / 4;
+        if version >= 7 {
+            let mut read = 0;
+            for i in (0..18).rev() {
+                read = read << 1 | u32::from(qr.modules[i / 3][qr.size - 11 + i % 3]);
+            }
+            assert_eq!(read, version_bits(version), "version bits");
+        }
+        let data_modules = qr.function.iter().flatten().filter(|&&function| !function).count();
+        assert_eq!(data_modules, raw_data_modules(version), "modules left for data");
+
+        let codewords = read_codewords(qr);
+        let (blocks, ecc_len) = (ECC_BLOCKS[version], ECC_CODEWORDS_PER_BLOCK[version]);
+        let short_len = codewords.len() / blocks;
+        let short_blocks = blocks - codewords.len() % blocks;
+        let data_lens: Vec<usize> = (0..blocks).map(|i| short_len - ecc_len + usize::from(i >= short_blocks)).collect();
+        let mut split: Vec<Vec<u8>> = vec![Vec::new(); blocks];
+        let mut next = codewords.iter();
+        for i in 0..short_len - ecc_len + 1 {
+            for (block, &len) in split.iter_mut().zip(&data_lens) {
+                if i < len {
+                    block.push(*next.next().unwrap());
+                }
+            }
+        }
+        for _ in 0..ecc_len {
+            for block in &mut split {
+                block.push(*next.next().unwrap());
+            }
+        }
+        let mut data = Vec::new();
+        for (block, len) in split.iter().zip(&data_lens) {
+            assert!(has_no_errors(block, ecc_len), "block error correction");
+            data.extend_from_slice(&block[..*len]);
+        }
+
+        let bit = |i: usize| usize::from(data[i / 8] >> (7 - i % 8) & 1);
+        let read = |from: usize, count: usize| (from..from + count).fold(0, |acc, i| acc << 1 | bit(i));
+        assert_eq!(read(0, 4), 0b0100, "byte mode");
+        let count_bits = if version < 10 { 8 } else { 16 };
+        let len = read(4, count_bits);
+        (0..len).map(|i| read(4 + count_bits + i * 8, 8) as u8).collect()
+    }
+
+    #[test]
+    fn reed_solomon_matches_the_hello_world_example() {
+        // Version 1-M "HELLO WORLD", from the standard's worked example
+        let data = [32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17];
+        let ecc = reed_solomon_remainder(&data, &reed_solomon_divisor(10));
+        assert_eq!(ecc, [196, 35, 39, 119, 235, 215, 231, 226, 93, 23]);
+        assert!(has_no_errors(&[&data[..], &ecc].concat(), 10));
+    }
+
+    #[test]
+    fn format_bits_match_the_level_m_table() {
+        let table = [
+            "101010000010010",
+            "101000100100101",
+            "101111001111100",
+            "101101101001011",
+            "100010111111001",
+            "100000011001110",
+            "100111110010111",
+            "100101010100000",
+        ];
+        for (mask, expected) in table.into_iter().enumerate() {
+            assert_eq!(format_bits(mask as u32), bits(expected), "mask {mask}");
+        }
+    }
+
+    #[test]
+    fn version_bits_match_the_table() {
+        assert_eq!(version_bits(7), bits("000111110010010100"));
+        assert_eq!(version_bits(8), bits("001000010110111100"));
+        assert_eq!(version_bits(40), bits("101000110001101001"));
+    }
+
+    #[test]
+    fn alignment_positions_match_the_table() {
+        let positions = |version: usize| alignment_positions(version, version * 4 + 17);
+        assert!(positions(1).is_empty());
+        assert_eq!(positions(2), [6, 18]);
+        assert_eq!(positions(7), [6, 22, 38]);
+        assert_eq!(positions(14), [6, 26, 46, 66]);
+        assert_eq!(positions(32), [6, 34, 60, 86, 112, 138]);
+        assert_eq!(positions(40), [6, 30, 58, 86, 114, 142, 170]);
+    }
+
+    #[test]
+    fn data_capacity_matches_the_level_m_table() {
+        for (version, codewords) in [(1, 16), (2, 28), (5, 86), (7, 124), (10, 216), (40, 2334)] {
+            assert_eq!(data_codewords(version), codewords, "version {version}");
+        }
+    }
+
+    #[test]
+    fn byte_mode_adds_the_terminator_and_padding() {
+        let codewords = data_codewords_for(1, b"A");
+        let mut expected = vec![0x40, 0x14, 0x10];
+        expected.extend([0xEC, 0x11].into_iter().cycle().take(13));
+        assert_eq!(codewords, expected);
+    }
+
+    #[test]
+    fn picks_the_smallest_version_that_fits() {
+        let size = |len: usize| QrCode::encode(&vec![b'a'; len]).map(|qr| qr.size);
+        assert_eq!(size(14), Some(21));
+        assert_eq!(size(15), Some(25));
+        assert_eq!(size(2331), Some(177));
+        assert_eq!(size(2332), None);
+    }
+
+    #[test]
+    fn codes_decode_back_to_their_data() {
+        // Versions 1, 5, 8 (version bits), 13 (16 bit length) and 40
+        for (len, version) in [(10, 1), (80, 5), (140, 8), (300, 13), (2331, 40)] {
+            let data: Vec<u8> = (0..len).map(|i| (i * 7 + 3) as u8).collect();
+            let qr = QrCode::encode(&data).unwrap();
+            assert_eq!(qr.size, version * 4 + 17, "{len} bytes");
+            assert_eq!(decode(&qr), data, "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn png_checksums_match_known_values() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use tracing::{error, info};
+
+use crate::{
+    contacts::Contact,
+    repository::{ContactRepository, SharedContactRepository},
+    validation,
+};
+
+/// How often the report is regenerated in the background.
+pub const QUALITY_REPORT_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// A contact with at least one data quality problem.
+#[derive(Debug, Clone)]
+pub struct QualityIssue {
+    pub contact: Contact,
+    /// Message ids from `validation`, translated when the report is shown.
+    pub problems: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QualityReport {
+    pub generated_at: DateTime<Utc>,
+    pub checked: usize,
+    pub issues: Vec<QualityIssue>,
+}
+
+impl QualityReport {
+    /// Runs every check in the validation module over the whole contacts table.
+    pub async fn generate(contacts: &dyn ContactRepository) -> Result<Self, sqlx::Error> {
+        let contacts = contacts.all().await?;
+        let checked = contacts.len();
+        let issues = contacts
+            .into_iter()
+            .filter_map(|contact| {
+                let problems: Vec<&'static str> = [
+                    validation::name_problem(&contact.first_name, &contact.last_name),
+                    validation::email_problem(&contact.email),
+                    validation::phone_problem(&contact.phone_number),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+                (!problems.is_empty()).then_some(QualityIssue { contact, problems })
+            })
+            .collect();
+
+        Ok(Self {
+            generated_at: Utc::now(),
+            checked,
+            issues,
+        })
+    }
+}
+
+/// The most recently generated report. Generating it means reading every contact, so the
+/// report page serves this cached copy and a background task keeps it fresh.
+#[derive(Clone, Default)]
+pub struct QualityReports {
+    latest: Arc<RwLock<Option<QualityReport>>>,
+}
+
+impl QualityReports {
+    pub fn latest(&self) -> Option<QualityReport> {
+        self.latest.read().unwrap().clone()
+    }
+
+    /// Regenerates the report and replaces the cached copy.
+    pub async fn refresh(&self, contacts: &dyn ContactRepository) {
+        match QualityReport::generate(contacts).await {
+            Ok(report) => {
+                info!(
+                    "Data quality report: {} of {} contacts have problems",
+                    report.issues.len(),
+                    report.checked
+                );
+                *self.latest.write().unwrap() = Some(report);
+            }
+            Err(e) => error!("Failed to generate data quality report: {e}"),
+        }
+    }
+
+    /// Regenerates the report in the background without waiting for it.
+    pub fn refresh_in_background(&self, contacts: SharedContactRepository) {
+        let reports = self.clone();
+        tokio::spawn(async move { reports.refresh(contacts.as_ref()).await });
+    }
+
+    /// Spawns the task that regenerates the report on startup and every
+    /// `QUALITY_REPORT_INTERVAL` after that.
+    pub fn spawn_scheduler(&self, contacts: SharedContactRepository) {
+        let reports = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(QUALITY_REPORT_INTERVAL);
+            loop {
+                interval.tick().await;
+                reports.refresh(contacts.as_ref()).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,191 @@
+//! The search box syntax. Besides plain words, `q` can hold filter tokens:
+//!
+//! - `email:example.com` or `email:@example.com`: email at that domain.
+//! - `created:2024-01-01`: created on that day. With `>`, `>=`, `<` or `<=` in front of the
+//!   date, created after, on or after, before, or on or before it.
+//!
+//! Everything else is free text, matched against the names, companies, job titles, emails and
+//! phone numbers, so `ana@example.com` or `444-9876` find a contact as well as `ana` does. The
+//! tokens end up in a `ContactFilter`, so they go through the same bound query parameters as the
+//! `email_domain` and `created_*` list parameters.
+
+use chrono::{Days, NaiveDate};
+
+use crate::{contacts::ContactFilter, i18n};
+
+/// Parses `q` into a filter. Errors are messages for the user, in the current locale.
+///
+/// Example usage:
+/// `query::parse("ana email:example.com created:>=2024-01-01")?`
+pub fn parse(q: &str) -> Result<ContactFilter, String> {
+    let mut filter = ContactFilter::default();
+    let mut words = Vec::new();
+    for token in q.split_whitespace() {
+        let Some((key, value)) = token.split_once(':') else {
+            words.push(token);
+            continue;
+        };
+        match key.to_ascii_lowercase().as_str() {
+            "email" => {
+                let domain = value.trim_start_matches('@').to_lowercase();
+                if domain.is_empty() || domain.contains('@') {
+                    return Err(i18n::t_with("query-email-needs-domain", "token", token));
+                }
+                let domain = ContactFilter {
+                    email_domain: Some(domain),
+                    ..ContactFilter::default()
+                };
+                filter = combine(filter, &domain)?;
+            }
+            "created" => {
+                let (after, before) = created_range(value)
+                    .ok_or_else(|| i18n::t_with("query-created-needs-date", "token", token))?;
+                let range = ContactFilter {
+                    created_after: after,
+                    created_before: before,
+                    ..ContactFilter::default()
+                };
+                filter = combine(filter, &range)?;
+            }
+            "tag" => return Err(i18n::t("query-no-tags")),
+            // Not a filter, just text with a colon in it
+            _ => words.push(token),
+        }
+    }
+    if !words.is_empty() {
+        filter.text = Some(words.join(" "));
+    }
+    Ok(filter)
+}
+
+/// The half-open `created_after`..`created_before` range for a `created:` value.
+fn created_range(value: &str) -> Option<(Option<NaiveDate>, Option<NaiveDate>)> {
+    let (operator, date) = match value.find(|c: char| c.is_ascii_digit()) {
+        Some(start) => value.split_at(start),
+        None => return None,
+    };
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    let next_day = date.checked_add_days(Days::new(1))?;
+    Some(match operator {
+        "" | "=" => (Some(date), Some(next_day)),
+        ">" => (Some(next_day), None),
+        ">=" => (Some(date), None),
+        "<" => (None, Some(date)),
+        "<=" => (None, Some(next_day)),
+        _ => return None,
+    })
+}
+
+/// Both filters at once: the free text of both, the later `created_after` and the earlier
+/// `created_before`. Fails if they ask for different email domains or the dates leave nothing.
+pub fn combine(filter: ContactFilter, other: &ContactFilter) -> Result<ContactFilter, String> {
+    let text = match (filter.text, &other.text) {
+        (Some(text), Some(more)) => Some(format!("{text} {more}")),
+        (text, more) => text.or_else(|| more.clone()),
+    };
+    let email_domain = match (filter.email_domain, &other.email_domain) {
+        (Some(domain), Some(other)) if domain != *other => {
+            return Err(i18n::t_args(
+                "query-conflicting-domains",
+                &[("domain", domain.into()), ("other", other.as_str().into())],
+            ));
+        }
+        (domain, other) => domain.or_else(|| other.clone()),
+    };
+    let created_after = filter.created_after.max(other.created_after);
+    let created_before = match (filter.created_before, other.created_before) {
+        (Some(before), Some(other)) => Some(before.min(other)),
+        (before, other) => before.or(other),
+    };
+    if let (Some(after), Some(before)) = (created_after, created_before)
+        && after >= before
+    {
+        return Err(i18n::t("query-empty-range"));
+    }
+    Ok(ContactFilter {
+        text,
+        created_after,
+        created_before,
+        email_domain,
+        last_initial: filter.last_initial.or(other.last_initial),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(day: &str) -> Option<NaiveDate> {
+        Some(day.parse().unwrap())
+    }
+
+    #[test]
+    fn tokens_become_filters_and_the_rest_is_text() {
+        let filter = parse("ana  EMAIL:@Example.COM created:>=2024-01-01 10:30 note:x").unwrap();
+        assert_eq!(filter.text.as_deref(), Some("ana 10:30 note:x"));
+        assert_eq!(filter.email_domain.as_deref(), Some("example.com"));
+        assert_eq!((filter.created_after, filter.created_before), (day("2024-01-01"), None));
+        assert_eq!(parse("  ").unwrap(), ContactFilter::default());
+    }
+
+    #[test]
+    fn created_operators_give_half_open_ranges() {
+        for (value, after, before) in [
+            ("2024-02-28", day("2024-02-28"), day("2024-02-29")),
+            ("=2024-12-31", day("2024-12-31"), day("2025-01-01")),
+            (">2024-02-29", day("2024-03-01"), None),
+            (">=2024-02-29", day("2024-02-29"), None),
+            ("<2024-02-29", None, day("2024-02-29")),
+            ("<=2024-02-29", None, day("2024-03-01")),
+        ] {
+            let filter = parse(&format!("created:{value}")).unwrap();
+            assert_eq!((filter.created_after, filter.created_before), (after, before), "{value}");
+        }
+        for value in ["", ">", "2023-02-29", "=>2024-01-01", "<<2024-01-01", "2024-1-1x", "yesterday"] {
+            let error = parse(&format!("created:{value}")).unwrap_err();
+            assert!(error.contains(&format!("created:{value}")), "{value}: {error}");
+        }
+    }
+
+    #[test]
+    fn bad_tokens_are_explained() {
+        for q in ["email:", "email:@", "email:ana@example.com"] {
+            assert!(parse(q).unwrap_err().contains(q), "{q}");
+        }
+        assert!(parse("tag:friends").is_err());
+        assert!(parse("email:example.com email:example.org").is_err());
+        assert!(parse("email:example.com email:@EXAMPLE.com").is_ok());
+        assert!(parse("created:>2024-01-01 created:<=2024-01-01").is_err());
+        assert!(parse("created:>=2024-01-01 created:<=2024-01-01").is_ok());
+    }
+
+    #[test]
+    fn combined_filters_need_both() {
+        let filter = ContactFilter {
+            text: Some("ana".into()),
+            created_after: day("2024-01-01"),
+            created_before: day("2024-06-01"),
+            last_initial: Some('S'),
+            ..ContactFilter::default()
+        };
+        let other = ContactFilter {
+            text: Some("smith".into()),
+            created_after: day("2024-03-01"),
+            created_before: day("2024-12-01"),
+            email_domain: Some("example.com".into()),
+            ..ContactFilter::default()
+        };
+        let combined = combine(filter.clone(), &other).unwrap();
+        assert_eq!(combined.text.as_deref(), Some("ana smith"));
+        assert_eq!((combined.created_after, combined.created_before), (day("2024-03-01"), day("2024-06-01")));
+        assert_eq!(combined.email_domain.as_deref(), Some("example.com"));
+        assert_eq!(combined.last_initial, Some('S'));
+        assert_eq!(combine(filter.clone(), &ContactFilter::default()).unwrap(), filter);
+        assert_eq!(combine(ContactFilter::default(), &filter).unwrap(), filter);
+
+        let later = ContactFilter { created_after: day("2024-06-01"), ..ContactFilter::default() };
+        assert!(combine(filter.clone(), &later).is_err());
+        let elsewhere = ContactFilter { email_domain: Some("example.org".into()), ..ContactFilter::default() };
+        assert!(combine(combined, &elsewhere).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+//! Token bucket rate limits: one per client IP for every page (`RateLimiter`, `limit`) and one
+//! per API key (`KeyRateLimiter`). Clients over their limit get a 429 with `Retry-After`.
+//!
+//! Buckets live in memory, so limits start over on a restart and aren't shared between
+//! processes. A bucket left alone long enough is full again, so idle ones are dropped, every
+//! `PRUNE_INTERVAL` rather than on every request: a client spreading requests over many
+//! addresses only adds buckets, it doesn't make each request scan them all.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use askama::Template;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderValue, StatusCode, header::RETRY_AFTER},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+
+use crate::{config::RateLimitConfig, templates::TooManyRequestsTemplate};
+
+/// Buckets that haven't been touched for this long are full again anyway, so they get dropped.
+const IDLE_BUCKET_TTL: Duration = Duration::from_secs(10 * 60);
+/// How often idle buckets are looked for.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn full(burst: f64, now: Instant) -> Self {
+        Bucket { tokens: burst, last_refill: now }
+    }
+
+    /// Refills the bucket up to `now` and takes a token out. Returns how long to wait before
+    /// retrying if it's empty.
+    fn take(&mut self, now: Instant, per_second: f64, burst: f64) -> Result<(), Duration> {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_second).min(burst);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(secs((1.0 - self.tokens) / per_second))
+        }
+    }
+}
+
+/// `secs` seconds, `Duration::MAX` for more than that holds, like a wait at a tiny rate.
+fn secs(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+}
+
+/// The buckets of one limiter, by client, with the idle ones dropped now and then.
+struct Buckets<K> {
+    buckets: HashMap<K, Bucket>,
+    last_pruned: Instant,
+}
+
+impl<K: Hash + Eq> Buckets<K> {
+    fn new(now: Instant) -> Self {
+        Self { buckets: HashMap::new(), last_pruned: now }
+    }
+
+    /// `key`'s bucket, a full one of `burst` tokens if it has none.
+    fn get(&mut self, key: K, now: Instant, burst: f64) -> &mut Bucket {
+        if now.duration_since(self.last_pruned) >= PRUNE_INTERVAL {
+            self.buckets.retain(|_, b| now.duration_since(b.last_refill) < IDLE_BUCKET_TTL);
+            self.last_pruned = now;
+        }
+        self.buckets.entry(key).or_insert_with(|| Bucket::full(burst, now))
+    }
+}
+
+/// Per client IP token bucket rate limiter. Every IP gets a bucket of `burst` tokens that
+/// refills at `per_second` tokens a second, and each request takes one token out.
+#[derive(Clone)]
+pub struct RateLimiter {
+    inner: Arc<Mutex<Limiter>>,
+}
+
+struct Limiter {
+    per_second: f64,
+    burst: f64,
+    buckets: Buckets<IpAddr>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Limiter {
+                per_second: config.per_second,
+                burst: config.burst,
+                buckets: Buckets::new(Instant::now()),
+            })),
+        }
+    }
+
+    /// Switches to the rates of `config`, when the config is reloaded. Buckets keep their
+    /// tokens, down to the new `burst` on their next request.
+    pub fn set_limits(&self, config: &RateLimitConfig) {
+        let mut limiter = self.inner.lock().unwrap();
+        limiter.per_second = config.per_second;
+        limiter.burst = config.burst;
+    }
+
+    /// Takes a token from the IP's bucket. Returns how long to wait before retrying if the
+    /// bucket is empty.
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut limiter = self.inner.lock().unwrap();
+        let Limiter { per_second, burst, buckets } = &mut *limiter;
+        buckets.get(ip, now, *burst).take(now, *per_second, *burst)
+    }
+}
+
+/// Where a client stands against one of its limits, for the `X-RateLimit-*` headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub limit: i64,
+    pub remaining: i64,
+    /// How long until all of `limit` can be used again.
+    pub reset: Duration,
+}
+
+/// Token buckets of API keys, each with its key's own `requests_per_minute`: a key can make
+/// that many requests at once, and gets them back over a minute.
+#[derive(Clone)]
+pub struct KeyRateLimiter {
+    buckets: Arc<Mutex<Buckets<i64>>>,
+}
+
+impl Default for KeyRateLimiter {
+    fn default() -> Self {
+        Self { buckets: Arc::new(Mutex::new(Buckets::new(Instant::now()))) }
+    }
+}
+
+impl KeyRateLimiter {
+    /// Takes a token from key `key_id`'s bucket. Returns the key's usage after that, or how
+    /// long to wait before retrying with the usage if the bucket is empty.
+    pub fn check(&self, key_id: i64, per_minute: i64) -> Result<Usage, (Duration, Usage)> {
+        let now = Instant::now();
+        let burst = per_minute.max(1) as f64;
+        let per_second = burst / 60.0;
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.get(key_id, now, burst);
+        let taken = bucket.take(now, per_second, burst);
+        let usage = Usage {
+            limit: per_minute,
+            remaining: bucket.tokens.floor() as i64,
+            reset: secs((burst - bucket.tokens) / per_second),
+        };
+        taken.map(|()| usage).map_err(|wait| (wait, usage))
+    }
+}
+
+/// Middleware: rejects requests from clients that have used up their bucket with a 429 and
+/// a `Retry-After` header.
+///
+/// Example usage:
+/// `router.layer(middleware::from_fn_with_state(limiter, rate_limit::limit))`
+pub async fn limit(
+    State(limiter): State<RateLimiter>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match limiter.check(addr.ip()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => too_many_requests(wait),
+    }
+}
+
+/// `wait` in whole seconds, rounded up so a client that waits that long finds room again, for
+/// `Retry-After` and `X-RateLimit-Reset`.
+pub fn retry_after_secs(wait: Duration) -> u64 {
+    u64::try_from(wait.as_nanos().div_ceil(1_000_000_000)).unwrap_or(u64::MAX)
+}
+
+fn too_many_requests(wait: Duration) -> Response {
+    let retry_after = retry_after_secs(wait).max(1);
+    let template = TooManyRequestsTemplate { retry_after };
+    let mut response = match template.render() {
+        Ok(html) => (StatusCode::TOO_MANY_REQUESTS, Html(html)).into_response(),
+        Err(_) => (StatusCode::TOO_MANY_REQUESTS, "Too Many Requests").into_response(),
+    };
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_round_up_to_whole_seconds() {
+        assert_eq!(retry_after_secs(Duration::ZERO), 0);
+        assert_eq!(retry_after_secs(Duration::from_nanos(1)), 1);
+        assert_eq!(retry_after_secs(Duration::from_millis(1000)), 1);
+        assert_eq!(retry_after_secs(Duration::from_millis(1001)), 2);
+        assert_eq!(retry_after_secs(Duration::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn tiny_rates_wait_as_long_as_a_duration_goes() {
+        let now = Instant::now();
+        let mut bucket = Bucket::full(1.0, now);
+        assert_eq!(bucket.take(now, 1e-300, 1.0), Ok(()));
+        assert_eq!(bucket.take(now, 1e-300, 1.0), Err(Duration::MAX));
+
+        let limiter = RateLimiter::new(&RateLimitConfig { per_second: f64::MIN_POSITIVE, burst: 1.0 });
+        let ip = IpAddr::from([192, 0, 2, 1]);
+        assert_eq!(limiter.check(ip), Ok(()));
+        assert_eq!(limiter.check(ip), Err(Duration::MAX));
+    }
+
+    #[test]
+    fn idle_buckets_are_dropped_once_a_prune_interval() {
+        let start = Instant::now();
+        let mut buckets = Buckets::new(start);
+        buckets.get(1, start, 5.0).take(start, 1.0, 5.0).unwrap();
+        let idle = start + IDLE_BUCKET_TTL;
+        buckets.get(2, idle, 5.0);
+        assert!(!buckets.buckets.contains_key(&1));
+        assert_eq!(buckets.last_pruned, idle);
+
+        // Not again until the interval is up
+        let soon = idle + PRUNE_INTERVAL / 2;
+        buckets.get(3, soon, 5.0);
+        assert_eq!((buckets.buckets.len(), buckets.last_pruned), (2, idle));
+    }
+}
@@ -0,0 +1,122 @@
+//! Labeled links between contacts, like "spouse of" or "works with". A relationship is stored
+//! once, from `contact_id` to `related_id`, and shows up on both contacts' pages: "Spouse of
+//! Bob" on Alice's, "Alice (spouse of this contact)" on Bob's.
+
+use serde::Serialize;
+use tracing::instrument;
+
+#[cfg(feature = "postgres")]
+use crate::postgres;
+use crate::{
+    db::{Db, shapes},
+    names::NameFormat,
+    preferences,
+};
+
+/// Longest label accepted, in characters.
+pub const MAX_LABEL_LEN: usize = 50;
+
+/// A relationship as seen from one of its two contacts.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, sqlx::FromRow)]
+pub struct Relationship {
+    pub id: i64,
+    pub label: String,
+    /// The contact on the other end.
+    pub other_id: i64,
+    pub other_first_name: String,
+    pub other_last_name: String,
+    /// `true` if the label describes the other contact ("Alice (spouse of this contact)"),
+    /// `false` if it describes this one ("Spouse of Bob").
+    pub incoming: bool,
+}
+
+impl Relationship {
+    /// The other contact's name in the current request's preferred format.
+    pub fn other_name(&self) -> String {
+        preferences::current().name(&self.other_first_name, &self.other_last_name)
+    }
+
+    /// Orders relationships by label, then by the other contact's name the way `format`
+    /// reads.
+    pub fn sort(relationships: &mut [Relationship], format: NameFormat) {
+        relationships.sort_by(|a, b| {
+            a.label
+                .cmp(&b.label)
+                .then_with(|| {
+                    format.compare(
+                        (&a.other_first_name, &a.other_last_name),
+                        (&b.other_first_name, &b.other_last_name),
+                    )
+                })
+                .then(a.id.cmp(&b.id))
+        });
+    }
+
+    /// The relationships of contact `id`, both the ones it was given and the ones pointing at
+    /// it, by label and then last name. See `sort` for the order they are shown in.
+    #[instrument(name = "relationships.for_contact", skip_all, fields(binds = %shapes(&[&id])))]
+    pub async fn for_contact(db: &Db, id: i64) -> Result<Vec<Relationship>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    Relationship,
+                    r#"SELECT r.id AS "id!", r.label, c.id AS "other_id!",
+                        c.first_name AS other_first_name, c.last_name AS other_last_name,
+                        r.contact_id <> ?1 AS "incoming!: bool"
+                    FROM contact_relationships r
+                    JOIN contacts c ON c.id = CASE WHEN r.contact_id = ?1 THEN r.related_id ELSE r.contact_id END
+                    WHERE r.contact_id = ?1 OR r.related_id = ?1
+                    ORDER BY r.label, c.last_name, c.first_name, r.id"#,
+                    id,
+                )
+                .fetch_all(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::contact_relationships(pool, id).await,
+        }
+    }
+
+    /// Links `contact_id` to `related_id` as `label`. Fails with a unique violation if they
+    /// already have that exact relationship.
+    #[instrument(name = "relationships.add", skip_all, fields(binds = %shapes(&[&contact_id, &related_id, &label])))]
+    pub async fn add(db: &Db, contact_id: i64, related_id: i64, label: &str) -> Result<i64, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_scalar!(
+                    r#"INSERT INTO contact_relationships (contact_id, related_id, label)
+                    VALUES (?, ?, ?)
+                    RETURNING id AS "id!""#,
+                    contact_id,
+                    related_id,
+                    label,
+                )
+                .fetch_one(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::add_relationship(pool, contact_id, related_id, label).await,
+        }
+    }
+
+    /// Removes relationship `id` if contact `contact_id` is on either end of it. Returns whether
+    /// there was one.
+    #[instrument(name = "relationships.remove", skip_all, fields(binds = %shapes(&[&id, &contact_id])))]
+    pub async fn remove(db: &Db, contact_id: i64, id: i64) -> Result<bool, sqlx::Error> {
+        let removed = match db {
+            Db::Sqlite(pool) => {
+                sqlx::query!(
+                    "DELETE FROM contact_relationships WHERE id = ?1 AND (contact_id = ?2 OR related_id = ?2)",
+                    id,
+                    contact_id,
+                )
+                .execute(pool)
+                .await?
+                .rows_affected()
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::remove_relationship(pool, contact_id, id).await?,
+        };
+        Ok(removed > 0)
+    }
+}
@@ -0,0 +1,156 @@
+//! Reloading the config while the server runs, on SIGHUP (`kill -HUP <pid>`), for the
+//! settings that can change without a restart and so without dropping the database
+//! connections:
+//! - `log_level`, unless `RUST_LOG` is set, which wins over it
+//! - `rate_limit`
+//! - `page_size` and `timezone`
+//! - `gravatar.enabled` and `api.require_key`
+//!
+//! The config is loaded again the way it was on startup: the file, then the environment and
+//! the flags the server was started with. The rest of it, like the database, the port or the
+//! job schedules, is only read on startup, so changes to it are logged as needing a restart
+//! and left out. A config that doesn't load changes nothing.
+
+use std::sync::{Arc, RwLock};
+
+use tracing::{error, info, warn};
+
+use crate::{
+    config::{Cli, Config},
+    logging,
+    rate_limit::RateLimiter,
+};
+
+/// The config handlers read, which a reload swaps for a new one. Clones share it.
+#[derive(Clone)]
+pub struct LiveConfig {
+    current: Arc<RwLock<Arc<Config>>>,
+}
+
+impl LiveConfig {
+    pub fn new(config: Config) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(config))),
+        }
+    }
+
+    /// The config as of now. A handler that reads several settings should get it once, so a
+    /// reload can't change them halfway through.
+    pub fn get(&self) -> Arc<Config> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Takes the settings of `new` that can change at runtime, see the module docs.
+    pub fn update(&self, new: &Config) {
+        let mut current = self.current.write().unwrap();
+        *current = Arc::new(reloaded(&current, new));
+    }
+}
+
+/// `config` with the settings of `new` that can change at runtime.
+fn reloaded(config: &Config, new: &Config) -> Config {
+    Config {
+        rate_limit: new.rate_limit.clone(),
+        api: new.api.clone(),
+        gravatar: new.gravatar.clone(),
+        page_size: new.page_size,
+        log_level: new.log_level.clone(),
+        timezone: new.timezone,
+        ..config.clone()
+    }
+}
+
+/// The settings that changed from `old` to `new` but are only read on startup, by section.
+fn needs_restart(old: &Config, new: &Config) -> Vec<&'static str> {
+    // Spelled out, so a new section can't be left out by mistake
+    let Config {
+        server,
+        database,
+        rate_limit: _,
+        email,
+        ldap,
+        attachments,
+        trash,
+        api: _,
+        search,
+        gravatar: _,
+        error_reporting,
+        tenancy,
+        page_size: _,
+        log_level: _,
+        log_format,
+        timezone: _,
+    } = new;
+    [
+        ("server", old.server != *server),
+        ("database", old.database != *database),
+        ("email", old.email != *email),
+        ("ldap", old.ldap != *ldap),
+        ("attachments", old.attachments != *attachments),
+        ("trash", old.trash != *trash),
+        ("search", old.search != *search),
+        ("error_reporting", old.error_reporting != *error_reporting),
+        ("tenancy", old.tenancy != *tenancy),
+        ("log_format", old.log_format != *log_format),
+    ]
+    .into_iter()
+    .filter_map(|(section, changed)| changed.then_some(section))
+    .collect()
+}
+
+/// Loads the config again from `cli` and applies it to the process and to `configs`, the
+/// main database's first.
+fn reload(cli: &Cli, rate_limiter: &RateLimiter, configs: &[LiveConfig]) {
+    let new = match Config::load(cli) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("Config not reloaded: {err:#}");
+            return;
+        }
+    };
+    if let Err(err) = logging::set_level(&new.log_level) {
+        error!("Config not reloaded, log_level {:?} is invalid: {err}", new.log_level);
+        return;
+    }
+    let Some(old) = configs.first().map(LiveConfig::get) else {
+        return;
+    };
+    rate_limiter.set_limits(&new.rate_limit);
+    for config in configs {
+        config.update(&new);
+    }
+
+    let restart = needs_restart(&old, &new);
+    if restart.is_empty() {
+        info!("Config reloaded");
+    } else {
+        warn!("Config reloaded, changes to {} take effect after a restart", restart.join(", "));
+    }
+}
+
+/// Reloads the config on every SIGHUP until the process exits, see `reload`. `configs` is
+/// asked for the configs to update at the time, as tenants are opened along the way. Does
+/// nothing outside of Unix, which has no SIGHUP.
+pub fn spawn_on_hangup<F>(cli: Cli, rate_limiter: RateLimiter, configs: F)
+where
+    F: Fn() -> Vec<LiveConfig> + Send + 'static,
+{
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(err) => {
+                error!("Failed to install SIGHUP handler, the config can't be reloaded: {err}");
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            info!("SIGHUP received, reloading the config");
+            reload(&cli, &rate_limiter, &configs());
+        }
+    });
+    #[cfg(not(unix))]
+    let _ = (cli, rate_limiter, configs);
+}
@@ -0,0 +1,281 @@
+use std::{pin::Pin, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use tokio_stream::Stream;
+
+use crate::{
+    contacts::{BatchFailure, Company, Contact, ContactCursor, ContactError, ContactFilter, ContactOrder, ContactPage, ContactWrite, LetterCount, NewContact},
+    dates::ContactDate,
+    db::{Db, retry_busy},
+    relationships::Relationship,
+    stats::ContactStats,
+};
+
+/// Contact storage as seen by the handlers and background tasks. `AppState` holds it as a
+/// `SharedContactRepository`, so a different store can be swapped in without touching them.
+#[async_trait]
+pub trait ContactRepository: Send + Sync {
+    /// Stores a new contact, created (and last updated) at `now`. Fails with
+    /// `ContactError::Taken` if another contact has its email or phone number.
+    async fn create(&self, new: NewContact, now: DateTime<Utc>) -> Result<Contact, ContactError>;
+    /// Stores the new contacts together, see `Contact::create_many`. A contact refused because
+    /// another one has its email or phone number gets that field back instead.
+    async fn create_many(
+        &self,
+        new: &[NewContact],
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Result<Contact, &'static str>>, sqlx::Error>;
+    /// What `create_many` would do with the new contacts, without storing any, see
+    /// `Contact::check_many`: the field another contact has for each one that would be refused.
+    async fn check_many(&self, new: &[NewContact], now: DateTime<Utc>) -> Result<Vec<Option<&'static str>>, sqlx::Error>;
+    /// Makes all of the changes or none of them, see `Contact::apply_batch`.
+    async fn apply_batch(
+        &self,
+        writes: &[ContactWrite],
+        now: DateTime<Utc>,
+    ) -> Result<Result<Vec<Contact>, BatchFailure>, sqlx::Error>;
+    /// Saves the contact's fields and sets its `updated_at` to `now`, or a millisecond past the
+    /// old one if that isn't later (see `Contact::next_version`). Fails like `create`, or with
+    /// `ContactError::NotFound` if the contact is gone.
+    async fn update(&self, contact: &Contact, now: DateTime<Utc>) -> Result<(), ContactError>;
+    /// `update`, if the stored contact still has `contact.updated_at`. Fails with
+    /// `ContactError::Changed` if it was changed or deleted since, see
+    /// `Contact::update_unchanged`.
+    async fn update_unchanged(&self, contact: &Contact, now: DateTime<Utc>) -> Result<(), ContactError>;
+    async fn delete(&self, id: i64) -> Result<(), ContactError>;
+    /// `delete`, if the stored contact still has `contact.updated_at`, like `update_unchanged`.
+    async fn delete_unchanged(&self, contact: &Contact) -> Result<(), ContactError>;
+    /// Stores a deleted contact again, id and all, see `Contact::restore`. Gives back the field
+    /// another contact has taken since instead, if any.
+    async fn restore(&self, contact: &Contact) -> Result<Result<(), &'static str>, sqlx::Error>;
+    /// One page of contacts, in `sort` order. Pages start at 1. With an `after` cursor the
+    /// page starts right after that contact instead, and `page` is ignored.
+    async fn get_all(
+        &self,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Result<Vec<Contact>, sqlx::Error>;
+    /// Every contact, ordered by id.
+    async fn all(&self) -> Result<Vec<Contact>, sqlx::Error>;
+    /// Every contact, ordered by id, one at a time as they are read. For exports, which
+    /// shouldn't have to hold the whole table in memory. Not retried when the database is busy.
+    fn stream_all(&self) -> ContactStream<'_>;
+    /// The `limit` most recently created contacts, newest first.
+    async fn newest(&self, limit: i64) -> Result<Vec<Contact>, sqlx::Error>;
+    /// Every contact with a company, grouped by it, companies in alphabetical order.
+    async fn companies(&self) -> Result<Vec<Company>, sqlx::Error>;
+    /// Fails with `ContactError::NotFound` if there is no such contact.
+    async fn find_by_id(&self, id: i64) -> Result<Contact, ContactError>;
+    /// The contact with this exact email, if there is one.
+    async fn find_by_email(&self, email: &str) -> Result<Option<Contact>, sqlx::Error>;
+    /// One page of contacts matching `filter` in `sort` order, paged like `get_all`. The free
+    /// text matches names, companies, job titles, emails or phone numbers containing it,
+    /// ignoring case and accents (see `fold`), and phone numbers with the same digits.
+    async fn search(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Result<Vec<Contact>, sqlx::Error>;
+    /// `search` and `count` together, see `Contact::search_page`.
+    async fn search_page(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Result<ContactPage, sqlx::Error>;
+    /// Number of contacts matching `filter`, all of them for an empty one.
+    async fn count(&self, filter: &ContactFilter) -> Result<i64, sqlx::Error>;
+    /// Number of contacts matching `filter` per last name initial, for every letter of the
+    /// A–Z bar. The filter's own initial is ignored.
+    async fn letter_counts(&self, filter: &ContactFilter) -> Result<Vec<LetterCount>, sqlx::Error>;
+    /// `true` if a contact already uses this email. `exclude_id` leaves out the contact
+    /// being edited, so its own email doesn't count.
+    async fn email_exists(&self, email: &str, exclude_id: Option<i64>) -> Result<bool, sqlx::Error>;
+    /// `true` if a contact already uses this phone number, not counting `exclude_id`.
+    async fn phone_exists(&self, phone_number: &str, exclude_id: Option<i64>) -> Result<bool, sqlx::Error>;
+    /// Figures for the dashboard, with the months up to `now`.
+    async fn stats(&self, now: DateTime<Utc>) -> Result<ContactStats, sqlx::Error>;
+    /// Contact `id`'s relationships in both directions, see `Relationship`.
+    async fn relationships(&self, id: i64) -> Result<Vec<Relationship>, sqlx::Error>;
+    /// Records that `contact_id` is `label` `related_id`. Returns the new relationship's id.
+    async fn add_relationship(&self, contact_id: i64, related_id: i64, label: &str) -> Result<i64, sqlx::Error>;
+    /// Removes relationship `id`, as long as contact `contact_id` is on one end of it. Returns
+    /// whether anything was removed.
+    async fn remove_relationship(&self, contact_id: i64, id: i64) -> Result<bool, sqlx::Error>;
+    /// Contact `id`'s dates, see `ContactDate`.
+    async fn dates(&self, id: i64) -> Result<Vec<ContactDate>, sqlx::Error>;
+    /// Every contact's dates, to pick the upcoming ones from.
+    async fn all_dates(&self) -> Result<Vec<ContactDate>, sqlx::Error>;
+    /// Gives contact `contact_id` the date `date` as `label`, every year if `yearly`. Returns
+    /// the new date's id.
+    async fn add_date(&self, contact_id: i64, label: &str, date: NaiveDate, yearly: bool) -> Result<i64, sqlx::Error>;
+    /// Removes date `id`, as long as it is contact `contact_id`'s. Returns whether anything was
+    /// removed.
+    async fn remove_date(&self, contact_id: i64, id: i64) -> Result<bool, sqlx::Error>;
+}
+
+pub type SharedContactRepository = Arc<dyn ContactRepository>;
+
+/// Contacts read one at a time, see `ContactRepository::stream_all`.
+pub type ContactStream<'a> = Pin<Box<dyn Stream<Item = Result<Contact, sqlx::Error>> + Send + 'a>>;
+
+/// The SQL backends, using the queries on `Contact`. Every call is retried a few times if
+/// SQLite reports the database as busy, see `retry_busy`.
+#[async_trait]
+impl ContactRepository for Db {
+    async fn create(&self, new: NewContact, now: DateTime<Utc>) -> Result<Contact, ContactError> {
+        retry_busy(|| Contact::create(self, new.clone(), now)).await
+    }
+
+    async fn create_many(
+        &self,
+        new: &[NewContact],
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Result<Contact, &'static str>>, sqlx::Error> {
+        retry_busy(|| Contact::create_many(self, new, now)).await
+    }
+
+    async fn check_many(&self, new: &[NewContact], now: DateTime<Utc>) -> Result<Vec<Option<&'static str>>, sqlx::Error> {
+        retry_busy(|| Contact::check_many(self, new, now)).await
+    }
+
+    async fn apply_batch(
+        &self,
+        writes: &[ContactWrite],
+        now: DateTime<Utc>,
+    ) -> Result<Result<Vec<Contact>, BatchFailure>, sqlx::Error> {
+        retry_busy(|| Contact::apply_batch(self, writes, now)).await
+    }
+
+    async fn update(&self, contact: &Contact, now: DateTime<Utc>) -> Result<(), ContactError> {
+        retry_busy(|| contact.update(self, now)).await
+    }
+
+    async fn update_unchanged(&self, contact: &Contact, now: DateTime<Utc>) -> Result<(), ContactError> {
+        retry_busy(|| contact.update_unchanged(self, now)).await
+    }
+
+    async fn delete(&self, id: i64) -> Result<(), ContactError> {
+        retry_busy(|| Contact::delete(self, id)).await
+    }
+
+    async fn delete_unchanged(&self, contact: &Contact) -> Result<(), ContactError> {
+        retry_busy(|| Contact::delete_unchanged(self, contact)).await
+    }
+
+    async fn restore(&self, contact: &Contact) -> Result<Result<(), &'static str>, sqlx::Error> {
+        retry_busy(|| Contact::restore(self, contact)).await
+    }
+
+    async fn get_all(
+        &self,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Result<Vec<Contact>, sqlx::Error> {
+        retry_busy(|| Contact::get_all(self, page, per_page, sort, after)).await
+    }
+
+    async fn all(&self) -> Result<Vec<Contact>, sqlx::Error> {
+        retry_busy(|| Contact::all(self)).await
+    }
+
+    fn stream_all(&self) -> ContactStream<'_> {
+        Contact::stream_all(self)
+    }
+
+    async fn newest(&self, limit: i64) -> Result<Vec<Contact>, sqlx::Error> {
+        retry_busy(|| Contact::newest(self, limit)).await
+    }
+
+    async fn companies(&self) -> Result<Vec<Company>, sqlx::Error> {
+        retry_busy(|| Contact::with_company(self)).await.map(Company::group)
+    }
+
+    async fn find_by_id(&self, id: i64) -> Result<Contact, ContactError> {
+        retry_busy(|| Contact::find_by_id(self, id)).await
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<Contact>, sqlx::Error> {
+        retry_busy(|| Contact::find_by_email(self, email)).await
+    }
+
+    async fn search(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Result<Vec<Contact>, sqlx::Error> {
+        retry_busy(|| Contact::search(self, filter, page, per_page, sort, after)).await
+    }
+
+    async fn search_page(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Result<ContactPage, sqlx::Error> {
+        retry_busy(|| Contact::search_page(self, filter, page, per_page, sort, after)).await
+    }
+
+    async fn count(&self, filter: &ContactFilter) -> Result<i64, sqlx::Error> {
+        retry_busy(|| Contact::count(self, filter)).await
+    }
+
+    async fn letter_counts(&self, filter: &ContactFilter) -> Result<Vec<LetterCount>, sqlx::Error> {
+        retry_busy(|| Contact::letter_counts(self, filter)).await
+    }
+
+    async fn email_exists(&self, email: &str, exclude_id: Option<i64>) -> Result<bool, sqlx::Error> {
+        retry_busy(|| Contact::validate_email(self, email, exclude_id)).await
+    }
+
+    async fn phone_exists(&self, phone_number: &str, exclude_id: Option<i64>) -> Result<bool, sqlx::Error> {
+        retry_busy(|| Contact::validate_phone(self, phone_number, exclude_id)).await
+    }
+
+    async fn stats(&self, now: DateTime<Utc>) -> Result<ContactStats, sqlx::Error> {
+        retry_busy(|| ContactStats::load(self, now)).await
+    }
+
+    async fn relationships(&self, id: i64) -> Result<Vec<Relationship>, sqlx::Error> {
+        retry_busy(|| Relationship::for_contact(self, id)).await
+    }
+
+    async fn add_relationship(&self, contact_id: i64, related_id: i64, label: &str) -> Result<i64, sqlx::Error> {
+        retry_busy(|| Relationship::add(self, contact_id, related_id, label)).await
+    }
+
+    async fn remove_relationship(&self, contact_id: i64, id: i64) -> Result<bool, sqlx::Error> {
+        retry_busy(|| Relationship::remove(self, contact_id, id)).await
+    }
+
+    async fn dates(&self, id: i64) -> Result<Vec<ContactDate>, sqlx::Error> {
+        retry_busy(|| ContactDate::for_contact(self, id)).await
+    }
+
+    async fn all_dates(&self) -> Result<Vec<ContactDate>, sqlx::Error> {
+        retry_busy(|| ContactDate::all(self)).await
+    }
+
+    async fn add_date(&self, contact_id: i64, label: &str, date: NaiveDate, yearly: bool) -> Result<i64, sqlx::Error> {
+        retry_busy(|| ContactDate::add(self, contact_id, label, date, yearly)).await
+    }
+
+    async fn remove_date(&self, contact_id: i64, id: i64) -> Result<bool, sqlx::Error> {
+        retry_busy(|| ContactDate::remove(self, contact_id, id)).await
+    }
+}
@@ -0,0 +1,261 @@
+use std::{cmp::Ordering, sync::Arc};
+
+use async_trait::async_trait;
+
+use crate::{
+    contacts::{Contact, ContactCursor, ContactFilter, ContactOrder, ContactPage},
+    fold,
+    repository::SharedContactRepository,
+};
+
+/// Lowest similarity, from 0 to 1, a search word can have to a contact's closest word for
+/// `FuzzySearch` to count it as found. A typo or swapped pair of letters in a five letter word
+/// is 0.8.
+const MIN_SIMILARITY: f64 = 0.7;
+
+/// Contact search as used by the list page. The default is `DatabaseSearch`, the database's
+/// own name matching; `FuzzySearch` and `MeilisearchSearch` add typo tolerance, the latter
+/// with a search engine's relevance ranking. Errors are `anyhow` since an engine doesn't fail
+/// with database errors.
+#[async_trait]
+pub trait SearchBackend: Send + Sync {
+    /// One page of contacts matching `filter`, in `sort` order. Pages start at 1, or right
+    /// after the `after` cursor if there is one. Besides the free text, the filter's dates and
+    /// email domain have to match exactly.
+    async fn search(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> anyhow::Result<Vec<Contact>>;
+    /// Total number of contacts matching `filter`, for the pager.
+    async fn count(&self, filter: &ContactFilter) -> anyhow::Result<i64>;
+    /// `search` and `count` together. Backends that can get both at once override this, like
+    /// `DatabaseSearch`, whose first pages come with their total (see `Contact::search_page`).
+    async fn search_page(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> anyhow::Result<ContactPage> {
+        let contacts = self.search(filter, page, per_page, sort, after).await?;
+        let total = self.count(filter).await?;
+        Ok(ContactPage { contacts, total })
+    }
+}
+
+pub type SharedSearchBackend = Arc<dyn SearchBackend>;
+
+/// Searches with the contact repository's `LIKE` match on name, company, job title, email and
+/// phone number. Always in sync since it reads the same table, but no ranking and no typo
+/// tolerance.
+pub struct DatabaseSearch {
+    contacts: SharedContactRepository,
+}
+
+impl DatabaseSearch {
+    pub fn new(contacts: SharedContactRepository) -> Self {
+        Self { contacts }
+    }
+}
+
+#[async_trait]
+impl SearchBackend for DatabaseSearch {
+    async fn search(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> anyhow::Result<Vec<Contact>> {
+        Ok(self.contacts.search(filter, page, per_page, sort, after).await?)
+    }
+
+    async fn count(&self, filter: &ContactFilter) -> anyhow::Result<i64> {
+        Ok(self.contacts.count(filter).await?)
+    }
+
+    async fn search_page(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> anyhow::Result<ContactPage> {
+        Ok(self.contacts.search_page(filter, page, per_page, sort, after).await?)
+    }
+}
+
+/// `DatabaseSearch` that also finds contacts with typos in the search: `Jhon Smiht` finds
+/// `John Smith`. Turned on with `search.fuzzy` in the config.
+///
+/// Every search word is compared with the contact's words (of its name, company, job title and
+/// email) by edit distance, and the contacts whose words are all close enough come back ranked
+/// by how close, ahead of the requested sort. Exact matches rank first. The contacts are
+/// compared in memory, so this reads every contact the rest of the filter allows on each
+/// search; fine for an address book, not for a CRM.
+pub struct FuzzySearch {
+    contacts: SharedContactRepository,
+}
+
+impl FuzzySearch {
+    pub fn new(contacts: SharedContactRepository) -> Self {
+        Self { contacts }
+    }
+
+    /// The contacts matching `filter`, closest first, then in `sort` order.
+    async fn ranked(&self, filter: &ContactFilter, text: &str, sort: ContactOrder) -> anyhow::Result<Vec<Contact>> {
+        let rest = ContactFilter { text: None, ..filter.clone() };
+        let folded = fold::fold(text);
+        let words = words(&folded);
+        let mut scored: Vec<(f64, Contact)> = self
+            .contacts
+            .all()
+            .await?
+            .into_iter()
+            .filter(|contact| rest.matches(contact))
+            .filter_map(|contact| {
+                let score = if filter.matches(&contact) { Some(1.0) } else { similarity(&words, &contact) };
+                score.map(|score| (score, contact))
+            })
+            .collect();
+        scored.sort_by(|(a_score, a), (b_score, b)| {
+            b_score
+                .partial_cmp(a_score)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| sort.compare(a, b))
+        });
+        Ok(scored.into_iter().map(|(_, contact)| contact).collect())
+    }
+}
+
+#[async_trait]
+impl SearchBackend for FuzzySearch {
+    async fn search(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> anyhow::Result<Vec<Contact>> {
+        let Some(text) = &filter.text else {
+            return Ok(self.contacts.search(filter, page, per_page, sort, after).await?);
+        };
+        let ranked = self.ranked(filter, text, sort).await?;
+        Ok(page_of(ranked, page, per_page, after))
+    }
+
+    async fn count(&self, filter: &ContactFilter) -> anyhow::Result<i64> {
+        match &filter.text {
+            Some(text) => Ok(self.ranked(filter, text, ContactOrder::default()).await?.len() as i64),
+            None => Ok(self.contacts.count(filter).await?),
+        }
+    }
+
+    /// Ranks the contacts once for both the page and the total.
+    async fn search_page(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> anyhow::Result<ContactPage> {
+        let Some(text) = &filter.text else {
+            return Ok(self.contacts.search_page(filter, page, per_page, sort, after).await?);
+        };
+        let ranked = self.ranked(filter, text, sort).await?;
+        let total = ranked.len() as i64;
+        Ok(ContactPage { contacts: page_of(ranked, page, per_page, after), total })
+    }
+}
+
+/// The page of `ranked` contacts after the `after` cursor, or numbered `page`. The ranking
+/// isn't the sort order, so a cursor is found by its contact instead of compared with the sort
+/// keys.
+fn page_of(ranked: Vec<Contact>, page: i64, per_page: i64, after: Option<&ContactCursor>) -> Vec<Contact> {
+    let skip = match after {
+        Some(cursor) => ranked.iter().position(|c| c.id == cursor.id).map_or(ranked.len(), |i| i + 1),
+        None => ((page - 1) * per_page).max(0) as usize,
+    };
+    ranked.into_iter().skip(skip).take(per_page.max(0) as usize).collect()
+}
+
+/// Folded text split into words at anything that isn't a letter or digit, so an email address
+/// is its parts.
+fn words(folded: &str) -> Vec<&str> {
+    folded.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).collect()
+}
+
+/// How close the search `words` are to the contact, the average of each word's similarity to
+/// its closest word in the contact. `None` if any of them isn't close to anything.
+fn similarity(words: &[&str], contact: &Contact) -> Option<f64> {
+    let search_text = contact.search_text();
+    let contact_words = self::words(&search_text);
+    let mut total = 0.0;
+    for word in words {
+        let best = contact_words
+            .iter()
+            .map(|candidate| word_similarity(word, candidate))
+            .fold(0.0, f64::max);
+        if best < MIN_SIMILARITY {
+            return None;
+        }
+        total += best;
+    }
+    (!words.is_empty()).then(|| total / words.len() as f64)
+}
+
+/// 1 if `candidate` contains `word`, like the database search. Otherwise one minus the edit
+/// distance over the longer word's length, comparing with just the start of `candidate` too,
+/// so `smiht` is as close to `smithson` as it is to `smith`. Numbers have to match exactly, one
+/// digit off is another phone number.
+fn word_similarity(word: &str, candidate: &str) -> f64 {
+    if candidate.contains(word) {
+        return 1.0;
+    }
+    if !word.chars().any(char::is_alphabetic) {
+        return 0.0;
+    }
+    let word: Vec<char> = word.chars().collect();
+    let candidate: Vec<char> = candidate.chars().collect();
+    let similarity = |other: &[char]| {
+        let longest = word.len().max(other.len());
+        1.0 - edit_distance(&word, other) as f64 / longest as f64
+    };
+    let whole = similarity(&candidate);
+    match candidate.get(..word.len()) {
+        Some(start) if start.len() < candidate.len() => whole.max(similarity(start)),
+        _ => whole,
+    }
+}
+
+/// Optimal string alignment distance: the number of inserted, deleted or replaced characters,
+/// or swapped neighbours, that turn `a` into `b`. Levenshtein distance plus transpositions,
+/// which are the most common typo.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    // Three rows of the distance matrix: two rows back, the previous one and the current one
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1).min(current[j - 1] + 1).min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
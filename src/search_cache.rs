@@ -0,0 +1,363 @@
+//! In-process cache of the contact list's counts and searches, so active search, which asks
+//! again with every keystroke, and paging back and forth don't run the same `COUNT` and `LIKE`
+//! queries over and over on a big table.
+//!
+//! `CachedContactRepository` wraps the repository the app works with and keeps the results of
+//! `count`, `letter_counts` and `search` for `search.cache_ttl_secs`, keyed on the filter the
+//! way the queries see it: with its text folded (see `fold`), so `José` and `jose` share an
+//! entry. `search_page` is a cached `search` and `count`, so every page of a filter shares
+//! one count; when the count isn't cached yet, a first page gets both from the repository's
+//! one-query `search_page`. Every contact change made through it empties the cache. Changes
+//! made by another process, like `webone import` next to a running server, show up once the
+//! entries expire.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use tracing::debug;
+
+use crate::{
+    contacts::{BatchFailure, Company, Contact, ContactCursor, ContactError, ContactFilter, ContactOrder, ContactPage, ContactWrite, LetterCount, NewContact},
+    dates::ContactDate,
+    fold,
+    relationships::Relationship,
+    repository::{ContactRepository, ContactStream, SharedContactRepository},
+    stats::ContactStats,
+};
+
+/// Most results kept at once. Past it, expired entries are dropped, and if that isn't enough
+/// the whole cache, which is simpler than tracking what was used last and rarely happens.
+const MAX_ENTRIES: usize = 1000;
+
+/// A filter the way the queries see it: the text folded for the `search_text` match, and the
+/// phone number pattern it makes, if any. Filters that only differ in case or accents are the
+/// same query.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Query {
+    filter: ContactFilter,
+    phone_pattern: Option<String>,
+}
+
+impl Query {
+    fn of(filter: &ContactFilter) -> Self {
+        Self {
+            filter: ContactFilter {
+                text: filter.text.as_deref().map(fold::fold),
+                ..filter.clone()
+            },
+            phone_pattern: filter.phone_pattern(),
+        }
+    }
+}
+
+/// What a cached result is the answer to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Count(Query),
+    LetterCounts(Query),
+    Search(Page),
+}
+
+/// The page of a search, see `ContactRepository::search`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Page {
+    query: Query,
+    page: i64,
+    per_page: i64,
+    sort: ContactOrder,
+    after: Option<ContactCursor>,
+}
+
+impl Page {
+    fn of(filter: &ContactFilter, page: i64, per_page: i64, sort: ContactOrder, after: Option<&ContactCursor>) -> Self {
+        Self {
+            query: Query::of(filter),
+            page,
+            per_page,
+            sort,
+            after: after.cloned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Count(i64),
+    LetterCounts(Vec<LetterCount>),
+    Contacts(Vec<Contact>),
+}
+
+struct Entry {
+    value: Value,
+    expires: Instant,
+}
+
+/// A `ContactRepository` that caches the list's counts and searches of the one it wraps, see
+/// the module docs. Everything else is passed through as is.
+pub struct CachedContactRepository {
+    inner: SharedContactRepository,
+    ttl: Duration,
+    entries: Mutex<HashMap<Key, Entry>>,
+    /// Bumped by every change, so a result read while a change was being made isn't stored
+    /// after the change emptied the cache.
+    generation: AtomicU64,
+}
+
+impl CachedContactRepository {
+    pub fn new(inner: SharedContactRepository, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            entries: Mutex::default(),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    fn get(&self, key: &Key) -> Option<Value> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|entry| entry.expires > Instant::now())
+            .map(|entry| entry.value.clone())
+    }
+
+    /// Keeps `value` for `key`, unless the contacts changed since `generation` when it was read.
+    fn put(&self, key: Key, value: Value, generation: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        if self.generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        let now = Instant::now();
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, entry| entry.expires > now);
+            if entries.len() >= MAX_ENTRIES {
+                entries.clear();
+            }
+        }
+        entries.insert(key, Entry { value, expires: now + self.ttl });
+    }
+
+    /// Forgets every result, after a contact was created, changed or deleted.
+    fn invalidate(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        if !entries.is_empty() {
+            debug!("Contacts changed, dropping {} cached searches", entries.len());
+            entries.clear();
+        }
+    }
+}
+
+#[async_trait]
+impl ContactRepository for CachedContactRepository {
+    async fn create(&self, new: NewContact, now: DateTime<Utc>) -> Result<Contact, ContactError> {
+        let result = self.inner.create(new, now).await;
+        self.invalidate();
+        result
+    }
+
+    async fn create_many(
+        &self,
+        new: &[NewContact],
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Result<Contact, &'static str>>, sqlx::Error> {
+        let result = self.inner.create_many(new, now).await;
+        self.invalidate();
+        result
+    }
+
+    async fn check_many(&self, new: &[NewContact], now: DateTime<Utc>) -> Result<Vec<Option<&'static str>>, sqlx::Error> {
+        self.inner.check_many(new, now).await
+    }
+
+    async fn apply_batch(
+        &self,
+        writes: &[ContactWrite],
+        now: DateTime<Utc>,
+    ) -> Result<Result<Vec<Contact>, BatchFailure>, sqlx::Error> {
+        let result = self.inner.apply_batch(writes, now).await;
+        self.invalidate();
+        result
+    }
+
+    async fn update(&self, contact: &Contact, now: DateTime<Utc>) -> Result<(), ContactError> {
+        let result = self.inner.update(contact, now).await;
+        self.invalidate();
+        result
+    }
+
+    async fn update_unchanged(&self, contact: &Contact, now: DateTime<Utc>) -> Result<(), ContactError> {
+        let result = self.inner.update_unchanged(contact, now).await;
+        self.invalidate();
+        result
+    }
+
+    async fn delete(&self, id: i64) -> Result<(), ContactError> {
+        let result = self.inner.delete(id).await;
+        self.invalidate();
+        result
+    }
+
+    async fn delete_unchanged(&self, contact: &Contact) -> Result<(), ContactError> {
+        let result = self.inner.delete_unchanged(contact).await;
+        self.invalidate();
+        result
+    }
+
+    async fn restore(&self, contact: &Contact) -> Result<Result<(), &'static str>, sqlx::Error> {
+        let result = self.inner.restore(contact).await;
+        self.invalidate();
+        result
+    }
+
+    async fn get_all(
+        &self,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Result<Vec<Contact>, sqlx::Error> {
+        self.inner.get_all(page, per_page, sort, after).await
+    }
+
+    async fn all(&self) -> Result<Vec<Contact>, sqlx::Error> {
+        self.inner.all().await
+    }
+
+    fn stream_all(&self) -> ContactStream<'_> {
+        self.inner.stream_all()
+    }
+
+    async fn newest(&self, limit: i64) -> Result<Vec<Contact>, sqlx::Error> {
+        self.inner.newest(limit).await
+    }
+
+    async fn companies(&self) -> Result<Vec<Company>, sqlx::Error> {
+        self.inner.companies().await
+    }
+
+    async fn find_by_id(&self, id: i64) -> Result<Contact, ContactError> {
+        self.inner.find_by_id(id).await
+    }
+
+    async fn find_by_email(&self, email: &str) -> Result<Option<Contact>, sqlx::Error> {
+        self.inner.find_by_email(email).await
+    }
+
+    async fn search(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Result<Vec<Contact>, sqlx::Error> {
+        let key = Key::Search(Page::of(filter, page, per_page, sort, after));
+        if let Some(Value::Contacts(contacts)) = self.get(&key) {
+            return Ok(contacts);
+        }
+        let generation = self.generation.load(Ordering::SeqCst);
+        let contacts = self.inner.search(filter, page, per_page, sort, after).await?;
+        self.put(key, Value::Contacts(contacts.clone()), generation);
+        Ok(contacts)
+    }
+
+    async fn search_page(
+        &self,
+        filter: &ContactFilter,
+        page: i64,
+        per_page: i64,
+        sort: ContactOrder,
+        after: Option<&ContactCursor>,
+    ) -> Result<ContactPage, sqlx::Error> {
+        // Cached apart, so every page of a filter shares its count. Without one yet, a first
+        // page gets both from one query and keeps them for the next time.
+        let count_key = Key::Count(Query::of(filter));
+        if let Some(Value::Count(total)) = self.get(&count_key) {
+            let contacts = self.search(filter, page, per_page, sort, after).await?;
+            return Ok(ContactPage { contacts, total });
+        }
+        if after.is_some() {
+            let contacts = self.search(filter, page, per_page, sort, after).await?;
+            let total = self.count(filter).await?;
+            return Ok(ContactPage { contacts, total });
+        }
+        let generation = self.generation.load(Ordering::SeqCst);
+        let contacts_page = self.inner.search_page(filter, page, per_page, sort, after).await?;
+        let key = Key::Search(Page::of(filter, page, per_page, sort, after));
+        self.put(key, Value::Contacts(contacts_page.contacts.clone()), generation);
+        self.put(count_key, Value::Count(contacts_page.total), generation);
+        Ok(contacts_page)
+    }
+
+    async fn count(&self, filter: &ContactFilter) -> Result<i64, sqlx::Error> {
+        let key = Key::Count(Query::of(filter));
+        if let Some(Value::Count(count)) = self.get(&key) {
+            return Ok(count);
+        }
+        let generation = self.generation.load(Ordering::SeqCst);
+        let count = self.inner.count(filter).await?;
+        self.put(key, Value::Count(count), generation);
+        Ok(count)
+    }
+
+    async fn letter_counts(&self, filter: &ContactFilter) -> Result<Vec<LetterCount>, sqlx::Error> {
+        // The filter's own initial doesn't change the counts, so all letters share an entry
+        let key = Key::LetterCounts(Query::of(&filter.without_initial()));
+        if let Some(Value::LetterCounts(counts)) = self.get(&key) {
+            return Ok(counts);
+        }
+        let generation = self.generation.load(Ordering::SeqCst);
+        let counts = self.inner.letter_counts(filter).await?;
+        self.put(key, Value::LetterCounts(counts.clone()), generation);
+        Ok(counts)
+    }
+
+    async fn email_exists(&self, email: &str, exclude_id: Option<i64>) -> Result<bool, sqlx::Error> {
+        self.inner.email_exists(email, exclude_id).await
+    }
+
+    async fn phone_exists(&self, phone_number: &str, exclude_id: Option<i64>) -> Result<bool, sqlx::Error> {
+        self.inner.phone_exists(phone_number, exclude_id).await
+    }
+
+    async fn stats(&self, now: DateTime<Utc>) -> Result<ContactStats, sqlx::Error> {
+        self.inner.stats(now).await
+    }
+
+    async fn relationships(&self, id: i64) -> Result<Vec<Relationship>, sqlx::Error> {
+        self.inner.relationships(id).await
+    }
+
+    async fn add_relationship(&self, contact_id: i64, related_id: i64, label: &str) -> Result<i64, sqlx::Error> {
+        self.inner.add_relationship(contact_id, related_id, label).await
+    }
+
+    async fn remove_relationship(&self, contact_id: i64, id: i64) -> Result<bool, sqlx::Error> {
+        self.inner.remove_relationship(contact_id, id).await
+    }
+
+    async fn dates(&self, id: i64) -> Result<Vec<ContactDate>, sqlx::Error> {
+        self.inner.dates(id).await
+    }
+
+    async fn all_dates(&self) -> Result<Vec<ContactDate>, sqlx::Error> {
+        self.inner.all_dates().await
+    }
+
+    async fn add_date(&self, contact_id: i64, label: &str, date: NaiveDate, yearly: bool) -> Result<i64, sqlx::Error> {
+        self.inner.add_date(contact_id, label, date, yearly).await
+    }
+
+    async fn remove_date(&self, contact_id: i64, id: i64) -> Result<bool, sqlx::Error> {
+        self.inner.remove_date(contact_id, id).await
+    }
+}
@@ -0,0 +1,96 @@
+//! Fake contact data for `webone seed`.
+
+use rand::{RngExt, seq::IndexedRandom};
+use tracing::info;
+
+use crate::{
+    clock::Clock,
+    contacts::{ContactError, ContactFilter, NewContact},
+    repository::ContactRepository,
+};
+
+const FIRST_NAMES: &[&str] = &[
+    "Ana", "Ben", "Chloe", "Daniel", "Elena", "Felix", "Grace", "Hugo", "Ivana", "James",
+    "Katarina", "Liam", "Maja", "Noah", "Olivia", "Petar", "Quinn", "Rosa", "Stefan", "Tara",
+    "Uma", "Viktor", "Wendy", "Xavier", "Yara", "Zoran", "Marko", "Sofia", "Lucas", "Mia",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Anderson", "Babic", "Clarke", "Dimitrov", "Evans", "Fischer", "Garcia", "Horvat",
+    "Ivanovic", "Jensen", "Kovac", "Lopez", "Markovic", "Nguyen", "O'Brien", "Petrovic",
+    "Quinn", "Rossi", "Schmidt", "Taylor", "Urban", "Vasquez", "Walker", "Young", "Zeller",
+    "Novak", "Jovanovic", "Müller", "Silva", "Kim",
+];
+
+const COMPANIES: &[&str] = &[
+    "Acme Corp", "Globex", "Initech", "Umbrella", "Stark Industries", "Wayne Enterprises",
+    "Hooli", "Vandelay Industries",
+];
+
+const JOB_TITLES: &[&str] = &[
+    "Engineer", "Designer", "Product Manager", "Sales Lead", "Accountant", "Recruiter",
+    "Support Specialist", "CTO",
+];
+
+const EMAIL_DOMAINS: &[&str] = &["example.com", "example.org", "example.net", "mail.example"];
+
+/// Builds `count` fake contacts. Emails and phone numbers get a running number starting after
+/// `offset` so they're unique, like the database requires.
+pub fn fake_contacts(count: usize, offset: i64) -> Vec<NewContact> {
+    let mut rng = rand::rng();
+    (offset + 1..=offset + count as i64)
+        .map(|n| {
+            let first_name = *FIRST_NAMES.choose(&mut rng).unwrap_or(&"Jane");
+            let last_name = *LAST_NAMES.choose(&mut rng).unwrap_or(&"Doe");
+            let domain = *EMAIL_DOMAINS.choose(&mut rng).unwrap_or(&"example.com");
+            let local: String = format!("{first_name}.{last_name}")
+                .to_lowercase()
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric() || *c == '.')
+                .collect();
+            NewContact {
+                first_name: first_name.into(),
+                last_name: last_name.into(),
+                // Numbered like the emails, phone numbers have to be unique too
+                phone_number: format!("+1 555 {:03} {:04}", 100 + n / 10000 % 900, n % 10000),
+                email: format!("{local}{n}@{domain}"),
+                // About a third of the contacts are left without a company
+                company: if rng.random_range(0..3) == 0 {
+                    String::new()
+                } else {
+                    COMPANIES.choose(&mut rng).unwrap_or(&"Acme Corp").to_string()
+                },
+                job_title: JOB_TITLES.choose(&mut rng).unwrap_or(&"Engineer").to_string(),
+                errors: None,
+                form_token: None,
+            }
+        })
+        .collect()
+}
+
+/// Inserts `count` fake contacts through the repository. Numbering starts after the number of
+/// contacts already there, and numbers another contact already has the email or phone number
+/// of (after deletions, or from seeding before) are skipped, so seeding again just works.
+pub async fn seed(contacts: &dyn ContactRepository, clock: &dyn Clock, count: usize) -> anyhow::Result<()> {
+    let mut offset = contacts.count(&ContactFilter::default()).await?;
+    let (mut created, mut skipped) = (0, 0);
+    while created < count {
+        let batch = fake_contacts(count - created, offset);
+        offset += batch.len() as i64;
+        for contact in batch {
+            match contacts.create(contact, clock.now()).await {
+                Ok(_) => created += 1,
+                Err(ContactError::Taken(_)) => {
+                    skipped += 1;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            }
+            if created % 100 == 0 {
+                info!("Seeded {created} of {count} contacts");
+            }
+        }
+    }
+    info!("Seeded {count} contacts, skipping {skipped} numbers already taken");
+    Ok(())
+}
@@ -0,0 +1,192 @@
+//! Share links: read-only pages at `/share/{token}` with a contact, or the contacts of a
+//! company, for someone who can't use the app.
+//!
+//! Anyone with the link can see what it shares until it expires or is revoked, so the token is
+//! a random UUID. A link to a contact that was deleted, or to a company nobody works at any
+//! more, shows the 404 page until it comes back.
+
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+
+use crate::db::Db;
+#[cfg(feature = "postgres")]
+use crate::postgres;
+
+/// The longest a link can be made to last, in days. Links can also last until revoked.
+pub const MAX_EXPIRY_DAYS: i64 = 365;
+/// Expiries offered by the share form, in days. `DEFAULT_EXPIRY_DAYS` is preselected.
+pub const EXPIRY_CHOICES: &[i64] = &[1, 7, 30, 90];
+pub const DEFAULT_EXPIRY_DAYS: i64 = 7;
+/// How `created_at`, `expires_at` and `revoked_at` are written, in UTC, like event timestamps.
+const SHARED_AT_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// What a link shares.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShareTarget {
+    Contact(i64),
+    /// The contacts whose company is exactly this.
+    Company(String),
+}
+
+/// Whether a link still works.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareStatus {
+    Active,
+    Expired,
+    Revoked,
+}
+
+impl ShareStatus {
+    /// Message id of the status, for the shares page.
+    pub fn label(self) -> &'static str {
+        match self {
+            ShareStatus::Active => "share-status-active",
+            ShareStatus::Expired => "share-status-expired",
+            ShareStatus::Revoked => "share-status-revoked",
+        }
+    }
+}
+
+/// A share link. Exactly one of `contact_id` and `company` is set, see `target`.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ShareLink {
+    pub id: i64,
+    pub token: String,
+    pub contact_id: Option<i64>,
+    pub company: Option<String>,
+    /// When the link was made, as `SHARED_AT_FORMAT`.
+    pub created_at: String,
+    /// When it stops working, as `SHARED_AT_FORMAT`, or `None` if it works until revoked.
+    pub expires_at: Option<String>,
+    /// When it was revoked, as `SHARED_AT_FORMAT`.
+    pub revoked_at: Option<String>,
+}
+
+impl ShareLink {
+    /// What the link shares.
+    pub fn target(&self) -> ShareTarget {
+        match (self.contact_id, &self.company) {
+            (Some(id), _) => ShareTarget::Contact(id),
+            (None, company) => ShareTarget::Company(company.clone().unwrap_or_default()),
+        }
+    }
+
+    /// When the link was made.
+    pub fn created(&self) -> Option<DateTime<Utc>> {
+        parse_time(&self.created_at)
+    }
+
+    /// When the link stops working, if it does.
+    pub fn expires(&self) -> Option<DateTime<Utc>> {
+        self.expires_at.as_deref().and_then(parse_time)
+    }
+
+    /// Whether the link works at `now`, was revoked or has expired.
+    pub fn status(&self, now: DateTime<Utc>) -> ShareStatus {
+        let now = now.format(SHARED_AT_FORMAT).to_string();
+        if self.revoked_at.is_some() {
+            ShareStatus::Revoked
+        } else if self.expires_at.as_ref().is_some_and(|expires_at| *expires_at <= now) {
+            ShareStatus::Expired
+        } else {
+            ShareStatus::Active
+        }
+    }
+
+    /// Makes a link to `target` that works until `now` plus `expires_in_days`, or until it's
+    /// revoked with `None`.
+    pub async fn create(
+        db: &Db,
+        target: &ShareTarget,
+        now: DateTime<Utc>,
+        expires_in_days: Option<i64>,
+    ) -> Result<ShareLink, sqlx::Error> {
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let (contact_id, company) = match target {
+            ShareTarget::Contact(id) => (Some(*id), None),
+            ShareTarget::Company(company) => (None, Some(company.as_str())),
+        };
+        let created_at = now.format(SHARED_AT_FORMAT).to_string();
+        let expires_at = expires_in_days.map(|days| (now + TimeDelta::days(days)).format(SHARED_AT_FORMAT).to_string());
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    ShareLink,
+                    r#"INSERT INTO share_links (token, contact_id, company, created_at, expires_at)
+                    VALUES (?, ?, ?, ?, ?)
+                    RETURNING id AS "id!", token, contact_id, company, created_at, expires_at, revoked_at"#,
+                    token,
+                    contact_id,
+                    company,
+                    created_at,
+                    expires_at,
+                )
+                .fetch_one(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => {
+                postgres::create_share_link(pool, &token, contact_id, company, &created_at, expires_at.as_deref()).await
+            }
+        }
+    }
+
+    /// Every link, newest first.
+    pub async fn list(db: &Db) -> Result<Vec<ShareLink>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    ShareLink,
+                    r#"SELECT id AS "id!", token, contact_id, company, created_at, expires_at, revoked_at
+                    FROM share_links ORDER BY id DESC"#
+                )
+                .fetch_all(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::list_share_links(pool).await,
+        }
+    }
+
+    /// The link with `token`, whether it still works or not.
+    pub async fn find_by_token(db: &Db, token: &str) -> Result<Option<ShareLink>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    ShareLink,
+                    r#"SELECT id AS "id!", token, contact_id, company, created_at, expires_at, revoked_at
+                    FROM share_links WHERE token = ?"#,
+                    token,
+                )
+                .fetch_optional(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::find_share_link(pool, token).await,
+        }
+    }
+
+    /// Revokes link `id` as of `now`. Returns the link, or `None` if there is no such link or
+    /// it was revoked already.
+    pub async fn revoke(db: &Db, id: i64, now: DateTime<Utc>) -> Result<Option<ShareLink>, sqlx::Error> {
+        let revoked_at = now.format(SHARED_AT_FORMAT).to_string();
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    ShareLink,
+                    r#"UPDATE share_links SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL
+                    RETURNING id AS "id!", token, contact_id, company, created_at, expires_at, revoked_at"#,
+                    revoked_at,
+                    id,
+                )
+                .fetch_optional(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::revoke_share_link(pool, id, &revoked_at).await,
+        }
+    }
+}
+
+fn parse_time(at: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(at, SHARED_AT_FORMAT).ok().map(|at| at.and_utc())
+}
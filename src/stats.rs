@@ -0,0 +1,200 @@
+//! Contact statistics for the dashboard: how many there are, how many were added each month,
+//! the most common email domains and the latest additions and edits.
+//!
+//! The SQL backends aggregate in the database with `ContactStats::load`. The in-memory store
+//! works the same numbers out of its contacts with `ContactStats::from_contacts`.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, Months, NaiveDate, TimeDelta, Utc};
+use tracing::instrument;
+
+#[cfg(feature = "postgres")]
+use crate::postgres;
+use crate::{
+    contacts::{Contact, ContactFilter},
+    db::Db,
+};
+
+/// Months shown in the per-month chart, the current one included.
+pub const MONTHS: u32 = 12;
+/// Email domains listed.
+pub const TOP_DOMAINS: i64 = 10;
+/// Contacts listed as recently added, and as recently edited.
+pub const RECENT: i64 = 5;
+
+/// Contacts created in one month, `YYYY-MM`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonthCount {
+    pub month: String,
+    pub count: i64,
+}
+
+/// Contacts with an email at `domain`, lowercase.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct DomainCount {
+    pub domain: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ContactStats {
+    pub total: i64,
+    /// The last `MONTHS` months, oldest first. Months without new contacts are in with 0.
+    pub per_month: Vec<MonthCount>,
+    /// Most contacts first, ties alphabetically.
+    pub top_domains: Vec<DomainCount>,
+    /// Newest first.
+    pub recently_added: Vec<Contact>,
+    /// Contacts changed at least a second after they were created, latest change first.
+    pub recently_edited: Vec<Contact>,
+}
+
+/// The `MONTHS` months up to the one `now` is in, oldest first.
+fn months(now: DateTime<Utc>) -> Vec<NaiveDate> {
+    let this_month = NaiveDate::from_ymd_opt(now.year(), now.month(), 1).expect("the 1st always exists");
+    (0..MONTHS)
+        .rev()
+        .filter_map(|back| this_month.checked_sub_months(Months::new(back)))
+        .collect()
+}
+
+/// `counts` by `YYYY-MM` spread over `months`, with 0 for the ones missing.
+fn per_month(months: &[NaiveDate], counts: &HashMap<String, i64>) -> Vec<MonthCount> {
+    months
+        .iter()
+        .map(|month| {
+            let month = month.format("%Y-%m").to_string();
+            let count = counts.get(&month).copied().unwrap_or(0);
+            MonthCount { month, count }
+        })
+        .collect()
+}
+
+impl ContactStats {
+    /// Runs the aggregate queries. The months counted are the ones up to `now`.
+    ///
+    /// Example usage:
+    /// `let stats = ContactStats::load(&db, clock.now()).await?;`
+    #[instrument(name = "stats.load", skip_all)]
+    pub async fn load(db: &Db, now: DateTime<Utc>) -> Result<Self, sqlx::Error> {
+        let months = months(now);
+        let since = months[0].format("%Y-%m-%d").to_string();
+        let counts = match db {
+            Db::Sqlite(pool) => sqlx::query!(
+                r#"SELECT SUBSTR(created_at, 1, 7) AS "month!: String", COUNT(*) AS "count!: i64"
+                FROM contacts
+                WHERE created_at >= ?
+                GROUP BY SUBSTR(created_at, 1, 7)"#,
+                since,
+            )
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|row| (row.month, row.count))
+            .collect(),
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::contacts_per_month(pool, &since).await?.into_iter().collect(),
+        };
+        Ok(Self {
+            total: Contact::count(db, &ContactFilter::default()).await?,
+            per_month: per_month(&months, &counts),
+            top_domains: top_domains(db).await?,
+            recently_added: Contact::newest(db, RECENT).await?,
+            recently_edited: recently_edited(db).await?,
+        })
+    }
+
+    /// The same numbers as `load`, from contacts in memory.
+    pub fn from_contacts(contacts: &[Contact], now: DateTime<Utc>) -> Self {
+        let mut counts = HashMap::new();
+        let mut domains: HashMap<String, i64> = HashMap::new();
+        for contact in contacts {
+            *counts.entry(contact.created_at.format("%Y-%m").to_string()).or_insert(0) += 1;
+            if let Some((_, domain)) = contact.email.split_once('@') {
+                *domains.entry(domain.to_lowercase()).or_insert(0) += 1;
+            }
+        }
+        let mut top_domains: Vec<DomainCount> = domains
+            .into_iter()
+            .map(|(domain, count)| DomainCount { domain, count })
+            .collect();
+        top_domains.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.domain.cmp(&b.domain)));
+        top_domains.truncate(TOP_DOMAINS as usize);
+
+        let mut recently_added = contacts.to_vec();
+        recently_added.sort_by_key(|c| std::cmp::Reverse((c.created_at, c.id)));
+        recently_added.truncate(RECENT as usize);
+
+        let mut recently_edited: Vec<Contact> = contacts
+            .iter()
+            .filter(|c| c.updated_at - c.created_at >= TimeDelta::seconds(1))
+            .cloned()
+            .collect();
+        recently_edited.sort_by_key(|c| std::cmp::Reverse((c.updated_at, c.id)));
+        recently_edited.truncate(RECENT as usize);
+
+        Self {
+            total: contacts.len() as i64,
+            per_month: per_month(&months(now), &counts),
+            top_domains,
+            recently_added,
+            recently_edited,
+        }
+    }
+
+    /// Most contacts added in any of the months, at least 1 so it can be divided by.
+    pub fn busiest_month(&self) -> i64 {
+        self.per_month.iter().map(|m| m.count).max().unwrap_or(0).max(1)
+    }
+
+    /// `count` as a percentage of the busiest month, for the width of its bar.
+    pub fn month_share(&self, count: &i64) -> i64 {
+        count * 100 / self.busiest_month()
+    }
+}
+
+async fn top_domains(db: &Db) -> Result<Vec<DomainCount>, sqlx::Error> {
+    match db {
+        Db::Sqlite(pool) => {
+            sqlx::query_as!(
+                DomainCount,
+                r#"SELECT LOWER(SUBSTR(email, INSTR(email, '@') + 1)) AS "domain!: String",
+                    COUNT(*) AS "count!: i64"
+                FROM contacts
+                WHERE INSTR(email, '@') > 0
+                GROUP BY 1
+                ORDER BY 2 DESC, 1
+                LIMIT ?"#,
+                TOP_DOMAINS,
+            )
+            .fetch_all(pool)
+            .await
+        }
+        #[cfg(feature = "postgres")]
+        Db::Postgres(pool) => postgres::top_email_domains(pool, TOP_DOMAINS).await,
+    }
+}
+
+async fn recently_edited(db: &Db) -> Result<Vec<Contact>, sqlx::Error> {
+    match db {
+        // `updated_at` has milliseconds and `created_at` doesn't, so a contact that was never
+        // edited already has a later `updated_at` as text
+        Db::Sqlite(pool) => {
+            sqlx::query_as!(
+                Contact,
+                r#"SELECT id, first_name, last_name, phone_number, email, company, job_title,
+                    created_at AS "created_at: _", updated_at AS "updated_at: _"
+                FROM contacts
+                WHERE updated_at >= datetime(created_at, '+1 second')
+                ORDER BY updated_at DESC, id DESC
+                LIMIT ?"#,
+                RECENT,
+            )
+            .fetch_all(pool)
+            .await
+        }
+        #[cfg(feature = "postgres")]
+        Db::Postgres(pool) => postgres::recently_edited_contacts(pool, RECENT).await,
+    }
+}
@@ -1,37 +1,468 @@
 use askama::Template;
+use chrono::{DateTime, Utc};
 
-use crate::contacts::{Contact, NewContact};
+use crate::{
+    api_keys::ApiKey,
+    attachments::Attachment,
+    backup::{Backup, Retention},
+    base_path,
+    carddav::DavResource,
+    clock::DisplayTimezone,
+    email::EmailTemplate,
+    contacts::{Company, Contact, ContactOrder, ContactSort, LetterCount, NewContact, NewContactErrors, SortDirection},
+    dates::{ContactDate, UpcomingDate},
+    events::{self, ActivityFilter, Event, EventFilter},
+    i18n,
+    import::{ColumnMapping, ContactField, CsvPreview, SavedMapping},
+    jobs::Job,
+    pagination::Pagination,
+    params::ListFilters,
+    names::NameFormat,
+    preferences::{self, Preferences},
+    quality::QualityReport,
+    relationships::Relationship,
+    shares::{ShareLink, ShareStatus},
+    stats::ContactStats,
+    trash::TrashedContact,
+    webhooks::{Webhook, WebhookDelivery},
+};
+
+/// Custom filters for the templates, used like the built-in ones: `{{ value|name(args) }}`.
+mod filters {
+    use askama::filters::Safe;
+    use chrono::{DateTime, NaiveDate, Utc};
+
+    use crate::{clock::DisplayTimezone, highlight, i18n, phone};
+
+    /// A timestamp in `timezone`, written the way the current locale writes dates and times.
+    ///
+    /// Example usage:
+    /// `{{ contact.created_at|datetime(timezone) }}`
+    pub fn datetime(
+        at: &DateTime<Utc>,
+        _: &dyn askama::Values,
+        timezone: &DisplayTimezone,
+    ) -> askama::Result<String> {
+        Ok(timezone.format(at, &i18n::t("datetime-format")))
+    }
+
+    /// A date the way the current locale writes dates.
+    ///
+    /// Example usage:
+    /// `{{ date.date|date }}`
+    pub fn date(date: &NaiveDate, _: &dyn askama::Values) -> askama::Result<String> {
+        Ok(date.format(&i18n::t("date-format")).to_string())
+    }
+
+    /// How long before `now` a timestamp was, in the current locale, see `i18n::relative_time`.
+    ///
+    /// Example usage:
+    /// `{{ contact.created_at|ago(now) }}`
+    pub fn ago(at: &DateTime<Utc>, _: &dyn askama::Values, now: &DateTime<Utc>) -> askama::Result<String> {
+        Ok(i18n::relative_time(at, now))
+    }
+
+    /// A phone number written out for reading, see `phone::format`.
+    ///
+    /// Example usage:
+    /// `{{ contact.phone_number|phone }}`
+    pub fn phone(phone_number: &str, _: &dyn askama::Values) -> askama::Result<String> {
+        Ok(phone::format(phone_number))
+    }
+
+    /// The text with the words of the search marked, see `highlight::mark`. Escapes the text
+    /// itself, so the result is safe to output as is.
+    ///
+    /// Example usage:
+    /// `{{ contact.first_name|highlight(search) }}`
+    pub fn highlight(text: &str, _: &dyn askama::Values, search: &str) -> askama::Result<Safe<String>> {
+        Ok(Safe(highlight::mark(text, search)))
+    }
+}
 
 #[derive(Template)]
 #[template(path = "index.html")]
 pub struct IndexTemplate {
-    pub q: String,
+    /// The search term and filters, for the search form and links.
+    pub filters: ListFilters,
+    /// The search's free text, marked in the rows.
+    pub search: String,
     pub contacts: Vec<Contact>,
-    pub page: i64,
-    pub per_page: i64,
+    pub pagination: Pagination,
+    pub sort: ContactOrder,
+    pub pending_deletions: Vec<Contact>,
+    /// URL of this list page, passed to the edit form so saving returns here.
+    pub return_to: String,
+    pub quick_add: QuickAdd,
+    /// The A–Z bar, with the number of contacts at each letter.
+    pub letters: Vec<LetterCount>,
+    /// What to show instead of rows when there are none.
+    pub empty_state: Option<EmptyState>,
+}
+
+impl IndexTemplate {
+    /// Whether contacts created elsewhere show up at the top of the list as they come in (see
+    /// `live`): only on the first page of all contacts, since they may not match a search.
+    pub fn shows_new_contacts(&self) -> bool {
+        !self.filters.is_narrowed() && self.pagination.page == 1
+    }
+
+    /// Link for a sortable column header: the first page of the same search, sorted by
+    /// `column`. Descending if it is already sorted by `column` ascending.
+    pub fn sort_url(&self, column: &str) -> String {
+        sort_url(&self.filters, self.pagination.per_page, self.sort, column)
+    }
+
+    /// Arrow after a column header, pointing the way the column is sorted, if it is.
+    pub fn sort_arrow(&self, column: &str) -> &'static str {
+        sort_arrow(self.sort, column)
+    }
+
+    /// `/contacts/rows` URL of the next page, for the "Load more" row.
+    pub fn load_more_url(&self) -> Option<String> {
+        load_more_url(&self.pagination)
+    }
+
+    /// Link to the first page of all contacts in the same order, to clear a search that
+    /// matched nothing.
+    pub fn clear_search_url(&self) -> String {
+        clear_search_url(self.pagination.per_page, self.sort)
+    }
+
+    /// Link for the A–Z bar: the first page of the same search limited to last names starting
+    /// with `letter`, or not limited for `None`.
+    pub fn letter_url(&self, letter: Option<char>) -> String {
+        letter_url(&self.filters, self.sort, letter)
+    }
+
+    /// CSV export of the same search in the same order, in `dialect` (empty for standard).
+    pub fn export_url(&self, dialect: &str) -> String {
+        export_url(&self.filters, self.sort, dialect)
+    }
+}
+
+/// Just the rows of the contact list, plus the header and pager as out-of-band swaps. Sent
+/// instead of the whole `IndexTemplate` for active search requests from htmx.
+#[derive(Template)]
+#[template(path = "contact_rows_partial.html")]
+pub struct ContactRowsTemplate {
+    pub filters: ListFilters,
+    pub search: String,
+    pub contacts: Vec<Contact>,
+    pub pagination: Pagination,
+    pub sort: ContactOrder,
+    pub return_to: String,
+    pub letters: Vec<LetterCount>,
+    /// Same as `IndexTemplate::empty_state`.
+    pub empty_state: Option<EmptyState>,
+}
+
+impl ContactRowsTemplate {
+    /// Same as `IndexTemplate::sort_url`.
+    pub fn sort_url(&self, column: &str) -> String {
+        sort_url(&self.filters, self.pagination.per_page, self.sort, column)
+    }
+
+    /// Same as `IndexTemplate::sort_arrow`.
+    pub fn sort_arrow(&self, column: &str) -> &'static str {
+        sort_arrow(self.sort, column)
+    }
+
+    /// Same as `IndexTemplate::load_more_url`.
+    pub fn load_more_url(&self) -> Option<String> {
+        load_more_url(&self.pagination)
+    }
+
+    /// Same as `IndexTemplate::clear_search_url`.
+    pub fn clear_search_url(&self) -> String {
+        clear_search_url(self.pagination.per_page, self.sort)
+    }
+
+    /// Same as `IndexTemplate::letter_url`.
+    pub fn letter_url(&self, letter: Option<char>) -> String {
+        letter_url(&self.filters, self.sort, letter)
+    }
+
+    /// Same as `IndexTemplate::export_url`.
+    pub fn export_url(&self, dialect: &str) -> String {
+        export_url(&self.filters, self.sort, dialect)
+    }
+}
+
+/// What the quick-add form above the contact list shows: the values and problem of a rejected
+/// add, or nothing for a fresh form.
+#[derive(Debug, Default)]
+pub struct QuickAdd {
+    pub contact: NewContact,
+    pub error_message: Option<String>,
+    /// An existing contact the new one may duplicate, with a button to add it anyway.
+    pub duplicate: Option<Contact>,
+}
+
+/// The quick-add form after a rejected add, swapped over the old one.
+#[derive(Template)]
+#[template(path = "quick_add_form.html")]
+pub struct QuickAddTemplate {
+    pub quick_add: QuickAdd,
+}
+
+/// A contact added with the quick-add form: its row for the top of the list and a fresh form.
+#[derive(Template)]
+#[template(path = "quick_added.html")]
+pub struct QuickAddedTemplate {
+    pub contact: Contact,
+    pub return_to: String,
+    /// Always empty, a new contact is shown whatever the list is searching for.
+    pub search: String,
+    pub quick_add: QuickAdd,
+}
+
+/// The rows of one page of the contact list for `/contacts/rows`, ending in a sentinel row
+/// that loads `next_url` when there is a next page.
+#[derive(Template)]
+#[template(path = "contact_rows_page.html")]
+pub struct ContactRowsPageTemplate {
+    pub contacts: Vec<Contact>,
+    pub return_to: String,
+    pub search: String,
+    pub next_url: Option<String>,
+}
+
+/// What the contact list shows instead of rows when it has none, see `empty_state.html`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyState {
+    /// There are no contacts at all: a call to create the first one.
+    NoContacts,
+    /// The search, filters or letter match nothing: a link to clear them.
+    NoResults,
+}
+
+impl EmptyState {
+    /// The empty state of a page with `shown` of the `total` contacts matching `filters`.
+    /// `None` when it has rows, or when it's only past the last page.
+    pub fn of(shown: usize, total: i64, filters: &ListFilters) -> Option<Self> {
+        if shown > 0 || total > 0 {
+            None
+        } else if filters.is_narrowed() {
+            Some(EmptyState::NoResults)
+        } else {
+            Some(EmptyState::NoContacts)
+        }
+    }
+}
+
+fn clear_search_url(per_page: i64, sort: ContactOrder) -> String {
+    let (sort, dir) = sort_params(sort);
+    Pagination::new(1, per_page, 0, &base_path::url("/contacts"), &[("sort", &sort), ("dir", &dir)]).current_url()
+}
+
+/// `sort` and `dir` values for links to the list in `sort` order. Empty for the default order,
+/// so `Pagination` leaves them out, unless a preference would fill in another one.
+pub fn sort_params(sort: ContactOrder) -> (String, String) {
+    if sort == ContactOrder::default() && preferences::current().sort.is_none() {
+        return (String::new(), String::new());
+    }
+    (sort.sort_param(), sort.dir_param())
+}
+
+fn sort_url(filters: &ListFilters, per_page: i64, current: ContactOrder, column: &str) -> String {
+    let ascending = ContactSort::parse(column).map(|column| (column, SortDirection::Asc));
+    let dir = if ascending == Some(current.primary()) { "desc" } else { "" };
+    let mut query = filters.query().to_vec();
+    query.extend([("sort", column), ("dir", dir)]);
+    Pagination::new(1, per_page, 0, &base_path::url("/contacts"), &query).current_url()
+}
+
+fn sort_arrow(sort: ContactOrder, column: &str) -> &'static str {
+    match ContactSort::parse(column).and_then(|column| sort.direction(column)) {
+        Some(SortDirection::Asc) => " ↓",
+        Some(SortDirection::Desc) => " ↑",
+        None => "",
+    }
+}
+
+fn letter_url(filters: &ListFilters, sort: ContactOrder, letter: Option<char>) -> String {
+    let letter = letter.map(String::from).unwrap_or_default();
+    let filters = ListFilters { letter, ..filters.clone() };
+    let mut query = filters.query().to_vec();
+    let (sort, dir) = sort_params(sort);
+    query.extend([("sort", sort.as_str()), ("dir", dir.as_str())]);
+    Pagination::new(1, 1, 0, &base_path::url("/contacts"), &query).current_url()
+}
+
+fn export_url(filters: &ListFilters, sort: ContactOrder, dialect: &str) -> String {
+    let mut query = filters.query().to_vec();
+    let (sort, dir) = sort_params(sort);
+    query.extend([("sort", sort.as_str()), ("dir", dir.as_str()), ("dialect", dialect)]);
+    let query: Vec<_> = query.into_iter().filter(|(_, value)| !value.is_empty()).collect();
+    if query.is_empty() {
+        return base_path::url("/contacts/export.csv");
+    }
+    // Serializing a list of string pairs can't fail
+    base_path::url(&format!("/contacts/export.csv?{}", serde_urlencoded::to_string(query).unwrap_or_default()))
+}
+
+fn load_more_url(pagination: &Pagination) -> Option<String> {
+    let rows = pagination.with_base_url(&base_path::url("/contacts/rows"));
+    rows.has_next().then(|| rows.next_url())
 }
 
 #[derive(Template)]
 #[template(path = "new.html")]
 pub struct NewContactTemplate {
     pub contact: Option<NewContact>,
+    pub form_token: String,
+    /// Why the last submission was rejected, when the form was posted without htmx.
+    pub error_message: Option<String>,
+    /// An existing contact the submission looks like, when the form was posted without htmx.
+    pub duplicate: Option<Contact>,
+}
+
+impl NewContactTemplate {
+    /// What's wrong with `field` of the submitted contact, or an empty string.
+    pub fn field_error(&self, field: &str) -> &str {
+        match self.contact.as_ref().and_then(|c| c.errors.as_ref()) {
+            Some(errors) => errors.get(field),
+            None => "",
+        }
+    }
+}
+
+/// Asks whether to create a contact that looks like `duplicate`, inside the new contact form.
+#[derive(Template)]
+#[template(path = "duplicate_warning.html")]
+pub struct DuplicateWarningTemplate {
+    pub duplicate: Contact,
 }
 
 #[derive(Template)]
 #[template(path = "show.html")]
 pub struct ShowContactTemplate {
     pub contact: Contact,
+    pub timezone: DisplayTimezone,
+    /// For how long ago the contact was created and changed.
+    pub now: DateTime<Utc>,
+    pub relationships: Vec<Relationship>,
+    /// Why a relationship couldn't be added, when the form was posted without htmx.
+    pub relationship_error: Option<String>,
+    pub dates: Vec<ContactDate>,
+    /// Why a date couldn't be added, when the form was posted without htmx.
+    pub date_error: Option<String>,
+    pub attachments: Vec<Attachment>,
+    /// Why an upload was turned down, when the form was posted without htmx.
+    pub attachment_error: Option<String>,
+}
+/// The relationships section of a contact's page, swapped in after adding or removing one.
+#[derive(Template)]
+#[template(path = "relationships.html")]
+pub struct RelationshipsTemplate {
+    pub contact_id: i64,
+    pub relationships: Vec<Relationship>,
+    pub error_message: Option<String>,
+}
+/// The dates section of a contact's page, swapped in after adding or removing one.
+#[derive(Template)]
+#[template(path = "dates.html")]
+pub struct DatesTemplate {
+    pub contact_id: i64,
+    pub dates: Vec<ContactDate>,
+    pub error_message: Option<String>,
+}
+/// The dates of every contact coming up in the next `days` days.
+#[derive(Template)]
+#[template(path = "upcoming_dates.html")]
+pub struct UpcomingDatesTemplate {
+    pub upcoming: Vec<UpcomingDate>,
+    pub days: u32,
+}
+/// The attachments section of a contact's page, swapped in after an upload or removal.
+#[derive(Template)]
+#[template(path = "attachments.html")]
+pub struct AttachmentsTemplate {
+    pub contact_id: i64,
+    pub attachments: Vec<Attachment>,
+    pub error_message: Option<String>,
+}
+/// The contacts grouped by company.
+#[derive(Template)]
+#[template(path = "companies.html")]
+pub struct CompaniesTemplate {
+    pub companies: Vec<Company>,
+}
+/// A single display row of the contact list, for inline editing.
+#[derive(Template)]
+#[template(path = "contact_row.html")]
+pub struct ContactRowTemplate {
+    pub contact: Contact,
+    pub return_to: String,
+    /// Always empty, the row is shown after an edit rather than as a search result.
+    pub search: String,
+}
+
+/// A contact list row as an inline editor.
+#[derive(Template)]
+#[template(path = "contact_edit_row.html")]
+pub struct ContactEditRowTemplate {
+    pub contact: Contact,
+    pub return_to: String,
+    /// Why the last save was rejected, with the submitted values in `contact`.
+    pub error_message: Option<String>,
 }
+
+#[derive(Template)]
+#[template(path = "delete.html")]
+pub struct DeleteContactTemplate {
+    pub contact: Contact,
+}
+
 #[derive(Template)]
 #[template(path = "edit.html")]
 pub struct EditContactTemplate {
+    /// With the submitted values when the last save was rejected.
     pub contact: Contact,
+    pub return_to: String,
+    pub errors: Option<NewContactErrors>,
+    /// Why the last save failed, when it wasn't down to the fields.
+    pub error_message: Option<String>,
+}
+
+impl EditContactTemplate {
+    /// Same as `NewContactTemplate::field_error`.
+    pub fn field_error(&self, field: &str) -> &str {
+        match &self.errors {
+            Some(errors) => errors.get(field),
+            None => "",
+        }
+    }
 }
 #[derive(Template)]
 #[template(path = "error.html")]
 pub struct Error5xxTemplate {
     pub error: String,
+    /// To quote when reporting the problem, see `logging::request_id`.
+    pub request_id: Option<String>,
 }
+/// The page for a contact or route that doesn't exist.
+#[derive(Template)]
+#[template(path = "not_found.html")]
+pub struct NotFoundTemplate;
+
+/// The page for a request refused as sent, see `AppError`: invalid, conflicting or without
+/// the right credentials.
+#[derive(Template)]
+#[template(path = "client_error.html")]
+pub struct ClientErrorTemplate {
+    pub title: String,
+    pub error_message: String,
+}
+
+/// The page for a form that couldn't be read, when it wasn't posted by htmx.
+#[derive(Template)]
+#[template(path = "bad_request.html")]
+pub struct BadRequestTemplate {
+    pub error_message: String,
+}
+
 #[derive(Template)]
 #[template(path = "success_redirect.html")]
 pub struct SuccessRedirectTemplate {
@@ -43,3 +474,305 @@ pub struct SuccessRedirectTemplate {
 pub struct ErrorMessageTemplate {
     pub error_message: String,
 }
+
+#[derive(Template)]
+#[template(path = "too_many_requests.html")]
+pub struct TooManyRequestsTemplate {
+    pub retry_after: u64,
+}
+
+#[derive(Template)]
+#[template(path = "admin_events.html")]
+pub struct AdminEventsTemplate {
+    pub events: Vec<Event>,
+    pub filter: EventFilter,
+    pub kinds: &'static [&'static str],
+}
+
+/// The activity feed: recent contact changes, newest first.
+#[derive(Template)]
+#[template(path = "activity.html")]
+pub struct ActivityTemplate {
+    pub events: Vec<Event>,
+    pub filter: ActivityFilter,
+    pub timezone: DisplayTimezone,
+    /// For how long ago the changes were.
+    pub now: DateTime<Utc>,
+    /// Id of the last event shown, when there are older ones.
+    pub older: Option<i64>,
+}
+
+impl ActivityTemplate {
+    /// What happened to the contact, in the current locale: "created", "edited", ...
+    pub fn action(&self, kind: &str) -> String {
+        i18n::t(match kind {
+            events::CONTACT_CREATED => "activity-created",
+            events::CONTACT_UPDATED => "activity-updated",
+            events::CONTACT_DELETED => "activity-deleted",
+            events::CONTACT_RESTORED => "activity-restored",
+            _ => "activity-changed",
+        })
+    }
+
+    /// The name of the contact the feed is narrowed down to, from its newest event, or its id
+    /// if it has none.
+    pub fn contact_name(&self) -> Option<String> {
+        let id = self.filter.contact?;
+        let name = self.events.first().and_then(Event::contact_name);
+        Some(name.unwrap_or_else(|| format!("#{id}")))
+    }
+
+    /// The next page of the feed, with the same filters.
+    pub fn older_url(&self) -> Option<String> {
+        let before = self.older?;
+        let query = [
+            ("contact", self.filter.contact.map(|id| id.to_string())),
+            ("actor", self.filter.actor.clone().filter(|actor| !actor.is_empty())),
+            ("before", Some(before.to_string())),
+        ];
+        let query: Vec<(&str, String)> = query
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name, value)))
+            .collect();
+        Some(base_path::url(&format!("/activity?{}", serde_urlencoded::to_string(query).unwrap_or_default())))
+    }
+}
+
+/// The dashboard with the contact statistics.
+#[derive(Template)]
+#[template(path = "dashboard.html")]
+pub struct DashboardTemplate {
+    pub stats: ContactStats,
+    pub timezone: DisplayTimezone,
+    /// For how long ago the contacts were added or changed.
+    pub now: DateTime<Utc>,
+}
+
+/// The display preferences form. `per_page` is left empty while it follows the configured
+/// page size, which is shown as the placeholder.
+#[derive(Template)]
+#[template(path = "settings.html")]
+pub struct SettingsTemplate {
+    pub preferences: Preferences,
+    pub default_per_page: i64,
+    pub max_per_page: i64,
+    pub error_message: Option<String>,
+}
+
+impl SettingsTemplate {
+    /// Label of a sort option.
+    pub fn sort_label(&self, sort: &ContactSort) -> String {
+        i18n::t(match sort {
+            ContactSort::Id => "sort-id",
+            ContactSort::FirstName => "sort-first-name",
+            ContactSort::LastName => "sort-last-name",
+            ContactSort::Email => "sort-email",
+            ContactSort::Company => "sort-company",
+            ContactSort::CreatedAt => "sort-created-at",
+        })
+    }
+
+    /// Label of a name format option, an example name written in it.
+    pub fn name_format_label(&self, format: &NameFormat) -> String {
+        let example = Preferences { name_format: *format, ..Preferences::default() };
+        example.name(&i18n::t("example-first-name"), &i18n::t("example-last-name"))
+    }
+
+    pub fn sort_selected(&self, sort: &ContactSort) -> bool {
+        self.preferences.sort.unwrap_or_default() == *sort
+    }
+
+    pub fn name_format_checked(&self, format: &NameFormat) -> bool {
+        self.preferences.name_format == *format
+    }
+}
+
+#[derive(Template)]
+#[template(path = "quality_report.html")]
+pub struct QualityReportTemplate {
+    pub report: Option<QualityReport>,
+}
+
+#[derive(Template)]
+#[template(path = "database_busy.html")]
+pub struct DatabaseBusyTemplate {
+    pub retry_after: u64,
+}
+
+#[derive(Template)]
+#[template(path = "server_busy.html")]
+pub struct ServerBusyTemplate {
+    pub retry_after: u64,
+}
+
+#[derive(Template)]
+#[template(path = "admin_backups.html")]
+pub struct AdminBackupsTemplate {
+    pub backups: Vec<Backup>,
+    /// `database.backup_interval_hours`, `None` for backups on demand only.
+    pub interval_hours: Option<u64>,
+    pub retention: Retention,
+    /// The newest backup job, for how the last backup went.
+    pub last_job: Option<Job>,
+}
+
+#[derive(Template)]
+#[template(path = "admin_jobs.html")]
+pub struct AdminJobsTemplate {
+    pub jobs: Vec<Job>,
+    /// Whether an LDAP server is configured, which shows the import buttons.
+    pub ldap_import: bool,
+}
+
+#[derive(Template)]
+#[template(path = "admin_webhooks.html")]
+pub struct AdminWebhooksTemplate {
+    pub webhooks: Vec<Webhook>,
+    pub deliveries: Vec<WebhookDelivery>,
+    /// The webhook just added, whose secret is shown this once.
+    pub created: Option<Webhook>,
+    pub error_message: Option<String>,
+}
+
+/// The import page: pick a CSV file to map and import.
+#[derive(Template)]
+#[template(path = "import.html")]
+pub struct ImportTemplate {
+    /// The largest file taken, like "5.0 MB".
+    pub max_size: String,
+    pub error_message: Option<String>,
+}
+
+/// Second step of an import: match the uploaded file's columns to contact fields.
+#[derive(Template)]
+#[template(path = "import_mapping.html")]
+pub struct ImportMappingTemplate {
+    pub token: String,
+    pub filename: String,
+    pub preview: CsvPreview,
+    /// What each column is read as to start with.
+    pub mapping: ColumnMapping,
+    pub saved_mappings: Vec<SavedMapping>,
+    /// The saved mapping `mapping` is from, if any.
+    pub saved_id: Option<i64>,
+    /// Name to save the mapping under, as posted.
+    pub save_as: String,
+    pub error_message: Option<String>,
+}
+
+impl ImportMappingTemplate {
+    /// Whether the column `header` starts out read as `field`.
+    pub fn is_mapped(&self, header: &str, field: &ContactField) -> bool {
+        self.mapping.field(header) == Some(*field)
+    }
+
+    /// Whether the mapping is `saved`, for the saved mapping picker.
+    pub fn is_saved(&self, saved: &SavedMapping) -> bool {
+        self.saved_id == Some(saved.id)
+    }
+
+    /// Whether the column `header` starts out ignored.
+    pub fn is_ignored(&self, header: &str) -> bool {
+        self.mapping.field(header).is_none()
+    }
+}
+
+/// A row of an imported file that wasn't created.
+pub struct ImportFailure {
+    /// The row's number, the header row not counted.
+    pub row: usize,
+    pub name: String,
+    pub errors: String,
+}
+
+/// What an import from the import page came to, or with `dry_run` would come to.
+#[derive(Template)]
+#[template(path = "import_result.html")]
+pub struct ImportResultTemplate {
+    pub filename: String,
+    pub created: usize,
+    pub failures: Vec<ImportFailure>,
+    pub dry_run: bool,
+    /// The upload, to import for real after a dry run.
+    pub token: String,
+    /// The mapping form as it was posted for the dry run, to post again without it.
+    pub form: Vec<(String, String)>,
+}
+
+/// The trash: deleted contacts that can be restored until they're purged.
+#[derive(Template)]
+#[template(path = "trash.html")]
+pub struct TrashTemplate {
+    pub contacts: Vec<TrashedContact>,
+    /// `trash.retention_days`, 0 when the trash is off.
+    pub retention_days: u32,
+    pub error_message: Option<String>,
+}
+
+/// The API keys, with the one just made, the only time it's shown, or the form's error.
+#[derive(Template)]
+#[template(path = "api_keys.html")]
+pub struct ApiKeysTemplate {
+    pub keys: Vec<ApiKey>,
+    /// The key just made, and the key itself.
+    pub new_key: Option<(ApiKey, String)>,
+    /// `api.require_key`.
+    pub require_key: bool,
+    pub timezone: DisplayTimezone,
+    /// To count the requests of today against the daily quotas.
+    pub now: DateTime<Utc>,
+    pub error_message: Option<String>,
+}
+
+/// A share link on the shares page.
+pub struct ShareRow {
+    pub link: ShareLink,
+    /// The name of the contact, or the company, it shares.
+    pub title: String,
+    /// The link, absolute so it can be copied and sent.
+    pub url: String,
+    pub status: ShareStatus,
+}
+
+/// The share links, with the form's error if a link couldn't be made.
+#[derive(Template)]
+#[template(path = "shares.html")]
+pub struct SharesTemplate {
+    pub shares: Vec<ShareRow>,
+    pub timezone: DisplayTimezone,
+    pub error_message: Option<String>,
+}
+
+/// What a share link shows: one contact, or the contacts of a company, read-only.
+#[derive(Template)]
+#[template(path = "shared.html")]
+pub struct SharedContactsTemplate {
+    /// The contact's name or the company.
+    pub title: String,
+    pub contacts: Vec<Contact>,
+    /// When the link stops working, if it does.
+    pub expires: Option<DateTime<Utc>>,
+    pub timezone: DisplayTimezone,
+    /// Where the contacts can be downloaded as vCards.
+    pub vcard_url: String,
+}
+
+/// A WebDAV `207 Multi-Status` body for `PROPFIND` and `REPORT`.
+#[derive(Template)]
+#[template(path = "dav/multistatus.xml")]
+pub struct DavMultistatusTemplate {
+    pub resources: Vec<DavResource>,
+}
+
+/// Sent from the admin jobs page to check the email settings.
+#[derive(Template)]
+#[template(path = "email/test.txt")]
+pub struct TestEmailTemplate {
+    pub sent_at: DateTime<Utc>,
+}
+
+impl EmailTemplate for TestEmailTemplate {
+    fn subject(&self) -> String {
+        "webone test email".into()
+    }
+}
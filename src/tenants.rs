@@ -0,0 +1,206 @@
+//! Multi-tenant mode: one server for several separate address books, each in its own SQLite
+//! file. The tenant comes from the request, by subdomain (`acme.contacts.example.com`) or by
+//! path (`/t/acme/contacts`), and requests for no tenant are served from `database.url` as
+//! usual.
+//!
+//! A tenant's database is `<tenancy.dir>/<tenant>.db`, created and migrated on its first
+//! request. From then on the tenant's app (its pool, job workers and the rest of `AppState`)
+//! is kept in `Tenants`. Backups and attachments go to a directory per tenant under the
+//! configured ones, and LDAP imports only ever go to the main database.
+//!
+//! Tenant names are lowercase letters, digits and dashes. Only the tenants `tenancy.allowed`
+//! lists are served, or with `"*"` any name, and at most `tenancy.max_tenants` of them are
+//! kept open, so visiting made-up names can't fill the disk with databases.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::Request,
+    http::{Uri, header::HOST, uri::PathAndQuery},
+};
+use serde::Deserialize;
+use tokio::sync::OnceCell;
+
+use crate::config::{Config, LdapConfig, TenancyConfig};
+
+/// Where tenants are served under in path mode, `/t/<tenant>/`.
+pub const PATH_PREFIX: &str = "/t";
+/// Longest tenant name, the longest a DNS label can be.
+const MAX_NAME_LEN: usize = 63;
+/// In `tenancy.allowed`, lets any valid name be a tenant.
+pub const ANY_TENANT: &str = "*";
+
+/// How requests are matched to tenants, `tenancy.mode`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TenancyMode {
+    /// Every request is served from `database.url`.
+    #[default]
+    Off,
+    /// By the subdomain of `tenancy.domain` the request is for.
+    Subdomain,
+    /// By the path segment after `/t/`.
+    Path,
+}
+
+/// A request for a tenant that isn't valid or allowed, answered with a 404.
+#[derive(Debug, Clone, Copy)]
+pub struct UnknownTenant;
+
+/// A new tenant when `tenancy.max_tenants` are open already, answered with a 404 too.
+#[derive(Debug, Clone, Copy)]
+pub struct TooManyTenants;
+
+impl std::fmt::Display for TooManyTenants {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Too many tenants are open already")
+    }
+}
+
+impl std::error::Error for TooManyTenants {}
+
+/// Whether `name` can be a tenant: a DNS label of lowercase letters, digits and dashes, so it
+/// is safe as a file name and a subdomain both.
+pub fn is_valid_name(name: &str) -> bool {
+    (1..=MAX_NAME_LEN).contains(&name.len())
+        && name.bytes().all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-')
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+}
+
+/// The tenant `request` is for, `None` for the main database. In path mode the tenant's
+/// segment is taken off the request's URI, so it can be routed like any other.
+///
+/// Example usage:
+/// `let tenant = tenants::resolve(&config.tenancy, &mut request)?;`
+pub fn resolve(config: &TenancyConfig, request: &mut Request) -> Result<Option<String>, UnknownTenant> {
+    let name = match config.mode {
+        TenancyMode::Off => return Ok(None),
+        TenancyMode::Subdomain => {
+            let host = request
+                .headers()
+                .get(HOST)
+                .and_then(|value| value.to_str().ok())
+                .or_else(|| request.uri().host())
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            // Without the port. IPv6 addresses are never a tenant
+            let host = host.split(':').next().unwrap_or_default();
+            let Some(name) = host
+                .strip_suffix(config.domain.as_str())
+                .and_then(|rest| rest.strip_suffix('.'))
+            else {
+                return Ok(None);
+            };
+            name.to_string()
+        }
+        TenancyMode::Path => {
+            let Some(rest) = request.uri().path().strip_prefix(PATH_PREFIX).and_then(|rest| rest.strip_prefix('/')) else {
+                return Ok(None);
+            };
+            let (name, path) = match rest.split_once('/') {
+                Some((name, path)) => (name.to_string(), format!("/{path}")),
+                None => (rest.to_string(), "/".to_string()),
+            };
+            let path_and_query = match request.uri().query() {
+                Some(query) => format!("{path}?{query}"),
+                None => path,
+            };
+            let mut parts = request.uri().clone().into_parts();
+            parts.path_and_query = Some(PathAndQuery::try_from(path_and_query).map_err(|_| UnknownTenant)?);
+            *request.uri_mut() = Uri::from_parts(parts).map_err(|_| UnknownTenant)?;
+            name
+        }
+    };
+    if !is_valid_name(&name) || !config.allowed.iter().any(|allowed| allowed == ANY_TENANT || *allowed == name) {
+        return Err(UnknownTenant);
+    }
+    Ok(Some(name))
+}
+
+/// `config` for the tenant `name`: its own database, backup and attachment directories and
+/// Meilisearch index, and no LDAP imports.
+pub fn tenant_config(config: &Config, name: &str) -> Config {
+    let mut tenant = config.clone();
+    tenant.database.url = format!("sqlite:{}", database_path(&config.tenancy, name).display());
+    tenant.database.backup_dir = config.database.backup_dir.join(name);
+    tenant.attachments.dir = config.attachments.dir.join(name);
+    tenant.ldap = LdapConfig::default();
+    tenant.search.meilisearch_index = format!("{}_{name}", config.search.meilisearch_index);
+    tenant
+}
+
+/// The SQLite file of the tenant `name`.
+pub fn database_path(config: &TenancyConfig, name: &str) -> PathBuf {
+    config.dir.join(format!("{name}.db"))
+}
+
+/// The base path the tenant `name` is served under (see `base_path::scope`): its own in path
+/// mode, the app's in subdomain mode. Leaked once per tenant, see `base_path::leak`.
+pub fn base_path(config: &Config, name: &str) -> &'static str {
+    match config.tenancy.mode {
+        TenancyMode::Path => crate::base_path::leak(&format!("{}{PATH_PREFIX}/{name}", config.server.base_path)),
+        TenancyMode::Off | TenancyMode::Subdomain => crate::base_path::leak(&config.server.base_path),
+    }
+}
+
+/// The apps of the tenants that had a request so far, by name. Cheap to clone.
+pub struct Tenants<T> {
+    apps: Arc<Mutex<HashMap<String, Arc<OnceCell<T>>>>>,
+}
+
+impl<T> Clone for Tenants<T> {
+    fn clone(&self) -> Self {
+        Self { apps: self.apps.clone() }
+    }
+}
+
+impl<T> Default for Tenants<T> {
+    fn default() -> Self {
+        Self { apps: Arc::default() }
+    }
+}
+
+impl<T: Clone> Tenants<T> {
+    /// The app of the tenant `name`, made with `create` if it has none yet. Requests that come
+    /// in while it's being made wait for it, and if it fails the next request tries again. A
+    /// tenant counts towards `max` from its first request, while it's still being made, so
+    /// many first requests at once can't get past it; past it new ones fail with
+    /// `TooManyTenants`.
+    ///
+    /// Example usage:
+    /// `let app = tenants.get_or_create(&name, max_tenants, || open_tenant(&tenancy, &name)).await?;`
+    pub async fn get_or_create<F, Fut>(&self, name: &str, max: usize, create: F) -> anyhow::Result<T>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let cell = {
+            let mut apps = self.apps.lock().unwrap();
+            if !apps.contains_key(name) && apps.len() >= max {
+                return Err(TooManyTenants.into());
+            }
+            apps.entry(name.to_string()).or_default().clone()
+        };
+        let app = cell.get_or_try_init(create).await.cloned();
+        if app.is_err() {
+            // Failed names aren't kept, or requests for made-up ones would pile up here
+            let mut apps = self.apps.lock().unwrap();
+            if apps.get(name).is_some_and(|kept| Arc::ptr_eq(kept, &cell) && !kept.initialized()) {
+                apps.remove(name);
+            }
+        }
+        app
+    }
+
+    /// Every tenant's app that was made, for shutting them down.
+    pub fn all(&self) -> Vec<T> {
+        let apps = self.apps.lock().unwrap();
+        apps.values().filter_map(|cell| cell.get().cloned()).collect()
+    }
+}
@@ -0,0 +1,254 @@
+//! The trash: deleted contacts, kept for `trash.retention_days` so they can be restored.
+//!
+//! A contact deleted from the app goes here once its undo window is over (see `deletions`),
+//! one deleted over CardDAV right away. Its attachments stay on disk until it's purged and its
+//! dates are kept with it, so a restored contact has them back; its relationships are gone.
+//! `PURGE_TRASH_JOB` jobs purge the contacts that have been in the trash longer than the
+//! retention, queued once a day by `spawn_scheduler`, and the trash page can empty it at once.
+//!
+//! With a retention of 0 there is no trash and deleted contacts are gone for good.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::{info, warn};
+
+use crate::{
+    attachments::Attachments,
+    clock::SharedClock,
+    contacts::{CREATED_AT_FORMAT, Contact},
+    dates::ContactDate,
+    db::Db,
+    jobs::{JobHandler, Jobs},
+};
+#[cfg(feature = "postgres")]
+use crate::postgres;
+
+/// Job kind that purges contacts trashed more than the payload's `older_than_days` ago.
+pub const PURGE_TRASH_JOB: &str = "trash.purge";
+/// How often `spawn_scheduler` queues a purge.
+const PURGE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How `deleted_at` is written, like event timestamps.
+const DELETED_AT_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// A contact in the trash, with the id it had.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct TrashedContact {
+    pub id: i64,
+    pub first_name: String,
+    pub last_name: String,
+    pub phone_number: String,
+    pub email: String,
+    pub company: String,
+    pub job_title: String,
+    /// When the contact was created, as `CREATED_AT_FORMAT`.
+    pub created_at: String,
+    /// When it went into the trash, as `DELETED_AT_FORMAT` in UTC.
+    pub deleted_at: String,
+    /// Its dates, as a JSON array of `TrashedDate`, see `dates`.
+    pub dates: String,
+}
+
+/// A date a trashed contact had, to give back to it on restore.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrashedDate {
+    pub label: String,
+    pub date: NaiveDate,
+    pub yearly: bool,
+}
+
+impl TrashedContact {
+    /// Puts `contact` in the trash with its `dates`. A contact with the same id that is in there
+    /// already, from before it was restored, is replaced.
+    pub async fn add(db: &Db, contact: &Contact, dates: &[ContactDate]) -> Result<(), sqlx::Error> {
+        let created_at = contact.created_at.format(CREATED_AT_FORMAT).to_string();
+        let dates: Vec<TrashedDate> = dates
+            .iter()
+            .map(|date| TrashedDate { label: date.label.clone(), date: date.date, yearly: date.yearly })
+            .collect();
+        let dates = json!(dates).to_string();
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query!(
+                    r#"INSERT OR REPLACE INTO trashed_contacts
+                        (id, first_name, last_name, phone_number, email, company, job_title, created_at, dates)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
+                    contact.id,
+                    contact.first_name,
+                    contact.last_name,
+                    contact.phone_number,
+                    contact.email,
+                    contact.company,
+                    contact.job_title,
+                    created_at,
+                    dates,
+                )
+                .execute(pool)
+                .await
+                .map(|_| ())
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::trash_contact(pool, contact, &created_at, &dates).await,
+        }
+    }
+
+    /// Everything in the trash, most recently deleted first.
+    pub async fn list(db: &Db) -> Result<Vec<TrashedContact>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    TrashedContact,
+                    r#"SELECT id AS "id!", first_name, last_name, phone_number, email, company, job_title,
+                        created_at, deleted_at, dates
+                    FROM trashed_contacts ORDER BY deleted_at DESC, id DESC"#
+                )
+                .fetch_all(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::list_trashed_contacts(pool).await,
+        }
+    }
+
+    /// Contact `id`, if it's in the trash.
+    pub async fn find(db: &Db, id: i64) -> Result<Option<TrashedContact>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    TrashedContact,
+                    r#"SELECT id AS "id!", first_name, last_name, phone_number, email, company, job_title,
+                        created_at, deleted_at, dates
+                    FROM trashed_contacts WHERE id = ?"#,
+                    id,
+                )
+                .fetch_optional(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::find_trashed_contact(pool, id).await,
+        }
+    }
+
+    /// Takes contact `id` out of the trash, as when it's restored.
+    pub async fn remove(db: &Db, id: i64) -> Result<(), sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query!("DELETE FROM trashed_contacts WHERE id = ?", id)
+                    .execute(pool)
+                    .await
+                    .map(|_| ())
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::remove_trashed_contact(pool, id).await,
+        }
+    }
+
+    /// Removes the contacts trashed before `before` (`DELETED_AT_FORMAT`), every one with
+    /// `None`, and returns them.
+    async fn remove_before(db: &Db, before: Option<&str>) -> Result<Vec<TrashedContact>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    TrashedContact,
+                    r#"DELETE FROM trashed_contacts WHERE ?1 IS NULL OR deleted_at < ?1
+                    RETURNING id AS "id!", first_name, last_name, phone_number, email, company, job_title,
+                        created_at, deleted_at, dates"#,
+                    before,
+                )
+                .fetch_all(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::remove_trashed_contacts(pool, before).await,
+        }
+    }
+
+    /// The contact as it was, to store again. Its `updated_at` is `now`, so CardDAV clients
+    /// pick it up again.
+    pub fn contact(&self, now: DateTime<Utc>) -> Contact {
+        let created_at = NaiveDateTime::parse_from_str(&self.created_at, CREATED_AT_FORMAT)
+            .map(|at| at.and_utc())
+            .unwrap_or(now);
+        Contact {
+            id: self.id,
+            first_name: self.first_name.clone(),
+            last_name: self.last_name.clone(),
+            phone_number: self.phone_number.clone(),
+            email: self.email.clone(),
+            company: self.company.clone(),
+            job_title: self.job_title.clone(),
+            created_at,
+            updated_at: now,
+        }
+    }
+
+    /// The dates the contact had. None if they can't be read, which can only be a bug.
+    pub fn dates(&self) -> Vec<TrashedDate> {
+        serde_json::from_str(&self.dates).unwrap_or_default()
+    }
+
+    /// The day the contact is purged on, with `retention_days`.
+    pub fn purge_date(&self, retention_days: u32) -> String {
+        NaiveDateTime::parse_from_str(&self.deleted_at, DELETED_AT_FORMAT)
+            .map(|at| (at + TimeDelta::days(retention_days.into())).format("%Y-%m-%d").to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// Deletes the contacts trashed before `before`, every one with `None`, and their attachments,
+/// logging each. Returns how many there were.
+///
+/// Example usage:
+/// `let purged = trash::purge(&db, &attachments, None).await?;`
+pub async fn purge(db: &Db, attachments: &Attachments, before: Option<&str>) -> anyhow::Result<usize> {
+    let purged = TrashedContact::remove_before(db, before).await?;
+    for contact in &purged {
+        attachments.remove_for_contact(contact.id).await?;
+        info!(
+            "Purged contact {} ({} {} <{}>) from the trash, deleted {}",
+            contact.id, contact.first_name, contact.last_name, contact.email, contact.deleted_at
+        );
+    }
+    Ok(purged.len())
+}
+
+/// Runs `PURGE_TRASH_JOB` jobs.
+///
+/// Example usage:
+/// `jobs.enqueue(PURGE_TRASH_JOB, json!({ "older_than_days": 30 })).await?;`
+pub struct PurgeTrashJob {
+    pub db: Db,
+    pub attachments: Attachments,
+    pub clock: SharedClock,
+}
+
+#[async_trait]
+impl JobHandler for PurgeTrashJob {
+    async fn run(&self, payload: &Value) -> anyhow::Result<String> {
+        let days = payload["older_than_days"]
+            .as_i64()
+            .filter(|days| *days >= 0)
+            .context("older_than_days must be a number of days")?;
+        let before = self.clock.now() - TimeDelta::days(days);
+        let before = before.format(DELETED_AT_FORMAT).to_string();
+        let purged = purge(&self.db, &self.attachments, Some(&before)).await?;
+        Ok(format!("Purged {purged} contact(s) deleted before {before} from the trash"))
+    }
+}
+
+/// Queues a `PURGE_TRASH_JOB` for contacts older than `retention_days` now and then once a day.
+pub fn spawn_scheduler(jobs: Jobs, retention_days: u32) {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            ticks.tick().await;
+            if let Err(e) = jobs.enqueue(PURGE_TRASH_JOB, json!({ "older_than_days": retention_days })).await {
+                warn!("Failed to queue the trash purge: {e}");
+            }
+        }
+    });
+}
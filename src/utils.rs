@@ -1,40 +1,200 @@
+use std::sync::Arc;
+
 use askama::Template;
 use axum::{
-    http::StatusCode,
-    response::{Html, IntoResponse},
+    Json,
+    extract::{Request, rejection::JsonRejection},
+    http::{HeaderValue, StatusCode, header::{CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER, WWW_AUTHENTICATE}},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
 };
-
+use serde_json::json;
 use tracing::error;
 
-use crate::templates::Error5xxTemplate;
+use crate::{
+    base_path,
+    contacts::{ContactError, NewContactErrors},
+    db::{self, BUSY_RETRY_AFTER_SECS},
+    error_reporting::ReportedError,
+    i18n,
+    logging,
+    templates::{ClientErrorTemplate, DatabaseBusyTemplate, Error5xxTemplate, NotFoundTemplate},
+};
 
-pub struct AppError(anyhow::Error);
+pub enum AppError {
+    /// What the request asked for isn't there, like a contact that was deleted. A database
+    /// lookup failing with `sqlx::Error::RowNotFound` ends up as this.
+    NotFound,
+    /// What was sent can't be used as it is, like a JSON body that doesn't parse: a `422` with
+    /// the problem, in the current locale.
+    Validation(String),
+    /// The request clashes with what's stored, like another contact having the email: a `409`
+    /// with the problem. `ContactError::Taken` and `Changed` end up as this.
+    Conflict(String),
+    /// The request needs credentials it didn't come with, or came with wrong ones: a `401`
+    /// with the problem and `WWW-Authenticate: Bearer`, the only scheme there is.
+    Unauthorized(String),
+    /// Everything else: a 500, and a report to the `ErrorReporter` (see `error_reporting`).
+    Internal(anyhow::Error),
+}
 
 impl IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
-        // Returning a HTML page for an error
-        let template = Error5xxTemplate {
-            error: self.0.to_string(),
-        };
-        match template.render() {
-            Ok(html) => {
-                error!("Internal Application Error: {}", self.0.to_string());
-                (StatusCode::INTERNAL_SERVER_ERROR, Html(html)).into_response()
+        let err = match self {
+            AppError::NotFound => return not_found(),
+            AppError::Validation(message) => {
+                return client_error(StatusCode::UNPROCESSABLE_ENTITY, "error-validation-title", message);
             }
-            // This has failed catastrophically - just return some string
-            Err(_) => {
-                error!("Internal Server Error: {}", self.0.to_string());
-                (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error").into_response()
+            AppError::Conflict(message) => return client_error(StatusCode::CONFLICT, "error-conflict-title", message),
+            AppError::Unauthorized(message) => {
+                let mut response = client_error(StatusCode::UNAUTHORIZED, "error-unauthorized-title", message);
+                response
+                    .headers_mut()
+                    .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+                return response;
             }
-        }
+            AppError::Internal(err) => err,
+        };
+        // The database was still locked after retrying, or every pooled connection stayed in
+        // use - that's temporary, so tell the client to come back instead of showing an
+        // internal error
+        let temporary = |err: &sqlx::Error| db::is_busy(err) || matches!(err, sqlx::Error::PoolTimedOut);
+        let mut response = if err.downcast_ref::<sqlx::Error>().is_some_and(temporary) {
+            database_busy(&err)
+        } else {
+            internal_error(&err)
+        };
+        response.extensions_mut().insert(ReportedError(Arc::new(err)));
+        response
     }
 }
 
+/// The message of an `AppError` response, for `json_errors` to send instead of its page.
+#[derive(Clone)]
+struct ErrorMessage(String);
+
+/// Middleware for the JSON API: responses made from an `AppError` have their page replaced
+/// by `{"error": "..."}` with the same message, keeping the status and headers.
+///
+/// Example usage:
+/// `router.layer(middleware::from_fn(utils::json_errors))`
+pub async fn json_errors(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let Some(ErrorMessage(message)) = response.extensions().get::<ErrorMessage>().cloned() else {
+        return response;
+    };
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(CONTENT_TYPE);
+    parts.headers.remove(CONTENT_LENGTH);
+    let mut json = Json(json!({ "error": message })).into_response();
+    json.headers_mut().extend(parts.headers);
+    *json.status_mut() = parts.status;
+    *json.extensions_mut() = parts.extensions;
+    json
+}
+
+/// `response` with `message` for `json_errors`.
+pub fn with_message(mut response: Response, message: String) -> Response {
+    response.extensions_mut().insert(ErrorMessage(message));
+    response
+}
+
+fn internal_error(err: &anyhow::Error) -> Response {
+    // Returning a HTML page for an error
+    let template = Error5xxTemplate {
+        error: err.to_string(),
+        request_id: logging::request_id(),
+    };
+    let response = match template.render() {
+        Ok(html) => {
+            error!("Internal Application Error: {}", err.to_string());
+            (StatusCode::INTERNAL_SERVER_ERROR, Html(html)).into_response()
+        }
+        // This has failed catastrophically - just return some string
+        Err(_) => {
+            error!("Internal Server Error: {}", err.to_string());
+            (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error").into_response()
+        }
+    };
+    with_message(response, i18n::t("error-internal-title"))
+}
+
+fn not_found() -> Response {
+    let response = match NotFoundTemplate.render() {
+        Ok(html) => (StatusCode::NOT_FOUND, Html(html)).into_response(),
+        Err(_) => (StatusCode::NOT_FOUND, "Not Found").into_response(),
+    };
+    with_message(response, i18n::t("not-found-message"))
+}
+
+/// The page for a request that can't be served as sent, titled by the `title` string.
+fn client_error(status: StatusCode, title: &str, message: String) -> Response {
+    let template = ClientErrorTemplate {
+        title: i18n::t(title),
+        error_message: message.clone(),
+    };
+    let response = match template.render() {
+        Ok(html) => (status, Html(html)).into_response(),
+        Err(_) => (status, message.clone()).into_response(),
+    };
+    with_message(response, message)
+}
+
+fn database_busy(err: &anyhow::Error) -> Response {
+    error!("Database busy, giving up: {err}");
+    let template = DatabaseBusyTemplate {
+        retry_after: BUSY_RETRY_AFTER_SECS,
+    };
+    let mut response = match template.render() {
+        Ok(html) => (StatusCode::SERVICE_UNAVAILABLE, Html(html)).into_response(),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "Service Unavailable").into_response(),
+    };
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(BUSY_RETRY_AFTER_SECS));
+    with_message(response, i18n::t_with("busy-message", "retry_after", BUSY_RETRY_AFTER_SECS))
+}
+
 impl<E> From<E> for AppError
 where
     E: Into<anyhow::Error>,
 {
     fn from(err: E) -> Self {
-        Self(err.into())
+        let err = err.into();
+        if let Some(rejection) = err.downcast_ref::<JsonRejection>() {
+            return Self::Validation(rejection.body_text());
+        }
+        let err = match err.downcast::<ContactError>() {
+            Ok(ContactError::NotFound) => return Self::NotFound,
+            Ok(ContactError::Taken(field)) => return Self::Conflict(NewContactErrors::taken(field).summary()),
+            Ok(ContactError::Changed) => return Self::Conflict(i18n::t("api-if-match-stale")),
+            // Unwrapped, so a busy database still gets the busy page
+            Ok(ContactError::Database(err)) => anyhow::Error::from(err),
+            Err(err) => err,
+        };
+        match err.downcast_ref::<sqlx::Error>() {
+            Some(sqlx::Error::RowNotFound) => Self::NotFound,
+            _ => Self::Internal(err),
+        }
+    }
+}
+
+/// Pages that are allowed as a `return_to` target after a form is submitted, by their route
+/// under the base path. Anything else falls back to the contact list, so the parameter can't
+/// be used as an open redirect.
+const RETURN_TO_ALLOWED: &[&str] = &["/contacts"];
+
+/// Validates a `return_to` parameter against the allow-list. Only local paths from
+/// `RETURN_TO_ALLOWED` (with any query string) under the base path are accepted.
+pub fn safe_return_to(return_to: Option<&str>) -> String {
+    let allowed = |target: &str| {
+        let path = target.split('?').next().unwrap_or_default();
+        path.strip_prefix(base_path::get())
+            .is_some_and(|route| RETURN_TO_ALLOWED.contains(&route))
+            && !target.contains(['\\', '#'])
+    };
+    match return_to {
+        Some(target) if allowed(target) => target.to_string(),
+        _ => base_path::url("/contacts"),
     }
 }
@@ -0,0 +1,59 @@
+//! Field level checks for contact data. These are deliberately lenient: they catch data that
+//! is clearly broken (typos, pasted garbage, missing parts), not everything an RFC would
+//! reject.
+//!
+//! Problems are returned as `i18n` message ids, like `problem-email-missing`, so they can be
+//! shown in whatever locale the page is rendered in.
+
+use crate::phone;
+
+/// Checks an email looks like `local@domain.tld`. Returns what's wrong with it, if anything.
+pub fn email_problem(email: &str) -> Option<&'static str> {
+    let email = email.trim();
+    if email.is_empty() {
+        return Some("problem-email-missing");
+    }
+    if email.contains(char::is_whitespace) {
+        return Some("problem-email-spaces");
+    }
+    let Some((local, domain)) = email.split_once('@') else {
+        return Some("problem-email-no-at");
+    };
+    if local.is_empty() || domain.contains('@') {
+        return Some("problem-email-form");
+    }
+    match domain.rsplit_once('.') {
+        Some((host, tld)) if !host.is_empty() && tld.len() >= 2 => None,
+        _ => Some("problem-email-domain"),
+    }
+}
+
+/// Checks a phone number is made of digits and common punctuation, with a plausible number
+/// of digits (7 to 15, the E.164 maximum).
+pub fn phone_problem(phone_number: &str) -> Option<&'static str> {
+    let phone_number = phone_number.trim();
+    if phone_number.is_empty() {
+        return Some("problem-phone-missing");
+    }
+    if phone_number
+        .chars()
+        .any(|c| !c.is_ascii_digit() && !phone::PUNCTUATION.contains(&c))
+    {
+        return Some("problem-phone-symbols");
+    }
+    let digits = phone_number.chars().filter(char::is_ascii_digit).count();
+    if !(7..=15).contains(&digits) {
+        return Some("problem-phone-digits");
+    }
+    None
+}
+
+/// Checks the contact has both a first and a last name.
+pub fn name_problem(first_name: &str, last_name: &str) -> Option<&'static str> {
+    match (first_name.trim().is_empty(), last_name.trim().is_empty()) {
+        (true, true) => Some("problem-name-missing"),
+        (true, false) => Some("problem-first-name-missing"),
+        (false, true) => Some("problem-last-name-missing"),
+        (false, false) => None,
+    }
+}
@@ -0,0 +1,223 @@
+//! vCard 3.0 (RFC 2426) for contacts: writing one per contact for CardDAV, the address book
+//! apps that sync with it and the QR code on the contact's page, and reading the ones those
+//! apps upload.
+//!
+//! Only the fields a contact has are mapped: `N`/`FN` for the name, the first `TEL` and
+//! `EMAIL`, and `ORG` and `TITLE` for the company and job title. Anything else in an uploaded
+//! card (photos, addresses, extra numbers) is dropped.
+
+use crate::contacts::{Contact, NewContact};
+
+/// Lines longer than this many bytes are folded, as the spec asks.
+const MAX_LINE: usize = 75;
+
+/// The parts of a vCard that map to a contact.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VCardFields {
+    pub first_name: String,
+    pub last_name: String,
+    pub phone_number: String,
+    pub email: String,
+    pub company: String,
+    pub job_title: String,
+}
+
+impl From<VCardFields> for NewContact {
+    fn from(fields: VCardFields) -> Self {
+        NewContact {
+            first_name: fields.first_name,
+            last_name: fields.last_name,
+            phone_number: fields.phone_number,
+            email: fields.email,
+            company: fields.company,
+            job_title: fields.job_title,
+            errors: None,
+            form_token: None,
+        }
+    }
+}
+
+/// Escapes a text value: backslashes, commas, semicolons and newlines.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ',' => escaped.push_str("\\,"),
+            ';' => escaped.push_str("\\;"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+/// Splits a structured value like `N` on the `;`s that aren't escaped.
+fn split_components(value: &str) -> Vec<String> {
+    let mut components = vec![String::new()];
+    let mut escaped = false;
+    for c in value.chars() {
+        let current = components.last_mut().expect("starts with one component");
+        match c {
+            _ if escaped => {
+                current.push('\\');
+                current.push(c);
+                escaped = false;
+            }
+            '\\' => escaped = true,
+            ';' => components.push(String::new()),
+            c => current.push(c),
+        }
+    }
+    components.iter().map(|c| unescape(c)).collect()
+}
+
+/// Appends `line` folded to `MAX_LINE` bytes, never splitting a UTF-8 character.
+fn push_folded(card: &mut String, line: &str) {
+    let mut rest = line;
+    let mut limit = MAX_LINE;
+    while rest.len() > limit {
+        let mut split = limit;
+        while !rest.is_char_boundary(split) {
+            split -= 1;
+        }
+        card.push_str(&rest[..split]);
+        card.push_str("\r\n ");
+        rest = &rest[split..];
+        // Continuation lines start with the space
+        limit = MAX_LINE - 1;
+    }
+    card.push_str(rest);
+    card.push_str("\r\n");
+}
+
+/// The contact as a vCard, with CRLF line endings. The `UID` is derived from the id, so it
+/// stays the same across edits.
+pub fn to_vcard(contact: &Contact) -> String {
+    let mut card = String::new();
+    let mut lines = vec![
+        "BEGIN:VCARD".to_string(),
+        "VERSION:3.0".to_string(),
+        format!("UID:webone-contact-{}", contact.id),
+        format!("N:{};{};;;", escape(&contact.last_name), escape(&contact.first_name)),
+        format!("FN:{}", escape(&contact.full_name())),
+    ];
+    if !contact.phone_number.is_empty() {
+        lines.push(format!("TEL;TYPE=CELL:{}", escape(&contact.phone_number)));
+    }
+    if !contact.email.is_empty() {
+        lines.push(format!("EMAIL;TYPE=INTERNET:{}", escape(&contact.email)));
+    }
+    if !contact.company.is_empty() {
+        lines.push(format!("ORG:{}", escape(&contact.company)));
+    }
+    if !contact.job_title.is_empty() {
+        lines.push(format!("TITLE:{}", escape(&contact.job_title)));
+    }
+    lines.push(format!("REV:{}", contact.updated_at.format("%Y%m%dT%H%M%SZ")));
+    lines.push("END:VCARD".to_string());
+    for line in &lines {
+        push_folded(&mut card, line);
+    }
+    card
+}
+
+/// Reads the name, first phone number, first email, company and job title out of a vCard
+/// (3.0 or 4.0). Fails if the text isn't a vCard or has no name at all.
+pub fn parse(text: &str) -> anyhow::Result<VCardFields> {
+    // Unfold: a line starting with a space or tab continues the previous one
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.split('\n').map(|l| l.strip_suffix('\r').unwrap_or(l)) {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continued), Some(last)) => last.push_str(continued),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    anyhow::ensure!(
+        lines.iter().any(|l| l.eq_ignore_ascii_case("BEGIN:VCARD")),
+        "Not a vCard"
+    );
+
+    let mut fields = VCardFields::default();
+    let mut full_name = None;
+    for line in &lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        // `item1.EMAIL;TYPE=work` -> `EMAIL`
+        let name = name.split(';').next().unwrap_or_default();
+        let name = name.rsplit('.').next().unwrap_or_default().to_ascii_uppercase();
+        match name.as_str() {
+            "N" => {
+                let components = split_components(value);
+                fields.last_name = components.first().cloned().unwrap_or_default();
+                fields.first_name = components.get(1).cloned().unwrap_or_default();
+            }
+            "FN" => full_name = Some(unescape(value)),
+            "TEL" if fields.phone_number.is_empty() => {
+                // vCard 4.0 numbers may be `tel:` URIs
+                let value = unescape(value);
+                fields.phone_number = value.strip_prefix("tel:").unwrap_or(&value).to_string();
+            }
+            "EMAIL" if fields.email.is_empty() => fields.email = unescape(value),
+            // `ORG` can go on with departments after a `;`, only the organization name is kept
+            "ORG" => fields.company = split_components(value).swap_remove(0),
+            "TITLE" => fields.job_title = unescape(value),
+            _ => {}
+        }
+    }
+
+    if fields.first_name.is_empty() && fields.last_name.is_empty() {
+        let full_name = full_name.unwrap_or_default();
+        let (first, last) = full_name.trim().split_once(' ').unwrap_or((full_name.trim(), ""));
+        fields.first_name = first.to_string();
+        fields.last_name = last.trim().to_string();
+    }
+    anyhow::ensure!(
+        !fields.first_name.is_empty() || !fields.last_name.is_empty(),
+        "The vCard has no name"
+    );
+    Ok(fields)
+}
+
+/// Reads every card of a file holding several, like address book apps export, with `parse`.
+/// Fails on the first card that can't be read, saying which one it is.
+pub fn parse_all(text: &str) -> anyhow::Result<Vec<VCardFields>> {
+    let mut cards = Vec::new();
+    let mut card: Option<String> = None;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed.eq_ignore_ascii_case("BEGIN:VCARD") {
+            card = Some(String::new());
+        }
+        if let Some(card) = card.as_mut() {
+            card.push_str(line);
+        }
+        if trimmed.eq_ignore_ascii_case("END:VCARD")
+            && let Some(card) = card.take()
+        {
+            let fields = parse(&card).map_err(|e| e.context(format!("Card {} is unreadable", cards.len() + 1)))?;
+            cards.push(fields);
+        }
+    }
+    anyhow::ensure!(!cards.is_empty(), "Not a vCard");
+    Ok(cards)
+}
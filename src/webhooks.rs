@@ -0,0 +1,455 @@
+//! Outbound webhooks. Every registered URL gets a JSON POST when a contact is created,
+//! updated, deleted or restored.
+//!
+//! Deliveries run as `WEBHOOK_JOB` jobs, off the request path and with the job queue's
+//! retries. Each attempt is written to the delivery log. Bodies are signed with the webhook's
+//! secret: `X-Webone-Timestamp` is the attempt's Unix time and `X-Webone-Signature` is
+//! `sha256=<hex HMAC-SHA256 of "<timestamp>.<body>">`, so receivers can check that a request
+//! really came from this app and turn away old ones played back.
+//!
+//! Webhooks only go to public addresses. Hosts are resolved when a webhook is added and again
+//! on every delivery, and loopback, private, link-local and similar addresses are refused, so a
+//! webhook can't be pointed at the app's own network. Redirects aren't followed either.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::{
+    Client, Url,
+    dns::{Addrs, Name, Resolve, Resolving},
+    header::CONTENT_TYPE,
+    redirect,
+};
+use serde_json::{Value, json};
+use sha2::Sha256;
+use tokio::net::lookup_host;
+use tracing::instrument;
+
+use crate::{
+    clock::SharedClock,
+    db::Db,
+    jobs::{JobHandler, Jobs},
+};
+#[cfg(feature = "postgres")]
+use crate::postgres;
+
+/// Job kind that delivers one event to one webhook.
+pub const WEBHOOK_JOB: &str = "webhook.deliver";
+pub const SIGNATURE_HEADER: &str = "X-Webone-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Webone-Timestamp";
+pub const EVENT_HEADER: &str = "X-Webone-Event";
+/// How long a receiver gets to answer before the attempt counts as failed.
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Webhook {
+    pub id: i64,
+    pub url: String,
+    /// Key for the body signature, shown once on the admin page right after the webhook is
+    /// added, for the receiver to store.
+    pub secret: String,
+    pub created_at: String,
+}
+
+/// One delivery attempt, successful or not.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub url: String,
+    pub event: String,
+    /// Unset when no response came back at all.
+    pub status_code: Option<i64>,
+    pub outcome: String,
+    pub duration_ms: i64,
+    pub created_at: String,
+}
+
+/// Checks that `url` is an absolute `http` or `https` URL with a host, and that the host
+/// isn't a non-public IP address. Host names are checked when they are resolved, see
+/// `resolve_public`.
+pub fn parse_url(url: &str) -> anyhow::Result<Url> {
+    let parsed = Url::parse(url.trim()).with_context(|| format!("Invalid webhook URL {url:?}"))?;
+    anyhow::ensure!(
+        matches!(parsed.scheme(), "http" | "https"),
+        "Webhook URLs have to be http:// or https://"
+    );
+    anyhow::ensure!(parsed.host_str().is_some(), "Webhook URLs need a host");
+    if let Some(ip) = host_ip(&parsed) {
+        anyhow::ensure!(is_public(ip), "Webhooks can't be sent to {ip}, it isn't a public address");
+    }
+    Ok(parsed)
+}
+
+/// The host of `url` if it is an IP address rather than a name. IPv6 hosts come bracketed.
+fn host_ip(url: &Url) -> Option<IpAddr> {
+    let host = url.host_str()?;
+    host.trim_start_matches('[').trim_end_matches(']').parse().ok()
+}
+
+/// Whether `ip` is reachable on the internet, not the app's own machine or network: not
+/// loopback, private, shared (CGNAT), link-local, unspecified, broadcast, multicast or
+/// documentation addresses. IPv4-mapped IPv6 addresses are judged as IPv4, and so are the IPv4
+/// addresses inside NAT64 (`64:ff9b::/96`) and 6to4 (`2002::/16`) ones, which reach them.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            let [.., a, b, c, d] = ip.octets();
+            match segments {
+                [0x64, 0xff9b, 0, 0, 0, 0, _, _] => is_public_v4(Ipv4Addr::new(a, b, c, d)),
+                [0x2002, high, low, ..] => is_public_v4(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low))),
+                [0x2001, 0xdb8, ..] => false,
+                _ => {
+                    !(ip.is_unspecified()
+                        || ip.is_loopback()
+                        || ip.is_unique_local()
+                        || ip.is_unicast_link_local()
+                        || ip.is_multicast())
+                }
+            }
+        }
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(a == 0
+        || ip.is_loopback()
+        || ip.is_private()
+        || (a == 100 && (64..128).contains(&b))
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation())
+}
+
+/// Resolves `host` and returns its addresses with `port`, failing when it has none or any of
+/// them isn't public. All of them have to be, so a name can't mix in a private address for
+/// the connection to land on.
+pub async fn resolve_public(host: &str, port: u16) -> anyhow::Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = lookup_host((host, port))
+        .await
+        .with_context(|| format!("Could not resolve {host}"))?
+        .collect();
+    anyhow::ensure!(!addrs.is_empty(), "{host} has no addresses");
+    if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+        anyhow::bail!("Webhooks can't be sent to {host}, it resolves to {}", addr.ip());
+    }
+    Ok(addrs)
+}
+
+/// Checks a webhook URL like `parse_url`, and resolves its host to make sure it is public.
+///
+/// Example usage:
+/// `let url = webhooks::check_url(&form.url).await?;`
+pub async fn check_url(url: &str) -> anyhow::Result<Url> {
+    let parsed = parse_url(url)?;
+    if let (None, Some(host)) = (host_ip(&parsed), parsed.host_str()) {
+        resolve_public(host, parsed.port_or_known_default().unwrap_or(0)).await?;
+    }
+    Ok(parsed)
+}
+
+/// DNS for webhook deliveries: resolves like the system does, but refuses non-public
+/// addresses. Checking at connection time means a name can't resolve to a public address when
+/// the webhook is added and to a private one later.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs = resolve_public(&host, 0).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+impl Webhook {
+    /// Registers `url` with a new random secret.
+    #[instrument(name = "webhooks.create", skip(db))]
+    pub async fn create(db: &Db, url: &Url) -> Result<Webhook, sqlx::Error> {
+        let url = url.as_str();
+        let secret = hex(&rand::random::<[u8; 32]>());
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    Webhook,
+                    r#"INSERT INTO webhooks (url, secret) VALUES (?, ?)
+                    RETURNING id AS "id!", url, secret, created_at"#,
+                    url,
+                    secret,
+                )
+                .fetch_one(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::create_webhook(pool, url, &secret).await,
+        }
+    }
+
+    pub async fn list(db: &Db) -> Result<Vec<Webhook>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(Webhook, "SELECT * FROM webhooks ORDER BY id")
+                    .fetch_all(pool)
+                    .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::list_webhooks(pool).await,
+        }
+    }
+
+    pub async fn find(db: &Db, id: i64) -> Result<Option<Webhook>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(Webhook, "SELECT * FROM webhooks WHERE id = ?", id)
+                    .fetch_optional(pool)
+                    .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::find_webhook(pool, id).await,
+        }
+    }
+
+    /// Removes the webhook. Deliveries already queued for it are dropped when they run; the
+    /// delivery log is kept.
+    #[instrument(name = "webhooks.delete", skip(db))]
+    pub async fn delete(db: &Db, id: i64) -> Result<(), sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query!("DELETE FROM webhooks WHERE id = ?", id)
+                    .execute(pool)
+                    .await
+                    .map(|_| ())
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::delete_webhook(pool, id).await,
+        }
+    }
+
+    /// `sha256=<hex>` signature of `body` sent at Unix time `timestamp`, for the signature
+    /// header. It covers `<timestamp>.<body>`, so the timestamp can't be changed either.
+    pub fn sign(&self, timestamp: i64, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(format!("{timestamp}.").as_bytes());
+        mac.update(body);
+        format!("sha256={}", hex(&mac.finalize().into_bytes()))
+    }
+}
+
+impl WebhookDelivery {
+    async fn record(
+        db: &Db,
+        webhook: &Webhook,
+        event: &str,
+        status_code: Option<i64>,
+        outcome: &str,
+        duration_ms: i64,
+    ) -> Result<(), sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query!(
+                    r#"INSERT INTO webhook_deliveries (webhook_id, url, event, status_code, outcome, duration_ms)
+                    VALUES (?, ?, ?, ?, ?, ?)"#,
+                    webhook.id,
+                    webhook.url,
+                    event,
+                    status_code,
+                    outcome,
+                    duration_ms,
+                )
+                .execute(pool)
+                .await
+                .map(|_| ())
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => {
+                postgres::record_webhook_delivery(pool, webhook, event, status_code, outcome, duration_ms)
+                    .await
+            }
+        }
+    }
+
+    /// The newest attempts first.
+    pub async fn list(db: &Db, limit: i64) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        match db {
+            Db::Sqlite(pool) => {
+                sqlx::query_as!(
+                    WebhookDelivery,
+                    "SELECT * FROM webhook_deliveries ORDER BY id DESC LIMIT ?",
+                    limit
+                )
+                .fetch_all(pool)
+                .await
+            }
+            #[cfg(feature = "postgres")]
+            Db::Postgres(pool) => postgres::list_webhook_deliveries(pool, limit).await,
+        }
+    }
+}
+
+/// Queues a delivery of `event` about a contact to every registered webhook. `contact` is the
+/// same snapshot that goes into the event log.
+///
+/// Example usage:
+/// `webhooks::fire(&db, &jobs, events::CONTACT_CREATED, contact.id, &payload, now).await?;`
+pub async fn fire(
+    db: &Db,
+    jobs: &Jobs,
+    event: &str,
+    contact_id: i64,
+    contact: &Value,
+    occurred_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let webhooks = Webhook::list(db).await?;
+    if webhooks.is_empty() {
+        return Ok(());
+    }
+    // Rendered once so every webhook, and every retry, gets exactly the same bytes
+    let body = json!({
+        "event": event,
+        "contact_id": contact_id,
+        "contact": contact,
+        "occurred_at": occurred_at.to_rfc3339(),
+    })
+    .to_string();
+    for webhook in webhooks {
+        let payload = json!({ "webhook_id": webhook.id, "event": event, "body": body });
+        jobs.enqueue(WEBHOOK_JOB, payload).await?;
+    }
+    Ok(())
+}
+
+/// Runs `WEBHOOK_JOB` jobs: POSTs the body, logs the attempt, and fails the job on anything
+/// but a 2xx response so it is retried. Redirects count as failures, they aren't followed.
+pub struct WebhookJob {
+    db: Db,
+    client: Client,
+    clock: SharedClock,
+}
+
+impl WebhookJob {
+    pub fn new(db: Db, clock: SharedClock) -> anyhow::Result<Self> {
+        let client = Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .user_agent(concat!("webone/", env!("CARGO_PKG_VERSION")))
+            .redirect(redirect::Policy::none())
+            .dns_resolver(std::sync::Arc::new(PublicResolver))
+            .build()?;
+        Ok(Self { db, client, clock })
+    }
+}
+
+#[async_trait]
+impl JobHandler for WebhookJob {
+    async fn run(&self, payload: &Value) -> anyhow::Result<String> {
+        let webhook_id = payload["webhook_id"].as_i64().context("Missing webhook_id")?;
+        let event = payload["event"].as_str().context("Missing event")?;
+        let body = payload["body"].as_str().context("Missing body")?;
+        let Some(webhook) = Webhook::find(&self.db, webhook_id).await? else {
+            return Ok(format!("Webhook {webhook_id} was removed, not delivered"));
+        };
+
+        // Webhooks added before addresses were checked may point at an IP that isn't public
+        if let Err(e) = parse_url(&webhook.url) {
+            WebhookDelivery::record(&self.db, &webhook, event, None, &e.to_string(), 0).await?;
+            return Ok(format!("Not delivered to {}: {e}", webhook.url));
+        }
+
+        let timestamp = self.clock.now().timestamp();
+        let started = Instant::now();
+        let response = self
+            .client
+            .post(&webhook.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, event)
+            .header(TIMESTAMP_HEADER, timestamp)
+            .header(SIGNATURE_HEADER, webhook.sign(timestamp, body.as_bytes()))
+            .body(body.to_string())
+            .send()
+            .await;
+        let duration_ms = started.elapsed().as_millis() as i64;
+        let (status_code, outcome) = match &response {
+            Ok(response) => (Some(i64::from(response.status().as_u16())), response.status().to_string()),
+            Err(e) => (None, e.to_string()),
+        };
+        WebhookDelivery::record(&self.db, &webhook, event, status_code, &outcome, duration_ms).await?;
+
+        let response = response?;
+        anyhow::ensure!(response.status().is_success(), "{} answered {outcome}", webhook.url);
+        Ok(format!("Delivered {event} to {} ({outcome})", webhook.url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn only_public_addresses_are_public() {
+        for ip in ["93.184.216.34", "1.1.1.1", "2606:4700::1111", "::ffff:93.184.216.34", "64:ff9b::5db8:d822", "2002:5db8:d822::1"] {
+            assert!(public(ip), "{ip} is public");
+        }
+        for ip in [
+            "0.0.0.0",
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "100.64.0.1",
+            "169.254.169.254",
+            "255.255.255.255",
+            "224.0.0.1",
+            "192.0.2.1",
+            "::",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "ff02::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::7f00:1",
+            "64:ff9b::a9fe:a9fe",
+            "2002:7f00:1::1",
+            "2002:c0a8:101::1",
+            "2001:db8::1",
+        ] {
+            assert!(!public(ip), "{ip} isn't public");
+        }
+    }
+
+    #[test]
+    fn urls_need_a_scheme_a_host_and_a_public_ip() {
+        assert!(parse_url(" https://example.com/hook ").is_ok());
+        assert!(parse_url("http://93.184.216.34:8080/hook").is_ok());
+        for url in ["example.com/hook", "ftp://example.com/hook", "file:///etc/passwd", "http://127.0.0.1/hook", "http://[::1]/hook", "http://[64:ff9b::a00:1]/hook"] {
+            assert!(parse_url(url).is_err(), "{url} is refused");
+        }
+    }
+
+    #[test]
+    fn signatures_are_the_hmac_of_the_timestamp_and_body() {
+        let webhook = Webhook { id: 1, url: "https://example.com/hook".into(), secret: "Jefe".into(), created_at: String::new() };
+        assert_eq!(
+            webhook.sign(1_700_000_000, br#"{"event":"contact.created"}"#),
+            "sha256=5699aaac4d28ab895ee5da9f13d34820f659811bba0d224ab9d92dc7137f4438"
+        );
+    }
+}
@@ -0,0 +1,46 @@
+// Live updates for the contact list (see live.rs): rows changed elsewhere are swapped in,
+// deleted ones removed, and new ones added at the top of the first page. Reconnects with
+// a growing delay when the connection drops.
+(function () {
+  const rows = document.getElementById("contact-rows");
+  if (!rows || !rows.dataset.live || !("WebSocket" in window)) {
+    return;
+  }
+  const scheme = location.protocol === "https:" ? "wss://" : "ws://";
+  const url = scheme + location.host + rows.dataset.live;
+
+  function apply(change) {
+    const row = document.getElementById("contact-" + change.id);
+    if (!change.html) {
+      if (row) {
+        row.remove();
+      }
+      return;
+    }
+    const template = document.createElement("template");
+    template.innerHTML = change.html.trim();
+    const fresh = template.content.firstElementChild;
+    if (row) {
+      row.replaceWith(fresh);
+    } else if (change.event !== "contact.updated" && rows.dataset.liveNew === "true") {
+      rows.prepend(fresh);
+    } else {
+      return;
+    }
+    if (window.htmx) {
+      htmx.process(fresh);
+    }
+  }
+
+  function connect(delay) {
+    const socket = new WebSocket(url);
+    socket.onopen = () => {
+      delay = 1000;
+    };
+    socket.onmessage = (message) => apply(JSON.parse(message.data));
+    socket.onclose = () => {
+      setTimeout(() => connect(Math.min(delay * 2, 30000)), delay);
+    };
+  }
+  connect(1000);
+})();
@@ -0,0 +1,282 @@
+//! The contact repositories the app is made with, the database and the in-memory one, held to
+//! the same behaviour.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, TimeZone, Utc};
+use webone::{
+    app,
+    carddav,
+    config::Config,
+    contacts::{BatchFailure, BatchFailureReason, ContactCursor, ContactError, ContactFilter, ContactOrder, ContactSort, ContactWrite, NewContact, OrderProblem, SortDirection},
+    db::MEMORY_URL,
+    memory::MemoryContactRepository,
+    repository::SharedContactRepository,
+};
+
+/// A fresh in-memory SQLite database, seeded like a new file, and an empty memory repository.
+async fn repositories() -> Vec<(&'static str, SharedContactRepository)> {
+    let mut config = Config::default();
+    config.database.url = MEMORY_URL.to_string();
+    let (pool, _) = app::connect(&config).await.expect("in-memory database");
+    vec![("sqlite", Arc::new(pool)), ("memory", Arc::new(MemoryContactRepository::default()))]
+}
+
+fn new_contact(n: usize) -> NewContact {
+    NewContact {
+        first_name: format!("Test{n}"),
+        last_name: "Contact".into(),
+        phone_number: format!("+1 999 000 01{n:02}"),
+        email: format!("test{n}@webone.test"),
+        ..NewContact::default()
+    }
+}
+
+fn at(millis: i64) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2026, 10, 15, 9, 30, 0).unwrap() + chrono::TimeDelta::milliseconds(millis)
+}
+
+#[tokio::test]
+async fn only_the_first_change_to_a_version_is_saved() {
+    for (name, contacts) in repositories().await {
+        let read = contacts.create(new_contact(1), at(0)).await.unwrap();
+        let mut first = read.clone();
+        first.job_title = "CTO".into();
+        let mut second = read.clone();
+        second.job_title = "CFO".into();
+
+        contacts.update_unchanged(&first, at(0)).await.unwrap();
+        let result = contacts.update_unchanged(&second, at(0)).await;
+        assert!(matches!(result, Err(ContactError::Changed)), "{name}: {result:?}");
+        let result = contacts.delete_unchanged(&read).await;
+        assert!(matches!(result, Err(ContactError::Changed)), "{name}: {result:?}");
+        let stored = contacts.find_by_id(read.id).await.unwrap();
+        assert_eq!(stored.job_title, "CTO", "{name}");
+
+        contacts.delete_unchanged(&stored).await.unwrap();
+        let result = contacts.find_by_id(read.id).await;
+        assert!(matches!(result, Err(ContactError::NotFound)), "{name}: {result:?}");
+        let result = contacts.update_unchanged(&stored, at(0)).await;
+        assert!(matches!(result, Err(ContactError::Changed)), "{name}: {result:?}");
+    }
+}
+
+#[tokio::test]
+async fn saves_within_a_millisecond_get_new_etags() {
+    for (name, contacts) in repositories().await {
+        let created = contacts.create(new_contact(2), at(0)).await.unwrap();
+        let mut etags = vec![carddav::etag(&created)];
+        let mut contact = created;
+        // All stamped with the same time, the way a fast client or a frozen clock would
+        for title in ["CTO", "CFO", "COO"] {
+            contact.job_title = title.into();
+            contacts.update(&contact, at(0)).await.unwrap();
+            contact = contacts.find_by_id(contact.id).await.unwrap();
+            etags.push(carddav::etag(&contact));
+        }
+        contact.job_title = "CEO".into();
+        contacts.update_unchanged(&contact, at(0)).await.unwrap();
+        etags.push(carddav::etag(&contacts.find_by_id(contact.id).await.unwrap()));
+
+        let mut unique = etags.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), etags.len(), "{name}: {etags:?}");
+    }
+}
+
+#[tokio::test]
+async fn batches_only_change_the_versions_they_read() {
+    for (name, contacts) in repositories().await {
+        let read = contacts.create(new_contact(3), at(0)).await.unwrap();
+        let mut other = read.clone();
+        other.company = "Elsewhere".into();
+        contacts.update(&other, at(0)).await.unwrap();
+
+        let mut stale = read.clone();
+        stale.job_title = "CTO".into();
+        for write in [ContactWrite::Update(stale), ContactWrite::Delete(read.clone())] {
+            let result = contacts.apply_batch(&[write], at(0)).await.unwrap();
+            let failure = BatchFailure { index: 0, reason: BatchFailureReason::Changed };
+            assert_eq!(result.unwrap_err(), failure, "{name}");
+        }
+        let stored = contacts.find_by_id(read.id).await.unwrap();
+        assert_eq!((stored.company.as_str(), stored.job_title.as_str()), ("Elsewhere", ""), "{name}");
+
+        // Later changes of the batch go on from the version the earlier ones left
+        let mut etags = vec![carddav::etag(&stored)];
+        let mut first = stored.clone();
+        first.job_title = "CTO".into();
+        let mut second = first.clone();
+        second.job_title = "CFO".into();
+        let writes = [ContactWrite::Update(first), ContactWrite::Update(second.clone())];
+        let applied = contacts.apply_batch(&writes, at(0)).await.unwrap().unwrap();
+        etags.extend(applied.iter().map(carddav::etag));
+        let stored = contacts.find_by_id(read.id).await.unwrap();
+        assert_eq!(stored.job_title, "CFO", "{name}");
+        assert_eq!(carddav::etag(&stored), carddav::etag(&applied[1]), "{name}");
+
+        let applied = contacts.apply_batch(&[ContactWrite::Delete(second)], at(0)).await.unwrap();
+        assert!(applied.is_err(), "{name}: deleted at a version the batch moved on from");
+        contacts.apply_batch(&[ContactWrite::Delete(stored)], at(0)).await.unwrap().unwrap();
+
+        let mut unique = etags.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), etags.len(), "{name}: {etags:?}");
+    }
+}
+
+#[tokio::test]
+async fn cursors_page_through_mixed_orders_without_skipping_or_repeating() {
+    let orders = [
+        ("last_name,first_name", "asc,desc"),
+        ("last_name,created_at", "desc,asc"),
+        ("first_name,id", "asc,desc"),
+        ("created_at,email", "desc,asc"),
+        ("email,last_name,first_name", "desc,asc,desc"),
+        ("created_at", "desc"),
+        ("company,last_name", ""),
+        ("company,first_name", "desc,asc"),
+    ];
+    for (name, contacts) in repositories().await {
+        // Few names and shared seconds, so most rows tie on the first columns
+        for n in 0..14 {
+            let mut new = new_contact(n + 10);
+            new.first_name = ["Ana", "Ben", "Cleo"][n % 3].into();
+            new.last_name = ["Smith", "Jones"][n % 2].into();
+            new.company = ["Acme", "", "Globex", "Acme"][n % 4].into();
+            contacts.create(new, at((n as i64 / 4) * 1000)).await.unwrap();
+        }
+        let all = contacts.all().await.unwrap();
+        for (sort, dir) in orders {
+            let order = ContactOrder::parse(sort, dir).unwrap();
+            let mut expected = all.clone();
+            expected.sort_by(|a, b| order.compare(a, b));
+            let expected: Vec<_> = expected.iter().map(|c| c.id).collect();
+
+            let mut seen = Vec::new();
+            let mut after = None;
+            loop {
+                let page = contacts.search(&ContactFilter::default(), 1, 4, order, after.as_ref()).await.unwrap();
+                seen.extend(page.iter().map(|c| c.id));
+                match page.last() {
+                    // Through the string form, the way the next link carries it
+                    Some(last) => after = ContactCursor::decode(&ContactCursor::after(last, order).encode()),
+                    None => break,
+                }
+            }
+            assert_eq!(seen, expected, "{name}: sort={sort} dir={dir}");
+        }
+    }
+}
+
+#[test]
+fn cursors_only_decode_what_encode_made() {
+    let hex = |text: &str| text.bytes().map(|b| format!("{b:02x}")).collect::<String>();
+    let order = ContactOrder::parse("last_name,created_at", "desc,asc").unwrap();
+    let cursor = ContactCursor { order, keys: vec!["Smith".into(), "2026-10-15 09:30:00".into()], id: 7 };
+    let encoded = cursor.encode();
+    assert_eq!(ContactCursor::decode(&encoded), Some(cursor));
+
+    for tampered in [
+        String::new(),
+        encoded[1..].to_string(),
+        format!("{}zz", &encoded[..encoded.len() - 2]),
+        format!("{}é", &encoded[..encoded.len() - 2]),
+        encoded.to_uppercase().replace(|c: char| c.is_ascii_digit(), "g"),
+        hex(r#"["last_name,created_at","desc,asc",7,["Smith"]]"#),
+        hex(r#"["last_name,created_at","desc,asc",7,["Smith","2026-10-15 09:30:00","x"]]"#),
+        hex(r#"["last_name,password","desc,asc",7,["Smith","x"]]"#),
+        hex(r#"["last_name,created_at","desc,sideways",7,["Smith","2026-10-15 09:30:00"]]"#),
+        hex(r#"["last_name,last_name","",7,["Smith","Smith"]]"#),
+        hex(r#"["last_name,created_at","desc,asc","7",["Smith","2026-10-15 09:30:00"]]"#),
+        hex(r#"{"sort":"last_name","id":7}"#),
+    ] {
+        assert_eq!(ContactCursor::decode(&tampered), None, "{tampered}");
+    }
+}
+
+#[test]
+fn orders_parse_from_the_sort_and_dir_parameters() {
+    use ContactSort::*;
+    use SortDirection::*;
+    let keys = |sort: &str, dir: &str| ContactOrder::parse(sort, dir).map(|order| order.keys().to_vec());
+
+    assert_eq!(keys("last_name", ""), Ok(vec![(LastName, Asc)]));
+    assert_eq!(keys(" last_name , first_name ", "desc"), Ok(vec![(LastName, Desc), (FirstName, Asc)]));
+    assert_eq!(keys("email,created_at,id", ",desc"), Ok(vec![(Email, Asc), (CreatedAt, Desc), (Id, Asc)]));
+    assert_eq!(keys("last_name,,first_name", "asc,desc"), Ok(vec![(LastName, Asc), (FirstName, Desc)]));
+    assert_eq!(keys("company,last_name", ""), Ok(vec![(Company, Asc), (LastName, Asc)]));
+
+    assert_eq!(keys("", ""), Err(OrderProblem::UnknownColumn(String::new())));
+    assert_eq!(keys("last_name,password", ""), Err(OrderProblem::UnknownColumn("password".into())));
+    assert_eq!(keys("last_name; DROP TABLE contacts", ""), Err(OrderProblem::UnknownColumn("last_name; DROP TABLE contacts".into())));
+    assert_eq!(keys("Last_Name", ""), Err(OrderProblem::UnknownColumn("Last_Name".into())));
+    assert_eq!(keys("last_name", "up"), Err(OrderProblem::UnknownDirection("up".into())));
+    assert_eq!(keys("last_name,first_name,email,id", ""), Err(OrderProblem::TooManyColumns));
+    assert_eq!(keys("last_name", "asc,desc"), Err(OrderProblem::TooManyDirections));
+    assert_eq!(keys("email,last_name,email", ""), Err(OrderProblem::RepeatedColumn(Email)));
+
+    // The parameters it writes parse back to the same order
+    let order = ContactOrder::parse("created_at,last_name", "desc").unwrap();
+    assert_eq!((order.sort_param().as_str(), order.dir_param().as_str()), ("created_at,last_name", "desc,asc"));
+    assert_eq!(ContactOrder::parse(&order.sort_param(), &order.dir_param()), Ok(order));
+    let ascending = ContactOrder::parse("first_name", "asc").unwrap();
+    assert_eq!(ascending.dir_param(), "");
+    assert_eq!(ascending.terms(), vec![(FirstName, Asc), (Id, Asc)]);
+    assert_eq!(ContactOrder::parse("first_name,id", "desc,desc").unwrap().terms(), vec![(FirstName, Desc), (Id, Desc)]);
+}
+
+#[tokio::test]
+async fn phone_checks_ignore_punctuation_like_the_index() {
+    for (name, contacts) in repositories().await {
+        let mut new = new_contact(40);
+        new.phone_number = "15550100000".into();
+        let stored = contacts.create(new, at(0)).await.unwrap();
+
+        for same in ["+1 (555) 010-0000", "1.555.010.0000", "1/555/010/0000", "15550100000"] {
+            assert!(contacts.phone_exists(same, None).await.unwrap(), "{name}: {same}");
+            assert!(!contacts.phone_exists(same, Some(stored.id)).await.unwrap(), "{name}: {same} is its own");
+            // What the check lets through, saving does too
+            let mut new = new_contact(41);
+            new.phone_number = same.into();
+            let result = contacts.create(new, at(0)).await;
+            assert!(matches!(result, Err(ContactError::Taken("phone_number"))), "{name}: {same}: {result:?}");
+        }
+        assert!(!contacts.phone_exists("+1 (555) 010-0001", None).await.unwrap(), "{name}");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn concurrent_creates_of_one_number_save_one() {
+    for (name, contacts) in repositories().await {
+        let runtime = tokio::runtime::Handle::current();
+        for round in 0..50 {
+            // Threads let loose at once, so the checks and writes really overlap
+            let start = Arc::new(std::sync::Barrier::new(8));
+            let threads: Vec<_> = (0..8)
+                .map(|n| {
+                    let (contacts, start, runtime) = (contacts.clone(), start.clone(), runtime.clone());
+                    let mut new = new_contact(50);
+                    new.phone_number = format!("+1 999 000 2{round:03}");
+                    new.email = format!("racer{round}-{n}@webone.test");
+                    std::thread::spawn(move || {
+                        start.wait();
+                        runtime.block_on(contacts.create(new, at(0)))
+                    })
+                })
+                .collect();
+            let results = tokio::task::spawn_blocking(|| threads.into_iter().map(|t| t.join().unwrap()).collect::<Vec<_>>());
+            let mut saved = 0;
+            for result in results.await.unwrap() {
+                match result {
+                    Ok(_) => saved += 1,
+                    Err(ContactError::Taken("phone_number")) => {}
+                    Err(e) => panic!("{name}: {e:?}"),
+                }
+            }
+            assert_eq!(saved, 1, "{name}: round {round}");
+        }
+    }
+}
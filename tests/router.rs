@@ -0,0 +1,242 @@
+//! Drives the app made by `build_router` with `oneshot`, the way a server embedding it would.
+
+use std::net::SocketAddr;
+
+use axum::{
+    Router,
+    body::{self, Body},
+    extract::ConnectInfo,
+    http::{Request, StatusCode},
+};
+use tower::ServiceExt;
+use webone::{
+    app::{self, ShutdownHandle},
+    config::Config,
+    db::MEMORY_URL,
+};
+
+/// The app on a fresh in-memory database, served under `base_path`.
+async fn app_at(base_path: &str) -> (Router, ShutdownHandle) {
+    let mut config = Config::default();
+    config.database.url = MEMORY_URL.to_string();
+    config.server.base_path = base_path.to_string();
+    let (pool, _) = app::connect(&config).await.expect("in-memory database");
+    app::build_router(config, pool).await.expect("router")
+}
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, String) {
+    send(app, Request::get(uri).body(Body::empty()).unwrap()).await
+}
+
+async fn send(app: &Router, mut request: Request<Body>) -> (StatusCode, String) {
+    request.extensions_mut().insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 1234))));
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8_lossy(&body).into_owned())
+}
+
+#[tokio::test]
+async fn probes_answer() {
+    let (app, shutdown) = app_at("").await;
+    for probe in ["/healthz", "/livez", "/readyz"] {
+        let (status, body) = get(&app, probe).await;
+        assert_eq!(status, StatusCode::OK, "{probe}: {body}");
+        assert!(body.contains(r#""status":"ok""#), "{probe}: {body}");
+    }
+    shutdown.shutdown().await;
+}
+
+#[tokio::test]
+async fn apps_in_one_process_keep_their_own_base_path() {
+    let (root, root_shutdown) = app_at("").await;
+    let (nested, nested_shutdown) = app_at("/book").await;
+
+    let (status, page) = get(&nested, "/book/contacts").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains(r#"href="/book/contacts/new""#));
+    assert!(page.contains("/book/static/"));
+
+    let (status, page) = get(&root, "/contacts").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains(r#"href="/contacts/new""#));
+    assert!(!page.contains("/book/"));
+
+    let (status, _) = get(&root, "/book/contacts").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    root_shutdown.shutdown().await;
+    nested_shutdown.shutdown().await;
+}
+
+#[tokio::test]
+async fn static_files_get_hashed_urls() {
+    let (app, shutdown) = app_at("").await;
+    let (_, page) = get(&app, "/contacts").await;
+    let start = page.find("/static/site.").expect("hashed stylesheet link");
+    let url = &page[start..start + page[start..].find('"').unwrap()];
+    assert_ne!(url, "/static/site.css");
+
+    let (status, css) = get(&app, url).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!css.is_empty());
+    shutdown.shutdown().await;
+}
+
+#[tokio::test]
+async fn huge_pages_are_clamped() {
+    let (app, shutdown) = app_at("").await;
+    for page in ["9999", "92233720368547758", "9223372036854775807"] {
+        let (status, body) = get(&app, &format!("/contacts?page={page}")).await;
+        assert_eq!(status, StatusCode::OK, "page {page}: {body}");
+        let (status, body) = get(&app, &format!("/contacts?page={page}&per_page=100&sort=last_name")).await;
+        assert_eq!(status, StatusCode::OK, "page {page}: {body}");
+    }
+    shutdown.shutdown().await;
+}
+
+#[tokio::test]
+async fn dav_cards_are_validated_like_the_form() {
+    let (app, shutdown) = app_at("").await;
+    let put = |card: &str| Request::put("/dav/addressbooks/contacts/new.vcf").body(Body::from(card.to_string())).unwrap();
+
+    let (status, body) = send(&app, put("BEGIN:VCARD\r\nVERSION:3.0\r\nN:Doe;Jane;;;\r\nEMAIL:not an email\r\nEND:VCARD\r\n")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{body}");
+    assert!(!body.is_empty());
+
+    let card = "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Doe;Jane;;;\r\nFN:Jane Doe\r\nTEL:+1 555 010 0199\r\nEMAIL:jane@webone.test\r\nEND:VCARD\r\n";
+    let (status, body) = send(&app, put(card)).await;
+    assert_eq!(status, StatusCode::CREATED, "{body}");
+    shutdown.shutdown().await;
+}
+
+#[tokio::test]
+async fn admin_pages_refuse_untrusted_proxies() {
+    let (app, shutdown) = app_at("").await;
+    let (status, body) = get(&app, "/admin/backups").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    // Through a proxy on the same machine, the client's address is the proxy's
+    for header in ["x-forwarded-for", "x-real-ip", "forwarded"] {
+        let request = Request::get("/admin/backups").header(header, "203.0.113.7").body(Body::empty()).unwrap();
+        let (status, _) = send(&app, request).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{header}");
+    }
+    shutdown.shutdown().await;
+}
+
+#[tokio::test]
+async fn restored_contacts_get_their_dates_back() {
+    let (app, shutdown) = app_at("").await;
+    let card = "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Doe;Jane;;;\r\nTEL:+1 555 010 0198\r\nEMAIL:jane.doe@webone.test\r\nEND:VCARD\r\n";
+    let mut request = Request::put("/dav/addressbooks/contacts/new.vcf").body(Body::from(card)).unwrap();
+    request.extensions_mut().insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 1234))));
+    let created = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(created.status(), StatusCode::CREATED);
+    let card = created.headers()["location"].to_str().unwrap().to_string();
+    let id = card.trim_end_matches(".vcf").rsplit('/').next().unwrap().to_string();
+
+    let form = "label=Anniversary&date=2015-06-20&yearly=on";
+    let request = Request::post(format!("/contacts/{id}/dates"))
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(Body::from(form))
+        .unwrap();
+    send(&app, request).await;
+    let (_, page) = get(&app, &format!("/contacts/{id}")).await;
+    assert!(page.contains("Anniversary"));
+
+    // Over CardDAV it goes into the trash right away
+    let (status, _) = send(&app, Request::delete(&card).body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = get(&app, &format!("/contacts/{id}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let request = Request::post(format!("/contacts/trash/{id}/restore")).body(Body::empty()).unwrap();
+    send(&app, request).await;
+    let (status, page) = get(&app, &format!("/contacts/{id}")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains("Anniversary"), "{page}");
+    shutdown.shutdown().await;
+}
+
+#[tokio::test]
+async fn missing_downloads_get_the_not_found_page() {
+    let (app, shutdown) = app_at("").await;
+    let (_, not_found) = get(&app, "/no/such/page").await;
+    assert!(!not_found.is_empty());
+    for uri in ["/contacts/1/attachments/999", "/admin/backups/webone-20250101-120000.000.db", "/admin/backups/..%2Fdatabase.db"] {
+        let (status, page) = get(&app, uri).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{uri}");
+        assert_eq!(page, not_found, "{uri}");
+    }
+    shutdown.shutdown().await;
+}
+
+#[tokio::test]
+async fn admin_changes_from_other_sites_are_refused() {
+    let (app, shutdown) = app_at("").await;
+    let form = |uri: &str, body: &str, (header, value): (&str, &str)| {
+        Request::post(uri)
+            .header("content-type", "application/x-www-form-urlencoded")
+            .header("host", "localhost")
+            .header(header, value)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let webhook = "url=https%3A%2F%2Fattacker.example%2Fhook";
+    for header in [("sec-fetch-site", "cross-site"), ("sec-fetch-site", "same-site"), ("origin", "https://attacker.example"), ("origin", "null")] {
+        for (uri, body) in [("/admin/webhooks", webhook), ("/admin/backup", ""), ("/settings/api_keys", "name=stolen"), ("/settings/api_keys/1/revoke", ""), ("/settings", "theme=dark")] {
+            let (status, _) = send(&app, form(uri, body, header)).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{uri} with {header:?}");
+        }
+    }
+    let (_, page) = get(&app, "/admin/webhooks").await;
+    assert!(!page.contains("attacker.example"));
+
+    // The app's own pages, and clients that aren't browsers
+    for header in [("sec-fetch-site", "same-origin"), ("origin", "http://localhost"), ("x-client", "curl")] {
+        let (status, _) = send(&app, form("/admin/webhooks", "url=not+a+url", header)).await;
+        assert_ne!(status, StatusCode::FORBIDDEN, "{header:?}");
+    }
+    shutdown.shutdown().await;
+}
+
+#[tokio::test]
+async fn contacts_sort_by_company_then_name() {
+    let (app, shutdown) = app_at("").await;
+    for (n, (company, last)) in [("Globex", "Adams"), ("Acme", "Young"), ("Acme", "Baker")].into_iter().enumerate() {
+        let card = format!(
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nN:{last};Sam;;;\r\nORG:{company}\r\nTEL:+1 555 010 02{n:02}\r\nEMAIL:{last}@sort.test\r\nEND:VCARD\r\n"
+        );
+        let request = Request::put(format!("/dav/addressbooks/contacts/sort-{n}.vcf")).body(Body::from(card)).unwrap();
+        let (status, body) = send(&app, request).await;
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+    }
+    let (status, page) = get(&app, "/contacts?q=sort.test&sort=company,last_name&per_page=2").await;
+    assert_eq!(status, StatusCode::OK, "{page}");
+    let at = |name: &str| page.find(name).unwrap_or_else(|| panic!("{name} missing"));
+    assert!(at("Baker") < at("Young"), "{page}");
+    assert!(!page.contains("Adams"), "{page}");
+    let (status, page) = get(&app, "/contacts?q=sort.test&sort=company,last_name&dir=desc,desc&per_page=2").await;
+    assert_eq!(status, StatusCode::OK, "{page}");
+    let at = |name: &str| page.find(name).unwrap_or_else(|| panic!("{name} missing"));
+    assert!(at("Adams") < at("Young"), "{page}");
+    assert!(!page.contains("Baker"), "{page}");
+    shutdown.shutdown().await;
+}
+
+#[tokio::test]
+async fn the_api_list_takes_the_same_parameters_as_the_page() {
+    let (app, shutdown) = app_at("").await;
+    for query in ["sort=phone_number", "sort=company,company", "dir=sideways", "page=0", "per_page=lots"] {
+        let (page_status, page) = get(&app, &format!("/contacts?{query}")).await;
+        assert_eq!(page_status, StatusCode::BAD_REQUEST, "{query}: {page}");
+        let (status, body) = get(&app, &format!("/api/v1/contacts?{query}")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{query}: {body}");
+        assert!(body.starts_with(r#"{"error":"#), "{query}: {body}");
+    }
+    let (status, body) = get(&app, "/api/v1/contacts?sort=company,last_name&dir=desc&per_page=2").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert!(body.contains(r#""per_page":2"#), "{body}");
+    shutdown.shutdown().await;
+}
@@ -0,0 +1,36 @@
+//! `Tenants` keeping to `tenancy.max_tenants`, however the first requests for them come in.
+
+use std::time::Duration;
+
+use webone::tenants::{Tenants, TooManyTenants};
+
+#[tokio::test]
+async fn tenants_being_made_count_towards_the_max() {
+    let tenants: Tenants<String> = Tenants::default();
+    let slow = |name: &'static str| {
+        let tenants = tenants.clone();
+        tokio::spawn(async move {
+            tenants
+                .get_or_create(name, 2, || async move {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Ok(name.to_string())
+                })
+                .await
+        })
+    };
+    let firsts: Vec<_> = ["acme", "globex", "initech", "umbrella"].into_iter().map(slow).collect();
+    let mut made = 0;
+    for first in firsts {
+        match first.await.unwrap() {
+            Ok(_) => made += 1,
+            Err(e) => assert!(e.is::<TooManyTenants>(), "{e:#}"),
+        }
+    }
+    assert_eq!(made, 2);
+    assert_eq!(tenants.all().len(), 2);
+
+    // A tenant that failed to be made frees its place
+    let failed = tenants.get_or_create("hooli", 3, || async { anyhow::bail!("no disk") }).await;
+    assert!(failed.is_err());
+    assert!(tenants.get_or_create("piedpiper", 3, || async { Ok("piedpiper".to_string()) }).await.is_ok());
+}